@@ -0,0 +1,135 @@
+//! # Database layer benchmarks
+//!
+//! Benchmarks the queries on the hot authentication path against each database backend. Run with
+//! `cargo bench --bench db`.
+
+use std::sync::Arc;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use iam_server::{
+    api::ApiOptions,
+    db::{clients::sqlite::SqliteClient, interface::DatabaseClient},
+    models::{NewPasskeyCredential, User},
+    test_utils::{TestApp, TestSession},
+};
+use tokio::runtime::Runtime;
+use uuid::Uuid;
+use webauthn_rs::prelude::Passkey;
+
+/// Number of users (each with a session) in the database during benchmarks
+const USERS: usize = 1000;
+
+/// Test data for a benchmarked database
+struct Fixture {
+    app: TestApp,
+    /// User with tags and a passkey
+    user: User,
+    session: TestSession,
+    credential_id: Vec<u8>,
+}
+
+/// Returns the database backends to benchmark, by name.
+async fn backends() -> Vec<(&'static str, Arc<dyn DatabaseClient>)> {
+    vec![(
+        "sqlite-memory",
+        Arc::new(SqliteClient::new_memory().await.unwrap()),
+    )]
+}
+
+/// Fills the given database with [`USERS`] users and sessions, one of which is an administrator
+/// with a passkey.
+async fn fixture(db: Arc<dyn DatabaseClient>) -> Fixture {
+    let app = TestApp::with_db(db, ApiOptions::default());
+    for i in 1..USERS {
+        let user = app
+            .create_user(&format!("user{i}@example.com"), &format!("User {i}"))
+            .await;
+        app.create_session(&user, false).await;
+    }
+
+    let user = app.create_user("admin@example.com", "Admin").await;
+    app.make_admin(&user).await;
+    let session = app.create_session(&user, true).await;
+    let passkey: Passkey = serde_json::from_str(include_str!(
+        "../src/db/clients/sqlite/tests/resources/passkey.json"
+    ))
+    .unwrap();
+    let credential_id = passkey.cred_id().to_vec();
+    app.db()
+        .create_passkey(
+            &Uuid::new_v4(),
+            user.id(),
+            &NewPasskeyCredential {
+                display_name: Some("Benchmark".to_string()),
+                passkey,
+            },
+        )
+        .await
+        .unwrap();
+
+    Fixture {
+        app,
+        user,
+        session,
+        credential_id,
+    }
+}
+
+fn bench_db(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let fixtures: Vec<(&str, Fixture)> = runtime.block_on(async {
+        let mut fixtures = Vec::new();
+        for (name, db) in backends().await {
+            fixtures.push((name, fixture(db).await));
+        }
+        fixtures
+    });
+
+    let mut group = c.benchmark_group("session_lookup");
+    for (name, fixture) in &fixtures {
+        group.bench_with_input(BenchmarkId::from_parameter(name), fixture, |b, fixture| {
+            b.to_async(&runtime).iter(|| async {
+                fixture
+                    .app
+                    .db()
+                    .get_session_by_id_hash(&fixture.session.session.id_hash)
+                    .await
+                    .unwrap()
+            });
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("user_with_relations");
+    for (name, fixture) in &fixtures {
+        group.bench_with_input(BenchmarkId::from_parameter(name), fixture, |b, fixture| {
+            b.to_async(&runtime).iter(|| async {
+                fixture
+                    .app
+                    .db()
+                    .get_user_with_relations(fixture.user.id())
+                    .await
+                    .unwrap()
+            });
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("passkey_by_credential_id");
+    for (name, fixture) in &fixtures {
+        group.bench_with_input(BenchmarkId::from_parameter(name), fixture, |b, fixture| {
+            b.to_async(&runtime).iter(|| async {
+                fixture
+                    .app
+                    .db()
+                    .get_passkey_by_credential_id(&fixture.credential_id)
+                    .await
+                    .unwrap()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_db);
+criterion_main!(benches);
@@ -0,0 +1,16 @@
+//! Fuzzes deserialization of passkey blobs, which are stored in the database as JSON and decoded
+//! via [`ViaJson`] whenever a passkey is loaded.
+
+#![no_main]
+
+use iam_server::models::ViaJson;
+use libfuzzer_sys::fuzz_target;
+use webauthn_rs::prelude::Passkey;
+
+fuzz_target!(|input: &[u8]| {
+    if let Ok(passkey) = serde_json::from_slice::<ViaJson<Passkey>>(input) {
+        // Anything which decodes must survive being stored again
+        let encoded = serde_json::to_vec(&passkey).unwrap();
+        serde_json::from_slice::<ViaJson<Passkey>>(&encoded).unwrap();
+    }
+});
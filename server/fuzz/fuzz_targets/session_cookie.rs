@@ -0,0 +1,43 @@
+//! Fuzzes cookie extraction by sending arbitrary `Cookie` headers to an endpoint which requires a
+//! session. The server must reject bad cookies without panicking or failing internally.
+
+#![no_main]
+
+use std::sync::LazyLock;
+
+use axum::{
+    body::Body,
+    http::{HeaderValue, Request, StatusCode, header::COOKIE},
+};
+use iam_server::test_utils::TestApp;
+use libfuzzer_sys::fuzz_target;
+use tokio::runtime::Runtime;
+
+static RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+});
+
+static APP: LazyLock<TestApp> = LazyLock::new(|| RUNTIME.block_on(TestApp::new()));
+
+fuzz_target!(|input: &[u8]| {
+    let Ok(cookie) = HeaderValue::from_bytes(input) else {
+        return;
+    };
+    let request = Request::get("/api/v1/auth/session")
+        .header(COOKIE, cookie)
+        .body(Body::empty())
+        .unwrap();
+    let response = RUNTIME.block_on(APP.request(request));
+    assert!(
+        matches!(
+            response.status,
+            StatusCode::UNAUTHORIZED | StatusCode::BAD_REQUEST
+        ),
+        "unexpected status {}: {}",
+        response.status,
+        response.text(),
+    );
+});
@@ -0,0 +1,99 @@
+//! # Administrator management
+//!
+//! Grants and revokes administrator privileges directly in the database, without needing an
+//! administrator session. This is how the first administrator is created, and how access is
+//! recovered if no administrator can log in. Administrators are the users with the
+//! [`ADMIN_TAG`], which is created if it doesn't exist yet.
+
+use crate::{
+    db::interface::{DatabaseClient, DatabaseError},
+    models::{ADMIN_TAG, TagUpdate, new_uuid},
+};
+use uuid::Uuid;
+
+/// Gives the user with the given UUID the administrator tag. Returns whether they weren't an
+/// administrator already.
+pub async fn grant_admin(db: &dyn DatabaseClient, user_id: &Uuid) -> Result<bool, DatabaseError> {
+    db.get_user_by_id(user_id).await?;
+    let tag = match db.get_tag_by_name(ADMIN_TAG).await {
+        Ok(tag) => tag,
+        Err(DatabaseError::NotFound) => {
+            db.create_tag(
+                &new_uuid(),
+                None,
+                &TagUpdate::new().with_name(ADMIN_TAG.to_string()),
+            )
+            .await?
+        }
+        Err(err) => return Err(err),
+    };
+    if is_admin(db, user_id).await? {
+        return Ok(false);
+    }
+    db.add_tag_to_user(user_id, &tag).await?;
+    Ok(true)
+}
+
+/// Removes the administrator tag from the user with the given UUID, and revokes their sessions so
+/// that any administrator sessions they have end. Returns whether they were an administrator.
+///
+/// Running servers may keep accepting the revoked sessions until their in-memory session cache
+/// expires.
+pub async fn revoke_admin(db: &dyn DatabaseClient, user_id: &Uuid) -> Result<bool, DatabaseError> {
+    db.get_user_by_id(user_id).await?;
+    if !is_admin(db, user_id).await? {
+        return Ok(false);
+    }
+    let tag = db.get_tag_by_name(ADMIN_TAG).await?;
+    db.remove_tag_from_user(user_id, &tag).await?;
+    db.revoke_sessions_by_user_id(user_id).await?;
+    Ok(true)
+}
+
+/// Returns whether the user with the given UUID has the administrator tag.
+async fn is_admin(db: &dyn DatabaseClient, user_id: &Uuid) -> Result<bool, DatabaseError> {
+    Ok(db
+        .get_tags_by_user_id(user_id)
+        .await?
+        .iter()
+        .any(|tag| tag.name == ADMIN_TAG))
+}
+
+#[cfg(all(test, feature = "sqlite3"))]
+mod tests {
+    use super::*;
+    use crate::{db::clients::sqlite::SqliteClient, models::UserCreate};
+
+    #[tokio::test]
+    async fn test_grant_and_revoke() {
+        let db = SqliteClient::new_memory().await.unwrap();
+        let user = db
+            .create_user(
+                &new_uuid(),
+                &UserCreate {
+                    email: "admin@kasad.com".to_string(),
+                    display_name: "Admin".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+
+        // The tag is created on first use, and granting is idempotent
+        assert!(matches!(
+            db.get_tag_by_name(ADMIN_TAG).await,
+            Err(DatabaseError::NotFound)
+        ));
+        assert!(grant_admin(&db, user.id()).await.unwrap());
+        assert!(!grant_admin(&db, user.id()).await.unwrap());
+        assert!(is_admin(&db, user.id()).await.unwrap());
+
+        assert!(revoke_admin(&db, user.id()).await.unwrap());
+        assert!(!revoke_admin(&db, user.id()).await.unwrap());
+        assert!(!is_admin(&db, user.id()).await.unwrap());
+
+        assert!(matches!(
+            grant_admin(&db, &new_uuid()).await,
+            Err(DatabaseError::NotFound)
+        ));
+    }
+}
@@ -0,0 +1,383 @@
+//! # Administrator security alerts
+//!
+//! The [`Alerter`] notifies administrators of [security events][SecurityEvent] by email and/or by
+//! sending a JSON payload to a webhook. To avoid noise, failed logins only trigger an alert once a
+//! [threshold][AlertOptions::failed_login_threshold] is reached, and repeated alerts for the same
+//! event and subject are suppressed for a [cooldown period][AlertOptions::cooldown].
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::email::Mailer;
+
+/// Timeout for webhook requests
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A security-relevant event which may warrant an alert
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum SecurityEvent {
+    /// A passkey authentication ceremony failed
+    #[serde(rename_all = "camelCase")]
+    FailedLogin {
+        /// Email address of the account, if known
+        email: Option<String>,
+        /// IP address of the client, if known
+        ip: Option<IpAddr>,
+    },
+
+    /// A passkey presented a signature counter which didn't increase, which indicates that the
+    /// passkey may have been cloned
+    #[serde(rename_all = "camelCase")]
+    SignCountRegression {
+        email: Option<String>,
+        /// UUID of the passkey, if known
+        passkey_id: Option<Uuid>,
+    },
+
+    /// A user's account was [locked][crate::lockout] after repeated failed logins
+    #[serde(rename_all = "camelCase")]
+    AccountLocked {
+        email: String,
+        /// Time at which the lock ends
+        until: DateTime<Utc>,
+    },
+
+    /// A user was given the administrator tag
+    #[serde(rename_all = "camelCase")]
+    AdminTagGranted { user_id: Uuid, email: String },
+
+    /// A user upgraded their session to an administrator session
+    #[serde(rename_all = "camelCase")]
+    AdminSessionUpgrade { email: String, ip: Option<IpAddr> },
+
+    /// An administrator started a session impersonating another user
+    #[serde(rename_all = "camelCase")]
+    ImpersonationStarted {
+        /// Email address of the administrator
+        email: String,
+        /// Email address of the impersonated user
+        target_email: String,
+        ip: Option<IpAddr>,
+    },
+
+    /// An administrator downgraded a session impersonating another user back to their own
+    #[serde(rename_all = "camelCase")]
+    ImpersonationEnded {
+        /// Email address of the administrator
+        email: String,
+        /// Email address of the impersonated user
+        target_email: String,
+        ip: Option<IpAddr>,
+    },
+
+    /// An administrator revoked all active sessions
+    #[serde(rename_all = "camelCase")]
+    AllSessionsRevoked {
+        email: String,
+        ip: Option<IpAddr>,
+        /// Number of sessions revoked
+        revoked: u64,
+    },
+}
+
+impl SecurityEvent {
+    /// Returns the key by which alerts for this event are throttled, consisting of the event type
+    /// and its subject.
+    fn throttle_key(&self) -> String {
+        match self {
+            Self::FailedLogin { email, ip } => match (email, ip) {
+                (Some(email), _) => format!("failed-login:{email}"),
+                (None, Some(ip)) => format!("failed-login:{ip}"),
+                (None, None) => "failed-login".to_string(),
+            },
+            Self::SignCountRegression { email, passkey_id } => {
+                format!("sign-count-regression:{email:?}:{passkey_id:?}")
+            }
+            Self::AccountLocked { email, .. } => format!("account-locked:{email}"),
+            Self::AdminTagGranted { user_id, .. } => format!("admin-tag-granted:{user_id}"),
+            Self::AdminSessionUpgrade { email, .. } => format!("admin-session-upgrade:{email}"),
+            Self::ImpersonationStarted {
+                email,
+                target_email,
+                ..
+            } => format!("impersonation-started:{email}:{target_email}"),
+            Self::ImpersonationEnded {
+                email,
+                target_email,
+                ..
+            } => format!("impersonation-ended:{email}:{target_email}"),
+            Self::AllSessionsRevoked { email, .. } => format!("all-sessions-revoked:{email}"),
+        }
+    }
+
+    /// Returns a one-line, human-readable description of the event.
+    fn summary(&self, count: usize) -> String {
+        match self {
+            Self::FailedLogin { email, ip } => format!(
+                "{count} failed login(s) for account {} (last from IP {})",
+                or_unknown(email.as_ref()),
+                or_unknown(ip.as_ref()),
+            ),
+            Self::SignCountRegression { email, .. } => format!(
+                "Possibly cloned passkey used for account {}",
+                or_unknown(email.as_ref()),
+            ),
+            Self::AccountLocked { email, until } => format!(
+                "Account {email} locked until {} after repeated failed logins",
+                until.format("%Y-%m-%d %H:%M:%S UTC"),
+            ),
+            Self::AdminTagGranted { email, .. } => {
+                format!("Administrator privileges granted to {email}")
+            }
+            Self::AdminSessionUpgrade { email, ip } => format!(
+                "{email} started an administrator session from IP {}",
+                or_unknown(ip.as_ref()),
+            ),
+            Self::ImpersonationStarted {
+                email,
+                target_email,
+                ip,
+            } => format!(
+                "{email} started impersonating {target_email} from IP {}",
+                or_unknown(ip.as_ref()),
+            ),
+            Self::ImpersonationEnded {
+                email,
+                target_email,
+                ip,
+            } => format!(
+                "{email} stopped impersonating {target_email} from IP {}",
+                or_unknown(ip.as_ref()),
+            ),
+            Self::AllSessionsRevoked { email, ip, revoked } => format!(
+                "{email} revoked all {revoked} active session(s) from IP {}",
+                or_unknown(ip.as_ref()),
+            ),
+        }
+    }
+}
+
+/// Formats the given value, or returns `unknown` if there is none.
+fn or_unknown<T: std::fmt::Display>(value: Option<&T>) -> String {
+    value.map_or_else(|| "unknown".to_string(), ToString::to_string)
+}
+
+/// Alert delivered to administrators, as sent to the webhook
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Alert<'a> {
+    #[serde(flatten)]
+    event: &'a SecurityEvent,
+    summary: String,
+    /// Number of occurrences which led to this alert
+    count: usize,
+    time: DateTime<Utc>,
+}
+
+/// Options for the [`Alerter`]
+#[derive(Debug, Clone)]
+pub struct AlertOptions {
+    /// Email addresses to which alerts are sent
+    pub email_recipients: Vec<String>,
+    /// URL to which alerts are `POST`ed as JSON
+    pub webhook_url: Option<String>,
+    /// Number of failed logins for the same account within
+    /// [`failed_login_window`][Self::failed_login_window] which triggers an alert
+    pub failed_login_threshold: usize,
+    /// Window in which failed logins are counted
+    pub failed_login_window: Duration,
+    /// Minimum time between alerts for the same event and subject
+    pub cooldown: Duration,
+}
+
+impl Default for AlertOptions {
+    fn default() -> Self {
+        Self {
+            email_recipients: Vec::new(),
+            webhook_url: None,
+            failed_login_threshold: 5,
+            failed_login_window: Duration::from_secs(15 * 60),
+            cooldown: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// # Security alert dispatcher
+///
+/// See the [module-level documentation][self] for details.
+pub struct Alerter {
+    options: AlertOptions,
+    mailer: Option<Arc<Mailer>>,
+    http: reqwest::Client,
+    throttle: Mutex<Throttle>,
+}
+
+impl Alerter {
+    /// Creates an alerter with the given options. Email alerts are only sent if a `mailer` is
+    /// given.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the HTTP client for webhooks can't be created, e.g. because the TLS backend
+    /// fails to initialize.
+    #[must_use]
+    pub fn new(options: AlertOptions, mailer: Option<Arc<Mailer>>) -> Self {
+        Self {
+            options,
+            mailer,
+            http: reqwest::Client::builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .build()
+                .expect("failed to create HTTP client"),
+            throttle: Mutex::default(),
+        }
+    }
+
+    /// Reports a security event, sending an alert in the background unless it is throttled.
+    pub fn report(self: &Arc<Self>, event: SecurityEvent) {
+        let is_failure = matches!(event, SecurityEvent::FailedLogin { .. });
+        let count = self.throttle.lock().unwrap().check(
+            event.throttle_key(),
+            is_failure,
+            Instant::now(),
+            &self.options,
+        );
+        let Some(count) = count else {
+            return;
+        };
+        let alerter = Arc::clone(self);
+        tokio::spawn(async move { alerter.deliver(&event, count).await });
+    }
+
+    async fn deliver(&self, event: &SecurityEvent, count: usize) {
+        let alert = Alert {
+            event,
+            summary: event.summary(count),
+            count,
+            time: Utc::now(),
+        };
+        info!(summary = %alert.summary, "sending security alert");
+
+        if let Some(mailer) = &self.mailer {
+            for recipient in &self.options.email_recipients {
+                if let Err(err) = mailer.send(recipient, "security_alert", &alert).await {
+                    error!(%err, %recipient, "failed to send security alert email");
+                }
+            }
+        }
+
+        if let Some(url) = &self.options.webhook_url {
+            let result = self
+                .http
+                .post(url)
+                .json(&alert)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+            if let Err(err) = result {
+                error!(%err, "failed to send security alert webhook");
+            }
+        }
+    }
+}
+
+/// Bookkeeping for alert thresholds and cooldowns
+#[derive(Debug, Default)]
+struct Throttle {
+    /// Times of recent failures, by throttle key
+    failures: HashMap<String, VecDeque<Instant>>,
+    /// Time of the last alert, by throttle key
+    last_alerts: HashMap<String, Instant>,
+}
+
+impl Throttle {
+    /// Records an occurrence of the event with the given throttle key at time `now`. Returns the
+    /// number of occurrences to report if an alert should be sent, or [`None`] if it is throttled.
+    fn check(
+        &mut self,
+        key: String,
+        is_failure: bool,
+        now: Instant,
+        options: &AlertOptions,
+    ) -> Option<usize> {
+        // Forget about old events so memory use stays bounded
+        self.failures.retain(|_, times| {
+            while times
+                .front()
+                .is_some_and(|&time| now - time > options.failed_login_window)
+            {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+        self.last_alerts
+            .retain(|_, &mut time| now - time < options.cooldown);
+
+        let count = if is_failure {
+            let times = self.failures.entry(key.clone()).or_default();
+            times.push_back(now);
+            if times.len() < options.failed_login_threshold {
+                return None;
+            }
+            times.len()
+        } else {
+            1
+        };
+        if self.last_alerts.contains_key(&key) {
+            return None;
+        }
+        self.last_alerts.insert(key, now);
+        Some(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_threshold() {
+        let options = AlertOptions {
+            failed_login_threshold: 3,
+            ..Default::default()
+        };
+        let mut throttle = Throttle::default();
+        let start = Instant::now();
+        let key = || "failed-login:a@example.com".to_string();
+        assert_eq!(throttle.check(key(), true, start, &options), None);
+        assert_eq!(throttle.check(key(), true, start, &options), None);
+        assert_eq!(throttle.check(key(), true, start, &options), Some(3));
+        // Further failures are suppressed during the cooldown
+        assert_eq!(throttle.check(key(), true, start, &options), None);
+
+        // Failures outside the window are forgotten
+        let later = start + options.cooldown + Duration::from_secs(1);
+        assert_eq!(throttle.check(key(), true, later, &options), None);
+    }
+
+    #[test]
+    fn test_cooldown() {
+        let options = AlertOptions::default();
+        let mut throttle = Throttle::default();
+        let start = Instant::now();
+        let key = || "admin-session-upgrade:a@example.com".to_string();
+        assert_eq!(throttle.check(key(), false, start, &options), Some(1));
+        assert_eq!(throttle.check(key(), false, start, &options), None);
+        assert_eq!(
+            throttle.check("other".to_string(), false, start, &options),
+            Some(1)
+        );
+        let later = start + options.cooldown;
+        assert_eq!(throttle.check(key(), false, later, &options), Some(1));
+    }
+}
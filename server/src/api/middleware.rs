@@ -1,8 +1,30 @@
 //! # Custom HTTP middleware
 
-use axum::http::{HeaderValue, header::CACHE_CONTROL};
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    body::{Body, to_bytes},
+    extract::{ConnectInfo, Request, State},
+    http::{
+        HeaderValue, Method, StatusCode,
+        header::{CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
 use chrono::Duration;
+use serde::Deserialize;
 use tower_http::set_header::SetResponseHeaderLayer;
+use tracing::{debug, error};
+
+use crate::{
+    api::v1::ApiV1Error,
+    bans::IpBanList,
+    client_ip::{ClientIp, TrustedProxies},
+    rate_limit::RateLimiter,
+};
+
+use super::MAX_REQUEST_PAYLOAD_BYTES;
 
 /// Publicity value used in the [`CacheControlLayer`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -107,3 +129,122 @@ impl From<CacheControlLayer> for SetResponseHeaderLayer<HeaderValue> {
         value.finish()
     }
 }
+
+/// Middleware which resolves the [address of the client][ClientIp] which made the request,
+/// looking through the [trusted proxies][TrustedProxies], and stores it in the request's
+/// extensions. The address is only known if the server was started with
+/// [connect info][axum::Router::into_make_service_with_connect_info]; otherwise, nothing is
+/// stored.
+pub async fn resolve_client_ip(
+    State(proxies): State<Arc<TrustedProxies>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(ConnectInfo(addr)) = request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        let ip = proxies.client_ip(addr.ip(), request.headers());
+        request.extensions_mut().insert(ClientIp(ip));
+    }
+    next.run(request).await
+}
+
+/// Middleware which rejects requests from clients whose IP address is [banned][IpBanList] with
+/// `403 Forbidden`. The address is only known once [resolved][resolve_client_ip]; otherwise, all
+/// requests are allowed.
+///
+/// If the ban list can't be loaded, requests are allowed, so that a database outage doesn't lock
+/// everyone out.
+pub async fn reject_banned_clients(
+    State(bans): State<Arc<IpBanList>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(&ClientIp(ip)) = request.extensions().get::<ClientIp>() {
+        match bans.is_banned(ip).await {
+            Ok(true) => {
+                debug!(%ip, "rejecting request from banned address");
+                return ApiV1Error::AddressBanned.into_response();
+            }
+            Ok(false) => (),
+            Err(err) => error!(%err, "failed to check IP ban list"),
+        }
+    }
+    next.run(request).await
+}
+
+/// Middleware which answers conditional `GET` and `HEAD` requests with `304 Not Modified` if the
+/// response's `ETag` matches one listed in the request's `If-None-Match` header, so that clients
+/// revalidating a cached response don't download it again. The handler still runs, so this only
+/// saves work for responses which are cheap to produce.
+pub async fn conditional_requests(request: Request, next: Next) -> Response {
+    let if_none_match = match *request.method() {
+        Method::GET | Method::HEAD => request.headers().get(IF_NONE_MATCH).cloned(),
+        _ => None,
+    };
+    let response = next.run(request).await;
+    let Some(if_none_match) = if_none_match else {
+        return response;
+    };
+    let matches = response.status().is_success()
+        && response
+            .headers()
+            .get(ETAG)
+            .is_some_and(|etag| etag_matches(&if_none_match, etag));
+    if !matches {
+        return response;
+    }
+    let (mut parts, _) = response.into_parts();
+    parts.status = StatusCode::NOT_MODIFIED;
+    parts.headers.remove(CONTENT_TYPE);
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, Body::empty())
+}
+
+/// Returns whether an `If-None-Match` header matches the given entity tag. Tags are compared
+/// weakly, as RFC 9110 requires for this header.
+fn etag_matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    fn opaque(tag: &str) -> &str {
+        tag.strip_prefix("W/").unwrap_or(tag)
+    }
+    let (Ok(if_none_match), Ok(etag)) = (if_none_match.to_str(), etag.to_str()) else {
+        return false;
+    };
+    if_none_match.trim() == "*"
+        || if_none_match
+            .split(',')
+            .any(|tag| opaque(tag.trim()) == opaque(etag))
+}
+
+/// Middleware which rejects requests exceeding the [rate limits][RateLimiter] with
+/// `429 Too Many Requests` and a `Retry-After` header. Requests are limited by the client's IP
+/// address, if it has been [resolved][resolve_client_ip], and by the `email` field of JSON request
+/// bodies. Only the requests which start a login or registration name the email address; the
+/// requests which finish a ceremony are limited by its start, since they can't be made without it.
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    /// Part of request bodies which the limiter looks at
+    #[derive(Deserialize)]
+    struct EmailField {
+        email: String,
+    }
+
+    let ip = request
+        .extensions()
+        .get::<ClientIp>()
+        .map(|&ClientIp(ip)| ip);
+    // The body has to be buffered to find the email address, so put it back afterwards
+    let (parts, body) = request.into_parts();
+    let Ok(body) = to_bytes(body, MAX_REQUEST_PAYLOAD_BYTES).await else {
+        return ApiV1Error::PayloadTooLarge.into_response();
+    };
+    let email = serde_json::from_slice::<EmailField>(&body)
+        .ok()
+        .map(|field| field.email);
+    if let Some(retry_after) = limiter.check(ip, email.as_deref()).await {
+        debug!(?ip, "rejecting rate-limited request");
+        return ApiV1Error::RateLimited(retry_after).into_response();
+    }
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
@@ -1,24 +1,52 @@
 //! # HTTP API routing & handlers
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use aide::openapi::OpenApi;
-use axum::{Router, http::header};
+use axum::{
+    Json, Router,
+    http::{Method, header},
+    middleware::from_fn_with_state,
+    routing::get,
+};
 use tower::ServiceBuilder;
 use tower_http::{
-    limit::RequestBodyLimitLayer, sensitive_headers::SetSensitiveHeadersLayer, trace::TraceLayer,
+    cors::{Any, CorsLayer},
+    sensitive_headers::SetSensitiveHeadersLayer,
+    trace::TraceLayer,
 };
-use webauthn_rs::Webauthn;
 
-use crate::{db::interface::DatabaseClient, models::AppConfig};
+use crate::{
+    alerts::Alerter,
+    bans::{AutoBanOptions, IpBanList},
+    client_ip::TrustedProxies,
+    crypto::jwt::KeySet,
+    db::interface::DatabaseClient,
+    email::Mailer,
+    lockout::LockoutOptions,
+    models::{
+        AppConfig, Branding, FeatureFlags, LoginNotificationPolicy, RegistrationMode,
+        SessionLifetimes, UserDeletionStrategy,
+    },
+    rate_limit::RateLimiter,
+    relying_party::{PasskeyPolicy, RelyingParties},
+    session_cookie::SessionCookieOptions,
+};
 
 mod middleware;
+mod typescript;
 mod utils;
 mod v1;
 
+pub use typescript::generate_typescript_types;
+
 /// Maximum request payload size in bytes
 const MAX_REQUEST_PAYLOAD_BYTES: usize = 8 * 1024; // 8 KiB
 
+/// Maximum request payload size in bytes for endpoints which process their payload as it arrives,
+/// rather than buffering it whole
+const MAX_STREAMED_PAYLOAD_BYTES: usize = 64 * 1024 * 1024; // 64 MiB
+
 /// A collection of API specifications.
 #[derive(Debug, Clone)]
 pub struct ApiSpecs {
@@ -38,20 +66,151 @@ impl From<ApiSpecs> for Vec<OpenApi> {
     }
 }
 
-/// Creates a new API router with the given database client, [`Webauthn`] client, and
-/// [app configuration][AppConfig].
+/// Server-side API options and services which are not exposed to clients
+#[derive(Clone, Default)]
+pub struct ApiOptions {
+    /// Mailer used to send emails to users. If [`None`], no emails are sent.
+    pub mailer: Option<Arc<Mailer>>,
+    /// When to email users about logins to their account
+    pub login_notifications: LoginNotificationPolicy,
+    /// Alerter used to notify administrators of security events. If [`None`], no alerts are sent.
+    pub alerter: Option<Arc<Alerter>>,
+    /// Mailgun webhook signing key with which bounce reports must be signed. If [`None`], the
+    /// bounce webhook is disabled.
+    pub email_webhook_secret: Option<String>,
+    /// Whether deleting a user removes or anonymizes their data
+    pub user_deletion: UserDeletionStrategy,
+    /// When to ban addresses from which logins repeatedly fail. If [`None`], addresses are only
+    /// banned by administrators.
+    pub auto_ban: Option<AutoBanOptions>,
+    /// When to lock accounts to which logins repeatedly fail. If [`None`], accounts are never
+    /// locked.
+    pub lockout: Option<LockoutOptions>,
+    /// How long sessions are cached in memory after being fetched from the database. Zero
+    /// disables the cache.
+    pub session_cache_ttl: Duration,
+    /// Keys with which tokens are signed, as loaded by a
+    /// [`SigningKeyManager`][crate::crypto::signing::SigningKeyManager]. If [`None`], a key is
+    /// generated at startup and kept in memory, so tokens become invalid when the server restarts.
+    pub signing_keys: Option<Arc<KeySet>>,
+    /// Limiter applied to login and registration requests. If [`None`], those requests aren't
+    /// rate-limited.
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// How to hide which email addresses have accounts. If [`None`], login and registration
+    /// requests reveal whether an account exists.
+    pub enumeration_protection: Option<EnumerationProtection>,
+    /// What is asked of authenticators when passkeys are registered and used
+    pub passkey_policy: PasskeyPolicy,
+    /// How session cookies are named and signed
+    pub session_cookies: SessionCookieOptions,
+    /// Reverse proxies whose `X-Forwarded-For` headers are trusted to give the client's address
+    pub trusted_proxies: TrustedProxies,
+}
+
+/// # Account enumeration protection
+///
+/// When enabled, starting a login for an email address without an account returns a decoy
+/// challenge listing credentials which don't exist, instead of an error, registering with an
+/// email address which is taken fails without saying why, and users asking to change their email
+/// address to one which is taken are told that a confirmation link was sent, as for any other
+/// address.
+#[derive(Clone)]
+pub struct EnumerationProtection {
+    /// Secret from which the IDs of decoy credentials are derived, so that repeated logins for the
+    /// same email address get the same decoy. Instances serving the same app must share it.
+    pub secret: Arc<[u8]>,
+}
+
+/// Creates a new API router with the given database client, `WebAuthn`
+/// [relying parties][RelyingParties], [app configuration][AppConfig], and [options][ApiOptions].
 pub fn new_api_router(
     db: Arc<dyn DatabaseClient>,
-    webauthn: Webauthn,
+    relying_parties: RelyingParties,
     config: &AppConfig,
+    options: ApiOptions,
 ) -> (Router<()>, ApiSpecs) {
-    let (v1_router, v1_spec) = v1::router_and_spec(db, webauthn, config);
-    let router = Router::new().nest_service("/v1", v1_router).layer(
-        // order is top to bottom
-        ServiceBuilder::new()
-            .layer(SetSensitiveHeadersLayer::new(vec![header::AUTHORIZATION]))
-            .layer(TraceLayer::new_for_http())
-            .layer(RequestBodyLimitLayer::new(MAX_REQUEST_PAYLOAD_BYTES)),
-    );
+    let bans = Arc::new(IpBanList::new(Arc::clone(&db), options.auto_ban.clone()));
+    let trusted_proxies = Arc::new(options.trusted_proxies.clone());
+    let (v1_router, v1_spec) =
+        v1::router_and_spec(db, relying_parties, config, options, Arc::clone(&bans));
+    // Unknown paths get a JSON error rather than falling through to the UI
+    let router = Router::new()
+        .nest_service("/v1", v1_router)
+        .fallback(v1::not_found)
+        .layer(
+            // order is top to bottom
+            ServiceBuilder::new()
+                .layer(from_fn_with_state(
+                    trusted_proxies,
+                    middleware::resolve_client_ip,
+                ))
+                .layer(from_fn_with_state(bans, middleware::reject_banned_clients))
+                .layer(SetSensitiveHeadersLayer::new(vec![header::AUTHORIZATION]))
+                .layer(TraceLayer::new_for_http()),
+        );
     (router, ApiSpecs { v1: v1_spec })
 }
+
+/// Creates a router for the `/.well-known` endpoints, which are served at the root of the app
+/// rather than under `/api`. Currently this only serves the public signing keys at
+/// `/.well-known/jwks.json`.
+pub fn new_well_known_router(keys: Arc<KeySet>) -> Router<()> {
+    Router::new()
+        .route(
+            "/.well-known/jwks.json",
+            get(async move || match keys.jwks() {
+                Ok(jwks) => Ok(Json(jwks)),
+                Err(err) => {
+                    tracing::error!(%err, "failed to encode signing keys");
+                    Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+                }
+            }),
+        )
+        .layer(
+            ServiceBuilder::new()
+                .layer(
+                    CorsLayer::new()
+                        .allow_origin(Any)
+                        .allow_methods(Method::GET)
+                        .allow_credentials(false),
+                )
+                // Keys are rotated rarely, and retired keys stay published for a while, so
+                // relying parties can cache the key set briefly
+                .layer(
+                    middleware::CacheControlLayer::new()
+                        .publicity(middleware::Publicity::Public)
+                        .max_age(chrono::Duration::minutes(5))
+                        .finish(),
+                ),
+        )
+}
+
+/// Generates the API specifications using placeholder services and configuration. The specs don't
+/// depend on the configuration, so this gives the same result as [`new_api_router()`].
+#[cfg(feature = "sqlite3")]
+pub async fn generate_api_specs() -> ApiSpecs {
+    use crate::db::clients::sqlite::SqliteClient;
+
+    let db = Arc::new(
+        SqliteClient::new_memory()
+            .await
+            .expect("failed to create in-memory database"),
+    );
+    let webauthn =
+        webauthn_rs::WebauthnBuilder::new("localhost", &"http://localhost:3000".parse().unwrap())
+            .unwrap()
+            .rp_name("IAM")
+            .build()
+            .unwrap();
+    let config = AppConfig {
+        instance_name: "IAM".to_string(),
+        base_path: String::new(),
+        branding: Branding::default(),
+        features: FeatureFlags::default(),
+        registration_mode: RegistrationMode::default(),
+        allowed_email_domains: Vec::new(),
+        session_lifetimes: SessionLifetimes::default(),
+    };
+    let (_router, specs) = new_api_router(db, webauthn.into(), &config, ApiOptions::default());
+    specs
+}
@@ -0,0 +1,302 @@
+//! # TypeScript type generation
+//!
+//! Generates TypeScript declarations for the API models from their JSON schemas, so clients
+//! written in TypeScript (including the bundled UI) can use types which match the server's.
+
+use std::fmt::Write;
+
+use schemars::{JsonSchema, SchemaGenerator, generate::SchemaSettings};
+use serde_json::{Map, Value};
+
+use crate::{
+    api::v1,
+    models::{
+        Announcement, ApiToken, ApiTokenScope, AppConfig, EmailStatus, EmailSuppression,
+        FederatedIdentity, FederatedProvider, FederatedProviderUpdate, IpBan, OAuthClient,
+        OAuthClientUpdate, PasskeyCredential, Permission, Policy, PolicyDocument, PolicyEffect,
+        PolicyStatement, PolicyUpdate, PolicyVersion, Role, RoleUpdate, SamlNameIdFormat,
+        SamlServiceProvider, SamlServiceProviderUpdate, Session, Tag, User, UserCreate, UserMerge,
+        UserNote, UserUpdate, Webhook, WebhookEventType, WebhookUpdate,
+    },
+};
+
+/// Generates TypeScript declarations for the v1 API models, as the contents of a module.
+#[must_use]
+pub fn generate_typescript_types() -> String {
+    let mut generator = SchemaSettings::draft2020_12()
+        .for_serialize()
+        .into_generator();
+    add_schemas(&mut generator);
+    let mut definitions: Vec<_> = generator.take_definitions(true).into_iter().collect();
+    definitions.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut out = String::from(
+        "// Generated by `ts-generator` from the server's API models. Do not edit by hand.\n\n\
+        export type Uuid = string;\n\n\
+        /** RFC 3339 date and time */\n\
+        export type DateTime = string;\n",
+    );
+    let mut status_codes: Vec<_> = v1::error_status_codes()
+        .iter()
+        .map(axum::http::StatusCode::as_u16)
+        .collect();
+    status_codes.sort_unstable();
+    let status_codes: Vec<_> = status_codes.iter().map(u16::to_string).collect();
+    write!(
+        out,
+        "\n/** HTTP status codes with which the API responds to errors */\n\
+        export type ApiErrorStatus = {};\n",
+        status_codes.join(" | ")
+    )
+    .unwrap();
+    for (name, schema) in definitions {
+        out.push('\n');
+        write_doc_comment(&mut out, &schema, "");
+        match object_properties(&schema) {
+            Some(properties) => {
+                writeln!(out, "export interface {name} {{").unwrap();
+                write_properties(&mut out, &schema, properties, "    ");
+                out.push_str("}\n");
+            }
+            None => writeln!(out, "export type {name} = {};", ts_type(&schema)).unwrap(),
+        }
+    }
+    out
+}
+
+/// Adds the schemas of all models exposed by the API to the generator's definitions.
+fn add_schemas(generator: &mut SchemaGenerator) {
+    fn add<T: JsonSchema>(generator: &mut SchemaGenerator) {
+        generator.subschema_for::<T>();
+    }
+    add::<v1::ApiErrorResponse>(generator);
+    add::<v1::ProblemDetails>(generator);
+    add::<Announcement>(generator);
+    add::<ApiToken>(generator);
+    add::<ApiTokenScope>(generator);
+    add::<AppConfig>(generator);
+    add::<EmailStatus>(generator);
+    add::<EmailSuppression>(generator);
+    add::<FederatedIdentity>(generator);
+    add::<FederatedProvider>(generator);
+    add::<FederatedProviderUpdate>(generator);
+    add::<IpBan>(generator);
+    add::<OAuthClient>(generator);
+    add::<OAuthClientUpdate>(generator);
+    add::<PasskeyCredential>(generator);
+    add::<Permission>(generator);
+    add::<Policy>(generator);
+    add::<PolicyDocument>(generator);
+    add::<PolicyEffect>(generator);
+    add::<PolicyStatement>(generator);
+    add::<PolicyUpdate>(generator);
+    add::<PolicyVersion>(generator);
+    add::<Role>(generator);
+    add::<RoleUpdate>(generator);
+    add::<SamlNameIdFormat>(generator);
+    add::<SamlServiceProvider>(generator);
+    add::<SamlServiceProviderUpdate>(generator);
+    add::<Session>(generator);
+    add::<Tag>(generator);
+    add::<User>(generator);
+    add::<UserCreate>(generator);
+    add::<UserMerge>(generator);
+    add::<UserNote>(generator);
+    add::<UserUpdate>(generator);
+    add::<Webhook>(generator);
+    add::<WebhookEventType>(generator);
+    add::<WebhookUpdate>(generator);
+}
+
+/// Returns the properties of the schema if it describes an object with known properties.
+fn object_properties(schema: &Value) -> Option<&Map<String, Value>> {
+    if schema.get("type")? != "object" {
+        return None;
+    }
+    schema.get("properties")?.as_object()
+}
+
+/// Writes the object's properties as the members of an interface or object type.
+fn write_properties(
+    out: &mut String,
+    schema: &Value,
+    properties: &Map<String, Value>,
+    indent: &str,
+) {
+    for (name, property) in properties {
+        write_doc_comment(out, property, indent);
+        let optional = if is_required(schema.as_object().unwrap(), name) {
+            ""
+        } else {
+            "?"
+        };
+        writeln!(out, "{indent}{name}{optional}: {};", ts_type(property)).unwrap();
+    }
+}
+
+/// Returns whether the object schema lists the given property as required.
+fn is_required(schema: &Map<String, Value>, property: &str) -> bool {
+    schema
+        .get("required")
+        .and_then(Value::as_array)
+        .is_some_and(|required| required.iter().any(|field| field == property))
+}
+
+/// Writes the schema's description, if any, as a `JSDoc` comment.
+fn write_doc_comment(out: &mut String, schema: &Value, indent: &str) {
+    let Some(description) = schema.get("description").and_then(Value::as_str) else {
+        return;
+    };
+    writeln!(out, "{indent}/**").unwrap();
+    for line in description.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            writeln!(out, "{indent} *").unwrap();
+        } else {
+            writeln!(out, "{indent} * {}", line.replace("*/", "*\\/")).unwrap();
+        }
+    }
+    writeln!(out, "{indent} */").unwrap();
+}
+
+/// Returns the TypeScript type expression for the given schema.
+fn ts_type(schema: &Value) -> String {
+    let Some(schema) = schema.as_object() else {
+        // `true` accepts any value and `false` accepts none
+        return if schema == &Value::Bool(false) {
+            "never".to_string()
+        } else {
+            "unknown".to_string()
+        };
+    };
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        return reference
+            .rsplit('/')
+            .next()
+            .unwrap_or(reference)
+            .to_string();
+    }
+    if let Some(value) = schema.get("const") {
+        return value.to_string();
+    }
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        return union(values.iter().map(Value::to_string));
+    }
+    for key in ["allOf", "anyOf", "oneOf"] {
+        if let Some(variants) = schema.get(key).and_then(Value::as_array) {
+            // Only single-element `allOf`s are generated, e.g. to attach a description to a `$ref`
+            let separator = if key == "allOf" { " & " } else { " | " };
+            let variants: Vec<_> = variants.iter().map(ts_type).collect();
+            return variants.join(separator);
+        }
+    }
+    match schema.get("type") {
+        Some(Value::String(ty)) => primitive_type(ty, schema),
+        Some(Value::Array(types)) => union(
+            types
+                .iter()
+                .filter_map(Value::as_str)
+                .map(|ty| primitive_type(ty, schema)),
+        ),
+        _ => "unknown".to_string(),
+    }
+}
+
+/// Returns the TypeScript type for a schema of the given JSON type.
+fn primitive_type(ty: &str, schema: &Map<String, Value>) -> String {
+    match ty {
+        "string" => match schema.get("format").and_then(Value::as_str) {
+            Some("uuid") => "Uuid".to_string(),
+            Some("date-time") => "DateTime".to_string(),
+            _ => "string".to_string(),
+        },
+        "integer" | "number" => "number".to_string(),
+        "boolean" => "boolean".to_string(),
+        "null" => "null".to_string(),
+        "array" => {
+            let items = schema.get("items").map_or("unknown".to_string(), ts_type);
+            if items.contains(' ') {
+                format!("({items})[]")
+            } else {
+                format!("{items}[]")
+            }
+        }
+        "object" => {
+            if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+                let mut out = String::from("{ ");
+                for (name, property) in properties {
+                    let optional = if is_required(schema, name) { "" } else { "?" };
+                    write!(out, "{name}{optional}: {}; ", ts_type(property)).unwrap();
+                }
+                out.push('}');
+                out
+            } else {
+                let values = schema
+                    .get("additionalProperties")
+                    .map_or("unknown".to_string(), ts_type);
+                format!("Record<string, {values}>")
+            }
+        }
+        _ => "unknown".to_string(),
+    }
+}
+
+/// Joins the types into a union, without duplicates.
+fn union(types: impl Iterator<Item = String>) -> String {
+    let mut unique: Vec<String> = Vec::new();
+    for ty in types {
+        if !unique.contains(&ty) {
+            unique.push(ty);
+        }
+    }
+    unique.join(" | ")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_ts_type() {
+        assert_eq!(
+            ts_type(&json!({ "type": ["string", "null"] })),
+            "string | null"
+        );
+        assert_eq!(
+            ts_type(&json!({ "type": "string", "format": "uuid" })),
+            "Uuid"
+        );
+        assert_eq!(
+            ts_type(&json!({ "type": "array", "items": { "$ref": "#/$defs/Tag" } })),
+            "Tag[]"
+        );
+        assert_eq!(
+            ts_type(&json!({ "type": "array", "items": { "type": ["string", "null"] } })),
+            "(string | null)[]"
+        );
+        assert_eq!(
+            ts_type(&json!({ "oneOf": [{ "const": "active" }, { "const": "revoked" }] })),
+            "\"active\" | \"revoked\""
+        );
+        assert_eq!(
+            ts_type(&json!({
+                "type": "object",
+                "properties": { "status": { "const": "ok" }, "reason": { "type": "string" } },
+                "required": ["status"],
+            })),
+            "{ reason?: string; status: \"ok\"; }"
+        );
+    }
+
+    #[test]
+    fn test_generate_typescript_types() {
+        let types = generate_typescript_types();
+        assert!(types.contains("export interface User {"));
+        assert!(types.contains("export type SessionState ="));
+        assert!(types.contains(
+            "export type ApiErrorStatus = 400 | 401 | 403 | 404 | 409 | 422 | 500 | 502;"
+        ));
+    }
+}
@@ -3,14 +3,24 @@
 use std::marker::PhantomData;
 
 use aide::{
+    OperationInput, OperationOutput,
     generate::GenContext,
     openapi::{Operation, Response},
-    OperationOutput,
 };
-use axum::{body::Bytes, http::header::CONTENT_TYPE, response::IntoResponse};
+use axum::{
+    body::Bytes,
+    extract::{FromRequestParts, Query, rejection::QueryRejection},
+    http::{
+        HeaderValue,
+        header::{CONTENT_TYPE, ETAG},
+        request::Parts,
+    },
+    response::IntoResponse,
+};
 use axum_extra::extract::CookieJar;
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
 use schemars::JsonSchema;
-use serde::Serialize;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
 /// # Pre-serialized JSON response
 ///
@@ -20,7 +30,9 @@ use serde::Serialize;
 ///
 /// [`PreSerializedJson::new()`] serializes the input object and stores the
 /// resulting JSON buffer, re-using that every time it is converted into a
-/// response via [`IntoResponse`].
+/// response via [`IntoResponse`]. Responses carry a strong `ETag` derived from the JSON, so that
+/// clients can revalidate cached copies with
+/// [conditional requests][super::middleware::conditional_requests].
 ///
 /// [`PreSerializedJson`] is cheaply cloneable and so does not need to be
 /// wrapped in an [`Arc`][std::sync::Arc].
@@ -44,13 +56,18 @@ use serde::Serialize;
 #[derive(Debug, Clone)]
 pub struct PreSerializedJson<T: ?Sized + Serialize> {
     json_bytes: Bytes,
+    etag: HeaderValue,
     type_marker: PhantomData<T>,
 }
 
 impl<T: ?Sized + Serialize> PreSerializedJson<T> {
     pub fn new(value: &T) -> Result<Self, serde_json::Error> {
+        let json = serde_json::to_vec(value)?;
+        let etag = HeaderValue::try_from(format!("\"{}\"", blake3::hash(&json)))
+            .expect("hex digests are valid header values");
         Ok(Self {
-            json_bytes: Bytes::from_owner(serde_json::to_vec(value)?),
+            json_bytes: Bytes::from_owner(json),
+            etag,
             type_marker: PhantomData,
         })
     }
@@ -58,7 +75,14 @@ impl<T: ?Sized + Serialize> PreSerializedJson<T> {
 
 impl<T: ?Sized + Serialize> IntoResponse for PreSerializedJson<T> {
     fn into_response(self) -> axum::response::Response {
-        ([(CONTENT_TYPE, "application/json")], self.json_bytes).into_response()
+        (
+            [
+                (CONTENT_TYPE, HeaderValue::from_static("application/json")),
+                (ETAG, self.etag),
+            ],
+            self.json_bytes,
+        )
+            .into_response()
     }
 }
 
@@ -167,3 +191,140 @@ where
         T::inferred_responses(ctx, operation)
     }
 }
+
+/// Number of items listed per page if the client doesn't ask for a specific number
+pub const DEFAULT_PAGE_SIZE: u32 = 50;
+/// Maximum number of items listed per page
+pub const MAX_PAGE_SIZE: u32 = 200;
+
+/// Query parameters selecting a page of a list
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PaginationParams {
+    /// Maximum number of items to return, up to 200. Defaults to 50.
+    pub limit: Option<u32>,
+    /// Cursor returned by the previous request, to get the next page. Other parameters, such as
+    /// the sort order, must be the same as in the previous request.
+    pub cursor: Option<String>,
+}
+
+/// # Pagination extractor
+///
+/// [`Pagination`] reads the `limit` and `cursor` query parameters of list endpoints, which respond
+/// with a [`Page`]. The limit is clamped to the range `1..=`[`MAX_PAGE_SIZE`].
+///
+/// Cursors are opaque to clients. They encode the position of the last item of the previous page,
+/// e.g. the value the list is sorted by and an ID, so that the next page starts after it even if
+/// items are added or removed in the meantime.
+#[derive(Debug, Clone)]
+pub struct Pagination {
+    /// Maximum number of items on the page
+    pub limit: u32,
+    /// Encoded cursor, if the client asked for a page other than the first
+    pub cursor: Option<String>,
+}
+
+impl Pagination {
+    /// Decodes the cursor given by the client, which was encoded by [`Page::new()`].
+    pub fn after<C: DeserializeOwned>(&self) -> Result<Option<C>, InvalidCursor> {
+        self.cursor.as_deref().map(decode_cursor).transpose()
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Pagination {
+    type Rejection = QueryRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(params) = Query::<PaginationParams>::from_request_parts(parts, state).await?;
+        Ok(Pagination {
+            limit: params
+                .limit
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+            cursor: params.cursor,
+        })
+    }
+}
+
+/// Same effect on the API spec as [`Query<PaginationParams>`].
+impl OperationInput for Pagination {
+    fn operation_input(ctx: &mut GenContext, operation: &mut Operation) {
+        Query::<PaginationParams>::operation_input(ctx, operation);
+    }
+
+    fn inferred_early_responses(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Vec<(Option<u16>, Response)> {
+        Query::<PaginationParams>::inferred_early_responses(ctx, operation)
+    }
+}
+
+/// Error returned when a pagination cursor can't be decoded
+#[derive(Debug, Copy, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid pagination cursor")]
+pub struct InvalidCursor;
+
+/// # Page of a list
+///
+/// Lists are returned one page at a time. To get the next page, request the list again with the
+/// `cursor` query parameter set to the `nextCursor` of this page.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+    /// Items on this page
+    pub items: Vec<T>,
+    /// Cursor with which to request the next page, if there is one
+    pub next_cursor: Option<String>,
+    /// Total number of items in the list
+    pub total: u64,
+}
+
+impl<T> Page<T> {
+    /// Creates a page of the given items, encoding the position of the last one, if there are
+    /// more items after it.
+    pub fn new<C: Serialize>(items: Vec<T>, total: u64, next: Option<&C>) -> Self {
+        Self {
+            items,
+            next_cursor: next.map(encode_cursor),
+            total,
+        }
+    }
+
+    /// Returns the requested page of a complete list, for lists which are small enough to be
+    /// loaded at once. The list must be sorted by `key`, which gives the position of an item, and
+    /// should be unique.
+    pub fn from_sorted<C>(
+        mut items: Vec<T>,
+        pagination: &Pagination,
+        key: impl Fn(&T) -> C,
+    ) -> Result<Self, InvalidCursor>
+    where
+        C: Ord + Serialize + DeserializeOwned,
+    {
+        let total = items.len() as u64;
+        if let Some(after) = pagination.after::<C>()? {
+            items.retain(|item| key(item) > after);
+        }
+        let limit = pagination.limit as usize;
+        let next = (items.len() > limit).then(|| {
+            items.truncate(limit);
+            key(&items[limit - 1])
+        });
+        Ok(Self::new(items, total, next.as_ref()))
+    }
+}
+
+/// Encodes a cursor as an opaque string for clients.
+fn encode_cursor<C: Serialize>(cursor: &C) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(serde_json::to_vec(cursor).expect("serializing cursor failed"))
+}
+
+/// Decodes a cursor encoded by [`encode_cursor()`].
+fn decode_cursor<C: DeserializeOwned>(cursor: &str) -> Result<C, InvalidCursor> {
+    BASE64_URL_SAFE_NO_PAD
+        .decode(cursor)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or(InvalidCursor)
+}
@@ -0,0 +1,76 @@
+//! # v1 announcement API endpoint handlers
+
+use axum::{Json, extract::State};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{
+    api::v1::{ApiV1Error, V1State, extractors::AdminSession},
+    db::interface::DatabaseError,
+    models::{Announcement, AnnouncementSeverity},
+};
+
+/// Request to set the instance announcement
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnouncementRequest {
+    pub message: String,
+    #[serde(default)]
+    pub severity: AnnouncementSeverity,
+    /// Time from which the announcement is shown. If omitted, it is shown immediately.
+    pub starts_at: Option<DateTime<Utc>>,
+    /// Time after which the announcement is no longer shown. If omitted, it is shown until it is
+    /// removed.
+    pub ends_at: Option<DateTime<Utc>>,
+}
+
+/// Returns the instance announcement if one is currently active, or `null` otherwise.
+pub async fn get_announcement(
+    State(state): State<V1State>,
+) -> Result<Json<Option<Announcement>>, ApiV1Error> {
+    match state.db.get_announcement().await {
+        Ok(announcement) if announcement.is_active(Utc::now()) => Ok(Json(Some(announcement))),
+        Ok(_) | Err(DatabaseError::NotFound) => Ok(Json(None)),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Sets the instance announcement, replacing the current one if there is one.
+pub async fn put_announcement(
+    AdminSession(session): AdminSession,
+    State(state): State<V1State>,
+    Json(request): Json<AnnouncementRequest>,
+) -> Result<Json<Announcement>, ApiV1Error> {
+    if request.message.trim().is_empty() {
+        return Err(ApiV1Error::InvalidAnnouncement("message must not be empty"));
+    }
+    if let (Some(starts_at), Some(ends_at)) = (request.starts_at, request.ends_at)
+        && ends_at <= starts_at
+    {
+        return Err(ApiV1Error::InvalidAnnouncement(
+            "end time must be after start time",
+        ));
+    }
+    let announcement = Announcement {
+        message: request.message,
+        severity: request.severity,
+        starts_at: request.starts_at,
+        ends_at: request.ends_at,
+        updated_at: Utc::now(),
+    };
+    state.db.set_announcement(&announcement).await?;
+    info!(admin = %session.user_id, severity = ?announcement.severity, "announcement set");
+    Ok(Json(announcement))
+}
+
+/// Removes the instance announcement.
+pub async fn delete_announcement(
+    AdminSession(session): AdminSession,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.delete_announcement().await?;
+    info!(admin = %session.user_id, "announcement removed");
+    Ok(())
+}
@@ -1,42 +1,75 @@
 //! # v1 authentication-related API endpoint handlers
 
-use std::borrow::Cow;
+use std::{borrow::Cow, cmp::Reverse};
 
 use axum::{Json, extract::State};
 use axum_extra::extract::{
     Cached, CookieJar,
     cookie::{Cookie, Expiration, SameSite},
 };
-use base64::{Engine, prelude::BASE64_STANDARD};
+use base64::{
+    Engine,
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+};
 use cookie::{CookieBuilder, time::Duration};
+use hmac::{Hmac, Mac};
 use rand::RngCore;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, error, warn};
+use sha2::Sha256;
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
-use webauthn_rs::prelude::{
-    AuthenticationResult, CreationChallengeResponse, DiscoverableKey, Passkey, PublicKeyCredential,
-    RegisterPublicKeyCredential, RequestChallengeResponse, WebauthnError,
+use webauthn_rs::{
+    Webauthn,
+    prelude::{
+        AuthenticationResult, CreationChallengeResponse, CredentialID, DiscoverableKey, Passkey,
+        PublicKeyCredential, RegisterPublicKeyCredential, RequestChallengeResponse, WebauthnError,
+    },
 };
-use webauthn_rs_proto::{AuthenticatorSelectionCriteria, ResidentKeyRequirement};
+use webauthn_rs_proto::{AllowCredentials, AuthenticatorTransport};
 
 use crate::{
-    api::{utils::WithCookies, v1::{extractors::AuthenticatedSession, ApiV1Error, V1State}},
-    db::interface::{DatabaseClient, DatabaseError},
+    alerts::SecurityEvent,
+    api::{
+        EnumerationProtection,
+        utils::{Page, Pagination, WithCookies},
+        v1::{
+            ApiV1Error, V1State, V1StateInner,
+            extractors::{
+                AdminSession, Authenticated, AuthenticatedSession, ClientInfo, FreshAuthentication,
+                RelyingParty, RequirePermission, ValidatedJson, permission,
+            },
+            notify,
+            role::ensure_can_manage,
+        },
+    },
+    authenticators::default_passkey_name,
+    db::interface::DatabaseError,
+    lockout,
     models::{
-        NewPasskeyCredential, PasskeyAuthenticationState, PasskeyAuthenticationStateType,
-        PasskeyCredentialUpdate, PasskeyRegistrationState, Session, SessionState, SessionUpdate,
-        User, UserCreate, ViaJson,
+        EncodableHash, LoginNotificationPolicy, NewPasskeyCredential, PasskeyAttestation,
+        PasskeyAuthenticationState, PasskeyAuthenticationStateType, PasskeyCredentialUpdate,
+        PasskeyRegistrationState, Permission, Permissions, RegistrationMode, Session, SessionState,
+        SessionUpdate, User, UserCreate, UserStatus, ViaJson, new_uuid, normalize_email,
     },
+    session_cookie::SessionSecret,
+    webhooks::WebhookEvent,
 };
 
 const REGISTRATION_ID_COOKIE: &str = "registration_id";
 const AUTHENTICATION_ID_COOKIE: &str = "authentication_id";
-pub const SESSION_ID_COOKIE: &str = "session_id";
 const IS_ADMIN_COOKIE: &str = "session_is_admin";
-const SESSION_DURATION: chrono::Duration = chrono::Duration::days(1);
+const DEVICE_ID_COOKIE: &str = "device_id";
+/// Lifetime of the device ID cookie. Browsers cap cookie lifetimes at 400 days.
+const DEVICE_ID_DURATION: Duration = Duration::days(400);
 
-fn new_secure_cookie<'a, K, V>(name: K, value: V) -> CookieBuilder<'a>
+/// Creates a builder for a secure, HTTP-only cookie scoped to the app's
+/// [cookie path][V1StateInner::cookie_path].
+pub(super) fn new_secure_cookie<'a, K, V>(
+    state: &V1StateInner,
+    name: K,
+    value: V,
+) -> CookieBuilder<'a>
 where
     K: Into<Cow<'a, str>>,
     V: Into<Cow<'a, str>>,
@@ -45,57 +78,122 @@ where
         .same_site(SameSite::Strict)
         .http_only(true)
         .secure(true)
-        .path("/")
+        .path(state.cookie_path().to_owned())
 }
 
 pub async fn start_registration(
     cookies: CookieJar,
     State(state): State<V1State>,
-    Json(request): Json<UserCreate>,
+    RelyingParty(webauthn): RelyingParty,
+    session: Option<AuthenticatedSession>,
+    ValidatedJson(request): ValidatedJson<UserCreate>,
 ) -> Result<WithCookies<Json<CreationChallengeResponse>>, ApiV1Error> {
-    let user_id = Uuid::new_v4();
-    let (mut challenge, reg) = state.webauthn.start_passkey_registration(
-        user_id,
-        &request.email,
+    check_registration_allowed(&state, &request.email)?;
+    let email = normalize_email(&request.email);
+    let exclude = registration_exclusions(&state, &email, session.as_ref()).await?;
+    begin_registration(
+        cookies,
+        &state,
+        &webauthn,
+        new_uuid(),
+        email,
         &request.display_name,
-        None,
-    )?;
+        Some(exclude),
+    )
+    .await
+}
 
-    // Prefer resident keys
-    challenge.public_key.authenticator_selection = Some(AuthenticatorSelectionCriteria {
-        resident_key: Some(ResidentKeyRequirement::Preferred),
-        ..Default::default()
-    });
+/// Returns the credentials to exclude from a registration with the given email address. If the
+/// client is logged in to the account which has the address, these are the account's passkeys,
+/// so that retrying a registration which already succeeded doesn't make the authenticator create
+/// a duplicate credential. Otherwise, nothing is excluded, so that the exclusions reveal neither
+/// whether an address is taken nor the IDs of anyone else's credentials.
+async fn registration_exclusions(
+    state: &V1StateInner,
+    email: &str,
+    session: Option<&AuthenticatedSession>,
+) -> Result<Vec<CredentialID>, ApiV1Error> {
+    let Some(AuthenticatedSession(session)) = session else {
+        return Ok(Vec::new());
+    };
+    let user = state.db.get_user_by_id(&session.user_id).await?;
+    if user.email() != email {
+        return Ok(Vec::new());
+    }
+    Ok(state
+        .db
+        .get_passkeys_by_user_id(user.id())
+        .await?
+        .into_iter()
+        .map(|passkey| passkey.passkey.0.cred_id().clone())
+        .collect())
+}
+
+/// Checks that users may register themselves with the given email address, according to the
+/// [registration mode][RegistrationMode] and allowed email domains.
+pub(super) fn check_registration_allowed(
+    state: &V1StateInner,
+    email: &str,
+) -> Result<(), ApiV1Error> {
+    if state.config.registration_mode != RegistrationMode::Open {
+        return Err(ApiV1Error::RegistrationClosed);
+    }
+    if !state.config.is_email_domain_allowed(email) {
+        return Err(ApiV1Error::EmailDomainNotAllowed);
+    }
+    Ok(())
+}
+
+/// Starts a passkey registration ceremony for the given user with the given relying party, storing
+/// its state and setting the registration ID cookie. Credentials in `exclude` are rejected by the
+/// authenticator, so that one can't be registered twice.
+pub(super) async fn begin_registration(
+    cookies: CookieJar,
+    state: &V1StateInner,
+    webauthn: &Webauthn,
+    user_id: Uuid,
+    email: String,
+    display_name: &str,
+    exclude: Option<Vec<CredentialID>>,
+) -> Result<WithCookies<Json<CreationChallengeResponse>>, ApiV1Error> {
+    let (mut challenge, reg) =
+        webauthn.start_passkey_registration(user_id, &email, display_name, exclude)?;
+    let reg = state
+        .options
+        .passkey_policy
+        .apply_to_registration(&mut challenge, reg);
 
     let reg_state = PasskeyRegistrationState {
-        id: Uuid::new_v4(),
+        id: new_uuid(),
         user_id,
-        email: request.email,
+        email,
+        display_name: display_name.to_string(),
         registration: ViaJson(reg),
         created_at: chrono::Utc::now(),
     };
     state.db.create_passkey_registration(&reg_state).await?;
     Ok((
         cookies.add(
-            new_secure_cookie(REGISTRATION_ID_COOKIE, reg_state.id.to_string())
+            new_secure_cookie(state, REGISTRATION_ID_COOKIE, reg_state.id.to_string())
                 .expires(Expiration::Session),
         ),
         Json(challenge),
-    ).into())
-}
-
-#[derive(Debug, Clone, Deserialize, JsonSchema)]
-pub struct FinishRegistrationRequest {
-    pub user: UserCreate,
-    pub passkey: RegisterPublicKeyCredential,
+    )
+        .into())
 }
 
-pub async fn finish_registration(
+/// Finishes the passkey registration ceremony identified by the registration ID cookie with the
+/// relying party which started it, consuming its state. Returns the cookie jar with the
+/// registration ID cookie removed, along with the ceremony's state and the newly registered
+/// passkey. Fails if the [attestation policy][crate::relying_party::AttestationPolicy] doesn't
+/// allow the passkey's authenticator.
+pub(super) async fn complete_registration(
     cookies: CookieJar,
-    State(state): State<V1State>,
-    Json(request): Json<FinishRegistrationRequest>,
-) -> Result<WithCookies<Json<User>>, ApiV1Error> {
-    let Some(registration_id_cookie) = cookies.get("registration_id") else {
+    state: &V1StateInner,
+    webauthn: &Webauthn,
+    credential: &RegisterPublicKeyCredential,
+) -> Result<(CookieJar, PasskeyRegistrationState, Passkey), ApiV1Error> {
+    let Some(registration_id_cookie) = cookies.get(REGISTRATION_ID_COOKIE) else {
         return Err(ApiV1Error::InvalidRegistrationId);
     };
     let Ok(registration_id) = Uuid::parse_str(registration_id_cookie.value()) else {
@@ -109,40 +207,99 @@ pub async fn finish_registration(
     if reg_state.created_at < five_minutes_ago {
         return Err(ApiV1Error::SessionExpired);
     }
-    let passkey = state
-        .webauthn
-        .finish_passkey_registration(&request.passkey, &reg_state.registration)?;
-    let new_passkey = NewPasskeyCredential {
-        display_name: None,
-        passkey,
-    };
-    let user = state
-        .db
-        .create_user(&reg_state.user_id, &request.user)
-        .await?;
+    let passkey = webauthn.finish_passkey_registration(credential, &reg_state.registration)?;
+    let attestation = PasskeyAttestation::from(&passkey);
+    if !state
+        .options
+        .passkey_policy
+        .attestation
+        .allows(&attestation)
+    {
+        info!(
+            format = attestation.format_name(),
+            aaguid = ?attestation.aaguid,
+            "refusing passkey from authenticator not allowed by the attestation policy",
+        );
+        return Err(ApiV1Error::AuthenticatorNotAllowed);
+    }
+    // Consume the registration state so that the ceremony can't be replayed
     match state
         .db
-        .create_passkey(&Uuid::new_v4(), user.id(), &new_passkey)
+        .delete_passkey_registration_by_id(&registration_id)
         .await
     {
-        Ok(_passkey) => (),
-        Err(err) => {
-            warn!(
-                "Passkey creation failed after user creation succeeded for {}: {err}",
-                user.email()
-            );
-            // If the passkey creation fails, the whole registration is invalidated.
-            if let Err(e2) = state.db.delete_user_by_id(user.id()).await {
-                error!("Failed to delete user after passkey creation failure: {e2}");
-            }
-            return Err(err.into());
+        Ok(()) => (),
+        Err(DatabaseError::NotFound) => return Err(ApiV1Error::InvalidRegistrationId),
+        Err(err) => return Err(err.into()),
+    }
+    let cookies = cookies.remove(new_secure_cookie(state, REGISTRATION_ID_COOKIE, ""));
+    Ok((cookies, reg_state, passkey))
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct FinishRegistrationRequest {
+    pub passkey: RegisterPublicKeyCredential,
+}
+
+pub async fn finish_registration(
+    cookies: CookieJar,
+    State(state): State<V1State>,
+    RelyingParty(webauthn): RelyingParty,
+    client: ClientInfo,
+    Json(request): Json<FinishRegistrationRequest>,
+) -> Result<WithCookies<Json<User>>, ApiV1Error> {
+    let (cookies, reg_state, passkey) =
+        complete_registration(cookies, &state, &webauthn, &request.passkey).await?;
+    // The user is created from the details which were checked when the ceremony started
+    let new_user = UserCreate {
+        email: reg_state.email,
+        display_name: reg_state.display_name,
+    };
+    let new_passkey = NewPasskeyCredential {
+        display_name: default_passkey_name(&request.passkey, client.user_agent.as_deref()),
+        passkey,
+    };
+    // Create the user and their passkey together, so that a user can't be left without a passkey
+    let mut tx = state.db.begin().await?;
+    let user = match tx.create_user(&reg_state.user_id, &new_user).await {
+        Ok(user) => user,
+        Err(DatabaseError::UniquenessViolation { .. }) => {
+            // Roll back before looking up the account which has the address
+            drop(tx);
+            return Err(email_taken(&state, &new_user.email, &client).await);
         }
+        Err(err) => return Err(err.into()),
+    };
+    let passkey = tx
+        .create_passkey(&new_uuid(), user.id(), &new_passkey)
+        .await?;
+    tx.commit().await?;
+    state.emit(WebhookEvent::UserCreated {
+        user_id: *user.id(),
+        email: user.email().to_string(),
+        display_name: user.display_name().to_string(),
+    });
+    state.emit(WebhookEvent::PasskeyCreated {
+        user_id: *user.id(),
+        passkey_id: passkey.id,
+        display_name: passkey.display_name,
+    });
+    let (_session, cookies) = new_session(cookies, &state, user.id(), false, &client).await?;
+    Ok((cookies, Json(user)).into())
+}
+
+/// Returns the error for a registration with an email address which is already taken. With
+/// [enumeration protection][EnumerationProtection], the error doesn't say why the registration
+/// failed, and the owner of the address is told about the attempt by email instead.
+async fn email_taken(state: &V1StateInner, email: &str, client: &ClientInfo) -> ApiV1Error {
+    if state.options.enumeration_protection.is_none() {
+        return ApiV1Error::EmailTaken;
     }
-    let (_session, cookies) = new_session(cookies, &*state.db, user.id(), false, None).await?;
-    Ok((
-        cookies.remove(new_secure_cookie(REGISTRATION_ID_COOKIE, "")),
-        Json(user),
-    ).into())
+    match state.db.get_user_by_email(email).await {
+        Ok(user) => notify::registration_attempt(state, &user, client),
+        Err(err) => warn!(%err, "failed to look up the user with a taken email address"),
+    }
+    ApiV1Error::RegistrationFailed
 }
 
 #[derive(Debug, Clone, Deserialize, JsonSchema)]
@@ -153,21 +310,43 @@ pub struct AuthenticationStartRequest {
 pub async fn start_authentication(
     cookies: CookieJar,
     State(state): State<V1State>,
+    RelyingParty(webauthn): RelyingParty,
     Json(request): Json<AuthenticationStartRequest>,
 ) -> Result<WithCookies<Json<RequestChallengeResponse>>, ApiV1Error> {
+    let email = normalize_email(&request.email);
     let passkeys: Vec<Passkey> = state
         .db
-        .get_passkeys_by_user_email(&request.email)
+        .get_passkeys_by_user_email(&email)
         .await?
         .into_iter()
         .map(std::convert::Into::into)
         .collect();
-    let (challenge, auth_state) = state.webauthn.start_passkey_authentication(&passkeys)?;
-    let auth_id = Uuid::new_v4();
+    let (challenge, auth_state_type, email) = match &state.options.enumeration_protection {
+        // Unknown addresses get a decoy, whose state is stored like that of a real login, so that
+        // both take about as long
+        Some(protection) if passkeys.is_empty() => (
+            decoy_challenge(&state, &webauthn, protection, &email)?,
+            PasskeyAuthenticationStateType::Decoy,
+            None,
+        ),
+        _ => {
+            let (mut challenge, auth_state) = webauthn.start_passkey_authentication(&passkeys)?;
+            let auth_state = state
+                .options
+                .passkey_policy
+                .apply_to_authentication(&mut challenge, auth_state);
+            (
+                challenge,
+                PasskeyAuthenticationStateType::Regular(auth_state),
+                Some(email),
+            )
+        }
+    };
+    let auth_id = new_uuid();
     let auth_state = PasskeyAuthenticationState {
         id: auth_id,
-        email: Some(request.email),
-        state: ViaJson(PasskeyAuthenticationStateType::Regular(auth_state)),
+        email,
+        state: ViaJson(auth_state_type),
         created_at: chrono::Utc::now(),
     };
     match state.db.create_passkey_authentication(&auth_state).await {
@@ -179,16 +358,56 @@ pub async fn start_authentication(
     }
     Ok((
         cookies.add(
-            new_secure_cookie(AUTHENTICATION_ID_COOKIE, auth_id.to_string())
+            new_secure_cookie(&state, AUTHENTICATION_ID_COOKIE, auth_id.to_string())
                 .expires(Expiration::Session),
         ),
         Json(challenge),
-    ).into())
+    )
+        .into())
+}
+
+/// Creates a decoy challenge for a login with an email address which has no account. Like the
+/// challenge of a real account, it lists a credential: the address's
+/// [decoy credential][decoy_credential_id].
+fn decoy_challenge(
+    state: &V1StateInner,
+    webauthn: &Webauthn,
+    protection: &EnumerationProtection,
+    email: &str,
+) -> Result<RequestChallengeResponse, ApiV1Error> {
+    let (mut challenge, _) = webauthn.start_discoverable_authentication()?;
+    state
+        .options
+        .passkey_policy
+        .apply_to_request(&mut challenge);
+    // Make the challenge look like one for specific credentials rather than a discoverable one
+    challenge.mediation = None;
+    challenge.public_key.extensions = None;
+    challenge.public_key.allow_credentials = vec![AllowCredentials {
+        type_: "public-key".to_string(),
+        id: decoy_credential_id(protection, email).into(),
+        transports: Some(vec![
+            AuthenticatorTransport::Internal,
+            AuthenticatorTransport::Hybrid,
+        ]),
+    }];
+    Ok(challenge)
+}
+
+/// Returns the ID of the decoy credential of an email address which has no account. It's derived
+/// from the address so that it doesn't change between ceremonies.
+fn decoy_credential_id(protection: &EnumerationProtection, email: &str) -> CredentialID {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&protection.secret)
+        .expect("HMAC accepts keys of any length");
+    mac.update(email.as_bytes());
+    mac.finalize().into_bytes().to_vec().into()
 }
 
 pub async fn finish_authentication(
     cookies: CookieJar,
     State(state): State<V1State>,
+    RelyingParty(webauthn): RelyingParty,
+    client: ClientInfo,
     Json(request): Json<PublicKeyCredential>,
 ) -> Result<WithCookies<Json<User>>, ApiV1Error> {
     let Some(authentication_id_cookie) = cookies.get(AUTHENTICATION_ID_COOKIE) else {
@@ -205,24 +424,133 @@ pub async fn finish_authentication(
     if auth_state.created_at < five_minutes_ago {
         return Err(ApiV1Error::SessionExpired);
     }
-    let PasskeyAuthenticationStateType::Regular(passkey_state) = auth_state.state.0 else {
-        return Err(ApiV1Error::InvalidAuthenticationId);
+    let passkey_state = match auth_state.state.0 {
+        PasskeyAuthenticationStateType::Regular(passkey_state) => passkey_state,
+        // No credential can finish a decoy login, so fail as if an unknown one was used
+        PasskeyAuthenticationStateType::Decoy => {
+            let err = WebauthnError::CredentialNotFound;
+            report_auth_failure(&state, &err, None, None, &client);
+            return Err(ApiV1Error::AuthFailed(err));
+        }
+        _ => return Err(ApiV1Error::InvalidAuthenticationId),
     };
-    let result = state
-        .webauthn
-        .finish_passkey_authentication(&request, &passkey_state)?;
-    if result.needs_update() {
-        do_passkey_update(&state, &result).await?;
-    }
     let Some(email) = auth_state.email else {
         return Err(ApiV1Error::InvalidAuthenticationId);
     };
     let user = state.db.get_user_by_email(&email).await?;
-    let (_session, cookies) = new_session(cookies, &*state.db, user.id(), false, None).await?;
+    check_can_log_in(&state, &user)?;
+    let result = match webauthn.finish_passkey_authentication(&request, &passkey_state) {
+        Ok(result) => result,
+        Err(err) => {
+            report_auth_failure(&state, &err, Some(email), None, &client);
+            // Attribute the failure to the passkey which was used, if it is one of the user's
+            let passkey_id = match state
+                .db
+                .get_passkey_by_credential_id(request.raw_id.as_slice())
+                .await
+            {
+                Ok(passkey) if passkey.user_id == *user.id() => Some(passkey.id),
+                _ => None,
+            };
+            record_lockout_failure(&state, &user, passkey_id).await;
+            return Err(ApiV1Error::AuthFailed(err));
+        }
+    };
+    consume_authentication(&state, &authentication_id).await?;
+    if result.needs_update() {
+        do_passkey_update(&state, &result).await?;
+    }
+    clear_lockout_failures(&state, &user).await;
+    let (session, cookies) = new_session(cookies, &state, user.id(), false, &client).await?;
+    let cookies = record_login(cookies, &state, &user, &session, &client).await;
     Ok((
-        cookies.remove(new_secure_cookie(AUTHENTICATION_ID_COOKIE, "")),
+        cookies.remove(new_secure_cookie(&state, AUTHENTICATION_ID_COOKIE, "")),
         Json(user),
-    ).into())
+    )
+        .into())
+}
+
+/// Deletes the state of a successfully finished authentication ceremony so that it can't be
+/// replayed. Fails if the state was already consumed, e.g. by a concurrent request.
+async fn consume_authentication(state: &V1StateInner, id: &Uuid) -> Result<(), ApiV1Error> {
+    match state.db.delete_passkey_authentication_by_id(id).await {
+        Ok(()) => Ok(()),
+        Err(DatabaseError::NotFound) => Err(ApiV1Error::InvalidAuthenticationId),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Reports a failed authentication ceremony to the alerter. A failure due to a signature counter
+/// regression is reported as such, since it indicates that the passkey may have been cloned.
+///
+/// The failure also counts towards automatically banning the client's address.
+fn report_auth_failure(
+    state: &V1StateInner,
+    err: &WebauthnError,
+    email: Option<String>,
+    passkey_id: Option<Uuid>,
+    client: &ClientInfo,
+) {
+    if matches!(err, WebauthnError::CredentialPossibleCompromise) {
+        state.report(SecurityEvent::SignCountRegression { email, passkey_id });
+    } else {
+        state.report(SecurityEvent::FailedLogin {
+            email,
+            ip: client.ip,
+        });
+    }
+    if let Some(ip) = client.ip {
+        state.bans.record_failure(ip);
+    }
+}
+
+/// Refuses logins to the given user's account if it isn't [active][UserStatus::Active], or while
+/// it is [locked][crate::lockout]. With [enumeration protection][EnumerationProtection], the
+/// refusal looks like any other failed login, since telling why would reveal that the account
+/// exists.
+pub(super) fn check_can_log_in(state: &V1StateInner, user: &User) -> Result<(), ApiV1Error> {
+    let err = if user.status() != UserStatus::Active {
+        ApiV1Error::AccountInactive(user.status())
+    } else if let Some(until) = user.locked_until().filter(|_| user.is_locked()) {
+        ApiV1Error::AccountLocked(until)
+    } else {
+        return Ok(());
+    };
+    debug!(user = %user.id(), %err, "refusing login");
+    if state.options.enumeration_protection.is_some() {
+        return Err(ApiV1Error::AuthFailed(WebauthnError::CredentialNotFound));
+    }
+    Err(err)
+}
+
+/// Counts a failed login towards [locking][crate::lockout] the given user's account, made with
+/// the passkey with the UUID `passkey_id` if it is known. Errors are only logged, since the login
+/// fails anyway.
+async fn record_lockout_failure(state: &V1StateInner, user: &User, passkey_id: Option<Uuid>) {
+    let Some(options) = &state.options.lockout else {
+        return;
+    };
+    match lockout::record_failure(state.db.as_ref(), options, user.id(), passkey_id.as_ref()).await
+    {
+        Ok(Some(until)) => state.report(SecurityEvent::AccountLocked {
+            email: user.email().to_string(),
+            until,
+        }),
+        Ok(None) => (),
+        Err(err) => error!(%err, user = %user.id(), "failed to record failed login"),
+    }
+}
+
+/// Forgets the failed logins to the given user's account after a successful one, so that they
+/// don't count towards [locking][crate::lockout] it. Errors are only logged, so that they don't
+/// prevent the login.
+async fn clear_lockout_failures(state: &V1StateInner, user: &User) {
+    if state.options.lockout.is_none() {
+        return;
+    }
+    if let Err(err) = state.db.clear_login_failures(user.id()).await {
+        error!(%err, user = %user.id(), "failed to clear failed logins");
+    }
 }
 
 async fn do_passkey_update(
@@ -251,11 +579,16 @@ async fn do_passkey_update(
 
 pub async fn start_conditional_ui_authentication(
     State(state): State<V1State>,
+    RelyingParty(webauthn): RelyingParty,
     cookies: CookieJar,
 ) -> Result<WithCookies<Json<RequestChallengeResponse>>, ApiV1Error> {
-    let (challenge, disco_state) = state.webauthn.start_discoverable_authentication()?;
+    let (mut challenge, disco_state) = webauthn.start_discoverable_authentication()?;
+    let disco_state = state
+        .options
+        .passkey_policy
+        .apply_to_authentication(&mut challenge, disco_state);
     let auth_state = PasskeyAuthenticationState {
-        id: Uuid::new_v4(),
+        id: new_uuid(),
         email: None,
         state: ViaJson(PasskeyAuthenticationStateType::Discoverable(disco_state)),
         created_at: chrono::Utc::now(),
@@ -263,16 +596,19 @@ pub async fn start_conditional_ui_authentication(
     state.db.create_passkey_authentication(&auth_state).await?;
     Ok((
         cookies.add(
-            new_secure_cookie(AUTHENTICATION_ID_COOKIE, auth_state.id.to_string())
+            new_secure_cookie(&state, AUTHENTICATION_ID_COOKIE, auth_state.id.to_string())
                 .expires(Expiration::Session),
         ),
         Json(challenge),
-    ).into())
+    )
+        .into())
 }
 
 pub async fn finish_conditional_ui_authentication(
     State(state): State<V1State>,
+    RelyingParty(webauthn): RelyingParty,
     cookies: CookieJar,
+    client: ClientInfo,
     Json(request): Json<PublicKeyCredential>,
 ) -> Result<WithCookies<Json<User>>, ApiV1Error> {
     // Get the authentication ID from the cookie
@@ -286,9 +622,7 @@ pub async fn finish_conditional_ui_authentication(
     };
 
     // Get the passkey from the credential ID in the request
-    let (user_id, cred_id) = state
-        .webauthn
-        .identify_discoverable_authentication(&request)?;
+    let (user_id, cred_id) = webauthn.identify_discoverable_authentication(&request)?;
     let auth_state = match state.db.get_passkey_authentication_by_id(&auth_id).await {
         Ok(auth_state) => auth_state,
         Err(DatabaseError::NotFound) => {
@@ -313,70 +647,411 @@ pub async fn finish_conditional_ui_authentication(
         return Err(ApiV1Error::InvalidAuthenticationId);
     };
 
+    let user = state.db.get_user_by_id(&passkey.user_id).await?;
+    check_can_log_in(&state, &user)?;
+
     // Finish the authentication
     let discoverable_key = DiscoverableKey::from(passkey.passkey.0);
-    let result = state
-        .webauthn
-        .finish_discoverable_authentication(&request, disco_state, &[discoverable_key])
-        .map_err(ApiV1Error::AuthFailed)?;
+    let result = match webauthn.finish_discoverable_authentication(
+        &request,
+        disco_state,
+        &[discoverable_key],
+    ) {
+        Ok(result) => result,
+        Err(err) => {
+            let email = Some(user.email().to_string());
+            report_auth_failure(&state, &err, email, Some(passkey.id), &client);
+            record_lockout_failure(&state, &user, Some(passkey.id)).await;
+            return Err(ApiV1Error::AuthFailed(err));
+        }
+    };
 
     // Ensure the user ID the user presented matches the one the passkey belongs to
     if passkey.user_id != user_id {
         debug!("Expected user ID {} but got {}", passkey.user_id, user_id);
         return Err(ApiV1Error::AuthFailed(WebauthnError::InvalidUserUniqueId));
     }
+    consume_authentication(&state, &auth_id).await?;
 
     if result.needs_update() {
         do_passkey_update(&state, &result).await?;
     }
 
+    clear_lockout_failures(&state, &user).await;
+
     // Create a new session for the user
-    let user = state.db.get_user_by_id(&user_id).await?;
-    let (_session, cookies) = new_session(cookies, &*state.db, user.id(), false, None).await?;
+    let (session, cookies) = new_session(cookies, &state, user.id(), false, &client).await?;
+    let cookies = record_login(cookies, &state, &user, &session, &client).await;
     Ok((
-        cookies.remove(new_secure_cookie(AUTHENTICATION_ID_COOKIE, "")),
+        cookies.remove(new_secure_cookie(&state, AUTHENTICATION_ID_COOKIE, "")),
         Json(user),
-    ).into())
+    )
+        .into())
 }
 
-async fn new_session(
-    mut cookies: CookieJar,
-    db: &dyn DatabaseClient,
+/// Starts re-authenticating the user of the current session with one of their passkeys, e.g.
+/// before a [sensitive action][FreshAuthentication]. The ceremony can only be finished with the
+/// same session.
+pub async fn start_reauthentication(
+    cookies: CookieJar,
+    State(state): State<V1State>,
+    AuthenticatedSession(session): AuthenticatedSession,
+    RelyingParty(webauthn): RelyingParty,
+) -> Result<WithCookies<Json<RequestChallengeResponse>>, ApiV1Error> {
+    let passkeys: Vec<Passkey> = state
+        .db
+        .get_passkeys_by_user_id(&session.user_id)
+        .await?
+        .into_iter()
+        .map(std::convert::Into::into)
+        .collect();
+    let (mut challenge, passkey_state) = webauthn.start_passkey_authentication(&passkeys)?;
+    let passkey_state = state
+        .options
+        .passkey_policy
+        .apply_to_authentication(&mut challenge, passkey_state);
+    let user = state.db.get_user_by_id(&session.user_id).await?;
+    let auth_state = PasskeyAuthenticationState {
+        id: new_uuid(),
+        email: Some(user.email().to_string()),
+        state: ViaJson(PasskeyAuthenticationStateType::Reauthentication {
+            session_id_hash: session.id_hash,
+            state: passkey_state,
+        }),
+        created_at: chrono::Utc::now(),
+    };
+    state.db.create_passkey_authentication(&auth_state).await?;
+    Ok((
+        cookies.add(
+            new_secure_cookie(&state, AUTHENTICATION_ID_COOKIE, auth_state.id.to_string())
+                .expires(Expiration::Session),
+        ),
+        Json(challenge),
+    )
+        .into())
+}
+
+/// Finishes re-authenticating the user of the current session, which then counts as
+/// [freshly authenticated][FreshAuthentication] for a few minutes.
+pub async fn finish_reauthentication(
+    cookies: CookieJar,
+    State(state): State<V1State>,
+    AuthenticatedSession(session): AuthenticatedSession,
+    RelyingParty(webauthn): RelyingParty,
+    client: ClientInfo,
+    Json(request): Json<PublicKeyCredential>,
+) -> Result<WithCookies<Json<Session>>, ApiV1Error> {
+    let Some(auth_id) = cookies
+        .get(AUTHENTICATION_ID_COOKIE)
+        .and_then(|cookie| Uuid::parse_str(cookie.value()).ok())
+    else {
+        return Err(ApiV1Error::InvalidAuthenticationId);
+    };
+    let auth_state = match state.db.get_passkey_authentication_by_id(&auth_id).await {
+        Err(DatabaseError::NotFound) => return Err(ApiV1Error::InvalidAuthenticationId),
+        result => result?,
+    };
+    if auth_state.created_at < chrono::Utc::now() - chrono::Duration::minutes(5) {
+        return Err(ApiV1Error::SessionExpired);
+    }
+    let PasskeyAuthenticationStateType::Reauthentication {
+        session_id_hash,
+        state: passkey_state,
+    } = auth_state.state.0
+    else {
+        return Err(ApiV1Error::InvalidAuthenticationId);
+    };
+    if *session_id_hash != *session.id_hash {
+        return Err(ApiV1Error::InvalidAuthenticationId);
+    }
+    let result = match webauthn.finish_passkey_authentication(&request, &passkey_state) {
+        Ok(result) => result,
+        Err(err) => {
+            report_auth_failure(&state, &err, auth_state.email, None, &client);
+            return Err(ApiV1Error::AuthFailed(err));
+        }
+    };
+    consume_authentication(&state, &auth_id).await?;
+    if result.needs_update() {
+        do_passkey_update(&state, &result).await?;
+    }
+    let session = state
+        .db
+        .update_session(
+            &session.id_hash,
+            &SessionUpdate::new().with_authenticated_at(chrono::Utc::now()),
+        )
+        .await?;
+    state.sessions.invalidate(&session.id_hash);
+    info!(user = %session.user_id, "session re-authenticated");
+    Ok((
+        cookies.remove(new_secure_cookie(&state, AUTHENTICATION_ID_COOKIE, "")),
+        Json(session),
+    )
+        .into())
+}
+
+/// Creates a new session for the given user, used by the given client, and sets its cookies.
+pub(super) async fn new_session(
+    cookies: CookieJar,
+    state: &V1StateInner,
     user_id: &Uuid,
     is_admin: bool,
-    parent: Option<&Session>,
+    client: &ClientInfo,
 ) -> Result<(Session, CookieJar), DatabaseError> {
-    // Create session
-    let mut id = [0u8; 32]; // 256 bits
-    rand::rng().fill_bytes(&mut id);
-    let id_hash = blake3::hash(&id);
+    let (session, secret) = generate_session(state, user_id, is_admin, None, client);
+    state.db.create_session(&session).await?;
+    state.emit(WebhookEvent::SessionCreated {
+        user_id: *user_id,
+        is_admin,
+        impersonator_id: None,
+    });
+    Ok((
+        session,
+        add_session_cookies(cookies, state, &secret, is_admin),
+    ))
+}
+
+/// Replaces the given session with a new child session with the given privileges, marking the
+/// given session as [superseded][SessionState::Superseded]. Both happen in one transaction, so
+/// that the user can't end up with both or neither session.
+///
+/// If `impersonator_id` is given, the new session is an impersonation of the user by that
+/// administrator, and expires no later than the given session.
+async fn replace_session(
+    cookies: CookieJar,
+    state: &V1StateInner,
+    session: &Session,
+    user_id: &Uuid,
+    is_admin: bool,
+    impersonator_id: Option<&Uuid>,
+    client: &ClientInfo,
+) -> Result<(Session, CookieJar), DatabaseError> {
+    let (mut new_session, secret) =
+        generate_session(state, user_id, is_admin, Some(session), client);
+    if let Some(impersonator_id) = impersonator_id {
+        new_session.impersonator_id = Some(*impersonator_id);
+        new_session.expires_at = new_session.expires_at.min(session.expires_at);
+    }
+    let mut tx = state.db.begin().await?;
+    tx.create_session(&new_session).await?;
+    tx.update_session(
+        &session.id_hash,
+        &SessionUpdate::new().with_state(SessionState::Superseded),
+    )
+    .await?;
+    tx.commit().await?;
+    state.sessions.invalidate(&session.id_hash);
+    state.emit(WebhookEvent::SessionCreated {
+        user_id: *user_id,
+        is_admin,
+        impersonator_id: new_session.impersonator_id,
+    });
+    Ok((
+        new_session,
+        add_session_cookies(cookies, state, &secret, is_admin),
+    ))
+}
+
+/// Generates a new session secret and returns a new active [`Session`] stored under its hash, as
+/// well as the secret. The session expires after the
+/// [configured lifetime][crate::models::SessionLifetimes].
+fn generate_session(
+    state: &V1StateInner,
+    user_id: &Uuid,
+    is_admin: bool,
+    parent: Option<&Session>,
+    client: &ClientInfo,
+) -> (Session, SessionSecret) {
+    let secret = SessionSecret::generate();
+    let now = chrono::Utc::now();
     let session = Session {
-        id_hash: id_hash.into(),
+        id_hash: secret.hash(),
         user_id: *user_id,
         state: SessionState::Active,
-        created_at: chrono::Utc::now(),
-        expires_at: chrono::Utc::now() + SESSION_DURATION,
+        created_at: now,
+        expires_at: now + state.config.session_lifetimes.for_session(is_admin),
         is_admin,
         parent_id_hash: parent.map(|p| p.id_hash),
+        ip: client.ip.map(|ip| ip.to_string()),
+        user_agent: client.user_agent.clone(),
+        last_seen_at: now,
+        impersonator_id: None,
+        authenticated_at: parent.map_or(now, |p| p.authenticated_at),
+        // Impersonating another user leaves the organization
+        organization_id: parent
+            .filter(|p| p.user_id == *user_id)
+            .and_then(|p| p.organization_id),
     };
+    (session, secret)
+}
 
-    // Store session in database
-    db.create_session(&session).await?;
-
-    // Set session cookie
-    cookies = cookies
-        .add(new_secure_cookie(SESSION_ID_COOKIE, id_hash.to_string()).max_age(Duration::days(1)));
+/// Sets the session and admin marker cookies for a new session.
+fn add_session_cookies(
+    mut cookies: CookieJar,
+    state: &V1StateInner,
+    secret: &SessionSecret,
+    is_admin: bool,
+) -> CookieJar {
+    // Set session cookie, which lasts as long as the session
+    let lifetime = state.config.session_lifetimes.for_session(is_admin);
+    cookies = cookies.add(
+        state
+            .session_cookies
+            .cookie(secret, Duration::seconds(lifetime.num_seconds())),
+    );
 
     // Set admin marker cookie.
     // admin cookie is not HTTP-only so the UI can detect whether the session is admin or not.
-    let is_admin_cookie = new_secure_cookie(IS_ADMIN_COOKIE, "y").http_only(false);
-    cookies = if is_admin {
+    let is_admin_cookie = new_secure_cookie(state, IS_ADMIN_COOKIE, "y").http_only(false);
+    if is_admin {
         cookies.add(is_admin_cookie)
     } else {
         cookies.remove(is_admin_cookie)
+    }
+}
+
+/// Records the user's last login time and the device from which they logged in and, depending on
+/// the [login notification policy][LoginNotificationPolicy] and the user's preference, emails them
+/// about the login. Returns the cookie jar with the device ID cookie set.
+///
+/// The login has already succeeded at this point, so errors are logged rather than returned.
+pub(super) async fn record_login(
+    mut cookies: CookieJar,
+    state: &V1StateInner,
+    user: &User,
+    session: &Session,
+    client: &ClientInfo,
+) -> CookieJar {
+    if let Err(err) = state.db.record_user_login(user.id()).await {
+        error!(%err, user = %user.id(), "failed to record login time");
+    }
+
+    let policy = state.options.login_notifications;
+    if policy == LoginNotificationPolicy::Off || state.options.mailer.is_none() {
+        return cookies;
+    }
+
+    // Identify the device using a long-lived random ID cookie
+    let device_id = cookies
+        .get(DEVICE_ID_COOKIE)
+        .and_then(|cookie| BASE64_URL_SAFE_NO_PAD.decode(cookie.value()).ok())
+        .filter(|id| id.len() == 32)
+        .unwrap_or_else(|| {
+            let mut id = vec![0u8; 32]; // 256 bits
+            rand::rng().fill_bytes(&mut id);
+            id
+        });
+    cookies = cookies.add(
+        new_secure_cookie(
+            state,
+            DEVICE_ID_COOKIE,
+            BASE64_URL_SAFE_NO_PAD.encode(&device_id),
+        )
+        .max_age(DEVICE_ID_DURATION),
+    );
+    let new_device = match state
+        .db
+        .record_user_device(user.id(), &blake3::hash(&device_id).into())
+        .await
+    {
+        Ok(new_device) => new_device,
+        Err(err) => {
+            error!(%err, user = %user.id(), "failed to record login device");
+            return cookies;
+        }
+    };
+
+    if !user.login_notifications() || (policy == LoginNotificationPolicy::NewDevice && !new_device)
+    {
+        return cookies;
+    }
+
+    // Create a token which lets the user revoke the session from the email
+    let mut token = [0u8; 32]; // 256 bits
+    rand::rng().fill_bytes(&mut token);
+    if let Err(err) = state
+        .db
+        .create_session_revocation_token(&blake3::hash(&token).into(), &session.id_hash)
+        .await
+    {
+        error!(%err, user = %user.id(), "failed to create session revocation token");
+        return cookies;
+    }
+
+    notify::login(
+        state,
+        user,
+        session.created_at,
+        client,
+        new_device,
+        &BASE64_URL_SAFE_NO_PAD.encode(token),
+    );
+    cookies
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct RevokeSessionRequest {
+    /// Revocation token from a login notification email
+    pub token: String,
+}
+
+/// Revokes the session for which a login notification was sent, along with any sessions it was
+/// upgraded to. Used by the "this wasn't me" link in login notification emails.
+pub async fn revoke_session(
+    State(state): State<V1State>,
+    Json(request): Json<RevokeSessionRequest>,
+) -> Result<(), ApiV1Error> {
+    let Ok(token) = BASE64_URL_SAFE_NO_PAD.decode(&request.token) else {
+        return Err(ApiV1Error::NotFound);
     };
+    let token_hash: EncodableHash = blake3::hash(&token).into();
+    state.db.revoke_session_by_token(&token_hash).await?;
+    // The revoked sessions aren't known here, so drop all cached ones
+    state.sessions.clear();
+    info!("session revoked from login notification");
+    Ok(())
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RevokeAllSessionsRequest {
+    /// Whether to keep the caller's own session
+    #[serde(default)]
+    pub keep_current: bool,
+}
 
-    Ok((session, cookies))
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct RevokeAllSessionsResponse {
+    /// Number of sessions revoked
+    pub revoked: u64,
+}
+
+/// Revokes all active sessions of all users, optionally except the caller's own session. Used
+/// after a suspected compromise of session IDs.
+pub async fn revoke_all_sessions(
+    State(state): State<V1State>,
+    AdminSession(session): AdminSession,
+    client: ClientInfo,
+    Json(request): Json<RevokeAllSessionsRequest>,
+) -> Result<Json<RevokeAllSessionsResponse>, ApiV1Error> {
+    let except = request.keep_current.then_some(&session.id_hash);
+    let revoked = state.db.revoke_all_sessions(except).await?;
+    state.sessions.clear();
+    let user = state.db.get_user_by_id(&session.user_id).await?;
+    warn!(
+        admin = %user.email(),
+        ip = ?client.ip,
+        revoked,
+        keep_current = request.keep_current,
+        "all sessions revoked",
+    );
+    state.report(SecurityEvent::AllSessionsRevoked {
+        email: user.email().to_string(),
+        ip: client.ip,
+        revoked,
+    });
+    Ok(Json(RevokeAllSessionsResponse { revoked }))
 }
 
 pub async fn logout(
@@ -393,8 +1068,9 @@ pub async fn logout(
                 &SessionUpdate::new().with_state(SessionState::LoggedOut),
             )
             .await?;
+        state.sessions.invalidate(&session.id_hash);
     }
-    let new_cookies = cookies.remove(new_secure_cookie(SESSION_ID_COOKIE, ""));
+    let new_cookies = cookies.remove(state.session_cookies.removal_cookie());
     Ok(new_cookies.into())
 }
 
@@ -402,34 +1078,91 @@ pub async fn logout(
 #[derive(Debug, Clone, Deserialize, JsonSchema)]
 #[serde(tag = "target")]
 pub enum UpgradeTarget {
+    /// Gain administrator privileges
     Admin,
-    // User { user_id: Uuid },
+    /// Act as another user. Only possible from an administrator session.
+    #[serde(rename_all = "camelCase")]
+    User { user_id: Uuid },
 }
 
-/// Upgrades a session, e.g. from regular user to admin privileges.
+/// Upgrades a session, e.g. from regular user to admin privileges, or from an administrator
+/// session to a session impersonating another user. Sessions which impersonate a user can't be
+/// upgraded; they must be [downgraded][downgrade_session] first.
+///
+/// The session must have been [authenticated recently][FreshAuthentication].
 pub async fn upgrade_session(
     State(state): State<V1State>,
     Cached(cookies): Cached<CookieJar>,
-    AuthenticatedSession(session): AuthenticatedSession,
+    FreshAuthentication(session): FreshAuthentication,
+    client: ClientInfo,
     Json(target): Json<UpgradeTarget>,
 ) -> Result<WithCookies<()>, ApiV1Error> {
-    // Check if user has admin tag
-    let tags = state.db.get_tags_by_user_id(&session.user_id).await?;
-    if !tags
-        .iter()
-        .map(|t| &*t.name)
-        .any(|tag_name| tag_name == "iam::admin")
-    {
+    // Only users with permissions, granted by their tags and groups, can act as administrators
+    let permissions = Permissions::for_user(state.db.as_ref(), &session.user_id).await?;
+    if permissions.is_empty() {
         return Err(ApiV1Error::NotAdmin);
     }
+    if session.impersonator_id.is_some() {
+        return Err(ApiV1Error::Impersonating);
+    }
 
     match target {
         UpgradeTarget::Admin => {
-            // Create new admin session
-            let (_session, cookies) =
-                new_session(cookies, &*state.db, &session.user_id, true, Some(&session)).await?;
-            // Invalidate current session
-            supersede_session(&*state.db, &session).await?;
+            // Replace the current session with a new admin session
+            let (_session, cookies) = replace_session(
+                cookies,
+                &state,
+                &session,
+                &session.user_id,
+                true,
+                None,
+                &client,
+            )
+            .await?;
+            let user = state.db.get_user_by_id(&session.user_id).await?;
+            state.report(SecurityEvent::AdminSessionUpgrade {
+                email: user.email().to_string(),
+                ip: client.ip,
+            });
+            Ok(cookies.into())
+        }
+        UpgradeTarget::User { user_id } => {
+            let RequirePermission {
+                session,
+                permissions,
+                ..
+            } = RequirePermission::<permission::UsersImpersonate>::check(session, permissions)?;
+            if user_id == session.user_id {
+                return Err(ApiV1Error::InvalidField(
+                    "userId",
+                    "cannot impersonate yourself",
+                ));
+            }
+            let target = match state.db.get_user_by_id(&user_id).await {
+                Err(DatabaseError::NotFound) => return Err(ApiV1Error::UserNotFound),
+                result => result?,
+            };
+            if target.status() != UserStatus::Active {
+                return Err(ApiV1Error::AccountInactive(target.status()));
+            }
+            ensure_can_manage(&state, &permissions, &user_id).await?;
+            let (_session, cookies) = replace_session(
+                cookies,
+                &state,
+                &session,
+                &user_id,
+                false,
+                Some(&session.user_id),
+                &client,
+            )
+            .await?;
+            let admin = state.db.get_user_by_id(&session.user_id).await?;
+            info!(admin = %admin.id(), user = %user_id, "administrator started impersonating user");
+            state.report(SecurityEvent::ImpersonationStarted {
+                email: admin.email().to_string(),
+                target_email: target.email().to_string(),
+                ip: client.ip,
+            });
             Ok(cookies.into())
         }
     }
@@ -440,47 +1173,64 @@ pub async fn downgrade_session(
     State(state): State<V1State>,
     Cached(mut cookies): Cached<CookieJar>,
     AuthenticatedSession(session): AuthenticatedSession,
+    client: ClientInfo,
 ) -> Result<WithCookies<()>, ApiV1Error> {
     if let Some(parent_id_hash) = session.parent_id_hash {
         let parent_session = state.db.get_session_by_id_hash(&parent_id_hash).await?;
         // We can't actually return to the parent session since we don't know the non-hashed ID, so we
         // create a new one with the same privileges.
-        (_, cookies) = new_session(
+        (_, cookies) = replace_session(
             cookies,
-            &*state.db,
+            &state,
+            &session,
             &parent_session.user_id,
             parent_session.is_admin,
-            Some(&session),
+            None,
+            &client,
         )
         .await?;
-        // Invalidate the current session
-        supersede_session(&*state.db, &session).await?;
+        if let Some(impersonator_id) = session.impersonator_id {
+            let admin = state.db.get_user_by_id(&impersonator_id).await?;
+            let target = state.db.get_user_by_id(&session.user_id).await?;
+            info!(
+                admin = %impersonator_id,
+                user = %session.user_id,
+                "administrator stopped impersonating user",
+            );
+            state.report(SecurityEvent::ImpersonationEnded {
+                email: admin.email().to_string(),
+                target_email: target.email().to_string(),
+                ip: client.ip,
+            });
+        }
         Ok(cookies.into())
     } else {
         Err(ApiV1Error::DowngradeImpossible)
     }
 }
 
-/// Mark the given session as ugraded/downgraded.
-async fn supersede_session(
-    db: &dyn DatabaseClient,
-    session: &Session,
-) -> Result<(), DatabaseError> {
-    db.update_session(
-        &session.id_hash,
-        &SessionUpdate {
-            state: Some(SessionState::Superseded),
-            expires_at: None,
-        },
-    )
-    .await?;
-    Ok(())
-}
-
 #[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct UserAndSessionInfo {
     pub user: User,
     pub session: Session,
+    /// Permissions of the user, which apply in administrator sessions
+    pub permissions: Vec<Permission>,
+}
+
+/// Lists the current user's active sessions, most recently used first, so they can recognize
+/// their devices.
+pub async fn list_current_user_sessions(
+    auth: Authenticated,
+    pagination: Pagination,
+    State(state): State<V1State>,
+) -> Result<Json<Page<Session>>, ApiV1Error> {
+    let sessions = state
+        .db
+        .get_active_sessions_by_user_id(auth.user_id())
+        .await?;
+    Ok(Json(Page::from_sorted(sessions, &pagination, |session| {
+        (Reverse(session.last_seen_at), *session.id_hash.0.as_bytes())
+    })?))
 }
 
 /// Return the currently logged in user and session.
@@ -490,5 +1240,10 @@ pub async fn get_session(
 ) -> Result<Json<UserAndSessionInfo>, ApiV1Error> {
     let mut user = state.db.get_user_by_id(&session.user_id).await?;
     user.fetch_tags(&*state.db).await?;
-    Ok(Json(UserAndSessionInfo { user, session }))
+    let permissions = Permissions::for_user(state.db.as_ref(), &session.user_id).await?;
+    Ok(Json(UserAndSessionInfo {
+        user,
+        session,
+        permissions: permissions.to_vec(),
+    }))
 }
@@ -0,0 +1,78 @@
+//! # v1 IP ban API endpoint handlers
+
+use std::net::IpAddr;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use chrono::{DateTime, Utc};
+use ipnet::IpNet;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    api::v1::{ApiV1Error, V1State, extractors::AdminSession},
+    models::{IpBan, IpBanSource},
+};
+
+/// Request to ban an IP address or network
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct IpBanRequest {
+    /// Address (e.g. `192.0.2.1`) or network in CIDR notation (e.g. `192.0.2.0/24`) to ban
+    pub network: String,
+    /// Why the network is banned
+    #[serde(default)]
+    pub reason: String,
+    /// Time at which the ban is lifted. If omitted, the ban is permanent.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+pub async fn list_bans(
+    AdminSession { .. }: AdminSession,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<IpBan>>, ApiV1Error> {
+    Ok(Json(state.db.list_ip_bans().await?))
+}
+
+/// Bans an address or network, replacing any existing ban for it. Requests from banned clients
+/// are rejected with `403 Forbidden`.
+pub async fn create_ban(
+    AdminSession(session): AdminSession,
+    State(state): State<V1State>,
+    Json(request): Json<IpBanRequest>,
+) -> Result<Json<IpBan>, ApiV1Error> {
+    let network = match request.network.parse::<IpNet>() {
+        Ok(network) => network,
+        Err(_) => request
+            .network
+            .parse::<IpAddr>()
+            .map(IpNet::from)
+            .map_err(|_| ApiV1Error::InvalidNetwork(request.network.clone()))?,
+    };
+    let ban = state
+        .bans
+        .ban(
+            network,
+            request.reason,
+            IpBanSource::Manual,
+            request.expires_at,
+        )
+        .await?;
+    info!(network = %ban.network, admin = %session.user_id, "IP ban added");
+    Ok(Json(ban))
+}
+
+/// Lifts a ban.
+pub async fn delete_ban(
+    AdminSession(session): AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.bans.unban(&id).await?;
+    info!(%id, admin = %session.user_id, "IP ban lifted");
+    Ok(())
+}
@@ -8,5 +8,5 @@ use crate::{
 };
 
 pub async fn get_config(State(state): State<V1State>) -> PreSerializedJson<AppConfig> {
-    state.config.clone()
+    state.config_json.clone()
 }
@@ -0,0 +1,243 @@
+//! # v1 email-related API endpoint handlers
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::{debug, info, warn};
+
+use crate::{
+    api::v1::{ApiV1Error, V1State, extractors::AdminSession},
+    email::{MailError, TransportError},
+    models::EmailSuppression,
+};
+
+/// How far the timestamp of a webhook request may be from the current time, in seconds, so that
+/// captured requests can't be replayed later
+const MAX_WEBHOOK_CLOCK_SKEW: i64 = 15 * 60;
+
+/// # Mailgun webhook request
+///
+/// Event notification sent by [Mailgun's webhooks][webhooks]. Only the fields needed to handle
+/// bounces and complaints are read.
+///
+/// [webhooks]: https://documentation.mailgun.com/docs/mailgun/user-manual/events/webhooks/
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct MailgunWebhook {
+    pub signature: MailgunSignature,
+    #[serde(rename = "event-data")]
+    pub event_data: MailgunEvent,
+}
+
+/// Signature of a [`MailgunWebhook`]: a hex-encoded HMAC-SHA256 of the timestamp followed by the
+/// token, keyed with the webhook signing key
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct MailgunSignature {
+    /// Seconds since the Unix epoch
+    pub timestamp: String,
+    /// Random string
+    pub token: String,
+    pub signature: String,
+}
+
+/// Event of a [`MailgunWebhook`]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct MailgunEvent {
+    /// Event type, e.g. `failed` or `complained`
+    pub event: String,
+    /// Whether a failure is `permanent` or `temporary`
+    pub severity: Option<String>,
+    /// Address to which the event relates
+    pub recipient: String,
+    /// Mailgun's reason for a failure, e.g. `bounce`
+    pub reason: Option<String>,
+    #[serde(rename = "delivery-status")]
+    pub delivery_status: Option<MailgunDeliveryStatus>,
+}
+
+/// Delivery status of a [`MailgunEvent`]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct MailgunDeliveryStatus {
+    /// Description of the failure, e.g. the SMTP error message
+    pub description: Option<String>,
+}
+
+impl MailgunSignature {
+    /// Returns whether the signature was made with the given signing key less than
+    /// [`MAX_WEBHOOK_CLOCK_SKEW`] ago.
+    fn verify(&self, key: &str) -> bool {
+        let Ok(timestamp) = self.timestamp.parse::<i64>() else {
+            return false;
+        };
+        if (Utc::now().timestamp() - timestamp).abs() > MAX_WEBHOOK_CLOCK_SKEW {
+            return false;
+        }
+        let Ok(signature) = hex::decode(&self.signature) else {
+            return false;
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(self.timestamp.as_bytes());
+        mac.update(self.token.as_bytes());
+        // Compares in constant time
+        mac.verify_slice(&signature).is_ok()
+    }
+}
+
+impl MailgunEvent {
+    /// Returns why the recipient should be suppressed, or [`None`] if it shouldn't be. Permanent
+    /// failures and complaints suppress the address; temporary failures and other events don't.
+    fn suppression_reason(&self) -> Option<String> {
+        match self.event.as_str() {
+            "complained" => Some("complaint".to_string()),
+            "failed" if self.severity.as_deref() == Some("permanent") => {
+                let reason = self.reason.as_deref().unwrap_or("failed");
+                let description = self
+                    .delivery_status
+                    .as_ref()
+                    .and_then(|status| status.description.as_deref())
+                    .filter(|description| !description.is_empty());
+                Some(match description {
+                    Some(description) => format!("{reason}: {description}"),
+                    None => reason.to_string(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Handles a Mailgun webhook, adding the recipient to the email suppression list if delivery
+/// failed permanently or the recipient complained. Requests must be signed with the configured
+/// [webhook signing key][crate::api::ApiOptions::email_webhook_secret]. Other events are
+/// acknowledged and ignored, so that Mailgun doesn't retry them.
+pub async fn report_bounce(
+    State(state): State<V1State>,
+    Json(webhook): Json<MailgunWebhook>,
+) -> Result<(), ApiV1Error> {
+    let Some(key) = &state.options.email_webhook_secret else {
+        return Err(ApiV1Error::NotFound);
+    };
+    if !webhook.signature.verify(key) {
+        return Err(ApiV1Error::InvalidWebhookSecret);
+    }
+    let event = webhook.event_data;
+    let Some(reason) = event.suppression_reason() else {
+        debug!(event = %event.event, "ignoring email event");
+        return Ok(());
+    };
+    state
+        .db
+        .create_email_suppression(&event.recipient, &reason)
+        .await?;
+    info!(address = %event.recipient, %reason, "email address suppressed after bounce");
+    Ok(())
+}
+
+pub async fn list_suppressions(
+    AdminSession { .. }: AdminSession,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<EmailSuppression>>, ApiV1Error> {
+    Ok(Json(state.db.list_email_suppressions().await?))
+}
+
+/// Removes an address from the email suppression list, so emails will be sent to it again.
+pub async fn delete_suppression(
+    AdminSession { .. }: AdminSession,
+    Path(address): Path<String>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.delete_email_suppression(&address).await?;
+    Ok(())
+}
+
+/// Request to send a test email
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TestEmailRequest {
+    /// Recipient address. Defaults to the current administrator's address.
+    pub to: Option<String>,
+}
+
+/// Outcome of sending a test email
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TestEmailResult {
+    /// Address to which the test email was sent
+    pub to: String,
+    /// Whether the email was accepted for delivery
+    pub sent: bool,
+    /// Whether retrying could succeed, if sending failed. Temporary failures are usually caused by
+    /// network problems or bad credentials; permanent ones by the provider rejecting the message.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temporary: Option<bool>,
+    /// Error message, including its causes, if sending failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Sends a test email immediately, bypassing the email queue, and reports whether it was sent.
+/// Used by operators to check the email configuration.
+pub async fn send_test_email(
+    AdminSession(session): AdminSession,
+    State(state): State<V1State>,
+    Json(request): Json<TestEmailRequest>,
+) -> Result<Json<TestEmailResult>, ApiV1Error> {
+    let Some(mailer) = &state.options.mailer else {
+        return Err(ApiV1Error::EmailDisabled);
+    };
+    let to = match request.to {
+        Some(to) => to,
+        None => state
+            .db
+            .get_user_by_id(&session.user_id)
+            .await?
+            .email()
+            .to_string(),
+    };
+    let result = match mailer.send_now(&to, "test", &()).await {
+        Ok(()) => {
+            info!(%to, "test email sent");
+            TestEmailResult {
+                to,
+                sent: true,
+                temporary: None,
+                error: None,
+            }
+        }
+        Err(err) => {
+            warn!(%err, %to, "failed to send test email");
+            TestEmailResult {
+                to,
+                sent: false,
+                temporary: Some(matches!(
+                    err,
+                    MailError::Transport(TransportError::Temporary(_))
+                )),
+                error: Some(error_chain(&err)),
+            }
+        }
+    };
+    Ok(Json(result))
+}
+
+/// Formats an error along with all of its sources.
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        let cause = err.to_string();
+        // Skip sources whose messages are already included, e.g. by `#[error(transparent)]`
+        if !message.contains(&cause) {
+            message.push_str(": ");
+            message.push_str(&cause);
+        }
+        source = err.source();
+    }
+    message
+}
@@ -0,0 +1,267 @@
+//! # v1 user export endpoint handler
+//!
+//! Exports are written one page of users at a time as the response is sent, so that exporting a
+//! large instance neither holds every user in memory nor holds a database connection for long.
+
+use std::{borrow::Cow, sync::Arc};
+
+use aide::transform::TransformOperation;
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use futures_util::{StreamExt, stream};
+use schemars::JsonSchema;
+use serde::{
+    Deserialize,
+    de::{IntoDeserializer, value::StrDeserializer},
+};
+use serde_json::{Map, Value};
+use tracing::{info, warn};
+
+use crate::{
+    api::v1::{
+        ApiV1Error, V1State,
+        extractors::{RequirePermission, permission},
+    },
+    db::interface::DatabaseError,
+    models::{Sort, User, UserCursor, UserListQuery, UserSort},
+};
+
+/// Number of users fetched from the database at a time
+const EXPORT_PAGE_SIZE: u32 = 200;
+
+/// Format of a user export file
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// Comma-separated values, starting with a header row naming the columns. Tags are listed by
+    /// name, separated by semicolons.
+    Csv,
+    /// One JSON object per line. Tags are listed by name.
+    Jsonl,
+}
+
+/// User field included in an export
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum ExportField {
+    Id,
+    Email,
+    DisplayName,
+    Status,
+    CreatedAt,
+    LastLoginAt,
+    Tags,
+}
+
+impl ExportField {
+    const ALL: [Self; 7] = [
+        Self::Id,
+        Self::Email,
+        Self::DisplayName,
+        Self::Status,
+        Self::CreatedAt,
+        Self::LastLoginAt,
+        Self::Tags,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Email => "email",
+            Self::DisplayName => "displayName",
+            Self::Status => "status",
+            Self::CreatedAt => "createdAt",
+            Self::LastLoginAt => "lastLoginAt",
+            Self::Tags => "tags",
+        }
+    }
+
+    /// Parses a comma-separated list of field names.
+    fn parse_list(names: &str) -> Result<Vec<Self>, ApiV1Error> {
+        let mut fields = Vec::new();
+        for name in names.split(',').map(str::trim) {
+            let deserializer: StrDeserializer<'_, serde::de::value::Error> =
+                name.into_deserializer();
+            let field = Self::deserialize(deserializer)
+                .map_err(|_| ApiV1Error::InvalidField("fields", "contains an unknown field"))?;
+            if !fields.contains(&field) {
+                fields.push(field);
+            }
+        }
+        Ok(fields)
+    }
+
+    fn value(self, user: &mut User) -> Value {
+        match self {
+            Self::Id => user.id().to_string().into(),
+            Self::Email => user.email().into(),
+            Self::DisplayName => user.display_name().into(),
+            Self::Status => serde_json::to_value(user.status()).unwrap_or_default(),
+            Self::CreatedAt => user.created_at().to_rfc3339().into(),
+            Self::LastLoginAt => user
+                .last_login_at()
+                .map_or(Value::Null, |time| time.to_rfc3339().into()),
+            Self::Tags => user.tags().map_or(Value::Null, |tags| {
+                tags.iter()
+                    .map(|tag| Value::from(tag.name.as_str()))
+                    .collect()
+            }),
+        }
+    }
+}
+
+/// Query parameters for exporting users
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct UserExportParams {
+    /// Format of the response body
+    pub format: ExportFormat,
+    /// Comma-separated fields to include, in the order in which they appear: any of `id`,
+    /// `email`, `displayName`, `status`, `createdAt`, `lastLoginAt`, and `tags`. Defaults to all
+    /// of them, in that order.
+    pub fields: Option<String>,
+}
+
+/// Exports every user as a CSV or JSON lines file.
+pub async fn export_users(
+    RequirePermission { session, .. }: RequirePermission<permission::UsersRead>,
+    Query(params): Query<UserExportParams>,
+    State(state): State<V1State>,
+) -> Result<Response, ApiV1Error> {
+    let fields = match params.fields.as_deref() {
+        Some(names) => ExportField::parse_list(names)?,
+        None => ExportField::ALL.to_vec(),
+    };
+    info!(admin = %session.user_id, "user export started");
+    let (content_type, filename) = match params.format {
+        ExportFormat::Csv => ("text/csv; charset=utf-8", "users.csv"),
+        ExportFormat::Jsonl => ("application/x-ndjson", "users.jsonl"),
+    };
+    let writer = Arc::new(ExportWriter {
+        format: params.format,
+        fields,
+    });
+    let header = writer.header();
+    let pages = stream::try_unfold(Some(None), move |after: Option<Option<UserCursor>>| {
+        let state = Arc::clone(&state);
+        let writer = Arc::clone(&writer);
+        async move {
+            let Some(after) = after else {
+                return Ok(None);
+            };
+            let (chunk, next) = writer
+                .page(&state, after.as_ref())
+                .await
+                .inspect_err(|err| warn!(%err, "user export failed; the response is incomplete"))?;
+            Ok::<_, DatabaseError>(Some((chunk, next.map(Some))))
+        }
+    });
+    let body = stream::iter(header.map(Ok)).chain(pages);
+    Ok((
+        [
+            (CONTENT_TYPE, content_type.to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response())
+}
+
+pub fn export_users_docs(op: TransformOperation) -> TransformOperation {
+    op.description(
+        "Exports every user, oldest first, as a CSV or JSON lines file in the format given by the \
+        `format` parameter. The file is sent in chunks as it's written, so its size isn't known \
+        in advance. If the export fails partway through, the response is cut off, so clients \
+        should treat a response which doesn't end cleanly as failed.",
+    )
+}
+
+/// Writer of the lines of an export file
+struct ExportWriter {
+    format: ExportFormat,
+    fields: Vec<ExportField>,
+}
+
+impl ExportWriter {
+    /// Returns the header row of the file, if its format has one.
+    fn header(&self) -> Option<Vec<u8>> {
+        match self.format {
+            ExportFormat::Csv => {
+                let names: Vec<_> = self.fields.iter().map(|field| field.name()).collect();
+                Some(format!("{}\r\n", names.join(",")).into_bytes())
+            }
+            ExportFormat::Jsonl => None,
+        }
+    }
+
+    /// Writes the page of users after `after`, returning the written lines and the position of
+    /// the last user if there are more users after it.
+    async fn page(
+        &self,
+        state: &V1State,
+        after: Option<&UserCursor>,
+    ) -> Result<(Vec<u8>, Option<UserCursor>), DatabaseError> {
+        let query = UserListQuery::sorted(Sort::asc(UserSort::CreatedAt));
+        let list = state.db.list_users(&query, after, EXPORT_PAGE_SIZE).await?;
+        let mut chunk = Vec::new();
+        for mut user in list.users {
+            if self.fields.contains(&ExportField::Tags) {
+                user.fetch_tags(state.db.as_ref()).await?;
+            }
+            self.write_user(&mut chunk, &mut user);
+        }
+        Ok((chunk, list.next))
+    }
+
+    fn write_user(&self, out: &mut Vec<u8>, user: &mut User) {
+        match self.format {
+            ExportFormat::Csv => {
+                let values: Vec<_> = self
+                    .fields
+                    .iter()
+                    .map(|field| csv_field(&csv_value(field.value(user))).into_owned())
+                    .collect();
+                out.extend_from_slice(values.join(",").as_bytes());
+                out.extend_from_slice(b"\r\n");
+            }
+            ExportFormat::Jsonl => {
+                let object: Map<String, Value> = self
+                    .fields
+                    .iter()
+                    .map(|field| (field.name().to_string(), field.value(user)))
+                    .collect();
+                out.extend_from_slice(Value::Object(object).to_string().as_bytes());
+                out.push(b'\n');
+            }
+        }
+    }
+}
+
+/// Returns the text of a field value in a CSV file.
+fn csv_value(value: Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) => text,
+        Value::Array(items) => items
+            .into_iter()
+            .map(csv_value)
+            .collect::<Vec<_>>()
+            .join(";"),
+        value => value.to_string(),
+    }
+}
+
+/// Quotes a CSV field if it contains characters which would otherwise end it.
+fn csv_field(text: &str) -> Cow<'_, str> {
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\"")).into()
+    } else {
+        text.into()
+    }
+}
@@ -1,29 +1,60 @@
 //! # Custom extractors for the v1 API
 
+use std::{convert::Infallible, marker::PhantomData, net::IpAddr, sync::Arc};
+
 use aide::{OperationInput, openapi::SecurityRequirement};
-use axum::{RequestPartsExt, http::request::Parts};
+use axum::{
+    Json, RequestPartsExt,
+    extract::{FromRequest, Request},
+    http::{
+        Method,
+        header::{AUTHORIZATION, HOST, USER_AGENT},
+        request::Parts,
+        uri::Authority,
+    },
+    response::{IntoResponse, Response},
+};
 use axum_extra::extract::{Cached, CookieJar};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use tracing::{debug, warn};
+use uuid::Uuid;
+use validator::Validate;
+use webauthn_rs::Webauthn;
 
 use crate::{
-    api::v1::{ApiV1Error, V1State, auth::SESSION_ID_COOKIE},
+    api::v1::{ApiV1Error, V1State},
+    client_ip::ClientIp,
     db::interface::DatabaseError,
-    models::{EncodableHash, Session, SessionState},
+    models::{
+        ApiToken, ApiTokenScope, EncodableHash, Permission, Permissions, Session, SessionState,
+        SessionUpdate, UserStatus,
+    },
 };
 
 /// # Authenticated session extractor
 ///
-/// [`AuthenticatedSession`] retrieves the client's session ID from the `session_id` cookie,
-/// fetches the session from the database, and validates it to ensure it's active and has not
-/// expired. If this succeeds, the validated [`Session`] is returned by the extractor.
+/// [`AuthenticatedSession`] verifies the client's [session cookie][crate::session_cookie],
+/// fetches the session stored under the hash of the secret it holds from the
+/// [session cache][super::session_cache] or the database, and validates it to ensure it's active
+/// and has not expired. If this succeeds, the validated [`Session`] is returned by the extractor.
+///
+/// The session's [last use][Session::last_seen_at] and the client's IP address and user agent are
+/// recorded at most once every [`LAST_SEEN_INTERVAL`], so that most requests don't write to the
+/// database.
 ///
 /// If validation fails, one of the following errors is returned:
 /// - [`ApiV1Error::NotLoggedIn`] if there is no session ID cookie
-/// - [`ApiV1Error::InvalidSessionId`] if the session ID cookie contains an invalid/unparseable value
+/// - [`ApiV1Error::InvalidSessionId`] if the session ID cookie's value is malformed or isn't
+///   signed by the server
 /// - [`ApiV1Error::SessionExpired`] if the session is expired or canceled
 /// - [`ApiV1Error::InternalServerError`] if a [`DatabaseError`] occurs
 #[derive(Debug, Clone)]
 pub struct AuthenticatedSession(pub Session);
 
+/// Minimum time between updates of a session's last use
+const LAST_SEEN_INTERVAL: chrono::Duration = chrono::Duration::minutes(5);
+
 impl axum::extract::FromRequestParts<V1State> for AuthenticatedSession {
     type Rejection = ApiV1Error;
 
@@ -33,28 +64,88 @@ impl axum::extract::FromRequestParts<V1State> for AuthenticatedSession {
     ) -> Result<Self, Self::Rejection> {
         // Get session ID hash from cookie
         let Cached(cookies): Cached<CookieJar> = parts.extract_with_state(state).await.unwrap();
-        let Some(session_id_cookie) = cookies.get(SESSION_ID_COOKIE) else {
+        let Some(session_cookie) = cookies.get(state.session_cookies.name()) else {
             return Err(ApiV1Error::NotLoggedIn);
         };
-        let Ok(session_id_hash) =
-            blake3::Hash::from_hex(session_id_cookie.value()).map(EncodableHash)
-        else {
-            return Err(ApiV1Error::InvalidSessionId);
+        let session_id_hash = match state.session_cookies.verify(session_cookie.value()) {
+            Ok(hash) => hash,
+            Err(err) => {
+                debug!(%err, "rejecting session cookie");
+                return Err(ApiV1Error::InvalidSessionId);
+            }
         };
 
-        // Look up session in database
-        match state.db.get_session_by_id_hash(&session_id_hash).await {
-            Ok(session) => {
-                // Ensure session is active and not expired
-                if session.state != SessionState::Active || session.expires_at < chrono::Utc::now()
-                {
-                    Err(ApiV1Error::SessionExpired)
-                } else {
-                    Ok(AuthenticatedSession(session))
+        // Look up session in the cache, falling back to the database
+        let mut session = match state.sessions.get(&session_id_hash) {
+            Some(session) => session,
+            None => match state.db.get_session_by_id_hash(&session_id_hash).await {
+                Ok(session) => {
+                    // Sessions of users who aren't active can't be used. They are revoked when
+                    // the user's status changes, so this only needs to be checked before caching.
+                    if session.state == SessionState::Active {
+                        match state.db.get_user_by_id(&session.user_id).await {
+                            Ok(user) if user.status() != UserStatus::Active => {
+                                return Err(ApiV1Error::AccountInactive(user.status()));
+                            }
+                            Ok(_) => (),
+                            Err(DatabaseError::NotFound) => return Err(ApiV1Error::NotLoggedIn),
+                            Err(e) => return Err(e.into()),
+                        }
+                    }
+                    state.sessions.insert(&session);
+                    session
                 }
+                Err(DatabaseError::NotFound) => return Err(ApiV1Error::NotLoggedIn),
+                Err(e) => return Err(e.into()),
+            },
+        };
+
+        // Ensure session is active and not expired
+        let now = chrono::Utc::now();
+        if session.state != SessionState::Active || session.expires_at < now {
+            return Err(ApiV1Error::SessionExpired);
+        }
+
+        if now - session.last_seen_at >= LAST_SEEN_INTERVAL {
+            let Ok(client) = parts.extract::<ClientInfo>().await;
+            let update = SessionUpdate::new().with_activity(
+                now,
+                client.ip.map(|ip| ip.to_string()),
+                client.user_agent,
+            );
+            // Failing to record the activity shouldn't fail the request
+            match state.db.update_session(&session.id_hash, &update).await {
+                Ok(updated) => {
+                    state.sessions.insert(&updated);
+                    session = updated;
+                }
+                Err(err) => warn!(%err, "failed to record session activity"),
             }
-            Err(DatabaseError::NotFound) => Err(ApiV1Error::NotLoggedIn),
-            Err(e) => Err(e.into()),
+        }
+        Ok(AuthenticatedSession(session))
+    }
+}
+
+/// Extracts the session as an [`Option`], which is [`None`] if the client isn't logged in or its
+/// session has expired. Database errors are still returned as errors.
+impl axum::extract::OptionalFromRequestParts<V1State> for AuthenticatedSession {
+    type Rejection = ApiV1Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &V1State,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        match <Self as axum::extract::FromRequestParts<V1State>>::from_request_parts(parts, state)
+            .await
+        {
+            Ok(session) => Ok(Some(session)),
+            Err(
+                ApiV1Error::NotLoggedIn
+                | ApiV1Error::InvalidSessionId
+                | ApiV1Error::SessionExpired
+                | ApiV1Error::AccountInactive(_),
+            ) => Ok(None),
+            Err(err) => Err(err),
         }
     }
 }
@@ -75,9 +166,11 @@ impl OperationInput for AuthenticatedSession {
 ///
 /// [`AdminSession`] is a wrapper around [`AuthenticatedSession`]. It behaves identically, except
 /// it also ensures that the client's session is an administrator session ([`Session::is_admin`]),
-/// returning [`ApiV1Error::NotAdmin`] if not.
+/// returning [`ApiV1Error::NotAdmin`] if not, and that its user has
+/// [every permission][Permission::All], returning [`ApiV1Error::MissingPermission`] if not.
+///
+/// Endpoints which finer-grained permissions cover use [`RequirePermission`] instead.
 #[derive(Debug, Clone)]
-#[expect(dead_code)]
 pub struct AdminSession(pub Session);
 
 impl axum::extract::FromRequestParts<V1State> for AdminSession {
@@ -87,25 +180,382 @@ impl axum::extract::FromRequestParts<V1State> for AdminSession {
         parts: &mut Parts,
         state: &V1State,
     ) -> Result<Self, Self::Rejection> {
-        // Get authenticated session
+        let RequirePermission { session, .. }: RequirePermission<permission::All> =
+            parts.extract_with_state(state).await?;
+        Ok(AdminSession(session))
+    }
+}
+
+impl OperationInput for AdminSession {
+    fn operation_input(
+        ctx: &mut aide::generate::GenContext,
+        operation: &mut aide::openapi::Operation,
+    ) {
+        RequirePermission::<permission::All>::operation_input(ctx, operation);
+    }
+}
+
+/// [`Permission`] required by a [`RequirePermission`] extractor
+pub trait RequiredPermission: Send + Sync {
+    const PERMISSION: Permission;
+}
+
+/// Types naming each [`Permission`], for use as the parameter of [`RequirePermission`]
+pub mod permission {
+    use super::{Permission, RequiredPermission};
+
+    macro_rules! required_permissions {
+        ($($name:ident),* $(,)?) => {
+            $(
+                #[doc = concat!("Requires [`Permission::", stringify!($name), "`]")]
+                #[derive(Debug, Clone)]
+                pub struct $name;
+
+                impl RequiredPermission for $name {
+                    const PERMISSION: Permission = Permission::$name;
+                }
+            )*
+        };
+    }
+
+    required_permissions!(
+        All,
+        UsersRead,
+        UsersWrite,
+        UsersImpersonate,
+        TagsRead,
+        TagsWrite,
+        GroupsRead,
+        GroupsWrite,
+        RolesRead,
+        RolesWrite,
+    );
+}
+
+/// # Permission extractor
+///
+/// [`RequirePermission`] is a wrapper around [`AuthenticatedSession`]. It behaves identically,
+/// except it also ensures that the client's session is an administrator session
+/// ([`Session::is_admin`]), returning [`ApiV1Error::NotAdmin`] if not, and that its user has the
+/// permission named by `P`, returning [`ApiV1Error::MissingPermission`] if not.
+///
+/// The user's [`Permissions`] are evaluated on every request, so that changes to their tags,
+/// groups, and roles apply immediately. Handlers use them to make sure that users can't grant
+/// permissions which they don't have themselves.
+#[derive(Debug, Clone)]
+pub struct RequirePermission<P> {
+    pub session: Session,
+    /// Permissions of the session's user
+    pub permissions: Permissions,
+    _permission: PhantomData<P>,
+}
+
+impl<P: RequiredPermission> axum::extract::FromRequestParts<V1State> for RequirePermission<P> {
+    type Rejection = ApiV1Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &V1State,
+    ) -> Result<Self, Self::Rejection> {
+        let AuthenticatedSession(session) = parts.extract_with_state(state).await?;
+        if !session.is_admin {
+            return Err(ApiV1Error::NotAdmin);
+        }
+        let permissions = Permissions::for_user(state.db.as_ref(), &session.user_id).await?;
+        Self::check(session, permissions)
+    }
+}
+
+impl<P: RequiredPermission> RequirePermission<P> {
+    /// Checks that the given session is an administrator session whose user has the given
+    /// permissions, which include the one named by `P`. Used by handlers which only require the
+    /// permission for some requests.
+    pub fn check(session: Session, permissions: Permissions) -> Result<Self, ApiV1Error> {
+        if !session.is_admin {
+            return Err(ApiV1Error::NotAdmin);
+        }
+        if !permissions.contains(P::PERMISSION) {
+            return Err(ApiV1Error::MissingPermission(P::PERMISSION));
+        }
+        Ok(RequirePermission {
+            session,
+            permissions,
+            _permission: PhantomData,
+        })
+    }
+}
+
+impl<P: RequiredPermission> OperationInput for RequirePermission<P> {
+    fn operation_input(
+        _ctx: &mut aide::generate::GenContext,
+        operation: &mut aide::openapi::Operation,
+    ) {
+        let security = SecurityRequirement::from([(
+            "adminSession".to_string(),
+            vec![P::PERMISSION.to_string()],
+        )]);
+        if !operation.security.contains(&security) {
+            operation.security.push(security);
+        }
+    }
+}
+
+/// # Freshly authenticated session extractor
+///
+/// [`FreshAuthentication`] is a wrapper around [`AuthenticatedSession`] for sensitive actions. It
+/// behaves identically, except it also ensures that the user
+/// [authenticated][Session::authenticated_at] within the last [`FRESH_AUTHENTICATION_WINDOW`],
+/// by logging in or by re-authenticating with `POST /auth/reauth/start` and `/finish`. If not,
+/// [`ApiV1Error::ReauthenticationRequired`] is returned.
+#[derive(Debug, Clone)]
+pub struct FreshAuthentication(pub Session);
+
+/// Time after authenticating during which a session may perform sensitive actions
+const FRESH_AUTHENTICATION_WINDOW: chrono::Duration = chrono::Duration::minutes(5);
+
+impl axum::extract::FromRequestParts<V1State> for FreshAuthentication {
+    type Rejection = ApiV1Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &V1State,
+    ) -> Result<Self, Self::Rejection> {
         let AuthenticatedSession(session) = parts.extract_with_state(state).await?;
-        // Ensure session has admin privilege
-        if session.is_admin {
-            Ok(AdminSession(session))
+        if chrono::Utc::now() - session.authenticated_at <= FRESH_AUTHENTICATION_WINDOW {
+            Ok(FreshAuthentication(session))
         } else {
-            Err(ApiV1Error::NotAdmin)
+            Err(ApiV1Error::ReauthenticationRequired)
         }
     }
 }
 
-impl OperationInput for AdminSession {
+impl OperationInput for FreshAuthentication {
+    fn operation_input(
+        ctx: &mut aide::generate::GenContext,
+        operation: &mut aide::openapi::Operation,
+    ) {
+        AuthenticatedSession::operation_input(ctx, operation);
+    }
+}
+
+/// # API token extractor
+///
+/// [`BearerAuth`] retrieves an API token from the `Authorization: Bearer <token>` header, fetches
+/// it from the database, and validates it to ensure it has not expired and has the scope required
+/// by the request's method: [`ApiTokenScope::Read`] for `GET` and `HEAD` requests, and
+/// [`ApiTokenScope::Write`] for all others. If this succeeds, the validated [`ApiToken`] is
+/// returned by the extractor.
+///
+/// If validation fails, one of the following errors is returned:
+/// - [`ApiV1Error::NotLoggedIn`] if there is no `Authorization` header
+/// - [`ApiV1Error::InvalidApiToken`] if the token is malformed, unknown, or expired
+/// - [`ApiV1Error::InsufficientScope`] if the token lacks the required scope
+/// - [`ApiV1Error::InternalServerError`] if a [`DatabaseError`] occurs
+#[derive(Debug, Clone)]
+pub struct BearerAuth(pub ApiToken);
+
+impl axum::extract::FromRequestParts<V1State> for BearerAuth {
+    type Rejection = ApiV1Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &V1State,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(header) = parts.headers.get(AUTHORIZATION) else {
+            return Err(ApiV1Error::NotLoggedIn);
+        };
+        let Some(token) = header
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return Err(ApiV1Error::InvalidApiToken);
+        };
+        let token_hash: EncodableHash = blake3::hash(token.trim().as_bytes()).into();
+        let token = match state.db.get_api_token_by_hash(&token_hash).await {
+            Ok(token) => token,
+            Err(DatabaseError::NotFound) => return Err(ApiV1Error::InvalidApiToken),
+            Err(e) => return Err(e.into()),
+        };
+        if token.is_expired() {
+            return Err(ApiV1Error::InvalidApiToken);
+        }
+
+        let scope = if matches!(parts.method, Method::GET | Method::HEAD) {
+            ApiTokenScope::Read
+        } else {
+            ApiTokenScope::Write
+        };
+        if token.has_scope(scope) {
+            Ok(BearerAuth(token))
+        } else {
+            Err(ApiV1Error::InsufficientScope)
+        }
+    }
+}
+
+impl OperationInput for BearerAuth {
     fn operation_input(
         _ctx: &mut aide::generate::GenContext,
         operation: &mut aide::openapi::Operation,
     ) {
-        let security = SecurityRequirement::from([("adminSession".to_string(), vec![])]);
+        let security = SecurityRequirement::from([("apiToken".to_string(), vec![])]);
         if !operation.security.contains(&security) {
             operation.security.push(security);
         }
     }
 }
+
+/// # User authentication extractor
+///
+/// [`Authenticated`] accepts either an API token (see [`BearerAuth`]) or a session cookie (see
+/// [`AuthenticatedSession`]), for endpoints which programmatic clients may call. If the request
+/// has an `Authorization` header, the token is used and the cookie is ignored.
+#[derive(Debug, Clone)]
+pub enum Authenticated {
+    Session(Session),
+    Token(ApiToken),
+}
+
+impl Authenticated {
+    /// Returns the UUID of the authenticated user.
+    #[must_use]
+    pub fn user_id(&self) -> &Uuid {
+        match self {
+            Authenticated::Session(session) => &session.user_id,
+            Authenticated::Token(token) => &token.user_id,
+        }
+    }
+}
+
+impl axum::extract::FromRequestParts<V1State> for Authenticated {
+    type Rejection = ApiV1Error;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &V1State,
+    ) -> Result<Self, Self::Rejection> {
+        if parts.headers.contains_key(AUTHORIZATION) {
+            let BearerAuth(token) = parts.extract_with_state(state).await?;
+            Ok(Authenticated::Token(token))
+        } else {
+            let AuthenticatedSession(session) = parts.extract_with_state(state).await?;
+            Ok(Authenticated::Session(session))
+        }
+    }
+}
+
+impl OperationInput for Authenticated {
+    fn operation_input(
+        ctx: &mut aide::generate::GenContext,
+        operation: &mut aide::openapi::Operation,
+    ) {
+        AuthenticatedSession::operation_input(ctx, operation);
+        BearerAuth::operation_input(ctx, operation);
+    }
+}
+
+/// # Client information extractor
+///
+/// [`ClientInfo`] describes the client which made the request, for display to users, e.g. in
+/// login notifications. It never fails; unknown fields are [`None`]. The IP address is the
+/// [resolved client address][ClientIp], which is only known if the server was started with
+/// [connect info][axum::Router::into_make_service_with_connect_info].
+#[derive(Debug, Clone, Default)]
+pub struct ClientInfo {
+    /// IP address of the client, as forwarded by trusted proxies
+    pub ip: Option<IpAddr>,
+    /// Contents of the `User-Agent` header
+    pub user_agent: Option<String>,
+}
+
+impl<S: Send + Sync> axum::extract::FromRequestParts<S> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(ClientInfo {
+            ip: parts.extensions.get::<ClientIp>().map(|&ClientIp(ip)| ip),
+            user_agent: parts
+                .headers
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        })
+    }
+}
+
+impl OperationInput for ClientInfo {}
+
+/// # Relying party extractor
+///
+/// [`RelyingParty`] gives the `WebAuthn` client of the [relying party][crate::relying_party]
+/// served at the request's `Host` header, or of the default one if there is none for that host.
+/// It never fails.
+///
+/// A ceremony must be finished with the same relying party with which it was started, so both
+/// requests must be made to the same host.
+#[derive(Debug, Clone)]
+pub struct RelyingParty(pub Arc<Webauthn>);
+
+impl axum::extract::FromRequestParts<V1State> for RelyingParty {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &V1State,
+    ) -> Result<Self, Self::Rejection> {
+        // HTTP/2 requests give the host in the URI instead of a header
+        let host = parts
+            .headers
+            .get(HOST)
+            .and_then(|value| value.to_str().ok())
+            .or_else(|| parts.uri.authority().map(Authority::as_str));
+        Ok(RelyingParty(Arc::clone(
+            state.relying_parties.for_host(host),
+        )))
+    }
+}
+
+impl OperationInput for RelyingParty {}
+
+/// # Validated JSON body extractor
+///
+/// [`ValidatedJson`] deserializes the request body like [`Json`], then [validates][Validate] it,
+/// returning [`ApiV1Error::ValidationFailed`] with a message for each invalid field if that fails.
+/// Bodies which can't be deserialized are rejected like [`Json`] rejects them.
+#[derive(Debug, Clone)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(request, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        value
+            .validate()
+            .map_err(|errors| ApiV1Error::from(errors).into_response())?;
+        Ok(ValidatedJson(value))
+    }
+}
+
+impl<T: JsonSchema> OperationInput for ValidatedJson<T> {
+    fn operation_input(
+        ctx: &mut aide::generate::GenContext,
+        operation: &mut aide::openapi::Operation,
+    ) {
+        Json::<T>::operation_input(ctx, operation);
+    }
+
+    fn inferred_early_responses(
+        ctx: &mut aide::generate::GenContext,
+        operation: &mut aide::openapi::Operation,
+    ) -> Vec<(Option<u16>, aide::openapi::Response)> {
+        Json::<T>::inferred_early_responses(ctx, operation)
+    }
+}
@@ -0,0 +1,495 @@
+//! # v1 identity federation endpoints
+//!
+//! These endpoints let users log in with an account at an upstream [`FederatedProvider`]:
+//!
+//! 1. The login page sends the user to the [start endpoint][start], which redirects them to the
+//!    provider. If the user is already logged in, the account they log in with is linked to their
+//!    user instead.
+//! 2. The provider returns the user to the [callback endpoint][callback], which exchanges the
+//!    authorization code for an ID token and logs the user in.
+//!
+//! On the first login with an account, it is linked to the user with the same email address, if
+//! both the provider and the user have verified that they own the address. Users can register
+//! with any address, so a user's address only counts once they
+//! [confirmed][crate::models::User::email_verified_at] it. Otherwise, a new user is created if the
+//! provider allows [auto-provisioning][FederatedProvider::auto_provision], unless the address is
+//! taken. See [`crate::federation`] for the client side of the protocol.
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    response::Redirect,
+};
+use axum_extra::extract::{CookieJar, cookie::SameSite};
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use chrono::Utc;
+use rand::RngCore;
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    api::{
+        utils::WithCookies,
+        v1::{
+            ApiV1Error, V1State, V1StateInner,
+            auth::{
+                check_can_log_in, check_registration_allowed, new_secure_cookie, new_session,
+                record_login,
+            },
+            extractors::{AdminSession, Authenticated, AuthenticatedSession, ClientInfo},
+        },
+    },
+    db::interface::DatabaseError,
+    federation::{FederationClient, IdTokenClaims},
+    models::{
+        FederatedIdentity, FederatedLoginState, FederatedProvider, FederatedProviderUpdate, User,
+        UserCreate, ViaJson, new_uuid,
+    },
+    webhooks::WebhookEvent,
+};
+
+/// Cookie binding a federated login to the browser which started it
+const STATE_COOKIE: &str = "federation_state";
+
+/// How long users have to log in at the provider
+const STATE_LIFETIME: chrono::Duration = chrono::Duration::minutes(10);
+
+/// Returns a random URL-safe string, such as a `state` parameter or PKCE code verifier.
+fn random_string() -> String {
+    let mut bytes = [0u8; 32]; // 256 bits
+    rand::rng().fill_bytes(&mut bytes);
+    BASE64_URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Returns the URL to which the provider returns users after they log in.
+fn redirect_uri(state: &V1StateInner, provider: &FederatedProvider) -> String {
+    format!(
+        "{}/auth/federated/{}/callback",
+        state.api_url, provider.slug
+    )
+}
+
+/// Fetches a provider by its slug, responding with `404 Not Found` if there is none.
+async fn get_provider(state: &V1StateInner, slug: &str) -> Result<FederatedProvider, ApiV1Error> {
+    Ok(state.db.get_federated_provider_by_slug(slug).await?)
+}
+
+/// Provider which users can log in with, as shown on the login page
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FederatedProviderInfo {
+    /// Unique short name of the provider, used in the URLs of its login endpoints
+    pub slug: String,
+    /// Name of the provider
+    pub name: String,
+}
+
+/// Lists the providers which users can log in with.
+pub async fn list_login_providers(
+    State(state): State<V1State>,
+) -> Result<Json<Vec<FederatedProviderInfo>>, ApiV1Error> {
+    let providers = state.db.list_federated_providers().await?;
+    Ok(Json(
+        providers
+            .into_iter()
+            .map(|provider| FederatedProviderInfo {
+                slug: provider.slug,
+                name: provider.name,
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct StartQuery {
+    /// Path on this site to which the user is sent after logging in
+    pub next: Option<String>,
+}
+
+/// Starts a login with the given provider
+///
+/// Redirects the user to the provider. If the user is already logged in, the account they log in
+/// with at the provider is linked to their user. Responds with `502 Bad Gateway` if the
+/// provider's metadata can't be fetched.
+pub async fn start(
+    session: Option<AuthenticatedSession>,
+    cookies: CookieJar,
+    Path(slug): Path<String>,
+    State(state): State<V1State>,
+    Query(query): Query<StartQuery>,
+) -> Result<WithCookies<Redirect>, ApiV1Error> {
+    if let Some(next) = &query.next
+        && (!next.starts_with('/') || next.starts_with("//") || next.starts_with("/\\"))
+    {
+        return Err(ApiV1Error::InvalidField(
+            "next",
+            "must be a path on this site",
+        ));
+    }
+    let provider = get_provider(&state, &slug).await?;
+    let metadata = state.federation.discover(&provider).await?;
+
+    let state_token = random_string();
+    let login = FederatedLoginState {
+        state_hash: blake3::hash(state_token.as_bytes()).into(),
+        provider_id: provider.id,
+        nonce: random_string(),
+        code_verifier: random_string(),
+        link_user_id: session.map(|AuthenticatedSession(session)| session.user_id),
+        next: query.next,
+        expires_at: Utc::now() + STATE_LIFETIME,
+    };
+    state.db.create_federated_login_state(&login).await?;
+    let url = FederationClient::authorization_url(
+        &metadata,
+        &provider,
+        &redirect_uri(&state, &provider),
+        &state_token,
+        &login.nonce,
+        &login.code_verifier,
+    )?;
+
+    // The provider returns the user with a cross-site navigation, so the cookie can't be strict
+    let cookie = new_secure_cookie(&state, STATE_COOKIE, state_token)
+        .same_site(SameSite::Lax)
+        .max_age(cookie::time::Duration::seconds(
+            STATE_LIFETIME.num_seconds(),
+        ));
+    Ok((cookies.add(cookie), Redirect::to(url.as_str())).into())
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct CallbackQuery {
+    /// Authorization code issued by the provider
+    pub code: Option<String>,
+    /// Value of the `state` parameter sent to the provider
+    pub state: String,
+    /// Error code, if the provider didn't authorize the login
+    pub error: Option<String>,
+}
+
+/// Finishes a login with the given provider
+///
+/// The provider redirects the user here after they log in. Logs the user in and redirects them
+/// to the path given when the login was started, or to the home page. Responds with
+/// `401 Unauthorized` if the account isn't linked to a user and can't be linked to one, and
+/// with `502 Bad Gateway` if the provider fails or returns an invalid ID token.
+pub async fn callback(
+    cookies: CookieJar,
+    Path(slug): Path<String>,
+    State(state): State<V1State>,
+    client: ClientInfo,
+    Query(query): Query<CallbackQuery>,
+) -> Result<WithCookies<Redirect>, ApiV1Error> {
+    // The state must come from the browser which started the login, to prevent login CSRF
+    if cookies
+        .get(STATE_COOKIE)
+        .is_none_or(|cookie| cookie.value() != query.state)
+    {
+        return Err(ApiV1Error::InvalidField(
+            "state",
+            "does not match the login started by this browser",
+        ));
+    }
+    let cookies =
+        cookies.remove(new_secure_cookie(&state, STATE_COOKIE, "").same_site(SameSite::Lax));
+    let provider = get_provider(&state, &slug).await?;
+    let login = match state
+        .db
+        .consume_federated_login_state(&blake3::hash(query.state.as_bytes()).into())
+        .await
+    {
+        Ok(login) if login.provider_id == provider.id => login,
+        Ok(_) | Err(DatabaseError::NotFound) => {
+            return Err(ApiV1Error::InvalidField("state", "invalid or expired"));
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let Some(code) = query.code.filter(|_| query.error.is_none()) else {
+        return Err(ApiV1Error::InvalidField(
+            "code",
+            "the provider didn't authorize the login",
+        ));
+    };
+
+    let metadata = state.federation.discover(&provider).await?;
+    let claims = state
+        .federation
+        .exchange_code(
+            &metadata,
+            &provider,
+            &redirect_uri(&state, &provider),
+            &code,
+            &login.code_verifier,
+            &login.nonce,
+        )
+        .await?;
+    let user = resolve_user(&state, &provider, &login, &claims).await?;
+
+    let next = login
+        .next
+        .unwrap_or_else(|| format!("{}/", state.config.base_path));
+    if login.link_user_id.is_some() {
+        return Ok((cookies, Redirect::to(&next)).into());
+    }
+    check_can_log_in(&state, &user)?;
+    let (session, cookies) = new_session(cookies, &state, user.id(), false, &client).await?;
+    let cookies = record_login(cookies, &state, &user, &session, &client).await;
+    info!(user = %user.id(), provider = %provider.id, "user logged in with federated identity");
+    Ok((cookies, Redirect::to(&next)).into())
+}
+
+/// Returns the user to whom the account described by `claims` is linked, linking it first if
+/// this is the account's first login.
+async fn resolve_user(
+    state: &V1StateInner,
+    provider: &FederatedProvider,
+    login: &FederatedLoginState,
+    claims: &IdTokenClaims,
+) -> Result<User, ApiV1Error> {
+    match state
+        .db
+        .get_federated_identity(&provider.id, &claims.sub)
+        .await
+    {
+        Ok(identity) if login.link_user_id.is_some_and(|id| id != identity.user_id) => {
+            return Err(ApiV1Error::IdentityLinked);
+        }
+        Ok(identity) => return Ok(state.db.get_user_by_id(&identity.user_id).await?),
+        Err(DatabaseError::NotFound) => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    // Email addresses which the provider hasn't verified could belong to anyone
+    let email = claims.email.as_deref().filter(|_| claims.email_verified);
+    let user = match (&login.link_user_id, email) {
+        (Some(user_id), _) => state.db.get_user_by_id(user_id).await?,
+        (None, Some(email)) => match state.db.get_user_by_email(email).await {
+            Ok(user) if user.email_verified_at().is_some() => user,
+            Err(DatabaseError::NotFound) if provider.auto_provision => {
+                provision_user(state, email, claims.name.as_deref()).await?
+            }
+            // Anyone could have set an address which wasn't confirmed, so its user has to log in
+            // and link the account themselves
+            Ok(_) | Err(DatabaseError::NotFound) => return Err(ApiV1Error::NoLinkedAccount),
+            Err(err) => return Err(err.into()),
+        },
+        (None, None) => return Err(ApiV1Error::NoLinkedAccount),
+    };
+    let identity = FederatedIdentity {
+        provider_id: provider.id,
+        subject: claims.sub.clone(),
+        user_id: *user.id(),
+        email: claims.email.clone(),
+        created_at: Utc::now(),
+    };
+    match state.db.create_federated_identity(&identity).await {
+        Ok(()) => {}
+        Err(DatabaseError::UniquenessViolation { .. }) => return Err(ApiV1Error::IdentityLinked),
+        Err(err) => return Err(err.into()),
+    }
+    info!(user = %user.id(), provider = %provider.id, "federated identity linked");
+    Ok(user)
+}
+
+/// Creates a user for an account logging in for the first time, if users may register themselves
+/// with its email address.
+async fn provision_user(
+    state: &V1StateInner,
+    email: &str,
+    name: Option<&str>,
+) -> Result<User, ApiV1Error> {
+    check_registration_allowed(state, email)?;
+    let id = new_uuid();
+    let user = UserCreate {
+        email: email.to_string(),
+        display_name: name
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .unwrap_or(email)
+            .to_string(),
+    };
+    let user = match state.db.create_user(&id, &user).await {
+        Ok(user) => user,
+        Err(DatabaseError::UniquenessViolation { .. }) => return Err(ApiV1Error::EmailTaken),
+        Err(err) => return Err(err.into()),
+    };
+    state.emit(WebhookEvent::UserCreated {
+        user_id: id,
+        email: user.email().to_string(),
+        display_name: user.display_name().to_string(),
+    });
+    info!(user = %id, "user provisioned from federated identity");
+    Ok(user)
+}
+
+/// Lists the accounts at upstream providers which are linked to the current user.
+pub async fn list_current_user_identities(
+    auth: Authenticated,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<FederatedIdentity>>, ApiV1Error> {
+    Ok(Json(
+        state
+            .db
+            .list_federated_identities_by_user_id(auth.user_id())
+            .await?,
+    ))
+}
+
+/// Unlinks the current user's accounts at the given provider.
+pub async fn delete_current_user_identities(
+    AuthenticatedSession(session): AuthenticatedSession,
+    Path(provider_id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state
+        .db
+        .delete_federated_identities(&session.user_id, &provider_id)
+        .await?;
+    info!(user = %session.user_id, provider = %provider_id, "federated identity unlinked");
+    Ok(())
+}
+
+/// Request to register a federated identity provider
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FederatedProviderRequest {
+    /// Unique short name of the provider, consisting of lowercase letters, digits, and hyphens
+    pub slug: String,
+    /// Name of the provider, shown on the login page
+    pub name: String,
+    /// Issuer identifier of the provider
+    pub issuer: String,
+    /// Client ID issued by the provider
+    pub client_id: String,
+    /// Client secret issued by the provider
+    pub client_secret: String,
+    /// Whether a new user is created when someone logs in for the first time with an account
+    /// which can't be linked to an existing user
+    #[serde(default)]
+    pub auto_provision: bool,
+}
+
+/// Checks that a required field isn't empty, returning it trimmed.
+fn validate_non_empty(field: &'static str, value: &str) -> Result<String, ApiV1Error> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(ApiV1Error::InvalidField(field, "must not be empty"));
+    }
+    Ok(value.to_string())
+}
+
+/// Checks that the slug can be used in URLs.
+fn validate_slug(slug: &str) -> Result<(), ApiV1Error> {
+    if slug.is_empty()
+        || !slug
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+    {
+        return Err(ApiV1Error::InvalidField(
+            "slug",
+            "must consist of lowercase letters, digits, and hyphens",
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that the issuer is an absolute HTTP(S) URL.
+fn validate_issuer(issuer: &str) -> Result<(), ApiV1Error> {
+    match Url::parse(issuer) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => Ok(()),
+        _ => Err(ApiV1Error::InvalidField(
+            "issuer",
+            "must be an absolute HTTP or HTTPS URL",
+        )),
+    }
+}
+
+pub async fn list_providers(
+    AdminSession { .. }: AdminSession,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<FederatedProvider>>, ApiV1Error> {
+    Ok(Json(state.db.list_federated_providers().await?))
+}
+
+/// Registers a federated identity provider.
+pub async fn create_provider(
+    AdminSession(session): AdminSession,
+    State(state): State<V1State>,
+    Json(request): Json<FederatedProviderRequest>,
+) -> Result<Json<FederatedProvider>, ApiV1Error> {
+    validate_slug(&request.slug)?;
+    let name = validate_non_empty("name", &request.name)?;
+    validate_issuer(&request.issuer)?;
+    let client_id = validate_non_empty("clientId", &request.client_id)?;
+    let now = Utc::now();
+    let provider = FederatedProvider {
+        id: new_uuid(),
+        slug: request.slug,
+        name,
+        issuer: request.issuer,
+        client_id,
+        client_secret: ViaJson(request.client_secret),
+        auto_provision: request.auto_provision,
+        created_at: now,
+        updated_at: now,
+    };
+    let provider = match state.db.create_federated_provider(&provider).await {
+        Ok(provider) => provider,
+        Err(DatabaseError::UniquenessViolation { .. }) => {
+            return Err(ApiV1Error::InvalidField(
+                "slug",
+                "another provider has the same slug",
+            ));
+        }
+        Err(err) => return Err(err.into()),
+    };
+    info!(provider = %provider.id, admin = %session.user_id, "federated provider registered");
+    Ok(Json(provider))
+}
+
+pub async fn get_provider_by_id(
+    AdminSession { .. }: AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<FederatedProvider>, ApiV1Error> {
+    Ok(Json(state.db.get_federated_provider_by_id(&id).await?))
+}
+
+/// Updates a federated identity provider.
+pub async fn update_provider(
+    AdminSession(session): AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    Json(mut update): Json<FederatedProviderUpdate>,
+) -> Result<Json<FederatedProvider>, ApiV1Error> {
+    if update.is_empty() {
+        return Ok(Json(state.db.get_federated_provider_by_id(&id).await?));
+    }
+    if let Some(name) = &update.name {
+        update.name = Some(validate_non_empty("name", name)?);
+    }
+    if let Some(issuer) = &update.issuer {
+        validate_issuer(issuer)?;
+    }
+    if let Some(client_id) = &update.client_id {
+        update.client_id = Some(validate_non_empty("clientId", client_id)?);
+    }
+    let provider = state.db.update_federated_provider(&id, &update).await?;
+    info!(provider = %id, admin = %session.user_id, "federated provider updated");
+    Ok(Json(provider))
+}
+
+/// Removes a federated identity provider, unlinking all accounts at it.
+pub async fn delete_provider(
+    AdminSession(session): AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.delete_federated_provider_by_id(&id).await?;
+    info!(provider = %id, admin = %session.user_id, "federated provider removed");
+    Ok(())
+}
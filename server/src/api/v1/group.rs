@@ -0,0 +1,398 @@
+//! # v1 group API endpoint handlers
+//!
+//! [`Group`]s are managed by users with the `groups:read` and `groups:write` permissions, who can
+//! only change the groups and tags which grant permissions they have themselves. Tags applied to a group apply to all of its
+//! members, including the members of its subgroups, so changes to a group's members, subgroups,
+//! or tags can grant or revoke administrator privileges. Users who lose them are logged out
+//! everywhere, like when the administrator tag is removed from them directly.
+
+use std::collections::HashSet;
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::info;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    alerts::SecurityEvent,
+    api::v1::{
+        ApiV1Error, V1State,
+        extractors::{RequirePermission, ValidatedJson, permission},
+        role::ensure_can_grant,
+        tag::tag_permissions,
+    },
+    db::interface::DatabaseError,
+    models::{
+        ADMIN_TAG, Group, GroupUpdate, MAX_NAME_LENGTH, Permissions, Tag, User, new_uuid, not_blank,
+    },
+};
+
+/// Request to create a group
+#[derive(Debug, Clone, Deserialize, JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateGroupRequest {
+    /// Group name (must be unique)
+    #[validate(
+        custom(function = not_blank),
+        length(max = MAX_NAME_LENGTH, message = "must be at most 100 characters long")
+    )]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupUsersQuery {
+    /// Whether to include the members of subgroups, at any depth
+    #[serde(default)]
+    pub include_subgroups: bool,
+}
+
+fn map_group_uniqueness_error(error: DatabaseError) -> ApiV1Error {
+    match error {
+        DatabaseError::UniquenessViolation { .. } => ApiV1Error::GroupExists,
+        error => error.into(),
+    }
+}
+
+/// Fetches the tags which apply to the members of the group with the given ID, i.e. those applied
+/// to the group or to a group containing it.
+async fn group_tags(state: &V1State, group_id: &Uuid) -> Result<Vec<Tag>, ApiV1Error> {
+    let mut group_ids = state.db.get_supergroup_ids(group_id).await?;
+    group_ids.push(*group_id);
+    let mut tags = Vec::new();
+    for id in &group_ids {
+        tags.extend(state.db.get_tags_by_group_id(id).await?);
+    }
+    Ok(tags)
+}
+
+/// Returns whether the administrator tag applies to the members of the group with the given ID.
+async fn grants_admin(state: &V1State, group_id: &Uuid) -> Result<bool, ApiV1Error> {
+    let tags = group_tags(state, group_id).await?;
+    Ok(tags.iter().any(|tag| tag.name == ADMIN_TAG))
+}
+
+/// Fetches the permissions granted to the members of the group with the given ID.
+async fn group_permissions(state: &V1State, group_id: &Uuid) -> Result<Permissions, ApiV1Error> {
+    let tags = group_tags(state, group_id).await?;
+    Ok(Permissions::for_tags(state.db.as_ref(), &tags).await?)
+}
+
+/// Returns the IDs of the given users who are administrators.
+async fn admin_ids(state: &V1State, users: &[User]) -> Result<HashSet<Uuid>, ApiV1Error> {
+    let mut admins = HashSet::new();
+    for user in users {
+        let tags = state.db.get_effective_tags_by_user_id(user.id()).await?;
+        if tags.iter().any(|tag| tag.name == ADMIN_TAG) {
+            admins.insert(*user.id());
+        }
+    }
+    Ok(admins)
+}
+
+/// Handles changes to the administrator privileges of the given users, of whom those in `before`
+/// were administrators before a change. Users who gained them are reported, and users who lost
+/// them are logged out everywhere.
+async fn apply_admin_changes(
+    state: &V1State,
+    users: &[User],
+    before: &HashSet<Uuid>,
+) -> Result<(), ApiV1Error> {
+    let after = admin_ids(state, users).await?;
+    for user in users {
+        match (before.contains(user.id()), after.contains(user.id())) {
+            (false, true) => state.report(SecurityEvent::AdminTagGranted {
+                user_id: *user.id(),
+                email: user.email().to_string(),
+            }),
+            (true, false) => {
+                state.db.revoke_sessions_by_user_id(user.id()).await?;
+                state.sessions.invalidate_user(user.id());
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+pub async fn list_groups(
+    RequirePermission { .. }: RequirePermission<permission::GroupsRead>,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<Group>>, ApiV1Error> {
+    Ok(Json(state.db.list_groups().await?))
+}
+
+/// Creates a new group.
+pub async fn create_group(
+    RequirePermission { session, .. }: RequirePermission<permission::GroupsWrite>,
+    State(state): State<V1State>,
+    ValidatedJson(request): ValidatedJson<CreateGroupRequest>,
+) -> Result<Json<Group>, ApiV1Error> {
+    let group = state
+        .db
+        .create_group(&new_uuid(), &GroupUpdate::new().with_name(request.name))
+        .await
+        .map_err(map_group_uniqueness_error)?;
+    info!(group = %group.id, admin = %session.user_id, "group created");
+    Ok(Json(group))
+}
+
+pub async fn get_group(
+    RequirePermission { .. }: RequirePermission<permission::GroupsRead>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Group>, ApiV1Error> {
+    Ok(Json(state.db.get_group_by_id(&id).await?))
+}
+
+/// Updates a group.
+pub async fn update_group(
+    RequirePermission { session, .. }: RequirePermission<permission::GroupsWrite>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    ValidatedJson(update): ValidatedJson<GroupUpdate>,
+) -> Result<Json<Group>, ApiV1Error> {
+    if update.is_empty() {
+        return Err(ApiV1Error::InvalidField("update", "must not be empty"));
+    }
+    let group = state
+        .db
+        .update_group(&id, &update)
+        .await
+        .map_err(map_group_uniqueness_error)?;
+    info!(group = %id, admin = %session.user_id, "group updated");
+    Ok(Json(group))
+}
+
+/// Deletes a group, along with its memberships and tags. Its subgroups are kept.
+pub async fn delete_group(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::GroupsWrite>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.get_group_by_id(&id).await?;
+    ensure_can_grant(&permissions, &group_permissions(&state, &id).await?)?;
+    let members = if grants_admin(&state, &id).await? {
+        state.db.get_users_by_group_id(&id, true).await?
+    } else {
+        Vec::new()
+    };
+    let admins = admin_ids(&state, &members).await?;
+    state.db.delete_group_by_id(&id).await?;
+    apply_admin_changes(&state, &members, &admins).await?;
+    info!(group = %id, admin = %session.user_id, "group deleted");
+    Ok(())
+}
+
+/// Returns the members of a group.
+pub async fn list_group_users(
+    RequirePermission { .. }: RequirePermission<permission::GroupsRead>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    Query(query): Query<GroupUsersQuery>,
+) -> Result<Json<Vec<User>>, ApiV1Error> {
+    // Distinguish a missing group from a group without members
+    state.db.get_group_by_id(&id).await?;
+    Ok(Json(
+        state
+            .db
+            .get_users_by_group_id(&id, query.include_subgroups)
+            .await?,
+    ))
+}
+
+/// Adds a user to a group. Adding a user who is already a member does nothing.
+pub async fn add_group_user(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::GroupsWrite>,
+    Path((id, user_id)): Path<(Uuid, Uuid)>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.get_group_by_id(&id).await?;
+    ensure_can_grant(&permissions, &group_permissions(&state, &id).await?)?;
+    let users = [state.db.get_user_by_id(&user_id).await?];
+    let admins = admin_ids(&state, &users).await?;
+    state.db.add_user_to_group(&id, &user_id).await?;
+    apply_admin_changes(&state, &users, &admins).await?;
+    info!(group = %id, user = %user_id, admin = %session.user_id, "user added to group");
+    Ok(())
+}
+
+/// Removes a user from a group.
+pub async fn remove_group_user(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::GroupsWrite>,
+    Path((id, user_id)): Path<(Uuid, Uuid)>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.get_group_by_id(&id).await?;
+    ensure_can_grant(&permissions, &group_permissions(&state, &id).await?)?;
+    let users = [state.db.get_user_by_id(&user_id).await?];
+    let admins = admin_ids(&state, &users).await?;
+    state.db.remove_user_from_group(&id, &user_id).await?;
+    apply_admin_changes(&state, &users, &admins).await?;
+    info!(group = %id, user = %user_id, admin = %session.user_id, "user removed from group");
+    Ok(())
+}
+
+/// Returns the direct subgroups of a group.
+pub async fn list_subgroups(
+    RequirePermission { .. }: RequirePermission<permission::GroupsRead>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<Group>>, ApiV1Error> {
+    state.db.get_group_by_id(&id).await?;
+    Ok(Json(state.db.get_subgroups(&id).await?))
+}
+
+/// Makes a group a subgroup of another. Adding an existing subgroup does nothing. Fails with
+/// `409 Conflict` if the parent group is the child group or one of its subgroups.
+pub async fn add_subgroup(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::GroupsWrite>,
+    Path((id, child_id)): Path<(Uuid, Uuid)>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.get_group_by_id(&id).await?;
+    state.db.get_group_by_id(&child_id).await?;
+    if child_id == id || state.db.get_supergroup_ids(&id).await?.contains(&child_id) {
+        return Err(ApiV1Error::GroupCycle);
+    }
+    ensure_can_grant(&permissions, &group_permissions(&state, &id).await?)?;
+    let members = if grants_admin(&state, &id).await? {
+        state.db.get_users_by_group_id(&child_id, true).await?
+    } else {
+        Vec::new()
+    };
+    let admins = admin_ids(&state, &members).await?;
+    state.db.add_subgroup(&id, &child_id).await?;
+    apply_admin_changes(&state, &members, &admins).await?;
+    info!(group = %id, subgroup = %child_id, admin = %session.user_id, "subgroup added");
+    Ok(())
+}
+
+/// Removes a subgroup from a group.
+pub async fn remove_subgroup(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::GroupsWrite>,
+    Path((id, child_id)): Path<(Uuid, Uuid)>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.get_group_by_id(&id).await?;
+    ensure_can_grant(&permissions, &group_permissions(&state, &id).await?)?;
+    let members = if grants_admin(&state, &id).await? {
+        state.db.get_users_by_group_id(&child_id, true).await?
+    } else {
+        Vec::new()
+    };
+    let admins = admin_ids(&state, &members).await?;
+    state.db.remove_subgroup(&id, &child_id).await?;
+    apply_admin_changes(&state, &members, &admins).await?;
+    info!(group = %id, subgroup = %child_id, admin = %session.user_id, "subgroup removed");
+    Ok(())
+}
+
+/// Returns the tags applied to a group.
+pub async fn list_group_tags(
+    RequirePermission { .. }: RequirePermission<permission::GroupsRead>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<Tag>>, ApiV1Error> {
+    state.db.get_group_by_id(&id).await?;
+    Ok(Json(state.db.get_tags_by_group_id(&id).await?))
+}
+
+/// Fetches the tag with the given ID, failing if it belongs to an organization, since groups
+/// span organizations.
+async fn get_group_tag(state: &V1State, tag_id: &Uuid) -> Result<Tag, ApiV1Error> {
+    let tag = state.db.get_tag_by_id(tag_id).await?;
+    if tag.organization_id.is_some() {
+        return Err(ApiV1Error::InvalidTag(
+            "organization tags can't be applied to groups",
+        ));
+    }
+    Ok(tag)
+}
+
+/// Applies a tag to a group, and so to all of its members. Applying a tag which the group already
+/// has does nothing.
+pub async fn add_group_tag(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::GroupsWrite>,
+    Path((id, tag_id)): Path<(Uuid, Uuid)>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.get_group_by_id(&id).await?;
+    let tag = get_group_tag(&state, &tag_id).await?;
+    ensure_can_grant(&permissions, &tag_permissions(&state, &tag).await?)?;
+    let members = if tag.name == ADMIN_TAG {
+        state.db.get_users_by_group_id(&id, true).await?
+    } else {
+        Vec::new()
+    };
+    let admins = admin_ids(&state, &members).await?;
+    state.db.add_tag_to_group(&id, &tag).await?;
+    apply_admin_changes(&state, &members, &admins).await?;
+    info!(group = %id, tag = %tag.name, admin = %session.user_id, "tag added to group");
+    Ok(())
+}
+
+/// Removes a tag from a group. Members who lose administrator privileges as a result are logged
+/// out everywhere.
+pub async fn remove_group_tag(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::GroupsWrite>,
+    Path((id, tag_id)): Path<(Uuid, Uuid)>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.get_group_by_id(&id).await?;
+    let tag = state.db.get_tag_by_id(&tag_id).await?;
+    ensure_can_grant(&permissions, &tag_permissions(&state, &tag).await?)?;
+    let members = if tag.name == ADMIN_TAG {
+        state.db.get_users_by_group_id(&id, true).await?
+    } else {
+        Vec::new()
+    };
+    let admins = admin_ids(&state, &members).await?;
+    state.db.remove_tag_from_group(&id, &tag).await?;
+    apply_admin_changes(&state, &members, &admins).await?;
+    info!(group = %id, tag = %tag.name, admin = %session.user_id, "tag removed from group");
+    Ok(())
+}
+
+/// Returns the groups of which a user is a direct member.
+pub async fn list_user_groups(
+    RequirePermission { .. }: RequirePermission<permission::GroupsRead>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<Group>>, ApiV1Error> {
+    state.db.get_user_by_id(&id).await?;
+    Ok(Json(state.db.get_groups_by_user_id(&id).await?))
+}
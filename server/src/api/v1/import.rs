@@ -0,0 +1,453 @@
+//! # v1 user import endpoint handler
+//!
+//! Import files are read line by line as the request body arrives, so that large files are never
+//! held in memory whole. Each line is imported on its own, and lines which can't be imported are
+//! reported without stopping the import.
+
+use std::{collections::VecDeque, error::Error as _};
+
+use aide::transform::TransformOperation;
+use axum::{
+    Json,
+    body::Body,
+    extract::{Query, State},
+};
+use http_body_util::{BodyExt, LengthLimitError};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use uuid::Uuid;
+use validator::{Validate, ValidationErrors};
+
+use crate::{
+    alerts::SecurityEvent,
+    api::v1::{
+        ApiV1Error, FieldErrors, V1State, collect_field_errors, describe_field_errors,
+        extractors::{RequirePermission, permission},
+        role::ensure_can_grant,
+    },
+    db::interface::DatabaseError,
+    models::{ADMIN_TAG, Permission, Permissions, RegistrationMode, Tag, UserCreate, new_uuid},
+    webhooks::WebhookEvent,
+};
+
+/// Format of a user import file
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    /// Comma-separated values, starting with a header row which names the `email` and
+    /// `displayName` columns. Fields may be quoted, but can't contain line breaks.
+    Csv,
+    /// One JSON object per line, with the same fields as the body of `POST /users`
+    Jsonl,
+}
+
+/// Query parameters for importing users
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct UserImportParams {
+    /// Format of the request body
+    pub format: ImportFormat,
+    /// Comma-separated IDs of tags to add to every imported user. Requires the `tags:write`
+    /// permission, along with every permission the tags grant.
+    pub tags: Option<String>,
+}
+
+/// Line of an import file which couldn't be imported
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserImportError {
+    /// Line number, starting at 1
+    pub line: u64,
+    /// Why the line couldn't be imported
+    pub message: String,
+}
+
+/// Outcome of a user import
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserImportReport {
+    /// Number of users created
+    pub imported: u64,
+    /// Lines which couldn't be imported, in file order
+    pub errors: Vec<UserImportError>,
+}
+
+/// Imports users from a CSV or JSON lines file.
+pub async fn import_users(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::UsersWrite>,
+    Query(params): Query<UserImportParams>,
+    State(state): State<V1State>,
+    body: Body,
+) -> Result<Json<UserImportReport>, ApiV1Error> {
+    if state.config.registration_mode == RegistrationMode::Closed {
+        return Err(ApiV1Error::RegistrationClosed);
+    }
+    let tags = import_tags(&state, &permissions, params.tags.as_deref()).await?;
+    let mut parser = RowParser::new(params.format);
+    let mut lines = BodyLines::new(body);
+    let mut report = UserImportReport::default();
+    // Results are bound before being matched, since errors aren't `Send` and so mustn't be held
+    // across the import below
+    loop {
+        let Some((number, line)) = lines.next().await? else {
+            break;
+        };
+        let row = parser.parse(&line)?;
+        let result = match row {
+            Row::User(user) => import_user(&state, &user, &tags).await?,
+            Row::Skipped => continue,
+            Row::Invalid(message) => Err(message),
+        };
+        match result {
+            Ok(()) => report.imported += 1,
+            Err(message) => report.errors.push(UserImportError {
+                line: number,
+                message,
+            }),
+        }
+    }
+    info!(
+        admin = %session.user_id,
+        imported = report.imported,
+        failed = report.errors.len(),
+        "users imported",
+    );
+    Ok(Json(report))
+}
+
+pub fn import_users_docs(op: TransformOperation) -> TransformOperation {
+    op.description(
+        "Creates users from a CSV or JSON lines file in the request body, in the format given by \
+        the `format` parameter. Blank lines are skipped. Each line is imported on its own: lines \
+        which are invalid or whose email address is taken are listed in the response, and don't \
+        stop the rest of the file from being imported. Import files may be up to 64 MiB in \
+        size; larger ones are rejected with `413 Payload Too Large`, after importing the lines \
+        read so far if the body's size isn't known in advance.",
+    )
+}
+
+/// Fetches the tags with the given comma-separated IDs, checking that the session's user may add
+/// them to users.
+async fn import_tags(
+    state: &V1State,
+    permissions: &Permissions,
+    ids: Option<&str>,
+) -> Result<Vec<Tag>, ApiV1Error> {
+    let mut tags: Vec<Tag> = Vec::new();
+    for id in ids.unwrap_or_default().split(',').map(str::trim) {
+        if id.is_empty() {
+            continue;
+        }
+        let id: Uuid = id.parse().map_err(|_| {
+            ApiV1Error::InvalidField("tags", "must be a comma-separated list of tag IDs")
+        })?;
+        if tags.iter().all(|tag| tag.id != id) {
+            tags.push(state.db.get_tag_by_id(&id).await?);
+        }
+    }
+    if !tags.is_empty() {
+        if !permissions.contains(Permission::TagsWrite) {
+            return Err(ApiV1Error::MissingPermission(Permission::TagsWrite));
+        }
+        ensure_can_grant(
+            permissions,
+            &Permissions::for_tags(state.db.as_ref(), &tags).await?,
+        )?;
+    }
+    Ok(tags)
+}
+
+/// Creates a user along with their tags. Returns `Ok(Err(_))` with the reason the user was
+/// rejected if they were, or `Err(_)` if the import can't go on.
+async fn import_user(
+    state: &V1State,
+    user: &UserCreate,
+    tags: &[Tag],
+) -> Result<Result<(), String>, ApiV1Error> {
+    if let Err(errors) = user.validate() {
+        return Ok(Err(describe_validation_errors(&errors)));
+    }
+    let id = new_uuid();
+    // Create the user and add their tags together, so that no user is left without their tags
+    let mut tx = state.db.begin().await?;
+    let user = match tx.create_user(&id, user).await {
+        Ok(user) => user,
+        Err(DatabaseError::UniquenessViolation { .. }) => {
+            return Ok(Err(ApiV1Error::EmailTaken.to_string()));
+        }
+        Err(err) => return Err(err.into()),
+    };
+    for tag in tags {
+        tx.add_tag_to_user(&id, tag).await?;
+    }
+    tx.commit().await?;
+    state.emit(WebhookEvent::UserCreated {
+        user_id: id,
+        email: user.email().to_string(),
+        display_name: user.display_name().to_string(),
+    });
+    if tags.iter().any(|tag| tag.name == ADMIN_TAG) {
+        state.report(SecurityEvent::AdminTagGranted {
+            user_id: id,
+            email: user.email().to_string(),
+        });
+    }
+    Ok(Ok(()))
+}
+
+fn describe_validation_errors(errors: &ValidationErrors) -> String {
+    let mut fields = FieldErrors::new();
+    collect_field_errors(errors, "", &mut fields);
+    describe_field_errors(&fields)
+}
+
+/// Parser for the lines of an import file
+enum RowParser {
+    /// CSV file, with the positions of the columns once the header has been read
+    Csv(Option<CsvColumns>),
+    Jsonl,
+}
+
+/// Line of an import file, as parsed by a [`RowParser`]
+enum Row {
+    User(UserCreate),
+    /// Line which doesn't contain a user, like a blank line or a header
+    Skipped,
+    /// Line which can't be parsed, with the reason why
+    Invalid(String),
+}
+
+/// Positions of the columns of a CSV import file
+struct CsvColumns {
+    count: usize,
+    email: usize,
+    display_name: usize,
+}
+
+impl RowParser {
+    fn new(format: ImportFormat) -> Self {
+        match format {
+            ImportFormat::Csv => Self::Csv(None),
+            ImportFormat::Jsonl => Self::Jsonl,
+        }
+    }
+
+    /// Parses a line of the file.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`ApiV1Error::InvalidField`] if the header of a CSV file can't be parsed, since
+    /// none of the lines after it could be either.
+    fn parse(&mut self, line: &[u8]) -> Result<Row, ApiV1Error> {
+        let Ok(line) = std::str::from_utf8(line) else {
+            return Ok(Row::Invalid("not valid UTF-8".to_string()));
+        };
+        if line.trim().is_empty() {
+            return Ok(Row::Skipped);
+        }
+        let row = match self {
+            Self::Jsonl => match serde_json::from_str(line) {
+                Ok(user) => Row::User(user),
+                Err(err) => Row::Invalid(format!("invalid JSON: {err}")),
+            },
+            Self::Csv(columns @ None) => {
+                *columns = Some(
+                    CsvColumns::from_header(line).ok_or(ApiV1Error::InvalidField(
+                        "CSV header",
+                        "must name the `email` and `displayName` columns",
+                    ))?,
+                );
+                Row::Skipped
+            }
+            Self::Csv(Some(columns)) => match split_csv_line(line) {
+                Ok(fields) if fields.len() != columns.count => Row::Invalid(format!(
+                    "expected {} fields, found {}",
+                    columns.count,
+                    fields.len()
+                )),
+                Ok(mut fields) => Row::User(UserCreate {
+                    email: std::mem::take(&mut fields[columns.email]),
+                    display_name: std::mem::take(&mut fields[columns.display_name]),
+                }),
+                Err(message) => Row::Invalid(message),
+            },
+        };
+        Ok(row)
+    }
+}
+
+impl CsvColumns {
+    fn from_header(line: &str) -> Option<Self> {
+        let names = split_csv_line(line).ok()?;
+        let find = |wanted: &[&str]| names.iter().position(|name| wanted.contains(&name.trim()));
+        Some(Self {
+            count: names.len(),
+            email: find(&["email"])?,
+            display_name: find(&["displayName", "display_name"])?,
+        })
+    }
+}
+
+/// Splits a line of CSV into its fields. Fields may be enclosed in double quotes, in which case
+/// they may contain commas, and double quotes written twice.
+fn split_csv_line(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        let mut last = true;
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next() {
+                    Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err("unterminated quoted field".to_string()),
+                }
+            }
+            match chars.next() {
+                Some(',') => last = false,
+                Some(_) => return Err("unexpected text after quoted field".to_string()),
+                None => (),
+            }
+        } else {
+            for c in chars.by_ref() {
+                match c {
+                    ',' => {
+                        last = false;
+                        break;
+                    }
+                    '"' => return Err("unexpected quote in unquoted field".to_string()),
+                    c => field.push(c),
+                }
+            }
+        }
+        fields.push(field);
+        if last {
+            return Ok(fields);
+        }
+    }
+}
+
+/// Reader of the lines of a request body, which reads the body as it arrives
+struct BodyLines {
+    body: Body,
+    /// Lines which have been read but not returned yet
+    lines: VecDeque<Vec<u8>>,
+    /// Start of a line whose end hasn't been read yet
+    partial: Vec<u8>,
+    /// Number of the last line returned
+    number: u64,
+    ended: bool,
+}
+
+impl BodyLines {
+    fn new(body: Body) -> Self {
+        Self {
+            body,
+            lines: VecDeque::new(),
+            partial: Vec::new(),
+            number: 0,
+            ended: false,
+        }
+    }
+
+    /// Returns the next line along with its number, without its line ending, or [`None`] once the
+    /// body has been read whole.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`ApiV1Error::PayloadTooLarge`] if the body exceeds the route's size limit.
+    async fn next(&mut self) -> Result<Option<(u64, Vec<u8>)>, ApiV1Error> {
+        while self.lines.is_empty() && !self.ended {
+            match self.body.frame().await {
+                Some(Ok(frame)) => {
+                    let Ok(data) = frame.into_data() else {
+                        continue;
+                    };
+                    let mut rest = &data[..];
+                    while let Some(end) = rest.iter().position(|&b| b == b'\n') {
+                        self.partial.extend_from_slice(&rest[..end]);
+                        self.lines.push_back(std::mem::take(&mut self.partial));
+                        rest = &rest[end + 1..];
+                    }
+                    self.partial.extend_from_slice(rest);
+                }
+                Some(Err(err)) => return Err(body_error(err)),
+                None => {
+                    self.ended = true;
+                    if !self.partial.is_empty() {
+                        self.lines.push_back(std::mem::take(&mut self.partial));
+                    }
+                }
+            }
+        }
+        let Some(mut line) = self.lines.pop_front() else {
+            return Ok(None);
+        };
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        // Spreadsheet programs often start files with a byte order mark
+        if self.number == 0 && line.starts_with(b"\xEF\xBB\xBF") {
+            line.drain(..3);
+        }
+        self.number += 1;
+        Ok(Some((self.number, line)))
+    }
+}
+
+/// Converts an error reading a request body into an [`ApiV1Error`].
+fn body_error(error: axum::Error) -> ApiV1Error {
+    let mut source = error.source();
+    while let Some(err) = source {
+        if err.is::<LengthLimitError>() {
+            return ApiV1Error::PayloadTooLarge;
+        }
+        source = err.source();
+    }
+    ApiV1Error::InternalServerError(error.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_csv_line() {
+        assert_eq!(split_csv_line("a,b,c").unwrap(), ["a", "b", "c"]);
+        assert_eq!(split_csv_line("a,,").unwrap(), ["a", "", ""]);
+        assert_eq!(
+            split_csv_line(r#""Doe, Jane","say ""hi""",x"#).unwrap(),
+            ["Doe, Jane", r#"say "hi""#, "x"]
+        );
+        assert_eq!(split_csv_line(r#""""#).unwrap(), [""]);
+        assert!(split_csv_line(r#""open"#).is_err());
+        assert!(split_csv_line(r#""a"b"#).is_err());
+        assert!(split_csv_line(r#"a"b"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let mut parser = RowParser::new(ImportFormat::Csv);
+        assert!(matches!(
+            parser.parse(b"display_name,email"),
+            Ok(Row::Skipped)
+        ));
+        let Ok(Row::User(user)) = parser.parse(b"Jane,jane@kasad.com") else {
+            panic!("expected a user");
+        };
+        assert_eq!(user.email, "jane@kasad.com");
+        assert_eq!(user.display_name, "Jane");
+        assert!(matches!(parser.parse(b"  "), Ok(Row::Skipped)));
+        assert!(matches!(parser.parse(b"Jane"), Ok(Row::Invalid(_))));
+        assert!(matches!(parser.parse(b"\xFF,x"), Ok(Row::Invalid(_))));
+
+        let mut parser = RowParser::new(ImportFormat::Csv);
+        assert!(parser.parse(b"email,name").is_err());
+    }
+}
@@ -1,77 +1,379 @@
 //! v1 API implementation
 
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 use aide::{
     OperationOutput,
     axum::{
         ApiRouter,
-        routing::{get, post},
+        routing::{delete, get, get_with, patch, post, post_with, put},
     },
     generate::GenContext,
     openapi::{
-        ApiKeyLocation, MediaType, OpenApi, Operation, Response as OapiResponse, SecurityScheme,
+        ApiKeyLocation, OpenApi, Operation, Response as OapiResponse, SecurityScheme, Server,
     },
 };
 use axum::{
-    Extension, Router,
-    http::{HeaderValue, Method, StatusCode, header::VARY},
+    Extension, Json, Router,
+    http::{
+        HeaderValue, Method, StatusCode,
+        header::{RETRY_AFTER, VARY},
+    },
+    middleware::{from_fn, from_fn_with_state},
     response::{IntoResponse, Response},
 };
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{Map, Value, json};
 use tower_http::{
     cors::{Any, CorsLayer},
+    limit::RequestBodyLimitLayer,
     set_header::SetResponseHeaderLayer,
 };
-use webauthn_rs::Webauthn;
+use validator::{ValidationErrors, ValidationErrorsKind};
 
 use crate::{
-    api::{middleware::CacheControlLayer, utils::PreSerializedJson},
+    alerts::SecurityEvent,
+    api::{
+        ApiOptions, MAX_REQUEST_PAYLOAD_BYTES, MAX_STREAMED_PAYLOAD_BYTES,
+        middleware::{self, CacheControlLayer},
+        utils::{InvalidCursor, PreSerializedJson},
+    },
+    bans::IpBanList,
+    crypto::jwt::{KeySet, SigningKey},
     db::interface::{DatabaseClient, DatabaseError},
-    models::AppConfig,
+    federation::{FederationClient, FederationError},
+    models::{AppConfig, Permission, UserStatus},
+    rate_limit::RateLimiter,
+    relying_party::RelyingParties,
+    session_cookie::SessionCookies,
+    webhooks::{WebhookDispatcher, WebhookEvent, WebhookOptions},
 };
 
 use super::middleware::Publicity;
 
+mod announcement;
 mod auth;
+mod bans;
 mod config;
+mod email;
+mod export;
 mod extractors;
+mod federation;
+mod group;
+mod import;
+mod notify;
+mod oauth;
+mod organization;
+mod passkey;
+mod policy;
+mod problem;
+mod role;
+mod saml;
+mod session_cache;
+mod tag;
+mod token;
 mod user;
+mod webhook;
+
+pub(super) use problem::ProblemDetails;
+use session_cache::SessionCache;
 
 struct V1StateInner {
     db: Arc<dyn DatabaseClient>,
-    webauthn: Webauthn,
-    config: PreSerializedJson<AppConfig>,
+    relying_parties: RelyingParties,
+    config: AppConfig,
+    config_json: PreSerializedJson<AppConfig>,
+    options: ApiOptions,
+    bans: Arc<IpBanList>,
+    sessions: SessionCache,
+    session_cookies: SessionCookies,
+    webhooks: Arc<WebhookDispatcher>,
+    /// Keys with which the OAuth provider signs tokens
+    keys: Arc<KeySet>,
+    /// Issuer identifier of the OAuth provider, which is also the base URL of its endpoints
+    issuer: String,
+    /// Absolute URL of the v1 API, from which the URLs given to other parties are built
+    api_url: String,
+    federation: FederationClient,
+}
+
+impl V1StateInner {
+    fn new(
+        db: Arc<dyn DatabaseClient>,
+        relying_parties: RelyingParties,
+        config: &AppConfig,
+        options: ApiOptions,
+        bans: Arc<IpBanList>,
+    ) -> Self {
+        let origin = relying_parties
+            .default_party()
+            .get_allowed_origins()
+            .first()
+            .map_or("", |origin| origin.as_str().trim_end_matches('/'))
+            .to_string();
+        Self {
+            keys: options.signing_keys.clone().unwrap_or_else(|| {
+                Arc::new(KeySet::new(
+                    SigningKey::generate().expect("generating signing key failed"),
+                ))
+            }),
+            issuer: format!("{origin}{}/api/v1/oauth", config.base_path),
+            api_url: format!("{origin}{}/api/v1", config.base_path),
+            federation: FederationClient::new(),
+            webhooks: Arc::new(WebhookDispatcher::new(
+                Arc::clone(&db),
+                WebhookOptions::default(),
+            )),
+            db,
+            relying_parties,
+            config: config.clone(),
+            config_json: PreSerializedJson::new(config).expect("serializing app config failed"),
+            sessions: SessionCache::new(options.session_cache_ttl),
+            session_cookies: SessionCookies::new(&options.session_cookies, cookie_path(config)),
+            options,
+            bans,
+        }
+    }
+
+    /// Returns the path to use for cookies set by the API, i.e. the app's base path, or `/` if
+    /// the app is served from the root.
+    fn cookie_path(&self) -> &str {
+        cookie_path(&self.config)
+    }
+
+    /// Reports a security event to the [alerter][ApiOptions::alerter], if one is configured.
+    fn report(&self, event: SecurityEvent) {
+        if let Some(alerter) = &self.options.alerter {
+            alerter.report(event);
+        }
+    }
+
+    /// Delivers an event to the registered webhooks which want it.
+    fn emit(&self, event: WebhookEvent) {
+        self.webhooks.dispatch(event);
+    }
+}
+
+/// Returns the path to use for cookies set by the API for the given app configuration.
+fn cookie_path(config: &AppConfig) -> &str {
+    if config.base_path.is_empty() {
+        "/"
+    } else {
+        &config.base_path
+    }
 }
 
 type V1State = Arc<V1StateInner>;
 
-/// Returns a sub-router for `/api/v1` and its [`OpenApi`] specification.
-///
-/// # Panics
-///
-/// Panics if serializing the given `config` into JSON fails.
-pub fn router_and_spec(
-    db: Arc<dyn DatabaseClient>,
-    webauthn: Webauthn,
-    config: &AppConfig,
-) -> (Router<()>, OpenApi) {
-    // Public (cross-origin allowed) router
-    let router_public: ApiRouter<V1State> = ApiRouter::new()
-        .api_route("/health", get(async || ()))
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods(Method::GET)
-                .allow_credentials(false),
-        );
+/// Returns the router for endpoints with which users manage their own account.
+fn router_account() -> ApiRouter<V1State> {
+    ApiRouter::new()
+        .api_route(
+            "/users/me",
+            get(user::get_current_user)
+                .patch_with(user::update_current_user, user::update_current_user_docs),
+        )
+        .api_route(
+            "/users/me/preferences",
+            patch(user::update_current_user_preferences),
+        )
+        .api_route(
+            "/users/me/passkeys",
+            get(passkey::list_current_user_passkeys),
+        )
+        .api_route("/users/me/sessions", get(auth::list_current_user_sessions))
+        .api_route(
+            "/users/me/tokens",
+            get(token::list_api_tokens).post(token::create_api_token),
+        )
+        .api_route("/users/me/tokens/{id}", delete(token::delete_api_token))
+        .api_route(
+            "/users/me/passkeys/register/start",
+            post(passkey::start_passkey_registration),
+        )
+        .api_route(
+            "/users/me/passkeys/register/finish",
+            post(passkey::finish_passkey_registration),
+        )
+        .api_route(
+            "/passkeys/{id}",
+            patch(passkey::rename_passkey).delete(passkey::delete_passkey),
+        )
+}
 
-    // Router for endpoints whose responses depend on authentication state.
-    let router_auth: ApiRouter<V1State> = ApiRouter::new()
-        .api_route("/users/{id}", get(user::get_user))
-        .api_route("/users", post(user::post_user))
-        .api_route("/users/me", get(user::get_current_user))
-        .api_route("/logout", post(auth::logout))
+/// Returns the router for the OAuth provider's endpoints which depend on authentication state.
+fn router_oauth() -> ApiRouter<V1State> {
+    ApiRouter::new()
+        .api_route("/oauth/authorize", get(oauth::authorize))
+        .api_route("/oauth/token", post(oauth::token))
+        .api_route(
+            "/oauth/userinfo",
+            get(oauth::userinfo).post(oauth::userinfo),
+        )
+        .api_route(
+            "/oauth/clients",
+            get(oauth::list_clients).post(oauth::create_client),
+        )
+        .api_route(
+            "/oauth/clients/{id}",
+            get(oauth::get_client)
+                .patch(oauth::update_client)
+                .delete(oauth::delete_client),
+        )
+}
+
+/// Returns the router for the SAML identity provider's endpoints which depend on authentication
+/// state.
+fn router_saml() -> ApiRouter<V1State> {
+    ApiRouter::new()
+        .api_route("/saml/sso", get(saml::sso_get).post(saml::sso_post))
+        .api_route(
+            "/saml/providers",
+            get(saml::list_service_providers).post(saml::create_service_provider),
+        )
+        .api_route(
+            "/saml/providers/{id}",
+            get(saml::get_service_provider)
+                .patch(saml::update_service_provider)
+                .delete(saml::delete_service_provider),
+        )
+}
+
+/// Returns the router for identity federation endpoints.
+fn router_federation() -> ApiRouter<V1State> {
+    ApiRouter::new()
+        .api_route("/auth/federated", get(federation::list_login_providers))
+        .api_route(
+            "/users/me/federated-identities",
+            get(federation::list_current_user_identities),
+        )
+        .api_route(
+            "/users/me/federated-identities/{provider_id}",
+            delete(federation::delete_current_user_identities),
+        )
+        .api_route(
+            "/federation/providers",
+            get(federation::list_providers).post(federation::create_provider),
+        )
+        .api_route(
+            "/federation/providers/{id}",
+            get(federation::get_provider_by_id)
+                .patch(federation::update_provider)
+                .delete(federation::delete_provider),
+        )
+}
+
+/// Returns the router for organization endpoints.
+fn router_organizations() -> ApiRouter<V1State> {
+    ApiRouter::new()
+        .api_route(
+            "/users/me/organizations",
+            get(organization::list_current_user_organizations),
+        )
+        .api_route("/auth/organization", put(organization::select_organization))
+        .api_route(
+            "/orgs",
+            get(organization::list_organizations).post(organization::create_organization),
+        )
+        .api_route(
+            "/orgs/{id}",
+            get(organization::get_organization)
+                .patch(organization::update_organization)
+                .delete(organization::delete_organization),
+        )
+        .api_route(
+            "/orgs/{id}/users",
+            get(organization::list_organization_members),
+        )
+        .api_route(
+            "/orgs/{id}/users/{user_id}",
+            put(organization::set_organization_member)
+                .delete(organization::remove_organization_member),
+        )
+        .api_route(
+            "/orgs/{id}/users/{user_id}/tags/{tag_id}",
+            put(organization::add_organization_tag_to_member)
+                .delete(organization::remove_organization_tag_from_member),
+        )
+        .api_route(
+            "/orgs/{id}/tags",
+            get(organization::list_organization_tags).post(organization::create_organization_tag),
+        )
+}
+
+fn router_groups() -> ApiRouter<V1State> {
+    ApiRouter::new()
+        .api_route("/users/{id}/groups", get(group::list_user_groups))
+        .api_route("/groups", get(group::list_groups).post(group::create_group))
+        .api_route(
+            "/groups/{id}",
+            get(group::get_group)
+                .patch(group::update_group)
+                .delete(group::delete_group),
+        )
+        .api_route("/groups/{id}/users", get(group::list_group_users))
+        .api_route(
+            "/groups/{id}/users/{user_id}",
+            put(group::add_group_user).delete(group::remove_group_user),
+        )
+        .api_route("/groups/{id}/groups", get(group::list_subgroups))
+        .api_route(
+            "/groups/{id}/groups/{child_id}",
+            put(group::add_subgroup).delete(group::remove_subgroup),
+        )
+        .api_route("/groups/{id}/tags", get(group::list_group_tags))
+        .api_route(
+            "/groups/{id}/tags/{tag_id}",
+            put(group::add_group_tag).delete(group::remove_group_tag),
+        )
+}
+
+fn router_policies() -> ApiRouter<V1State> {
+    ApiRouter::new()
+        .api_route(
+            "/policies",
+            get(policy::list_policies).post(policy::create_policy),
+        )
+        .api_route("/policies/validate", post(policy::validate_policy))
+        .api_route(
+            "/policies/{id}",
+            get(policy::get_policy)
+                .patch(policy::update_policy)
+                .delete(policy::delete_policy),
+        )
+        .api_route("/policies/{id}/versions", get(policy::list_policy_versions))
+        .api_route(
+            "/policies/{id}/versions/{version}",
+            get(policy::get_policy_version),
+        )
+        .api_route(
+            "/policies/{id}/versions/{version}/restore",
+            post(policy::restore_policy_version),
+        )
+}
+
+fn router_roles() -> ApiRouter<V1State> {
+    ApiRouter::new()
+        .api_route("/roles", get(role::list_roles).post(role::create_role))
+        .api_route(
+            "/roles/{id}",
+            get(role::get_role)
+                .patch(role::update_role)
+                .delete(role::delete_role),
+        )
+        .api_route("/tags/{id}/roles", get(role::list_tag_roles))
+        .api_route(
+            "/tags/{id}/roles/{role_id}",
+            put(role::add_tag_role).delete(role::remove_tag_role),
+        )
+}
+
+/// Returns the router for the login and registration ceremonies, which are rate-limited by the
+/// given limiter, if any.
+fn router_login(rate_limiter: Option<Arc<RateLimiter>>) -> ApiRouter<V1State> {
+    let router = ApiRouter::new()
         .api_route("/register/start", post(auth::start_registration))
         .api_route("/register/finish", post(auth::finish_registration))
         .api_route("/auth/start", post(auth::start_authentication))
@@ -85,13 +387,150 @@ pub fn router_and_spec(
             post(auth::finish_conditional_ui_authentication),
         )
         .api_route("/auth/upgrade", post(auth::upgrade_session))
+        .api_route("/auth/reauth/start", post(auth::start_reauthentication))
+        .api_route("/auth/reauth/finish", post(auth::finish_reauthentication))
+        .api_route("/auth/federated/{slug}/start", get(federation::start))
+        .api_route("/auth/federated/{slug}/callback", get(federation::callback));
+    match rate_limiter {
+        Some(limiter) => router.route_layer(from_fn_with_state(limiter, middleware::rate_limit)),
+        None => router,
+    }
+}
+
+/// Returns the router for endpoints whose responses depend on authentication state.
+fn router_auth(rate_limiter: Option<Arc<RateLimiter>>) -> ApiRouter<V1State> {
+    ApiRouter::new()
+        .merge(router_login(rate_limiter))
+        .merge(router_account())
+        .merge(router_oauth())
+        .merge(router_saml())
+        .merge(router_federation())
+        .merge(router_organizations())
+        .merge(router_groups())
+        .merge(router_roles())
+        .merge(router_policies())
+        .api_route(
+            "/users/{id}",
+            get(user::get_user)
+                .patch_with(user::update_user, user::update_user_docs)
+                .delete_with(user::delete_user, user::delete_user_docs),
+        )
+        .api_route(
+            "/users/{id}/notes",
+            get(user::get_user_notes).put(user::put_user_notes),
+        )
+        .api_route("/users/{id}/merge", post(user::merge_user))
+        .api_route(
+            "/users/{id}/anonymize",
+            post_with(user::anonymize_user, user::anonymize_user_docs),
+        )
+        .api_route("/users/{id}/logout-all", post(user::logout_user_everywhere))
+        .api_route("/users/{id}/unlock", post(user::unlock_user))
+        .api_route("/users/{id}/status", put(user::set_user_status))
+        .api_route(
+            "/users/{id}/tags/{tag_id}",
+            put(tag::add_tag_to_user).delete(tag::remove_tag_from_user),
+        )
+        .api_route("/users", get(user::list_users).post(user::post_user))
+        .api_route("/users/search", get(user::search_users))
+        .api_route(
+            "/users/export",
+            get_with(export::export_users, export::export_users_docs),
+        )
+        .api_route("/tags", get(tag::list_tags).post(tag::create_tag))
+        .api_route(
+            "/tags/{id}",
+            get(tag::get_tag)
+                .patch(tag::update_tag)
+                .delete(tag::delete_tag),
+        )
+        .api_route("/tags/{id}/users", get(tag::get_tag_users))
+        .api_route("/logout", post(auth::logout))
         .api_route("/auth/downgrade", post(auth::downgrade_session))
         .api_route("/auth/session", get(auth::get_session))
+        .api_route("/auth/revoke", post(auth::revoke_session))
+        .api_route(
+            "/auth/confirm-email",
+            post_with(user::confirm_email_change, user::confirm_email_change_docs),
+        )
+        .api_route("/auth/revoke-all", post(auth::revoke_all_sessions))
+        .api_route("/email/bounces", post(email::report_bounce))
+        .api_route("/email/suppressions", get(email::list_suppressions))
+        .api_route(
+            "/email/suppressions/{address}",
+            delete(email::delete_suppression),
+        )
+        .api_route("/email/test", post(email::send_test_email))
+        .api_route(
+            "/webhooks",
+            get(webhook::list_webhooks).post(webhook::create_webhook),
+        )
+        .api_route(
+            "/webhooks/{id}",
+            get(webhook::get_webhook)
+                .patch(webhook::update_webhook)
+                .delete(webhook::delete_webhook),
+        )
+        .api_route("/ip-bans", get(bans::list_bans).post(bans::create_ban))
+        .api_route("/ip-bans/{id}", delete(bans::delete_ban))
+        .api_route(
+            "/announcement",
+            get(announcement::get_announcement)
+                .put(announcement::put_announcement)
+                .delete(announcement::delete_announcement),
+        )
+        .layer(SetResponseHeaderLayer::appending(
+            VARY,
+            HeaderValue::from_static("Cookie, Authorization"),
+        ))
+        .layer(CacheControlLayer::new().no_store(true).finish())
+}
+
+/// Returns the router for endpoints which process their request bodies as they arrive, and so
+/// accept larger bodies than other endpoints.
+fn router_streamed() -> ApiRouter<V1State> {
+    ApiRouter::new()
+        .api_route(
+            "/users/import",
+            post_with(import::import_users, import::import_users_docs),
+        )
+        .layer(RequestBodyLimitLayer::new(MAX_STREAMED_PAYLOAD_BYTES))
         .layer(SetResponseHeaderLayer::appending(
             VARY,
-            HeaderValue::from_static("Cookie"),
+            HeaderValue::from_static("Cookie, Authorization"),
         ))
-        .layer(CacheControlLayer::new().no_store(true).finish());
+        .layer(CacheControlLayer::new().no_store(true).finish())
+}
+
+/// Returns a sub-router for `/api/v1` and its [`OpenApi`] specification.
+///
+/// # Panics
+///
+/// Panics if serializing the given `config` into JSON fails.
+pub fn router_and_spec(
+    db: Arc<dyn DatabaseClient>,
+    relying_parties: RelyingParties,
+    config: &AppConfig,
+    options: ApiOptions,
+    bans: Arc<IpBanList>,
+) -> (Router<()>, OpenApi) {
+    // Public (cross-origin allowed) router
+    let router_public: ApiRouter<V1State> = ApiRouter::new()
+        .api_route("/health", get(async || ()))
+        .api_route(
+            "/oauth/.well-known/openid-configuration",
+            get(oauth::get_provider_metadata),
+        )
+        .api_route("/oauth/jwks", get(oauth::get_jwks))
+        .api_route("/saml/metadata", get(saml::get_metadata))
+        .layer(
+            CorsLayer::new()
+                .allow_origin(Any)
+                .allow_methods(Method::GET)
+                .allow_credentials(false),
+        );
+
+    let router_auth = router_auth(options.rate_limiter.clone());
 
     // Router for endpoints whose responses do not depend on authentication state.
     let mut router_unauthenticated: ApiRouter<V1State> = ApiRouter::new()
@@ -104,30 +543,38 @@ pub fn router_and_spec(
         use aide::scalar::Scalar;
         router_unauthenticated = router_unauthenticated.route(
             "/docs",
-            Scalar::new("/api/v1/docs/openapi.json").axum_route(),
+            Scalar::new(format!("{}/api/v1/docs/openapi.json", config.base_path)).axum_route(),
         );
     }
 
-    // Allow clients/proxies to cache for up to 24 hours
-    router_unauthenticated = router_unauthenticated.layer(
-        CacheControlLayer::new()
-            .publicity(Publicity::Public)
-            .max_age(Duration::hours(24))
-            .finish(),
-    );
-
-    let state = V1StateInner {
-        db,
-        webauthn,
-        config: PreSerializedJson::new(config).expect("serializing app config failed"),
-    };
+    // Allow clients/proxies to cache for up to 24 hours, and to revalidate cheaply afterwards
+    router_unauthenticated = router_unauthenticated
+        .layer(
+            CacheControlLayer::new()
+                .publicity(Publicity::Public)
+                .max_age(Duration::hours(24))
+                .finish(),
+        )
+        .layer(from_fn(middleware::conditional_requests));
+
+    let state = V1StateInner::new(db, relying_parties, config, options, bans);
     let mut openapi = OpenApi::default();
     let mut router = router_public
         .merge(router_auth)
         .merge(router_unauthenticated)
+        // Layers only apply to the routes added before them, so streamed endpoints keep their own
+        // limit
+        .layer(RequestBodyLimitLayer::new(MAX_REQUEST_PAYLOAD_BYTES))
+        .merge(router_streamed())
+        .fallback(not_found)
+        .layer(from_fn(problem::negotiate_error_format))
         .with_state(Arc::new(state))
         .finish_api_with(&mut openapi, |api| {
-            api.security_scheme(
+            api.server(Server {
+                url: format!("{}/api/v1", config.base_path),
+                ..Default::default()
+            })
+            .security_scheme(
                 "userSession",
                 SecurityScheme::ApiKey {
                     location: ApiKeyLocation::Cookie,
@@ -137,6 +584,16 @@ pub fn router_and_spec(
                     extensions: Default::default(),
                 },
             )
+            .security_scheme(
+                "apiToken",
+                SecurityScheme::Http {
+                    scheme: "bearer".to_string(),
+                    bearer_format: None,
+                    description: Some("An API token created by the user, sent in an `Authorization: Bearer` header. Only accepted by some endpoints.".to_string()),
+                    #[allow(clippy::default_trait_access, reason = "using the type would require a direct dependency on indexmap")]
+                    extensions: Default::default(),
+                },
+            )
         });
 
     // Add OpenAPI spec JSON to the router
@@ -150,10 +607,11 @@ pub fn router_and_spec(
 /// # Error type for the v1 API
 ///
 /// Implements [`IntoResponse`], thus returning a response with a sensible status code when used as
-/// the return type of a handler. Currently, the response body is a plain text error message, but
-/// that will change to JSON in the future.
+/// the return type of a handler. The response body is an [`ApiErrorResponse`], whose
+/// [code][ApiErrorCode] clients can rely on to tell errors apart, unlike the message, or
+/// [problem details][problem] if the client prefers them.
 #[derive(Debug, thiserror::Error)]
-enum ApiV1Error {
+pub(super) enum ApiV1Error {
     #[error("Not found")]
     NotFound,
 
@@ -189,6 +647,131 @@ enum ApiV1Error {
 
     #[error("Session downgrade impossible")]
     DowngradeImpossible,
+
+    #[error("Invalid, missing, or expired webhook signature")]
+    InvalidWebhookSecret,
+
+    #[error("Email is not configured")]
+    EmailDisabled,
+
+    #[error("Cannot merge a user into itself")]
+    MergeIntoSelf,
+
+    #[error("Invalid IP address or network: {0}")]
+    InvalidNetwork(String),
+
+    #[error("Invalid announcement: {0}")]
+    InvalidAnnouncement(&'static str),
+
+    #[error("Invalid pagination cursor")]
+    InvalidCursor,
+
+    #[error("Invalid tag: {0}")]
+    InvalidTag(&'static str),
+
+    #[error("A tag with that name already exists")]
+    TagExists,
+
+    #[error("Cannot remove the last passkey of an account")]
+    LastPasskey,
+
+    #[error("Email address is already in use")]
+    EmailTaken,
+
+    #[error("Invalid {0}: {1}")]
+    InvalidField(&'static str, &'static str),
+
+    #[error("Invalid or expired API token")]
+    InvalidApiToken,
+
+    #[error("API token lacks the scope required for this request")]
+    InsufficientScope,
+
+    #[error("Federated login failed: {0}")]
+    FederationFailed(#[from] FederationError),
+
+    #[error("No account is linked to this identity")]
+    NoLinkedAccount,
+
+    #[error("This identity is already linked to another account")]
+    IdentityLinked,
+
+    #[error("Registration is closed")]
+    RegistrationClosed,
+
+    #[error("Registration is not allowed with email addresses at this domain")]
+    EmailDomainNotAllowed,
+
+    #[error("Not allowed while impersonating a user")]
+    Impersonating,
+
+    #[error("Re-authentication is required for this action")]
+    ReauthenticationRequired,
+
+    #[error("Registration could not be completed")]
+    RegistrationFailed,
+
+    #[error("Passkeys from this authenticator are not allowed")]
+    AuthenticatorNotAllowed,
+
+    #[error("Account is locked until {}", .0.format("%Y-%m-%d %H:%M:%S UTC"))]
+    AccountLocked(DateTime<Utc>),
+
+    #[error("Account is not active")]
+    AccountInactive(UserStatus),
+
+    #[error("Not a member of this organization")]
+    NotOrganizationMember,
+
+    #[error("Not an administrator of this organization")]
+    NotOrganizationAdmin,
+
+    #[error("Session does not act within this organization")]
+    OrganizationNotSelected,
+
+    #[error("An organization with that name already exists")]
+    OrganizationExists,
+
+    #[error("A group with that name already exists")]
+    GroupExists,
+
+    #[error("A group can't contain itself, directly or through its subgroups")]
+    GroupCycle,
+
+    #[error("Missing the `{0}` permission")]
+    MissingPermission(Permission),
+
+    #[error("A role with that name already exists")]
+    RoleExists,
+
+    #[error("Invalid policy: {}", .0.join("; "))]
+    InvalidPolicy(Vec<String>),
+
+    #[error("A policy with that name already exists")]
+    PolicyExists,
+
+    #[error("Invalid request body: {}", describe_field_errors(.0))]
+    ValidationFailed(FieldErrors),
+
+    #[error("Payload too large")]
+    PayloadTooLarge,
+
+    #[error("Too many requests")]
+    RateLimited(std::time::Duration),
+
+    #[error("Requests from this address are not allowed")]
+    AddressBanned,
+}
+
+/// Messages describing why fields of a request body are invalid, by field name
+pub(super) type FieldErrors = BTreeMap<String, Vec<String>>;
+
+fn describe_field_errors(errors: &FieldErrors) -> String {
+    errors
+        .iter()
+        .map(|(field, messages)| format!("{field}: {}", messages.join(", ")))
+        .collect::<Vec<_>>()
+        .join("; ")
 }
 
 impl From<DatabaseError> for ApiV1Error {
@@ -200,6 +783,67 @@ impl From<DatabaseError> for ApiV1Error {
     }
 }
 
+impl From<InvalidCursor> for ApiV1Error {
+    fn from(_: InvalidCursor) -> Self {
+        ApiV1Error::InvalidCursor
+    }
+}
+
+impl From<ValidationErrors> for ApiV1Error {
+    fn from(errors: ValidationErrors) -> Self {
+        let mut fields = FieldErrors::new();
+        collect_field_errors(&errors, "", &mut fields);
+        ApiV1Error::ValidationFailed(fields)
+    }
+}
+
+/// Adds the messages of the validation errors to `fields`, naming fields the way clients do: in
+/// camel case, with nested fields separated by dots and list items indexed.
+fn collect_field_errors(errors: &ValidationErrors, prefix: &str, fields: &mut FieldErrors) {
+    for (field, kind) in errors.errors() {
+        let name = format!("{prefix}{}", snake_to_camel_case(field));
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                fields
+                    .entry(name)
+                    .or_default()
+                    .extend(errors.iter().map(|error| {
+                        error
+                            .message
+                            .as_ref()
+                            .map_or_else(|| error.code.to_string(), ToString::to_string)
+                    }));
+            }
+            ValidationErrorsKind::Struct(errors) => {
+                collect_field_errors(errors, &format!("{name}."), fields);
+            }
+            ValidationErrorsKind::List(items) => {
+                for (i, errors) in items {
+                    collect_field_errors(errors, &format!("{name}[{i}]."), fields);
+                }
+            }
+        }
+    }
+}
+
+fn snake_to_camel_case(name: &str) -> String {
+    let mut parts = name.split('_');
+    let mut camel = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            camel.extend(first.to_uppercase());
+            camel.push_str(chars.as_str());
+        }
+    }
+    camel
+}
+
+/// Returns the HTTP status codes with which v1 endpoints can respond to errors.
+pub(super) fn error_status_codes() -> Vec<StatusCode> {
+    ApiV1Error::possible_status_codes()
+}
+
 impl ApiV1Error {
     fn possible_status_codes() -> Vec<StatusCode> {
         vec![
@@ -207,46 +851,253 @@ impl ApiV1Error {
             StatusCode::BAD_REQUEST,
             StatusCode::NOT_FOUND,
             StatusCode::UNAUTHORIZED,
+            StatusCode::FORBIDDEN,
+            StatusCode::CONFLICT,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            StatusCode::BAD_GATEWAY,
         ]
     }
 }
 
-impl IntoResponse for ApiV1Error {
-    fn into_response(self) -> Response {
+impl ApiV1Error {
+    /// Returns the HTTP status code of the error's response.
+    fn status(&self) -> StatusCode {
         #[allow(clippy::enum_glob_use)]
         use ApiV1Error::*;
-        let status = match self {
+        match self {
             WebAuthn(_) | InternalServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             InvalidAuthenticationId
             | InvalidRegistrationId
             | InvalidSessionId
-            | DowngradeImpossible => StatusCode::BAD_REQUEST,
+            | DowngradeImpossible
+            | EmailDisabled
+            | MergeIntoSelf
+            | InvalidNetwork(_)
+            | InvalidAnnouncement(_)
+            | InvalidCursor
+            | InvalidTag(_)
+            | InvalidField(..)
+            | RegistrationFailed
+            | InvalidPolicy(_) => StatusCode::BAD_REQUEST,
             UserNotFound | NotFound => StatusCode::NOT_FOUND,
-            NotLoggedIn | SessionExpired | NotAdmin | AuthFailed(_) => StatusCode::UNAUTHORIZED,
+            NotLoggedIn
+            | SessionExpired
+            | NotAdmin
+            | AuthFailed(_)
+            | InvalidWebhookSecret
+            | InvalidApiToken
+            | InsufficientScope
+            | NoLinkedAccount
+            | ReauthenticationRequired => StatusCode::UNAUTHORIZED,
+            RegistrationClosed
+            | EmailDomainNotAllowed
+            | AuthenticatorNotAllowed
+            | Impersonating
+            | AccountLocked(_)
+            | AccountInactive(_)
+            | NotOrganizationMember
+            | NotOrganizationAdmin
+            | OrganizationNotSelected
+            | MissingPermission(_)
+            | AddressBanned => StatusCode::FORBIDDEN,
+            TagExists | LastPasskey | EmailTaken | IdentityLinked | OrganizationExists
+            | GroupExists | GroupCycle | RoleExists | PolicyExists => StatusCode::CONFLICT,
+            ValidationFailed(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            FederationFailed(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    /// Returns the error's machine-readable code.
+    fn code(&self) -> ApiErrorCode {
+        #[allow(clippy::enum_glob_use)]
+        use ApiV1Error::*;
+        match self {
+            NotFound => ApiErrorCode::NotFound,
+            WebAuthn(_) => ApiErrorCode::WebauthnError,
+            InternalServerError(_) => ApiErrorCode::InternalError,
+            InvalidRegistrationId => ApiErrorCode::InvalidRegistrationId,
+            SessionExpired => ApiErrorCode::SessionExpired,
+            InvalidAuthenticationId => ApiErrorCode::InvalidAuthenticationId,
+            UserNotFound => ApiErrorCode::UserNotFound,
+            InvalidSessionId => ApiErrorCode::InvalidSessionId,
+            NotLoggedIn => ApiErrorCode::NotLoggedIn,
+            NotAdmin => ApiErrorCode::NotAdmin,
+            AuthFailed(_) => ApiErrorCode::AuthenticationFailed,
+            DowngradeImpossible => ApiErrorCode::DowngradeImpossible,
+            InvalidWebhookSecret => ApiErrorCode::InvalidWebhookSecret,
+            EmailDisabled => ApiErrorCode::EmailDisabled,
+            MergeIntoSelf => ApiErrorCode::MergeIntoSelf,
+            InvalidNetwork(_) => ApiErrorCode::InvalidNetwork,
+            InvalidAnnouncement(_) => ApiErrorCode::InvalidAnnouncement,
+            InvalidCursor => ApiErrorCode::InvalidCursor,
+            InvalidTag(_) => ApiErrorCode::InvalidTag,
+            TagExists => ApiErrorCode::TagExists,
+            LastPasskey => ApiErrorCode::LastPasskey,
+            EmailTaken => ApiErrorCode::EmailTaken,
+            InvalidField(..) => ApiErrorCode::InvalidField,
+            InvalidApiToken => ApiErrorCode::InvalidApiToken,
+            InsufficientScope => ApiErrorCode::InsufficientScope,
+            FederationFailed(_) => ApiErrorCode::FederationFailed,
+            NoLinkedAccount => ApiErrorCode::NoLinkedAccount,
+            IdentityLinked => ApiErrorCode::IdentityLinked,
+            RegistrationClosed => ApiErrorCode::RegistrationClosed,
+            EmailDomainNotAllowed => ApiErrorCode::EmailDomainNotAllowed,
+            Impersonating => ApiErrorCode::Impersonating,
+            ReauthenticationRequired => ApiErrorCode::ReauthenticationRequired,
+            RegistrationFailed => ApiErrorCode::RegistrationFailed,
+            AuthenticatorNotAllowed => ApiErrorCode::AuthenticatorNotAllowed,
+            AccountLocked(_) => ApiErrorCode::AccountLocked,
+            AccountInactive(_) => ApiErrorCode::AccountInactive,
+            NotOrganizationMember => ApiErrorCode::NotOrganizationMember,
+            NotOrganizationAdmin => ApiErrorCode::NotOrganizationAdmin,
+            OrganizationNotSelected => ApiErrorCode::OrganizationNotSelected,
+            OrganizationExists => ApiErrorCode::OrganizationExists,
+            GroupExists => ApiErrorCode::GroupExists,
+            GroupCycle => ApiErrorCode::GroupCycle,
+            MissingPermission(_) => ApiErrorCode::MissingPermission,
+            RoleExists => ApiErrorCode::RoleExists,
+            InvalidPolicy(_) => ApiErrorCode::InvalidPolicy,
+            PolicyExists => ApiErrorCode::PolicyExists,
+            ValidationFailed(_) => ApiErrorCode::ValidationFailed,
+            PayloadTooLarge => ApiErrorCode::PayloadTooLarge,
+            RateLimited(_) => ApiErrorCode::RateLimited,
+            AddressBanned => ApiErrorCode::AddressBanned,
+        }
+    }
+
+    /// Returns structured information about the error, for errors which have any.
+    fn details(&self) -> Option<Map<String, Value>> {
+        let details = match self {
+            ApiV1Error::InvalidNetwork(network) => json!({ "network": network }),
+            ApiV1Error::InvalidField(field, reason) => json!({ "field": field, "reason": reason }),
+            ApiV1Error::AccountLocked(until) => json!({ "until": until }),
+            ApiV1Error::AccountInactive(status) => json!({ "status": status }),
+            ApiV1Error::MissingPermission(permission) => json!({ "permission": permission }),
+            ApiV1Error::InvalidPolicy(errors) => json!({ "errors": errors }),
+            ApiV1Error::ValidationFailed(fields) => json!({ "fields": fields }),
+            _ => return None,
         };
-        (status, self.to_string()).into_response()
+        details.as_object().cloned()
+    }
+}
+
+/// Stable, machine-readable code identifying the kind of an API error
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum ApiErrorCode {
+    NotFound,
+    WebauthnError,
+    InternalError,
+    InvalidRegistrationId,
+    SessionExpired,
+    InvalidAuthenticationId,
+    UserNotFound,
+    InvalidSessionId,
+    NotLoggedIn,
+    NotAdmin,
+    AuthenticationFailed,
+    DowngradeImpossible,
+    InvalidWebhookSecret,
+    EmailDisabled,
+    MergeIntoSelf,
+    InvalidNetwork,
+    InvalidAnnouncement,
+    InvalidCursor,
+    InvalidTag,
+    TagExists,
+    LastPasskey,
+    EmailTaken,
+    InvalidField,
+    InvalidApiToken,
+    InsufficientScope,
+    FederationFailed,
+    NoLinkedAccount,
+    IdentityLinked,
+    RegistrationClosed,
+    EmailDomainNotAllowed,
+    Impersonating,
+    ReauthenticationRequired,
+    RegistrationFailed,
+    AuthenticatorNotAllowed,
+    AccountLocked,
+    AccountInactive,
+    NotOrganizationMember,
+    NotOrganizationAdmin,
+    OrganizationNotSelected,
+    OrganizationExists,
+    GroupExists,
+    GroupCycle,
+    MissingPermission,
+    RoleExists,
+    InvalidPolicy,
+    PolicyExists,
+    ValidationFailed,
+    PayloadTooLarge,
+    RateLimited,
+    AddressBanned,
+}
+
+/// Body of API error responses
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub(super) struct ApiErrorResponse {
+    error: ApiError,
+}
+
+/// Description of an API error
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+struct ApiError {
+    code: ApiErrorCode,
+    /// Human-readable description of the error. Messages may change between versions, so clients
+    /// should use the code to tell errors apart.
+    message: String,
+    /// Structured information about the error, depending on its code. For `invalid_field` errors,
+    /// this contains the name of the `field` and the `reason` it is invalid; for
+    /// `invalid_network` errors, the `network` which couldn't be parsed; for `account_locked`
+    /// errors, the time `until` which the account is locked; for `account_inactive` errors, the
+    /// account's `status`; for `missing_permission` errors, the `permission` which is missing; for
+    /// `invalid_policy` errors, the `errors` found in the policy; for `validation_failed` errors,
+    /// the messages describing each invalid field, by field name, in `fields`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<Map<String, Value>>,
+}
+
+impl From<&ApiV1Error> for ApiErrorResponse {
+    fn from(error: &ApiV1Error) -> Self {
+        Self {
+            error: ApiError {
+                code: error.code(),
+                message: error.to_string(),
+                details: error.details(),
+            },
+        }
+    }
+}
+
+impl IntoResponse for ApiV1Error {
+    fn into_response(self) -> Response {
+        let body = ApiErrorResponse::from(&self);
+        let mut response = (self.status(), Json(&body)).into_response();
+        if let ApiV1Error::RateLimited(retry_after) = self {
+            // Round up, so that clients retrying on time aren't rejected again
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response.headers_mut().insert(RETRY_AFTER, seconds.into());
+        }
+        // Lets the error be converted to problem details if the client prefers them
+        response.extensions_mut().insert(body);
+        response
     }
 }
 
 impl OperationOutput for ApiV1Error {
     type Inner = Self;
 
-    fn operation_response(
-        _ctx: &mut GenContext,
-        _operation: &mut Operation,
-    ) -> Option<OapiResponse> {
-        Some(OapiResponse {
-            description: "Error response".to_string(),
-            content: [(
-                "text/plain".to_string(),
-                MediaType {
-                    example: Some("Not logged in".into()),
-                    ..Default::default()
-                },
-            )]
-            .into(),
-            ..Default::default()
-        })
+    fn operation_response(ctx: &mut GenContext, operation: &mut Operation) -> Option<OapiResponse> {
+        let mut response = Json::<ApiErrorResponse>::operation_response(ctx, operation)?;
+        response.description = "Error response".to_string();
+        problem::document(ctx, &mut response);
+        Some(response)
     }
 
     fn inferred_responses(
@@ -265,6 +1116,12 @@ impl OperationOutput for ApiV1Error {
     }
 }
 
+/// Responds to requests for paths which don't match any endpoint with a JSON
+/// [`ApiV1Error::NotFound`] error.
+pub(super) async fn not_found() -> Response {
+    ApiV1Error::NotFound.into_response()
+}
+
 async fn get_openapi_json(
     Extension(api): Extension<PreSerializedJson<OpenApi>>,
 ) -> PreSerializedJson<OpenApi> {
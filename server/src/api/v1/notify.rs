@@ -0,0 +1,150 @@
+//! # User notification emails
+//!
+//! Functions in this module send security-related notification emails to users in the background.
+//! They do nothing if no [mailer][crate::api::ApiOptions::mailer] is configured.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{
+    api::v1::{V1StateInner, extractors::ClientInfo},
+    models::{PasskeyCredential, User},
+};
+
+/// Details of the request which triggered a notification, shared by all notification emails
+#[derive(Debug, Serialize)]
+struct RequestDetails<'a> {
+    display_name: &'a str,
+    time: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device: Option<&'a str>,
+}
+
+impl<'a> RequestDetails<'a> {
+    fn new(user: &'a User, time: DateTime<Utc>, client: &'a ClientInfo) -> Self {
+        Self {
+            display_name: user.display_name(),
+            time: time.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+            ip: client.ip.map(|ip| ip.to_string()),
+            device: client.user_agent.as_deref(),
+        }
+    }
+}
+
+/// Template context for login notification emails
+#[derive(Debug, Serialize)]
+struct LoginNotification<'a> {
+    #[serde(flatten)]
+    request: RequestDetails<'a>,
+    new_device: bool,
+    revoke_token: &'a str,
+}
+
+/// Template context for passkey lifecycle notification emails
+#[derive(Debug, Serialize)]
+struct PasskeyNotification<'a> {
+    #[serde(flatten)]
+    request: RequestDetails<'a>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    passkey_name: Option<&'a str>,
+}
+
+/// Template context for email address change confirmation emails
+#[derive(Debug, Serialize)]
+struct EmailChangeConfirmation<'a> {
+    display_name: &'a str,
+    email: &'a str,
+    token: &'a str,
+}
+
+/// Sends the given user a link to confirm the change of their email address to `email` to the new
+/// address. `token` is the token which confirms the change.
+pub fn email_change(state: &V1StateInner, user: &User, email: &str, token: &str) {
+    if let Some(mailer) = &state.options.mailer {
+        mailer.send_in_background(
+            email.to_string(),
+            "email_change",
+            &EmailChangeConfirmation {
+                display_name: user.display_name(),
+                email,
+                token,
+            },
+        );
+    }
+}
+
+/// Sends the given user a notification of a login which started a session at `time`.
+/// `revoke_token` is the token which revokes the session.
+pub fn login(
+    state: &V1StateInner,
+    user: &User,
+    time: DateTime<Utc>,
+    client: &ClientInfo,
+    new_device: bool,
+    revoke_token: &str,
+) {
+    if let Some(mailer) = &state.options.mailer {
+        mailer.send_in_background(
+            user.email().to_string(),
+            "login",
+            &LoginNotification {
+                request: RequestDetails::new(user, time, client),
+                new_device,
+                revoke_token,
+            },
+        );
+    }
+}
+
+/// Sends the given user a notification that someone tried to register a new account with their
+/// email address.
+pub fn registration_attempt(state: &V1StateInner, user: &User, client: &ClientInfo) {
+    if let Some(mailer) = &state.options.mailer {
+        mailer.send_in_background(
+            user.email().to_string(),
+            "registration_attempt",
+            &RequestDetails::new(user, Utc::now(), client),
+        );
+    }
+}
+
+/// Sends the given user a notification that a passkey was added to their account.
+pub fn passkey_added(
+    state: &V1StateInner,
+    user: &User,
+    passkey: &PasskeyCredential,
+    client: &ClientInfo,
+) {
+    send_passkey_notification(state, "passkey_added", user, passkey, client);
+}
+
+/// Sends the given user a notification that a passkey was removed from their account.
+pub fn passkey_removed(
+    state: &V1StateInner,
+    user: &User,
+    passkey: &PasskeyCredential,
+    client: &ClientInfo,
+) {
+    send_passkey_notification(state, "passkey_removed", user, passkey, client);
+}
+
+fn send_passkey_notification(
+    state: &V1StateInner,
+    template: &'static str,
+    user: &User,
+    passkey: &PasskeyCredential,
+    client: &ClientInfo,
+) {
+    if let Some(mailer) = &state.options.mailer {
+        mailer.send_in_background(
+            user.email().to_string(),
+            template,
+            &PasskeyNotification {
+                request: RequestDetails::new(user, Utc::now(), client),
+                passkey_name: passkey.display_name.as_deref(),
+            },
+        );
+    }
+}
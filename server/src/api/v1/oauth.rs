@@ -0,0 +1,744 @@
+//! # v1 OAuth 2.0 / OIDC provider endpoints
+//!
+//! These endpoints let registered [`OAuthClient`]s log users in with the authorization code flow,
+//! so that other applications can offer single sign-on backed by passkey login:
+//!
+//! 1. The client redirects the user to the [authorization endpoint][authorize]. If the user isn't
+//!    logged in, they are sent to the login page first. Clients are registered by administrators,
+//!    so they are trusted and the user isn't asked for consent.
+//! 2. The user is redirected back to the client with a short-lived authorization code.
+//! 3. The client exchanges the code at the [token endpoint][token] for an ID token and an access
+//!    token, both of which are JWTs signed with the provider's [keys][crate::crypto::jwt].
+//! 4. The client may fetch the user's profile from the [userinfo endpoint][userinfo] with the
+//!    access token.
+//!
+//! Only confidential clients (which authenticate with a secret) are supported. PKCE is supported
+//! with the `S256` method, and required if the authorization request includes a code challenge.
+
+use aide::{
+    OperationOutput,
+    generate::GenContext,
+    openapi::{MediaType, Operation, Response as OapiResponse},
+};
+use axum::{
+    Form, Json,
+    extract::{Path, Query, RawQuery, State},
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    },
+    response::{IntoResponse, Redirect, Response},
+};
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    api::v1::{
+        ApiV1Error, V1State,
+        extractors::{AdminSession, AuthenticatedSession},
+    },
+    crypto::jwt::{ALGORITHM, Jwks},
+    db::interface::DatabaseError,
+    models::{AuthorizationCode, EncodableHash, OAuthClient, OAuthClientUpdate, ViaJson, new_uuid},
+    policy::{AUTHORIZE_ACTION, Decision, PolicySet, Principal},
+};
+
+/// How long authorization codes are valid for
+const CODE_LIFETIME: Duration = Duration::minutes(5);
+
+/// How long ID tokens and access tokens are valid for
+const TOKEN_LIFETIME: Duration = Duration::hours(1);
+
+/// Scopes which clients can request. Other requested scopes are ignored.
+const SUPPORTED_SCOPES: [&str; 3] = ["openid", "profile", "email"];
+
+/// Media type of access tokens, placed in their `typ` header (see RFC 9068)
+const ACCESS_TOKEN_TYPE: &str = "at+jwt";
+
+/// Returns a random token, such as an authorization code or client secret.
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// # Error type for the OAuth endpoints
+///
+/// Responds with a JSON error object as specified by RFC 6749, section 5.2, rather than the plain
+/// text used by the rest of the API, since that's what OAuth clients expect.
+#[derive(Debug)]
+pub struct OAuthError {
+    status: StatusCode,
+    /// OAuth error code, e.g. `invalid_grant`
+    error: &'static str,
+    description: &'static str,
+}
+
+impl OAuthError {
+    fn new(status: StatusCode, error: &'static str, description: &'static str) -> Self {
+        Self {
+            status,
+            error,
+            description,
+        }
+    }
+
+    fn invalid_request(description: &'static str) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_request", description)
+    }
+
+    fn invalid_client() -> Self {
+        Self::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_client",
+            "Client authentication failed",
+        )
+    }
+
+    fn invalid_grant(description: &'static str) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "invalid_grant", description)
+    }
+
+    fn invalid_token() -> Self {
+        Self::new(
+            StatusCode::UNAUTHORIZED,
+            "invalid_token",
+            "The access token is invalid or has expired",
+        )
+    }
+}
+
+impl From<DatabaseError> for OAuthError {
+    fn from(err: DatabaseError) -> Self {
+        error!(%err, "database error in OAuth endpoint");
+        Self::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "server_error",
+            "Internal server error",
+        )
+    }
+}
+
+/// Body of an [`OAuthError`] response
+#[derive(Debug, Serialize)]
+struct OAuthErrorBody {
+    error: &'static str,
+    error_description: &'static str,
+}
+
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> Response {
+        let body = Json(OAuthErrorBody {
+            error: self.error,
+            error_description: self.description,
+        });
+        let mut response = (self.status, body).into_response();
+        if self.error == "invalid_token" {
+            response.headers_mut().insert(
+                WWW_AUTHENTICATE,
+                HeaderValue::from_static("Bearer error=\"invalid_token\""),
+            );
+        }
+        response
+    }
+}
+
+impl OperationOutput for OAuthError {
+    type Inner = Self;
+
+    fn operation_response(
+        _ctx: &mut GenContext,
+        _operation: &mut Operation,
+    ) -> Option<OapiResponse> {
+        Some(OapiResponse {
+            description: "OAuth error response".to_string(),
+            content: [(
+                "application/json".to_string(),
+                MediaType {
+                    example: Some(serde_json::json!({
+                        "error": "invalid_grant",
+                        "error_description": "The authorization code is invalid or has expired",
+                    })),
+                    ..Default::default()
+                },
+            )]
+            .into(),
+            ..Default::default()
+        })
+    }
+
+    fn inferred_responses(
+        ctx: &mut GenContext,
+        operation: &mut Operation,
+    ) -> Vec<(Option<u16>, OapiResponse)> {
+        [StatusCode::BAD_REQUEST, StatusCode::UNAUTHORIZED]
+            .into_iter()
+            .map(|status| {
+                (
+                    Some(status.as_u16()),
+                    Self::operation_response(ctx, operation).unwrap(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// OIDC provider metadata, as served by the discovery endpoint
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub jwks_uri: String,
+    pub response_types_supported: Vec<&'static str>,
+    pub grant_types_supported: Vec<&'static str>,
+    pub subject_types_supported: Vec<&'static str>,
+    pub id_token_signing_alg_values_supported: Vec<&'static str>,
+    pub scopes_supported: Vec<&'static str>,
+    pub claims_supported: Vec<&'static str>,
+    pub token_endpoint_auth_methods_supported: Vec<&'static str>,
+    pub code_challenge_methods_supported: Vec<&'static str>,
+    pub authorization_response_iss_parameter_supported: bool,
+}
+
+/// Serves the OIDC provider metadata, with which clients discover the other endpoints.
+pub async fn get_provider_metadata(State(state): State<V1State>) -> Json<ProviderMetadata> {
+    let issuer = &state.issuer;
+    Json(ProviderMetadata {
+        issuer: issuer.clone(),
+        authorization_endpoint: format!("{issuer}/authorize"),
+        token_endpoint: format!("{issuer}/token"),
+        userinfo_endpoint: format!("{issuer}/userinfo"),
+        jwks_uri: format!("{issuer}/jwks"),
+        response_types_supported: vec!["code"],
+        grant_types_supported: vec!["authorization_code"],
+        subject_types_supported: vec!["public"],
+        id_token_signing_alg_values_supported: vec![ALGORITHM],
+        scopes_supported: SUPPORTED_SCOPES.to_vec(),
+        claims_supported: vec![
+            "iss",
+            "sub",
+            "aud",
+            "exp",
+            "iat",
+            "auth_time",
+            "nonce",
+            "name",
+            "email",
+        ],
+        token_endpoint_auth_methods_supported: vec!["client_secret_basic", "client_secret_post"],
+        code_challenge_methods_supported: vec!["S256"],
+        authorization_response_iss_parameter_supported: true,
+    })
+}
+
+/// Serves the public keys with which tokens are signed.
+pub async fn get_jwks(State(state): State<V1State>) -> Result<Json<Jwks>, ApiV1Error> {
+    let jwks = state
+        .keys
+        .jwks()
+        .map_err(|err| ApiV1Error::InternalServerError(err.into()))?;
+    Ok(Json(jwks))
+}
+
+/// Authorization request parameters
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct AuthorizeRequest {
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scope: String,
+    pub state: Option<String>,
+    pub nonce: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+}
+
+/// Fetches the client with the given ID, which comes from a request and so may not be a UUID.
+async fn find_client(state: &V1State, id: &str) -> Result<Option<OAuthClient>, DatabaseError> {
+    let Ok(id) = id.parse::<Uuid>() else {
+        return Ok(None);
+    };
+    match state.db.get_oauth_client_by_id(&id).await {
+        Ok(client) => Ok(Some(client)),
+        Err(DatabaseError::NotFound) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Authorization endpoint
+///
+/// Redirects the user back to the client with an authorization code, or with an error if the
+/// request is invalid. Users who aren't logged in are redirected to the login page, which returns
+/// them here afterwards. Responds with `400 Bad Request` instead of redirecting if the client or
+/// redirect URL is invalid, since the user can't safely be sent back to the client then. Users
+/// whom a [policy][crate::policy] forbids from authorizing the client are sent back with an
+/// `access_denied` error.
+pub async fn authorize(
+    session: Option<AuthenticatedSession>,
+    State(state): State<V1State>,
+    RawQuery(query): RawQuery,
+    Query(request): Query<AuthorizeRequest>,
+) -> Result<Redirect, ApiV1Error> {
+    let Some(client) = find_client(&state, &request.client_id).await? else {
+        return Err(ApiV1Error::InvalidField("client_id", "unknown client"));
+    };
+    if !client.redirect_uris.contains(&request.redirect_uri) {
+        return Err(ApiV1Error::InvalidField(
+            "redirect_uri",
+            "not registered for this client",
+        ));
+    }
+
+    // From here on, errors are reported to the client
+    let redirect = |params: &[(&str, &str)]| {
+        let mut url = Url::parse(&request.redirect_uri)
+            .map_err(|_| ApiV1Error::InvalidField("redirect_uri", "invalid URL"))?;
+        {
+            let mut pairs = url.query_pairs_mut();
+            pairs.extend_pairs(params);
+            if let Some(client_state) = &request.state {
+                pairs.append_pair("state", client_state);
+            }
+            pairs.append_pair("iss", &state.issuer);
+        }
+        Ok(Redirect::to(url.as_str()))
+    };
+    let error = |error: &str, description: &str| {
+        redirect(&[("error", error), ("error_description", description)])
+    };
+    if request.response_type != "code" {
+        return error(
+            "unsupported_response_type",
+            "Only the authorization code flow is supported",
+        );
+    }
+    let scopes: Vec<&str> = request
+        .scope
+        .split_whitespace()
+        .filter(|scope| SUPPORTED_SCOPES.contains(scope))
+        .collect();
+    if !scopes.contains(&"openid") {
+        return error("invalid_scope", "The openid scope is required");
+    }
+    match (
+        &request.code_challenge,
+        request.code_challenge_method.as_deref(),
+    ) {
+        (Some(_), Some("S256")) | (None, None) => {}
+        (Some(_), _) => {
+            return error(
+                "invalid_request",
+                "Only the S256 code challenge method is supported",
+            );
+        }
+        (None, Some(_)) => return error("invalid_request", "Missing code challenge"),
+    }
+
+    let Some(AuthenticatedSession(session)) = session else {
+        // The login page sends the user to `next` once they have logged in
+        let base_path = &state.config.base_path;
+        let next = format!(
+            "{base_path}/api/v1/oauth/authorize?{}",
+            query.unwrap_or_default()
+        );
+        let mut login = Url::parse("http://localhost/login").expect("placeholder URL is valid");
+        login.query_pairs_mut().append_pair("next", &next);
+        return Ok(Redirect::to(&format!(
+            "{base_path}/login?{}",
+            login.query().unwrap_or_default()
+        )));
+    };
+
+    let tags = state
+        .db
+        .get_effective_tags_by_user_id(&session.user_id)
+        .await?;
+    let principal = Principal {
+        user_id: &session.user_id,
+        tags: &tags,
+    };
+    let policies = PolicySet::load(state.db.as_ref()).await?;
+    if policies.evaluate(principal, AUTHORIZE_ACTION, Some(&client.id)) == Decision::Forbid {
+        info!(client = %client.id, user = %session.user_id, "policy forbade OAuth authorization");
+        return error(
+            "access_denied",
+            "A policy forbids you from using this application",
+        );
+    }
+
+    let code = random_token();
+    state
+        .db
+        .create_authorization_code(&AuthorizationCode {
+            code_hash: blake3::hash(code.as_bytes()).into(),
+            client_id: client.id,
+            user_id: session.user_id,
+            redirect_uri: request.redirect_uri.clone(),
+            scope: scopes.join(" "),
+            nonce: request.nonce.clone(),
+            code_challenge: request.code_challenge.clone(),
+            auth_time: session.created_at,
+            expires_at: Utc::now() + CODE_LIFETIME,
+        })
+        .await?;
+    info!(client = %client.id, user = %session.user_id, "user authorized OAuth client");
+    redirect(&[("code", &code)])
+}
+
+/// Token request parameters
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    /// Client ID, if the client doesn't authenticate with HTTP Basic authentication
+    pub client_id: Option<String>,
+    /// Client secret, if the client doesn't authenticate with HTTP Basic authentication
+    pub client_secret: Option<String>,
+}
+
+/// Successful token response
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+    pub id_token: String,
+    pub scope: String,
+}
+
+/// Claims of an ID token
+#[derive(Debug, Clone, Serialize)]
+struct IdTokenClaims<'a> {
+    iss: &'a str,
+    sub: Uuid,
+    aud: Uuid,
+    exp: i64,
+    iat: i64,
+    auth_time: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email: Option<&'a str>,
+}
+
+/// Claims of an access token. Its audience is the issuer itself, which tells it apart from ID
+/// tokens, whose audience is the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AccessTokenClaims {
+    iss: String,
+    sub: Uuid,
+    aud: String,
+    client_id: Uuid,
+    scope: String,
+    exp: i64,
+    iat: i64,
+    jti: Uuid,
+}
+
+/// Authenticates the client making a token request, with either HTTP Basic authentication or the
+/// `client_id` and `client_secret` parameters.
+async fn authenticate_client(
+    state: &V1State,
+    headers: &HeaderMap,
+    request: &TokenRequest,
+) -> Result<OAuthClient, OAuthError> {
+    let basic = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|value| base64::prelude::BASE64_STANDARD.decode(value.trim()).ok())
+        .and_then(|value| String::from_utf8(value).ok());
+    let (id, secret) = match (&basic, &request.client_id, &request.client_secret) {
+        (Some(credentials), _, _) => credentials
+            .split_once(':')
+            .ok_or_else(OAuthError::invalid_client)?,
+        (None, Some(id), Some(secret)) => (id.as_str(), secret.as_str()),
+        _ => return Err(OAuthError::invalid_client()),
+    };
+    let client = find_client(state, id)
+        .await?
+        .ok_or_else(OAuthError::invalid_client)?;
+    if *client.secret_hash == blake3::hash(secret.as_bytes()) {
+        Ok(client)
+    } else {
+        Err(OAuthError::invalid_client())
+    }
+}
+
+/// Token endpoint
+///
+/// Exchanges an authorization code for an ID token and an access token.
+pub async fn token(
+    State(state): State<V1State>,
+    headers: HeaderMap,
+    Form(request): Form<TokenRequest>,
+) -> Result<Json<TokenResponse>, OAuthError> {
+    let client = authenticate_client(&state, &headers, &request).await?;
+    if request.grant_type != "authorization_code" {
+        return Err(OAuthError::new(
+            StatusCode::BAD_REQUEST,
+            "unsupported_grant_type",
+            "Only the authorization_code grant type is supported",
+        ));
+    }
+    let code = request
+        .code
+        .as_deref()
+        .ok_or_else(|| OAuthError::invalid_request("Missing code"))?;
+    let invalid_code =
+        || OAuthError::invalid_grant("The authorization code is invalid or has expired");
+    let code_hash: EncodableHash = blake3::hash(code.as_bytes()).into();
+    let code = match state.db.consume_authorization_code(&code_hash).await {
+        Ok(code) => code,
+        Err(DatabaseError::NotFound) => return Err(invalid_code()),
+        Err(err) => return Err(err.into()),
+    };
+    if code.client_id != client.id || code.expires_at <= Utc::now() {
+        return Err(invalid_code());
+    }
+    if request.redirect_uri.as_ref() != Some(&code.redirect_uri) {
+        return Err(OAuthError::invalid_grant("Redirect URL doesn't match"));
+    }
+    if let Some(challenge) = &code.code_challenge {
+        let verifier = request
+            .code_verifier
+            .as_deref()
+            .ok_or_else(|| OAuthError::invalid_grant("Missing code verifier"))?;
+        if BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) != *challenge {
+            return Err(OAuthError::invalid_grant("Code verifier doesn't match"));
+        }
+    }
+    let user = match state.db.get_user_by_id(&code.user_id).await {
+        Ok(user) => user,
+        Err(DatabaseError::NotFound) => return Err(invalid_code()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let now = Utc::now();
+    let exp = (now + TOKEN_LIFETIME).timestamp();
+    let has_scope = |scope: &str| code.scope.split(' ').any(|granted| granted == scope);
+    let id_token = IdTokenClaims {
+        iss: &state.issuer,
+        sub: *user.id(),
+        aud: client.id,
+        exp,
+        iat: now.timestamp(),
+        auth_time: code.auth_time.timestamp(),
+        nonce: code.nonce.as_deref(),
+        name: has_scope("profile").then(|| user.display_name()),
+        email: has_scope("email").then(|| user.email()),
+    };
+    let access_token = AccessTokenClaims {
+        iss: state.issuer.clone(),
+        sub: *user.id(),
+        aud: state.issuer.clone(),
+        client_id: client.id,
+        scope: code.scope.clone(),
+        exp,
+        iat: now.timestamp(),
+        jti: new_uuid(),
+    };
+    let sign_error = |err| {
+        error!(%err, "failed to sign token");
+        OAuthError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "server_error",
+            "Internal server error",
+        )
+    };
+    let response = TokenResponse {
+        access_token: state
+            .keys
+            .sign(&access_token, Some(ACCESS_TOKEN_TYPE))
+            .map_err(sign_error)?,
+        token_type: "Bearer",
+        expires_in: TOKEN_LIFETIME.num_seconds(),
+        id_token: state.keys.sign(&id_token, None).map_err(sign_error)?,
+        scope: code.scope,
+    };
+    info!(client = %client.id, user = %user.id(), "issued OAuth tokens");
+    Ok(Json(response))
+}
+
+/// Claims about the user, as returned by the userinfo endpoint
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct UserInfo {
+    pub sub: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+/// Userinfo endpoint
+///
+/// Returns the claims about the user which the access token's scopes allow.
+pub async fn userinfo(
+    State(state): State<V1State>,
+    headers: HeaderMap,
+) -> Result<Json<UserInfo>, OAuthError> {
+    let token = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(OAuthError::invalid_token)?;
+    let claims: AccessTokenClaims = state
+        .keys
+        .verify(token.trim())
+        .map_err(|_| OAuthError::invalid_token())?;
+    if claims.aud != state.issuer {
+        return Err(OAuthError::invalid_token());
+    }
+    let user = match state.db.get_user_by_id(&claims.sub).await {
+        Ok(user) => user,
+        Err(DatabaseError::NotFound) => return Err(OAuthError::invalid_token()),
+        Err(err) => return Err(err.into()),
+    };
+    let has_scope = |scope: &str| claims.scope.split(' ').any(|granted| granted == scope);
+    Ok(Json(UserInfo {
+        sub: *user.id(),
+        name: has_scope("profile").then(|| user.display_name().to_string()),
+        email: has_scope("email").then(|| user.email().to_string()),
+    }))
+}
+
+/// Request to register an OAuth client
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OAuthClientRequest {
+    /// Name of the application
+    pub name: String,
+    /// URLs to which users may be redirected after authorizing the client
+    pub redirect_uris: Vec<String>,
+}
+
+/// A newly registered OAuth client, along with its secret
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedOAuthClient {
+    #[serde(flatten)]
+    pub client: OAuthClient,
+    /// Secret with which the client authenticates. It can't be retrieved again later.
+    pub client_secret: String,
+}
+
+/// Checks that the name of a client isn't empty, returning it trimmed.
+fn validate_name(name: &str) -> Result<String, ApiV1Error> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ApiV1Error::InvalidField("name", "must not be empty"));
+    }
+    Ok(name.to_string())
+}
+
+/// Checks that each redirect URL is an absolute HTTP(S) URL without a fragment.
+fn validate_redirect_uris(uris: &[String]) -> Result<(), ApiV1Error> {
+    if uris.is_empty() {
+        return Err(ApiV1Error::InvalidField(
+            "redirectUris",
+            "must not be empty",
+        ));
+    }
+    for uri in uris {
+        match Url::parse(uri) {
+            Ok(url)
+                if matches!(url.scheme(), "http" | "https")
+                    && url.has_host()
+                    && url.fragment().is_none() => {}
+            _ => {
+                return Err(ApiV1Error::InvalidField(
+                    "redirectUris",
+                    "must be absolute HTTP or HTTPS URLs without a fragment",
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn list_clients(
+    AdminSession { .. }: AdminSession,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<OAuthClient>>, ApiV1Error> {
+    Ok(Json(state.db.list_oauth_clients().await?))
+}
+
+/// Registers an OAuth client. The response contains the client's secret, which can't be
+/// retrieved again.
+pub async fn create_client(
+    AdminSession(session): AdminSession,
+    State(state): State<V1State>,
+    Json(request): Json<OAuthClientRequest>,
+) -> Result<Json<CreatedOAuthClient>, ApiV1Error> {
+    let name = validate_name(&request.name)?;
+    validate_redirect_uris(&request.redirect_uris)?;
+    let secret = random_token();
+    let now = Utc::now();
+    let client = OAuthClient {
+        id: new_uuid(),
+        name,
+        secret_hash: blake3::hash(secret.as_bytes()).into(),
+        redirect_uris: ViaJson(request.redirect_uris),
+        created_at: now,
+        updated_at: now,
+    };
+    let client = state.db.create_oauth_client(&client).await?;
+    info!(client = %client.id, admin = %session.user_id, "OAuth client registered");
+    Ok(Json(CreatedOAuthClient {
+        client,
+        client_secret: secret,
+    }))
+}
+
+pub async fn get_client(
+    AdminSession { .. }: AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<OAuthClient>, ApiV1Error> {
+    Ok(Json(state.db.get_oauth_client_by_id(&id).await?))
+}
+
+/// Updates an OAuth client's name and/or redirect URLs.
+pub async fn update_client(
+    AdminSession(session): AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    Json(mut update): Json<OAuthClientUpdate>,
+) -> Result<Json<OAuthClient>, ApiV1Error> {
+    if update.is_empty() {
+        return Ok(Json(state.db.get_oauth_client_by_id(&id).await?));
+    }
+    if let Some(name) = &update.name {
+        update.name = Some(validate_name(name)?);
+    }
+    if let Some(uris) = &update.redirect_uris {
+        validate_redirect_uris(uris)?;
+    }
+    let client = state.db.update_oauth_client(&id, &update).await?;
+    info!(client = %id, admin = %session.user_id, "OAuth client updated");
+    Ok(Json(client))
+}
+
+/// Removes an OAuth client. Tokens which were already issued to it remain valid until they expire.
+pub async fn delete_client(
+    AdminSession(session): AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.delete_oauth_client_by_id(&id).await?;
+    info!(client = %id, admin = %session.user_id, "OAuth client removed");
+    Ok(())
+}
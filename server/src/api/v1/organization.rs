@@ -0,0 +1,375 @@
+//! # v1 organization API endpoint handlers
+//!
+//! Instance administrators create and delete [`Organization`]s. Everything else about an
+//! organization is managed by its own administrators, who only see the organization's members
+//! and tags. Their sessions must [act within the organization][Session::organization_id], which
+//! users select with `PUT /auth/organization`.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::info;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    api::v1::{
+        ApiV1Error, V1State,
+        extractors::{AdminSession, AuthenticatedSession, ValidatedJson},
+        tag::{map_uniqueness_error, validate_name},
+    },
+    db::interface::DatabaseError,
+    models::{
+        MAX_NAME_LENGTH, Organization, OrganizationMember, OrganizationRole, OrganizationUpdate,
+        Session, SessionUpdate, Tag, TagUpdate, new_uuid, not_blank,
+    },
+};
+
+/// Request to create an organization
+#[derive(Debug, Clone, Deserialize, JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateOrganizationRequest {
+    /// Organization name (must be unique)
+    #[validate(
+        custom(function = not_blank),
+        length(max = MAX_NAME_LENGTH, message = "must be at most 100 characters long")
+    )]
+    pub name: String,
+}
+
+/// Request to add a member to an organization or to change their role
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizationMemberRequest {
+    /// Role of the member in the organization
+    pub role: OrganizationRole,
+}
+
+/// Request to create an organization tag
+#[derive(Debug, Clone, Deserialize, JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateOrganizationTagRequest {
+    /// Tag name (must be unique within the organization)
+    #[validate(
+        custom(function = not_blank),
+        length(max = MAX_NAME_LENGTH, message = "must be at most 100 characters long")
+    )]
+    pub name: String,
+}
+
+/// Request to select the organization within which the session acts
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SelectOrganizationRequest {
+    /// UUID of the organization, or `null` to act within none
+    pub organization_id: Option<Uuid>,
+}
+
+fn map_organization_uniqueness_error(error: DatabaseError) -> ApiV1Error {
+    match error {
+        DatabaseError::UniquenessViolation { .. } => ApiV1Error::OrganizationExists,
+        error => error.into(),
+    }
+}
+
+/// Fetches the organization with the given ID, checking that the session may act in it with at
+/// least the given role. Administrator sessions may act in every organization. Other sessions
+/// must act within the organization, and their user must have the role in it.
+async fn authorize(
+    state: &V1State,
+    session: &Session,
+    organization_id: &Uuid,
+    role: OrganizationRole,
+) -> Result<Organization, ApiV1Error> {
+    let organization = state.db.get_organization_by_id(organization_id).await?;
+    if session.is_admin {
+        return Ok(organization);
+    }
+    if session.organization_id != Some(*organization_id) {
+        return Err(ApiV1Error::OrganizationNotSelected);
+    }
+    let member = match state
+        .db
+        .get_organization_member(organization_id, &session.user_id)
+        .await
+    {
+        Ok(member) => member,
+        Err(DatabaseError::NotFound) => return Err(ApiV1Error::NotOrganizationMember),
+        Err(err) => return Err(err.into()),
+    };
+    if member.role < role {
+        return Err(ApiV1Error::NotOrganizationAdmin);
+    }
+    Ok(organization)
+}
+
+/// Fetches the member with the given ID of the organization with the given ID, failing with
+/// [`ApiV1Error::NotFound`] if the user isn't a member.
+async fn get_member(
+    state: &V1State,
+    organization_id: &Uuid,
+    user_id: &Uuid,
+) -> Result<OrganizationMember, ApiV1Error> {
+    Ok(state
+        .db
+        .get_organization_member(organization_id, user_id)
+        .await?)
+}
+
+/// Fetches the tag with the given ID, failing with [`ApiV1Error::NotFound`] if it doesn't belong
+/// to the organization with the given ID.
+async fn get_organization_tag(
+    state: &V1State,
+    organization_id: &Uuid,
+    tag_id: &Uuid,
+) -> Result<Tag, ApiV1Error> {
+    let tag = state.db.get_tag_by_id(tag_id).await?;
+    if tag.organization_id != Some(*organization_id) {
+        return Err(ApiV1Error::NotFound);
+    }
+    Ok(tag)
+}
+
+pub async fn list_organizations(
+    AdminSession { .. }: AdminSession,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<Organization>>, ApiV1Error> {
+    Ok(Json(state.db.list_organizations().await?))
+}
+
+/// Creates a new organization.
+pub async fn create_organization(
+    AdminSession(session): AdminSession,
+    State(state): State<V1State>,
+    ValidatedJson(request): ValidatedJson<CreateOrganizationRequest>,
+) -> Result<Json<Organization>, ApiV1Error> {
+    let organization = state
+        .db
+        .create_organization(
+            &new_uuid(),
+            &OrganizationUpdate::new().with_name(request.name),
+        )
+        .await
+        .map_err(map_organization_uniqueness_error)?;
+    info!(organization = %organization.id, admin = %session.user_id, "organization created");
+    Ok(Json(organization))
+}
+
+pub async fn get_organization(
+    AuthenticatedSession(session): AuthenticatedSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Organization>, ApiV1Error> {
+    Ok(Json(
+        authorize(&state, &session, &id, OrganizationRole::Member).await?,
+    ))
+}
+
+/// Updates an organization.
+pub async fn update_organization(
+    AuthenticatedSession(session): AuthenticatedSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    ValidatedJson(update): ValidatedJson<OrganizationUpdate>,
+) -> Result<Json<Organization>, ApiV1Error> {
+    if update.is_empty() {
+        return Err(ApiV1Error::InvalidField("update", "must not be empty"));
+    }
+    authorize(&state, &session, &id, OrganizationRole::Admin).await?;
+    let organization = state
+        .db
+        .update_organization(&id, &update)
+        .await
+        .map_err(map_organization_uniqueness_error)?;
+    info!(organization = %id, user = %session.user_id, "organization updated");
+    Ok(Json(organization))
+}
+
+/// Deletes an organization, along with its memberships and tags.
+pub async fn delete_organization(
+    AdminSession(session): AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.delete_organization_by_id(&id).await?;
+    // Cached sessions may still act within the organization
+    state.sessions.clear();
+    info!(organization = %id, admin = %session.user_id, "organization deleted");
+    Ok(())
+}
+
+/// Returns the members of an organization.
+pub async fn list_organization_members(
+    AuthenticatedSession(session): AuthenticatedSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<OrganizationMember>>, ApiV1Error> {
+    authorize(&state, &session, &id, OrganizationRole::Member).await?;
+    Ok(Json(state.db.get_organization_members(&id).await?))
+}
+
+/// Adds a user to an organization, or changes the role of a member.
+pub async fn set_organization_member(
+    AuthenticatedSession(session): AuthenticatedSession,
+    Path((id, user_id)): Path<(Uuid, Uuid)>,
+    State(state): State<V1State>,
+    Json(request): Json<OrganizationMemberRequest>,
+) -> Result<Json<OrganizationMember>, ApiV1Error> {
+    authorize(&state, &session, &id, OrganizationRole::Admin).await?;
+    state.db.get_user_by_id(&user_id).await?;
+    let member = state
+        .db
+        .set_organization_member(&id, &user_id, request.role)
+        .await?;
+    info!(
+        organization = %id,
+        user = %user_id,
+        role = ?request.role,
+        by = %session.user_id,
+        "organization member set",
+    );
+    Ok(Json(member))
+}
+
+/// Removes a user from an organization, along with the organization's tags.
+pub async fn remove_organization_member(
+    AuthenticatedSession(session): AuthenticatedSession,
+    Path((id, user_id)): Path<(Uuid, Uuid)>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    authorize(&state, &session, &id, OrganizationRole::Admin).await?;
+    state.db.remove_organization_member(&id, &user_id).await?;
+    state.sessions.invalidate_user(&user_id);
+    info!(
+        organization = %id,
+        user = %user_id,
+        by = %session.user_id,
+        "organization member removed",
+    );
+    Ok(())
+}
+
+/// Returns the tags of an organization.
+pub async fn list_organization_tags(
+    AuthenticatedSession(session): AuthenticatedSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<Tag>>, ApiV1Error> {
+    authorize(&state, &session, &id, OrganizationRole::Member).await?;
+    Ok(Json(state.db.get_tags_by_organization_id(&id).await?))
+}
+
+/// Creates a tag belonging to an organization.
+pub async fn create_organization_tag(
+    AuthenticatedSession(session): AuthenticatedSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    ValidatedJson(request): ValidatedJson<CreateOrganizationTagRequest>,
+) -> Result<Json<Tag>, ApiV1Error> {
+    validate_name(&request.name)?;
+    authorize(&state, &session, &id, OrganizationRole::Admin).await?;
+    let tag = state
+        .db
+        .create_tag(
+            &new_uuid(),
+            Some(&id),
+            &TagUpdate::new().with_name(request.name),
+        )
+        .await
+        .map_err(map_uniqueness_error)?;
+    info!(organization = %id, tag = %tag.name, by = %session.user_id, "organization tag created");
+    Ok(Json(tag))
+}
+
+/// Applies an organization's tag to one of its members. Applying a tag which the member already
+/// has does nothing.
+pub async fn add_organization_tag_to_member(
+    AuthenticatedSession(session): AuthenticatedSession,
+    Path((id, user_id, tag_id)): Path<(Uuid, Uuid, Uuid)>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    authorize(&state, &session, &id, OrganizationRole::Admin).await?;
+    get_member(&state, &id, &user_id).await?;
+    let tag = get_organization_tag(&state, &id, &tag_id).await?;
+    let tags = state.db.get_tags_by_user_id(&user_id).await?;
+    if tags.iter().any(|existing| existing.id == tag.id) {
+        return Ok(());
+    }
+    state.db.add_tag_to_user(&user_id, &tag).await?;
+    info!(
+        organization = %id,
+        tag = %tag.name,
+        user = %user_id,
+        by = %session.user_id,
+        "organization tag added to member",
+    );
+    Ok(())
+}
+
+/// Removes an organization's tag from one of its members.
+pub async fn remove_organization_tag_from_member(
+    AuthenticatedSession(session): AuthenticatedSession,
+    Path((id, user_id, tag_id)): Path<(Uuid, Uuid, Uuid)>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    authorize(&state, &session, &id, OrganizationRole::Admin).await?;
+    get_member(&state, &id, &user_id).await?;
+    let tag = get_organization_tag(&state, &id, &tag_id).await?;
+    state.db.remove_tag_from_user(&user_id, &tag).await?;
+    info!(
+        organization = %id,
+        tag = %tag.name,
+        user = %user_id,
+        by = %session.user_id,
+        "organization tag removed from member",
+    );
+    Ok(())
+}
+
+/// Returns the organizations of which the current user is a member.
+pub async fn list_current_user_organizations(
+    AuthenticatedSession(session): AuthenticatedSession,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<Organization>>, ApiV1Error> {
+    Ok(Json(
+        state
+            .db
+            .get_organizations_by_user_id(&session.user_id)
+            .await?,
+    ))
+}
+
+/// Selects the organization within which the current session acts. Users can only select
+/// organizations of which they are a member, except in administrator sessions.
+pub async fn select_organization(
+    AuthenticatedSession(session): AuthenticatedSession,
+    State(state): State<V1State>,
+    Json(request): Json<SelectOrganizationRequest>,
+) -> Result<Json<Session>, ApiV1Error> {
+    if let Some(organization_id) = &request.organization_id {
+        state.db.get_organization_by_id(organization_id).await?;
+        if !session.is_admin {
+            match state
+                .db
+                .get_organization_member(organization_id, &session.user_id)
+                .await
+            {
+                Ok(_) => (),
+                Err(DatabaseError::NotFound) => return Err(ApiV1Error::NotOrganizationMember),
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+    let session = state
+        .db
+        .update_session(
+            &session.id_hash,
+            &SessionUpdate::new().with_organization(request.organization_id),
+        )
+        .await?;
+    state.sessions.insert(&session);
+    Ok(Json(session))
+}
@@ -0,0 +1,189 @@
+//! # v1 passkey API endpoint handlers
+//!
+//! These endpoints let users manage the passkeys on their own account.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use axum_extra::extract::CookieJar;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::info;
+use uuid::Uuid;
+use webauthn_rs::prelude::{CreationChallengeResponse, RegisterPublicKeyCredential};
+
+use crate::{
+    api::{
+        utils::WithCookies,
+        v1::{
+            ApiV1Error, V1State, auth,
+            extractors::{
+                Authenticated, AuthenticatedSession, ClientInfo, FreshAuthentication, RelyingParty,
+            },
+            notify,
+        },
+    },
+    authenticators::default_passkey_name,
+    models::{NewPasskeyCredential, PasskeyCredential, PasskeyCredentialUpdate, Session, new_uuid},
+    webhooks::WebhookEvent,
+};
+
+/// Request to rename a passkey
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PasskeyRenameRequest {
+    /// New display name for the passkey, or `null` to remove its name
+    pub display_name: Option<String>,
+}
+
+/// Fetches the passkey with the given ID if it belongs to the session's user. Other users'
+/// passkeys are reported as not found, so that their IDs can't be probed.
+async fn get_own_passkey(
+    state: &V1State,
+    session: &Session,
+    id: &Uuid,
+) -> Result<PasskeyCredential, ApiV1Error> {
+    let passkey = state.db.get_passkey_by_id(id).await?;
+    if passkey.user_id == session.user_id {
+        Ok(passkey)
+    } else {
+        Err(ApiV1Error::NotFound)
+    }
+}
+
+/// Returns the passkeys belonging to the current user.
+pub async fn list_current_user_passkeys(
+    auth: Authenticated,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<PasskeyCredential>>, ApiV1Error> {
+    Ok(Json(
+        state.db.get_passkeys_by_user_id(auth.user_id()).await?,
+    ))
+}
+
+/// Renames one of the current user's passkeys.
+pub async fn rename_passkey(
+    AuthenticatedSession(session): AuthenticatedSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    Json(request): Json<PasskeyRenameRequest>,
+) -> Result<Json<PasskeyCredential>, ApiV1Error> {
+    get_own_passkey(&state, &session, &id).await?;
+    let display_name = request
+        .display_name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty());
+    let passkey = state
+        .db
+        .update_passkey(
+            &id,
+            &PasskeyCredentialUpdate::new().with_display_name(display_name),
+        )
+        .await?;
+    Ok(Json(passkey))
+}
+
+/// Removes one of the current user's passkeys. A user's last passkey can't be removed, as they
+/// would have no way to log in again. The session must have been
+/// [authenticated recently][FreshAuthentication].
+pub async fn delete_passkey(
+    FreshAuthentication(session): FreshAuthentication,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    client: ClientInfo,
+) -> Result<(), ApiV1Error> {
+    let passkey = get_own_passkey(&state, &session, &id).await?;
+    if !state.db.delete_passkey_unless_last(&id).await? {
+        return Err(ApiV1Error::LastPasskey);
+    }
+    info!(user = %session.user_id, passkey = %id, "passkey removed");
+    state.emit(WebhookEvent::PasskeyDeleted {
+        user_id: session.user_id,
+        passkey_id: id,
+    });
+    let user = state.db.get_user_by_id(&session.user_id).await?;
+    notify::passkey_removed(&state, &user, &passkey, &client);
+    Ok(())
+}
+
+/// Starts registering an additional passkey for the current user. The user's existing passkeys
+/// are excluded, so that the same authenticator can't be registered twice.
+pub async fn start_passkey_registration(
+    cookies: CookieJar,
+    AuthenticatedSession(session): AuthenticatedSession,
+    State(state): State<V1State>,
+    RelyingParty(webauthn): RelyingParty,
+) -> Result<WithCookies<Json<CreationChallengeResponse>>, ApiV1Error> {
+    let user = state.db.get_user_by_id(&session.user_id).await?;
+    let exclude = state
+        .db
+        .get_passkeys_by_user_id(&session.user_id)
+        .await?
+        .into_iter()
+        .map(|passkey| passkey.passkey.0.cred_id().clone())
+        .collect();
+    auth::begin_registration(
+        cookies,
+        &state,
+        &webauthn,
+        *user.id(),
+        user.email().to_string(),
+        user.display_name(),
+        Some(exclude),
+    )
+    .await
+}
+
+/// Request to finish registering an additional passkey
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AddPasskeyRequest {
+    pub passkey: RegisterPublicKeyCredential,
+    /// Display name for the new passkey. Defaults to the name of its authenticator, or else of the
+    /// browser and platform from which it was registered.
+    pub display_name: Option<String>,
+}
+
+/// Finishes registering an additional passkey for the current user.
+pub async fn finish_passkey_registration(
+    cookies: CookieJar,
+    AuthenticatedSession(session): AuthenticatedSession,
+    State(state): State<V1State>,
+    RelyingParty(webauthn): RelyingParty,
+    client: ClientInfo,
+    Json(request): Json<AddPasskeyRequest>,
+) -> Result<WithCookies<Json<PasskeyCredential>>, ApiV1Error> {
+    let (cookies, reg_state, passkey) =
+        auth::complete_registration(cookies, &state, &webauthn, &request.passkey).await?;
+    // Don't let a ceremony started for a different user (or for a new account) add a passkey to
+    // this one
+    if reg_state.user_id != session.user_id {
+        return Err(ApiV1Error::InvalidRegistrationId);
+    }
+    let display_name = request
+        .display_name
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(|| default_passkey_name(&request.passkey, client.user_agent.as_deref()));
+    let passkey = state
+        .db
+        .create_passkey(
+            &new_uuid(),
+            &session.user_id,
+            &NewPasskeyCredential {
+                display_name,
+                passkey,
+            },
+        )
+        .await?;
+    info!(user = %session.user_id, passkey = %passkey.id, "passkey added");
+    state.emit(WebhookEvent::PasskeyCreated {
+        user_id: session.user_id,
+        passkey_id: passkey.id,
+        display_name: passkey.display_name.clone(),
+    });
+    let user = state.db.get_user_by_id(&session.user_id).await?;
+    notify::passkey_added(&state, &user, &passkey, &client);
+    Ok((cookies, Json(passkey)).into())
+}
@@ -0,0 +1,204 @@
+//! # v1 policy API endpoint handlers
+//!
+//! [`Policy`] documents permit or forbid actions on top of the permissions granted by roles (see
+//! [`crate::policy`]). Since a policy can permit anything, managing policies requires every
+//! permission. Documents are validated before they are stored, and every change to a policy's
+//! document creates a new version, to which the policy can later be restored.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::info;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    api::v1::{
+        ApiV1Error, V1State,
+        extractors::{AdminSession, ValidatedJson},
+    },
+    db::interface::DatabaseError,
+    models::{
+        MAX_NAME_LENGTH, Policy, PolicyDocument, PolicyUpdate, PolicyVersion, new_uuid, not_blank,
+    },
+    policy,
+};
+
+/// Request to create a policy
+#[derive(Debug, Clone, Deserialize, JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatePolicyRequest {
+    /// Policy name (must be unique)
+    #[validate(
+        custom(function = not_blank),
+        length(max = MAX_NAME_LENGTH, message = "must be at most 100 characters long")
+    )]
+    pub name: String,
+    /// Whether the policy is evaluated (defaults to true)
+    pub enabled: Option<bool>,
+    pub document: PolicyDocument,
+}
+
+/// Policy document to validate, which may not even have the right structure
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct ValidatePolicyRequest(#[schemars(with = "PolicyDocument")] Value);
+
+/// Result of validating a policy document
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyValidation {
+    /// Whether the document is valid
+    pub valid: bool,
+    /// Problems found in the document
+    pub errors: Vec<String>,
+}
+
+/// Checks that the document is valid, failing with [`ApiV1Error::InvalidPolicy`] if not.
+fn validate_document(document: &PolicyDocument) -> Result<(), ApiV1Error> {
+    let errors = policy::validate(document);
+    if !errors.is_empty() {
+        return Err(ApiV1Error::InvalidPolicy(errors));
+    }
+    Ok(())
+}
+
+fn map_policy_uniqueness_error(error: DatabaseError) -> ApiV1Error {
+    match error {
+        DatabaseError::UniquenessViolation { .. } => ApiV1Error::PolicyExists,
+        error => error.into(),
+    }
+}
+
+pub async fn list_policies(
+    AdminSession { .. }: AdminSession,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<Policy>>, ApiV1Error> {
+    Ok(Json(state.db.list_policies().await?))
+}
+
+/// Creates a new policy, which takes effect immediately unless it is disabled.
+pub async fn create_policy(
+    AdminSession(session): AdminSession,
+    State(state): State<V1State>,
+    ValidatedJson(request): ValidatedJson<CreatePolicyRequest>,
+) -> Result<Json<Policy>, ApiV1Error> {
+    validate_document(&request.document)?;
+    let mut create = PolicyUpdate::new()
+        .with_name(request.name)
+        .with_document(request.document);
+    create.enabled = request.enabled;
+    let policy = state
+        .db
+        .create_policy(&new_uuid(), &create)
+        .await
+        .map_err(map_policy_uniqueness_error)?;
+    info!(policy = %policy.name, admin = %session.user_id, "policy created");
+    Ok(Json(policy))
+}
+
+/// Validates a policy document without storing it.
+pub async fn validate_policy(
+    AdminSession { .. }: AdminSession,
+    Json(ValidatePolicyRequest(document)): Json<ValidatePolicyRequest>,
+) -> Json<PolicyValidation> {
+    let errors = match serde_json::from_value(document) {
+        Ok(document) => policy::validate(&document),
+        Err(err) => vec![err.to_string()],
+    };
+    Json(PolicyValidation {
+        valid: errors.is_empty(),
+        errors,
+    })
+}
+
+pub async fn get_policy(
+    AdminSession { .. }: AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Policy>, ApiV1Error> {
+    Ok(Json(state.db.get_policy_by_id(&id).await?))
+}
+
+/// Updates a policy. Replacing its document creates a new version.
+pub async fn update_policy(
+    AdminSession(session): AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    ValidatedJson(update): ValidatedJson<PolicyUpdate>,
+) -> Result<Json<Policy>, ApiV1Error> {
+    if update.is_empty() {
+        return Err(ApiV1Error::InvalidField("update", "must not be empty"));
+    }
+    if let Some(document) = &update.document {
+        validate_document(document)?;
+    }
+    let policy = state
+        .db
+        .update_policy(&id, &update)
+        .await
+        .map_err(map_policy_uniqueness_error)?;
+    info!(
+        policy = %policy.name,
+        version = policy.version,
+        admin = %session.user_id,
+        "policy updated"
+    );
+    Ok(Json(policy))
+}
+
+/// Deletes a policy along with its versions.
+pub async fn delete_policy(
+    AdminSession(session): AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    let policy = state.db.get_policy_by_id(&id).await?;
+    state.db.delete_policy_by_id(&id).await?;
+    info!(policy = %policy.name, admin = %session.user_id, "policy deleted");
+    Ok(())
+}
+
+/// Returns the versions of a policy, newest first.
+pub async fn list_policy_versions(
+    AdminSession { .. }: AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<PolicyVersion>>, ApiV1Error> {
+    state.db.get_policy_by_id(&id).await?;
+    Ok(Json(state.db.list_policy_versions(&id).await?))
+}
+
+pub async fn get_policy_version(
+    AdminSession { .. }: AdminSession,
+    Path((id, version)): Path<(Uuid, i64)>,
+    State(state): State<V1State>,
+) -> Result<Json<PolicyVersion>, ApiV1Error> {
+    Ok(Json(state.db.get_policy_version(&id, version).await?))
+}
+
+/// Restores an earlier version of a policy's document. This creates a new version with the same
+/// document, so that the restore can itself be undone.
+pub async fn restore_policy_version(
+    AdminSession(session): AdminSession,
+    Path((id, version)): Path<(Uuid, i64)>,
+    State(state): State<V1State>,
+) -> Result<Json<Policy>, ApiV1Error> {
+    let restored = state.db.get_policy_version(&id, version).await?;
+    let policy = state
+        .db
+        .update_policy(&id, &PolicyUpdate::new().with_document(restored.document.0))
+        .await?;
+    info!(
+        policy = %policy.name,
+        restored = version,
+        version = policy.version,
+        admin = %session.user_id,
+        "policy version restored"
+    );
+    Ok(Json(policy))
+}
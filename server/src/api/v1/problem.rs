@@ -0,0 +1,163 @@
+//! # Problem details for errors
+//!
+//! Clients which prefer `application/problem+json` (RFC 7807) in their `Accept` header get error
+//! responses in that format instead of an [`ApiErrorResponse`]. Each [error code][ApiErrorCode]
+//! has its own `type` URI, `urn:iam:problem:<code>`, which doesn't depend on the API version.
+
+use aide::{
+    generate::GenContext,
+    openapi::{MediaType, Response as OapiResponse, SchemaObject},
+};
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{
+        HeaderMap, HeaderValue, StatusCode,
+        header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, VARY},
+    },
+    middleware::Next,
+    response::Response,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use super::{ApiErrorCode, ApiErrorResponse};
+
+/// Media type of problem details
+const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Prefix of the `type` URIs of problems
+const TYPE_URI_PREFIX: &str = "urn:iam:problem:";
+
+/// Description of an API error in the format of RFC 7807
+#[derive(Debug, Serialize, JsonSchema)]
+pub(crate) struct ProblemDetails {
+    /// URI identifying the kind of error, `urn:iam:problem:` followed by the error code
+    #[serde(rename = "type")]
+    type_uri: String,
+    /// Short summary of the kind of error, i.e. the reason phrase of the status code
+    title: String,
+    /// HTTP status code of the response
+    status: u16,
+    /// Human-readable description of this occurrence of the error
+    detail: String,
+    code: ApiErrorCode,
+    /// Structured information about the error, as in the JSON error format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<Map<String, Value>>,
+}
+
+impl ProblemDetails {
+    /// Describes the given error, which was returned with the given status code.
+    fn new(status: StatusCode, error: ApiErrorResponse) -> Self {
+        let error = error.error;
+        let code = serde_json::to_value(error.code).expect("serializing error code failed");
+        Self {
+            type_uri: format!(
+                "{TYPE_URI_PREFIX}{}",
+                code.as_str().expect("error codes are strings")
+            ),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            status: status.as_u16(),
+            detail: error.message,
+            code: error.code,
+            details: error.details,
+        }
+    }
+}
+
+/// Adds the problem details format to the documentation of an error response.
+pub(super) fn document(ctx: &mut GenContext, response: &mut OapiResponse) {
+    response.content.insert(
+        PROBLEM_JSON.to_string(),
+        MediaType {
+            schema: Some(SchemaObject {
+                json_schema: ctx.schema.subschema_for::<ProblemDetails>(),
+                example: None,
+                external_docs: None,
+            }),
+            ..Default::default()
+        },
+    );
+}
+
+/// Middleware which converts error responses to problem details if the client prefers them.
+pub(super) async fn negotiate_error_format(request: Request, next: Next) -> Response {
+    let prefers_problem = prefers_problem_json(request.headers());
+    let mut response = next.run(request).await;
+    let Some(error) = response.extensions_mut().remove::<ApiErrorResponse>() else {
+        return response;
+    };
+    response
+        .headers_mut()
+        .append(VARY, HeaderValue::from_static("Accept"));
+    if !prefers_problem {
+        return response;
+    }
+    let (mut parts, _) = response.into_parts();
+    let problem = ProblemDetails::new(parts.status, error);
+    let body = serde_json::to_vec(&problem).expect("serializing problem details failed");
+    parts
+        .headers
+        .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// Returns whether the `Accept` header ranks problem details at least as high as plain JSON.
+fn prefers_problem_json(headers: &HeaderMap) -> bool {
+    let mut problem_quality = 0.0;
+    let mut json_quality = 0.0_f32;
+    for range in headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+    {
+        let mut params = range.split(';');
+        let media_type = params.next().unwrap_or_default().trim();
+        let quality = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.parse().ok())
+            .unwrap_or(1.0);
+        if media_type.eq_ignore_ascii_case(PROBLEM_JSON) {
+            problem_quality = quality;
+        } else if ["application/json", "application/*", "*/*"]
+            .iter()
+            .any(|json| media_type.eq_ignore_ascii_case(json))
+        {
+            json_quality = json_quality.max(quality);
+        }
+    }
+    problem_quality > 0.0 && problem_quality >= json_quality
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &str) -> HeaderMap {
+        HeaderMap::from_iter([(ACCEPT, HeaderValue::from_str(value).unwrap())])
+    }
+
+    #[test]
+    fn test_prefers_problem_json() {
+        assert!(!prefers_problem_json(&HeaderMap::new()));
+        assert!(!prefers_problem_json(&accept("*/*")));
+        assert!(!prefers_problem_json(&accept("application/json")));
+        assert!(prefers_problem_json(&accept("application/problem+json")));
+        assert!(prefers_problem_json(&accept(
+            "application/json, application/problem+json"
+        )));
+        assert!(!prefers_problem_json(&accept(
+            "application/problem+json;q=0.5, application/json"
+        )));
+        assert!(prefers_problem_json(&accept(
+            "application/problem+json, */*;q=0.1"
+        )));
+        assert!(!prefers_problem_json(&accept(
+            "application/problem+json;q=0"
+        )));
+    }
+}
@@ -0,0 +1,214 @@
+//! # v1 role API endpoint handlers
+//!
+//! [`Role`]s grant [`Permission`]s to the users to whom their tags apply. Users can only grant
+//! permissions which they have themselves: creating, changing, assigning, or removing a role
+//! requires every permission it grants, and so does managing tags, groups, and users which have
+//! them.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::info;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    api::v1::{
+        ApiV1Error, V1State,
+        extractors::{RequirePermission, ValidatedJson, permission},
+    },
+    db::interface::DatabaseError,
+    models::{MAX_NAME_LENGTH, Permission, Permissions, Role, RoleUpdate, new_uuid, not_blank},
+};
+
+/// Request to create a role
+#[derive(Debug, Clone, Deserialize, JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateRoleRequest {
+    /// Role name (must be unique)
+    #[validate(
+        custom(function = not_blank),
+        length(max = MAX_NAME_LENGTH, message = "must be at most 100 characters long")
+    )]
+    pub name: String,
+    /// Permissions granted by the role
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+}
+
+/// Checks that users with the permissions `permissions` may grant those in `granted`, failing with
+/// [`ApiV1Error::MissingPermission`] if not.
+pub(super) fn ensure_can_grant(
+    permissions: &Permissions,
+    granted: &Permissions,
+) -> Result<(), ApiV1Error> {
+    match permissions.first_missing(granted) {
+        Some(permission) => Err(ApiV1Error::MissingPermission(permission)),
+        None => Ok(()),
+    }
+}
+
+/// Checks that users with the permissions `permissions` may manage the user with the given ID,
+/// i.e. that they have every permission that user has.
+pub(super) async fn ensure_can_manage(
+    state: &V1State,
+    permissions: &Permissions,
+    user_id: &Uuid,
+) -> Result<(), ApiV1Error> {
+    let target = Permissions::for_user(state.db.as_ref(), user_id).await?;
+    ensure_can_grant(permissions, &target)
+}
+
+fn map_role_uniqueness_error(error: DatabaseError) -> ApiV1Error {
+    match error {
+        DatabaseError::UniquenessViolation { .. } => ApiV1Error::RoleExists,
+        error => error.into(),
+    }
+}
+
+/// Returns the permissions granted by the given role.
+fn role_permissions(role: &Role) -> Permissions {
+    role.permissions.iter().copied().collect()
+}
+
+pub async fn list_roles(
+    RequirePermission { .. }: RequirePermission<permission::RolesRead>,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<Role>>, ApiV1Error> {
+    Ok(Json(state.db.list_roles().await?))
+}
+
+/// Creates a new role.
+pub async fn create_role(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::RolesWrite>,
+    State(state): State<V1State>,
+    ValidatedJson(request): ValidatedJson<CreateRoleRequest>,
+) -> Result<Json<Role>, ApiV1Error> {
+    ensure_can_grant(&permissions, &request.permissions.iter().copied().collect())?;
+    let role = state
+        .db
+        .create_role(
+            &new_uuid(),
+            &RoleUpdate::new()
+                .with_name(request.name)
+                .with_permissions(request.permissions),
+        )
+        .await
+        .map_err(map_role_uniqueness_error)?;
+    info!(role = %role.name, admin = %session.user_id, "role created");
+    Ok(Json(role))
+}
+
+pub async fn get_role(
+    RequirePermission { .. }: RequirePermission<permission::RolesRead>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Role>, ApiV1Error> {
+    Ok(Json(state.db.get_role_by_id(&id).await?))
+}
+
+/// Updates a role. Changing a role's permissions requires the permissions it granted before and
+/// those it grants afterwards.
+pub async fn update_role(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::RolesWrite>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    ValidatedJson(update): ValidatedJson<RoleUpdate>,
+) -> Result<Json<Role>, ApiV1Error> {
+    if update.is_empty() {
+        return Err(ApiV1Error::InvalidField("update", "must not be empty"));
+    }
+    let role = state.db.get_role_by_id(&id).await?;
+    ensure_can_grant(&permissions, &role_permissions(&role))?;
+    if let Some(granted) = &update.permissions {
+        ensure_can_grant(&permissions, &granted.iter().copied().collect())?;
+    }
+    let role = state
+        .db
+        .update_role(&id, &update)
+        .await
+        .map_err(map_role_uniqueness_error)?;
+    info!(role = %role.name, admin = %session.user_id, "role updated");
+    Ok(Json(role))
+}
+
+/// Deletes a role, removing it from all tags.
+pub async fn delete_role(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::RolesWrite>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    let role = state.db.get_role_by_id(&id).await?;
+    ensure_can_grant(&permissions, &role_permissions(&role))?;
+    state.db.delete_role_by_id(&id).await?;
+    info!(role = %role.name, admin = %session.user_id, "role deleted");
+    Ok(())
+}
+
+/// Returns the roles assigned to a tag.
+pub async fn list_tag_roles(
+    RequirePermission { .. }: RequirePermission<permission::RolesRead>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<Role>>, ApiV1Error> {
+    state.db.get_tag_by_id(&id).await?;
+    Ok(Json(state.db.get_roles_by_tag_id(&id).await?))
+}
+
+/// Assigns a role to a tag, granting its permissions to everyone to whom the tag applies.
+/// Assigning a role which the tag already has does nothing. Roles can't be assigned to
+/// organization tags, since organization administrators apply those.
+pub async fn add_tag_role(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::RolesWrite>,
+    Path((id, role_id)): Path<(Uuid, Uuid)>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    let tag = state.db.get_tag_by_id(&id).await?;
+    if tag.organization_id.is_some() {
+        return Err(ApiV1Error::InvalidTag(
+            "roles can't be assigned to organization tags",
+        ));
+    }
+    let role = state.db.get_role_by_id(&role_id).await?;
+    ensure_can_grant(&permissions, &role_permissions(&role))?;
+    state.db.add_role_to_tag(&id, &role_id).await?;
+    info!(tag = %tag.name, role = %role.name, admin = %session.user_id, "role added to tag");
+    Ok(())
+}
+
+/// Removes a role from a tag.
+pub async fn remove_tag_role(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::RolesWrite>,
+    Path((id, role_id)): Path<(Uuid, Uuid)>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    let tag = state.db.get_tag_by_id(&id).await?;
+    let role = state.db.get_role_by_id(&role_id).await?;
+    ensure_can_grant(&permissions, &role_permissions(&role))?;
+    state.db.remove_role_from_tag(&id, &role_id).await?;
+    info!(tag = %tag.name, role = %role.name, admin = %session.user_id, "role removed from tag");
+    Ok(())
+}
@@ -0,0 +1,313 @@
+//! # v1 SAML 2.0 identity provider endpoints
+//!
+//! These endpoints let registered [`SamlServiceProvider`]s log users in with SP-initiated single
+//! sign-on over the HTTP-POST binding:
+//!
+//! 1. The service provider posts an authentication request to the [SSO endpoint][sso_post]. If the
+//!    user isn't logged in, they are sent to the login page first, which returns them to the
+//!    [`GET` variant][sso_get] of the endpoint.
+//! 2. The user's browser posts a response containing a signed assertion about the user to the
+//!    service provider's assertion consumer service.
+//!
+//! Service providers are configured from the [metadata document][get_metadata], whose URL is also
+//! the identity provider's entity ID. See [`crate::saml`] for the message formats.
+
+use axum::{
+    Form, Json,
+    extract::{Path, Query, State},
+    http::{HeaderName, header::CONTENT_TYPE},
+    response::{Html, IntoResponse, Redirect, Response},
+};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chrono::Utc;
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    api::v1::{
+        ApiV1Error, V1State,
+        extractors::{AdminSession, AuthenticatedSession},
+    },
+    db::interface::DatabaseError,
+    models::{SamlNameIdFormat, SamlServiceProvider, SamlServiceProviderUpdate, new_uuid},
+    saml::{self, AuthnRequest, SamlResponse},
+};
+
+/// Media type of SAML metadata documents
+const METADATA_TYPE: &str = "application/samlmetadata+xml";
+
+/// Serves the identity provider's metadata, with which service providers are configured.
+pub async fn get_metadata(
+    State(state): State<V1State>,
+) -> Result<([(HeaderName, &'static str); 1], String), ApiV1Error> {
+    let certificates = state
+        .keys
+        .certificates()
+        .map_err(|err| ApiV1Error::InternalServerError(err.into()))?;
+    let metadata = saml::metadata(
+        &format!("{}/saml/metadata", state.api_url),
+        &format!("{}/saml/sso", state.api_url),
+        &certificates,
+    );
+    Ok(([(CONTENT_TYPE, METADATA_TYPE)], metadata))
+}
+
+/// Parameters of the HTTP-POST binding
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct SsoRequest {
+    /// Base64-encoded `AuthnRequest`
+    #[serde(rename = "SAMLRequest")]
+    pub saml_request: String,
+    /// Opaque value which is returned to the service provider along with the response
+    #[serde(rename = "RelayState")]
+    pub relay_state: Option<String>,
+}
+
+/// Single sign-on endpoint
+///
+/// Responds with a page which posts a signed response to the requesting service provider. Users
+/// who aren't logged in are redirected to the login page, which returns them to the `GET` variant
+/// of this endpoint afterwards. Responds with `400 Bad Request` if the request is malformed or
+/// comes from an unknown service provider.
+pub async fn sso_post(
+    session: Option<AuthenticatedSession>,
+    State(state): State<V1State>,
+    Form(request): Form<SsoRequest>,
+) -> Result<Response, ApiV1Error> {
+    sso(session, &state, request).await
+}
+
+/// Single sign-on endpoint for users returning from the login page
+///
+/// Takes the same parameters as the `POST` variant, but in the query string.
+pub async fn sso_get(
+    session: Option<AuthenticatedSession>,
+    State(state): State<V1State>,
+    Query(request): Query<SsoRequest>,
+) -> Result<Response, ApiV1Error> {
+    sso(session, &state, request).await
+}
+
+async fn sso(
+    session: Option<AuthenticatedSession>,
+    state: &V1State,
+    request: SsoRequest,
+) -> Result<Response, ApiV1Error> {
+    let authn_request = AuthnRequest::decode(&request.saml_request)
+        .map_err(|_| ApiV1Error::InvalidField("SAMLRequest", "malformed request"))?;
+    let provider = match state
+        .db
+        .get_saml_service_provider_by_entity_id(&authn_request.issuer)
+        .await
+    {
+        Ok(provider) => provider,
+        Err(DatabaseError::NotFound) => {
+            return Err(ApiV1Error::InvalidField(
+                "SAMLRequest",
+                "unknown service provider",
+            ));
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if authn_request
+        .acs_url
+        .as_ref()
+        .is_some_and(|acs_url| *acs_url != provider.acs_url)
+    {
+        return Err(ApiV1Error::InvalidField(
+            "SAMLRequest",
+            "assertion consumer service URL is not registered",
+        ));
+    }
+
+    let Some(AuthenticatedSession(session)) = session else {
+        // The login page sends the user to `next` once they have logged in
+        let base_path = &state.config.base_path;
+        let mut resume = Url::parse("http://localhost/sso").expect("placeholder URL is valid");
+        resume
+            .query_pairs_mut()
+            .append_pair("SAMLRequest", &request.saml_request)
+            .extend_pairs(
+                request
+                    .relay_state
+                    .as_ref()
+                    .map(|relay| ("RelayState", relay)),
+            );
+        let next = format!(
+            "{base_path}/api/v1/saml/sso?{}",
+            resume.query().unwrap_or_default()
+        );
+        let mut login = Url::parse("http://localhost/login").expect("placeholder URL is valid");
+        login.query_pairs_mut().append_pair("next", &next);
+        return Ok(Redirect::to(&format!(
+            "{base_path}/login?{}",
+            login.query().unwrap_or_default()
+        ))
+        .into_response());
+    };
+
+    let user = state.db.get_user_by_id(&session.user_id).await?;
+    let tags = state
+        .db
+        .get_effective_tags_by_user_id(&session.user_id)
+        .await?;
+    let name_id = match provider.name_id_format {
+        SamlNameIdFormat::Persistent => user.id().to_string(),
+        SamlNameIdFormat::EmailAddress => user.email().to_string(),
+    };
+    let attributes = [
+        ("email", vec![user.email().to_string()]),
+        ("displayName", vec![user.display_name().to_string()]),
+        ("tags", tags.into_iter().map(|tag| tag.name).collect()),
+    ];
+    let response = SamlResponse {
+        issuer: &format!("{}/saml/metadata", state.api_url),
+        request: &authn_request,
+        audience: &provider.entity_id,
+        acs_url: &provider.acs_url,
+        name_id_format: provider.name_id_format.uri(),
+        name_id: &name_id,
+        authn_instant: session.created_at,
+        attributes: &attributes,
+    }
+    .sign(&state.keys.active())
+    .map_err(|err| ApiV1Error::InternalServerError(err.into()))?;
+    info!(user = %session.user_id, provider = %provider.id, "issued SAML assertion");
+    Ok(Html(saml::post_form(
+        &provider.acs_url,
+        &BASE64_STANDARD.encode(response),
+        request.relay_state.as_deref(),
+    ))
+    .into_response())
+}
+
+/// Request to register a SAML service provider
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SamlServiceProviderRequest {
+    /// SAML entity ID of the service provider
+    pub entity_id: String,
+    /// Name of the application
+    pub name: String,
+    /// URL of the service provider's assertion consumer service
+    pub acs_url: String,
+    /// Format of the `NameID` sent to the service provider
+    #[serde(default)]
+    pub name_id_format: SamlNameIdFormat,
+}
+
+/// Checks that a required field isn't empty, returning it trimmed.
+fn validate_non_empty(field: &'static str, value: &str) -> Result<String, ApiV1Error> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(ApiV1Error::InvalidField(field, "must not be empty"));
+    }
+    Ok(value.to_string())
+}
+
+/// Checks that the assertion consumer service URL is an absolute HTTP(S) URL.
+fn validate_acs_url(acs_url: &str) -> Result<(), ApiV1Error> {
+    match Url::parse(acs_url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => Ok(()),
+        _ => Err(ApiV1Error::InvalidField(
+            "acsUrl",
+            "must be an absolute HTTP or HTTPS URL",
+        )),
+    }
+}
+
+/// Maps a uniqueness violation to an error about the entity ID.
+fn entity_id_taken(err: DatabaseError) -> ApiV1Error {
+    match err {
+        DatabaseError::UniquenessViolation { .. } => ApiV1Error::InvalidField(
+            "entityId",
+            "another service provider has the same entity ID",
+        ),
+        err => err.into(),
+    }
+}
+
+pub async fn list_service_providers(
+    AdminSession { .. }: AdminSession,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<SamlServiceProvider>>, ApiV1Error> {
+    Ok(Json(state.db.list_saml_service_providers().await?))
+}
+
+/// Registers a SAML service provider.
+pub async fn create_service_provider(
+    AdminSession(session): AdminSession,
+    State(state): State<V1State>,
+    Json(request): Json<SamlServiceProviderRequest>,
+) -> Result<Json<SamlServiceProvider>, ApiV1Error> {
+    let entity_id = validate_non_empty("entityId", &request.entity_id)?;
+    let name = validate_non_empty("name", &request.name)?;
+    validate_acs_url(&request.acs_url)?;
+    let now = Utc::now();
+    let provider = SamlServiceProvider {
+        id: new_uuid(),
+        entity_id,
+        name,
+        acs_url: request.acs_url,
+        name_id_format: request.name_id_format,
+        created_at: now,
+        updated_at: now,
+    };
+    let provider = state
+        .db
+        .create_saml_service_provider(&provider)
+        .await
+        .map_err(entity_id_taken)?;
+    info!(provider = %provider.id, admin = %session.user_id, "SAML service provider registered");
+    Ok(Json(provider))
+}
+
+pub async fn get_service_provider(
+    AdminSession { .. }: AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<SamlServiceProvider>, ApiV1Error> {
+    Ok(Json(state.db.get_saml_service_provider_by_id(&id).await?))
+}
+
+/// Updates a SAML service provider.
+pub async fn update_service_provider(
+    AdminSession(session): AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    Json(mut update): Json<SamlServiceProviderUpdate>,
+) -> Result<Json<SamlServiceProvider>, ApiV1Error> {
+    if update.is_empty() {
+        return Ok(Json(state.db.get_saml_service_provider_by_id(&id).await?));
+    }
+    if let Some(entity_id) = &update.entity_id {
+        update.entity_id = Some(validate_non_empty("entityId", entity_id)?);
+    }
+    if let Some(name) = &update.name {
+        update.name = Some(validate_non_empty("name", name)?);
+    }
+    if let Some(acs_url) = &update.acs_url {
+        validate_acs_url(acs_url)?;
+    }
+    let provider = state
+        .db
+        .update_saml_service_provider(&id, &update)
+        .await
+        .map_err(entity_id_taken)?;
+    info!(provider = %id, admin = %session.user_id, "SAML service provider updated");
+    Ok(Json(provider))
+}
+
+/// Removes a SAML service provider.
+pub async fn delete_service_provider(
+    AdminSession(session): AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.delete_saml_service_provider_by_id(&id).await?;
+    info!(provider = %id, admin = %session.user_id, "SAML service provider removed");
+    Ok(())
+}
@@ -0,0 +1,62 @@
+//! # In-process session cache
+//!
+//! Every authenticated request needs the client's [`Session`], so the [`SessionCache`] keeps
+//! recently fetched sessions in memory for a short time instead of reading them from the
+//! database each time. Handlers which change sessions must invalidate the affected entries.
+//!
+//! The cache is per instance, so a session changed by another instance sharing the database may
+//! be used here for up to the cache's TTL. Expiration times are checked on every request, so the
+//! cache never extends a session's lifetime.
+
+use std::time::Duration;
+
+use uuid::Uuid;
+
+use crate::{
+    models::{EncodableHash, Session},
+    ttl_cache::TtlCache,
+};
+
+/// # In-process session cache
+///
+/// See the [module-level documentation][self] for details.
+pub struct SessionCache {
+    /// Sessions by ID hash. Keyed by the inner [`blake3::Hash`], whose comparison is
+    /// constant-time.
+    entries: TtlCache<blake3::Hash, Session>,
+}
+
+impl SessionCache {
+    /// Creates a cache which keeps sessions for `ttl`. A zero `ttl` disables the cache.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: TtlCache::new(ttl),
+        }
+    }
+
+    /// Returns the cached session with the given ID hash, if it was cached less than the TTL ago.
+    pub fn get(&self, id_hash: &EncodableHash) -> Option<Session> {
+        self.entries.get(&id_hash.0)
+    }
+
+    /// Caches the given session.
+    pub fn insert(&self, session: &Session) {
+        self.entries.insert(session.id_hash.0, session.clone());
+    }
+
+    /// Removes the session with the given ID hash from the cache.
+    pub fn invalidate(&self, id_hash: &EncodableHash) {
+        self.entries.remove(&id_hash.0);
+    }
+
+    /// Removes all sessions belonging to the user with the given UUID from the cache.
+    pub fn invalidate_user(&self, user_id: &Uuid) {
+        self.entries
+            .retain(|_, session| session.user_id != *user_id);
+    }
+
+    /// Removes all sessions from the cache.
+    pub fn clear(&self) {
+        self.entries.clear();
+    }
+}
@@ -0,0 +1,213 @@
+//! # v1 tag API endpoint handlers
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::info;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    alerts::SecurityEvent,
+    api::{
+        utils::{Page, Pagination},
+        v1::{
+            ApiV1Error, V1State,
+            extractors::{RequirePermission, ValidatedJson, permission},
+            role::ensure_can_grant,
+        },
+    },
+    db::interface::DatabaseError,
+    models::{ADMIN_TAG, MAX_NAME_LENGTH, Permissions, Tag, TagUpdate, User, not_blank},
+};
+
+/// Prefix of the names of built-in tags, like `iam::admin`. Tags in this namespace have special
+/// meaning to the server, so they can't be created, renamed, or deleted through the API.
+const BUILTIN_TAG_PREFIX: &str = "iam::";
+
+/// Request to create a tag
+#[derive(Debug, Clone, Deserialize, JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateTagRequest {
+    /// Tag name (must be unique)
+    #[validate(
+        custom(function = not_blank),
+        length(max = MAX_NAME_LENGTH, message = "must be at most 100 characters long")
+    )]
+    pub name: String,
+}
+
+/// Checks that `name` can be used as the name of a tag created or renamed through the API.
+pub(super) fn validate_name(name: &str) -> Result<(), ApiV1Error> {
+    if name.starts_with(BUILTIN_TAG_PREFIX) {
+        return Err(ApiV1Error::InvalidTag(
+            "names starting with `iam::` are reserved",
+        ));
+    }
+    Ok(())
+}
+
+/// Fetches the tag with the given ID, failing if it's a built-in tag.
+async fn get_mutable_tag(state: &V1State, id: &Uuid) -> Result<Tag, ApiV1Error> {
+    let tag = state.db.get_tag_by_id(id).await?;
+    if tag.name.starts_with(BUILTIN_TAG_PREFIX) {
+        return Err(ApiV1Error::InvalidTag("built-in tags can't be modified"));
+    }
+    Ok(tag)
+}
+
+/// Fetches the permissions granted by the given tag.
+pub(super) async fn tag_permissions(state: &V1State, tag: &Tag) -> Result<Permissions, ApiV1Error> {
+    Ok(Permissions::for_tags(state.db.as_ref(), std::slice::from_ref(tag)).await?)
+}
+
+pub(super) fn map_uniqueness_error(error: DatabaseError) -> ApiV1Error {
+    match error {
+        DatabaseError::UniquenessViolation { .. } => ApiV1Error::TagExists,
+        error => error.into(),
+    }
+}
+
+pub async fn list_tags(
+    RequirePermission { .. }: RequirePermission<permission::TagsRead>,
+    pagination: Pagination,
+    State(state): State<V1State>,
+) -> Result<Json<Page<Tag>>, ApiV1Error> {
+    let tags = state.db.list_tags().await?;
+    Ok(Json(Page::from_sorted(tags, &pagination, |tag| {
+        (tag.name.clone(), tag.id)
+    })?))
+}
+
+/// Creates a new tag.
+pub async fn create_tag(
+    RequirePermission { session, .. }: RequirePermission<permission::TagsWrite>,
+    State(state): State<V1State>,
+    ValidatedJson(request): ValidatedJson<CreateTagRequest>,
+) -> Result<Json<Tag>, ApiV1Error> {
+    validate_name(&request.name)?;
+    let tag = state
+        .db
+        .create_tag(
+            &Uuid::new_v4(),
+            None,
+            &TagUpdate::new().with_name(request.name),
+        )
+        .await
+        .map_err(map_uniqueness_error)?;
+    info!(tag = %tag.name, admin = %session.user_id, "tag created");
+    Ok(Json(tag))
+}
+
+pub async fn get_tag(
+    RequirePermission { .. }: RequirePermission<permission::TagsRead>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Tag>, ApiV1Error> {
+    Ok(Json(state.db.get_tag_by_id(&id).await?))
+}
+
+/// Updates a tag. Built-in tags can't be updated.
+pub async fn update_tag(
+    RequirePermission { session, .. }: RequirePermission<permission::TagsWrite>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    ValidatedJson(update): ValidatedJson<TagUpdate>,
+) -> Result<Json<Tag>, ApiV1Error> {
+    if update.is_empty() {
+        return Err(ApiV1Error::InvalidTag("update must not be empty"));
+    }
+    if let Some(name) = &update.name {
+        validate_name(name)?;
+    }
+    get_mutable_tag(&state, &id).await?;
+    let tag = state
+        .db
+        .update_tag(&id, &update)
+        .await
+        .map_err(map_uniqueness_error)?;
+    info!(tag = %tag.name, admin = %session.user_id, "tag updated");
+    Ok(Json(tag))
+}
+
+/// Deletes a tag, removing it from all users. Built-in tags can't be deleted.
+pub async fn delete_tag(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::TagsWrite>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    let tag = get_mutable_tag(&state, &id).await?;
+    ensure_can_grant(&permissions, &tag_permissions(&state, &tag).await?)?;
+    state.db.delete_tag_by_id(&id).await?;
+    info!(tag = %tag.name, admin = %session.user_id, "tag deleted");
+    Ok(())
+}
+
+/// Returns the users to which a tag is applied.
+pub async fn get_tag_users(
+    RequirePermission { .. }: RequirePermission<permission::TagsRead>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<User>>, ApiV1Error> {
+    // Distinguish a missing tag from a tag without users
+    state.db.get_tag_by_id(&id).await?;
+    Ok(Json(state.db.get_users_by_tag_id(&id).await?))
+}
+
+/// Applies a tag to a user. Applying a tag which the user already has does nothing.
+pub async fn add_tag_to_user(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::TagsWrite>,
+    Path((user_id, tag_id)): Path<(Uuid, Uuid)>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    let user = state.db.get_user_by_id(&user_id).await?;
+    let tag = state.db.get_tag_by_id(&tag_id).await?;
+    ensure_can_grant(&permissions, &tag_permissions(&state, &tag).await?)?;
+    let tags = state.db.get_tags_by_user_id(&user_id).await?;
+    if tags.iter().any(|existing| existing.id == tag.id) {
+        return Ok(());
+    }
+    state.db.add_tag_to_user(&user_id, &tag).await?;
+    info!(tag = %tag.name, user = %user_id, admin = %session.user_id, "tag added to user");
+    if tag.name == ADMIN_TAG {
+        state.report(SecurityEvent::AdminTagGranted {
+            user_id,
+            email: user.email().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Removes a tag from a user. Removing the administrator tag also logs the user out everywhere,
+/// so that any administrator sessions they have end immediately.
+pub async fn remove_tag_from_user(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::TagsWrite>,
+    Path((user_id, tag_id)): Path<(Uuid, Uuid)>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.get_user_by_id(&user_id).await?;
+    let tag = state.db.get_tag_by_id(&tag_id).await?;
+    ensure_can_grant(&permissions, &tag_permissions(&state, &tag).await?)?;
+    state.db.remove_tag_from_user(&user_id, &tag).await?;
+    info!(tag = %tag.name, user = %user_id, admin = %session.user_id, "tag removed from user");
+    if tag.name == ADMIN_TAG {
+        state.db.revoke_sessions_by_user_id(&user_id).await?;
+        state.sessions.invalidate_user(&user_id);
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+//! # v1 API token endpoint handlers
+//!
+//! These endpoints let users manage the [API tokens][ApiToken] with which programmatic clients can
+//! call the API on their behalf. Tokens can only be managed with a session, so that a leaked token
+//! can't be used to mint more tokens.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    api::v1::{ApiV1Error, V1State, extractors::AuthenticatedSession},
+    models::{ApiToken, ApiTokenScope, ViaJson, new_uuid},
+};
+
+/// Prefix of all API tokens, which makes them easy to recognize, e.g. by secret scanners
+const TOKEN_PREFIX: &str = "iam_";
+
+/// Maximum length of a token's name, in characters
+const MAX_NAME_LENGTH: usize = 100;
+
+fn default_scopes() -> Vec<ApiTokenScope> {
+    vec![ApiTokenScope::Read]
+}
+
+/// Request to create an API token
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiTokenRequest {
+    /// Name by which to recognize the token
+    pub name: String,
+    /// Permissions to grant to the token. Defaults to read-only access.
+    #[serde(default = "default_scopes")]
+    pub scopes: Vec<ApiTokenScope>,
+    /// Time at which the token expires. If omitted, the token never expires.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// A newly created API token, along with the token itself
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedApiToken {
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+    /// The token, to be sent in an `Authorization: Bearer` header. It can't be retrieved again
+    /// later.
+    pub token: String,
+}
+
+/// Returns the current user's API tokens.
+pub async fn list_api_tokens(
+    AuthenticatedSession(session): AuthenticatedSession,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<ApiToken>>, ApiV1Error> {
+    Ok(Json(
+        state.db.get_api_tokens_by_user_id(&session.user_id).await?,
+    ))
+}
+
+/// Creates an API token for the current user. The response contains the token, which can't be
+/// retrieved again.
+pub async fn create_api_token(
+    AuthenticatedSession(session): AuthenticatedSession,
+    State(state): State<V1State>,
+    Json(request): Json<ApiTokenRequest>,
+) -> Result<Json<CreatedApiToken>, ApiV1Error> {
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err(ApiV1Error::InvalidField("name", "must not be empty"));
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(ApiV1Error::InvalidField(
+            "name",
+            "must be at most 100 characters long",
+        ));
+    }
+    if request.scopes.is_empty() {
+        return Err(ApiV1Error::InvalidField("scopes", "must not be empty"));
+    }
+    let now = Utc::now();
+    if request
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= now)
+    {
+        return Err(ApiV1Error::InvalidField(
+            "expiresAt",
+            "must be in the future",
+        ));
+    }
+
+    let mut secret = [0u8; 32];
+    rand::rng().fill_bytes(&mut secret);
+    let token = format!("{TOKEN_PREFIX}{}", hex::encode(secret));
+    let mut scopes = request.scopes;
+    scopes.sort_unstable();
+    scopes.dedup();
+    let api_token = ApiToken {
+        id: new_uuid(),
+        user_id: session.user_id,
+        name: name.to_string(),
+        token_hash: blake3::hash(token.as_bytes()).into(),
+        scopes: ViaJson(scopes),
+        created_at: now,
+        expires_at: request.expires_at,
+    };
+    let api_token = state.db.create_api_token(&api_token).await?;
+    info!(token = %api_token.id, user = %session.user_id, "API token created");
+    Ok(Json(CreatedApiToken { api_token, token }))
+}
+
+/// Revokes one of the current user's API tokens.
+pub async fn delete_api_token(
+    AuthenticatedSession(session): AuthenticatedSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.delete_api_token(&session.user_id, &id).await?;
+    info!(token = %id, user = %session.user_id, "API token revoked");
+    Ok(())
+}
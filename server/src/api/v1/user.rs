@@ -1,45 +1,630 @@
 //! # v1 user-related API endpoint handlers
 
+use aide::transform::TransformOperation;
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
 };
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use rand::RngCore;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tracing::info;
 use uuid::Uuid;
+use validator::Validate;
 
 use crate::{
-    api::v1::{
-        ApiV1Error, V1State,
-        extractors::{AdminSession, AuthenticatedSession},
+    api::{
+        utils::{MAX_PAGE_SIZE, Page, Pagination},
+        v1::{
+            ApiV1Error, V1State, V1StateInner,
+            auth::RevokeAllSessionsResponse,
+            extractors::{
+                Authenticated, AuthenticatedSession, RequirePermission, ValidatedJson, permission,
+            },
+            notify,
+            role::ensure_can_manage,
+        },
     },
-    models::{User, UserCreate},
+    db::interface::DatabaseError,
+    models::{
+        MAX_DISPLAY_NAME_LENGTH, MAX_EMAIL_LENGTH, RegistrationMode, Sort, SortDirection, User,
+        UserCreate, UserCursor, UserDeletionStrategy, UserFilter, UserListQuery, UserMerge,
+        UserNote, UserSort, UserStatus, UserUpdate, new_uuid, normalize_email, not_blank,
+        valid_email,
+    },
+    webhooks::WebhookEvent,
 };
 
+/// Query parameters for listing users
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct UserListParams {
+    /// Field by which to sort the users (`created_at`, `email`, or `display_name`), optionally
+    /// followed by `:asc` or `:desc`. Defaults to `created_at:asc`.
+    #[serde(default)]
+    pub sort: Sort<UserSort>,
+    /// Only list users whose email address contains this text, ignoring case
+    #[serde(rename = "filter[email]")]
+    pub email: Option<String>,
+    /// Only list users whose display name contains this text, ignoring case
+    #[serde(rename = "filter[display_name]")]
+    pub display_name: Option<String>,
+    /// Only list users with this status
+    #[serde(rename = "filter[status]")]
+    pub status: Option<UserStatus>,
+}
+
+impl From<UserListParams> for UserListQuery {
+    fn from(params: UserListParams) -> Self {
+        UserListQuery::sorted(params.sort).with_filter(UserFilter {
+            email: params.email,
+            display_name: params.display_name,
+            status: params.status,
+        })
+    }
+}
+
+/// Lists the users matching the given filters, one page at a time.
+pub async fn list_users(
+    RequirePermission { .. }: RequirePermission<permission::UsersRead>,
+    Query(params): Query<UserListParams>,
+    pagination: Pagination,
+    State(state): State<V1State>,
+) -> Result<Json<Page<User>>, ApiV1Error> {
+    let query = UserListQuery::from(params);
+    // A cursor only makes sense in a list with the same order
+    let after = match pagination.after::<(SortDirection, UserCursor)>()? {
+        Some((direction, cursor))
+            if cursor.sort() != query.sort.field || direction != query.sort.direction =>
+        {
+            return Err(ApiV1Error::InvalidCursor);
+        }
+        after => after.map(|(_, cursor)| cursor),
+    };
+    let list = state
+        .db
+        .list_users(&query, after.as_ref(), pagination.limit)
+        .await?;
+    let next = list.next.map(|cursor| (query.sort.direction, cursor));
+    Ok(Json(Page::new(list.users, list.total, next.as_ref())))
+}
+
+/// Maximum number of users returned by a search if the client doesn't ask for a specific number
+const DEFAULT_SEARCH_LIMIT: u32 = 20;
+
+/// Query parameters for searching users
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct UserSearchParams {
+    /// Words to search for. Users match if their email address or display name contains a word
+    /// starting with each of them.
+    pub q: String,
+    /// Maximum number of users to return, up to 200. Defaults to 20.
+    pub limit: Option<u32>,
+}
+
+/// Searches for users by email address and display name, best matches first.
+pub async fn search_users(
+    RequirePermission { .. }: RequirePermission<permission::UsersRead>,
+    Query(params): Query<UserSearchParams>,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<User>>, ApiV1Error> {
+    if params.q.trim().is_empty() {
+        return Err(ApiV1Error::InvalidField("q", "must not be blank"));
+    }
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .clamp(1, MAX_PAGE_SIZE);
+    Ok(Json(state.db.search_users(&params.q, limit).await?))
+}
+
 pub async fn get_user(
-    AdminSession { .. }: AdminSession,
+    RequirePermission { .. }: RequirePermission<permission::UsersRead>,
     Path(id): Path<Uuid>,
     State(state): State<V1State>,
 ) -> Result<Json<User>, ApiV1Error> {
-    let mut user = state.db.get_user_by_id(&id).await?;
-    user.fetch_passkeys(state.db.as_ref()).await?;
-    user.fetch_tags(state.db.as_ref()).await?;
+    let mut user = state.db.get_user_with_relations(&id).await?;
+    user.fetch_email_status(state.db.as_ref()).await?;
     Ok(Json(user))
 }
 
 pub async fn post_user(
-    AdminSession { .. }: AdminSession,
+    RequirePermission { .. }: RequirePermission<permission::UsersWrite>,
+    State(state): State<V1State>,
+    ValidatedJson(user): ValidatedJson<UserCreate>,
+) -> Result<Json<User>, ApiV1Error> {
+    if state.config.registration_mode == RegistrationMode::Closed {
+        return Err(ApiV1Error::RegistrationClosed);
+    }
+    let id = new_uuid();
+    let user = state
+        .db
+        .create_user(&id, &user)
+        .await
+        .map_err(map_email_conflict)?;
+    state.emit(WebhookEvent::UserCreated {
+        user_id: id,
+        email: user.email().to_string(),
+        display_name: user.display_name().to_string(),
+    });
+    Ok(Json(user))
+}
+
+/// Converts a uniqueness violation into [`ApiV1Error::EmailTaken`], since the email address is the
+/// only unique field which can be set on a user.
+fn map_email_conflict(error: DatabaseError) -> ApiV1Error {
+    match error {
+        DatabaseError::UniquenessViolation { .. } => ApiV1Error::EmailTaken,
+        error => error.into(),
+    }
+}
+
+/// Updates a user's details.
+pub async fn update_user(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::UsersWrite>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    ValidatedJson(update): ValidatedJson<UserUpdate>,
+) -> Result<Json<User>, ApiV1Error> {
+    ensure_can_manage(&state, &permissions, &id).await?;
+    if update.is_empty() {
+        return Ok(Json(state.db.get_user_by_id(&id).await?));
+    }
+    let user = state
+        .db
+        .update_user(&id, &update)
+        .await
+        .map_err(map_email_conflict)?;
+    info!(admin = %session.user_id, user = %id, "user updated");
+    Ok(Json(user))
+}
+
+pub fn update_user_docs(op: TransformOperation) -> TransformOperation {
+    op.description(
+        "Updates a user's details. Omitted fields are left unchanged. \
+        Responds with `409 Conflict` if the new email address belongs to another user.",
+    )
+}
+
+/// Deletes a user according to the instance's deletion strategy.
+pub async fn delete_user(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::UsersWrite>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    ensure_can_manage(&state, &permissions, &id).await?;
+    state.db.get_user_by_id(&id).await?;
+    delete_user_by_policy(&state, &id).await?;
+    info!(admin = %session.user_id, user = %id, "user deleted by administrator");
+    Ok(())
+}
+
+pub fn delete_user_docs(op: TransformOperation) -> TransformOperation {
+    op.description(
+        "Deletes a user. What happens depends on the instance's user deletion strategy. \
+        With the `delete` strategy, the user is removed along with their passkeys, tag \
+        memberships, sessions, known devices, and administrator notes about them; notes they \
+        wrote about other users are kept without an author. With the `anonymize` strategy, \
+        the user's record is kept but their email address and display name are replaced by \
+        placeholders, their passkeys and known devices are deleted, their sessions are \
+        revoked and stripped of IP addresses and user agents, and notes about them are \
+        redacted. \
+        Either way, the user is logged out everywhere immediately.",
+    )
+}
+
+/// Anonymizes a user regardless of the instance's deletion strategy, scrubbing their personal
+/// data while keeping their record, so that references to it and statistics remain intact.
+pub async fn anonymize_user(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::UsersWrite>,
+    Path(id): Path<Uuid>,
     State(state): State<V1State>,
-    Json(user): Json<UserCreate>,
 ) -> Result<Json<User>, ApiV1Error> {
-    let id = Uuid::new_v4();
-    Ok(Json(state.db.create_user(&id, &user).await?))
+    ensure_can_manage(&state, &permissions, &id).await?;
+    let user = state.db.anonymize_user(&id).await?;
+    state.sessions.invalidate_user(&id);
+    state.emit(WebhookEvent::UserDeleted { user_id: id });
+    info!(admin = %session.user_id, user = %id, "user anonymized by administrator");
+    Ok(Json(user))
+}
+
+pub fn anonymize_user_docs(op: TransformOperation) -> TransformOperation {
+    op.description(
+        "Anonymizes a user as an alternative to deleting them, regardless of the instance's user \
+        deletion strategy. The user's record is kept, so that references to it and statistics \
+        such as their creation and last login times remain intact, but their email address and \
+        display name are replaced by placeholders, their passkeys, known devices, API tokens, \
+        and federated identities are deleted, their sessions are revoked and stripped of IP \
+        addresses and user agents, and notes about them are redacted. Anonymizing a user again \
+        has no further effect.",
+    )
 }
 
 pub async fn get_current_user(
+    auth: Authenticated,
+    State(state): State<V1State>,
+) -> Result<Json<User>, ApiV1Error> {
+    let user = state.db.get_user_with_relations(auth.user_id()).await?;
+    Ok(Json(user))
+}
+
+/// Preferences which users can change for their own account
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserPreferencesUpdate {
+    /// Whether to email the user about logins to their account
+    pub login_notifications: Option<bool>,
+}
+
+pub async fn update_current_user_preferences(
     AuthenticatedSession(session): AuthenticatedSession,
     State(state): State<V1State>,
+    Json(preferences): Json<UserPreferencesUpdate>,
+) -> Result<Json<User>, ApiV1Error> {
+    let mut update = UserUpdate::new();
+    if let Some(login_notifications) = preferences.login_notifications {
+        update = update.with_login_notifications(login_notifications);
+    }
+    if update.is_empty() {
+        return Ok(Json(state.db.get_user_by_id(&session.user_id).await?));
+    }
+    Ok(Json(state.db.update_user(&session.user_id, &update).await?))
+}
+
+/// Profile details which users can change for their own account
+#[derive(Debug, Clone, Deserialize, JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct UserProfileUpdate {
+    /// New display name
+    #[validate(
+        custom(function = not_blank),
+        length(
+            max = MAX_DISPLAY_NAME_LENGTH,
+            message = "must be at most 100 characters long"
+        )
+    )]
+    pub display_name: Option<String>,
+    /// New email address, which replaces the current one once the user confirms that they own it
+    #[validate(
+        custom(function = valid_email),
+        length(max = MAX_EMAIL_LENGTH, message = "must be at most 254 characters long")
+    )]
+    #[schemars(email)]
+    pub email: Option<String>,
+}
+
+/// Time within which a change of email address must be confirmed
+const EMAIL_CHANGE_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// Updates the current user's display name and/or requests a change of their email address. The
+/// email address isn't changed right away: a link which confirms the change is sent to the new
+/// address, and the change is applied by [`confirm_email_change`] once the user follows it.
+pub async fn update_current_user(
+    auth: Authenticated,
+    State(state): State<V1State>,
+    ValidatedJson(profile): ValidatedJson<UserProfileUpdate>,
+) -> Result<Json<User>, ApiV1Error> {
+    let mut user = state.db.get_user_by_id(auth.user_id()).await?;
+    let email = profile
+        .email
+        .map(|email| normalize_email(&email))
+        .filter(|email| email != user.email());
+    if let Some(email) = &email {
+        check_email_change_allowed(&state, email).await?;
+    }
+    if let Some(display_name) = profile.display_name {
+        let update = UserUpdate::new().with_display_name(display_name.trim().to_string());
+        user = state.db.update_user(auth.user_id(), &update).await?;
+    }
+    if let Some(email) = email {
+        let mut token = [0u8; 32]; // 256 bits
+        rand::rng().fill_bytes(&mut token);
+        state
+            .db
+            .create_email_change(user.id(), &email, &blake3::hash(&token).into())
+            .await?;
+        notify::email_change(&state, &user, &email, &BASE64_URL_SAFE_NO_PAD.encode(token));
+        info!(user = %user.id(), "user requested to change their email address");
+    }
+    Ok(Json(user))
+}
+
+pub fn update_current_user_docs(op: TransformOperation) -> TransformOperation {
+    op.description(
+        "Updates the current user's display name and/or requests a change of their email address. \
+        A new email address only replaces the current one once the user follows the confirmation \
+        link sent to it within 24 hours, so the response still contains the current address. \
+        Requesting another change replaces the pending one. \
+        Responds with `409 Conflict` if the new email address belongs to another user, unless \
+        account enumeration protection is enabled, in which case the confirmation fails instead. \
+        Responds with `403 Forbidden` if users may not register with addresses at its domain.",
+    )
+}
+
+/// Checks that the current user may change their email address to the given one: users may
+/// register with addresses at its domain, it isn't taken, and the confirmation can be emailed.
+///
+/// With [enumeration protection][crate::api::EnumerationProtection], whether the address is taken
+/// isn't checked, so that the response doesn't tell. A change to a taken address can't be
+/// confirmed, and only the address's owner can try.
+async fn check_email_change_allowed(state: &V1StateInner, email: &str) -> Result<(), ApiV1Error> {
+    if !state.config.is_email_domain_allowed(email) {
+        return Err(ApiV1Error::EmailDomainNotAllowed);
+    }
+    if state.options.mailer.is_none() {
+        return Err(ApiV1Error::EmailDisabled);
+    }
+    if state.options.enumeration_protection.is_some() {
+        return Ok(());
+    }
+    match state.db.get_user_by_email(email).await {
+        Ok(_) => Err(ApiV1Error::EmailTaken),
+        Err(DatabaseError::NotFound) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct EmailChangeConfirmation {
+    /// Confirmation token from the link sent to the new email address
+    pub token: String,
+}
+
+/// Applies the change of email address confirmed by the given token, marking the new address as
+/// verified. Used by the link sent by [`update_current_user`], which may be opened in a browser in
+/// which the user isn't logged in, so the token is all that's needed.
+pub async fn confirm_email_change(
+    State(state): State<V1State>,
+    Json(request): Json<EmailChangeConfirmation>,
+) -> Result<(), ApiV1Error> {
+    let Ok(token) = BASE64_URL_SAFE_NO_PAD.decode(&request.token) else {
+        return Err(ApiV1Error::NotFound);
+    };
+    let user = state
+        .db
+        .confirm_email_change(&blake3::hash(&token).into(), EMAIL_CHANGE_MAX_AGE)
+        .await
+        .map_err(map_email_conflict)?;
+    info!(user = %user.id(), "user changed their email address");
+    Ok(())
+}
+
+pub fn confirm_email_change_docs(op: TransformOperation) -> TransformOperation {
+    op.description(
+        "Changes a user's email address to the one whose ownership the given token confirms. \
+        Tokens can only be used once, and expire 24 hours after the change was requested. \
+        Responds with `404 Not Found` if the token is invalid, used, or expired, and with \
+        `409 Conflict` if another user took the address in the meantime.",
+    )
+}
+
+/// Fetches every revision of the administrator notes on a user, newest first.
+pub async fn get_user_notes(
+    RequirePermission { .. }: RequirePermission<permission::UsersRead>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<UserNote>>, ApiV1Error> {
+    // Distinguish a missing user from a user without notes
+    state.db.get_user_by_id(&id).await?;
+    Ok(Json(state.db.get_user_notes(&id).await?))
+}
+
+/// New administrator notes on a user
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserNotesUpdate {
+    /// Text of the notes, replacing the current notes
+    pub body: String,
+}
+
+/// Replaces the administrator notes on a user. The previous notes are kept in the history.
+pub async fn put_user_notes(
+    RequirePermission { session, .. }: RequirePermission<permission::UsersWrite>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    Json(update): Json<UserNotesUpdate>,
+) -> Result<Json<UserNote>, ApiV1Error> {
+    state.db.get_user_by_id(&id).await?;
+    let note = UserNote {
+        id: new_uuid(),
+        user_id: id,
+        author_id: Some(session.user_id),
+        body: update.body,
+        created_at: chrono::Utc::now(),
+    };
+    Ok(Json(state.db.create_user_note(&note).await?))
+}
+
+/// Deletes or anonymizes the user with the given ID, according to the instance's
+/// [deletion strategy][crate::api::ApiOptions::user_deletion].
+async fn delete_user_by_policy(state: &V1StateInner, id: &Uuid) -> Result<(), ApiV1Error> {
+    match state.options.user_deletion {
+        UserDeletionStrategy::Delete => state.db.delete_user_by_id(id).await?,
+        UserDeletionStrategy::Anonymize => {
+            state.db.anonymize_user(id).await?;
+        }
+    }
+    state.sessions.invalidate_user(id);
+    state.emit(WebhookEvent::UserDeleted { user_id: *id });
+    info!(%id, strategy = ?state.options.user_deletion, "deleted user");
+    Ok(())
+}
+
+/// Request to merge a duplicate user into another user
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserMergeRequest {
+    /// ID of the user to keep, into which the duplicate is merged
+    pub into: Uuid,
+    /// Whether to only report what would be merged, without changing anything
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Merges a duplicate user into another user. The duplicate's passkeys, tags, sessions, devices,
+/// and notes are moved to the other user, and the duplicate is deleted. The remaining user's notes
+/// are replaced by a revision combining both users' notes and recording the merge.
+pub async fn merge_user(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::UsersWrite>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    Json(request): Json<UserMergeRequest>,
+) -> Result<Json<UserMerge>, ApiV1Error> {
+    ensure_can_manage(&state, &permissions, &id).await?;
+    ensure_can_manage(&state, &permissions, &request.into).await?;
+    if id == request.into {
+        return Err(ApiV1Error::MergeIntoSelf);
+    }
+    // Fetch the current notes now, since the merge interleaves both users' revisions
+    let mut notes = Vec::new();
+    if !request.dry_run {
+        for user_id in [&request.into, &id] {
+            if let Some(note) = state.db.get_user_notes(user_id).await?.into_iter().next() {
+                notes.push(note.body);
+            }
+        }
+    }
+    let merge = state
+        .db
+        .merge_users(&id, &request.into, request.dry_run)
+        .await?;
+    if !merge.dry_run {
+        // The duplicate's sessions now belong to the other user
+        state.sessions.invalidate_user(&id);
+        state.emit(WebhookEvent::UserDeleted { user_id: id });
+        info!(source = %id, target = %request.into, "merged duplicate user");
+        notes.push(format!(
+            "Merged duplicate user {} <{}> ({id}).",
+            merge.source.display_name(),
+            merge.source.email()
+        ));
+        let note = UserNote {
+            id: new_uuid(),
+            user_id: request.into,
+            author_id: Some(session.user_id),
+            body: notes.join("\n\n"),
+            created_at: chrono::Utc::now(),
+        };
+        state.db.create_user_note(&note).await?;
+    }
+    Ok(Json(merge))
+}
+
+/// Logs a user out everywhere by revoking all of their active sessions, e.g. after their device
+/// was lost or stolen.
+pub async fn logout_user_everywhere(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::UsersWrite>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<RevokeAllSessionsResponse>, ApiV1Error> {
+    ensure_can_manage(&state, &permissions, &id).await?;
+    // Distinguish a missing user from a user without sessions
+    state.db.get_user_by_id(&id).await?;
+    let revoked = state.db.revoke_sessions_by_user_id(&id).await?;
+    state.sessions.invalidate_user(&id);
+    info!(admin = %session.user_id, user = %id, revoked, "user logged out everywhere");
+    Ok(Json(RevokeAllSessionsResponse { revoked }))
+}
+
+/// Unlocks a user's account which was locked after repeated failed logins, before the lock ends.
+/// This also forgets the account's failed logins, so that it isn't locked again right away.
+pub async fn unlock_user(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::UsersWrite>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<User>, ApiV1Error> {
+    ensure_can_manage(&state, &permissions, &id).await?;
+    state.db.set_user_locked_until(&id, None).await?;
+    info!(admin = %session.user_id, user = %id, "user account unlocked");
+    Ok(Json(state.db.get_user_by_id(&id).await?))
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+pub struct UserStatusRequest {
+    /// New status of the user
+    pub status: UserStatus,
+    /// Why the status is changed, which is recorded in the user's notes
+    pub reason: String,
+}
+
+/// Suspends, deactivates, or reactivates a user's account. The change and its reason are added to
+/// the user's [notes][UserNote], and unless the account is reactivated, the user's sessions are
+/// revoked.
+pub async fn set_user_status(
+    RequirePermission {
+        session,
+        permissions,
+        ..
+    }: RequirePermission<permission::UsersWrite>,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    Json(request): Json<UserStatusRequest>,
 ) -> Result<Json<User>, ApiV1Error> {
-    let mut user = state.db.get_user_by_id(&session.user_id).await?;
-    user.fetch_passkeys(state.db.as_ref()).await?;
-    user.fetch_tags(state.db.as_ref()).await?;
+    ensure_can_manage(&state, &permissions, &id).await?;
+    let reason = request.reason.trim();
+    if reason.is_empty() {
+        return Err(ApiV1Error::InvalidField("reason", "must not be empty"));
+    }
+    if id == session.user_id && request.status != UserStatus::Active {
+        return Err(ApiV1Error::InvalidField(
+            "status",
+            "cannot suspend or deactivate yourself",
+        ));
+    }
+    let user = state.db.set_user_status(&id, request.status).await?;
+    if request.status != UserStatus::Active {
+        state.db.revoke_sessions_by_user_id(&id).await?;
+        state.sessions.invalidate_user(&id);
+    }
+    let change = match request.status {
+        UserStatus::Active => "reactivated",
+        UserStatus::Suspended => "suspended",
+        UserStatus::Deactivated => "deactivated",
+    };
+    info!(admin = %session.user_id, user = %id, status = ?request.status, "user account {change}");
+
+    // The newest revision of the notes holds the current notes, so keep them above the change
+    let mut notes: Vec<String> = state
+        .db
+        .get_user_notes(&id)
+        .await?
+        .into_iter()
+        .next()
+        .map(|note| note.body)
+        .into_iter()
+        .collect();
+    notes.push(format!("Account {change}: {reason}"));
+    let note = UserNote {
+        id: new_uuid(),
+        user_id: id,
+        author_id: Some(session.user_id),
+        body: notes.join("\n\n"),
+        created_at: chrono::Utc::now(),
+    };
+    state.db.create_user_note(&note).await?;
     Ok(Json(user))
 }
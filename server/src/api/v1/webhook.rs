@@ -0,0 +1,120 @@
+//! # v1 webhook API endpoint handlers
+
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use chrono::Utc;
+use rand::RngCore;
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    api::v1::{ApiV1Error, V1State, extractors::AdminSession},
+    models::{ViaJson, Webhook, WebhookEventType, WebhookUpdate, new_uuid},
+};
+
+/// Request to register a webhook
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookRequest {
+    /// HTTP(S) URL to which events are delivered
+    pub url: String,
+    /// Types of events to deliver. If omitted or empty, all events are delivered.
+    #[serde(default)]
+    pub events: Vec<WebhookEventType>,
+}
+
+/// A newly registered webhook, along with its secret
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    /// Key with which deliveries are signed. It can't be retrieved again later.
+    pub secret: String,
+}
+
+/// Checks that `url` is an absolute HTTP(S) URL.
+fn validate_url(url: &str) -> Result<(), ApiV1Error> {
+    match Url::parse(url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => Ok(()),
+        _ => Err(ApiV1Error::InvalidField(
+            "url",
+            "must be an absolute HTTP or HTTPS URL",
+        )),
+    }
+}
+
+pub async fn list_webhooks(
+    AdminSession { .. }: AdminSession,
+    State(state): State<V1State>,
+) -> Result<Json<Vec<Webhook>>, ApiV1Error> {
+    Ok(Json(state.db.list_webhooks().await?))
+}
+
+/// Registers a webhook. The response contains the secret with which deliveries are signed, which
+/// can't be retrieved again.
+pub async fn create_webhook(
+    AdminSession(session): AdminSession,
+    State(state): State<V1State>,
+    Json(request): Json<WebhookRequest>,
+) -> Result<Json<CreatedWebhook>, ApiV1Error> {
+    validate_url(&request.url)?;
+    let mut secret = [0u8; 32];
+    rand::rng().fill_bytes(&mut secret);
+    let secret = hex::encode(secret);
+    let now = Utc::now();
+    let webhook = Webhook {
+        id: new_uuid(),
+        url: request.url,
+        events: ViaJson(request.events),
+        secret: ViaJson(secret.clone()),
+        enabled: true,
+        created_at: now,
+        updated_at: now,
+    };
+    let webhook = state.db.create_webhook(&webhook).await?;
+    info!(webhook = %webhook.id, url = %webhook.url, admin = %session.user_id, "webhook registered");
+    Ok(Json(CreatedWebhook { webhook, secret }))
+}
+
+pub async fn get_webhook(
+    AdminSession { .. }: AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<Json<Webhook>, ApiV1Error> {
+    Ok(Json(state.db.get_webhook_by_id(&id).await?))
+}
+
+/// Updates a webhook.
+pub async fn update_webhook(
+    AdminSession(session): AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+    Json(update): Json<WebhookUpdate>,
+) -> Result<Json<Webhook>, ApiV1Error> {
+    if update.is_empty() {
+        return Ok(Json(state.db.get_webhook_by_id(&id).await?));
+    }
+    if let Some(url) = &update.url {
+        validate_url(url)?;
+    }
+    let webhook = state.db.update_webhook(&id, &update).await?;
+    info!(webhook = %id, admin = %session.user_id, "webhook updated");
+    Ok(Json(webhook))
+}
+
+/// Removes a webhook. Deliveries which are already being retried are still attempted.
+pub async fn delete_webhook(
+    AdminSession(session): AdminSession,
+    Path(id): Path<Uuid>,
+    State(state): State<V1State>,
+) -> Result<(), ApiV1Error> {
+    state.db.delete_webhook_by_id(&id).await?;
+    info!(webhook = %id, admin = %session.user_id, "webhook removed");
+    Ok(())
+}
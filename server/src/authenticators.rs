@@ -0,0 +1,291 @@
+//! # Authenticator names
+//!
+//! Passkeys registered without a display name are given a default one, so that a user's list of
+//! passkeys tells them apart. The name comes from the model of the authenticator which created
+//! the passkey, looked up by the AAGUID which it reports when registering, e.g. "iCloud Keychain"
+//! or "Windows Hello". Many authenticators report an all-zero AAGUID, in which case the name
+//! describes the browser and platform from which the passkey was registered instead, e.g. "Chrome
+//! on Windows".
+//!
+//! The AAGUID is read from the authenticator data without verifying it, so it only serves as a
+//! hint for the name. Verified AAGUIDs come from the [`PasskeyAttestation`][1].
+//!
+//! [1]: crate::models::PasskeyAttestation
+
+use serde_cbor_2::Value;
+use uuid::{Uuid, uuid};
+use webauthn_rs::prelude::RegisterPublicKeyCredential;
+
+/// Names of well-known authenticator models and passkey providers, by AAGUID
+const KNOWN_AUTHENTICATORS: &[(Uuid, &str)] = &[
+    (
+        uuid!("fbfc3007-154e-4ecc-8c0b-6e020557d7bd"),
+        "iCloud Keychain",
+    ),
+    (
+        uuid!("dd4ec289-e01d-41c9-bb89-70fa845d4bf2"),
+        "iCloud Keychain (Managed)",
+    ),
+    (
+        uuid!("ea9b8d66-4d01-1d21-3ce4-b6b48cb575d4"),
+        "Google Password Manager",
+    ),
+    (
+        uuid!("adce0002-35bc-c60a-648b-0b25f1f05503"),
+        "Chrome on Mac",
+    ),
+    (
+        uuid!("08987058-cadc-4b81-b6e1-30de50dcbe96"),
+        "Windows Hello",
+    ),
+    (
+        uuid!("9ddd1817-af5a-4672-a2b9-3e3dd95000a9"),
+        "Windows Hello",
+    ),
+    (
+        uuid!("6028b017-b1d4-4c02-b4b3-afcdafc96bb2"),
+        "Windows Hello",
+    ),
+    (
+        uuid!("53414d53-554e-4700-0000-000000000000"),
+        "Samsung Pass",
+    ),
+    (uuid!("bada5566-a7aa-401f-bd96-45619a55120d"), "1Password"),
+    (uuid!("d548826e-79b4-db40-a3d8-11116f7e8349"), "Bitwarden"),
+    (uuid!("531126d6-e717-415c-9320-3d9aa6981239"), "Dashlane"),
+    (uuid!("0ea242b4-43c4-4a1b-8b17-dd6d0b6baec6"), "Keeper"),
+    (uuid!("b84e4048-15dc-4dd0-8640-f4f60813c8af"), "NordPass"),
+    (
+        uuid!("cb69481e-8ff7-4039-93ec-0a2729a154a8"),
+        "YubiKey 5 Series",
+    ),
+    (
+        uuid!("ee882879-721c-4913-9775-3dfcce97072a"),
+        "YubiKey 5 Series",
+    ),
+    (
+        uuid!("fa2b99dc-9e39-4257-8f92-4a30d23c4118"),
+        "YubiKey 5 Series with NFC",
+    ),
+    (
+        uuid!("2fc0579f-8113-47ea-b116-bb5a8db9202a"),
+        "YubiKey 5 Series with NFC",
+    ),
+    (uuid!("c5ef55ff-ad9a-4b9f-b580-adebafe026d0"), "YubiKey 5Ci"),
+    (
+        uuid!("d8522d9f-575b-4866-88a9-ba99fa02f35b"),
+        "YubiKey Bio Series",
+    ),
+    (
+        uuid!("149a2021-8ef6-4133-96b8-81f8d5b7f1f5"),
+        "Security Key by Yubico with NFC",
+    ),
+    (
+        uuid!("a4e9fc6d-4cbe-4758-b8ba-37598bb5bbaa"),
+        "Security Key NFC by Yubico",
+    ),
+];
+
+/// Browsers, by a token which identifies them in a `User-Agent` header. Browsers based on Chrome
+/// also send its token, and most browsers send Safari's, so the first match wins.
+const BROWSERS: &[(&str, &str)] = &[
+    ("Edg/", "Edge"),
+    ("EdgA/", "Edge"),
+    ("EdgiOS/", "Edge"),
+    ("OPR/", "Opera"),
+    ("SamsungBrowser/", "Samsung Internet"),
+    ("Firefox/", "Firefox"),
+    ("FxiOS/", "Firefox"),
+    ("Chrome/", "Chrome"),
+    ("CriOS/", "Chrome"),
+    ("Safari/", "Safari"),
+];
+
+/// Platforms, by a token which identifies them in a `User-Agent` header. iOS and Android also
+/// send the tokens of macOS and Linux respectively, so the first match wins.
+const PLATFORMS: &[(&str, &str)] = &[
+    ("iPhone", "iPhone"),
+    ("iPad", "iPad"),
+    ("Android", "Android"),
+    ("CrOS", "ChromeOS"),
+    ("Windows", "Windows"),
+    ("Macintosh", "macOS"),
+    ("Linux", "Linux"),
+];
+
+/// Returns the default display name for a passkey registered with the given credential by a
+/// client with the given `User-Agent` header, or [`None`] if nothing is known about either.
+#[must_use]
+pub fn default_passkey_name(
+    credential: &RegisterPublicKeyCredential,
+    user_agent: Option<&str>,
+) -> Option<String> {
+    reported_aaguid(credential)
+        .and_then(|aaguid| authenticator_name(&aaguid))
+        .map(str::to_string)
+        .or_else(|| client_name(user_agent?))
+}
+
+/// Returns the name of the authenticator model with the given AAGUID, if it is well known.
+#[must_use]
+pub fn authenticator_name(aaguid: &Uuid) -> Option<&'static str> {
+    KNOWN_AUTHENTICATORS
+        .iter()
+        .find(|(known, _)| known == aaguid)
+        .map(|&(_, name)| name)
+}
+
+/// Returns the AAGUID which the authenticator reported in the given registration credential, or
+/// [`None`] if it didn't report one or reported the all-zero AAGUID.
+#[must_use]
+pub fn reported_aaguid(credential: &RegisterPublicKeyCredential) -> Option<Uuid> {
+    // Offsets within the authenticator data: a 32-byte RP ID hash, then a flags byte and a 4-byte
+    // signature counter, followed by the attested credential data, which starts with the AAGUID
+    const FLAGS: usize = 32;
+    const AAGUID: usize = 37;
+    const ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+    let Ok(Value::Map(object)) = serde_cbor_2::from_slice(&credential.response.attestation_object)
+    else {
+        return None;
+    };
+    let Some(Value::Bytes(auth_data)) = object.get(&Value::Text("authData".to_string())) else {
+        return None;
+    };
+    if auth_data.get(FLAGS)? & ATTESTED_CREDENTIAL_DATA == 0 {
+        return None;
+    }
+    let aaguid = Uuid::from_slice(auth_data.get(AAGUID..AAGUID + 16)?).ok()?;
+    (!aaguid.is_nil()).then_some(aaguid)
+}
+
+/// Describes the browser and platform of the client with the given `User-Agent` header, e.g.
+/// "Firefox on Linux", or returns [`None`] if neither is recognized.
+#[must_use]
+pub fn client_name(user_agent: &str) -> Option<String> {
+    let find = |tokens: &[(&str, &'static str)]| {
+        tokens
+            .iter()
+            .find(|(token, _)| user_agent.contains(token))
+            .map(|&(_, name)| name)
+    };
+    match (find(BROWSERS), find(PLATFORMS)) {
+        (Some(browser), Some(platform)) => Some(format!("{browser} on {platform}")),
+        (Some(name), None) | (None, Some(name)) => Some(name.to_string()),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+
+    use super::*;
+
+    /// Returns a registration credential whose attestation object holds the given authenticator
+    /// data.
+    fn credential_with_auth_data(auth_data: Vec<u8>) -> RegisterPublicKeyCredential {
+        let object = Value::Map(BTreeMap::from([
+            (
+                Value::Text("fmt".to_string()),
+                Value::Text("none".to_string()),
+            ),
+            (
+                Value::Text("attStmt".to_string()),
+                Value::Map(BTreeMap::new()),
+            ),
+            (Value::Text("authData".to_string()), Value::Bytes(auth_data)),
+        ]));
+        let attestation_object =
+            BASE64_URL_SAFE_NO_PAD.encode(serde_cbor_2::to_vec(&object).unwrap());
+        serde_json::from_value(serde_json::json!({
+            "id": "AA",
+            "rawId": "AA",
+            "type": "public-key",
+            "extensions": {},
+            "response": {
+                "attestationObject": attestation_object,
+                "clientDataJSON": "",
+            },
+        }))
+        .unwrap()
+    }
+
+    /// Returns authenticator data with attested credential data from the given AAGUID.
+    fn auth_data(aaguid: Uuid) -> Vec<u8> {
+        let mut data = vec![0; 32];
+        data.push(0x45);
+        data.extend([0, 0, 0, 1]);
+        data.extend(aaguid.as_bytes());
+        data.extend([0, 1, 0xff]);
+        data
+    }
+
+    #[test]
+    fn test_reported_aaguid() {
+        let yubikey = uuid!("cb69481e-8ff7-4039-93ec-0a2729a154a8");
+        let credential = credential_with_auth_data(auth_data(yubikey));
+        assert_eq!(reported_aaguid(&credential), Some(yubikey));
+        assert_eq!(
+            default_passkey_name(&credential, Some("Mozilla/5.0 (X11; Linux x86_64)")).as_deref(),
+            Some("YubiKey 5 Series"),
+        );
+
+        // Without attested credential data, or with the all-zero AAGUID, there's no AAGUID
+        let mut data = auth_data(yubikey);
+        data[32] = 0x05;
+        assert_eq!(reported_aaguid(&credential_with_auth_data(data)), None);
+        let credential = credential_with_auth_data(auth_data(Uuid::nil()));
+        assert_eq!(reported_aaguid(&credential), None);
+        assert_eq!(
+            reported_aaguid(&credential_with_auth_data(vec![0; 40])),
+            None
+        );
+
+        // Which falls back to the client's name, if there is one
+        let user_agent = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
+            (KHTML, like Gecko) Chrome/141.0.0.0 Safari/537.36";
+        assert_eq!(
+            default_passkey_name(&credential, Some(user_agent)).as_deref(),
+            Some("Chrome on Windows"),
+        );
+        assert_eq!(default_passkey_name(&credential, None), None);
+    }
+
+    #[test]
+    fn test_client_name() {
+        let cases = [
+            (
+                "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 \
+                (KHTML, like Gecko) Version/18.5 Safari/605.1.15",
+                Some("Safari on macOS"),
+            ),
+            (
+                "Mozilla/5.0 (iPhone; CPU iPhone OS 18_5 like Mac OS X) AppleWebKit/605.1.15 \
+                (KHTML, like Gecko) Version/18.5 Mobile/15E148 Safari/604.1",
+                Some("Safari on iPhone"),
+            ),
+            (
+                "Mozilla/5.0 (Linux; Android 10; K) AppleWebKit/537.36 (KHTML, like Gecko) \
+                Chrome/141.0.0.0 Mobile Safari/537.36",
+                Some("Chrome on Android"),
+            ),
+            (
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
+                (KHTML, like Gecko) Chrome/141.0.0.0 Safari/537.36 Edg/141.0.0.0",
+                Some("Edge on Windows"),
+            ),
+            (
+                "Mozilla/5.0 (X11; Linux x86_64; rv:143.0) Gecko/20100101 Firefox/143.0",
+                Some("Firefox on Linux"),
+            ),
+            ("Mozilla/5.0 (X11; CrOS x86_64 14541.0.0)", Some("ChromeOS")),
+            ("curl/8.5.0", None),
+        ];
+        for (user_agent, expected) in cases {
+            assert_eq!(client_name(user_agent).as_deref(), expected, "{user_agent}");
+        }
+    }
+}
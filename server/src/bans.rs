@@ -0,0 +1,216 @@
+//! # IP ban list
+//!
+//! The [`IpBanList`] decides whether requests from a client's IP address are rejected. Bans are
+//! stored in the database, either added by administrators or automatically after too many failed
+//! logins from the same address (see [`AutoBanOptions`]).
+//!
+//! Checking a ban on every request must be cheap, so the list keeps an in-memory copy of the
+//! active bans. The copy is refreshed whenever this instance changes a ban, and periodically to
+//! pick up changes made by other instances.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use ipnet::IpNet;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::{
+    db::interface::{DatabaseClient, DatabaseError},
+    models::{IpBan, IpBanSource, new_uuid},
+};
+
+/// Maximum age of the in-memory copy of the bans before it is reloaded from the database
+const CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Options for automatically banning addresses from which logins repeatedly fail
+#[derive(Debug, Clone)]
+pub struct AutoBanOptions {
+    /// Number of failed logins from the same address within [`window`][Self::window] which
+    /// triggers a ban
+    pub threshold: usize,
+    /// Window in which failed logins are counted
+    pub window: Duration,
+    /// How long automatic bans last
+    pub duration: Duration,
+}
+
+impl Default for AutoBanOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 20,
+            window: Duration::from_secs(15 * 60),
+            duration: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+/// # IP ban list
+///
+/// See the [module-level documentation][self] for details.
+pub struct IpBanList {
+    db: Arc<dyn DatabaseClient>,
+    auto_ban: Option<AutoBanOptions>,
+    cache: RwLock<Cache>,
+    /// Times of recent failed logins, by address
+    failures: Mutex<HashMap<IpAddr, VecDeque<Instant>>>,
+}
+
+/// Active bans, with their expiration times
+type ActiveBans = Arc<[(IpNet, Option<DateTime<Utc>>)]>;
+
+/// In-memory copy of the active bans
+#[derive(Default)]
+struct Cache {
+    /// Incremented whenever the bans change, so that a load which started before the change
+    /// doesn't overwrite the copy with stale data
+    generation: u64,
+    /// Bans and the time at which they were loaded
+    bans: Option<(Instant, ActiveBans)>,
+}
+
+impl IpBanList {
+    /// Creates a ban list backed by the given database. Addresses are only banned automatically
+    /// if `auto_ban` is given.
+    #[must_use]
+    pub fn new(db: Arc<dyn DatabaseClient>, auto_ban: Option<AutoBanOptions>) -> Self {
+        Self {
+            db,
+            auto_ban,
+            cache: RwLock::default(),
+            failures: Mutex::default(),
+        }
+    }
+
+    /// Returns whether the given address is in a banned network.
+    pub async fn is_banned(&self, ip: IpAddr) -> Result<bool, DatabaseError> {
+        let now = Utc::now();
+        Ok(self.bans().await?.iter().any(|(network, expires_at)| {
+            network.contains(&ip) && expires_at.is_none_or(|expires_at| expires_at > now)
+        }))
+    }
+
+    /// Returns the active bans, loading them from the database if the in-memory copy is missing
+    /// or outdated.
+    async fn bans(&self) -> Result<ActiveBans, DatabaseError> {
+        let generation = {
+            let cache = self.cache.read().unwrap();
+            if let Some((loaded_at, bans)) = &cache.bans
+                && loaded_at.elapsed() < CACHE_TTL
+            {
+                return Ok(Arc::clone(bans));
+            }
+            cache.generation
+        };
+        let bans: ActiveBans = self
+            .db
+            .list_ip_bans()
+            .await?
+            .into_iter()
+            .filter_map(|ban| match ban.network.parse::<IpNet>() {
+                Ok(network) => Some((network, ban.expires_at)),
+                Err(err) => {
+                    error!(%err, network = %ban.network, "ignoring ban with invalid network");
+                    None
+                }
+            })
+            .collect();
+        let mut cache = self.cache.write().unwrap();
+        if cache.generation == generation {
+            cache.bans = Some((Instant::now(), Arc::clone(&bans)));
+        }
+        Ok(bans)
+    }
+
+    /// Discards the in-memory copy of the bans, so that it is reloaded on the next check.
+    fn invalidate(&self) {
+        let mut cache = self.cache.write().unwrap();
+        cache.generation += 1;
+        cache.bans = None;
+    }
+
+    /// Bans the given network, replacing any existing ban for it. The ban is permanent unless
+    /// `expires_at` is given.
+    pub async fn ban(
+        &self,
+        network: IpNet,
+        reason: String,
+        source: IpBanSource,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<IpBan, DatabaseError> {
+        let ban = IpBan {
+            id: new_uuid(),
+            // Normalize e.g. `192.0.2.1/24` to `192.0.2.0/24`
+            network: network.trunc().to_string(),
+            reason,
+            source,
+            created_at: Utc::now(),
+            expires_at,
+        };
+        let ban = self.db.create_ip_ban(&ban).await;
+        self.invalidate();
+        ban
+    }
+
+    /// Lifts the ban with the given UUID.
+    pub async fn unban(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = self.db.delete_ip_ban_by_id(id).await;
+        self.invalidate();
+        result
+    }
+
+    /// Records a failed login from the given address, banning it in the background if the
+    /// [automatic ban threshold][AutoBanOptions::threshold] is reached.
+    pub fn record_failure(self: &Arc<Self>, ip: IpAddr) {
+        let Some(options) = &self.auto_ban else {
+            return;
+        };
+        let now = Instant::now();
+        {
+            let mut failures = self.failures.lock().unwrap();
+            // Forget about old failures so memory use stays bounded
+            failures.retain(|_, times| {
+                while times
+                    .front()
+                    .is_some_and(|&time| now - time > options.window)
+                {
+                    times.pop_front();
+                }
+                !times.is_empty()
+            });
+            let times = failures.entry(ip).or_default();
+            times.push_back(now);
+            if times.len() < options.threshold {
+                return;
+            }
+            failures.remove(&ip);
+        }
+
+        warn!(%ip, "banning address after repeated failed logins");
+        let list = Arc::clone(self);
+        let reason = format!(
+            "{} failed logins within {} seconds",
+            options.threshold,
+            options.window.as_secs()
+        );
+        let expires_at = Utc::now() + options.duration;
+        tokio::spawn(async move {
+            let result = list
+                .ban(
+                    IpNet::from(ip),
+                    reason,
+                    IpBanSource::Automatic,
+                    Some(expires_at),
+                )
+                .await;
+            if let Err(err) = result {
+                error!(%err, %ip, "failed to ban address");
+            }
+        });
+    }
+}
@@ -0,0 +1,446 @@
+//! # Load generator
+//!
+//! This binary sends a configurable mix of requests to a running IAM instance from many concurrent
+//! workers, then reports throughput and latency percentiles for each kind of request. It's meant
+//! to help operators size deployments.
+//!
+//! It is configured using environment variables:
+//!
+//! | Variable                | Description                                               | Default                       |
+//! |-------------------------|-----------------------------------------------------------|-------------------------------|
+//! | `LOADGEN_URL`           | Base URL of the instance, including any base path         | (required)                    |
+//! | `LOADGEN_CONCURRENCY`   | Number of concurrent workers                              | `16`                          |
+//! | `LOADGEN_DURATION`      | Test duration in seconds                                  | `30`                          |
+//! | `LOADGEN_MIX`           | Relative weights of each operation                        | `login=1,session=8,admin=1`   |
+//! | `LOADGEN_LOGIN_EMAIL`   | Email address to log in as                                | (discoverable login)          |
+//! | `LOADGEN_SESSION`       | Value of a user's `session_id` cookie                     | (none)                        |
+//! | `LOADGEN_ADMIN_SESSION` | Value of an administrator session's `session_id` cookie   | (none)                        |
+//!
+//! The operations are:
+//! - `login`: starts a passkey login, which looks up the user's passkeys and stores a challenge.
+//!   Finishing the login requires the user's authenticator, so it isn't simulated.
+//! - `session`: checks the session given by `LOADGEN_SESSION`.
+//! - `admin`: reads the full profile of the administrator given by `LOADGEN_ADMIN_SESSION`,
+//!   including their passkeys and tags, as the admin UI does.
+//!
+//! Operations with a non-zero weight must have the sessions they need configured. Session cookie
+//! values can be copied from a browser which is logged in to the instance.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    process::ExitCode,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use reqwest::{Client, RequestBuilder, header::COOKIE};
+use serde_json::json;
+use tracing::{error, info};
+
+mod vars {
+    pub const URL: &str = "LOADGEN_URL";
+    pub const CONCURRENCY: &str = "LOADGEN_CONCURRENCY";
+    pub const DURATION: &str = "LOADGEN_DURATION";
+    pub const MIX: &str = "LOADGEN_MIX";
+    pub const LOGIN_EMAIL: &str = "LOADGEN_LOGIN_EMAIL";
+    pub const SESSION: &str = "LOADGEN_SESSION";
+    pub const ADMIN_SESSION: &str = "LOADGEN_ADMIN_SESSION";
+}
+
+mod defaults {
+    pub const CONCURRENCY: usize = 16;
+    pub const DURATION_SECS: u64 = 30;
+    pub const MIX: &str = "login=1,session=8,admin=1";
+}
+
+/// Name of the cookie which holds the session ID
+const SESSION_ID_COOKIE: &str = "session_id";
+
+/// Percentiles included in the report
+const PERCENTILES: [f64; 4] = [0.5, 0.9, 0.99, 1.0];
+
+/// Kind of request sent by a worker
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Operation {
+    Login,
+    Session,
+    Admin,
+}
+
+impl Operation {
+    const ALL: [Operation; 3] = [Self::Login, Self::Session, Self::Admin];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Login => "login",
+            Self::Session => "session",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+impl FromStr for Operation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|op| op.name() == s)
+            .ok_or_else(|| format!("unknown operation `{s}`"))
+    }
+}
+
+/// Relative weights of each operation
+#[derive(Debug, Clone)]
+struct Mix(Vec<(Operation, u32)>);
+
+impl Mix {
+    fn total(&self) -> u32 {
+        self.0.iter().map(|(_, weight)| weight).sum()
+    }
+
+    fn weight(&self, op: Operation) -> u32 {
+        self.0
+            .iter()
+            .filter(|(other, _)| *other == op)
+            .map(|(_, weight)| weight)
+            .sum()
+    }
+
+    /// Picks an operation at random, according to the weights.
+    fn choose(&self, rng: &mut impl Rng) -> Operation {
+        let mut n = rng.random_range(0..self.total());
+        for &(op, weight) in &self.0 {
+            if n < weight {
+                return op;
+            }
+            n -= weight;
+        }
+        unreachable!("random number is less than the total weight")
+    }
+}
+
+impl FromStr for Mix {
+    type Err = String;
+
+    /// Parses a mix of the form `login=1,session=8,admin=1`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mix = s
+            .split(',')
+            .map(|entry| {
+                let (op, weight) = entry
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected `operation=weight`, got `{entry}`"))?;
+                let weight = weight
+                    .trim()
+                    .parse()
+                    .map_err(|err| format!("invalid weight for `{op}`: {err}"))?;
+                Ok((op.trim().parse()?, weight))
+            })
+            .collect::<Result<Vec<_>, String>>()
+            .map(Self)?;
+        if mix.total() == 0 {
+            return Err("at least one operation must have a non-zero weight".to_string());
+        }
+        Ok(mix)
+    }
+}
+
+/// Everything a worker needs to send requests
+struct Target {
+    http: Client,
+    /// Base URL of the API, without a trailing slash
+    api_url: String,
+    login_email: Option<String>,
+    session: Option<String>,
+    admin_session: Option<String>,
+    /// ID of the user to whom the administrator session belongs
+    admin_user_id: Option<String>,
+}
+
+impl Target {
+    fn with_session(request: RequestBuilder, session: Option<&String>) -> RequestBuilder {
+        match session {
+            Some(session) => request.header(COOKIE, format!("{SESSION_ID_COOKIE}={session}")),
+            None => request,
+        }
+    }
+
+    fn request(&self, op: Operation) -> RequestBuilder {
+        match op {
+            Operation::Login => match &self.login_email {
+                Some(email) => self
+                    .http
+                    .post(format!("{}/auth/start", self.api_url))
+                    .json(&json!({ "email": email })),
+                None => self
+                    .http
+                    .post(format!("{}/auth/discoverable/start", self.api_url)),
+            },
+            Operation::Session => Self::with_session(
+                self.http.get(format!("{}/auth/session", self.api_url)),
+                self.session.as_ref(),
+            ),
+            Operation::Admin => Self::with_session(
+                self.http.get(format!(
+                    "{}/users/{}",
+                    self.api_url,
+                    self.admin_user_id.as_deref().unwrap_or_default()
+                )),
+                self.admin_session.as_ref(),
+            ),
+        }
+    }
+
+    /// Sends a request for the given operation and returns how long it took, or an error if the
+    /// request failed or didn't succeed.
+    async fn run(&self, op: Operation) -> Result<Duration, String> {
+        let start = Instant::now();
+        let response = self
+            .request(op)
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        let status = response.status();
+        // Read the whole body, since it's part of the response time
+        let body = response.bytes().await.map_err(|err| err.to_string())?;
+        let elapsed = start.elapsed();
+        if status.is_success() {
+            Ok(elapsed)
+        } else {
+            Err(format!(
+                "HTTP {status}: {}",
+                String::from_utf8_lossy(&body).trim()
+            ))
+        }
+    }
+}
+
+/// Results for one kind of operation
+#[derive(Debug, Default)]
+struct Stats {
+    latencies: Vec<Duration>,
+    errors: u64,
+    /// Error messages and how many times each occurred
+    error_messages: HashMap<String, u64>,
+}
+
+impl Stats {
+    fn merge(&mut self, other: Stats) {
+        self.latencies.extend(other.latencies);
+        self.errors += other.errors;
+        for (message, count) in other.error_messages {
+            *self.error_messages.entry(message).or_default() += count;
+        }
+    }
+
+    /// Returns the given percentile (between 0 and 1) of the latencies, using the nearest-rank
+    /// method. The latencies must be sorted.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn percentile(&self, p: f64) -> Option<Duration> {
+        let rank = (p * self.latencies.len() as f64).ceil() as usize;
+        self.latencies.get(rank.saturating_sub(1)).copied()
+    }
+}
+
+/// Sends requests until the deadline and returns the results for each operation.
+async fn worker(target: &Target, mix: &Mix, deadline: Instant) -> HashMap<Operation, Stats> {
+    let mut results: HashMap<Operation, Stats> = HashMap::new();
+    while Instant::now() < deadline {
+        let op = mix.choose(&mut rand::rng());
+        let stats = results.entry(op).or_default();
+        match target.run(op).await {
+            Ok(latency) => stats.latencies.push(latency),
+            Err(message) => {
+                stats.errors += 1;
+                *stats.error_messages.entry(message).or_default() += 1;
+            }
+        }
+    }
+    results
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+
+    let Ok(url) = std::env::var(vars::URL) else {
+        error!(var = %vars::URL, "environment variable is not set");
+        return ExitCode::FAILURE;
+    };
+    let Some(concurrency) = getenv_parse(vars::CONCURRENCY, defaults::CONCURRENCY) else {
+        return ExitCode::FAILURE;
+    };
+    let Some(duration) = getenv_parse(vars::DURATION, defaults::DURATION_SECS) else {
+        return ExitCode::FAILURE;
+    };
+    let Some(mix) = getenv_parse(vars::MIX, defaults::MIX.parse::<Mix>().unwrap()) else {
+        return ExitCode::FAILURE;
+    };
+
+    let mut target = Target {
+        http: Client::new(),
+        api_url: format!("{}/api/v1", url.trim_end_matches('/')),
+        login_email: std::env::var(vars::LOGIN_EMAIL).ok(),
+        session: std::env::var(vars::SESSION).ok(),
+        admin_session: std::env::var(vars::ADMIN_SESSION).ok(),
+        admin_user_id: None,
+    };
+    for (op, var, session) in [
+        (Operation::Session, vars::SESSION, &target.session),
+        (Operation::Admin, vars::ADMIN_SESSION, &target.admin_session),
+    ] {
+        if mix.weight(op) > 0 && session.is_none() {
+            error!(operation = %op.name(), %var, "operation is enabled but its session is not set");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    // Check that the instance is reachable and find out who the administrator is
+    if let Err(err) = target
+        .http
+        .get(format!("{}/health", target.api_url))
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+    {
+        error!(%err, "instance is not reachable");
+        return ExitCode::FAILURE;
+    }
+    if mix.weight(Operation::Admin) > 0 {
+        match fetch_user_id(&target, target.admin_session.as_ref()).await {
+            Ok(id) => target.admin_user_id = Some(id),
+            Err(err) => {
+                error!(%err, "failed to fetch administrator's user");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    info!(%url, concurrency, duration, mix = ?mix.0, "starting load test");
+    let start = Instant::now();
+    let deadline = start + Duration::from_secs(duration);
+    let target = std::sync::Arc::new(target);
+    let mix = std::sync::Arc::new(mix);
+    let workers: Vec<_> = (0..concurrency)
+        .map(|_| {
+            let target = std::sync::Arc::clone(&target);
+            let mix = std::sync::Arc::clone(&mix);
+            tokio::spawn(async move { worker(&target, &mix, deadline).await })
+        })
+        .collect();
+    let mut results: HashMap<Operation, Stats> = HashMap::new();
+    for worker in workers {
+        match worker.await {
+            Ok(worker_results) => {
+                for (op, stats) in worker_results {
+                    results.entry(op).or_default().merge(stats);
+                }
+            }
+            Err(err) => {
+                error!(%err, "worker failed");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+
+    print_report(&mut results, elapsed);
+    if results.values().any(|stats| stats.errors > 0) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Returns the ID of the user to whom the given session belongs.
+async fn fetch_user_id(target: &Target, session: Option<&String>) -> Result<String, String> {
+    let user: serde_json::Value = Target::with_session(
+        target.http.get(format!("{}/users/me", target.api_url)),
+        session,
+    )
+    .send()
+    .await
+    .and_then(reqwest::Response::error_for_status)
+    .map_err(|err| err.to_string())?
+    .json()
+    .await
+    .map_err(|err| err.to_string())?;
+    user.get("id")
+        .and_then(serde_json::Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| "response has no user ID".to_string())
+}
+
+/// Prints throughput and latency percentiles for each operation to the standard output stream.
+#[allow(clippy::cast_precision_loss)]
+fn print_report(results: &mut HashMap<Operation, Stats>, elapsed: Duration) {
+    let header = PERCENTILES
+        .iter()
+        .map(|&p| {
+            if p >= 1.0 {
+                format!("{:>10}", "max")
+            } else {
+                format!("{:>10}", format!("p{}", p * 100.0))
+            }
+        })
+        .collect::<Vec<_>>()
+        .concat();
+    println!(
+        "{:<10}{:>10}{:>10}{:>10}{header}",
+        "operation", "requests", "errors", "req/s"
+    );
+    let mut ops: Vec<_> = results.keys().copied().collect();
+    ops.sort();
+    for op in ops {
+        let stats = results.get_mut(&op).unwrap();
+        stats.latencies.sort();
+        let requests = stats.latencies.len() as u64 + stats.errors;
+        let percentiles = PERCENTILES
+            .iter()
+            .map(|&p| match stats.percentile(p) {
+                Some(latency) => format!("{:>10}", format!("{:.1}ms", latency.as_secs_f64() * 1e3)),
+                None => format!("{:>10}", "-"),
+            })
+            .collect::<Vec<_>>()
+            .concat();
+        println!(
+            "{:<10}{requests:>10}{:>10}{:>10.1}{percentiles}",
+            op.name(),
+            stats.errors,
+            requests as f64 / elapsed.as_secs_f64(),
+        );
+    }
+    for (op, stats) in results {
+        for (message, count) in &stats.error_messages {
+            println!("{} error ({count}x): {message}", op.name());
+        }
+    }
+}
+
+/// Parses the given environment variable, returning `default` if it is not set. Logs an error and
+/// returns `None` if the variable is set but invalid.
+fn getenv_parse<T>(name: &str, default: T) -> Option<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match std::env::var(name) {
+        Ok(value) => match value.parse() {
+            Ok(parsed) => Some(parsed),
+            Err(err) => {
+                error!(%err, var = %name, %value, "invalid value for environment variable");
+                None
+            }
+        },
+        Err(_) => Some(default),
+    }
+}
@@ -0,0 +1,55 @@
+//! # Global session revocation
+//!
+//! This binary revokes all active sessions of all users, e.g. after a suspected leak of session
+//! IDs, without needing an administrator session. Everyone, including administrators, has to log
+//! in again afterwards.
+//!
+//! It opens the server's database directly, using the same `DB_PATH` environment variable as the
+//! server, as well as `REDIS_URL` and `REDIS_KEY_PREFIX` if it's built with the `redis` feature.
+//! Running servers may keep accepting revoked sessions until their in-memory session cache expires
+//! (see `SESSION_CACHE_TTL`).
+
+use std::{process::ExitCode, sync::Arc};
+
+use iam_server::db::{clients::sqlite::SqliteClient, interface::DatabaseClient};
+use tracing::{error, warn};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    tracing_subscriber::fmt().init();
+
+    let db: Arc<dyn DatabaseClient> = match SqliteClient::open().await {
+        Ok(db) => Arc::new(db),
+        Err(err) => {
+            error!(%err, "failed to open database");
+            return ExitCode::FAILURE;
+        }
+    };
+    #[cfg(feature = "redis")]
+    let db = match std::env::var("REDIS_URL") {
+        Ok(url) => {
+            let prefix = std::env::var("REDIS_KEY_PREFIX").unwrap_or_else(|_| "iam:".to_string());
+            match iam_server::db::clients::redis::RedisSessionClient::connect(db, &url, &prefix)
+                .await
+            {
+                Ok(client) => Arc::new(client),
+                Err(err) => {
+                    error!(%err, "failed to connect to Redis");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        Err(_) => db,
+    };
+    match db.revoke_all_sessions(None).await {
+        Ok(revoked) => {
+            warn!(revoked, "all sessions revoked from the command line");
+            println!("Revoked {revoked} session(s).");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            error!(%err, "failed to revoke sessions");
+            ExitCode::FAILURE
+        }
+    }
+}
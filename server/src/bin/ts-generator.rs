@@ -0,0 +1,10 @@
+//! # TypeScript type generator
+//!
+//! This binary generates TypeScript declarations for [`iam_server`]'s API models.
+//! The generated module is written to the standard output stream.
+
+use iam_server::api::generate_typescript_types;
+
+fn main() {
+    print!("{}", generate_typescript_types());
+}
@@ -0,0 +1,161 @@
+//! # Client IP addresses
+//!
+//! Behind a reverse proxy, every request comes from the proxy's address. Proxies append the
+//! address from which they received a request to its `X-Forwarded-For` header, so if a request
+//! comes from one of the [`TrustedProxies`], the client's address is the last address in the
+//! header which isn't a trusted proxy's. The header is ignored on requests from other addresses,
+//! since clients can send any header they like.
+//!
+//! The address is resolved once per request by the API's middleware and stored in the request's
+//! extensions as a [`ClientIp`], from which the ban list, the rate limiter, and handlers read it.
+
+use std::{fmt::Display, net::IpAddr, str::FromStr};
+
+use axum::http::HeaderMap;
+use ipnet::IpNet;
+
+/// Name of the header in which proxies list the addresses for which they forwarded a request
+pub const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// # Client IP address
+///
+/// Request extension holding the resolved address of the client which made the request. Only
+/// present if the server was started with
+/// [connect info][axum::Router::into_make_service_with_connect_info].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// # Trusted proxies
+///
+/// Networks of the reverse proxies whose `X-Forwarded-For` headers are trusted. Empty by default,
+/// so that the header is ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    /// Creates a set of the proxies in the given networks.
+    #[must_use]
+    pub fn new(networks: Vec<IpNet>) -> Self {
+        Self(networks)
+    }
+
+    /// Returns whether the given address is a trusted proxy's.
+    #[must_use]
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|network| network.contains(&ip))
+    }
+
+    /// Returns the address of the client which made a request received from `peer` with the
+    /// given headers. See the [module-level documentation][self] for details.
+    #[must_use]
+    pub fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = peer.to_canonical();
+        // Proxies append to the last header, so the nearest hops come last
+        let hops = headers
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .rev()
+            .flat_map(|value| value.to_str().unwrap_or_default().rsplit(','));
+        for hop in hops {
+            if !self.contains(client) {
+                break;
+            }
+            match hop.trim().parse::<IpAddr>() {
+                Ok(ip) => client = ip.to_canonical(),
+                // Whatever a proxy sent us can't be trusted beyond this point
+                Err(_) => break,
+            }
+        }
+        client
+    }
+}
+
+impl FromStr for TrustedProxies {
+    type Err = String;
+
+    /// Parses a comma-separated list of networks in CIDR notation or single addresses.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| format!("`{entry}` is not an IP address or network"))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl Display for TrustedProxies {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let networks: Vec<_> = self.0.iter().map(ToString::to_string).collect();
+        f.write_str(&networks.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn headers(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(X_FORWARDED_FOR, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let proxies: TrustedProxies = "10.0.0.0/8, 192.0.2.1".parse().unwrap();
+        let forwarded = headers(&["203.0.113.9, 198.51.100.7", "10.1.2.3"]);
+
+        // Hops are followed from the nearest until one isn't a trusted proxy
+        assert_eq!(
+            proxies.client_ip(ip("192.0.2.1"), &forwarded),
+            ip("198.51.100.7")
+        );
+        assert_eq!(
+            proxies.client_ip(ip("::ffff:10.0.0.1"), &headers(&["2001:db8::1"])),
+            ip("2001:db8::1")
+        );
+        // Requests from other addresses can't choose their address
+        assert_eq!(
+            proxies.client_ip(ip("198.51.100.1"), &forwarded),
+            ip("198.51.100.1")
+        );
+        assert_eq!(
+            TrustedProxies::default().client_ip(ip("10.0.0.1"), &forwarded),
+            ip("10.0.0.1")
+        );
+        // Without the header, or if it is malformed, the last trusted address is used
+        assert_eq!(
+            proxies.client_ip(ip("10.0.0.1"), &HeaderMap::new()),
+            ip("10.0.0.1")
+        );
+        assert_eq!(
+            proxies.client_ip(ip("10.0.0.1"), &headers(&["203.0.113.9, unknown"])),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let proxies: TrustedProxies = "10.0.0.0/8,::1".parse().unwrap();
+        assert_eq!(proxies.to_string(), "10.0.0.0/8,::1/128");
+        assert!(proxies.contains(ip("10.255.0.1")));
+        assert!(!proxies.contains(ip("11.0.0.1")));
+        assert!("10.0.0.0/33".parse::<TrustedProxies>().is_err());
+        assert!("proxy.local".parse::<TrustedProxies>().is_err());
+    }
+}
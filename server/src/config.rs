@@ -0,0 +1,1889 @@
+//! # Server configuration
+//!
+//! The server is configured by environment variables and, optionally, a configuration file.
+//! Environment variables override the file's values, so a file can hold the settings shared by
+//! all instances while secrets and per-instance settings come from the environment. Some settings
+//! can also be given as command-line arguments, which override both.
+//! [`ServerConfig::load()`] reads and validates the whole configuration before anything is
+//! started, so that all problems with it are reported at once.
+//!
+//! ## Configuration file
+//!
+//! The file is written in TOML, or in YAML if its name ends in `.yaml` or `.yml`. Every setting is
+//! named after its environment variable: keys are the variable's name in lowercase, and tables
+//! group variables sharing a prefix. For example, each of these sets `EMAIL_TRANSPORT`:
+//!
+//! ```toml
+//! email_transport = "ses"
+//!
+//! [email]
+//! transport = "ses"
+//! ```
+//!
+//! Hyphens in keys are treated as underscores. Lists, such as `ALERT_EMAILS`, may be given as
+//! arrays. Unknown keys are rejected, so that misspelled settings don't go unnoticed.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fmt::Display,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use rand::RngCore;
+use reqwest::Url;
+use serde_json::Value;
+use tracing::{info, warn};
+use uuid::Uuid;
+use webauthn_rs_proto::{AuthenticatorAttachment, ResidentKeyRequirement, UserVerificationPolicy};
+
+#[cfg(feature = "aws-kms")]
+use crate::crypto::AwsKmsKeyProvider;
+#[cfg(feature = "vault")]
+use crate::crypto::VaultKeyProvider;
+#[cfg(feature = "chaos")]
+use crate::db::clients::chaos::ChaosOptions;
+use crate::{
+    alerts::AlertOptions,
+    api::EnumerationProtection,
+    bans::AutoBanOptions,
+    client_ip::TrustedProxies,
+    crypto::{
+        FileKeyProvider, KeyProvider, Keyring, StaticKeyProvider, signing::SigningKeyOptions,
+    },
+    db::connect::ConnectRetryOptions,
+    email::{
+        AwsCredentials, EmailTemplates, LogTransport, MailgunTransport, QueueOptions,
+        SendGridTransport, SesTransport, SmtpCredentials, SmtpOptions, SmtpSecurity, SmtpTransport,
+        Transport,
+    },
+    lockout::LockoutOptions,
+    models::{
+        AppConfig, Branding, FeatureFlags, LoginNotificationPolicy, MaintenanceTask,
+        RegistrationMode, RetentionPolicy, SessionLifetimes, UserDeletionStrategy, UuidVersion,
+    },
+    rate_limit::{RateLimit, RateLimitOptions},
+    relying_party::{AttestationPolicy, PasskeyPolicy, RelyingPartyConfig},
+    session_cookie::{SessionCookieKeys, SessionCookieOptions},
+    ui::{UiOptions, validate_static_dir},
+};
+
+/// Names of the environment variables, which are also the names of the settings
+pub mod vars {
+    pub const LISTEN_ADDR: &str = "LISTEN_ADDR";
+    pub const TRUSTED_PROXIES: &str = "TRUSTED_PROXIES";
+    pub const STATIC_DIR: &str = "STATIC_DIR";
+    pub const ORIGIN: &str = "ORIGIN";
+    pub const SERVER_NAME: &str = "SERVER_NAME";
+    pub const RP_ID: &str = "RP_ID";
+    pub const RELYING_PARTIES: &str = "RELYING_PARTIES";
+    pub const WEBAUTHN_USER_VERIFICATION: &str = "WEBAUTHN_USER_VERIFICATION";
+    pub const WEBAUTHN_RESIDENT_KEY: &str = "WEBAUTHN_RESIDENT_KEY";
+    pub const WEBAUTHN_AUTHENTICATOR_ATTACHMENT: &str = "WEBAUTHN_AUTHENTICATOR_ATTACHMENT";
+    pub const WEBAUTHN_REQUIRE_ATTESTATION: &str = "WEBAUTHN_REQUIRE_ATTESTATION";
+    pub const WEBAUTHN_ALLOWED_AAGUIDS: &str = "WEBAUTHN_ALLOWED_AAGUIDS";
+    pub const WEBAUTHN_DENIED_AAGUIDS: &str = "WEBAUTHN_DENIED_AAGUIDS";
+    pub const DB_BACKEND: &str = "DB_BACKEND";
+    pub const DB_PATH: &str = "DB_PATH";
+    pub const DB_CONNECT_RETRIES: &str = "DB_CONNECT_RETRIES";
+    pub const DB_CONNECT_RETRY_DELAY: &str = "DB_CONNECT_RETRY_DELAY";
+    pub const DB_CONNECT_RETRY_MAX_DELAY: &str = "DB_CONNECT_RETRY_MAX_DELAY";
+    pub const DB_REQUIRE_MIGRATED: &str = "DB_REQUIRE_MIGRATED";
+    pub const DB_CACHE_TTL: &str = "DB_CACHE_TTL";
+    pub const BASE_PATH: &str = "BASE_PATH";
+    pub const UI_PAGES_DIR: &str = "UI_PAGES_DIR";
+    pub const MAINTENANCE_MODE: &str = "MAINTENANCE_MODE";
+    pub const STATIC_DIR_WATCH: &str = "STATIC_DIR_WATCH";
+    pub const EMAIL_TRANSPORT: &str = "EMAIL_TRANSPORT";
+    pub const EMAIL_FROM: &str = "EMAIL_FROM";
+    pub const EMAIL_TEMPLATES_DIR: &str = "EMAIL_TEMPLATES_DIR";
+    pub const EMAIL_MAX_ATTEMPTS: &str = "EMAIL_MAX_ATTEMPTS";
+    pub const EMAIL_WEBHOOK_SECRET: &str = "EMAIL_WEBHOOK_SECRET";
+    pub const SMTP_HOST: &str = "SMTP_HOST";
+    pub const SMTP_PORT: &str = "SMTP_PORT";
+    pub const SMTP_SECURITY: &str = "SMTP_SECURITY";
+    pub const SMTP_USERNAME: &str = "SMTP_USERNAME";
+    pub const SMTP_PASSWORD: &str = "SMTP_PASSWORD";
+    pub const SENDGRID_API_KEY: &str = "SENDGRID_API_KEY";
+    pub const MAILGUN_API_KEY: &str = "MAILGUN_API_KEY";
+    pub const MAILGUN_DOMAIN: &str = "MAILGUN_DOMAIN";
+    pub const MAILGUN_API_BASE: &str = "MAILGUN_API_BASE";
+    pub const AWS_REGION: &str = "AWS_REGION";
+    pub const AWS_ACCESS_KEY_ID: &str = "AWS_ACCESS_KEY_ID";
+    pub const AWS_SECRET_ACCESS_KEY: &str = "AWS_SECRET_ACCESS_KEY";
+    pub const AWS_SESSION_TOKEN: &str = "AWS_SESSION_TOKEN";
+    pub const LOGIN_NOTIFICATIONS: &str = "LOGIN_NOTIFICATIONS";
+    pub const USER_DELETION_STRATEGY: &str = "USER_DELETION_STRATEGY";
+    pub const REGISTRATION_MODE: &str = "REGISTRATION_MODE";
+    pub const ALLOWED_EMAIL_DOMAINS: &str = "ALLOWED_EMAIL_DOMAINS";
+    pub const ALERT_EMAILS: &str = "ALERT_EMAILS";
+    pub const ALERT_WEBHOOK_URL: &str = "ALERT_WEBHOOK_URL";
+    pub const ALERT_FAILED_LOGIN_THRESHOLD: &str = "ALERT_FAILED_LOGIN_THRESHOLD";
+    pub const ALERT_FAILED_LOGIN_WINDOW: &str = "ALERT_FAILED_LOGIN_WINDOW";
+    pub const ALERT_COOLDOWN: &str = "ALERT_COOLDOWN";
+    pub const AUTO_BAN_THRESHOLD: &str = "AUTO_BAN_THRESHOLD";
+    pub const AUTO_BAN_WINDOW: &str = "AUTO_BAN_WINDOW";
+    pub const AUTO_BAN_DURATION: &str = "AUTO_BAN_DURATION";
+    pub const LOCKOUT_THRESHOLD: &str = "LOCKOUT_THRESHOLD";
+    pub const LOCKOUT_WINDOW: &str = "LOCKOUT_WINDOW";
+    pub const LOCKOUT_DURATION: &str = "LOCKOUT_DURATION";
+    pub const SESSION_CACHE_TTL: &str = "SESSION_CACHE_TTL";
+    pub const SESSION_LIFETIME: &str = "SESSION_LIFETIME";
+    pub const ADMIN_SESSION_LIFETIME: &str = "ADMIN_SESSION_LIFETIME";
+    pub const SESSION_COOKIE_KEYS: &str = "SESSION_COOKIE_KEYS";
+    pub const SESSION_COOKIE_HOST_PREFIX: &str = "SESSION_COOKIE_HOST_PREFIX";
+    pub const RATE_LIMIT_IP_MAX: &str = "RATE_LIMIT_IP_MAX";
+    pub const RATE_LIMIT_IP_WINDOW: &str = "RATE_LIMIT_IP_WINDOW";
+    pub const RATE_LIMIT_EMAIL_MAX: &str = "RATE_LIMIT_EMAIL_MAX";
+    pub const RATE_LIMIT_EMAIL_WINDOW: &str = "RATE_LIMIT_EMAIL_WINDOW";
+    pub const ENUMERATION_PROTECTION: &str = "ENUMERATION_PROTECTION";
+    pub const ENUMERATION_PROTECTION_SECRET: &str = "ENUMERATION_PROTECTION_SECRET";
+    pub const UUID_VERSION: &str = "UUID_VERSION";
+    pub const EMAIL_UNICODE_NORMALIZATION: &str = "EMAIL_UNICODE_NORMALIZATION";
+    pub const KEY_PROVIDER: &str = "KEY_PROVIDER";
+    pub const ENCRYPTION_KEYS: &str = "ENCRYPTION_KEYS";
+    pub const ENCRYPTION_KEYS_FILE: &str = "ENCRYPTION_KEYS_FILE";
+    pub const KEY_REFRESH_INTERVAL: &str = "KEY_REFRESH_INTERVAL";
+    pub const SIGNING_KEY_ROTATION_DAYS: &str = "SIGNING_KEY_ROTATION_DAYS";
+    #[cfg(feature = "vault")]
+    pub const VAULT_ADDR: &str = "VAULT_ADDR";
+    #[cfg(feature = "vault")]
+    pub const VAULT_TOKEN: &str = "VAULT_TOKEN";
+    #[cfg(feature = "vault")]
+    pub const VAULT_SECRET_PATH: &str = "VAULT_SECRET_PATH";
+    pub const RETENTION_SESSIONS_DAYS: &str = "RETENTION_SESSIONS_DAYS";
+    pub const RETENTION_LOGIN_HISTORY_DAYS: &str = "RETENTION_LOGIN_HISTORY_DAYS";
+    pub const RETENTION_ANONYMIZED_USERS_DAYS: &str = "RETENTION_ANONYMIZED_USERS_DAYS";
+    pub const RETENTION_DEAD_EMAILS_DAYS: &str = "RETENTION_DEAD_EMAILS_DAYS";
+    pub const RETENTION_INTERVAL: &str = "RETENTION_INTERVAL";
+    pub const MAINTENANCE_TASKS: &str = "MAINTENANCE_TASKS";
+    pub const MAINTENANCE_INTERVAL: &str = "MAINTENANCE_INTERVAL";
+    pub const BRANDING_LOGO_URL: &str = "BRANDING_LOGO_URL";
+    pub const BRANDING_PRIMARY_COLOR: &str = "BRANDING_PRIMARY_COLOR";
+    pub const BRANDING_ACCENT_COLOR: &str = "BRANDING_ACCENT_COLOR";
+    pub const BRANDING_SUPPORT_EMAIL: &str = "BRANDING_SUPPORT_EMAIL";
+    pub const BRANDING_PRIVACY_POLICY_URL: &str = "BRANDING_PRIVACY_POLICY_URL";
+    pub const BRANDING_TERMS_URL: &str = "BRANDING_TERMS_URL";
+    pub const BRANDING_LOGIN_MESSAGE: &str = "BRANDING_LOGIN_MESSAGE";
+    #[cfg(feature = "chaos")]
+    pub const DB_CHAOS_ERROR_RATE: &str = "DB_CHAOS_ERROR_RATE";
+    #[cfg(feature = "chaos")]
+    pub const DB_CHAOS_MIN_LATENCY_MS: &str = "DB_CHAOS_MIN_LATENCY_MS";
+    #[cfg(feature = "chaos")]
+    pub const DB_CHAOS_MAX_LATENCY_MS: &str = "DB_CHAOS_MAX_LATENCY_MS";
+    #[cfg(feature = "redis")]
+    pub const REDIS_URL: &str = "REDIS_URL";
+    #[cfg(feature = "redis")]
+    pub const REDIS_KEY_PREFIX: &str = "REDIS_KEY_PREFIX";
+
+    /// All settings which this build of the server supports
+    pub const ALL: &[&str] = &[
+        LISTEN_ADDR,
+        TRUSTED_PROXIES,
+        STATIC_DIR,
+        ORIGIN,
+        SERVER_NAME,
+        RP_ID,
+        RELYING_PARTIES,
+        WEBAUTHN_USER_VERIFICATION,
+        WEBAUTHN_RESIDENT_KEY,
+        WEBAUTHN_AUTHENTICATOR_ATTACHMENT,
+        WEBAUTHN_REQUIRE_ATTESTATION,
+        WEBAUTHN_ALLOWED_AAGUIDS,
+        WEBAUTHN_DENIED_AAGUIDS,
+        DB_BACKEND,
+        DB_PATH,
+        DB_CONNECT_RETRIES,
+        DB_CONNECT_RETRY_DELAY,
+        DB_CONNECT_RETRY_MAX_DELAY,
+        DB_REQUIRE_MIGRATED,
+        DB_CACHE_TTL,
+        BASE_PATH,
+        UI_PAGES_DIR,
+        MAINTENANCE_MODE,
+        STATIC_DIR_WATCH,
+        EMAIL_TRANSPORT,
+        EMAIL_FROM,
+        EMAIL_TEMPLATES_DIR,
+        EMAIL_MAX_ATTEMPTS,
+        EMAIL_WEBHOOK_SECRET,
+        SMTP_HOST,
+        SMTP_PORT,
+        SMTP_SECURITY,
+        SMTP_USERNAME,
+        SMTP_PASSWORD,
+        SENDGRID_API_KEY,
+        MAILGUN_API_KEY,
+        MAILGUN_DOMAIN,
+        MAILGUN_API_BASE,
+        AWS_REGION,
+        AWS_ACCESS_KEY_ID,
+        AWS_SECRET_ACCESS_KEY,
+        AWS_SESSION_TOKEN,
+        LOGIN_NOTIFICATIONS,
+        USER_DELETION_STRATEGY,
+        REGISTRATION_MODE,
+        ALLOWED_EMAIL_DOMAINS,
+        ALERT_EMAILS,
+        ALERT_WEBHOOK_URL,
+        ALERT_FAILED_LOGIN_THRESHOLD,
+        ALERT_FAILED_LOGIN_WINDOW,
+        ALERT_COOLDOWN,
+        AUTO_BAN_THRESHOLD,
+        AUTO_BAN_WINDOW,
+        AUTO_BAN_DURATION,
+        LOCKOUT_THRESHOLD,
+        LOCKOUT_WINDOW,
+        LOCKOUT_DURATION,
+        SESSION_CACHE_TTL,
+        SESSION_LIFETIME,
+        ADMIN_SESSION_LIFETIME,
+        SESSION_COOKIE_KEYS,
+        SESSION_COOKIE_HOST_PREFIX,
+        RATE_LIMIT_IP_MAX,
+        RATE_LIMIT_IP_WINDOW,
+        RATE_LIMIT_EMAIL_MAX,
+        RATE_LIMIT_EMAIL_WINDOW,
+        ENUMERATION_PROTECTION,
+        ENUMERATION_PROTECTION_SECRET,
+        UUID_VERSION,
+        EMAIL_UNICODE_NORMALIZATION,
+        KEY_PROVIDER,
+        ENCRYPTION_KEYS,
+        ENCRYPTION_KEYS_FILE,
+        KEY_REFRESH_INTERVAL,
+        SIGNING_KEY_ROTATION_DAYS,
+        #[cfg(feature = "vault")]
+        VAULT_ADDR,
+        #[cfg(feature = "vault")]
+        VAULT_TOKEN,
+        #[cfg(feature = "vault")]
+        VAULT_SECRET_PATH,
+        RETENTION_SESSIONS_DAYS,
+        RETENTION_LOGIN_HISTORY_DAYS,
+        RETENTION_ANONYMIZED_USERS_DAYS,
+        RETENTION_DEAD_EMAILS_DAYS,
+        RETENTION_INTERVAL,
+        MAINTENANCE_TASKS,
+        MAINTENANCE_INTERVAL,
+        BRANDING_LOGO_URL,
+        BRANDING_PRIMARY_COLOR,
+        BRANDING_ACCENT_COLOR,
+        BRANDING_SUPPORT_EMAIL,
+        BRANDING_PRIVACY_POLICY_URL,
+        BRANDING_TERMS_URL,
+        BRANDING_LOGIN_MESSAGE,
+        #[cfg(feature = "chaos")]
+        DB_CHAOS_ERROR_RATE,
+        #[cfg(feature = "chaos")]
+        DB_CHAOS_MIN_LATENCY_MS,
+        #[cfg(feature = "chaos")]
+        DB_CHAOS_MAX_LATENCY_MS,
+        #[cfg(feature = "redis")]
+        REDIS_URL,
+        #[cfg(feature = "redis")]
+        REDIS_KEY_PREFIX,
+    ];
+}
+
+mod defaults {
+    pub const LISTEN_ADDR: &str = "0.0.0.0:3000";
+    pub const STATIC_DIR: &str = "./ui/build";
+    /// Seconds between runs of the data retention job
+    pub const RETENTION_INTERVAL: u64 = 60 * 60;
+    /// Days for which sessions are kept after they end. Unlike other data, ended sessions are
+    /// useless, so they aren't kept forever by default.
+    pub const RETENTION_SESSIONS_DAYS: u64 = 30;
+    pub const KEY_REFRESH_INTERVAL: u64 = 5 * 60;
+    /// Days for which a token signing key is used before it is replaced
+    pub const SIGNING_KEY_ROTATION_DAYS: u64 = 30;
+    /// Database maintenance tasks which are run by default
+    pub const MAINTENANCE_TASKS: &str = "optimize";
+    /// Seconds between runs of the database maintenance job
+    pub const MAINTENANCE_INTERVAL: u64 = 24 * 60 * 60;
+    /// Seconds for which sessions are cached in memory
+    pub const SESSION_CACHE_TTL: u64 = 5;
+    /// Seconds for which frequently read database records are cached in memory
+    pub const DB_CACHE_TTL: u64 = 5;
+    /// Seconds before the first retry of a failed database connection
+    pub const DB_CONNECT_RETRY_DELAY: u64 = 1;
+    /// Longest number of seconds between retries of a failed database connection
+    pub const DB_CONNECT_RETRY_MAX_DELAY: u64 = 30;
+    /// Prefix of keys stored in Redis
+    #[cfg(feature = "redis")]
+    pub const REDIS_KEY_PREFIX: &str = "iam:";
+}
+
+/// A problem with a setting, found while loading the configuration
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{setting}: {message}")]
+pub struct ConfigProblem {
+    /// Name of the setting, or the path of the configuration file for problems with the file
+    /// itself
+    pub setting: String,
+    pub message: String,
+}
+
+/// # Server configuration
+///
+/// See the [module-level documentation][self] for details.
+pub struct ServerConfig {
+    /// Address on which the server listens
+    pub listen_addr: SocketAddr,
+    /// Reverse proxies whose `X-Forwarded-For` headers are trusted
+    pub trusted_proxies: TrustedProxies,
+    /// Origin at which the app is served
+    pub origin: Url,
+    /// `WebAuthn` relying party ID
+    pub rp_id: String,
+    /// Additional `WebAuthn` relying parties, served at other hosts than [`origin`][Self::origin]
+    pub relying_parties: Vec<RelyingPartyConfig>,
+    /// Configuration exposed to the UI
+    pub app: AppConfig,
+    /// Directory containing the built UI
+    pub static_dir: PathBuf,
+    pub ui: UiOptions,
+    pub db: DbSettings,
+    pub uuid_version: UuidVersion,
+    /// Whether email addresses are converted to Unicode normalization form C when they are stored
+    /// and looked up
+    pub email_unicode_normalization: bool,
+    /// Source of the keys with which stored credentials are encrypted, or [`None`] if they
+    /// aren't encrypted
+    pub keys: Option<KeySettings>,
+    pub signing_keys: SigningKeyOptions,
+    /// Email settings, or [`None`] if email is disabled
+    pub email: Option<EmailSettings>,
+    /// Mailgun webhook signing key with which bounce reports are signed
+    pub email_webhook_secret: Option<String>,
+    pub alerts: AlertOptions,
+    /// Options for banning addresses automatically, or [`None`] if automatic bans are disabled
+    pub auto_ban: Option<AutoBanOptions>,
+    /// Options for locking accounts, or [`None`] if accounts are never locked
+    pub lockout: Option<LockoutOptions>,
+    /// How long sessions are cached in memory
+    pub session_cache_ttl: Duration,
+    /// How session cookies are named and signed
+    pub session_cookies: SessionCookieOptions,
+    /// Limits on login and registration requests
+    pub rate_limit: RateLimitOptions,
+    /// Account enumeration protection settings, or [`None`] if it is disabled
+    pub enumeration_protection: Option<EnumerationProtection>,
+    /// What is asked of authenticators when passkeys are registered and used
+    pub passkey_policy: PasskeyPolicy,
+    pub login_notifications: LoginNotificationPolicy,
+    pub user_deletion: UserDeletionStrategy,
+    /// Data retention settings
+    pub retention: RetentionSettings,
+    /// Database maintenance settings, or [`None`] if maintenance is disabled
+    pub maintenance: Option<MaintenanceSettings>,
+    #[cfg(feature = "chaos")]
+    pub chaos: ChaosOptions,
+}
+
+/// Database backend in which data is stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DbBackend {
+    /// Database file at the given path, which is created if it doesn't exist
+    #[cfg(feature = "sqlite3")]
+    Sqlite { path: PathBuf },
+}
+
+/// Database backend and the stores wrapped around it
+#[derive(Debug, Clone)]
+pub struct DbSettings {
+    pub backend: DbBackend,
+    /// How failed connections to the database and the stores wrapped around it are retried
+    pub connect_retry: ConnectRetryOptions,
+    /// Whether the server refuses to start if the database has pending migrations, rather than
+    /// applying them
+    pub require_migrated: bool,
+    /// How long frequently read records are cached in memory. Zero disables the cache.
+    pub cache_ttl: Duration,
+    /// Redis server in which sessions and passkey ceremony state are stored, if any
+    #[cfg(feature = "redis")]
+    pub redis: Option<RedisSettings>,
+}
+
+/// Connection settings for Redis
+#[cfg(feature = "redis")]
+#[derive(Debug, Clone)]
+pub struct RedisSettings {
+    pub url: String,
+    /// Prefix of the keys stored in Redis
+    pub key_prefix: String,
+}
+
+/// Source of the keys used to encrypt sensitive data before it is stored, and how often they are
+/// reloaded
+pub struct KeySettings {
+    pub provider: Arc<dyn KeyProvider>,
+    /// How often the keys are reloaded, or [`None`] if they can't change while the server runs
+    pub refresh_interval: Option<Duration>,
+}
+
+/// Email transport, templates, and queue options
+pub struct EmailSettings {
+    pub transport: Arc<dyn Transport>,
+    pub templates: EmailTemplates,
+    /// Sender of outgoing emails
+    pub from: String,
+    pub queue: QueueOptions,
+}
+
+/// Data retention policy and the interval at which it is enforced
+#[derive(Debug, Clone)]
+pub struct RetentionSettings {
+    pub policy: RetentionPolicy,
+    pub interval: Duration,
+}
+
+/// Database maintenance tasks and the interval at which they are run
+#[derive(Debug, Clone)]
+pub struct MaintenanceSettings {
+    pub tasks: Vec<MaintenanceTask>,
+    pub interval: Duration,
+}
+
+impl ServerConfig {
+    /// Loads the configuration from the environment and, if a path is given, the configuration
+    /// file at that path. Returns every problem found if the configuration is invalid.
+    /// Settings given in `overrides`, e.g. by command-line arguments, take precedence over both.
+    pub fn load(
+        file: Option<&Path>,
+        overrides: impl IntoIterator<Item = (&'static str, OsString)>,
+    ) -> Result<Self, Vec<ConfigProblem>> {
+        Self::read(Reader::load(file, overrides))
+    }
+
+    /// Reads the configuration from the given reader.
+    fn read(reader: Reader) -> Result<Self, Vec<ConfigProblem>> {
+        let uuid_version = reader.parse(vars::UUID_VERSION, UuidVersion::default());
+        let keys = read_key_settings(&reader);
+        let origin = read_origin(&reader);
+        let registration_mode = reader.parse(vars::REGISTRATION_MODE, RegistrationMode::default());
+        let app = AppConfig {
+            instance_name: reader.var(vars::SERVER_NAME).unwrap_or_else(|| {
+                let default = origin.authority();
+                warn!(
+                    var = %vars::SERVER_NAME,
+                    %default,
+                    "variable not set; using default",
+                );
+                default.to_string()
+            }),
+            base_path: reader
+                .var(vars::BASE_PATH)
+                .map(|path| normalize_base_path(&path))
+                .unwrap_or_default(),
+            branding: read_branding(&reader),
+            features: FeatureFlags {
+                registration: registration_mode == RegistrationMode::Open,
+                // The provider's endpoints are always served
+                oidc: true,
+                docs: cfg!(feature = "scalar"),
+                ..FeatureFlags::default()
+            },
+            registration_mode,
+            allowed_email_domains: read_allowed_email_domains(&reader),
+            session_lifetimes: read_session_lifetimes(&reader),
+        };
+        let rp_id = read_rp_id(&reader, &origin);
+        let relying_parties = read_relying_parties(&reader, &origin, &app.instance_name);
+        let app_url = format!("{}{}", origin.as_str().trim_end_matches('/'), app.base_path);
+        let (static_dir, ui) = read_ui_options(&reader);
+        let email = read_email_settings(&reader, &app, &app_url);
+        let config = Self {
+            listen_addr: reader.parse(
+                vars::LISTEN_ADDR,
+                defaults::LISTEN_ADDR.parse().expect("default is valid"),
+            ),
+            trusted_proxies: reader.parse(vars::TRUSTED_PROXIES, TrustedProxies::default()),
+            db: read_db_settings(&reader),
+            alerts: read_alert_options(&reader, email.is_some()),
+            auto_ban: read_auto_ban_options(&reader),
+            lockout: read_lockout_options(&reader),
+            rate_limit: read_rate_limit_options(&reader),
+            enumeration_protection: read_enumeration_protection(&reader),
+            passkey_policy: read_passkey_policy(&reader),
+            session_cache_ttl: Duration::from_secs(
+                reader.parse(vars::SESSION_CACHE_TTL, defaults::SESSION_CACHE_TTL),
+            ),
+            session_cookies: read_session_cookie_options(&reader),
+            retention: read_retention_settings(&reader),
+            maintenance: read_maintenance_settings(&reader),
+            signing_keys: read_signing_key_options(&reader),
+            login_notifications: reader.parse(
+                vars::LOGIN_NOTIFICATIONS,
+                LoginNotificationPolicy::default(),
+            ),
+            user_deletion: reader.parse(
+                vars::USER_DELETION_STRATEGY,
+                UserDeletionStrategy::default(),
+            ),
+            email_webhook_secret: reader.var(vars::EMAIL_WEBHOOK_SECRET),
+            email_unicode_normalization: reader.bool(vars::EMAIL_UNICODE_NORMALIZATION),
+            #[cfg(feature = "chaos")]
+            chaos: read_chaos_options(&reader),
+            origin,
+            rp_id,
+            relying_parties,
+            app,
+            static_dir,
+            ui,
+            uuid_version,
+            keys,
+            email,
+        };
+        reader.check()?;
+        if config.login_notifications != LoginNotificationPolicy::Off && config.email.is_none() {
+            warn!(
+                var = %vars::EMAIL_TRANSPORT,
+                "login notifications are enabled but email is not configured; none will be sent",
+            );
+        }
+        Ok(config)
+    }
+}
+
+impl DbSettings {
+    /// Loads only the database settings, in the same way as [`ServerConfig::load()`], for tasks
+    /// which don't need the rest of the configuration.
+    pub fn load(
+        file: Option<&Path>,
+        overrides: impl IntoIterator<Item = (&'static str, OsString)>,
+    ) -> Result<Self, Vec<ConfigProblem>> {
+        let reader = Reader::load(file, overrides);
+        let settings = read_db_settings(&reader);
+        reader.check()?;
+        Ok(settings)
+    }
+}
+
+/// Reads the origin at which the app is served. If it is missing or invalid, a placeholder is
+/// returned so the rest of the configuration can still be checked.
+fn read_origin(reader: &Reader) -> Url {
+    reader
+        .required(vars::ORIGIN)
+        .and_then(|origin| parse_origin(reader, vars::ORIGIN, &origin))
+        .unwrap_or_else(|| Url::parse("http://localhost").unwrap())
+}
+
+/// Parses the origin given by the given setting, recording a problem if it is invalid.
+fn parse_origin(reader: &Reader, setting: &str, origin: &str) -> Option<Url> {
+    match Url::parse(origin) {
+        Ok(url) if url.host_str().is_some() && matches!(url.scheme(), "http" | "https") => {
+            Some(url)
+        }
+        Ok(_) => {
+            reader.problem(
+                setting,
+                format!(
+                    "`{origin}` is not an HTTP(S) origin; expected e.g. `https://iam.example.com`"
+                ),
+            );
+            None
+        }
+        Err(err) => {
+            reader.problem(
+                setting,
+                format!(
+                    "`{origin}` is not a valid URL ({err}); expected e.g. `https://iam.example.com`"
+                ),
+            );
+            None
+        }
+    }
+}
+
+/// Reads the `WebAuthn` relying party ID, which defaults to the origin's host. It must be the
+/// origin's host or a parent domain of it, or browsers will refuse to use passkeys.
+fn read_rp_id(reader: &Reader, origin: &Url) -> String {
+    let Some(rp_id) = reader.var(vars::RP_ID) else {
+        return origin.host_str().unwrap_or_default().to_string();
+    };
+    check_rp_id(reader, vars::RP_ID, &rp_id, origin);
+    rp_id
+}
+
+/// Checks that the RP ID given by the given setting is the origin's host or a parent domain of
+/// it, recording a problem if not.
+fn check_rp_id(reader: &Reader, setting: &str, rp_id: &str, origin: &Url) {
+    let host = origin.host_str().unwrap_or_default();
+    if rp_id != host && !host.ends_with(&format!(".{rp_id}")) {
+        reader.problem(
+            setting,
+            format!(
+                "RP ID `{rp_id}` does not match the origin's host `{host}`; it must be the host \
+                or a parent domain of it, or be left unset to use the host"
+            ),
+        );
+    }
+}
+
+/// Reads the additional `WebAuthn` relying parties. Each is given as `ORIGIN[|RP_ID[|NAME]]`,
+/// where the RP ID defaults to the origin's host and the name to the server's name, e.g.
+/// `https://login.example.org|example.org|Example`. Each must be served at a different host than
+/// the main `origin` and the others.
+fn read_relying_parties(reader: &Reader, origin: &Url, name: &str) -> Vec<RelyingPartyConfig> {
+    let Some(parties) = reader.var(vars::RELYING_PARTIES) else {
+        return Vec::new();
+    };
+    let mut hosts = vec![origin.authority().to_ascii_lowercase()];
+    parties
+        .split(',')
+        .map(str::trim)
+        .filter(|party| !party.is_empty())
+        .filter_map(|party| {
+            let mut parts = party.splitn(3, '|').map(str::trim);
+            let origin = parse_origin(reader, vars::RELYING_PARTIES, parts.next()?)?;
+            let host = origin.authority().to_ascii_lowercase();
+            if hosts.contains(&host) {
+                reader.problem(
+                    vars::RELYING_PARTIES,
+                    format!("more than one relying party is served at `{host}`"),
+                );
+                return None;
+            }
+            hosts.push(host);
+            let rp_id = match parts.next().filter(|rp_id| !rp_id.is_empty()) {
+                Some(rp_id) => {
+                    check_rp_id(reader, vars::RELYING_PARTIES, rp_id, &origin);
+                    rp_id.to_string()
+                }
+                None => origin.host_str().unwrap_or_default().to_string(),
+            };
+            let name = parts
+                .next()
+                .filter(|name| !name.is_empty())
+                .unwrap_or(name)
+                .to_string();
+            Some(RelyingPartyConfig {
+                origin,
+                rp_id,
+                name,
+            })
+        })
+        .collect()
+}
+
+/// Reads the static directory and UI options, and checks that the static directory contains a
+/// built UI.
+fn read_ui_options(reader: &Reader) -> (PathBuf, UiOptions) {
+    let static_dir = reader.path(vars::STATIC_DIR).unwrap_or_else(|| {
+        warn!(
+            var = %vars::STATIC_DIR,
+            default = %defaults::STATIC_DIR,
+            "variable not set; using default",
+        );
+        PathBuf::from(defaults::STATIC_DIR)
+    });
+    let ui_options = UiOptions {
+        pages_dir: reader.path(vars::UI_PAGES_DIR),
+        maintenance: reader.bool(vars::MAINTENANCE_MODE),
+        watch: reader.bool(vars::STATIC_DIR_WATCH),
+    };
+    // The static directory is not used in maintenance mode
+    if !ui_options.maintenance
+        && let Err(err) = validate_static_dir(&static_dir)
+    {
+        reader.problem(vars::STATIC_DIR, err);
+    }
+    (static_dir, ui_options)
+}
+
+/// Reads the branding options and checks that they are valid.
+fn read_branding(reader: &Reader) -> Branding {
+    let branding = Branding {
+        logo_url: reader.var(vars::BRANDING_LOGO_URL),
+        primary_color: reader.var(vars::BRANDING_PRIMARY_COLOR),
+        accent_color: reader.var(vars::BRANDING_ACCENT_COLOR),
+        support_email: reader.var(vars::BRANDING_SUPPORT_EMAIL),
+        privacy_policy_url: reader.var(vars::BRANDING_PRIVACY_POLICY_URL),
+        terms_url: reader.var(vars::BRANDING_TERMS_URL),
+        login_message: reader.var(vars::BRANDING_LOGIN_MESSAGE),
+    };
+    if let Err(err) = branding.validate() {
+        reader.problem("BRANDING_*", err);
+    }
+    branding
+}
+
+/// Reads the lifetimes of sessions, in seconds, and checks that they are valid.
+fn read_session_lifetimes(reader: &Reader) -> SessionLifetimes {
+    /// Longest allowed lifetime. Browsers cap cookie lifetimes at 400 days, so session cookies
+    /// wouldn't outlive it anyway.
+    const MAX_LIFETIME: u64 = 400 * 24 * 60 * 60;
+
+    let defaults = SessionLifetimes::default();
+    let lifetimes = SessionLifetimes {
+        session_secs: reader.parse(vars::SESSION_LIFETIME, defaults.session_secs),
+        admin_session_secs: reader.parse(vars::ADMIN_SESSION_LIFETIME, defaults.admin_session_secs),
+    };
+    for (name, secs) in [
+        (vars::SESSION_LIFETIME, lifetimes.session_secs),
+        (vars::ADMIN_SESSION_LIFETIME, lifetimes.admin_session_secs),
+    ] {
+        if !(1..=MAX_LIFETIME).contains(&secs) {
+            reader.problem(
+                name,
+                format!("invalid lifetime `{secs}`; expected 1 to {MAX_LIFETIME} seconds"),
+            );
+        }
+    }
+    lifetimes
+}
+
+/// Reads how session cookies are named and signed. If no keys are set, the key stored in the
+/// database is used.
+fn read_session_cookie_options(reader: &Reader) -> SessionCookieOptions {
+    let keys = reader.var(vars::SESSION_COOKIE_KEYS).and_then(|spec| {
+        SessionCookieKeys::parse(&spec)
+            .inspect_err(|err| reader.problem(vars::SESSION_COOKIE_KEYS, err))
+            .ok()
+            .map(Arc::new)
+    });
+    SessionCookieOptions {
+        host_prefix: reader.bool(vars::SESSION_COOKIE_HOST_PREFIX),
+        keys,
+    }
+}
+
+/// Reads the domains at which users may register. Domains are lowercased, and a leading `@` is
+/// removed, so that `@Example.com` is the same as `example.com`.
+fn read_allowed_email_domains(reader: &Reader) -> Vec<String> {
+    reader
+        .var(vars::ALLOWED_EMAIL_DOMAINS)
+        .map(|domains| {
+            domains
+                .split(',')
+                .map(|domain| domain.trim().trim_start_matches('@').to_lowercase())
+                .filter(|domain| !domain.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reads the data retention windows. Ended sessions are always purged, after
+/// [`defaults::RETENTION_SESSIONS_DAYS`] unless set otherwise.
+fn read_retention_settings(reader: &Reader) -> RetentionSettings {
+    let days = |name: &str| {
+        reader
+            .var(name)
+            .is_some()
+            .then(|| Duration::from_secs(reader.parse::<u64>(name, 0) * 24 * 60 * 60))
+    };
+    let policy = RetentionPolicy {
+        terminated_sessions: Some(Duration::from_secs(
+            reader.parse(
+                vars::RETENTION_SESSIONS_DAYS,
+                defaults::RETENTION_SESSIONS_DAYS,
+            ) * 24
+                * 60
+                * 60,
+        )),
+        login_history: days(vars::RETENTION_LOGIN_HISTORY_DAYS),
+        anonymized_users: days(vars::RETENTION_ANONYMIZED_USERS_DAYS),
+        dead_emails: days(vars::RETENTION_DEAD_EMAILS_DAYS),
+    };
+    let interval =
+        Duration::from_secs(reader.parse(vars::RETENTION_INTERVAL, defaults::RETENTION_INTERVAL));
+    RetentionSettings { policy, interval }
+}
+
+/// Reads the database maintenance tasks. Returns [`None`] if maintenance is disabled, i.e. no
+/// tasks are set.
+fn read_maintenance_settings(reader: &Reader) -> Option<MaintenanceSettings> {
+    let tasks: Vec<MaintenanceTask> = reader
+        .var(vars::MAINTENANCE_TASKS)
+        .as_deref()
+        .unwrap_or(defaults::MAINTENANCE_TASKS)
+        .split(',')
+        .map(str::trim)
+        .filter(|task| !task.is_empty())
+        .filter_map(|task| {
+            task.parse()
+                .map_err(|err| reader.problem(vars::MAINTENANCE_TASKS, err))
+                .ok()
+        })
+        .collect();
+    let interval = Duration::from_secs(
+        reader.parse(vars::MAINTENANCE_INTERVAL, defaults::MAINTENANCE_INTERVAL),
+    );
+    if tasks.is_empty() {
+        info!("no database maintenance tasks set; maintenance is disabled");
+        return None;
+    }
+    Some(MaintenanceSettings { tasks, interval })
+}
+
+/// Reads the security alert options. `email_enabled` is whether email is configured, without
+/// which alerts can't be emailed.
+fn read_alert_options(reader: &Reader, email_enabled: bool) -> AlertOptions {
+    let defaults = AlertOptions::default();
+    let options = AlertOptions {
+        email_recipients: reader
+            .var(vars::ALERT_EMAILS)
+            .map(|emails| {
+                emails
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|email| !email.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        webhook_url: reader.var(vars::ALERT_WEBHOOK_URL),
+        failed_login_threshold: reader.parse(
+            vars::ALERT_FAILED_LOGIN_THRESHOLD,
+            defaults.failed_login_threshold,
+        ),
+        failed_login_window: Duration::from_secs(reader.parse(
+            vars::ALERT_FAILED_LOGIN_WINDOW,
+            defaults.failed_login_window.as_secs(),
+        )),
+        cooldown: Duration::from_secs(
+            reader.parse(vars::ALERT_COOLDOWN, defaults.cooldown.as_secs()),
+        ),
+    };
+    if !options.email_recipients.is_empty() && !email_enabled {
+        warn!(
+            var = %vars::EMAIL_TRANSPORT,
+            "alert email recipients are set but email is not configured; no alert emails will be sent",
+        );
+    }
+    options
+}
+
+/// Reads the source of the encryption keys. Returns [`None`] if encryption is disabled, i.e. if
+/// keys are read from the environment but none are set.
+fn read_key_settings(reader: &Reader) -> Option<KeySettings> {
+    let name = reader
+        .var(vars::KEY_PROVIDER)
+        .unwrap_or_else(|| "env".to_string());
+    let provider: Arc<dyn KeyProvider> = match name.as_str() {
+        "env" => match Keyring::parse(&reader.var(vars::ENCRYPTION_KEYS)?) {
+            Ok(keyring) => Arc::new(StaticKeyProvider(keyring)),
+            Err(err) => {
+                reader.problem(vars::ENCRYPTION_KEYS, err);
+                return None;
+            }
+        },
+        "file" => Arc::new(FileKeyProvider(
+            reader.required(vars::ENCRYPTION_KEYS_FILE)?.into(),
+        )),
+        #[cfg(feature = "vault")]
+        "vault" => {
+            let address = reader.required(vars::VAULT_ADDR);
+            let path = reader.required(vars::VAULT_SECRET_PATH);
+            let token = reader.required(vars::VAULT_TOKEN);
+            Arc::new(VaultKeyProvider::new(&address?, &path?, token?))
+        }
+        #[cfg(feature = "aws-kms")]
+        "aws-kms" => {
+            let region = reader.required(vars::AWS_REGION);
+            let credentials = read_aws_credentials(reader);
+            let keys = reader.required(vars::ENCRYPTION_KEYS);
+            Arc::new(AwsKmsKeyProvider::new(region?, credentials?, keys?))
+        }
+        _ => {
+            let expected: Vec<_> = ["`env`", "`file`"]
+                .into_iter()
+                .chain(cfg!(feature = "vault").then_some("`vault`"))
+                .chain(cfg!(feature = "aws-kms").then_some("`aws-kms`"))
+                .collect();
+            reader.problem(
+                vars::KEY_PROVIDER,
+                format!(
+                    "invalid key provider `{name}`; expected one of {}",
+                    expected.join(", ")
+                ),
+            );
+            return None;
+        }
+    };
+    // Keys from the configuration can't change while the server runs
+    let refresh_interval = (name != "env").then(|| {
+        Duration::from_secs(
+            reader.parse(vars::KEY_REFRESH_INTERVAL, defaults::KEY_REFRESH_INTERVAL),
+        )
+    });
+    Some(KeySettings {
+        provider,
+        refresh_interval,
+    })
+}
+
+/// Reads the options for rotating the keys with which tokens are signed.
+fn read_signing_key_options(reader: &Reader) -> SigningKeyOptions {
+    let days = reader.parse(
+        vars::SIGNING_KEY_ROTATION_DAYS,
+        defaults::SIGNING_KEY_ROTATION_DAYS,
+    );
+    if days == 0 {
+        reader.problem(vars::SIGNING_KEY_ROTATION_DAYS, "must be at least 1");
+    }
+    SigningKeyOptions {
+        rotation_interval: Duration::from_secs(days * 24 * 60 * 60),
+        ..SigningKeyOptions::default()
+    }
+}
+
+/// Reads the options for automatically banning addresses. Returns [`None`] if automatic bans
+/// are disabled, i.e. if no threshold is set or it is zero.
+fn read_auto_ban_options(reader: &Reader) -> Option<AutoBanOptions> {
+    let defaults = AutoBanOptions::default();
+    let threshold = reader.parse(vars::AUTO_BAN_THRESHOLD, 0);
+    (threshold > 0).then(|| AutoBanOptions {
+        threshold,
+        window: Duration::from_secs(reader.parse(vars::AUTO_BAN_WINDOW, defaults.window.as_secs())),
+        duration: Duration::from_secs(
+            reader.parse(vars::AUTO_BAN_DURATION, defaults.duration.as_secs()),
+        ),
+    })
+}
+
+/// Reads the options for locking accounts. Returns [`None`] if accounts are never locked, i.e. if
+/// no threshold is set or it is zero.
+fn read_lockout_options(reader: &Reader) -> Option<LockoutOptions> {
+    let defaults = LockoutOptions::default();
+    let threshold = reader.parse(vars::LOCKOUT_THRESHOLD, 0);
+    (threshold > 0).then(|| LockoutOptions {
+        threshold,
+        window: Duration::from_secs(reader.parse(vars::LOCKOUT_WINDOW, defaults.window.as_secs())),
+        duration: Duration::from_secs(
+            reader.parse(vars::LOCKOUT_DURATION, defaults.duration.as_secs()),
+        ),
+    })
+}
+
+/// Reads the account enumeration protection settings. Returns [`None`] if it is disabled. If no
+/// secret is set, a random one is generated, so decoys change when the server restarts.
+fn read_enumeration_protection(reader: &Reader) -> Option<EnumerationProtection> {
+    if !reader.bool(vars::ENUMERATION_PROTECTION) {
+        return None;
+    }
+    let secret = reader.var(vars::ENUMERATION_PROTECTION_SECRET).map_or_else(
+        || {
+            warn!(
+                var = %vars::ENUMERATION_PROTECTION_SECRET,
+                "variable not set; using a random secret",
+            );
+            let mut secret = vec![0; 32];
+            rand::rng().fill_bytes(&mut secret);
+            secret
+        },
+        String::into_bytes,
+    );
+    Some(EnumerationProtection {
+        secret: secret.into(),
+    })
+}
+
+/// Reads what is asked of authenticators when passkeys are registered and used. Unset settings
+/// keep the [default policy][PasskeyPolicy::default()].
+fn read_passkey_policy(reader: &Reader) -> PasskeyPolicy {
+    let default = PasskeyPolicy::default();
+    PasskeyPolicy {
+        user_verification: reader
+            .choice(
+                vars::WEBAUTHN_USER_VERIFICATION,
+                &[
+                    ("required", UserVerificationPolicy::Required),
+                    ("preferred", UserVerificationPolicy::Preferred),
+                ],
+            )
+            .unwrap_or(default.user_verification),
+        resident_key: reader
+            .choice(
+                vars::WEBAUTHN_RESIDENT_KEY,
+                &[
+                    ("required", ResidentKeyRequirement::Required),
+                    ("preferred", ResidentKeyRequirement::Preferred),
+                    ("discouraged", ResidentKeyRequirement::Discouraged),
+                ],
+            )
+            .unwrap_or(default.resident_key),
+        authenticator_attachment: reader.choice(
+            vars::WEBAUTHN_AUTHENTICATOR_ATTACHMENT,
+            &[
+                ("platform", AuthenticatorAttachment::Platform),
+                ("cross-platform", AuthenticatorAttachment::CrossPlatform),
+            ],
+        ),
+        attestation: AttestationPolicy {
+            required: reader.bool(vars::WEBAUTHN_REQUIRE_ATTESTATION),
+            allowed_aaguids: read_aaguids(reader, vars::WEBAUTHN_ALLOWED_AAGUIDS),
+            denied_aaguids: read_aaguids(reader, vars::WEBAUTHN_DENIED_AAGUIDS),
+        },
+    }
+}
+
+/// Reads a comma-separated list of authenticator AAGUIDs.
+fn read_aaguids(reader: &Reader, name: &str) -> HashSet<Uuid> {
+    let Some(aaguids) = reader.var(name) else {
+        return HashSet::new();
+    };
+    aaguids
+        .split(',')
+        .map(str::trim)
+        .filter(|aaguid| !aaguid.is_empty())
+        .filter_map(|aaguid| {
+            Uuid::parse_str(aaguid)
+                .map_err(|err| reader.problem(name, format!("invalid AAGUID `{aaguid}`: {err}")))
+                .ok()
+        })
+        .collect()
+}
+
+/// Reads the rate limits on login and registration requests. A limit with a maximum of zero is
+/// disabled.
+fn read_rate_limit_options(reader: &Reader) -> RateLimitOptions {
+    let read_limit = |max_var, window_var, default: RateLimit| {
+        let max_requests = reader.parse(max_var, default.max_requests);
+        (max_requests > 0).then(|| RateLimit {
+            max_requests,
+            window: Duration::from_secs(reader.parse(window_var, default.window.as_secs())),
+        })
+    };
+    RateLimitOptions {
+        per_ip: read_limit(
+            vars::RATE_LIMIT_IP_MAX,
+            vars::RATE_LIMIT_IP_WINDOW,
+            RateLimit::DEFAULT_PER_IP,
+        ),
+        per_email: read_limit(
+            vars::RATE_LIMIT_EMAIL_MAX,
+            vars::RATE_LIMIT_EMAIL_WINDOW,
+            RateLimit::DEFAULT_PER_EMAIL,
+        ),
+    }
+}
+
+/// Reads the email transport, templates, and sender, and checks that they are complete. Returns
+/// [`None`] if no email transport is configured or the configuration is invalid.
+fn read_email_settings(
+    reader: &Reader,
+    config: &AppConfig,
+    app_url: &str,
+) -> Option<EmailSettings> {
+    let Some(transport) = reader.var(vars::EMAIL_TRANSPORT) else {
+        info!(var = %vars::EMAIL_TRANSPORT, "email transport not set; emails are disabled");
+        return None;
+    };
+    let transport = read_email_transport(reader, &transport);
+    let templates_dir = reader.path(vars::EMAIL_TEMPLATES_DIR);
+    let templates = EmailTemplates::new(config, app_url, templates_dir.as_deref())
+        .map_err(|err| {
+            reader.problem(
+                vars::EMAIL_TEMPLATES_DIR,
+                format!("failed to load email templates: {err}"),
+            );
+        })
+        .ok();
+    let from = reader.required(vars::EMAIL_FROM);
+    if let Some(from) = &from
+        && !from.contains('@')
+    {
+        reader.problem(
+            vars::EMAIL_FROM,
+            format!("`{from}` is not an email address; expected e.g. `IAM <iam@example.com>`"),
+        );
+    }
+    let queue = QueueOptions {
+        max_attempts: reader.parse(
+            vars::EMAIL_MAX_ATTEMPTS,
+            QueueOptions::default().max_attempts,
+        ),
+        ..Default::default()
+    };
+    Some(EmailSettings {
+        transport: transport?,
+        templates: templates?,
+        from: from?,
+        queue,
+    })
+}
+
+/// Creates the email transport with the given name, configured by other settings. Returns
+/// [`None`] if the name is invalid or the configuration is incomplete.
+fn read_email_transport(reader: &Reader, name: &str) -> Option<Arc<dyn Transport>> {
+    match name {
+        "log" => Some(Arc::new(LogTransport)),
+        "smtp" => {
+            let options = read_smtp_options(reader);
+            Some(Arc::new(SmtpTransport::new(options?)))
+        }
+        "sendgrid" => {
+            let api_key = reader.required(vars::SENDGRID_API_KEY);
+            Some(Arc::new(SendGridTransport::new(api_key?)))
+        }
+        "mailgun" => {
+            let api_key = reader.required(vars::MAILGUN_API_KEY);
+            let domain = reader.required(vars::MAILGUN_DOMAIN);
+            let api_base = reader
+                .var(vars::MAILGUN_API_BASE)
+                .unwrap_or_else(|| MailgunTransport::DEFAULT_API_BASE.to_string());
+            Some(Arc::new(MailgunTransport::new(
+                api_key?, &domain?, &api_base,
+            )))
+        }
+        "ses" => {
+            let region = reader.required(vars::AWS_REGION);
+            let credentials = read_aws_credentials(reader);
+            Some(Arc::new(SesTransport::new(region?, credentials?)))
+        }
+        _ => {
+            reader.problem(
+                vars::EMAIL_TRANSPORT,
+                format!(
+                    "invalid email transport `{name}`; expected `log`, `smtp`, `sendgrid`, `mailgun`, or `ses`"
+                ),
+            );
+            None
+        }
+    }
+}
+
+/// Reads the SMTP relay settings. Returns [`None`] if the host is missing, if only one of the
+/// username and password is set, or if credentials are set for an unencrypted connection.
+fn read_smtp_options(reader: &Reader) -> Option<SmtpOptions> {
+    let host = reader.required(vars::SMTP_HOST);
+    let security = reader.parse(vars::SMTP_SECURITY, SmtpSecurity::default());
+    let credentials = match (
+        reader.var(vars::SMTP_USERNAME),
+        reader.var(vars::SMTP_PASSWORD),
+    ) {
+        (Some(username), Some(password)) => Some(SmtpCredentials { username, password }),
+        (None, None) => None,
+        (Some(_), None) => {
+            reader.problem(vars::SMTP_PASSWORD, "must be set if the username is set");
+            return None;
+        }
+        (None, Some(_)) => {
+            reader.problem(vars::SMTP_USERNAME, "must be set if the password is set");
+            return None;
+        }
+    };
+    if credentials.is_some() && security == SmtpSecurity::None {
+        reader.problem(
+            vars::SMTP_SECURITY,
+            "must be `starttls` or `tls` if credentials are set, so that they aren't sent in \
+            plain text",
+        );
+        return None;
+    }
+    Some(SmtpOptions {
+        host: host?,
+        port: reader.parse(vars::SMTP_PORT, security.default_port()),
+        security,
+        credentials,
+    })
+}
+
+/// Reads the credentials for AWS services. Returns [`None`] if any are missing.
+fn read_aws_credentials(reader: &Reader) -> Option<AwsCredentials> {
+    let access_key_id = reader.required(vars::AWS_ACCESS_KEY_ID);
+    let secret_access_key = reader.required(vars::AWS_SECRET_ACCESS_KEY);
+    Some(AwsCredentials {
+        access_key_id: access_key_id?,
+        secret_access_key: secret_access_key?,
+        session_token: reader.var(vars::AWS_SESSION_TOKEN),
+    })
+}
+
+/// Reads the database backend and the stores wrapped around it. Returns a placeholder if the
+/// backend is invalid, in which case a problem is recorded.
+fn read_db_settings(reader: &Reader) -> DbSettings {
+    let backend = reader.required(vars::DB_BACKEND).and_then(|name| {
+        let backend = match name.as_str() {
+            #[cfg(feature = "sqlite3")]
+            "sqlite3" | "sqlite" => Some(DbBackend::Sqlite {
+                path: reader.path(vars::DB_PATH).or_else(|| {
+                    reader.problem(vars::DB_PATH, "required setting is not set");
+                    None
+                })?,
+            }),
+            _ => None,
+        };
+        if backend.is_none() {
+            reader.problem(
+                vars::DB_BACKEND,
+                format!("invalid or unsupported database backend `{name}`"),
+            );
+        }
+        backend
+    });
+    DbSettings {
+        backend: backend.unwrap_or_else(placeholder_backend),
+        connect_retry: ConnectRetryOptions {
+            retries: reader.parse(vars::DB_CONNECT_RETRIES, 0),
+            base_delay: Duration::from_secs(reader.parse(
+                vars::DB_CONNECT_RETRY_DELAY,
+                defaults::DB_CONNECT_RETRY_DELAY,
+            )),
+            max_delay: Duration::from_secs(reader.parse(
+                vars::DB_CONNECT_RETRY_MAX_DELAY,
+                defaults::DB_CONNECT_RETRY_MAX_DELAY,
+            )),
+        },
+        require_migrated: reader.bool(vars::DB_REQUIRE_MIGRATED),
+        cache_ttl: Duration::from_secs(reader.parse(vars::DB_CACHE_TTL, defaults::DB_CACHE_TTL)),
+        #[cfg(feature = "redis")]
+        redis: reader.var(vars::REDIS_URL).map(|url| RedisSettings {
+            url,
+            key_prefix: reader
+                .var(vars::REDIS_KEY_PREFIX)
+                .unwrap_or_else(|| defaults::REDIS_KEY_PREFIX.to_string()),
+        }),
+    }
+}
+
+/// Returns a backend to use in place of an invalid one, so that the rest of the configuration can
+/// still be checked.
+#[cfg(feature = "sqlite3")]
+fn placeholder_backend() -> DbBackend {
+    DbBackend::Sqlite {
+        path: PathBuf::new(),
+    }
+}
+
+/// Without any backends, the configuration can never be valid.
+#[cfg(not(feature = "sqlite3"))]
+fn placeholder_backend() -> DbBackend {
+    panic!("the server was built without any database backends")
+}
+
+/// Reads the database fault injection options.
+#[cfg(feature = "chaos")]
+fn read_chaos_options(reader: &Reader) -> ChaosOptions {
+    ChaosOptions {
+        min_latency: Duration::from_millis(reader.parse(vars::DB_CHAOS_MIN_LATENCY_MS, 0)),
+        max_latency: Duration::from_millis(reader.parse(vars::DB_CHAOS_MAX_LATENCY_MS, 0)),
+        error_rate: reader.parse(vars::DB_CHAOS_ERROR_RATE, 0.0),
+        ..ChaosOptions::default()
+    }
+}
+
+/// Normalizes a base path given by the user so that it either is empty (meaning the root) or
+/// starts with a slash and does not end with one.
+fn normalize_base_path(path: &str) -> String {
+    let trimmed = path.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{trimmed}")
+    }
+}
+
+/// # Configuration reader
+///
+/// Reads settings from environment variables, falling back to the configuration file, and
+/// records problems with them instead of failing on the first one, so that [`Reader::check()`]
+/// can report them all at once. Readers return a fallback value when a setting is invalid so
+/// that the remaining settings can still be checked.
+struct Reader {
+    env: HashMap<String, OsString>,
+    /// Values from the configuration file, by setting name
+    file: HashMap<String, String>,
+    problems: RefCell<Vec<ConfigProblem>>,
+}
+
+impl Reader {
+    /// Creates a reader for the given environment variables, without a configuration file.
+    fn new(env: impl IntoIterator<Item = (OsString, OsString)>) -> Self {
+        Self {
+            env: env
+                .into_iter()
+                .filter_map(|(name, value)| Some((name.into_string().ok()?, value)))
+                .collect(),
+            file: HashMap::new(),
+            problems: RefCell::default(),
+        }
+    }
+
+    /// Creates a reader for the process's environment and the configuration file at the given
+    /// path, if any, with the given settings taking precedence over both.
+    fn load(
+        file: Option<&Path>,
+        overrides: impl IntoIterator<Item = (&'static str, OsString)>,
+    ) -> Self {
+        let mut reader = Self::new(std::env::vars_os());
+        if let Some(path) = file {
+            match std::fs::read_to_string(path) {
+                Ok(contents) => reader.add_file(path, &contents),
+                Err(err) => reader.problem(
+                    &path.display().to_string(),
+                    format!("failed to read configuration file: {err}"),
+                ),
+            }
+        }
+        reader.add_overrides(overrides);
+        reader
+    }
+
+    /// Sets the given settings, overriding both the environment and the configuration file.
+    fn add_overrides(&mut self, overrides: impl IntoIterator<Item = (&'static str, OsString)>) {
+        self.env.extend(
+            overrides
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value)),
+        );
+    }
+
+    /// Adds the settings from the configuration file at the given path, with the given contents.
+    /// The file is parsed as YAML if the path ends in `.yaml` or `.yml`, and as TOML otherwise.
+    fn add_file(&mut self, path: &Path, contents: &str) {
+        let is_yaml = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"));
+        let parsed = if is_yaml {
+            serde_yaml::from_str::<Value>(contents).map_err(|err| err.to_string())
+        } else {
+            toml::from_str::<Value>(contents).map_err(|err| err.to_string())
+        };
+        let file = path.display().to_string();
+        match parsed {
+            Ok(Value::Object(settings)) => {
+                for (key, value) in settings {
+                    self.add_setting(&file, &setting_name("", &key), value);
+                }
+            }
+            // An empty YAML document
+            Ok(Value::Null) => {}
+            Ok(_) => self.problem(&file, "configuration file must contain a table of settings"),
+            Err(err) => self.problem(&file, format!("invalid configuration file: {err}")),
+        }
+    }
+
+    /// Adds a setting from the configuration file, or the settings in a table.
+    fn add_setting(&mut self, file: &str, name: &str, value: Value) {
+        let value = match value {
+            Value::Object(table) => {
+                for (key, value) in table {
+                    self.add_setting(file, &setting_name(name, &key), value);
+                }
+                return;
+            }
+            Value::Null => return,
+            Value::Array(items) => items
+                .into_iter()
+                .map(scalar_to_string)
+                .collect::<Option<Vec<_>>>()
+                .map(|items| items.join(",")),
+            value => scalar_to_string(value),
+        };
+        if !vars::ALL.contains(&name) {
+            self.problem(file, format!("unknown setting `{name}`"));
+        } else if let Some(value) = value {
+            self.file.insert(name.to_string(), value);
+        } else {
+            self.problem(
+                name,
+                "lists may only contain strings, numbers, and booleans",
+            );
+        }
+    }
+
+    /// Records a problem with the given setting.
+    fn problem(&self, setting: &str, message: impl Display) {
+        self.problems.borrow_mut().push(ConfigProblem {
+            setting: setting.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    /// Returns the value of the given setting, or [`None`] if it is not set. Records a problem if
+    /// its environment variable is not valid UTF-8.
+    fn var(&self, name: &str) -> Option<String> {
+        match self.env.get(name) {
+            Some(value) => value.to_str().map(str::to_string).or_else(|| {
+                self.problem(name, "environment variable is not valid UTF-8");
+                None
+            }),
+            None => self.file.get(name).cloned(),
+        }
+    }
+
+    /// Returns the value of the given setting as a path, or [`None`] if it is not set.
+    fn path(&self, name: &str) -> Option<PathBuf> {
+        match self.env.get(name) {
+            Some(value) => Some(PathBuf::from(value)),
+            None => self.file.get(name).map(PathBuf::from),
+        }
+    }
+
+    /// Returns the value of the given setting. Records a problem if it is not set.
+    fn required(&self, name: &str) -> Option<String> {
+        if !self.env.contains_key(name) && !self.file.contains_key(name) {
+            self.problem(name, "required setting is not set");
+        }
+        self.var(name)
+    }
+
+    /// Parses the given setting, returning `default` if it is not set. Records a problem and
+    /// returns `default` if it is set but invalid.
+    fn parse<T>(&self, name: &str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let Some(value) = self.var(name) else {
+            return default;
+        };
+        value.parse().unwrap_or_else(|err| {
+            self.problem(name, format!("invalid value `{value}`: {err}"));
+            default
+        })
+    }
+
+    /// Returns the value paired with the given setting's value in `choices`, or [`None`] if the
+    /// setting isn't set. Records a problem if the value isn't one of the choices.
+    fn choice<T: Copy>(&self, name: &str, choices: &[(&str, T)]) -> Option<T> {
+        let value = self.var(name)?;
+        let choice = choices
+            .iter()
+            .find(|(choice, _)| value == *choice)
+            .map(|&(_, choice)| choice);
+        if choice.is_none() {
+            let expected: Vec<_> = choices
+                .iter()
+                .map(|(choice, _)| format!("`{choice}`"))
+                .collect();
+            self.problem(
+                name,
+                format!(
+                    "invalid value `{value}`; expected one of {}",
+                    expected.join(", ")
+                ),
+            );
+        }
+        choice
+    }
+
+    /// Returns whether the given setting is set to a truthy value (`1`, `true`, `yes`, or `on`,
+    /// case-insensitive). Unset settings are treated as `false`. Records a problem if the value
+    /// isn't a recognized boolean.
+    fn bool(&self, name: &str) -> bool {
+        let Some(value) = self.var(name) else {
+            return false;
+        };
+        match value.to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => true,
+            "" | "0" | "false" | "no" | "off" => false,
+            _ => {
+                self.problem(
+                    name,
+                    format!("invalid value `{value}`; expected `true` or `false`"),
+                );
+                false
+            }
+        }
+    }
+
+    /// Returns the recorded problems, if there were any.
+    fn check(self) -> Result<(), Vec<ConfigProblem>> {
+        let problems = self.problems.into_inner();
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+/// Returns the name of the setting with the given key in the configuration file, within the
+/// table of settings whose names start with `prefix`.
+fn setting_name(prefix: &str, key: &str) -> String {
+    let key = key.to_ascii_uppercase().replace('-', "_");
+    if prefix.is_empty() {
+        key
+    } else {
+        format!("{prefix}_{key}")
+    }
+}
+
+/// Converts a scalar value from the configuration file to the string form used by environment
+/// variables. Returns [`None`] if the value isn't a scalar.
+fn scalar_to_string(value: Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader(env: &[(&str, &str)], file: Option<(&str, &str)>) -> Reader {
+        let mut reader = Reader::new(
+            env.iter()
+                .map(|(name, value)| (OsString::from(name), OsString::from(value))),
+        );
+        if let Some((path, contents)) = file {
+            reader.add_file(Path::new(path), contents);
+        }
+        reader
+    }
+
+    fn settings(problems: &[ConfigProblem]) -> Vec<&str> {
+        problems
+            .iter()
+            .map(|problem| problem.setting.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_file_formats() {
+        let toml = r#"
+            origin = "https://iam.example.com"
+            session-cache-ttl = 10
+
+            [alert]
+            emails = ["a@kasad.com", "b@kasad.com"]
+        "#;
+        let yaml = "
+            origin: https://iam.example.com
+            session_cache_ttl: 10
+            alert:
+              emails: [a@kasad.com, b@kasad.com]
+        ";
+        for file in [("iam.toml", toml), ("iam.yaml", yaml)] {
+            let reader = reader(&[], Some(file));
+            assert_eq!(
+                reader.var(vars::ORIGIN).as_deref(),
+                Some("https://iam.example.com")
+            );
+            assert_eq!(reader.parse(vars::SESSION_CACHE_TTL, 0), 10);
+            assert_eq!(
+                reader.var(vars::ALERT_EMAILS).as_deref(),
+                Some("a@kasad.com,b@kasad.com")
+            );
+            assert!(reader.check().is_ok(), "{}", file.0);
+        }
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let file = (
+            "iam.toml",
+            "server_name = \"From file\"\nrp_id = \"example.com\"",
+        );
+        let mut reader = reader(&[("SERVER_NAME", "From env")], Some(file));
+        assert_eq!(reader.var(vars::SERVER_NAME).as_deref(), Some("From env"));
+        assert_eq!(reader.var(vars::RP_ID).as_deref(), Some("example.com"));
+
+        reader.add_overrides([(vars::SERVER_NAME, OsString::from("From argument"))]);
+        assert_eq!(
+            reader.var(vars::SERVER_NAME).as_deref(),
+            Some("From argument")
+        );
+    }
+
+    #[test]
+    fn test_problems_reported_together() {
+        let file = (
+            "iam.toml",
+            "orign = \"https://iam.example.com\"\n[email]\ntransport = \"carrier-pigeon\"",
+        );
+        let env = [
+            ("SESSION_CACHE_TTL", "soon"),
+            ("MAINTENANCE_MODE", "true"),
+            ("DB_BACKEND", "sqlite"),
+            ("DB_PATH", "iam.sqlite3"),
+        ];
+        let Err(problems) = ServerConfig::read(reader(&env, Some(file))) else {
+            panic!("invalid configuration was accepted");
+        };
+        assert_eq!(
+            settings(&problems),
+            [
+                "iam.toml",
+                "ORIGIN",
+                "EMAIL_TRANSPORT",
+                "EMAIL_FROM",
+                "SESSION_CACHE_TTL"
+            ]
+        );
+        assert_eq!(problems[0].message, "unknown setting `ORIGN`");
+
+        let Err(problems) = ServerConfig::read(reader(&[], Some(("iam.yml", "- a")))) else {
+            panic!("invalid configuration was accepted");
+        };
+        assert_eq!(
+            problems[0].message,
+            "configuration file must contain a table of settings"
+        );
+    }
+
+    #[test]
+    fn test_smtp_options() {
+        let env = [("SMTP_HOST", "mail.kasad.com"), ("SMTP_SECURITY", "tls")];
+        let options = read_smtp_options(&reader(&env, None)).unwrap();
+        assert_eq!(options.port, 465);
+        assert!(options.credentials.is_none());
+
+        let env = [("SMTP_HOST", "mail.kasad.com"), ("SMTP_USERNAME", "iam")];
+        let incomplete = reader(&env, None);
+        assert!(read_smtp_options(&incomplete).is_none());
+        let Err(problems) = incomplete.check() else {
+            panic!("incomplete SMTP settings were accepted");
+        };
+        assert_eq!(settings(&problems), ["SMTP_PASSWORD"]);
+
+        let env = [
+            ("SMTP_HOST", "mail.kasad.com"),
+            ("SMTP_SECURITY", "none"),
+            ("SMTP_USERNAME", "iam"),
+            ("SMTP_PASSWORD", "hunter2"),
+        ];
+        let insecure = reader(&env, None);
+        assert!(read_smtp_options(&insecure).is_none());
+        let Err(problems) = insecure.check() else {
+            panic!("unencrypted SMTP credentials were accepted");
+        };
+        assert_eq!(settings(&problems), ["SMTP_SECURITY"]);
+    }
+
+    #[test]
+    fn test_session_lifetimes() {
+        let defaults = SessionLifetimes::default();
+        let env = [("SESSION_LIFETIME", "604800")];
+        let lifetimes = read_session_lifetimes(&reader(&env, None));
+        assert_eq!(lifetimes.session_secs, 7 * 24 * 60 * 60);
+        assert_eq!(lifetimes.admin_session_secs, defaults.admin_session_secs);
+
+        let env = [("ADMIN_SESSION_LIFETIME", "0")];
+        let invalid = reader(&env, None);
+        read_session_lifetimes(&invalid);
+        let Err(problems) = invalid.check() else {
+            panic!("zero session lifetime was accepted");
+        };
+        assert_eq!(settings(&problems), ["ADMIN_SESSION_LIFETIME"]);
+    }
+
+    #[test]
+    fn test_session_cookie_options() {
+        let options = read_session_cookie_options(&reader(&[], None));
+        assert!(!options.host_prefix);
+        assert!(options.keys.is_none());
+
+        let env = [
+            ("SESSION_COOKIE_HOST_PREFIX", "true"),
+            (
+                "SESSION_COOKIE_KEYS",
+                "k2:AgICAgICAgICAgICAgICAgICAgICAgICAgICAgICAgI=, k1:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=",
+            ),
+        ];
+        let options = read_session_cookie_options(&reader(&env, None));
+        assert!(options.host_prefix);
+        assert_eq!(options.keys.unwrap().active_key_id(), "k2");
+
+        let env = [(
+            "SESSION_COOKIE_KEYS",
+            "k.1:AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=",
+        )];
+        let invalid = reader(&env, None);
+        read_session_cookie_options(&invalid);
+        let Err(problems) = invalid.check() else {
+            panic!("invalid session cookie key was accepted");
+        };
+        assert_eq!(settings(&problems), ["SESSION_COOKIE_KEYS"]);
+    }
+
+    #[test]
+    fn test_trusted_proxies() {
+        let proxies = reader(&[], None).parse(vars::TRUSTED_PROXIES, TrustedProxies::default());
+        assert_eq!(proxies, TrustedProxies::default());
+
+        let env = [("TRUSTED_PROXIES", "10.0.0.0/8, ::1")];
+        let proxies = reader(&env, None).parse(vars::TRUSTED_PROXIES, TrustedProxies::default());
+        assert!(proxies.contains("10.1.2.3".parse().unwrap()));
+        assert!(proxies.contains("::1".parse().unwrap()));
+
+        let env = [("TRUSTED_PROXIES", "10.0.0.0/8, proxy.local")];
+        let invalid = reader(&env, None);
+        invalid.parse(vars::TRUSTED_PROXIES, TrustedProxies::default());
+        let Err(problems) = invalid.check() else {
+            panic!("invalid trusted proxy was accepted");
+        };
+        assert_eq!(settings(&problems), ["TRUSTED_PROXIES"]);
+    }
+
+    #[test]
+    fn test_db_connect_retry() {
+        let env = [
+            ("DB_BACKEND", "sqlite"),
+            ("DB_PATH", "db.sqlite3"),
+            ("DB_CONNECT_RETRIES", "10"),
+            ("DB_CONNECT_RETRY_DELAY", "2"),
+        ];
+        let settings = read_db_settings(&reader(&env, None));
+        assert_eq!(
+            settings.connect_retry,
+            ConnectRetryOptions {
+                retries: 10,
+                base_delay: Duration::from_secs(2),
+                max_delay: Duration::from_secs(defaults::DB_CONNECT_RETRY_MAX_DELAY),
+            }
+        );
+
+        let env = [("DB_BACKEND", "sqlite"), ("DB_PATH", "db.sqlite3")];
+        let settings = read_db_settings(&reader(&env, None));
+        assert_eq!(settings.connect_retry.retries, 0);
+    }
+
+    #[test]
+    fn test_allowed_email_domains() {
+        let env = [("ALLOWED_EMAIL_DOMAINS", "kasad.com, @Example.COM,,")];
+        assert_eq!(
+            read_allowed_email_domains(&reader(&env, None)),
+            ["kasad.com", "example.com"]
+        );
+        assert!(read_allowed_email_domains(&reader(&[], None)).is_empty());
+    }
+
+    #[test]
+    fn test_passkey_policy() {
+        assert_eq!(
+            read_passkey_policy(&reader(&[], None)),
+            PasskeyPolicy::default()
+        );
+        let env = [
+            ("WEBAUTHN_USER_VERIFICATION", "preferred"),
+            ("WEBAUTHN_RESIDENT_KEY", "required"),
+            ("WEBAUTHN_AUTHENTICATOR_ATTACHMENT", "cross-platform"),
+        ];
+        assert_eq!(
+            read_passkey_policy(&reader(&env, None)),
+            PasskeyPolicy {
+                user_verification: UserVerificationPolicy::Preferred,
+                resident_key: ResidentKeyRequirement::Required,
+                authenticator_attachment: Some(AuthenticatorAttachment::CrossPlatform),
+                attestation: AttestationPolicy::default(),
+            }
+        );
+
+        let yubikey = "cb69481e-8ff7-4039-93ec-0a2729a154a8";
+        let env = [
+            ("WEBAUTHN_REQUIRE_ATTESTATION", "true"),
+            ("WEBAUTHN_ALLOWED_AAGUIDS", &format!("{yubikey}, ")),
+        ];
+        let attestation = read_passkey_policy(&reader(&env, None)).attestation;
+        assert!(attestation.required);
+        assert_eq!(
+            attestation.allowed_aaguids,
+            HashSet::from([yubikey.parse().unwrap()])
+        );
+        assert!(attestation.denied_aaguids.is_empty());
+
+        let env = [
+            ("WEBAUTHN_USER_VERIFICATION", "discouraged"),
+            ("WEBAUTHN_AUTHENTICATOR_ATTACHMENT", "usb"),
+            ("WEBAUTHN_DENIED_AAGUIDS", "yubikey"),
+        ];
+        let invalid = reader(&env, None);
+        read_passkey_policy(&invalid);
+        let Err(problems) = invalid.check() else {
+            panic!("invalid passkey policy was accepted");
+        };
+        assert_eq!(
+            settings(&problems),
+            [
+                "WEBAUTHN_USER_VERIFICATION",
+                "WEBAUTHN_AUTHENTICATOR_ATTACHMENT",
+                "WEBAUTHN_DENIED_AAGUIDS"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_relying_parties() {
+        let origin = Url::parse("https://iam.example.com").unwrap();
+        let env = [(
+            "RELYING_PARTIES",
+            "https://login.example.org, https://auth.kasad.com|kasad.com|Kasad Login,",
+        )];
+        let parties = read_relying_parties(&reader(&env, None), &origin, "IAM");
+        assert_eq!(
+            parties,
+            [
+                RelyingPartyConfig {
+                    origin: Url::parse("https://login.example.org").unwrap(),
+                    rp_id: "login.example.org".to_string(),
+                    name: "IAM".to_string(),
+                },
+                RelyingPartyConfig {
+                    origin: Url::parse("https://auth.kasad.com").unwrap(),
+                    rp_id: "kasad.com".to_string(),
+                    name: "Kasad Login".to_string(),
+                },
+            ]
+        );
+        assert!(read_relying_parties(&reader(&[], None), &origin, "IAM").is_empty());
+
+        let env = [(
+            "RELYING_PARTIES",
+            "https://IAM.example.com,https://auth.kasad.com|example.com,ftp://kasad.com",
+        )];
+        let invalid = reader(&env, None);
+        assert_eq!(read_relying_parties(&invalid, &origin, "IAM").len(), 1);
+        let Err(problems) = invalid.check() else {
+            panic!("invalid relying parties were accepted");
+        };
+        assert_eq!(settings(&problems), ["RELYING_PARTIES"; 3]);
+        assert_eq!(
+            problems[0].message,
+            "more than one relying party is served at `iam.example.com`"
+        );
+    }
+
+    #[test]
+    fn test_valid_config() {
+        let file = (
+            "iam.toml",
+            r#"
+                origin = "https://iam.example.com/"
+                base_path = "/iam/"
+                maintenance_mode = true
+
+                [db]
+                backend = "sqlite"
+                path = "/var/lib/iam/db.sqlite3"
+
+                [retention]
+                sessions_days = 30
+            "#,
+        );
+        let config = match ServerConfig::read(reader(&[("RP_ID", "example.com")], Some(file))) {
+            Ok(config) => config,
+            Err(problems) => panic!("{problems:?}"),
+        };
+        assert_eq!(config.listen_addr.to_string(), "0.0.0.0:3000");
+        assert_eq!(
+            config.db.backend,
+            DbBackend::Sqlite {
+                path: PathBuf::from("/var/lib/iam/db.sqlite3")
+            }
+        );
+        assert_eq!(config.origin.as_str(), "https://iam.example.com/");
+        assert_eq!(config.rp_id, "example.com");
+        assert_eq!(config.app.instance_name, "iam.example.com");
+        assert_eq!(config.app.base_path, "/iam");
+        assert!(config.app.features.oidc);
+        assert!(config.ui.maintenance);
+        assert!(config.email.is_none());
+        assert_eq!(
+            config.retention.policy.terminated_sessions,
+            Some(Duration::from_secs(30 * 24 * 60 * 60))
+        );
+    }
+}
@@ -0,0 +1,77 @@
+//! # Multi-instance coordination
+//!
+//! Several server instances can share one database, e.g. when running replicas behind a load
+//! balancer. Most state is stored in the database and is therefore shared, but some work must
+//! only be done by one instance at a time. The [`Coordinator`] elects a leader for each such
+//! background job using leases stored in the database: an instance only runs a job while it
+//! holds the job's lease, and renews the lease every time it runs the job. If the leader stops,
+//! its lease expires and another instance takes over.
+//!
+//! Passkey ceremony state is stored in the database, so a ceremony can be started on one
+//! instance and finished on another. The following state is kept in memory by each instance and
+//! is therefore not shared:
+//!
+//! - the session cache, which keeps sessions for a few seconds, so a session revoked on another
+//!   instance may still be accepted here until its cached copy expires;
+//! - the copy of the [IP ban list][crate::bans::IpBanList], which is reloaded periodically, so
+//!   bans added on another instance may take up to a minute to apply;
+//! - the copy of the token [signing keys][crate::crypto::signing], which is reloaded every few
+//!   minutes, so a key rotated by another instance may take that long to be used here;
+//! - counts of failed logins used for [automatic bans][crate::bans::AutoBanOptions] and
+//!   [alert thresholds][crate::alerts::AlertOptions], which are counted per instance.
+
+use std::{sync::Arc, time::Duration};
+
+use tracing::{debug, error};
+use uuid::Uuid;
+
+use crate::{db::interface::DatabaseClient, models::new_uuid};
+
+/// # Background job coordinator
+///
+/// See the [module-level documentation][self] for details.
+pub struct Coordinator {
+    db: Arc<dyn DatabaseClient>,
+    instance_id: Uuid,
+}
+
+impl Coordinator {
+    /// Creates a coordinator for this instance, which stores leases in the given database.
+    #[must_use]
+    pub fn new(db: Arc<dyn DatabaseClient>) -> Self {
+        Self {
+            db,
+            instance_id: new_uuid(),
+        }
+    }
+
+    /// Returns the random UUID which identifies this instance.
+    #[must_use]
+    pub fn instance_id(&self) -> &Uuid {
+        &self.instance_id
+    }
+
+    /// Returns whether this instance should run the job named `job`, which runs every
+    /// `interval`. This acquires or renews the job's lease. The lease lasts for two intervals, so
+    /// the leader keeps it as long as it keeps running the job.
+    ///
+    /// Returns `false` if the lease can't be checked, so that the job is skipped rather than
+    /// possibly run twice.
+    pub async fn should_run(&self, job: &str, interval: Duration) -> bool {
+        match self
+            .db
+            .try_acquire_lease(job, &self.instance_id, interval * 2)
+            .await
+        {
+            Ok(true) => true,
+            Ok(false) => {
+                debug!(job, "skipping job; another instance is running it");
+                false
+            }
+            Err(err) => {
+                error!(%err, job, "failed to acquire job lease; skipping job");
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,472 @@
+//! # JSON Web Tokens
+//!
+//! Issues and verifies JWTs signed with ES256 (ECDSA using P-256 and SHA-256), such as the ID
+//! tokens issued by the OIDC provider. The public halves of the [`SigningKey`]s are
+//! published as a [JSON Web Key Set][Jwks] so that relying parties can verify the tokens.
+
+use std::sync::RwLock;
+
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use openssl::{
+    asn1::Asn1Time,
+    bn::{BigNum, BigNumContext, MsbOption},
+    ec::{EcGroup, EcKey},
+    ecdsa::EcdsaSig,
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private, Public},
+    x509::{X509, X509Builder, X509NameBuilder},
+};
+use rand::RngCore;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+
+use crate::crypto::CryptoError;
+
+/// Algorithm with which tokens are signed
+pub const ALGORITHM: &str = "ES256";
+
+/// Length of each of the two halves of an ES256 signature, and of each coordinate of a P-256
+/// public key, in bytes
+const COORDINATE_LEN: usize = 32;
+#[expect(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+const PADDED_LEN: i32 = COORDINATE_LEN as i32;
+
+/// Errors which can occur while verifying a token
+#[derive(Debug, thiserror::Error)]
+pub enum JwtError {
+    #[error("token is malformed")]
+    Malformed,
+
+    #[error("token was signed with unknown key `{0}`")]
+    UnknownKey(String),
+
+    #[error("token signature is invalid")]
+    InvalidSignature,
+
+    #[error("token has expired")]
+    Expired,
+
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+}
+
+impl From<openssl::error::ErrorStack> for JwtError {
+    fn from(error: openssl::error::ErrorStack) -> Self {
+        Self::Crypto(error.into())
+    }
+}
+
+/// Header of a JWT
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    typ: Option<String>,
+    kid: String,
+}
+
+/// Public key in JSON Web Key format
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Jwk {
+    pub kty: String,
+    pub crv: String,
+    pub x: String,
+    pub y: String,
+    pub kid: String,
+    #[serde(rename = "use")]
+    pub use_: String,
+    pub alg: String,
+}
+
+/// JSON Web Key Set, as served to relying parties
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+/// # ES256 signing key
+///
+/// A P-256 private key, identified by a random key ID which is included in the header of the
+/// tokens it signs. Each key comes with a self-signed X.509 certificate, for consumers which
+/// expect keys in that form, such as SAML service providers.
+#[derive(Clone)]
+pub struct SigningKey {
+    kid: String,
+    key: EcKey<Private>,
+    certificate: X509,
+}
+
+impl std::fmt::Debug for SigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Don't leak the key into logs
+        f.debug_struct("SigningKey")
+            .field("kid", &self.kid)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SigningKey {
+    /// Generates a new key with a random key ID.
+    pub fn generate() -> Result<Self, CryptoError> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+        let mut kid = [0u8; 16];
+        rand::rng().fill_bytes(&mut kid);
+        let kid = BASE64_URL_SAFE_NO_PAD.encode(kid);
+        let key = EcKey::generate(&group)?;
+        let certificate = self_signed_certificate(&kid, &key)?;
+        Ok(Self {
+            kid,
+            key,
+            certificate,
+        })
+    }
+
+    /// Loads a key from its ID, PEM-encoded private key, and PEM-encoded certificate, as returned
+    /// by [`to_pem()`][Self::to_pem] and [`certificate_pem()`][Self::certificate_pem]. If the
+    /// certificate is missing, a new one is generated.
+    pub fn from_pem(
+        kid: String,
+        pem: &str,
+        certificate_pem: Option<&str>,
+    ) -> Result<Self, CryptoError> {
+        let key = EcKey::private_key_from_pem(pem.as_bytes())?;
+        let certificate = match certificate_pem {
+            Some(certificate_pem) => X509::from_pem(certificate_pem.as_bytes())?,
+            None => self_signed_certificate(&kid, &key)?,
+        };
+        Ok(Self {
+            kid,
+            key,
+            certificate,
+        })
+    }
+
+    /// Returns the PEM-encoded private key.
+    pub fn to_pem(&self) -> Result<String, CryptoError> {
+        String::from_utf8(self.key.private_key_to_pem()?).map_err(|_| CryptoError::Malformed)
+    }
+
+    /// Returns the PEM-encoded certificate.
+    pub fn certificate_pem(&self) -> Result<String, CryptoError> {
+        String::from_utf8(self.certificate.to_pem()?).map_err(|_| CryptoError::Malformed)
+    }
+
+    /// Returns the DER-encoded certificate.
+    pub fn certificate_der(&self) -> Result<Vec<u8>, CryptoError> {
+        Ok(self.certificate.to_der()?)
+    }
+
+    /// Returns the key's ID.
+    #[must_use]
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    /// Signs the given claims, returning the encoded token. `typ` is the media type placed in the
+    /// header's `typ` field, if any.
+    pub fn sign<C: Serialize>(&self, claims: &C, typ: Option<&str>) -> Result<String, CryptoError> {
+        let header = Header {
+            alg: ALGORITHM.to_string(),
+            typ: typ.map(str::to_string),
+            kid: self.kid.clone(),
+        };
+        let mut token = format!("{}.{}", encode_json(&header), encode_json(claims));
+        let signature = self.sign_bytes(token.as_bytes())?;
+        token.push('.');
+        token.push_str(&BASE64_URL_SAFE_NO_PAD.encode(signature));
+        Ok(token)
+    }
+
+    /// Signs the given data with ECDSA using SHA-256, returning the signature as the concatenated
+    /// big-endian `r` and `s` values, as used by both JWS and XML signatures.
+    pub fn sign_bytes(&self, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        let signature = EcdsaSig::sign(&Sha256::digest(data), &self.key)?;
+        let mut raw = signature.r().to_vec_padded(PADDED_LEN)?;
+        raw.extend(signature.s().to_vec_padded(PADDED_LEN)?);
+        Ok(raw)
+    }
+
+    /// Verifies a signature created by [`sign_bytes()`][Self::sign_bytes].
+    pub fn verify_bytes(&self, data: &[u8], signature: &[u8]) -> Result<bool, CryptoError> {
+        if signature.len() != 2 * COORDINATE_LEN {
+            return Ok(false);
+        }
+        let (r, s) = signature.split_at(COORDINATE_LEN);
+        let signature =
+            EcdsaSig::from_private_components(BigNum::from_slice(r)?, BigNum::from_slice(s)?)?;
+        let public_key = self.public_key()?;
+        Ok(signature.verify(&Sha256::digest(data), &public_key)?)
+    }
+
+    /// Returns the public half of the key as a JWK.
+    pub fn jwk(&self) -> Result<Jwk, CryptoError> {
+        let mut ctx = BigNumContext::new()?;
+        let mut x = BigNum::new()?;
+        let mut y = BigNum::new()?;
+        self.key
+            .public_key()
+            .affine_coordinates(self.key.group(), &mut x, &mut y, &mut ctx)?;
+        Ok(Jwk {
+            kty: "EC".to_string(),
+            crv: "P-256".to_string(),
+            x: BASE64_URL_SAFE_NO_PAD.encode(x.to_vec_padded(PADDED_LEN)?),
+            y: BASE64_URL_SAFE_NO_PAD.encode(y.to_vec_padded(PADDED_LEN)?),
+            kid: self.kid.clone(),
+            use_: "sig".to_string(),
+            alg: ALGORITHM.to_string(),
+        })
+    }
+
+    fn public_key(&self) -> Result<EcKey<Public>, CryptoError> {
+        Ok(EcKey::from_public_key(
+            self.key.group(),
+            self.key.public_key(),
+        )?)
+    }
+}
+
+/// # Set of signing keys
+///
+/// Tokens are signed with the active key, and can be verified with any key in the set.
+#[derive(Debug)]
+pub struct KeySet {
+    keys: RwLock<Vec<SigningKey>>,
+}
+
+impl KeySet {
+    /// Creates a key set whose active key is the given key.
+    #[must_use]
+    pub fn new(key: SigningKey) -> Self {
+        Self::from_keys(vec![key])
+    }
+
+    /// Creates a key set from the given keys. The first key becomes the active key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keys` is empty.
+    #[must_use]
+    pub fn from_keys(keys: Vec<SigningKey>) -> Self {
+        assert!(!keys.is_empty(), "key set must not be empty");
+        Self {
+            keys: RwLock::new(keys),
+        }
+    }
+
+    /// Replaces the keys in the set. The first key becomes the active key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keys` is empty.
+    pub fn replace(&self, keys: Vec<SigningKey>) {
+        assert!(!keys.is_empty(), "key set must not be empty");
+        *self.keys.write().unwrap() = keys;
+    }
+
+    /// Returns the IDs of the keys in the set, starting with the active key.
+    #[must_use]
+    pub fn kids(&self) -> Vec<String> {
+        self.keys
+            .read()
+            .unwrap()
+            .iter()
+            .map(|key| key.kid.clone())
+            .collect()
+    }
+
+    /// Returns the key with which tokens are signed.
+    #[must_use]
+    pub fn active(&self) -> SigningKey {
+        self.keys.read().unwrap()[0].clone()
+    }
+
+    /// Signs the given claims with the active key. See [`SigningKey::sign()`].
+    pub fn sign<C: Serialize>(&self, claims: &C, typ: Option<&str>) -> Result<String, CryptoError> {
+        self.active().sign(claims, typ)
+    }
+
+    /// Returns the public halves of all keys in the set.
+    pub fn jwks(&self) -> Result<Jwks, CryptoError> {
+        let keys = self
+            .keys
+            .read()
+            .unwrap()
+            .iter()
+            .map(SigningKey::jwk)
+            .collect::<Result<_, _>>()?;
+        Ok(Jwks { keys })
+    }
+
+    /// Returns the DER-encoded certificates of all keys in the set.
+    pub fn certificates(&self) -> Result<Vec<Vec<u8>>, CryptoError> {
+        self.keys
+            .read()
+            .unwrap()
+            .iter()
+            .map(SigningKey::certificate_der)
+            .collect()
+    }
+
+    /// Verifies the signature of the given token, which must have been signed by a key in the
+    /// set, and that its `exp` claim is in the future, and returns its claims.
+    pub fn verify<C: DeserializeOwned>(&self, token: &str) -> Result<C, JwtError> {
+        let mut parts = token.split('.');
+        let (Some(header), Some(claims), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(JwtError::Malformed);
+        };
+        let header: Header = decode_json(header)?;
+        if header.alg != ALGORITHM {
+            return Err(JwtError::Malformed);
+        }
+        let key = self
+            .keys
+            .read()
+            .unwrap()
+            .iter()
+            .find(|key| key.kid == header.kid)
+            .cloned()
+            .ok_or_else(|| JwtError::UnknownKey(header.kid.clone()))?;
+
+        let signature = BASE64_URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| JwtError::Malformed)?;
+        let (signed, _) = token.rsplit_once('.').ok_or(JwtError::Malformed)?;
+        if !key.verify_bytes(signed.as_bytes(), &signature)? {
+            return Err(JwtError::InvalidSignature);
+        }
+
+        let value: serde_json::Value = decode_json(claims)?;
+        let expires_at = value
+            .get("exp")
+            .and_then(serde_json::Value::as_i64)
+            .ok_or(JwtError::Malformed)?;
+        if expires_at <= chrono::Utc::now().timestamp() {
+            return Err(JwtError::Expired);
+        }
+        serde_json::from_value(value).map_err(|_| JwtError::Malformed)
+    }
+}
+
+/// Lifetime of the certificates generated for signing keys. Keys are rotated long before this.
+const CERTIFICATE_LIFETIME_DAYS: u32 = 10 * 365;
+
+/// Creates a self-signed certificate for the given key, whose subject is the key's ID.
+fn self_signed_certificate(kid: &str, key: &EcKey<Private>) -> Result<X509, CryptoError> {
+    let pkey = PKey::from_ec_key(key.clone())?;
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, kid)?;
+    let name = name.build();
+    let mut serial = BigNum::new()?;
+    serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+    let serial = serial.to_asn1_integer()?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    let not_after = Asn1Time::days_from_now(CERTIFICATE_LIFETIME_DAYS)?;
+    let mut builder = X509Builder::new()?;
+    builder.set_version(2)?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(&name)?;
+    builder.set_issuer_name(&name)?;
+    builder.set_pubkey(&pkey)?;
+    builder.set_not_before(&not_before)?;
+    builder.set_not_after(&not_after)?;
+    builder.sign(&pkey, MessageDigest::sha256())?;
+    Ok(builder.build())
+}
+
+fn encode_json<T: Serialize + ?Sized>(value: &T) -> String {
+    BASE64_URL_SAFE_NO_PAD
+        .encode(serde_json::to_vec(value).expect("serializing JWT component failed"))
+}
+
+fn decode_json<T: DeserializeOwned>(encoded: &str) -> Result<T, JwtError> {
+    let bytes = BASE64_URL_SAFE_NO_PAD
+        .decode(encoded)
+        .map_err(|_| JwtError::Malformed)?;
+    serde_json::from_slice(&bytes).map_err(|_| JwtError::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let keys = KeySet::new(SigningKey::generate().unwrap());
+        let exp = chrono::Utc::now().timestamp() + 60;
+        let token = keys
+            .sign(&json!({ "sub": "me", "exp": exp }), None)
+            .unwrap();
+        let claims: Value = keys.verify(&token).unwrap();
+        assert_eq!(claims["sub"], "me");
+
+        // Tampering with the claims invalidates the signature
+        let (header, rest) = token.split_once('.').unwrap();
+        let (_, signature) = rest.split_once('.').unwrap();
+        let forged = format!(
+            "{header}.{}.{signature}",
+            encode_json(&json!({ "sub": "you", "exp": exp }))
+        );
+        assert!(matches!(
+            keys.verify::<Value>(&forged),
+            Err(JwtError::InvalidSignature)
+        ));
+
+        // Tokens signed by other keys aren't accepted
+        let other = KeySet::new(SigningKey::generate().unwrap());
+        assert!(matches!(
+            other.verify::<Value>(&token),
+            Err(JwtError::UnknownKey(_))
+        ));
+
+        let expired = keys
+            .sign(&json!({ "sub": "me", "exp": exp - 120 }), None)
+            .unwrap();
+        assert!(matches!(
+            keys.verify::<Value>(&expired),
+            Err(JwtError::Expired)
+        ));
+    }
+
+    #[test]
+    fn test_pem_round_trip() {
+        let keys = KeySet::new(SigningKey::generate().unwrap());
+        let exp = chrono::Utc::now().timestamp() + 60;
+        let token = keys.sign(&json!({ "exp": exp }), None).unwrap();
+        let key = keys.active();
+        let loaded = SigningKey::from_pem(
+            key.kid().to_string(),
+            &key.to_pem().unwrap(),
+            Some(&key.certificate_pem().unwrap()),
+        )
+        .unwrap();
+        assert_eq!(loaded.jwk().unwrap(), key.jwk().unwrap());
+        assert_eq!(
+            loaded.certificate_der().unwrap(),
+            key.certificate_der().unwrap()
+        );
+
+        // Retired keys can still verify tokens
+        keys.replace(vec![SigningKey::generate().unwrap(), loaded]);
+        assert_ne!(keys.active().kid(), key.kid());
+        keys.verify::<Value>(&token).unwrap();
+    }
+
+    #[test]
+    fn test_jwk() {
+        let key = SigningKey::generate().unwrap();
+        let jwk = key.jwk().unwrap();
+        assert_eq!(jwk.kid, key.kid());
+        assert_eq!(BASE64_URL_SAFE_NO_PAD.decode(&jwk.x).unwrap().len(), 32);
+        assert_eq!(BASE64_URL_SAFE_NO_PAD.decode(&jwk.y).unwrap().len(), 32);
+        assert_eq!(
+            serde_json::to_value(&jwk).unwrap()["use"],
+            Value::from("sig")
+        );
+    }
+}
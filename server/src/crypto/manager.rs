@@ -0,0 +1,146 @@
+//! # Key manager
+//!
+//! The [`KeyManager`] loads keys from a [`KeyProvider`], caches them, and periodically reloads them
+//! so that rotated keys are picked up without a restart. Whenever the keys change, the manager
+//! calls its rotation hooks, e.g. to [install][super::set_keyring] the new keyring.
+
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use tracing::{error, info};
+
+use crate::crypto::{KeyProvider, KeyProviderError, Keyring};
+
+/// Function called with the new keys whenever they change
+type RotationHook = Box<dyn Fn(&Arc<Keyring>) + Send + Sync>;
+
+/// # Key manager
+///
+/// See the [module-level documentation][self] for details.
+pub struct KeyManager {
+    provider: Arc<dyn KeyProvider>,
+    current: RwLock<Option<Arc<Keyring>>>,
+    hooks: Vec<RotationHook>,
+}
+
+impl KeyManager {
+    /// Creates a manager which loads keys from the given provider.
+    #[must_use]
+    pub fn new(provider: Arc<dyn KeyProvider>) -> Self {
+        Self {
+            provider,
+            current: RwLock::default(),
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Adds a hook which is called with the new keys whenever they change, including when they
+    /// are first loaded.
+    #[must_use]
+    pub fn on_rotate(mut self, hook: impl Fn(&Arc<Keyring>) + Send + Sync + 'static) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Returns the cached keys, or [`None`] if they haven't been loaded yet.
+    #[must_use]
+    pub fn keyring(&self) -> Option<Arc<Keyring>> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Loads the keys from the provider, and calls the rotation hooks if they changed. Returns
+    /// whether the keys changed.
+    pub async fn refresh(&self) -> Result<bool, KeyProviderError> {
+        let keyring = self.provider.load().await?;
+        let keyring = {
+            let mut current = self.current.write().unwrap();
+            if current.as_deref() == Some(&keyring) {
+                return Ok(false);
+            }
+            if let Some(previous) = current.as_deref()
+                && previous.active_key_id() != keyring.active_key_id()
+            {
+                info!(
+                    from = %previous.active_key_id(),
+                    to = %keyring.active_key_id(),
+                    "active encryption key rotated",
+                );
+            }
+            let keyring = Arc::new(keyring);
+            *current = Some(Arc::clone(&keyring));
+            keyring
+        };
+        for hook in &self.hooks {
+            hook(&keyring);
+        }
+        Ok(true)
+    }
+
+    /// Spawns a task which [refreshes][Self::refresh] the keys every `interval`.
+    pub fn spawn(self: &Arc<Self>, interval: Duration) {
+        let manager = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            // The first tick completes immediately, but the keys were loaded at startup
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(err) = manager.refresh().await {
+                    error!(%err, "failed to refresh encryption keys");
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        pin::Pin,
+        sync::{
+            Mutex,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    use super::*;
+    use crate::crypto::KEY_LEN;
+
+    /// Provider whose keys can be replaced by the test
+    struct MockProvider(Mutex<Keyring>);
+
+    impl KeyProvider for MockProvider {
+        fn load<'a>(
+            &'a self,
+        ) -> Pin<Box<dyn Future<Output = Result<Keyring, KeyProviderError>> + Send + 'a>> {
+            Box::pin(async move { Ok(self.0.lock().unwrap().clone()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotation_hooks() {
+        let provider = Arc::new(MockProvider(Mutex::new(Keyring::new("k1", [1; KEY_LEN]))));
+        let rotations = Arc::new(AtomicUsize::new(0));
+        let manager = KeyManager::new(Arc::clone(&provider) as Arc<dyn KeyProvider>).on_rotate({
+            let rotations = Arc::clone(&rotations);
+            move |_| {
+                rotations.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+        assert!(manager.keyring().is_none());
+
+        assert!(manager.refresh().await.unwrap());
+        assert_eq!(rotations.load(Ordering::Relaxed), 1);
+        // Unchanged keys don't trigger the hooks
+        assert!(!manager.refresh().await.unwrap());
+        assert_eq!(rotations.load(Ordering::Relaxed), 1);
+
+        *provider.0.lock().unwrap() = Keyring::new("k2", [2; KEY_LEN]).with_key("k1", [1; KEY_LEN]);
+        assert!(manager.refresh().await.unwrap());
+        assert_eq!(rotations.load(Ordering::Relaxed), 2);
+        assert_eq!(manager.keyring().unwrap().active_key_id(), "k2");
+    }
+}
@@ -0,0 +1,305 @@
+//! # Application-level encryption of stored data
+//!
+//! Sensitive values, such as the serialized passkeys stored via [`ViaJson`][1], are encrypted
+//! with AES-256-GCM before they are written to the database, so that a database dump alone doesn't
+//! expose credential material. Encryption is enabled by [installing][set_keyring] a [`Keyring`].
+//!
+//! Encrypted values are stored as text of the form `enc:<key ID>:<base64 data>`, where the data
+//! is the nonce followed by the ciphertext and authentication tag. Values are always encrypted
+//! with the keyring's active key, but can be decrypted with any key in the keyring, so keys can be
+//! rotated by adding a new active key and keeping the old ones until all values have been
+//! rewritten. Values stored before encryption was enabled are read as-is.
+//!
+//! Keys can be loaded from a [`KeyProvider`], such as Vault or AWS KMS, and kept up to
+//! date by a [`KeyManager`].
+//!
+//! [1]: crate::models::ViaJson
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use openssl::symm::{Cipher, decrypt_aead, encrypt_aead};
+use rand::RngCore;
+
+pub mod jwt;
+pub mod manager;
+pub mod providers;
+pub mod signing;
+
+pub use manager::KeyManager;
+#[cfg(feature = "aws-kms")]
+pub use providers::AwsKmsKeyProvider;
+#[cfg(feature = "vault")]
+pub use providers::VaultKeyProvider;
+pub use providers::{FileKeyProvider, KeyProvider, KeyProviderError, StaticKeyProvider};
+
+/// Prefix which marks a stored value as encrypted
+const PREFIX: &str = "enc:";
+
+/// Length of AES-256 keys in bytes
+pub const KEY_LEN: usize = 32;
+
+/// Length of GCM nonces in bytes
+const NONCE_LEN: usize = 12;
+
+/// Length of GCM authentication tags in bytes
+const TAG_LEN: usize = 16;
+
+/// Keyring used to encrypt stored values, if any
+static KEYRING: RwLock<Option<Arc<Keyring>>> = RwLock::new(None);
+
+/// Errors which can occur while encrypting or decrypting a stored value
+#[derive(Debug, thiserror::Error)]
+pub enum CryptoError {
+    #[error("value is encrypted but no encryption keys are configured")]
+    NoKeyring,
+
+    #[error("value is encrypted with unknown key `{0}`")]
+    UnknownKey(String),
+
+    #[error("encrypted value is malformed")]
+    Malformed,
+
+    #[error("invalid key specification: {0}")]
+    InvalidKeySpec(String),
+
+    #[error("encryption failed: {0}")]
+    OpenSsl(#[from] openssl::error::ErrorStack),
+}
+
+/// # Set of encryption keys
+///
+/// See the [module-level documentation][self] for details.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Keyring {
+    /// ID of the key used for encryption
+    active: String,
+    keys: HashMap<String, [u8; KEY_LEN]>,
+}
+
+impl std::fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Don't leak the keys into logs
+        f.debug_struct("Keyring")
+            .field("active", &self.active)
+            .field("keys", &self.keys.keys())
+            .finish()
+    }
+}
+
+impl Keyring {
+    /// Creates a keyring which encrypts values with the given key.
+    #[must_use]
+    pub fn new(id: impl Into<String>, key: [u8; KEY_LEN]) -> Self {
+        let id = id.into();
+        Self {
+            keys: HashMap::from([(id.clone(), key)]),
+            active: id,
+        }
+    }
+
+    /// Adds a key which is only used to decrypt values, e.g. a key which was rotated out.
+    #[must_use]
+    pub fn with_key(mut self, id: impl Into<String>, key: [u8; KEY_LEN]) -> Self {
+        self.keys.entry(id.into()).or_insert(key);
+        self
+    }
+
+    /// Returns the ID of the key used for encryption.
+    #[must_use]
+    pub fn active_key_id(&self) -> &str {
+        &self.active
+    }
+
+    /// Creates a keyring from the given keys. The first key is the active one. Returns [`None`]
+    /// if no keys are given.
+    pub fn from_keys(keys: impl IntoIterator<Item = (String, [u8; KEY_LEN])>) -> Option<Self> {
+        keys.into_iter().fold(None, |keyring, (id, key)| {
+            Some(match keyring {
+                None => Self::new(id, key),
+                Some(keyring) => keyring.with_key(id, key),
+            })
+        })
+    }
+
+    /// Parses a keyring from a comma-separated list of `<key ID>:<base64 key>` pairs. The first
+    /// key is the active one.
+    pub fn parse(spec: &str) -> Result<Self, CryptoError> {
+        let keys = split_key_spec(spec)?
+            .into_iter()
+            .map(|(id, key)| Ok((id.to_string(), decode_key(id, key)?)))
+            .collect::<Result<Vec<_>, CryptoError>>()?;
+        Self::from_keys(keys)
+            .ok_or_else(|| CryptoError::InvalidKeySpec("no keys given".to_string()))
+    }
+
+    /// Encrypts the given value with the active key.
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, CryptoError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::rng().fill_bytes(&mut nonce);
+        let mut tag = [0u8; TAG_LEN];
+        let ciphertext = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.keys[&self.active],
+            Some(&nonce),
+            self.active.as_bytes(),
+            plaintext.as_bytes(),
+            &mut tag,
+        )?;
+        let mut data = Vec::with_capacity(NONCE_LEN + ciphertext.len() + TAG_LEN);
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&ciphertext);
+        data.extend_from_slice(&tag);
+        Ok(format!(
+            "{PREFIX}{}:{}",
+            self.active,
+            BASE64_STANDARD.encode(data)
+        ))
+    }
+
+    /// Decrypts the given stored value. Values which aren't encrypted are returned as-is.
+    pub fn decrypt<'a>(&self, stored: &'a str) -> Result<Cow<'a, str>, CryptoError> {
+        let Some(encrypted) = stored.strip_prefix(PREFIX) else {
+            return Ok(Cow::Borrowed(stored));
+        };
+        let (id, data) = encrypted.split_once(':').ok_or(CryptoError::Malformed)?;
+        let key = self
+            .keys
+            .get(id)
+            .ok_or_else(|| CryptoError::UnknownKey(id.to_string()))?;
+        let data = BASE64_STANDARD
+            .decode(data)
+            .map_err(|_| CryptoError::Malformed)?;
+        if data.len() < NONCE_LEN + TAG_LEN {
+            return Err(CryptoError::Malformed);
+        }
+        let (nonce, rest) = data.split_at(NONCE_LEN);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_LEN);
+        let plaintext = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            key,
+            Some(nonce),
+            id.as_bytes(),
+            ciphertext,
+            tag,
+        )?;
+        String::from_utf8(plaintext)
+            .map(Cow::Owned)
+            .map_err(|_| CryptoError::Malformed)
+    }
+}
+
+/// Splits a comma-separated list of `<key ID>:<value>` pairs, as used to configure keys.
+pub fn split_key_spec(spec: &str) -> Result<Vec<(&str, &str)>, CryptoError> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (id, value) = entry.split_once(':').ok_or_else(|| {
+                CryptoError::InvalidKeySpec("expected `<key ID>:<value>`".to_string())
+            })?;
+            if id.is_empty() {
+                return Err(CryptoError::InvalidKeySpec("empty key ID".to_string()));
+            }
+            Ok((id, value))
+        })
+        .collect()
+}
+
+/// Decodes the base64-encoded key with the given ID.
+pub fn decode_key(id: &str, key: &str) -> Result<[u8; KEY_LEN], CryptoError> {
+    BASE64_STANDARD
+        .decode(key)
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or_else(|| {
+            CryptoError::InvalidKeySpec(format!("key `{id}` is not {KEY_LEN} base64-encoded bytes"))
+        })
+}
+
+/// Sets the keyring used to encrypt stored values for the rest of the program, or disables
+/// encryption if [`None`] is given. Replacing the keyring takes effect immediately, e.g. after a
+/// key rotation.
+pub fn set_keyring(keyring: Option<Arc<Keyring>>) {
+    *KEYRING.write().unwrap() = keyring;
+}
+
+/// Returns the installed keyring, if any.
+fn keyring() -> Option<Arc<Keyring>> {
+    KEYRING.read().unwrap().clone()
+}
+
+/// Encrypts a value for storage with the [installed keyring][set_keyring]. If no keyring is
+/// installed, the value is returned as-is.
+pub fn encrypt_stored(plaintext: String) -> Result<String, CryptoError> {
+    match keyring() {
+        Some(keyring) => keyring.encrypt(&plaintext),
+        None => Ok(plaintext),
+    }
+}
+
+/// Decrypts a stored value with the [installed keyring][set_keyring]. Values which aren't
+/// encrypted are returned as-is.
+pub fn decrypt_stored(stored: &str) -> Result<Cow<'_, str>, CryptoError> {
+    if !stored.starts_with(PREFIX) {
+        return Ok(Cow::Borrowed(stored));
+    }
+    keyring().ok_or(CryptoError::NoKeyring)?.decrypt(stored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let keyring = Keyring::new("k1", [1; KEY_LEN]);
+        let encrypted = keyring.encrypt("{\"secret\":1}").unwrap();
+        assert!(encrypted.starts_with("enc:k1:"));
+        assert!(!encrypted.contains("secret"));
+        assert_eq!(keyring.decrypt(&encrypted).unwrap(), "{\"secret\":1}");
+        // Plaintext values are passed through
+        assert_eq!(keyring.decrypt("{}").unwrap(), "{}");
+    }
+
+    #[test]
+    fn test_rotation() {
+        let old = Keyring::new("k1", [1; KEY_LEN]);
+        let encrypted = old.encrypt("value").unwrap();
+        let new = Keyring::new("k2", [2; KEY_LEN]).with_key("k1", [1; KEY_LEN]);
+        assert_eq!(new.decrypt(&encrypted).unwrap(), "value");
+        assert!(new.encrypt("value").unwrap().starts_with("enc:k2:"));
+        let without_old = Keyring::new("k2", [2; KEY_LEN]);
+        assert!(matches!(
+            without_old.decrypt(&encrypted),
+            Err(CryptoError::UnknownKey(id)) if id == "k1"
+        ));
+    }
+
+    #[test]
+    fn test_tampering() {
+        let keyring = Keyring::new("k1", [1; KEY_LEN]);
+        let encrypted = keyring.encrypt("value").unwrap();
+        let mut data = BASE64_STANDARD
+            .decode(encrypted.strip_prefix("enc:k1:").unwrap())
+            .unwrap();
+        data[NONCE_LEN] ^= 1;
+        let tampered = format!("enc:k1:{}", BASE64_STANDARD.encode(data));
+        assert!(keyring.decrypt(&tampered).is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        let key = BASE64_STANDARD.encode([1; KEY_LEN]);
+        let keyring = Keyring::parse(&format!("new:{key}, old:{key}")).unwrap();
+        assert_eq!(keyring.active_key_id(), "new");
+        assert_eq!(keyring.keys.len(), 2);
+        assert!(Keyring::parse("").is_err());
+        assert!(Keyring::parse("k1:c2hvcnQ=").is_err());
+        assert!(Keyring::parse(&key).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+//! # Key material providers
+//!
+//! A [`KeyProvider`] loads the [`Keyring`] used to encrypt stored data. Besides keys given
+//! [directly][StaticKeyProvider] or in a [file][FileKeyProvider], keys can be fetched from
+//! Vault (with the `vault` feature) or unwrapped with AWS KMS (with the `aws-kms`
+//! feature), so they never have to be stored in the environment.
+//!
+//! Providers are called again whenever the [`KeyManager`][super::KeyManager] refreshes the keys,
+//! so they should return the current keys, including rotated-out ones which are still needed for
+//! decryption.
+
+use std::{future::Future, path::PathBuf, pin::Pin};
+
+#[cfg(any(feature = "vault", feature = "aws-kms"))]
+use std::time::Duration;
+
+use crate::crypto::{CryptoError, Keyring};
+
+/// Timeout for requests to key management services
+#[cfg(any(feature = "vault", feature = "aws-kms"))]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Errors which can occur while loading keys
+#[derive(Debug, thiserror::Error)]
+pub enum KeyProviderError {
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+
+    #[error("failed to read keys: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("request to key management service failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The key management service returned an unexpected response.
+    #[error("unexpected response from key management service: {0}")]
+    Response(String),
+}
+
+/// # Key provider interface
+///
+/// See the [module-level documentation][self] for details.
+pub trait KeyProvider: Send + Sync + 'static {
+    /// Loads the current keys.
+    fn load<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<Keyring, KeyProviderError>> + Send + 'a>>;
+}
+
+/// # Static key provider
+///
+/// A [`KeyProvider`] which always returns the same keys, e.g. ones given in an environment
+/// variable.
+#[derive(Debug, Clone)]
+pub struct StaticKeyProvider(pub Keyring);
+
+impl KeyProvider for StaticKeyProvider {
+    fn load<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<Keyring, KeyProviderError>> + Send + 'a>> {
+        Box::pin(async move { Ok(self.0.clone()) })
+    }
+}
+
+/// # File key provider
+///
+/// A [`KeyProvider`] which reads keys in the format accepted by [`Keyring::parse()`] from a file,
+/// e.g. one mounted from a secret store. The file is read again on every refresh, so keys can be
+/// rotated by replacing it.
+#[derive(Debug, Clone)]
+pub struct FileKeyProvider(pub PathBuf);
+
+impl KeyProvider for FileKeyProvider {
+    fn load<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<Keyring, KeyProviderError>> + Send + 'a>> {
+        Box::pin(async move {
+            let spec = tokio::fs::read_to_string(&self.0).await?;
+            Ok(Keyring::parse(&spec)?)
+        })
+    }
+}
+
+/// Creates the HTTP client used by key management service providers.
+///
+/// # Panics
+///
+/// Panics if the client can't be created, e.g. because the TLS backend fails to initialize.
+#[cfg(any(feature = "vault", feature = "aws-kms"))]
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("failed to create HTTP client")
+}
+
+/// # Vault key provider
+///
+/// A [`KeyProvider`] which reads keys from a secret in Vault's key/value secrets engine. The
+/// secret's `keys` field must contain keys in the format accepted by [`Keyring::parse()`]. Both
+/// versions of the key/value engine are supported.
+#[cfg(feature = "vault")]
+pub struct VaultKeyProvider {
+    http: reqwest::Client,
+    url: String,
+    token: String,
+}
+
+#[cfg(feature = "vault")]
+impl VaultKeyProvider {
+    /// Creates a provider which reads the secret at `path` (e.g. `secret/data/iam` for version 2
+    /// of the key/value engine mounted at `secret`) from the Vault server at `address`,
+    /// authenticating with `token`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the HTTP client can't be created.
+    #[must_use]
+    pub fn new(address: &str, path: &str, token: String) -> Self {
+        Self {
+            http: http_client(),
+            url: format!(
+                "{}/v1/{}",
+                address.trim_end_matches('/'),
+                path.trim_start_matches('/')
+            ),
+            token,
+        }
+    }
+}
+
+#[cfg(feature = "vault")]
+impl KeyProvider for VaultKeyProvider {
+    fn load<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<Keyring, KeyProviderError>> + Send + 'a>> {
+        Box::pin(async move {
+            let response: serde_json::Value = self
+                .http
+                .get(&self.url)
+                .header("x-vault-token", &self.token)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            // Version 2 of the key/value engine nests the secret's data in another object
+            let data = &response["data"];
+            let spec = data["data"]["keys"]
+                .as_str()
+                .or_else(|| data["keys"].as_str())
+                .ok_or_else(|| {
+                    KeyProviderError::Response("secret has no `keys` field".to_string())
+                })?;
+            Ok(Keyring::parse(spec)?)
+        })
+    }
+}
+
+/// # AWS KMS key provider
+///
+/// A [`KeyProvider`] for envelope encryption: keys are given encrypted by a KMS key, in the
+/// format accepted by [`Keyring::parse()`] but with each key replaced by its base64-encoded
+/// ciphertext blob, and are decrypted with KMS. Decrypted keys are cached, so KMS is only called
+/// for keys which weren't seen before.
+#[cfg(feature = "aws-kms")]
+pub struct AwsKmsKeyProvider {
+    http: reqwest::Client,
+    region: String,
+    credentials: crate::email::AwsCredentials,
+    encrypted_keys: String,
+    cache: std::sync::Mutex<std::collections::HashMap<String, [u8; super::KEY_LEN]>>,
+}
+
+#[cfg(feature = "aws-kms")]
+impl AwsKmsKeyProvider {
+    const SERVICE: &str = "kms";
+
+    /// Creates a provider which decrypts the given encrypted keys with KMS in the given region.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the HTTP client can't be created.
+    #[must_use]
+    pub fn new(
+        region: String,
+        credentials: crate::email::AwsCredentials,
+        encrypted_keys: String,
+    ) -> Self {
+        Self {
+            http: http_client(),
+            region,
+            credentials,
+            encrypted_keys,
+            cache: std::sync::Mutex::default(),
+        }
+    }
+
+    /// Decrypts a ciphertext blob with KMS.
+    async fn decrypt(
+        &self,
+        id: &str,
+        blob: &str,
+    ) -> Result<[u8; super::KEY_LEN], KeyProviderError> {
+        if let Some(key) = self.cache.lock().unwrap().get(blob) {
+            return Ok(*key);
+        }
+        let body = serde_json::json!({ "CiphertextBlob": blob }).to_string();
+        let host = format!("kms.{}.amazonaws.com", self.region);
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1"),
+            ("host", host.as_str()),
+            ("x-amz-date", amz_date.as_str()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token));
+        }
+        headers.push(("x-amz-target", "TrentService.Decrypt"));
+        let authorization = crate::email::providers::sigv4_authorization(
+            &self.credentials,
+            &self.region,
+            Self::SERVICE,
+            "POST",
+            "/",
+            &headers,
+            body.as_bytes(),
+            &amz_date,
+        );
+        let mut request = self
+            .http
+            .post(format!("https://{host}/"))
+            .header("authorization", authorization)
+            .body(body);
+        for (name, value) in headers {
+            if name != "host" {
+                request = request.header(name, value);
+            }
+        }
+        let response: serde_json::Value = request.send().await?.error_for_status()?.json().await?;
+        let plaintext = response["Plaintext"].as_str().ok_or_else(|| {
+            KeyProviderError::Response("KMS response has no plaintext".to_string())
+        })?;
+        let key = super::decode_key(id, plaintext)?;
+        self.cache.lock().unwrap().insert(blob.to_string(), key);
+        Ok(key)
+    }
+}
+
+#[cfg(feature = "aws-kms")]
+impl KeyProvider for AwsKmsKeyProvider {
+    fn load<'a>(
+        &'a self,
+    ) -> Pin<Box<dyn Future<Output = Result<Keyring, KeyProviderError>> + Send + 'a>> {
+        Box::pin(async move {
+            let mut keys = Vec::new();
+            for (id, blob) in super::split_key_spec(&self.encrypted_keys)? {
+                keys.push((id.to_string(), self.decrypt(id, blob).await?));
+            }
+            Keyring::from_keys(keys)
+                .ok_or_else(|| CryptoError::InvalidKeySpec("no keys given".to_string()).into())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{Engine, prelude::BASE64_STANDARD};
+
+    use super::*;
+    use crate::crypto::KEY_LEN;
+
+    #[tokio::test]
+    async fn test_file_key_provider() {
+        let path = std::env::temp_dir().join(format!("iam-keys-{}", uuid::Uuid::new_v4()));
+        let key = BASE64_STANDARD.encode([1; KEY_LEN]);
+        std::fs::write(&path, format!("k1:{key}\n")).unwrap();
+        let provider = FileKeyProvider(path.clone());
+        assert_eq!(provider.load().await.unwrap().active_key_id(), "k1");
+
+        // Rotated keys are picked up on the next load
+        std::fs::write(&path, format!("k2:{key},k1:{key}\n")).unwrap();
+        assert_eq!(provider.load().await.unwrap().active_key_id(), "k2");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
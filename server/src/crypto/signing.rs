@@ -0,0 +1,225 @@
+//! # Signing key management
+//!
+//! The [`SigningKeyManager`] generates the asymmetric keys with which the server signs tokens,
+//! such as OIDC ID tokens, stores them in the database so that they survive restarts and are
+//! shared by all instances, and rotates them periodically. The public halves of the keys are
+//! published as a [JSON Web Key Set][super::jwt::Jwks] at `/.well-known/jwks.json`.
+//!
+//! A new key is generated once the active key is older than the
+//! [rotation interval][SigningKeyOptions::rotation_interval]. The previous key is retired: it no
+//! longer signs new tokens, but is still published for the
+//! [retired key lifetime][SigningKeyOptions::retired_key_lifetime] so that tokens signed before
+//! the rotation can be verified until they expire. Private keys are stored via
+//! [`ViaJson`][crate::models::ViaJson], so they are [encrypted][super] if a keyring is installed.
+//!
+//! Keys are only rotated by the instance holding the rotation job's
+//! [lease][crate::coordination::Coordinator]; every instance reloads the keys periodically to
+//! pick up rotations done by others.
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use tracing::{debug, error, info};
+
+use crate::{
+    coordination::Coordinator,
+    crypto::{
+        CryptoError,
+        jwt::{KeySet, SigningKey},
+    },
+    db::interface::{DatabaseClient, DatabaseError},
+    models::{StoredSigningKey, ViaJson},
+};
+
+const JOB_NAME: &str = "signing-key-rotation";
+
+/// How often keys are reloaded from the database, and rotated if they are due
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Errors which can occur while loading or rotating signing keys
+#[derive(Debug, thiserror::Error)]
+pub enum SigningKeyError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+}
+
+/// Options for rotating signing keys
+#[derive(Debug, Clone)]
+pub struct SigningKeyOptions {
+    /// How long a key is used to sign tokens before it is replaced
+    pub rotation_interval: Duration,
+    /// How long a key is still published after it is replaced. This must be longer than the
+    /// lifetime of the tokens it signed.
+    pub retired_key_lifetime: Duration,
+}
+
+impl Default for SigningKeyOptions {
+    fn default() -> Self {
+        Self {
+            rotation_interval: Duration::from_secs(30 * 24 * 60 * 60),
+            retired_key_lifetime: Duration::from_secs(2 * 24 * 60 * 60),
+        }
+    }
+}
+
+/// # Signing key manager
+///
+/// See the [module-level documentation][self] for details.
+pub struct SigningKeyManager {
+    db: Arc<dyn DatabaseClient>,
+    keys: Arc<KeySet>,
+    options: SigningKeyOptions,
+}
+
+impl SigningKeyManager {
+    /// Loads the keys from the database, generating the first key if there is none or rotating
+    /// the active key if it is due.
+    pub async fn load(
+        db: Arc<dyn DatabaseClient>,
+        options: SigningKeyOptions,
+    ) -> Result<Self, SigningKeyError> {
+        rotate_if_due(db.as_ref(), &options).await?;
+        let keys = KeySet::from_keys(load_keys(db.as_ref()).await?);
+        info!(kid = %keys.active().kid(), "loaded token signing keys");
+        Ok(Self {
+            db,
+            keys: Arc::new(keys),
+            options,
+        })
+    }
+
+    /// Returns the managed keys. The returned set is updated in place whenever the keys change.
+    #[must_use]
+    pub fn keys(&self) -> Arc<KeySet> {
+        Arc::clone(&self.keys)
+    }
+
+    /// Rotates the active key if it is due, then reloads the keys from the database. Only the
+    /// instance holding the rotation lease should pass `rotate = true`.
+    pub async fn refresh(&self, rotate: bool) -> Result<(), SigningKeyError> {
+        if rotate {
+            rotate_if_due(self.db.as_ref(), &self.options).await?;
+        }
+        let keys = load_keys(self.db.as_ref()).await?;
+        let previous = self.keys.active();
+        if keys[0].kid() != previous.kid() {
+            info!(from = %previous.kid(), to = %keys[0].kid(), "active signing key rotated");
+        }
+        self.keys.replace(keys);
+        Ok(())
+    }
+
+    /// Spawns a task which [refreshes][Self::refresh] the keys periodically, rotating them while
+    /// this instance holds the rotation lease.
+    pub fn spawn(self: &Arc<Self>, coordinator: Arc<Coordinator>) {
+        let manager = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+            // The first tick completes immediately, but the keys were loaded at startup
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let rotate = coordinator.should_run(JOB_NAME, REFRESH_INTERVAL).await;
+                if let Err(err) = manager.refresh(rotate).await {
+                    error!(%err, "failed to refresh signing keys");
+                }
+            }
+        });
+    }
+}
+
+/// Generates and stores a new key if there is no active key or the active key is older than the
+/// rotation interval, retiring the previous keys. Also deletes keys which have been retired for
+/// longer than the retired key lifetime.
+async fn rotate_if_due(
+    db: &dyn DatabaseClient,
+    options: &SigningKeyOptions,
+) -> Result<(), SigningKeyError> {
+    let keys = db.list_signing_keys().await?;
+    let due = keys
+        .iter()
+        .find(|key| key.retired_at.is_none())
+        .is_none_or(|active| {
+            (Utc::now() - active.created_at)
+                .to_std()
+                .is_ok_and(|age| age >= options.rotation_interval)
+        });
+    if due {
+        let key = SigningKey::generate()?;
+        db.create_signing_key(&StoredSigningKey {
+            kid: key.kid().to_string(),
+            private_key: ViaJson(key.to_pem()?),
+            certificate: Some(key.certificate_pem()?),
+            created_at: Utc::now(),
+            retired_at: None,
+        })
+        .await?;
+        let retired = db.retire_signing_keys_older_than(key.kid()).await?;
+        info!(kid = %key.kid(), retired, "generated new signing key");
+    }
+    let deleted = db
+        .delete_retired_signing_keys(options.retired_key_lifetime)
+        .await?;
+    if deleted > 0 {
+        info!(deleted, "deleted expired signing keys");
+    } else {
+        debug!("no expired signing keys");
+    }
+    Ok(())
+}
+
+/// Loads the stored keys, with the newest unretired key first so that it becomes the active key.
+async fn load_keys(db: &dyn DatabaseClient) -> Result<Vec<SigningKey>, SigningKeyError> {
+    let mut stored = db.list_signing_keys().await?;
+    // Keys are listed newest first, and the sort is stable
+    stored.sort_by_key(|key| key.retired_at.is_some());
+    let keys: Vec<SigningKey> = stored
+        .into_iter()
+        .map(|key| SigningKey::from_pem(key.kid, &key.private_key, key.certificate.as_deref()))
+        .collect::<Result<_, _>>()?;
+    if keys.is_empty() {
+        // Only possible if the keys were deleted after they were checked
+        return Err(DatabaseError::NotFound.into());
+    }
+    Ok(keys)
+}
+
+#[cfg(all(test, feature = "sqlite3"))]
+mod tests {
+    use super::*;
+    use crate::db::clients::sqlite::SqliteClient;
+
+    #[tokio::test]
+    async fn test_rotation() {
+        let db: Arc<dyn DatabaseClient> = Arc::new(SqliteClient::new_memory().await.unwrap());
+        let manager = SigningKeyManager::load(Arc::clone(&db), SigningKeyOptions::default())
+            .await
+            .unwrap();
+        let first = manager.keys().active().kid().to_string();
+        // Keys persist, and aren't rotated until they are due
+        let reloaded = SigningKeyManager::load(Arc::clone(&db), SigningKeyOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(reloaded.keys().kids(), vec![first.clone()]);
+
+        let rotating = SigningKeyManager {
+            options: SigningKeyOptions {
+                rotation_interval: Duration::ZERO,
+                ..SigningKeyOptions::default()
+            },
+            ..reloaded
+        };
+        rotating.refresh(true).await.unwrap();
+        let kids = rotating.keys().kids();
+        assert_eq!(kids.len(), 2);
+        assert_ne!(kids[0], first);
+        assert_eq!(kids[1], first);
+
+        // Other instances pick up the rotation when they refresh
+        manager.refresh(false).await.unwrap();
+        assert_eq!(manager.keys().kids(), kids);
+    }
+}
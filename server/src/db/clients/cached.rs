@@ -0,0 +1,1231 @@
+//! # Caching database client
+//!
+//! [`CachedClient`] wraps another [`DatabaseClient`] and keeps the results of the reads made on
+//! every authenticated request in memory for a short time: users by UUID, and the direct and
+//! effective tags of users. Sessions aren't cached here, since the API keeps its own
+//! session cache in front of the database. Writes made through the client invalidate the entries
+//! they affect once they complete, so the instance which made a change never reads stale data
+//! afterwards.
+//!
+//! The cache is per instance, so a change made by another instance sharing the database, or by an
+//! administrative command, may go unnoticed here for up to the cache's TTL.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::{
+    db::interface::{BoxedTransaction, DatabaseClient, DatabaseError, DatabaseTransaction},
+    models::{
+        Announcement, ApiToken, AuthorizationCode, Backup, EmailSuppression, EncodableHash,
+        FederatedIdentity, FederatedLoginState, FederatedProvider, FederatedProviderUpdate, Group,
+        GroupUpdate, IpBan, MaintenanceTask, NewPasskeyCredential, OAuthClient, OAuthClientUpdate,
+        Organization, OrganizationMember, OrganizationRole, OrganizationUpdate,
+        PasskeyAuthenticationState, PasskeyCredential, PasskeyCredentialUpdate,
+        PasskeyRegistrationState, Policy, PolicyUpdate, PolicyVersion, QueuedEmail,
+        QueuedEmailUpdate, RetentionPolicy, RetentionReport, Role, RoleUpdate, SamlServiceProvider,
+        SamlServiceProviderUpdate, Session, SessionUpdate, StoredSessionCookieKey,
+        StoredSigningKey, Tag, TagUpdate, User, UserCreate, UserCursor, UserList, UserListQuery,
+        UserMerge, UserNote, UserStatus, UserUpdate, Webhook, WebhookUpdate,
+    },
+    ttl_cache::TtlCache,
+};
+
+/// Caches shared by a [`CachedClient`] and its transactions
+struct Caches {
+    users: TtlCache<Uuid, User>,
+    /// Tags applied directly to each user
+    tags: TtlCache<Uuid, Vec<Tag>>,
+    /// Tags applied to each user directly or through groups
+    effective_tags: TtlCache<Uuid, Vec<Tag>>,
+}
+
+impl Caches {
+    /// Removes everything cached about the user with the given UUID.
+    fn invalidate_user(&self, user_id: &Uuid) {
+        self.users.remove(user_id);
+        self.invalidate_tags(user_id);
+    }
+
+    /// Removes the cached tags of the user with the given UUID.
+    fn invalidate_tags(&self, user_id: &Uuid) {
+        self.tags.remove(user_id);
+        self.effective_tags.remove(user_id);
+    }
+
+    /// Removes the cached tags of all users.
+    fn clear_tags(&self) {
+        self.tags.clear();
+        self.effective_tags.clear();
+    }
+
+    fn clear(&self) {
+        self.users.clear();
+        self.clear_tags();
+    }
+}
+
+/// # Caching database client
+///
+/// See the [module-level documentation][self] for details.
+pub struct CachedClient<C: ?Sized = dyn DatabaseClient> {
+    inner: Arc<C>,
+    caches: Arc<Caches>,
+}
+
+impl<C: DatabaseClient + ?Sized> CachedClient<C> {
+    /// Wraps the given client, caching reads for `ttl`.
+    #[must_use]
+    pub fn new(inner: Arc<C>, ttl: Duration) -> Self {
+        Self {
+            inner,
+            caches: Arc::new(Caches {
+                users: TtlCache::new(ttl),
+                tags: TtlCache::new(ttl),
+                effective_tags: TtlCache::new(ttl),
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl<C: DatabaseClient + ?Sized> DatabaseClient for CachedClient<C> {
+    async fn create_user(&self, id: &Uuid, user: &UserCreate) -> Result<User, DatabaseError> {
+        self.inner.create_user(id, user).await
+    }
+
+    async fn get_user_by_id(&self, id: &Uuid) -> Result<User, DatabaseError> {
+        self.caches
+            .users
+            .get_or_fetch(*id, self.inner.get_user_by_id(id))
+            .await
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<User, DatabaseError> {
+        self.inner.get_user_by_email(email).await
+    }
+
+    async fn get_user_with_relations(&self, id: &Uuid) -> Result<User, DatabaseError> {
+        self.inner.get_user_with_relations(id).await
+    }
+
+    async fn update_user(&self, id: &Uuid, update: &UserUpdate) -> Result<User, DatabaseError> {
+        let result = self.inner.update_user(id, update).await;
+        self.caches.users.remove(id);
+        result
+    }
+
+    async fn create_email_change(
+        &self,
+        user_id: &Uuid,
+        email: &str,
+        token_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError> {
+        self.inner
+            .create_email_change(user_id, email, token_hash)
+            .await
+    }
+
+    async fn confirm_email_change(
+        &self,
+        token_hash: &EncodableHash,
+        max_age: Duration,
+    ) -> Result<User, DatabaseError> {
+        let user = self.inner.confirm_email_change(token_hash, max_age).await?;
+        self.caches.users.remove(user.id());
+        Ok(user)
+    }
+
+    async fn record_user_login(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = self.inner.record_user_login(id).await;
+        self.caches.users.remove(id);
+        result
+    }
+
+    async fn record_login_failure(
+        &self,
+        user_id: &Uuid,
+        passkey_id: Option<&Uuid>,
+        window: Duration,
+    ) -> Result<u64, DatabaseError> {
+        self.inner
+            .record_login_failure(user_id, passkey_id, window)
+            .await
+    }
+
+    async fn clear_login_failures(&self, user_id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.clear_login_failures(user_id).await
+    }
+
+    async fn set_user_locked_until(
+        &self,
+        id: &Uuid,
+        until: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<(), DatabaseError> {
+        let result = self.inner.set_user_locked_until(id, until).await;
+        self.caches.invalidate_user(id);
+        result
+    }
+
+    async fn set_user_status(&self, id: &Uuid, status: UserStatus) -> Result<User, DatabaseError> {
+        let result = self.inner.set_user_status(id, status).await;
+        self.caches.invalidate_user(id);
+        result
+    }
+
+    async fn merge_users(
+        &self,
+        source_id: &Uuid,
+        target_id: &Uuid,
+        dry_run: bool,
+    ) -> Result<UserMerge, DatabaseError> {
+        let result = self.inner.merge_users(source_id, target_id, dry_run).await;
+        self.caches.invalidate_user(source_id);
+        self.caches.invalidate_user(target_id);
+        result
+    }
+
+    async fn anonymize_user(&self, id: &Uuid) -> Result<User, DatabaseError> {
+        let result = self.inner.anonymize_user(id).await;
+        self.caches.invalidate_user(id);
+        result
+    }
+
+    async fn list_users(
+        &self,
+        query: &UserListQuery,
+        after: Option<&UserCursor>,
+        limit: u32,
+    ) -> Result<UserList, DatabaseError> {
+        self.inner.list_users(query, after, limit).await
+    }
+
+    async fn search_users(&self, query: &str, limit: u32) -> Result<Vec<User>, DatabaseError> {
+        self.inner.search_users(query, limit).await
+    }
+
+    async fn delete_user_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = self.inner.delete_user_by_id(id).await;
+        self.caches.invalidate_user(id);
+        result
+    }
+
+    async fn add_tag_to_user(&self, user_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        let result = self.inner.add_tag_to_user(user_id, tag).await;
+        self.caches.invalidate_tags(user_id);
+        result
+    }
+
+    async fn remove_tag_from_user(&self, user_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        let result = self.inner.remove_tag_from_user(user_id, tag).await;
+        self.caches.invalidate_tags(user_id);
+        result
+    }
+
+    async fn get_users_by_tag_id(&self, tag_id: &Uuid) -> Result<Vec<User>, DatabaseError> {
+        self.inner.get_users_by_tag_id(tag_id).await
+    }
+
+    async fn create_user_note(&self, note: &UserNote) -> Result<UserNote, DatabaseError> {
+        self.inner.create_user_note(note).await
+    }
+
+    async fn get_user_notes(&self, user_id: &Uuid) -> Result<Vec<UserNote>, DatabaseError> {
+        self.inner.get_user_notes(user_id).await
+    }
+
+    async fn create_tag(
+        &self,
+        id: &Uuid,
+        organization_id: Option<&Uuid>,
+        tag: &TagUpdate,
+    ) -> Result<Tag, DatabaseError> {
+        self.inner.create_tag(id, organization_id, tag).await
+    }
+
+    async fn get_tag_by_id(&self, id: &Uuid) -> Result<Tag, DatabaseError> {
+        self.inner.get_tag_by_id(id).await
+    }
+
+    async fn get_tag_by_name(&self, name: &str) -> Result<Tag, DatabaseError> {
+        self.inner.get_tag_by_name(name).await
+    }
+
+    async fn list_tags(&self) -> Result<Vec<Tag>, DatabaseError> {
+        self.inner.list_tags().await
+    }
+
+    async fn update_tag(&self, id: &Uuid, update: &TagUpdate) -> Result<Tag, DatabaseError> {
+        let result = self.inner.update_tag(id, update).await;
+        self.caches.clear_tags();
+        result
+    }
+
+    async fn delete_tag_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = self.inner.delete_tag_by_id(id).await;
+        self.caches.clear_tags();
+        result
+    }
+
+    async fn get_tags_by_user_id(&self, user_id: &Uuid) -> Result<Vec<Tag>, DatabaseError> {
+        self.caches
+            .tags
+            .get_or_fetch(*user_id, self.inner.get_tags_by_user_id(user_id))
+            .await
+    }
+
+    async fn get_tags_by_user_ids(
+        &self,
+        user_ids: &[Uuid],
+    ) -> Result<HashMap<Uuid, Vec<Tag>>, DatabaseError> {
+        self.inner.get_tags_by_user_ids(user_ids).await
+    }
+
+    async fn get_tags_by_organization_id(
+        &self,
+        organization_id: &Uuid,
+    ) -> Result<Vec<Tag>, DatabaseError> {
+        self.inner
+            .get_tags_by_organization_id(organization_id)
+            .await
+    }
+
+    async fn create_organization(
+        &self,
+        id: &Uuid,
+        organization: &OrganizationUpdate,
+    ) -> Result<Organization, DatabaseError> {
+        self.inner.create_organization(id, organization).await
+    }
+
+    async fn get_organization_by_id(&self, id: &Uuid) -> Result<Organization, DatabaseError> {
+        self.inner.get_organization_by_id(id).await
+    }
+
+    async fn list_organizations(&self) -> Result<Vec<Organization>, DatabaseError> {
+        self.inner.list_organizations().await
+    }
+
+    async fn update_organization(
+        &self,
+        id: &Uuid,
+        update: &OrganizationUpdate,
+    ) -> Result<Organization, DatabaseError> {
+        self.inner.update_organization(id, update).await
+    }
+
+    async fn delete_organization_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = self.inner.delete_organization_by_id(id).await;
+        self.caches.clear_tags();
+        result
+    }
+
+    async fn get_organizations_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<Organization>, DatabaseError> {
+        self.inner.get_organizations_by_user_id(user_id).await
+    }
+
+    async fn get_organization_members(
+        &self,
+        organization_id: &Uuid,
+    ) -> Result<Vec<OrganizationMember>, DatabaseError> {
+        self.inner.get_organization_members(organization_id).await
+    }
+
+    async fn get_organization_member(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<OrganizationMember, DatabaseError> {
+        self.inner
+            .get_organization_member(organization_id, user_id)
+            .await
+    }
+
+    async fn set_organization_member(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+        role: OrganizationRole,
+    ) -> Result<OrganizationMember, DatabaseError> {
+        let result = self
+            .inner
+            .set_organization_member(organization_id, user_id, role)
+            .await;
+        self.caches.invalidate_tags(user_id);
+        result
+    }
+
+    async fn remove_organization_member(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        let result = self
+            .inner
+            .remove_organization_member(organization_id, user_id)
+            .await;
+        self.caches.invalidate_tags(user_id);
+        result
+    }
+
+    async fn create_group(&self, id: &Uuid, group: &GroupUpdate) -> Result<Group, DatabaseError> {
+        self.inner.create_group(id, group).await
+    }
+
+    async fn get_group_by_id(&self, id: &Uuid) -> Result<Group, DatabaseError> {
+        self.inner.get_group_by_id(id).await
+    }
+
+    async fn list_groups(&self) -> Result<Vec<Group>, DatabaseError> {
+        self.inner.list_groups().await
+    }
+
+    async fn update_group(&self, id: &Uuid, update: &GroupUpdate) -> Result<Group, DatabaseError> {
+        self.inner.update_group(id, update).await
+    }
+
+    async fn delete_group_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = self.inner.delete_group_by_id(id).await;
+        self.caches.effective_tags.clear();
+        result
+    }
+
+    async fn get_users_by_group_id(
+        &self,
+        group_id: &Uuid,
+        include_subgroups: bool,
+    ) -> Result<Vec<User>, DatabaseError> {
+        self.inner
+            .get_users_by_group_id(group_id, include_subgroups)
+            .await
+    }
+
+    async fn get_groups_by_user_id(&self, user_id: &Uuid) -> Result<Vec<Group>, DatabaseError> {
+        self.inner.get_groups_by_user_id(user_id).await
+    }
+
+    async fn add_user_to_group(
+        &self,
+        group_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        let result = self.inner.add_user_to_group(group_id, user_id).await;
+        self.caches.effective_tags.remove(user_id);
+        result
+    }
+
+    async fn remove_user_from_group(
+        &self,
+        group_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        let result = self.inner.remove_user_from_group(group_id, user_id).await;
+        self.caches.effective_tags.remove(user_id);
+        result
+    }
+
+    async fn get_subgroups(&self, group_id: &Uuid) -> Result<Vec<Group>, DatabaseError> {
+        self.inner.get_subgroups(group_id).await
+    }
+
+    async fn get_supergroup_ids(&self, group_id: &Uuid) -> Result<Vec<Uuid>, DatabaseError> {
+        self.inner.get_supergroup_ids(group_id).await
+    }
+
+    async fn add_subgroup(&self, parent_id: &Uuid, child_id: &Uuid) -> Result<(), DatabaseError> {
+        let result = self.inner.add_subgroup(parent_id, child_id).await;
+        self.caches.effective_tags.clear();
+        result
+    }
+
+    async fn remove_subgroup(
+        &self,
+        parent_id: &Uuid,
+        child_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        let result = self.inner.remove_subgroup(parent_id, child_id).await;
+        self.caches.effective_tags.clear();
+        result
+    }
+
+    async fn add_tag_to_group(&self, group_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        let result = self.inner.add_tag_to_group(group_id, tag).await;
+        self.caches.effective_tags.clear();
+        result
+    }
+
+    async fn remove_tag_from_group(&self, group_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        let result = self.inner.remove_tag_from_group(group_id, tag).await;
+        self.caches.effective_tags.clear();
+        result
+    }
+
+    async fn get_tags_by_group_id(&self, group_id: &Uuid) -> Result<Vec<Tag>, DatabaseError> {
+        self.inner.get_tags_by_group_id(group_id).await
+    }
+
+    async fn get_effective_tags_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<Tag>, DatabaseError> {
+        self.caches
+            .effective_tags
+            .get_or_fetch(*user_id, self.inner.get_effective_tags_by_user_id(user_id))
+            .await
+    }
+
+    async fn create_role(&self, id: &Uuid, role: &RoleUpdate) -> Result<Role, DatabaseError> {
+        self.inner.create_role(id, role).await
+    }
+
+    async fn get_role_by_id(&self, id: &Uuid) -> Result<Role, DatabaseError> {
+        self.inner.get_role_by_id(id).await
+    }
+
+    async fn list_roles(&self) -> Result<Vec<Role>, DatabaseError> {
+        self.inner.list_roles().await
+    }
+
+    async fn update_role(&self, id: &Uuid, update: &RoleUpdate) -> Result<Role, DatabaseError> {
+        self.inner.update_role(id, update).await
+    }
+
+    async fn delete_role_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_role_by_id(id).await
+    }
+
+    async fn get_roles_by_tag_id(&self, tag_id: &Uuid) -> Result<Vec<Role>, DatabaseError> {
+        self.inner.get_roles_by_tag_id(tag_id).await
+    }
+
+    async fn add_role_to_tag(&self, tag_id: &Uuid, role_id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.add_role_to_tag(tag_id, role_id).await
+    }
+
+    async fn remove_role_from_tag(
+        &self,
+        tag_id: &Uuid,
+        role_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        self.inner.remove_role_from_tag(tag_id, role_id).await
+    }
+
+    async fn create_policy(
+        &self,
+        id: &Uuid,
+        policy: &PolicyUpdate,
+    ) -> Result<Policy, DatabaseError> {
+        self.inner.create_policy(id, policy).await
+    }
+
+    async fn get_policy_by_id(&self, id: &Uuid) -> Result<Policy, DatabaseError> {
+        self.inner.get_policy_by_id(id).await
+    }
+
+    async fn list_policies(&self) -> Result<Vec<Policy>, DatabaseError> {
+        self.inner.list_policies().await
+    }
+
+    async fn update_policy(
+        &self,
+        id: &Uuid,
+        update: &PolicyUpdate,
+    ) -> Result<Policy, DatabaseError> {
+        self.inner.update_policy(id, update).await
+    }
+
+    async fn delete_policy_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_policy_by_id(id).await
+    }
+
+    async fn list_policy_versions(
+        &self,
+        policy_id: &Uuid,
+    ) -> Result<Vec<PolicyVersion>, DatabaseError> {
+        self.inner.list_policy_versions(policy_id).await
+    }
+
+    async fn get_policy_version(
+        &self,
+        policy_id: &Uuid,
+        version: i64,
+    ) -> Result<PolicyVersion, DatabaseError> {
+        self.inner.get_policy_version(policy_id, version).await
+    }
+
+    async fn create_passkey(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        passkey: &NewPasskeyCredential,
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        self.inner.create_passkey(id, user_id, passkey).await
+    }
+
+    async fn get_passkey_by_id(&self, id: &Uuid) -> Result<PasskeyCredential, DatabaseError> {
+        self.inner.get_passkey_by_id(id).await
+    }
+
+    async fn get_passkey_by_credential_id(
+        &self,
+        credential_id: &[u8],
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        self.inner.get_passkey_by_credential_id(credential_id).await
+    }
+
+    async fn get_passkeys_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<PasskeyCredential>, DatabaseError> {
+        self.inner.get_passkeys_by_user_id(user_id).await
+    }
+
+    async fn get_passkeys_by_user_email(
+        &self,
+        email: &str,
+    ) -> Result<Vec<PasskeyCredential>, DatabaseError> {
+        self.inner.get_passkeys_by_user_email(email).await
+    }
+
+    async fn update_passkey(
+        &self,
+        id: &Uuid,
+        passkey: &PasskeyCredentialUpdate,
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        self.inner.update_passkey(id, passkey).await
+    }
+
+    async fn delete_passkey_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_passkey_by_id(id).await
+    }
+
+    async fn delete_passkey_unless_last(&self, id: &Uuid) -> Result<bool, DatabaseError> {
+        self.inner.delete_passkey_unless_last(id).await
+    }
+
+    async fn create_passkey_registration(
+        &self,
+        registration: &PasskeyRegistrationState,
+    ) -> Result<(), DatabaseError> {
+        self.inner.create_passkey_registration(registration).await
+    }
+
+    async fn get_passkey_registration_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<PasskeyRegistrationState, DatabaseError> {
+        self.inner.get_passkey_registration_by_id(id).await
+    }
+
+    async fn delete_passkey_registration_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_passkey_registration_by_id(id).await
+    }
+
+    async fn create_passkey_authentication(
+        &self,
+        state: &PasskeyAuthenticationState,
+    ) -> Result<(), DatabaseError> {
+        self.inner.create_passkey_authentication(state).await
+    }
+
+    async fn get_passkey_authentication_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<PasskeyAuthenticationState, DatabaseError> {
+        self.inner.get_passkey_authentication_by_id(id).await
+    }
+
+    async fn delete_passkey_authentication_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_passkey_authentication_by_id(id).await
+    }
+
+    async fn delete_expired_ceremonies(&self, age: Duration) -> Result<u64, DatabaseError> {
+        self.inner.delete_expired_ceremonies(age).await
+    }
+
+    async fn create_session(&self, session: &Session) -> Result<(), DatabaseError> {
+        self.inner.create_session(session).await
+    }
+
+    async fn get_session_by_id_hash(
+        &self,
+        id_hash: &EncodableHash,
+    ) -> Result<Session, DatabaseError> {
+        self.inner.get_session_by_id_hash(id_hash).await
+    }
+
+    async fn get_active_sessions_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<Session>, DatabaseError> {
+        self.inner.get_active_sessions_by_user_id(user_id).await
+    }
+
+    async fn update_session(
+        &self,
+        id_hash: &EncodableHash,
+        update: &SessionUpdate,
+    ) -> Result<Session, DatabaseError> {
+        self.inner.update_session(id_hash, update).await
+    }
+
+    async fn create_session_revocation_token(
+        &self,
+        token_hash: &EncodableHash,
+        session_id_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError> {
+        self.inner
+            .create_session_revocation_token(token_hash, session_id_hash)
+            .await
+    }
+
+    async fn revoke_session_by_token(
+        &self,
+        token_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError> {
+        self.inner.revoke_session_by_token(token_hash).await
+    }
+
+    async fn delete_ended_sessions(&self, age: Duration) -> Result<u64, DatabaseError> {
+        self.inner.delete_ended_sessions(age).await
+    }
+
+    async fn revoke_all_sessions(
+        &self,
+        except: Option<&EncodableHash>,
+    ) -> Result<u64, DatabaseError> {
+        self.inner.revoke_all_sessions(except).await
+    }
+
+    async fn revoke_sessions_by_user_id(&self, user_id: &Uuid) -> Result<u64, DatabaseError> {
+        self.inner.revoke_sessions_by_user_id(user_id).await
+    }
+
+    async fn create_api_token(&self, token: &ApiToken) -> Result<ApiToken, DatabaseError> {
+        self.inner.create_api_token(token).await
+    }
+
+    async fn get_api_token_by_hash(
+        &self,
+        token_hash: &EncodableHash,
+    ) -> Result<ApiToken, DatabaseError> {
+        self.inner.get_api_token_by_hash(token_hash).await
+    }
+
+    async fn get_api_tokens_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<ApiToken>, DatabaseError> {
+        self.inner.get_api_tokens_by_user_id(user_id).await
+    }
+
+    async fn delete_api_token(&self, user_id: &Uuid, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_api_token(user_id, id).await
+    }
+
+    async fn record_user_device(
+        &self,
+        user_id: &Uuid,
+        device_id_hash: &EncodableHash,
+    ) -> Result<bool, DatabaseError> {
+        self.inner.record_user_device(user_id, device_id_hash).await
+    }
+
+    async fn create_queued_email(&self, email: &QueuedEmail) -> Result<(), DatabaseError> {
+        self.inner.create_queued_email(email).await
+    }
+
+    async fn get_due_queued_emails(&self, limit: u32) -> Result<Vec<QueuedEmail>, DatabaseError> {
+        self.inner.get_due_queued_emails(limit).await
+    }
+
+    async fn update_queued_email(
+        &self,
+        id: &Uuid,
+        update: &QueuedEmailUpdate,
+    ) -> Result<QueuedEmail, DatabaseError> {
+        self.inner.update_queued_email(id, update).await
+    }
+
+    async fn delete_queued_email_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_queued_email_by_id(id).await
+    }
+
+    async fn create_email_suppression(
+        &self,
+        address: &str,
+        reason: &str,
+    ) -> Result<EmailSuppression, DatabaseError> {
+        self.inner.create_email_suppression(address, reason).await
+    }
+
+    async fn get_email_suppression(
+        &self,
+        address: &str,
+    ) -> Result<EmailSuppression, DatabaseError> {
+        self.inner.get_email_suppression(address).await
+    }
+
+    async fn list_email_suppressions(&self) -> Result<Vec<EmailSuppression>, DatabaseError> {
+        self.inner.list_email_suppressions().await
+    }
+
+    async fn delete_email_suppression(&self, address: &str) -> Result<(), DatabaseError> {
+        self.inner.delete_email_suppression(address).await
+    }
+
+    async fn create_ip_ban(&self, ban: &IpBan) -> Result<IpBan, DatabaseError> {
+        self.inner.create_ip_ban(ban).await
+    }
+
+    async fn list_ip_bans(&self) -> Result<Vec<IpBan>, DatabaseError> {
+        self.inner.list_ip_bans().await
+    }
+
+    async fn delete_ip_ban_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_ip_ban_by_id(id).await
+    }
+
+    async fn try_acquire_lease(
+        &self,
+        name: &str,
+        holder: &Uuid,
+        ttl: Duration,
+    ) -> Result<bool, DatabaseError> {
+        self.inner.try_acquire_lease(name, holder, ttl).await
+    }
+
+    async fn get_announcement(&self) -> Result<Announcement, DatabaseError> {
+        self.inner.get_announcement().await
+    }
+
+    async fn set_announcement(&self, announcement: &Announcement) -> Result<(), DatabaseError> {
+        self.inner.set_announcement(announcement).await
+    }
+
+    async fn delete_announcement(&self) -> Result<(), DatabaseError> {
+        self.inner.delete_announcement().await
+    }
+
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<Webhook, DatabaseError> {
+        self.inner.create_webhook(webhook).await
+    }
+
+    async fn list_webhooks(&self) -> Result<Vec<Webhook>, DatabaseError> {
+        self.inner.list_webhooks().await
+    }
+
+    async fn get_webhook_by_id(&self, id: &Uuid) -> Result<Webhook, DatabaseError> {
+        self.inner.get_webhook_by_id(id).await
+    }
+
+    async fn update_webhook(
+        &self,
+        id: &Uuid,
+        update: &WebhookUpdate,
+    ) -> Result<Webhook, DatabaseError> {
+        self.inner.update_webhook(id, update).await
+    }
+
+    async fn delete_webhook_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_webhook_by_id(id).await
+    }
+
+    async fn create_oauth_client(
+        &self,
+        client: &OAuthClient,
+    ) -> Result<OAuthClient, DatabaseError> {
+        self.inner.create_oauth_client(client).await
+    }
+
+    async fn list_oauth_clients(&self) -> Result<Vec<OAuthClient>, DatabaseError> {
+        self.inner.list_oauth_clients().await
+    }
+
+    async fn get_oauth_client_by_id(&self, id: &Uuid) -> Result<OAuthClient, DatabaseError> {
+        self.inner.get_oauth_client_by_id(id).await
+    }
+
+    async fn update_oauth_client(
+        &self,
+        id: &Uuid,
+        update: &OAuthClientUpdate,
+    ) -> Result<OAuthClient, DatabaseError> {
+        self.inner.update_oauth_client(id, update).await
+    }
+
+    async fn delete_oauth_client_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_oauth_client_by_id(id).await
+    }
+
+    async fn create_authorization_code(
+        &self,
+        code: &AuthorizationCode,
+    ) -> Result<(), DatabaseError> {
+        self.inner.create_authorization_code(code).await
+    }
+
+    async fn consume_authorization_code(
+        &self,
+        code_hash: &EncodableHash,
+    ) -> Result<AuthorizationCode, DatabaseError> {
+        self.inner.consume_authorization_code(code_hash).await
+    }
+
+    async fn create_saml_service_provider(
+        &self,
+        provider: &SamlServiceProvider,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        self.inner.create_saml_service_provider(provider).await
+    }
+
+    async fn list_saml_service_providers(&self) -> Result<Vec<SamlServiceProvider>, DatabaseError> {
+        self.inner.list_saml_service_providers().await
+    }
+
+    async fn get_saml_service_provider_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        self.inner.get_saml_service_provider_by_id(id).await
+    }
+
+    async fn get_saml_service_provider_by_entity_id(
+        &self,
+        entity_id: &str,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        self.inner
+            .get_saml_service_provider_by_entity_id(entity_id)
+            .await
+    }
+
+    async fn update_saml_service_provider(
+        &self,
+        id: &Uuid,
+        update: &SamlServiceProviderUpdate,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        self.inner.update_saml_service_provider(id, update).await
+    }
+
+    async fn delete_saml_service_provider_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_saml_service_provider_by_id(id).await
+    }
+
+    async fn create_federated_provider(
+        &self,
+        provider: &FederatedProvider,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        self.inner.create_federated_provider(provider).await
+    }
+
+    async fn list_federated_providers(&self) -> Result<Vec<FederatedProvider>, DatabaseError> {
+        self.inner.list_federated_providers().await
+    }
+
+    async fn get_federated_provider_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        self.inner.get_federated_provider_by_id(id).await
+    }
+
+    async fn get_federated_provider_by_slug(
+        &self,
+        slug: &str,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        self.inner.get_federated_provider_by_slug(slug).await
+    }
+
+    async fn update_federated_provider(
+        &self,
+        id: &Uuid,
+        update: &FederatedProviderUpdate,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        self.inner.update_federated_provider(id, update).await
+    }
+
+    async fn delete_federated_provider_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_federated_provider_by_id(id).await
+    }
+
+    async fn create_federated_login_state(
+        &self,
+        state: &FederatedLoginState,
+    ) -> Result<(), DatabaseError> {
+        self.inner.create_federated_login_state(state).await
+    }
+
+    async fn consume_federated_login_state(
+        &self,
+        state_hash: &EncodableHash,
+    ) -> Result<FederatedLoginState, DatabaseError> {
+        self.inner.consume_federated_login_state(state_hash).await
+    }
+
+    async fn get_federated_identity(
+        &self,
+        provider_id: &Uuid,
+        subject: &str,
+    ) -> Result<FederatedIdentity, DatabaseError> {
+        self.inner
+            .get_federated_identity(provider_id, subject)
+            .await
+    }
+
+    async fn create_federated_identity(
+        &self,
+        identity: &FederatedIdentity,
+    ) -> Result<(), DatabaseError> {
+        self.inner.create_federated_identity(identity).await
+    }
+
+    async fn list_federated_identities_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<FederatedIdentity>, DatabaseError> {
+        self.inner
+            .list_federated_identities_by_user_id(user_id)
+            .await
+    }
+
+    async fn delete_federated_identities(
+        &self,
+        user_id: &Uuid,
+        provider_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        self.inner
+            .delete_federated_identities(user_id, provider_id)
+            .await
+    }
+
+    async fn create_signing_key(&self, key: &StoredSigningKey) -> Result<(), DatabaseError> {
+        self.inner.create_signing_key(key).await
+    }
+
+    async fn list_signing_keys(&self) -> Result<Vec<StoredSigningKey>, DatabaseError> {
+        self.inner.list_signing_keys().await
+    }
+
+    async fn retire_signing_keys_older_than(&self, kid: &str) -> Result<u64, DatabaseError> {
+        self.inner.retire_signing_keys_older_than(kid).await
+    }
+
+    async fn delete_retired_signing_keys(&self, age: Duration) -> Result<u64, DatabaseError> {
+        self.inner.delete_retired_signing_keys(age).await
+    }
+
+    async fn get_or_create_session_cookie_key(
+        &self,
+        key: &StoredSessionCookieKey,
+    ) -> Result<StoredSessionCookieKey, DatabaseError> {
+        self.inner.get_or_create_session_cookie_key(key).await
+    }
+
+    async fn purge_retained_data(
+        &self,
+        policy: &RetentionPolicy,
+    ) -> Result<RetentionReport, DatabaseError> {
+        let result = self.inner.purge_retained_data(policy).await;
+        self.caches.clear();
+        result
+    }
+
+    async fn run_maintenance(&self, task: MaintenanceTask) -> Result<(), DatabaseError> {
+        self.inner.run_maintenance(task).await
+    }
+
+    async fn export_backup(&self) -> Result<Backup, DatabaseError> {
+        self.inner.export_backup().await
+    }
+
+    async fn import_backup(&self, backup: &Backup) -> Result<(), DatabaseError> {
+        let result = self.inner.import_backup(backup).await;
+        self.caches.clear();
+        result
+    }
+
+    async fn begin(&self) -> Result<BoxedTransaction, DatabaseError> {
+        let tx: BoxedTransaction = Box::new(CachedTransaction {
+            inner: self.inner.begin().await?,
+            caches: Arc::clone(&self.caches),
+            tagged_users: Vec::new(),
+        });
+        Ok(tx)
+    }
+}
+
+/// # Transaction of a [`CachedClient`]
+///
+/// Invalidates the entries affected by the operations performed through it once it's committed.
+struct CachedTransaction {
+    inner: BoxedTransaction,
+    caches: Arc<Caches>,
+    /// UUIDs of the users whose tags were changed through the transaction
+    tagged_users: Vec<Uuid>,
+}
+
+#[async_trait]
+impl DatabaseTransaction for CachedTransaction {
+    async fn create_user(&mut self, id: &Uuid, user: &UserCreate) -> Result<User, DatabaseError> {
+        self.inner.create_user(id, user).await
+    }
+
+    async fn add_tag_to_user(&mut self, user_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        self.tagged_users.push(*user_id);
+        self.inner.add_tag_to_user(user_id, tag).await
+    }
+
+    async fn create_passkey(
+        &mut self,
+        id: &Uuid,
+        user_id: &Uuid,
+        passkey: &NewPasskeyCredential,
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        self.inner.create_passkey(id, user_id, passkey).await
+    }
+
+    async fn create_session(&mut self, session: &Session) -> Result<(), DatabaseError> {
+        self.inner.create_session(session).await
+    }
+
+    async fn update_session(
+        &mut self,
+        id_hash: &EncodableHash,
+        update: &SessionUpdate,
+    ) -> Result<Session, DatabaseError> {
+        self.inner.update_session(id_hash, update).await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), DatabaseError> {
+        let result = self.inner.commit().await;
+        for user_id in &self.tagged_users {
+            self.caches.invalidate_tags(user_id);
+        }
+        result
+    }
+}
+
+#[cfg(all(test, feature = "sqlite3"))]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{
+        db::clients::sqlite::SqliteClient,
+        models::{SessionState, TagUpdate},
+    };
+
+    #[tokio::test]
+    async fn test_writes_invalidate_cached_reads() {
+        let inner = Arc::new(SqliteClient::new_memory().await.unwrap());
+        let client = CachedClient::new(Arc::clone(&inner), Duration::from_secs(60));
+        let user = client
+            .create_user(
+                &Uuid::new_v4(),
+                &UserCreate {
+                    email: "test@example.com".to_string(),
+                    display_name: "Test User".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        let id = user.id();
+
+        // Reads are cached, so changes bypassing the client aren't seen...
+        client.get_user_by_id(id).await.unwrap();
+        let update = UserUpdate::new().with_display_name("Bypassed".to_string());
+        inner.update_user(id, &update).await.unwrap();
+        let cached = client.get_user_by_id(id).await.unwrap();
+        assert_eq!(cached.display_name(), "Test User");
+        // ...but changes made through it are
+        let update = UserUpdate::new().with_display_name("Renamed".to_string());
+        client.update_user(id, &update).await.unwrap();
+        let fetched = client.get_user_by_id(id).await.unwrap();
+        assert_eq!(fetched.display_name(), "Renamed");
+
+        let tag = client
+            .create_tag(
+                &Uuid::new_v4(),
+                None,
+                &TagUpdate::new().with_name("tag".to_string()),
+            )
+            .await
+            .unwrap();
+        assert!(client.get_tags_by_user_id(id).await.unwrap().is_empty());
+        assert!(
+            client
+                .get_effective_tags_by_user_id(id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        client.add_tag_to_user(id, &tag).await.unwrap();
+        assert_eq!(client.get_tags_by_user_id(id).await.unwrap().len(), 1);
+        assert_eq!(
+            client
+                .get_effective_tags_by_user_id(id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Changes made through transactions are seen once they're committed
+        let other = client
+            .create_tag(
+                &Uuid::new_v4(),
+                None,
+                &TagUpdate::new().with_name("other".to_string()),
+            )
+            .await
+            .unwrap();
+        let mut tx = client.begin().await.unwrap();
+        tx.add_tag_to_user(id, &other).await.unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(client.get_tags_by_user_id(id).await.unwrap().len(), 2);
+
+        // Sessions aren't cached, since the API caches them itself
+        let now = Utc::now();
+        let session = Session {
+            user_id: *id,
+            id_hash: blake3::hash(b"session").into(),
+            state: SessionState::Active,
+            created_at: now,
+            expires_at: now + chrono::Duration::days(1),
+            is_admin: false,
+            parent_id_hash: None,
+            ip: None,
+            user_agent: None,
+            last_seen_at: now,
+            impersonator_id: None,
+            authenticated_at: now,
+            organization_id: None,
+        };
+        client.create_session(&session).await.unwrap();
+        client
+            .get_session_by_id_hash(&session.id_hash)
+            .await
+            .unwrap();
+        let update = SessionUpdate::new().with_state(SessionState::Revoked);
+        inner
+            .update_session(&session.id_hash, &update)
+            .await
+            .unwrap();
+        let fetched = client
+            .get_session_by_id_hash(&session.id_hash)
+            .await
+            .unwrap();
+        assert_eq!(fetched.state, SessionState::Revoked);
+
+        client
+            .set_user_status(id, UserStatus::Suspended)
+            .await
+            .unwrap();
+        let fetched = client.get_user_by_id(id).await.unwrap();
+        assert_eq!(fetched.status(), UserStatus::Suspended);
+    }
+}
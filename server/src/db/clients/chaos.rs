@@ -0,0 +1,1123 @@
+//! # Fault-injecting database client
+//!
+//! [`ChaosClient`] wraps another [`DatabaseClient`] and makes it slow and unreliable: every
+//! operation is delayed by a random amount of time and fails with a random [`DatabaseError`] with
+//! a configurable probability. It's used to test how API handlers and the UI cope with database
+//! failures, and must not be used in production.
+//!
+//! Only available with the `chaos` feature.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use rand::Rng;
+use tracing::debug;
+use uuid::Uuid;
+
+use crate::{
+    db::interface::{BoxedTransaction, DatabaseClient, DatabaseError},
+    models::{
+        Announcement, ApiToken, AuthorizationCode, Backup, EmailSuppression, EncodableHash,
+        FederatedIdentity, FederatedLoginState, FederatedProvider, FederatedProviderUpdate, Group,
+        GroupUpdate, IpBan, MaintenanceTask, NewPasskeyCredential, OAuthClient, OAuthClientUpdate,
+        Organization, OrganizationMember, OrganizationRole, OrganizationUpdate,
+        PasskeyAuthenticationState, PasskeyCredential, PasskeyCredentialUpdate,
+        PasskeyRegistrationState, Policy, PolicyUpdate, PolicyVersion, QueuedEmail,
+        QueuedEmailUpdate, RetentionPolicy, RetentionReport, Role, RoleUpdate, SamlServiceProvider,
+        SamlServiceProviderUpdate, Session, SessionUpdate, StoredSessionCookieKey,
+        StoredSigningKey, Tag, TagUpdate, User, UserCreate, UserCursor, UserList, UserListQuery,
+        UserMerge, UserNote, UserStatus, UserUpdate, Webhook, WebhookUpdate,
+    },
+};
+
+/// Kind of error injected by a [`ChaosClient`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InjectedError {
+    /// [`DatabaseError::NotFound`]
+    NotFound,
+    /// [`DatabaseError::UniquenessViolation`]
+    UniquenessViolation,
+    /// [`DatabaseError::Other`], e.g. a lost connection
+    Other,
+}
+
+impl From<InjectedError> for DatabaseError {
+    fn from(error: InjectedError) -> Self {
+        match error {
+            InjectedError::NotFound => Self::NotFound,
+            InjectedError::UniquenessViolation => Self::UniquenessViolation { field: None },
+            InjectedError::Other => Self::Other("injected fault".into()),
+        }
+    }
+}
+
+/// Faults injected by a [`ChaosClient`]
+#[derive(Debug, Clone)]
+pub struct ChaosOptions {
+    /// Minimum latency added to each operation
+    pub min_latency: Duration,
+    /// Maximum latency added to each operation. Treated as equal to
+    /// [`min_latency`][Self::min_latency] if it's less.
+    pub max_latency: Duration,
+    /// Probability (from 0 to 1) that an operation fails instead of reaching the inner client
+    pub error_rate: f64,
+    /// Errors to inject, one of which is chosen at random for each failure. Failures are
+    /// [`InjectedError::Other`] if this is empty.
+    pub errors: Vec<InjectedError>,
+}
+
+impl Default for ChaosOptions {
+    /// Returns options which inject no faults.
+    fn default() -> Self {
+        Self {
+            min_latency: Duration::ZERO,
+            max_latency: Duration::ZERO,
+            error_rate: 0.0,
+            errors: vec![InjectedError::Other],
+        }
+    }
+}
+
+/// # Fault-injecting database client
+///
+/// See the [module-level documentation][self] for details.
+pub struct ChaosClient {
+    inner: Arc<dyn DatabaseClient>,
+    options: RwLock<ChaosOptions>,
+}
+
+impl ChaosClient {
+    /// Wraps the given client, injecting faults according to the given options.
+    #[must_use]
+    pub fn new(inner: Arc<dyn DatabaseClient>, options: ChaosOptions) -> Self {
+        Self {
+            inner,
+            options: RwLock::new(options),
+        }
+    }
+
+    /// Returns the current options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the options lock is poisoned.
+    #[must_use]
+    pub fn options(&self) -> ChaosOptions {
+        self.options.read().unwrap().clone()
+    }
+
+    /// Replaces the options, e.g. to start injecting faults after setting up test data. Affects
+    /// operations started after the call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the options lock is poisoned.
+    pub fn set_options(&self, options: ChaosOptions) {
+        *self.options.write().unwrap() = options;
+    }
+
+    /// Wraps a future returned by the inner client so that it's delayed and possibly replaced by
+    /// an error, according to the current options.
+    async fn inject<T>(
+        &self,
+        operation: impl Future<Output = Result<T, DatabaseError>>,
+    ) -> Result<T, DatabaseError> {
+        // Make random choices now, since the thread-local RNG can't be held across await points
+        let (latency, error) = {
+            let options = self.options.read().unwrap();
+            let mut rng = rand::rng();
+            let latency = if options.max_latency > options.min_latency {
+                rng.random_range(options.min_latency..=options.max_latency)
+            } else {
+                options.min_latency
+            };
+            let error = rng
+                .random_bool(options.error_rate.clamp(0.0, 1.0))
+                .then(|| {
+                    if options.errors.is_empty() {
+                        InjectedError::Other
+                    } else {
+                        options.errors[rng.random_range(0..options.errors.len())]
+                    }
+                });
+            (latency, error)
+        };
+        if !latency.is_zero() {
+            tokio::time::sleep(latency).await;
+        }
+        if let Some(error) = error {
+            debug!(?error, "injecting database error");
+            return Err(error.into());
+        }
+        operation.await
+    }
+}
+
+#[async_trait]
+impl DatabaseClient for ChaosClient {
+    async fn create_user(&self, id: &Uuid, user: &UserCreate) -> Result<User, DatabaseError> {
+        self.inject(self.inner.create_user(id, user)).await
+    }
+
+    async fn get_user_by_id(&self, id: &Uuid) -> Result<User, DatabaseError> {
+        self.inject(self.inner.get_user_by_id(id)).await
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<User, DatabaseError> {
+        self.inject(self.inner.get_user_by_email(email)).await
+    }
+
+    async fn get_user_with_relations(&self, id: &Uuid) -> Result<User, DatabaseError> {
+        self.inject(self.inner.get_user_with_relations(id)).await
+    }
+
+    async fn update_user(&self, id: &Uuid, update: &UserUpdate) -> Result<User, DatabaseError> {
+        self.inject(self.inner.update_user(id, update)).await
+    }
+
+    async fn create_email_change(
+        &self,
+        user_id: &Uuid,
+        email: &str,
+        token_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError> {
+        self.inject(self.inner.create_email_change(user_id, email, token_hash))
+            .await
+    }
+
+    async fn confirm_email_change(
+        &self,
+        token_hash: &EncodableHash,
+        max_age: Duration,
+    ) -> Result<User, DatabaseError> {
+        self.inject(self.inner.confirm_email_change(token_hash, max_age))
+            .await
+    }
+
+    async fn record_user_login(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.record_user_login(id)).await
+    }
+
+    async fn record_login_failure(
+        &self,
+        user_id: &Uuid,
+        passkey_id: Option<&Uuid>,
+        window: Duration,
+    ) -> Result<u64, DatabaseError> {
+        self.inject(self.inner.record_login_failure(user_id, passkey_id, window))
+            .await
+    }
+
+    async fn clear_login_failures(&self, user_id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.clear_login_failures(user_id)).await
+    }
+
+    async fn set_user_locked_until(
+        &self,
+        id: &Uuid,
+        until: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<(), DatabaseError> {
+        self.inject(self.inner.set_user_locked_until(id, until))
+            .await
+    }
+
+    async fn set_user_status(&self, id: &Uuid, status: UserStatus) -> Result<User, DatabaseError> {
+        self.inject(self.inner.set_user_status(id, status)).await
+    }
+
+    async fn merge_users(
+        &self,
+        source_id: &Uuid,
+        target_id: &Uuid,
+        dry_run: bool,
+    ) -> Result<UserMerge, DatabaseError> {
+        self.inject(self.inner.merge_users(source_id, target_id, dry_run))
+            .await
+    }
+
+    async fn anonymize_user(&self, id: &Uuid) -> Result<User, DatabaseError> {
+        self.inject(self.inner.anonymize_user(id)).await
+    }
+
+    async fn list_users(
+        &self,
+        query: &UserListQuery,
+        after: Option<&UserCursor>,
+        limit: u32,
+    ) -> Result<UserList, DatabaseError> {
+        self.inject(self.inner.list_users(query, after, limit))
+            .await
+    }
+
+    async fn search_users(&self, query: &str, limit: u32) -> Result<Vec<User>, DatabaseError> {
+        self.inject(self.inner.search_users(query, limit)).await
+    }
+
+    async fn delete_user_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_user_by_id(id)).await
+    }
+
+    async fn add_tag_to_user(&self, user_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        self.inject(self.inner.add_tag_to_user(user_id, tag)).await
+    }
+
+    async fn remove_tag_from_user(&self, user_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        self.inject(self.inner.remove_tag_from_user(user_id, tag))
+            .await
+    }
+
+    async fn get_users_by_tag_id(&self, tag_id: &Uuid) -> Result<Vec<User>, DatabaseError> {
+        self.inject(self.inner.get_users_by_tag_id(tag_id)).await
+    }
+
+    async fn create_user_note(&self, note: &UserNote) -> Result<UserNote, DatabaseError> {
+        self.inject(self.inner.create_user_note(note)).await
+    }
+
+    async fn get_user_notes(&self, user_id: &Uuid) -> Result<Vec<UserNote>, DatabaseError> {
+        self.inject(self.inner.get_user_notes(user_id)).await
+    }
+
+    async fn create_tag(
+        &self,
+        id: &Uuid,
+        organization_id: Option<&Uuid>,
+        tag: &TagUpdate,
+    ) -> Result<Tag, DatabaseError> {
+        self.inject(self.inner.create_tag(id, organization_id, tag))
+            .await
+    }
+
+    async fn get_tag_by_id(&self, id: &Uuid) -> Result<Tag, DatabaseError> {
+        self.inject(self.inner.get_tag_by_id(id)).await
+    }
+
+    async fn get_tag_by_name(&self, name: &str) -> Result<Tag, DatabaseError> {
+        self.inject(self.inner.get_tag_by_name(name)).await
+    }
+
+    async fn list_tags(&self) -> Result<Vec<Tag>, DatabaseError> {
+        self.inject(self.inner.list_tags()).await
+    }
+
+    async fn update_tag(&self, id: &Uuid, update: &TagUpdate) -> Result<Tag, DatabaseError> {
+        self.inject(self.inner.update_tag(id, update)).await
+    }
+
+    async fn delete_tag_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_tag_by_id(id)).await
+    }
+
+    async fn get_tags_by_user_id(&self, user_id: &Uuid) -> Result<Vec<Tag>, DatabaseError> {
+        self.inject(self.inner.get_tags_by_user_id(user_id)).await
+    }
+
+    async fn get_tags_by_user_ids(
+        &self,
+        user_ids: &[Uuid],
+    ) -> Result<HashMap<Uuid, Vec<Tag>>, DatabaseError> {
+        self.inject(self.inner.get_tags_by_user_ids(user_ids)).await
+    }
+
+    async fn get_tags_by_organization_id(
+        &self,
+        organization_id: &Uuid,
+    ) -> Result<Vec<Tag>, DatabaseError> {
+        self.inject(self.inner.get_tags_by_organization_id(organization_id))
+            .await
+    }
+
+    async fn create_organization(
+        &self,
+        id: &Uuid,
+        organization: &OrganizationUpdate,
+    ) -> Result<Organization, DatabaseError> {
+        self.inject(self.inner.create_organization(id, organization))
+            .await
+    }
+
+    async fn get_organization_by_id(&self, id: &Uuid) -> Result<Organization, DatabaseError> {
+        self.inject(self.inner.get_organization_by_id(id)).await
+    }
+
+    async fn list_organizations(&self) -> Result<Vec<Organization>, DatabaseError> {
+        self.inject(self.inner.list_organizations()).await
+    }
+
+    async fn update_organization(
+        &self,
+        id: &Uuid,
+        update: &OrganizationUpdate,
+    ) -> Result<Organization, DatabaseError> {
+        self.inject(self.inner.update_organization(id, update))
+            .await
+    }
+
+    async fn delete_organization_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_organization_by_id(id)).await
+    }
+
+    async fn get_organizations_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<Organization>, DatabaseError> {
+        self.inject(self.inner.get_organizations_by_user_id(user_id))
+            .await
+    }
+
+    async fn get_organization_members(
+        &self,
+        organization_id: &Uuid,
+    ) -> Result<Vec<OrganizationMember>, DatabaseError> {
+        self.inject(self.inner.get_organization_members(organization_id))
+            .await
+    }
+
+    async fn get_organization_member(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<OrganizationMember, DatabaseError> {
+        self.inject(self.inner.get_organization_member(organization_id, user_id))
+            .await
+    }
+
+    async fn set_organization_member(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+        role: OrganizationRole,
+    ) -> Result<OrganizationMember, DatabaseError> {
+        self.inject(
+            self.inner
+                .set_organization_member(organization_id, user_id, role),
+        )
+        .await
+    }
+
+    async fn remove_organization_member(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        self.inject(
+            self.inner
+                .remove_organization_member(organization_id, user_id),
+        )
+        .await
+    }
+
+    async fn create_group(&self, id: &Uuid, group: &GroupUpdate) -> Result<Group, DatabaseError> {
+        self.inject(self.inner.create_group(id, group)).await
+    }
+
+    async fn get_group_by_id(&self, id: &Uuid) -> Result<Group, DatabaseError> {
+        self.inject(self.inner.get_group_by_id(id)).await
+    }
+
+    async fn list_groups(&self) -> Result<Vec<Group>, DatabaseError> {
+        self.inject(self.inner.list_groups()).await
+    }
+
+    async fn update_group(&self, id: &Uuid, update: &GroupUpdate) -> Result<Group, DatabaseError> {
+        self.inject(self.inner.update_group(id, update)).await
+    }
+
+    async fn delete_group_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_group_by_id(id)).await
+    }
+
+    async fn get_users_by_group_id(
+        &self,
+        group_id: &Uuid,
+        include_subgroups: bool,
+    ) -> Result<Vec<User>, DatabaseError> {
+        self.inject(
+            self.inner
+                .get_users_by_group_id(group_id, include_subgroups),
+        )
+        .await
+    }
+
+    async fn get_groups_by_user_id(&self, user_id: &Uuid) -> Result<Vec<Group>, DatabaseError> {
+        self.inject(self.inner.get_groups_by_user_id(user_id)).await
+    }
+
+    async fn add_user_to_group(
+        &self,
+        group_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        self.inject(self.inner.add_user_to_group(group_id, user_id))
+            .await
+    }
+
+    async fn remove_user_from_group(
+        &self,
+        group_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        self.inject(self.inner.remove_user_from_group(group_id, user_id))
+            .await
+    }
+
+    async fn get_subgroups(&self, group_id: &Uuid) -> Result<Vec<Group>, DatabaseError> {
+        self.inject(self.inner.get_subgroups(group_id)).await
+    }
+
+    async fn get_supergroup_ids(&self, group_id: &Uuid) -> Result<Vec<Uuid>, DatabaseError> {
+        self.inject(self.inner.get_supergroup_ids(group_id)).await
+    }
+
+    async fn add_subgroup(&self, parent_id: &Uuid, child_id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.add_subgroup(parent_id, child_id))
+            .await
+    }
+
+    async fn remove_subgroup(
+        &self,
+        parent_id: &Uuid,
+        child_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        self.inject(self.inner.remove_subgroup(parent_id, child_id))
+            .await
+    }
+
+    async fn add_tag_to_group(&self, group_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        self.inject(self.inner.add_tag_to_group(group_id, tag))
+            .await
+    }
+
+    async fn remove_tag_from_group(&self, group_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        self.inject(self.inner.remove_tag_from_group(group_id, tag))
+            .await
+    }
+
+    async fn get_tags_by_group_id(&self, group_id: &Uuid) -> Result<Vec<Tag>, DatabaseError> {
+        self.inject(self.inner.get_tags_by_group_id(group_id)).await
+    }
+
+    async fn get_effective_tags_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<Tag>, DatabaseError> {
+        self.inject(self.inner.get_effective_tags_by_user_id(user_id))
+            .await
+    }
+
+    async fn create_role(&self, id: &Uuid, role: &RoleUpdate) -> Result<Role, DatabaseError> {
+        self.inject(self.inner.create_role(id, role)).await
+    }
+
+    async fn get_role_by_id(&self, id: &Uuid) -> Result<Role, DatabaseError> {
+        self.inject(self.inner.get_role_by_id(id)).await
+    }
+
+    async fn list_roles(&self) -> Result<Vec<Role>, DatabaseError> {
+        self.inject(self.inner.list_roles()).await
+    }
+
+    async fn update_role(&self, id: &Uuid, update: &RoleUpdate) -> Result<Role, DatabaseError> {
+        self.inject(self.inner.update_role(id, update)).await
+    }
+
+    async fn delete_role_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_role_by_id(id)).await
+    }
+
+    async fn get_roles_by_tag_id(&self, tag_id: &Uuid) -> Result<Vec<Role>, DatabaseError> {
+        self.inject(self.inner.get_roles_by_tag_id(tag_id)).await
+    }
+
+    async fn add_role_to_tag(&self, tag_id: &Uuid, role_id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.add_role_to_tag(tag_id, role_id))
+            .await
+    }
+
+    async fn remove_role_from_tag(
+        &self,
+        tag_id: &Uuid,
+        role_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        self.inject(self.inner.remove_role_from_tag(tag_id, role_id))
+            .await
+    }
+
+    async fn create_policy(
+        &self,
+        id: &Uuid,
+        policy: &PolicyUpdate,
+    ) -> Result<Policy, DatabaseError> {
+        self.inject(self.inner.create_policy(id, policy)).await
+    }
+
+    async fn get_policy_by_id(&self, id: &Uuid) -> Result<Policy, DatabaseError> {
+        self.inject(self.inner.get_policy_by_id(id)).await
+    }
+
+    async fn list_policies(&self) -> Result<Vec<Policy>, DatabaseError> {
+        self.inject(self.inner.list_policies()).await
+    }
+
+    async fn update_policy(
+        &self,
+        id: &Uuid,
+        update: &PolicyUpdate,
+    ) -> Result<Policy, DatabaseError> {
+        self.inject(self.inner.update_policy(id, update)).await
+    }
+
+    async fn delete_policy_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_policy_by_id(id)).await
+    }
+
+    async fn list_policy_versions(
+        &self,
+        policy_id: &Uuid,
+    ) -> Result<Vec<PolicyVersion>, DatabaseError> {
+        self.inject(self.inner.list_policy_versions(policy_id))
+            .await
+    }
+
+    async fn get_policy_version(
+        &self,
+        policy_id: &Uuid,
+        version: i64,
+    ) -> Result<PolicyVersion, DatabaseError> {
+        self.inject(self.inner.get_policy_version(policy_id, version))
+            .await
+    }
+
+    async fn create_passkey(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        passkey: &NewPasskeyCredential,
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        self.inject(self.inner.create_passkey(id, user_id, passkey))
+            .await
+    }
+
+    async fn get_passkey_by_id(&self, id: &Uuid) -> Result<PasskeyCredential, DatabaseError> {
+        self.inject(self.inner.get_passkey_by_id(id)).await
+    }
+
+    async fn get_passkey_by_credential_id(
+        &self,
+        credential_id: &[u8],
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        self.inject(self.inner.get_passkey_by_credential_id(credential_id))
+            .await
+    }
+
+    async fn get_passkeys_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<PasskeyCredential>, DatabaseError> {
+        self.inject(self.inner.get_passkeys_by_user_id(user_id))
+            .await
+    }
+
+    async fn get_passkeys_by_user_email(
+        &self,
+        email: &str,
+    ) -> Result<Vec<PasskeyCredential>, DatabaseError> {
+        self.inject(self.inner.get_passkeys_by_user_email(email))
+            .await
+    }
+
+    async fn update_passkey(
+        &self,
+        id: &Uuid,
+        passkey: &PasskeyCredentialUpdate,
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        self.inject(self.inner.update_passkey(id, passkey)).await
+    }
+
+    async fn delete_passkey_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_passkey_by_id(id)).await
+    }
+
+    async fn delete_passkey_unless_last(&self, id: &Uuid) -> Result<bool, DatabaseError> {
+        self.inject(self.inner.delete_passkey_unless_last(id)).await
+    }
+
+    async fn create_passkey_registration(
+        &self,
+        registration: &PasskeyRegistrationState,
+    ) -> Result<(), DatabaseError> {
+        self.inject(self.inner.create_passkey_registration(registration))
+            .await
+    }
+
+    async fn get_passkey_registration_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<PasskeyRegistrationState, DatabaseError> {
+        self.inject(self.inner.get_passkey_registration_by_id(id))
+            .await
+    }
+
+    async fn delete_passkey_registration_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_passkey_registration_by_id(id))
+            .await
+    }
+
+    async fn create_passkey_authentication(
+        &self,
+        state: &PasskeyAuthenticationState,
+    ) -> Result<(), DatabaseError> {
+        self.inject(self.inner.create_passkey_authentication(state))
+            .await
+    }
+
+    async fn get_passkey_authentication_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<PasskeyAuthenticationState, DatabaseError> {
+        self.inject(self.inner.get_passkey_authentication_by_id(id))
+            .await
+    }
+
+    async fn delete_passkey_authentication_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_passkey_authentication_by_id(id))
+            .await
+    }
+
+    async fn delete_expired_ceremonies(&self, age: Duration) -> Result<u64, DatabaseError> {
+        self.inject(self.inner.delete_expired_ceremonies(age)).await
+    }
+
+    async fn create_session(&self, session: &Session) -> Result<(), DatabaseError> {
+        self.inject(self.inner.create_session(session)).await
+    }
+
+    async fn get_session_by_id_hash(
+        &self,
+        id_hash: &EncodableHash,
+    ) -> Result<Session, DatabaseError> {
+        self.inject(self.inner.get_session_by_id_hash(id_hash))
+            .await
+    }
+
+    async fn get_active_sessions_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<Session>, DatabaseError> {
+        self.inject(self.inner.get_active_sessions_by_user_id(user_id))
+            .await
+    }
+
+    async fn update_session(
+        &self,
+        id_hash: &EncodableHash,
+        update: &SessionUpdate,
+    ) -> Result<Session, DatabaseError> {
+        self.inject(self.inner.update_session(id_hash, update))
+            .await
+    }
+
+    async fn create_session_revocation_token(
+        &self,
+        token_hash: &EncodableHash,
+        session_id_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError> {
+        self.inject(
+            self.inner
+                .create_session_revocation_token(token_hash, session_id_hash),
+        )
+        .await
+    }
+
+    async fn revoke_session_by_token(
+        &self,
+        token_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError> {
+        self.inject(self.inner.revoke_session_by_token(token_hash))
+            .await
+    }
+
+    async fn delete_ended_sessions(&self, age: Duration) -> Result<u64, DatabaseError> {
+        self.inject(self.inner.delete_ended_sessions(age)).await
+    }
+
+    async fn revoke_all_sessions(
+        &self,
+        except: Option<&EncodableHash>,
+    ) -> Result<u64, DatabaseError> {
+        self.inject(self.inner.revoke_all_sessions(except)).await
+    }
+
+    async fn revoke_sessions_by_user_id(&self, user_id: &Uuid) -> Result<u64, DatabaseError> {
+        self.inject(self.inner.revoke_sessions_by_user_id(user_id))
+            .await
+    }
+
+    async fn create_api_token(&self, token: &ApiToken) -> Result<ApiToken, DatabaseError> {
+        self.inject(self.inner.create_api_token(token)).await
+    }
+
+    async fn get_api_token_by_hash(
+        &self,
+        token_hash: &EncodableHash,
+    ) -> Result<ApiToken, DatabaseError> {
+        self.inject(self.inner.get_api_token_by_hash(token_hash))
+            .await
+    }
+
+    async fn get_api_tokens_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<ApiToken>, DatabaseError> {
+        self.inject(self.inner.get_api_tokens_by_user_id(user_id))
+            .await
+    }
+
+    async fn delete_api_token(&self, user_id: &Uuid, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_api_token(user_id, id)).await
+    }
+
+    async fn record_user_device(
+        &self,
+        user_id: &Uuid,
+        device_id_hash: &EncodableHash,
+    ) -> Result<bool, DatabaseError> {
+        self.inject(self.inner.record_user_device(user_id, device_id_hash))
+            .await
+    }
+
+    async fn create_queued_email(&self, email: &QueuedEmail) -> Result<(), DatabaseError> {
+        self.inject(self.inner.create_queued_email(email)).await
+    }
+
+    async fn get_due_queued_emails(&self, limit: u32) -> Result<Vec<QueuedEmail>, DatabaseError> {
+        self.inject(self.inner.get_due_queued_emails(limit)).await
+    }
+
+    async fn update_queued_email(
+        &self,
+        id: &Uuid,
+        update: &QueuedEmailUpdate,
+    ) -> Result<QueuedEmail, DatabaseError> {
+        self.inject(self.inner.update_queued_email(id, update))
+            .await
+    }
+
+    async fn delete_queued_email_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_queued_email_by_id(id)).await
+    }
+
+    async fn create_email_suppression(
+        &self,
+        address: &str,
+        reason: &str,
+    ) -> Result<EmailSuppression, DatabaseError> {
+        self.inject(self.inner.create_email_suppression(address, reason))
+            .await
+    }
+
+    async fn get_email_suppression(
+        &self,
+        address: &str,
+    ) -> Result<EmailSuppression, DatabaseError> {
+        self.inject(self.inner.get_email_suppression(address)).await
+    }
+
+    async fn list_email_suppressions(&self) -> Result<Vec<EmailSuppression>, DatabaseError> {
+        self.inject(self.inner.list_email_suppressions()).await
+    }
+
+    async fn delete_email_suppression(&self, address: &str) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_email_suppression(address))
+            .await
+    }
+
+    async fn create_ip_ban(&self, ban: &IpBan) -> Result<IpBan, DatabaseError> {
+        self.inject(self.inner.create_ip_ban(ban)).await
+    }
+
+    async fn list_ip_bans(&self) -> Result<Vec<IpBan>, DatabaseError> {
+        self.inject(self.inner.list_ip_bans()).await
+    }
+
+    async fn delete_ip_ban_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_ip_ban_by_id(id)).await
+    }
+
+    async fn try_acquire_lease(
+        &self,
+        name: &str,
+        holder: &Uuid,
+        ttl: Duration,
+    ) -> Result<bool, DatabaseError> {
+        self.inject(self.inner.try_acquire_lease(name, holder, ttl))
+            .await
+    }
+
+    async fn get_announcement(&self) -> Result<Announcement, DatabaseError> {
+        self.inject(self.inner.get_announcement()).await
+    }
+
+    async fn set_announcement(&self, announcement: &Announcement) -> Result<(), DatabaseError> {
+        self.inject(self.inner.set_announcement(announcement)).await
+    }
+
+    async fn delete_announcement(&self) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_announcement()).await
+    }
+
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<Webhook, DatabaseError> {
+        self.inject(self.inner.create_webhook(webhook)).await
+    }
+
+    async fn list_webhooks(&self) -> Result<Vec<Webhook>, DatabaseError> {
+        self.inject(self.inner.list_webhooks()).await
+    }
+
+    async fn get_webhook_by_id(&self, id: &Uuid) -> Result<Webhook, DatabaseError> {
+        self.inject(self.inner.get_webhook_by_id(id)).await
+    }
+
+    async fn update_webhook(
+        &self,
+        id: &Uuid,
+        update: &WebhookUpdate,
+    ) -> Result<Webhook, DatabaseError> {
+        self.inject(self.inner.update_webhook(id, update)).await
+    }
+
+    async fn delete_webhook_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_webhook_by_id(id)).await
+    }
+
+    async fn create_oauth_client(
+        &self,
+        client: &OAuthClient,
+    ) -> Result<OAuthClient, DatabaseError> {
+        self.inject(self.inner.create_oauth_client(client)).await
+    }
+
+    async fn list_oauth_clients(&self) -> Result<Vec<OAuthClient>, DatabaseError> {
+        self.inject(self.inner.list_oauth_clients()).await
+    }
+
+    async fn get_oauth_client_by_id(&self, id: &Uuid) -> Result<OAuthClient, DatabaseError> {
+        self.inject(self.inner.get_oauth_client_by_id(id)).await
+    }
+
+    async fn update_oauth_client(
+        &self,
+        id: &Uuid,
+        update: &OAuthClientUpdate,
+    ) -> Result<OAuthClient, DatabaseError> {
+        self.inject(self.inner.update_oauth_client(id, update))
+            .await
+    }
+
+    async fn delete_oauth_client_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_oauth_client_by_id(id)).await
+    }
+
+    async fn create_authorization_code(
+        &self,
+        code: &AuthorizationCode,
+    ) -> Result<(), DatabaseError> {
+        self.inject(self.inner.create_authorization_code(code))
+            .await
+    }
+
+    async fn consume_authorization_code(
+        &self,
+        code_hash: &EncodableHash,
+    ) -> Result<AuthorizationCode, DatabaseError> {
+        self.inject(self.inner.consume_authorization_code(code_hash))
+            .await
+    }
+
+    async fn create_saml_service_provider(
+        &self,
+        provider: &SamlServiceProvider,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        self.inject(self.inner.create_saml_service_provider(provider))
+            .await
+    }
+
+    async fn list_saml_service_providers(&self) -> Result<Vec<SamlServiceProvider>, DatabaseError> {
+        self.inject(self.inner.list_saml_service_providers()).await
+    }
+
+    async fn get_saml_service_provider_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        self.inject(self.inner.get_saml_service_provider_by_id(id))
+            .await
+    }
+
+    async fn get_saml_service_provider_by_entity_id(
+        &self,
+        entity_id: &str,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        self.inject(self.inner.get_saml_service_provider_by_entity_id(entity_id))
+            .await
+    }
+
+    async fn update_saml_service_provider(
+        &self,
+        id: &Uuid,
+        update: &SamlServiceProviderUpdate,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        self.inject(self.inner.update_saml_service_provider(id, update))
+            .await
+    }
+
+    async fn delete_saml_service_provider_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_saml_service_provider_by_id(id))
+            .await
+    }
+
+    async fn create_federated_provider(
+        &self,
+        provider: &FederatedProvider,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        self.inject(self.inner.create_federated_provider(provider))
+            .await
+    }
+
+    async fn list_federated_providers(&self) -> Result<Vec<FederatedProvider>, DatabaseError> {
+        self.inject(self.inner.list_federated_providers()).await
+    }
+
+    async fn get_federated_provider_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        self.inject(self.inner.get_federated_provider_by_id(id))
+            .await
+    }
+
+    async fn get_federated_provider_by_slug(
+        &self,
+        slug: &str,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        self.inject(self.inner.get_federated_provider_by_slug(slug))
+            .await
+    }
+
+    async fn update_federated_provider(
+        &self,
+        id: &Uuid,
+        update: &FederatedProviderUpdate,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        self.inject(self.inner.update_federated_provider(id, update))
+            .await
+    }
+
+    async fn delete_federated_provider_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_federated_provider_by_id(id))
+            .await
+    }
+
+    async fn create_federated_login_state(
+        &self,
+        state: &FederatedLoginState,
+    ) -> Result<(), DatabaseError> {
+        self.inject(self.inner.create_federated_login_state(state))
+            .await
+    }
+
+    async fn consume_federated_login_state(
+        &self,
+        state_hash: &EncodableHash,
+    ) -> Result<FederatedLoginState, DatabaseError> {
+        self.inject(self.inner.consume_federated_login_state(state_hash))
+            .await
+    }
+
+    async fn get_federated_identity(
+        &self,
+        provider_id: &Uuid,
+        subject: &str,
+    ) -> Result<FederatedIdentity, DatabaseError> {
+        self.inject(self.inner.get_federated_identity(provider_id, subject))
+            .await
+    }
+
+    async fn create_federated_identity(
+        &self,
+        identity: &FederatedIdentity,
+    ) -> Result<(), DatabaseError> {
+        self.inject(self.inner.create_federated_identity(identity))
+            .await
+    }
+
+    async fn list_federated_identities_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<FederatedIdentity>, DatabaseError> {
+        self.inject(self.inner.list_federated_identities_by_user_id(user_id))
+            .await
+    }
+
+    async fn delete_federated_identities(
+        &self,
+        user_id: &Uuid,
+        provider_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        self.inject(self.inner.delete_federated_identities(user_id, provider_id))
+            .await
+    }
+
+    async fn create_signing_key(&self, key: &StoredSigningKey) -> Result<(), DatabaseError> {
+        self.inject(self.inner.create_signing_key(key)).await
+    }
+
+    async fn list_signing_keys(&self) -> Result<Vec<StoredSigningKey>, DatabaseError> {
+        self.inject(self.inner.list_signing_keys()).await
+    }
+
+    async fn retire_signing_keys_older_than(&self, kid: &str) -> Result<u64, DatabaseError> {
+        self.inject(self.inner.retire_signing_keys_older_than(kid))
+            .await
+    }
+
+    async fn delete_retired_signing_keys(&self, age: Duration) -> Result<u64, DatabaseError> {
+        self.inject(self.inner.delete_retired_signing_keys(age))
+            .await
+    }
+
+    async fn get_or_create_session_cookie_key(
+        &self,
+        key: &StoredSessionCookieKey,
+    ) -> Result<StoredSessionCookieKey, DatabaseError> {
+        self.inject(self.inner.get_or_create_session_cookie_key(key))
+            .await
+    }
+
+    async fn purge_retained_data(
+        &self,
+        policy: &RetentionPolicy,
+    ) -> Result<RetentionReport, DatabaseError> {
+        self.inject(self.inner.purge_retained_data(policy)).await
+    }
+
+    async fn run_maintenance(&self, task: MaintenanceTask) -> Result<(), DatabaseError> {
+        self.inject(self.inner.run_maintenance(task)).await
+    }
+
+    async fn export_backup(&self) -> Result<Backup, DatabaseError> {
+        self.inject(self.inner.export_backup()).await
+    }
+
+    async fn import_backup(&self, backup: &Backup) -> Result<(), DatabaseError> {
+        self.inject(self.inner.import_backup(backup)).await
+    }
+
+    async fn begin(&self) -> Result<BoxedTransaction, DatabaseError> {
+        self.inject(self.inner.begin()).await
+    }
+}
@@ -5,5 +5,10 @@
 //!
 //! [`DatabaseClient`]: crate::db::interface::DatabaseClient
 
+pub mod cached;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+#[cfg(feature = "redis")]
+pub mod redis;
 #[cfg(feature = "sqlite3")]
 pub mod sqlite;
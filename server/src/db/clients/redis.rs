@@ -0,0 +1,1641 @@
+//! # Redis-backed session store
+//!
+//! [`RedisSessionClient`] wraps another [`DatabaseClient`] and keeps short-lived authentication
+//! state in Redis instead: [`Session`]s, session revocation tokens, [`PasskeyRegistrationState`]s,
+//! and [`PasskeyAuthenticationState`]s. Everything else, i.e. all durable data, is passed through
+//! to the inner client.
+//!
+//! Entries expire on their own (sessions when they expire, ceremony states after 5 minutes), so
+//! no periodic cleanup is needed, and server instances which share the Redis server share
+//! sessions without hitting the SQL database on every request.
+//!
+//! ## Key layout
+//!
+//! All keys start with a configurable prefix. `<hash>` is the hex-encoded hash of a session ID
+//! or revocation token.
+//!
+//! | Key                            | Type   | Contents                                        |
+//! | ------------------------------ | ------ | ----------------------------------------------- |
+//! | `session:<hash>`               | hash   | Fields of the [`Session`]                       |
+//! | `user-sessions:<user ID>`      | set    | Hashes of the user's sessions                   |
+//! | `session-children:<hash>`      | set    | Hashes of sessions superseding the session      |
+//! | `revocation-token:<hash>`      | string | Hash of the session the token revokes           |
+//! | `registration:<ID>`            | string | (Possibly encrypted) JSON registration state    |
+//! | `authentication:<ID>`          | string | (Possibly encrypted) JSON authentication state  |
+//!
+//! Requires Redis 7.0 or later, and doesn't support Redis Cluster, since the scripts used for
+//! atomic updates touch keys they don't declare.
+//!
+//! Only available with the `redis` feature.
+
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fmt::Display,
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::DateTime;
+use redis::{AsyncCommands, Script, aio::ConnectionManager};
+use serde::{Serialize, de::DeserializeOwned};
+use uuid::Uuid;
+
+use crate::{
+    crypto::{decrypt_stored, encrypt_stored},
+    db::interface::{BoxedTransaction, DatabaseClient, DatabaseError, DatabaseTransaction},
+    models::{
+        Announcement, ApiToken, AuthorizationCode, Backup, EmailSuppression, EncodableHash,
+        FederatedIdentity, FederatedLoginState, FederatedProvider, FederatedProviderUpdate, Group,
+        GroupUpdate, IpBan, MaintenanceTask, NewPasskeyCredential, OAuthClient, OAuthClientUpdate,
+        Organization, OrganizationMember, OrganizationRole, OrganizationUpdate,
+        PasskeyAuthenticationState, PasskeyCredential, PasskeyCredentialUpdate,
+        PasskeyRegistrationState, Policy, PolicyUpdate, PolicyVersion, QueuedEmail,
+        QueuedEmailUpdate, RetentionPolicy, RetentionReport, Role, RoleUpdate, SamlServiceProvider,
+        SamlServiceProviderUpdate, Session, SessionState, SessionUpdate, StoredSessionCookieKey,
+        StoredSigningKey, Tag, TagUpdate, User, UserCreate, UserCursor, UserList, UserListQuery,
+        UserMerge, UserNote, UserStatus, UserUpdate, Webhook, WebhookUpdate,
+    },
+};
+
+const SESSION: &str = "session";
+const USER_SESSIONS: &str = "user-sessions";
+const SESSION_CHILDREN: &str = "session-children";
+const REVOCATION_TOKEN: &str = "revocation-token";
+const REGISTRATION: &str = "registration";
+const AUTHENTICATION: &str = "authentication";
+
+/// Time in seconds after which unfinished passkey ceremonies expire
+const CEREMONY_TTL_SECS: u64 = 5 * 60;
+
+/// Maximum number of keys passed to a single script invocation
+const SCRIPT_BATCH_SIZE: usize = 1000;
+
+/// Applies a session update and returns the updated session's fields, or nothing if the session
+/// doesn't exist.
+///
+/// - `KEYS[1]`: the session
+/// - `ARGV[1]`: prefix of user session set keys
+/// - `ARGV[2]`: new expiry timestamp, or an empty string to keep the current one
+/// - `ARGV[3..]`: field/value pairs to set
+static UPDATE_SESSION: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+if redis.call('EXISTS', KEYS[1]) == 0 then return {} end
+local user_sessions = ARGV[1] .. redis.call('HGET', KEYS[1], 'user_id')
+redis.call('HSET', KEYS[1], unpack(ARGV, 3))
+local fields = redis.call('HGETALL', KEYS[1])
+if ARGV[2] ~= '' then
+  redis.call('EXPIREAT', KEYS[1], ARGV[2])
+  redis.call('EXPIREAT', user_sessions, ARGV[2], 'NX')
+  redis.call('EXPIREAT', user_sessions, ARGV[2], 'GT')
+end
+return fields
+",
+    )
+});
+
+/// Changes the state of the given sessions which are in one state to another, and returns the
+/// number of sessions changed.
+///
+/// - `KEYS`: the sessions
+/// - `ARGV[1]`: state to change
+/// - `ARGV[2]`: new state
+static CHANGE_STATE: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+local changed = 0
+for _, key in ipairs(KEYS) do
+  if redis.call('HGET', key, 'state') == ARGV[1] then
+    redis.call('HSET', key, 'state', ARGV[2])
+    changed = changed + 1
+  end
+end
+return changed
+",
+    )
+});
+
+/// Moves the sessions of one user to another, and returns the number of sessions moved.
+///
+/// - `KEYS[1]`: the source user's session set
+/// - `KEYS[2]`: the target user's session set
+/// - `ARGV[1]`: prefix of session keys
+/// - `ARGV[2]`: target user ID
+/// - `ARGV[3]`: `1` to only count the sessions which would be moved
+static MOVE_SESSIONS: LazyLock<Script> = LazyLock::new(|| {
+    Script::new(
+        r"
+local moved = 0
+for _, hash in ipairs(redis.call('SMEMBERS', KEYS[1])) do
+  local key = ARGV[1] .. hash
+  local expires_at = redis.call('HGET', key, 'expires_at')
+  if expires_at then
+    moved = moved + 1
+    if ARGV[3] ~= '1' then
+      redis.call('HSET', key, 'user_id', ARGV[2])
+      redis.call('SADD', KEYS[2], hash)
+      redis.call('EXPIREAT', KEYS[2], expires_at, 'NX')
+      redis.call('EXPIREAT', KEYS[2], expires_at, 'GT')
+    end
+  end
+end
+if ARGV[3] ~= '1' then redis.call('DEL', KEYS[1]) end
+return moved
+",
+    )
+});
+
+/// # Redis-backed session store
+///
+/// See the [module-level documentation][self] for details.
+#[derive(Clone)]
+pub struct RedisSessionClient {
+    inner: Arc<dyn DatabaseClient>,
+    conn: ConnectionManager,
+    prefix: Arc<str>,
+}
+
+impl RedisSessionClient {
+    /// Connects to the Redis server at the given URL and wraps the given client, storing keys
+    /// with the given prefix.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URL is invalid or the connection fails.
+    pub async fn connect(
+        inner: Arc<dyn DatabaseClient>,
+        url: &str,
+        prefix: &str,
+    ) -> Result<Self, redis::RedisError> {
+        let conn = ConnectionManager::new(redis::Client::open(url)?).await?;
+        Ok(Self {
+            inner,
+            conn,
+            prefix: prefix.into(),
+        })
+    }
+
+    /// Returns the key for the given kind of entry with the given ID.
+    fn key(&self, kind: &str, id: impl Display) -> String {
+        key(&self.prefix, kind, id)
+    }
+}
+
+fn key(prefix: &str, kind: &str, id: impl Display) -> String {
+    format!("{prefix}{kind}:{id}")
+}
+
+fn malformed(what: &str) -> DatabaseError {
+    DatabaseError::Other(format!("malformed {what} in Redis").into())
+}
+
+/// Serializes and encrypts a value for storage, like [`ViaJson`][crate::models::ViaJson] does
+/// for SQL databases.
+fn to_stored<T: Serialize>(value: &T) -> Result<String, DatabaseError> {
+    let json = serde_json::to_string(value).map_err(|err| DatabaseError::Other(err.into()))?;
+    encrypt_stored(json).map_err(|err| DatabaseError::Other(err.into()))
+}
+
+/// Decrypts and deserializes a value stored with [`to_stored()`].
+fn from_stored<T: DeserializeOwned>(stored: &str) -> Result<T, DatabaseError> {
+    let json = decrypt_stored(stored).map_err(|err| DatabaseError::Other(err.into()))?;
+    serde_json::from_str(&json).map_err(|err| DatabaseError::Other(err.into()))
+}
+
+/// Stores a ceremony state which expires after [`CEREMONY_TTL_SECS`].
+async fn put_ceremony<T: Serialize>(
+    mut conn: ConnectionManager,
+    key: String,
+    state: &T,
+) -> Result<(), DatabaseError> {
+    let created: Option<String> = redis::cmd("SET")
+        .arg(&key)
+        .arg(to_stored(state)?)
+        .arg("EX")
+        .arg(CEREMONY_TTL_SECS)
+        .arg("NX")
+        .query_async(&mut conn)
+        .await?;
+    match created {
+        Some(_) => Ok(()),
+        None => Err(DatabaseError::UniquenessViolation {
+            field: Some("id".into()),
+        }),
+    }
+}
+
+async fn get_ceremony<T: DeserializeOwned>(
+    mut conn: ConnectionManager,
+    key: String,
+) -> Result<T, DatabaseError> {
+    let stored: Option<String> = conn.get(&key).await?;
+    from_stored(&stored.ok_or(DatabaseError::NotFound)?)
+}
+
+async fn delete_ceremony(mut conn: ConnectionManager, key: String) -> Result<(), DatabaseError> {
+    let deleted: u64 = conn.del(&key).await?;
+    if deleted == 0 {
+        return Err(DatabaseError::NotFound);
+    }
+    Ok(())
+}
+
+fn state_to_redis(state: SessionState) -> String {
+    (state as u8).to_string()
+}
+
+fn state_from_redis(value: &str) -> Option<SessionState> {
+    let value: u8 = value.parse().ok()?;
+    [
+        SessionState::Active,
+        SessionState::Revoked,
+        SessionState::LoggedOut,
+        SessionState::Superseded,
+    ]
+    .into_iter()
+    .find(|state| *state as u8 == value)
+}
+
+/// Returns the fields under which a [`Session`] is stored, except its ID hash, which is part of
+/// the key.
+fn session_to_fields(session: &Session) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("user_id", session.user_id.to_string()),
+        ("state", state_to_redis(session.state)),
+        ("created_at", session.created_at.timestamp().to_string()),
+        ("expires_at", session.expires_at.timestamp().to_string()),
+        ("is_admin", u8::from(session.is_admin).to_string()),
+        ("last_seen_at", session.last_seen_at.timestamp().to_string()),
+        (
+            "authenticated_at",
+            session.authenticated_at.timestamp().to_string(),
+        ),
+    ];
+    if let Some(parent) = &session.parent_id_hash {
+        fields.push(("parent", parent.to_hex().to_string()));
+    }
+    if let Some(ip) = &session.ip {
+        fields.push(("ip", ip.clone()));
+    }
+    if let Some(user_agent) = &session.user_agent {
+        fields.push(("user_agent", user_agent.clone()));
+    }
+    if let Some(impersonator_id) = &session.impersonator_id {
+        fields.push(("impersonator_id", impersonator_id.to_string()));
+    }
+    if let Some(organization_id) = &session.organization_id {
+        fields.push(("organization_id", organization_id.to_string()));
+    }
+    fields
+}
+
+/// Parses a [`Session`] from the fields returned by [`session_to_fields()`].
+fn session_from_fields(
+    id_hash: EncodableHash,
+    fields: &HashMap<String, String>,
+) -> Result<Session, DatabaseError> {
+    let field = |name: &str| {
+        fields
+            .get(name)
+            .map(String::as_str)
+            .ok_or_else(|| malformed("session"))
+    };
+    let timestamp = |name: &str| {
+        field(name)?
+            .parse()
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs, 0))
+            .ok_or_else(|| malformed("session"))
+    };
+    let created_at = timestamp("created_at")?;
+    Ok(Session {
+        id_hash,
+        user_id: field("user_id")?
+            .parse()
+            .map_err(|_| malformed("session"))?,
+        state: state_from_redis(field("state")?).ok_or_else(|| malformed("session"))?,
+        created_at,
+        expires_at: timestamp("expires_at")?,
+        is_admin: field("is_admin")? == "1",
+        parent_id_hash: fields
+            .get("parent")
+            .map(|hex| blake3::Hash::from_hex(hex).map(EncodableHash))
+            .transpose()
+            .map_err(|_| malformed("session"))?,
+        ip: fields.get("ip").cloned(),
+        user_agent: fields.get("user_agent").cloned(),
+        // Sessions stored before the field existed haven't been seen since they were created
+        last_seen_at: if fields.contains_key("last_seen_at") {
+            timestamp("last_seen_at")?
+        } else {
+            created_at
+        },
+        impersonator_id: fields
+            .get("impersonator_id")
+            .map(|id| id.parse())
+            .transpose()
+            .map_err(|_| malformed("session"))?,
+        // Sessions stored before the field existed were authenticated when they were created
+        authenticated_at: if fields.contains_key("authenticated_at") {
+            timestamp("authenticated_at")?
+        } else {
+            created_at
+        },
+        // An empty value means the session left its organization
+        organization_id: fields
+            .get("organization_id")
+            .filter(|id| !id.is_empty())
+            .map(|id| id.parse())
+            .transpose()
+            .map_err(|_| malformed("session"))?,
+    })
+}
+
+/// Marks the given sessions which are active as [revoked][SessionState::Revoked], and returns
+/// the number of sessions revoked.
+async fn revoke_sessions(
+    conn: &mut ConnectionManager,
+    keys: &[String],
+) -> Result<u64, DatabaseError> {
+    let mut revoked = 0;
+    for batch in keys.chunks(SCRIPT_BATCH_SIZE) {
+        let mut invocation = CHANGE_STATE.prepare_invoke();
+        for key in batch {
+            invocation.key(key);
+        }
+        revoked += invocation
+            .arg(state_to_redis(SessionState::Active))
+            .arg(state_to_redis(SessionState::Revoked))
+            .invoke_async::<u64>(conn)
+            .await?;
+    }
+    Ok(revoked)
+}
+
+/// Returns the keys of the sessions in the given user's session set.
+async fn user_session_keys(
+    conn: &mut ConnectionManager,
+    prefix: &str,
+    user_id: &Uuid,
+) -> Result<Vec<String>, DatabaseError> {
+    let hashes: Vec<String> = conn.smembers(key(prefix, USER_SESSIONS, user_id)).await?;
+    Ok(hashes
+        .iter()
+        .map(|hash| key(prefix, SESSION, hash))
+        .collect())
+}
+
+/// Adds commands to a pipeline which make the given key expire at the given time, unless it
+/// already expires later.
+fn extend_expiry(pipe: &mut redis::Pipeline, key: &str, expires_at: i64) {
+    pipe.cmd("EXPIREAT")
+        .arg(key)
+        .arg(expires_at)
+        .arg("NX")
+        .ignore()
+        .cmd("EXPIREAT")
+        .arg(key)
+        .arg(expires_at)
+        .arg("GT")
+        .ignore();
+}
+
+#[async_trait]
+impl DatabaseClient for RedisSessionClient {
+    async fn create_user(&self, id: &Uuid, user: &UserCreate) -> Result<User, DatabaseError> {
+        self.inner.create_user(id, user).await
+    }
+
+    async fn get_user_by_id(&self, id: &Uuid) -> Result<User, DatabaseError> {
+        self.inner.get_user_by_id(id).await
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<User, DatabaseError> {
+        self.inner.get_user_by_email(email).await
+    }
+
+    async fn get_user_with_relations(&self, id: &Uuid) -> Result<User, DatabaseError> {
+        self.inner.get_user_with_relations(id).await
+    }
+
+    async fn update_user(&self, id: &Uuid, update: &UserUpdate) -> Result<User, DatabaseError> {
+        self.inner.update_user(id, update).await
+    }
+
+    async fn create_email_change(
+        &self,
+        user_id: &Uuid,
+        email: &str,
+        token_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError> {
+        self.inner
+            .create_email_change(user_id, email, token_hash)
+            .await
+    }
+
+    async fn confirm_email_change(
+        &self,
+        token_hash: &EncodableHash,
+        max_age: Duration,
+    ) -> Result<User, DatabaseError> {
+        self.inner.confirm_email_change(token_hash, max_age).await
+    }
+
+    async fn record_user_login(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.record_user_login(id).await
+    }
+
+    async fn record_login_failure(
+        &self,
+        user_id: &Uuid,
+        passkey_id: Option<&Uuid>,
+        window: Duration,
+    ) -> Result<u64, DatabaseError> {
+        self.inner
+            .record_login_failure(user_id, passkey_id, window)
+            .await
+    }
+
+    async fn clear_login_failures(&self, user_id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.clear_login_failures(user_id).await
+    }
+
+    async fn set_user_locked_until(
+        &self,
+        id: &Uuid,
+        until: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<(), DatabaseError> {
+        self.inner.set_user_locked_until(id, until).await
+    }
+
+    async fn set_user_status(&self, id: &Uuid, status: UserStatus) -> Result<User, DatabaseError> {
+        self.inner.set_user_status(id, status).await
+    }
+
+    async fn merge_users(
+        &self,
+        source_id: &Uuid,
+        target_id: &Uuid,
+        dry_run: bool,
+    ) -> Result<UserMerge, DatabaseError> {
+        let mut conn = self.conn.clone();
+        let prefix = &self.prefix;
+        let mut merge = self
+            .inner
+            .merge_users(source_id, target_id, dry_run)
+            .await?;
+        // The inner client has already deleted the source user, so there's no way to make
+        // this atomic with the rest of the merge
+        merge.sessions += MOVE_SESSIONS
+            .key(key(prefix, USER_SESSIONS, source_id))
+            .key(key(prefix, USER_SESSIONS, target_id))
+            .arg(format!("{prefix}{SESSION}:"))
+            .arg(target_id.to_string())
+            .arg(u8::from(dry_run))
+            .invoke_async::<u64>(&mut conn)
+            .await?;
+        Ok(merge)
+    }
+
+    async fn anonymize_user(&self, id: &Uuid) -> Result<User, DatabaseError> {
+        let mut conn = self.conn.clone();
+        let prefix = &self.prefix;
+        let user = self.inner.anonymize_user(id).await?;
+        let keys = user_session_keys(&mut conn, prefix, id).await?;
+        revoke_sessions(&mut conn, &keys).await?;
+        // Sessions are kept until they expire, but not where they were used from
+        if !keys.is_empty() {
+            let mut pipe = redis::pipe();
+            for key in &keys {
+                pipe.hdel(key, &["ip", "user_agent"]).ignore();
+            }
+            pipe.query_async::<()>(&mut conn).await?;
+        }
+        Ok(user)
+    }
+
+    async fn list_users(
+        &self,
+        query: &UserListQuery,
+        after: Option<&UserCursor>,
+        limit: u32,
+    ) -> Result<UserList, DatabaseError> {
+        self.inner.list_users(query, after, limit).await
+    }
+
+    async fn search_users(&self, query: &str, limit: u32) -> Result<Vec<User>, DatabaseError> {
+        self.inner.search_users(query, limit).await
+    }
+
+    async fn delete_user_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let mut conn = self.conn.clone();
+        let prefix = &self.prefix;
+        self.inner.delete_user_by_id(id).await?;
+        let mut keys = user_session_keys(&mut conn, prefix, id).await?;
+        keys.push(key(prefix, USER_SESSIONS, id));
+        conn.del::<_, ()>(keys).await?;
+        Ok(())
+    }
+
+    async fn add_tag_to_user(&self, user_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        self.inner.add_tag_to_user(user_id, tag).await
+    }
+
+    async fn remove_tag_from_user(&self, user_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        self.inner.remove_tag_from_user(user_id, tag).await
+    }
+
+    async fn get_users_by_tag_id(&self, tag_id: &Uuid) -> Result<Vec<User>, DatabaseError> {
+        self.inner.get_users_by_tag_id(tag_id).await
+    }
+
+    async fn create_user_note(&self, note: &UserNote) -> Result<UserNote, DatabaseError> {
+        self.inner.create_user_note(note).await
+    }
+
+    async fn get_user_notes(&self, user_id: &Uuid) -> Result<Vec<UserNote>, DatabaseError> {
+        self.inner.get_user_notes(user_id).await
+    }
+
+    async fn create_tag(
+        &self,
+        id: &Uuid,
+        organization_id: Option<&Uuid>,
+        tag: &TagUpdate,
+    ) -> Result<Tag, DatabaseError> {
+        self.inner.create_tag(id, organization_id, tag).await
+    }
+
+    async fn get_tag_by_id(&self, id: &Uuid) -> Result<Tag, DatabaseError> {
+        self.inner.get_tag_by_id(id).await
+    }
+
+    async fn get_tag_by_name(&self, name: &str) -> Result<Tag, DatabaseError> {
+        self.inner.get_tag_by_name(name).await
+    }
+
+    async fn list_tags(&self) -> Result<Vec<Tag>, DatabaseError> {
+        self.inner.list_tags().await
+    }
+
+    async fn update_tag(&self, id: &Uuid, update: &TagUpdate) -> Result<Tag, DatabaseError> {
+        self.inner.update_tag(id, update).await
+    }
+
+    async fn delete_tag_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_tag_by_id(id).await
+    }
+
+    async fn get_tags_by_user_id(&self, user_id: &Uuid) -> Result<Vec<Tag>, DatabaseError> {
+        self.inner.get_tags_by_user_id(user_id).await
+    }
+
+    async fn get_tags_by_user_ids(
+        &self,
+        user_ids: &[Uuid],
+    ) -> Result<HashMap<Uuid, Vec<Tag>>, DatabaseError> {
+        self.inner.get_tags_by_user_ids(user_ids).await
+    }
+
+    async fn get_tags_by_organization_id(
+        &self,
+        organization_id: &Uuid,
+    ) -> Result<Vec<Tag>, DatabaseError> {
+        self.inner
+            .get_tags_by_organization_id(organization_id)
+            .await
+    }
+
+    async fn create_organization(
+        &self,
+        id: &Uuid,
+        organization: &OrganizationUpdate,
+    ) -> Result<Organization, DatabaseError> {
+        self.inner.create_organization(id, organization).await
+    }
+
+    async fn get_organization_by_id(&self, id: &Uuid) -> Result<Organization, DatabaseError> {
+        self.inner.get_organization_by_id(id).await
+    }
+
+    async fn list_organizations(&self) -> Result<Vec<Organization>, DatabaseError> {
+        self.inner.list_organizations().await
+    }
+
+    async fn update_organization(
+        &self,
+        id: &Uuid,
+        update: &OrganizationUpdate,
+    ) -> Result<Organization, DatabaseError> {
+        self.inner.update_organization(id, update).await
+    }
+
+    async fn delete_organization_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_organization_by_id(id).await
+    }
+
+    async fn get_organizations_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<Organization>, DatabaseError> {
+        self.inner.get_organizations_by_user_id(user_id).await
+    }
+
+    async fn get_organization_members(
+        &self,
+        organization_id: &Uuid,
+    ) -> Result<Vec<OrganizationMember>, DatabaseError> {
+        self.inner.get_organization_members(organization_id).await
+    }
+
+    async fn get_organization_member(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<OrganizationMember, DatabaseError> {
+        self.inner
+            .get_organization_member(organization_id, user_id)
+            .await
+    }
+
+    async fn set_organization_member(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+        role: OrganizationRole,
+    ) -> Result<OrganizationMember, DatabaseError> {
+        self.inner
+            .set_organization_member(organization_id, user_id, role)
+            .await
+    }
+
+    async fn remove_organization_member(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        self.inner
+            .remove_organization_member(organization_id, user_id)
+            .await
+    }
+
+    async fn create_group(&self, id: &Uuid, group: &GroupUpdate) -> Result<Group, DatabaseError> {
+        self.inner.create_group(id, group).await
+    }
+
+    async fn get_group_by_id(&self, id: &Uuid) -> Result<Group, DatabaseError> {
+        self.inner.get_group_by_id(id).await
+    }
+
+    async fn list_groups(&self) -> Result<Vec<Group>, DatabaseError> {
+        self.inner.list_groups().await
+    }
+
+    async fn update_group(&self, id: &Uuid, update: &GroupUpdate) -> Result<Group, DatabaseError> {
+        self.inner.update_group(id, update).await
+    }
+
+    async fn delete_group_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_group_by_id(id).await
+    }
+
+    async fn get_users_by_group_id(
+        &self,
+        group_id: &Uuid,
+        include_subgroups: bool,
+    ) -> Result<Vec<User>, DatabaseError> {
+        self.inner
+            .get_users_by_group_id(group_id, include_subgroups)
+            .await
+    }
+
+    async fn get_groups_by_user_id(&self, user_id: &Uuid) -> Result<Vec<Group>, DatabaseError> {
+        self.inner.get_groups_by_user_id(user_id).await
+    }
+
+    async fn add_user_to_group(
+        &self,
+        group_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        self.inner.add_user_to_group(group_id, user_id).await
+    }
+
+    async fn remove_user_from_group(
+        &self,
+        group_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        self.inner.remove_user_from_group(group_id, user_id).await
+    }
+
+    async fn get_subgroups(&self, group_id: &Uuid) -> Result<Vec<Group>, DatabaseError> {
+        self.inner.get_subgroups(group_id).await
+    }
+
+    async fn get_supergroup_ids(&self, group_id: &Uuid) -> Result<Vec<Uuid>, DatabaseError> {
+        self.inner.get_supergroup_ids(group_id).await
+    }
+
+    async fn add_subgroup(&self, parent_id: &Uuid, child_id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.add_subgroup(parent_id, child_id).await
+    }
+
+    async fn remove_subgroup(
+        &self,
+        parent_id: &Uuid,
+        child_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        self.inner.remove_subgroup(parent_id, child_id).await
+    }
+
+    async fn add_tag_to_group(&self, group_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        self.inner.add_tag_to_group(group_id, tag).await
+    }
+
+    async fn remove_tag_from_group(&self, group_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        self.inner.remove_tag_from_group(group_id, tag).await
+    }
+
+    async fn get_tags_by_group_id(&self, group_id: &Uuid) -> Result<Vec<Tag>, DatabaseError> {
+        self.inner.get_tags_by_group_id(group_id).await
+    }
+
+    async fn get_effective_tags_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<Tag>, DatabaseError> {
+        self.inner.get_effective_tags_by_user_id(user_id).await
+    }
+
+    async fn create_role(&self, id: &Uuid, role: &RoleUpdate) -> Result<Role, DatabaseError> {
+        self.inner.create_role(id, role).await
+    }
+
+    async fn get_role_by_id(&self, id: &Uuid) -> Result<Role, DatabaseError> {
+        self.inner.get_role_by_id(id).await
+    }
+
+    async fn list_roles(&self) -> Result<Vec<Role>, DatabaseError> {
+        self.inner.list_roles().await
+    }
+
+    async fn update_role(&self, id: &Uuid, update: &RoleUpdate) -> Result<Role, DatabaseError> {
+        self.inner.update_role(id, update).await
+    }
+
+    async fn delete_role_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_role_by_id(id).await
+    }
+
+    async fn get_roles_by_tag_id(&self, tag_id: &Uuid) -> Result<Vec<Role>, DatabaseError> {
+        self.inner.get_roles_by_tag_id(tag_id).await
+    }
+
+    async fn add_role_to_tag(&self, tag_id: &Uuid, role_id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.add_role_to_tag(tag_id, role_id).await
+    }
+
+    async fn remove_role_from_tag(
+        &self,
+        tag_id: &Uuid,
+        role_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        self.inner.remove_role_from_tag(tag_id, role_id).await
+    }
+
+    async fn create_policy(
+        &self,
+        id: &Uuid,
+        policy: &PolicyUpdate,
+    ) -> Result<Policy, DatabaseError> {
+        self.inner.create_policy(id, policy).await
+    }
+
+    async fn get_policy_by_id(&self, id: &Uuid) -> Result<Policy, DatabaseError> {
+        self.inner.get_policy_by_id(id).await
+    }
+
+    async fn list_policies(&self) -> Result<Vec<Policy>, DatabaseError> {
+        self.inner.list_policies().await
+    }
+
+    async fn update_policy(
+        &self,
+        id: &Uuid,
+        update: &PolicyUpdate,
+    ) -> Result<Policy, DatabaseError> {
+        self.inner.update_policy(id, update).await
+    }
+
+    async fn delete_policy_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_policy_by_id(id).await
+    }
+
+    async fn list_policy_versions(
+        &self,
+        policy_id: &Uuid,
+    ) -> Result<Vec<PolicyVersion>, DatabaseError> {
+        self.inner.list_policy_versions(policy_id).await
+    }
+
+    async fn get_policy_version(
+        &self,
+        policy_id: &Uuid,
+        version: i64,
+    ) -> Result<PolicyVersion, DatabaseError> {
+        self.inner.get_policy_version(policy_id, version).await
+    }
+
+    async fn create_passkey(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        passkey: &NewPasskeyCredential,
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        self.inner.create_passkey(id, user_id, passkey).await
+    }
+
+    async fn get_passkey_by_id(&self, id: &Uuid) -> Result<PasskeyCredential, DatabaseError> {
+        self.inner.get_passkey_by_id(id).await
+    }
+
+    async fn get_passkey_by_credential_id(
+        &self,
+        credential_id: &[u8],
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        self.inner.get_passkey_by_credential_id(credential_id).await
+    }
+
+    async fn get_passkeys_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<PasskeyCredential>, DatabaseError> {
+        self.inner.get_passkeys_by_user_id(user_id).await
+    }
+
+    async fn get_passkeys_by_user_email(
+        &self,
+        email: &str,
+    ) -> Result<Vec<PasskeyCredential>, DatabaseError> {
+        self.inner.get_passkeys_by_user_email(email).await
+    }
+
+    async fn update_passkey(
+        &self,
+        id: &Uuid,
+        passkey: &PasskeyCredentialUpdate,
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        self.inner.update_passkey(id, passkey).await
+    }
+
+    async fn delete_passkey_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_passkey_by_id(id).await
+    }
+
+    async fn delete_passkey_unless_last(&self, id: &Uuid) -> Result<bool, DatabaseError> {
+        self.inner.delete_passkey_unless_last(id).await
+    }
+
+    async fn create_passkey_registration(
+        &self,
+        registration: &PasskeyRegistrationState,
+    ) -> Result<(), DatabaseError> {
+        put_ceremony(
+            self.conn.clone(),
+            self.key(REGISTRATION, registration.id),
+            registration,
+        )
+        .await
+    }
+
+    async fn get_passkey_registration_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<PasskeyRegistrationState, DatabaseError> {
+        get_ceremony(self.conn.clone(), self.key(REGISTRATION, id)).await
+    }
+
+    async fn delete_passkey_registration_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        delete_ceremony(self.conn.clone(), self.key(REGISTRATION, id)).await
+    }
+
+    async fn create_passkey_authentication(
+        &self,
+        state: &PasskeyAuthenticationState,
+    ) -> Result<(), DatabaseError> {
+        put_ceremony(self.conn.clone(), self.key(AUTHENTICATION, state.id), state).await
+    }
+
+    async fn get_passkey_authentication_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<PasskeyAuthenticationState, DatabaseError> {
+        get_ceremony(self.conn.clone(), self.key(AUTHENTICATION, id)).await
+    }
+
+    async fn delete_passkey_authentication_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        delete_ceremony(self.conn.clone(), self.key(AUTHENTICATION, id)).await
+    }
+
+    /// Ceremony state expires on its own in Redis, so there is nothing to delete.
+    async fn delete_expired_ceremonies(&self, _age: Duration) -> Result<u64, DatabaseError> {
+        async { Ok(0) }.await
+    }
+
+    async fn create_session(&self, session: &Session) -> Result<(), DatabaseError> {
+        let mut conn = self.conn.clone();
+        let hash = session.id_hash.to_hex();
+        let session_key = self.key(SESSION, hash);
+        let user_key = self.key(USER_SESSIONS, session.user_id);
+        let parent_key = session
+            .parent_id_hash
+            .map(|parent| self.key(SESSION_CHILDREN, parent.to_hex()));
+        let expires_at = session.expires_at.timestamp();
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .hset_multiple(&session_key, &session_to_fields(session))
+            .ignore()
+            .cmd("EXPIREAT")
+            .arg(&session_key)
+            .arg(expires_at)
+            .ignore()
+            .sadd(&user_key, hash.as_str())
+            .ignore();
+        extend_expiry(&mut pipe, &user_key, expires_at);
+        if let Some(parent_key) = &parent_key {
+            pipe.sadd(parent_key, hash.as_str()).ignore();
+            extend_expiry(&mut pipe, parent_key, expires_at);
+        }
+        pipe.query_async::<()>(&mut conn).await?;
+        Ok(())
+    }
+
+    async fn get_session_by_id_hash(
+        &self,
+        id_hash: &EncodableHash,
+    ) -> Result<Session, DatabaseError> {
+        let mut conn = self.conn.clone();
+        let session_key = self.key(SESSION, id_hash.to_hex());
+        let fields: HashMap<String, String> = conn.hgetall(&session_key).await?;
+        if fields.is_empty() {
+            return Err(DatabaseError::NotFound);
+        }
+        session_from_fields(*id_hash, &fields)
+    }
+
+    async fn get_active_sessions_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<Session>, DatabaseError> {
+        let mut conn = self.conn.clone();
+        let prefix = &self.prefix;
+        let hashes: Vec<String> = conn.smembers(key(prefix, USER_SESSIONS, user_id)).await?;
+        let mut pipe = redis::pipe();
+        for hash in &hashes {
+            pipe.hgetall(key(prefix, SESSION, hash));
+        }
+        let all_fields: Vec<HashMap<String, String>> = pipe.query_async(&mut conn).await?;
+        let now = chrono::Utc::now();
+        let mut sessions = Vec::new();
+        // Sessions which have expired are still in the set until it expires itself
+        for (hash, fields) in hashes.iter().zip(&all_fields) {
+            if fields.is_empty() {
+                continue;
+            }
+            let id_hash = blake3::Hash::from_hex(hash).map_err(|_| malformed("session set"))?;
+            let session = session_from_fields(EncodableHash(id_hash), fields)?;
+            if session.state == SessionState::Active && session.expires_at > now {
+                sessions.push(session);
+            }
+        }
+        sessions.sort_by_key(|session| Reverse(session.last_seen_at));
+        Ok(sessions)
+    }
+
+    async fn update_session(
+        &self,
+        id_hash: &EncodableHash,
+        update: &SessionUpdate,
+    ) -> Result<Session, DatabaseError> {
+        let mut conn = self.conn.clone();
+        let session_key = self.key(SESSION, id_hash.to_hex());
+        let user_key_prefix = format!("{}{USER_SESSIONS}:", self.prefix);
+        let mut fields = Vec::new();
+        if let Some(state) = update.state {
+            fields.push(("state", state_to_redis(state)));
+        }
+        let expires_at = update.expires_at.map(|time| time.timestamp().to_string());
+        if let Some(expires_at) = &expires_at {
+            fields.push(("expires_at", expires_at.clone()));
+        }
+        if let Some(last_seen_at) = update.last_seen_at {
+            fields.push(("last_seen_at", last_seen_at.timestamp().to_string()));
+        }
+        if let Some(ip) = &update.ip {
+            fields.push(("ip", ip.clone()));
+        }
+        if let Some(user_agent) = &update.user_agent {
+            fields.push(("user_agent", user_agent.clone()));
+        }
+        if let Some(authenticated_at) = update.authenticated_at {
+            fields.push(("authenticated_at", authenticated_at.timestamp().to_string()));
+        }
+        if let Some(organization_id) = update.organization_id {
+            fields.push((
+                "organization_id",
+                organization_id.map(|id| id.to_string()).unwrap_or_default(),
+            ));
+        }
+        if fields.is_empty() {
+            return Err(DatabaseError::EmptyUpdate);
+        }
+        let mut invocation = UPDATE_SESSION.key(&session_key);
+        invocation
+            .arg(&user_key_prefix)
+            .arg(expires_at.unwrap_or_default());
+        for (name, value) in fields {
+            invocation.arg(name).arg(value);
+        }
+        let fields: HashMap<String, String> = invocation.invoke_async(&mut conn).await?;
+        if fields.is_empty() {
+            return Err(DatabaseError::NotFound);
+        }
+        session_from_fields(*id_hash, &fields)
+    }
+
+    async fn create_session_revocation_token(
+        &self,
+        token_hash: &EncodableHash,
+        session_id_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError> {
+        let mut conn = self.conn.clone();
+        let session_hash = session_id_hash.to_hex();
+        let session_key = self.key(SESSION, session_hash);
+        let token_key = self.key(REVOCATION_TOKEN, token_hash.to_hex());
+        // The token is useless once the session has expired, so let it expire with it
+        let expires_at: Option<i64> = conn.hget(&session_key, "expires_at").await?;
+        let created: Option<String> = redis::cmd("SET")
+            .arg(&token_key)
+            .arg(session_hash.as_str())
+            .arg("EXAT")
+            .arg(expires_at.ok_or(DatabaseError::NotFound)?)
+            .arg("NX")
+            .query_async(&mut conn)
+            .await?;
+        match created {
+            Some(_) => Ok(()),
+            None => Err(DatabaseError::UniquenessViolation {
+                field: Some("token_hash".into()),
+            }),
+        }
+    }
+
+    async fn revoke_session_by_token(
+        &self,
+        token_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError> {
+        let mut conn = self.conn.clone();
+        let prefix = &self.prefix;
+        let token_key = self.key(REVOCATION_TOKEN, token_hash.to_hex());
+        let session_hash: Option<String> = redis::cmd("GETDEL")
+            .arg(&token_key)
+            .query_async(&mut conn)
+            .await?;
+        // Upgrading or downgrading a session creates a child session, so revoke the whole
+        // chain of sessions started by the login.
+        let mut pending = vec![session_hash.ok_or(DatabaseError::NotFound)?];
+        let mut keys = Vec::new();
+        while let Some(hash) = pending.pop() {
+            let children: Vec<String> = conn.smembers(key(prefix, SESSION_CHILDREN, &hash)).await?;
+            pending.extend(children);
+            keys.push(key(prefix, SESSION, &hash));
+        }
+        revoke_sessions(&mut conn, &keys).await?;
+        Ok(())
+    }
+
+    /// Sessions in Redis are deleted when they expire, so only those in the inner client, i.e.
+    /// those created before Redis was used, need deleting.
+    async fn delete_ended_sessions(&self, age: Duration) -> Result<u64, DatabaseError> {
+        self.inner.delete_ended_sessions(age).await
+    }
+
+    async fn revoke_all_sessions(
+        &self,
+        except: Option<&EncodableHash>,
+    ) -> Result<u64, DatabaseError> {
+        let mut conn = self.conn.clone();
+        let pattern = self.key(SESSION, "*");
+        let except = except.map(|hash| self.key(SESSION, hash.to_hex()));
+        let mut keys = Vec::new();
+        let mut cursor = 0u64;
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(&pattern)
+                .arg("COUNT")
+                .arg(SCRIPT_BATCH_SIZE)
+                .query_async(&mut conn)
+                .await?;
+            keys.extend(batch.into_iter().filter(|key| except.as_ref() != Some(key)));
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+        revoke_sessions(&mut conn, &keys).await
+    }
+
+    async fn revoke_sessions_by_user_id(&self, user_id: &Uuid) -> Result<u64, DatabaseError> {
+        let mut conn = self.conn.clone();
+        let prefix = &self.prefix;
+        let keys = user_session_keys(&mut conn, prefix, user_id).await?;
+        revoke_sessions(&mut conn, &keys).await
+    }
+
+    async fn create_api_token(&self, token: &ApiToken) -> Result<ApiToken, DatabaseError> {
+        self.inner.create_api_token(token).await
+    }
+
+    async fn get_api_token_by_hash(
+        &self,
+        token_hash: &EncodableHash,
+    ) -> Result<ApiToken, DatabaseError> {
+        self.inner.get_api_token_by_hash(token_hash).await
+    }
+
+    async fn get_api_tokens_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<ApiToken>, DatabaseError> {
+        self.inner.get_api_tokens_by_user_id(user_id).await
+    }
+
+    async fn delete_api_token(&self, user_id: &Uuid, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_api_token(user_id, id).await
+    }
+
+    async fn record_user_device(
+        &self,
+        user_id: &Uuid,
+        device_id_hash: &EncodableHash,
+    ) -> Result<bool, DatabaseError> {
+        self.inner.record_user_device(user_id, device_id_hash).await
+    }
+
+    async fn create_queued_email(&self, email: &QueuedEmail) -> Result<(), DatabaseError> {
+        self.inner.create_queued_email(email).await
+    }
+
+    async fn get_due_queued_emails(&self, limit: u32) -> Result<Vec<QueuedEmail>, DatabaseError> {
+        self.inner.get_due_queued_emails(limit).await
+    }
+
+    async fn update_queued_email(
+        &self,
+        id: &Uuid,
+        update: &QueuedEmailUpdate,
+    ) -> Result<QueuedEmail, DatabaseError> {
+        self.inner.update_queued_email(id, update).await
+    }
+
+    async fn delete_queued_email_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_queued_email_by_id(id).await
+    }
+
+    async fn create_email_suppression(
+        &self,
+        address: &str,
+        reason: &str,
+    ) -> Result<EmailSuppression, DatabaseError> {
+        self.inner.create_email_suppression(address, reason).await
+    }
+
+    async fn get_email_suppression(
+        &self,
+        address: &str,
+    ) -> Result<EmailSuppression, DatabaseError> {
+        self.inner.get_email_suppression(address).await
+    }
+
+    async fn list_email_suppressions(&self) -> Result<Vec<EmailSuppression>, DatabaseError> {
+        self.inner.list_email_suppressions().await
+    }
+
+    async fn delete_email_suppression(&self, address: &str) -> Result<(), DatabaseError> {
+        self.inner.delete_email_suppression(address).await
+    }
+
+    async fn create_ip_ban(&self, ban: &IpBan) -> Result<IpBan, DatabaseError> {
+        self.inner.create_ip_ban(ban).await
+    }
+
+    async fn list_ip_bans(&self) -> Result<Vec<IpBan>, DatabaseError> {
+        self.inner.list_ip_bans().await
+    }
+
+    async fn delete_ip_ban_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_ip_ban_by_id(id).await
+    }
+
+    async fn try_acquire_lease(
+        &self,
+        name: &str,
+        holder: &Uuid,
+        ttl: Duration,
+    ) -> Result<bool, DatabaseError> {
+        self.inner.try_acquire_lease(name, holder, ttl).await
+    }
+
+    async fn get_announcement(&self) -> Result<Announcement, DatabaseError> {
+        self.inner.get_announcement().await
+    }
+
+    async fn set_announcement(&self, announcement: &Announcement) -> Result<(), DatabaseError> {
+        self.inner.set_announcement(announcement).await
+    }
+
+    async fn delete_announcement(&self) -> Result<(), DatabaseError> {
+        self.inner.delete_announcement().await
+    }
+
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<Webhook, DatabaseError> {
+        self.inner.create_webhook(webhook).await
+    }
+
+    async fn list_webhooks(&self) -> Result<Vec<Webhook>, DatabaseError> {
+        self.inner.list_webhooks().await
+    }
+
+    async fn get_webhook_by_id(&self, id: &Uuid) -> Result<Webhook, DatabaseError> {
+        self.inner.get_webhook_by_id(id).await
+    }
+
+    async fn update_webhook(
+        &self,
+        id: &Uuid,
+        update: &WebhookUpdate,
+    ) -> Result<Webhook, DatabaseError> {
+        self.inner.update_webhook(id, update).await
+    }
+
+    async fn delete_webhook_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_webhook_by_id(id).await
+    }
+
+    async fn create_oauth_client(
+        &self,
+        client: &OAuthClient,
+    ) -> Result<OAuthClient, DatabaseError> {
+        self.inner.create_oauth_client(client).await
+    }
+
+    async fn list_oauth_clients(&self) -> Result<Vec<OAuthClient>, DatabaseError> {
+        self.inner.list_oauth_clients().await
+    }
+
+    async fn get_oauth_client_by_id(&self, id: &Uuid) -> Result<OAuthClient, DatabaseError> {
+        self.inner.get_oauth_client_by_id(id).await
+    }
+
+    async fn update_oauth_client(
+        &self,
+        id: &Uuid,
+        update: &OAuthClientUpdate,
+    ) -> Result<OAuthClient, DatabaseError> {
+        self.inner.update_oauth_client(id, update).await
+    }
+
+    async fn delete_oauth_client_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_oauth_client_by_id(id).await
+    }
+
+    async fn create_authorization_code(
+        &self,
+        code: &AuthorizationCode,
+    ) -> Result<(), DatabaseError> {
+        self.inner.create_authorization_code(code).await
+    }
+
+    async fn consume_authorization_code(
+        &self,
+        code_hash: &EncodableHash,
+    ) -> Result<AuthorizationCode, DatabaseError> {
+        self.inner.consume_authorization_code(code_hash).await
+    }
+
+    async fn create_saml_service_provider(
+        &self,
+        provider: &SamlServiceProvider,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        self.inner.create_saml_service_provider(provider).await
+    }
+
+    async fn list_saml_service_providers(&self) -> Result<Vec<SamlServiceProvider>, DatabaseError> {
+        self.inner.list_saml_service_providers().await
+    }
+
+    async fn get_saml_service_provider_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        self.inner.get_saml_service_provider_by_id(id).await
+    }
+
+    async fn get_saml_service_provider_by_entity_id(
+        &self,
+        entity_id: &str,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        self.inner
+            .get_saml_service_provider_by_entity_id(entity_id)
+            .await
+    }
+
+    async fn update_saml_service_provider(
+        &self,
+        id: &Uuid,
+        update: &SamlServiceProviderUpdate,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        self.inner.update_saml_service_provider(id, update).await
+    }
+
+    async fn delete_saml_service_provider_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_saml_service_provider_by_id(id).await
+    }
+
+    async fn create_federated_provider(
+        &self,
+        provider: &FederatedProvider,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        self.inner.create_federated_provider(provider).await
+    }
+
+    async fn list_federated_providers(&self) -> Result<Vec<FederatedProvider>, DatabaseError> {
+        self.inner.list_federated_providers().await
+    }
+
+    async fn get_federated_provider_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        self.inner.get_federated_provider_by_id(id).await
+    }
+
+    async fn get_federated_provider_by_slug(
+        &self,
+        slug: &str,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        self.inner.get_federated_provider_by_slug(slug).await
+    }
+
+    async fn update_federated_provider(
+        &self,
+        id: &Uuid,
+        update: &FederatedProviderUpdate,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        self.inner.update_federated_provider(id, update).await
+    }
+
+    async fn delete_federated_provider_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        self.inner.delete_federated_provider_by_id(id).await
+    }
+
+    async fn create_federated_login_state(
+        &self,
+        state: &FederatedLoginState,
+    ) -> Result<(), DatabaseError> {
+        self.inner.create_federated_login_state(state).await
+    }
+
+    async fn consume_federated_login_state(
+        &self,
+        state_hash: &EncodableHash,
+    ) -> Result<FederatedLoginState, DatabaseError> {
+        self.inner.consume_federated_login_state(state_hash).await
+    }
+
+    async fn get_federated_identity(
+        &self,
+        provider_id: &Uuid,
+        subject: &str,
+    ) -> Result<FederatedIdentity, DatabaseError> {
+        self.inner
+            .get_federated_identity(provider_id, subject)
+            .await
+    }
+
+    async fn create_federated_identity(
+        &self,
+        identity: &FederatedIdentity,
+    ) -> Result<(), DatabaseError> {
+        self.inner.create_federated_identity(identity).await
+    }
+
+    async fn list_federated_identities_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<FederatedIdentity>, DatabaseError> {
+        self.inner
+            .list_federated_identities_by_user_id(user_id)
+            .await
+    }
+
+    async fn delete_federated_identities(
+        &self,
+        user_id: &Uuid,
+        provider_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        self.inner
+            .delete_federated_identities(user_id, provider_id)
+            .await
+    }
+
+    async fn create_signing_key(&self, key: &StoredSigningKey) -> Result<(), DatabaseError> {
+        self.inner.create_signing_key(key).await
+    }
+
+    async fn list_signing_keys(&self) -> Result<Vec<StoredSigningKey>, DatabaseError> {
+        self.inner.list_signing_keys().await
+    }
+
+    async fn retire_signing_keys_older_than(&self, kid: &str) -> Result<u64, DatabaseError> {
+        self.inner.retire_signing_keys_older_than(kid).await
+    }
+
+    async fn delete_retired_signing_keys(&self, age: Duration) -> Result<u64, DatabaseError> {
+        self.inner.delete_retired_signing_keys(age).await
+    }
+
+    async fn get_or_create_session_cookie_key(
+        &self,
+        key: &StoredSessionCookieKey,
+    ) -> Result<StoredSessionCookieKey, DatabaseError> {
+        self.inner.get_or_create_session_cookie_key(key).await
+    }
+
+    async fn purge_retained_data(
+        &self,
+        policy: &RetentionPolicy,
+    ) -> Result<RetentionReport, DatabaseError> {
+        self.inner.purge_retained_data(policy).await
+    }
+
+    async fn run_maintenance(&self, task: MaintenanceTask) -> Result<(), DatabaseError> {
+        self.inner.run_maintenance(task).await
+    }
+
+    // Sessions and ceremony state kept in Redis aren't backed up, and restored sessions are
+    // ignored, as if they had expired
+    async fn export_backup(&self) -> Result<Backup, DatabaseError> {
+        self.inner.export_backup().await
+    }
+
+    async fn import_backup(&self, backup: &Backup) -> Result<(), DatabaseError> {
+        self.inner.import_backup(backup).await
+    }
+
+    async fn begin(&self) -> Result<BoxedTransaction, DatabaseError> {
+        let tx: BoxedTransaction = Box::new(RedisTransaction {
+            inner: self.inner.begin().await?,
+            client: self.clone(),
+            created: Vec::new(),
+            updated: Vec::new(),
+        });
+        Ok(tx)
+    }
+}
+
+/// # Transaction of a [`RedisSessionClient`]
+///
+/// Redis can't take part in the inner client's transaction, so session changes are buffered and
+/// written to Redis after the inner transaction has been committed. They're discarded along with
+/// the inner transaction if it's rolled back or fails to commit, but if writing them fails, the
+/// inner transaction stays committed.
+struct RedisTransaction {
+    inner: Box<dyn DatabaseTransaction>,
+    client: RedisSessionClient,
+    created: Vec<Session>,
+    updated: Vec<(EncodableHash, SessionUpdate)>,
+}
+
+#[async_trait]
+impl DatabaseTransaction for RedisTransaction {
+    async fn create_user(&mut self, id: &Uuid, user: &UserCreate) -> Result<User, DatabaseError> {
+        self.inner.create_user(id, user).await
+    }
+
+    async fn add_tag_to_user(&mut self, user_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        self.inner.add_tag_to_user(user_id, tag).await
+    }
+
+    async fn create_passkey(
+        &mut self,
+        id: &Uuid,
+        user_id: &Uuid,
+        passkey: &NewPasskeyCredential,
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        self.inner.create_passkey(id, user_id, passkey).await
+    }
+
+    async fn create_session(&mut self, session: &Session) -> Result<(), DatabaseError> {
+        self.created.push(session.clone());
+        Ok(())
+    }
+
+    async fn update_session(
+        &mut self,
+        id_hash: &EncodableHash,
+        update: &SessionUpdate,
+    ) -> Result<Session, DatabaseError> {
+        if update.is_empty() {
+            return Err(DatabaseError::EmptyUpdate);
+        }
+        // Apply the update to a copy of the session as it will be once the transaction's
+        // earlier changes are written
+        let mut session = match self
+            .created
+            .iter()
+            .rfind(|session| *session.id_hash == **id_hash)
+        {
+            Some(session) => session.clone(),
+            None => self.client.get_session_by_id_hash(id_hash).await?,
+        };
+        let earlier = self
+            .updated
+            .iter()
+            .filter(|(hash, _)| **hash == **id_hash)
+            .map(|(_, update)| update);
+        for update in earlier.chain([update]) {
+            session.state = update.state.unwrap_or(session.state);
+            session.expires_at = update.expires_at.unwrap_or(session.expires_at);
+            session.last_seen_at = update.last_seen_at.unwrap_or(session.last_seen_at);
+            session.ip = update.ip.clone().or(session.ip);
+            session.user_agent = update.user_agent.clone().or(session.user_agent);
+            session.authenticated_at = update.authenticated_at.unwrap_or(session.authenticated_at);
+        }
+        self.updated.push((*id_hash, update.clone()));
+        Ok(session)
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), DatabaseError> {
+        self.inner.commit().await?;
+        for session in &self.created {
+            self.client.create_session(session).await?;
+        }
+        for (id_hash, update) in &self.updated {
+            self.client.update_session(id_hash, update).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{SubsecRound, Utc};
+
+    use super::*;
+
+    #[test]
+    fn test_session_fields_round_trip() {
+        let now = Utc::now().trunc_subsecs(0);
+        let session = Session {
+            id_hash: EncodableHash(blake3::hash(b"session")),
+            user_id: Uuid::new_v4(),
+            state: SessionState::Superseded,
+            created_at: now,
+            expires_at: now + chrono::Duration::hours(1),
+            is_admin: true,
+            parent_id_hash: Some(EncodableHash(blake3::hash(b"parent"))),
+            ip: Some("192.0.2.1".to_string()),
+            user_agent: None,
+            last_seen_at: now + chrono::Duration::minutes(5),
+            impersonator_id: Some(Uuid::new_v4()),
+            authenticated_at: now - chrono::Duration::minutes(5),
+            organization_id: Some(Uuid::new_v4()),
+        };
+        let fields = session_to_fields(&session)
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        let parsed = session_from_fields(session.id_hash, &fields).unwrap();
+        assert_eq!(parsed.user_id, session.user_id);
+        assert_eq!(parsed.state, session.state);
+        assert_eq!(parsed.created_at, session.created_at);
+        assert_eq!(parsed.expires_at, session.expires_at);
+        assert!(parsed.is_admin);
+        assert_eq!(parsed.ip, session.ip);
+        assert_eq!(parsed.user_agent, None);
+        assert_eq!(parsed.last_seen_at, session.last_seen_at);
+        assert_eq!(parsed.impersonator_id, session.impersonator_id);
+        assert_eq!(parsed.authenticated_at, session.authenticated_at);
+        assert_eq!(parsed.organization_id, session.organization_id);
+        assert_eq!(
+            parsed.parent_id_hash.map(|hash| hash.to_hex()),
+            session.parent_id_hash.map(|hash| hash.to_hex())
+        );
+    }
+}
@@ -0,0 +1,289 @@
+//! # SQLite3 backups
+//!
+//! Every table of the schema is backed up, except for those holding
+//! [short-lived state][EPHEMERAL_TABLES], so a table added by a migration is backed up as soon as
+//! it exists. Each backed-up table holds one kind of entity, and its columns are the records'
+//! fields. Since
+//! every table is `STRICT`, a column's declared type tells how its values are stored in a
+//! [`Backup`]. Backups are restored with foreign key checks deferred to the end of the
+//! transaction, so that tables can be restored in any order.
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chrono::Utc;
+use serde_json::Value;
+use sqlx::{Row, SqliteConnection, SqlitePool};
+
+use crate::{
+    db::interface::DatabaseError,
+    models::{BACKUP_VERSION, Backup, BackupRecord},
+};
+
+/// Tables which aren't backed up, since they hold short-lived state, such as passkey ceremonies.
+/// Every other table is, so that tables added by migrations are backed up without further ado.
+const EPHEMERAL_TABLES: &[&str] = &[
+    "email_changes",
+    "federated_login_states",
+    "job_leases",
+    "oauth_authorization_codes",
+    "passkey_authentications",
+    "passkey_registrations",
+];
+
+/// Returns the names of the tables which are backed up, i.e. the ordinary tables of the schema
+/// except for the [`EPHEMERAL_TABLES`] and those maintained by SQLite or sqlx. Virtual tables,
+/// such as full-text search indexes, and their shadow tables are rebuilt from the others.
+async fn backup_tables(conn: &mut SqliteConnection) -> Result<Vec<String>, DatabaseError> {
+    let tables: Vec<String> = sqlx::query_scalar(
+        "SELECT name FROM pragma_table_list
+        WHERE schema = 'main' AND type = 'table'
+            AND name NOT LIKE 'sqlite_%' AND name <> '_sqlx_migrations'
+        ORDER BY name",
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(tables
+        .into_iter()
+        .filter(|table| !EPHEMERAL_TABLES.contains(&table.as_str()))
+        .collect())
+}
+
+/// Type of a column, as declared in its table
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ColumnType {
+    Integer,
+    Text,
+    Blob,
+}
+
+struct Column {
+    name: String,
+    column_type: ColumnType,
+}
+
+/// Returns the columns of the given table.
+async fn columns(conn: &mut SqliteConnection, table: &str) -> Result<Vec<Column>, DatabaseError> {
+    let columns: Vec<(String, String)> =
+        sqlx::query_as("SELECT name, type FROM pragma_table_info($1)")
+            .bind(table)
+            .fetch_all(conn)
+            .await?;
+    columns
+        .into_iter()
+        .map(|(name, declared)| {
+            let column_type = match declared.as_str() {
+                "INTEGER" => ColumnType::Integer,
+                "TEXT" => ColumnType::Text,
+                "BLOB" => ColumnType::Blob,
+                _ => {
+                    return Err(DatabaseError::Other(
+                        format!("column {table}.{name} has unsupported type {declared}").into(),
+                    ));
+                }
+            };
+            Ok(Column { name, column_type })
+        })
+        .collect()
+}
+
+/// Returns the columns of the given table, separated by commas, for use in queries.
+fn column_list(columns: &[Column]) -> String {
+    columns
+        .iter()
+        .map(|column| format!("\"{}\"", column.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub(super) async fn export_backup(pool: &SqlitePool) -> Result<Backup, DatabaseError> {
+    // Read every table in one transaction, so that the backup is consistent
+    let mut tx = pool.begin().await?;
+    let mut entities = BTreeMap::new();
+    for table in backup_tables(&mut tx).await? {
+        let columns = columns(&mut tx, &table).await?;
+        let query = format!("SELECT {} FROM \"{table}\"", column_list(&columns));
+        let rows = sqlx::query(&query).fetch_all(&mut *tx).await?;
+        let mut records = Vec::with_capacity(rows.len());
+        for row in rows {
+            let mut record = BackupRecord::new();
+            for (i, column) in columns.iter().enumerate() {
+                let value = match column.column_type {
+                    ColumnType::Integer => row.try_get::<Option<i64>, _>(i)?.into(),
+                    ColumnType::Text => row.try_get::<Option<String>, _>(i)?.into(),
+                    ColumnType::Blob => row
+                        .try_get::<Option<Vec<u8>>, _>(i)?
+                        .map(|bytes| BASE64_STANDARD.encode(bytes))
+                        .into(),
+                };
+                record.insert(column.name.clone(), value);
+            }
+            records.push(record);
+        }
+        entities.insert(table, records);
+    }
+    Ok(Backup {
+        version: BACKUP_VERSION,
+        created_at: Utc::now(),
+        entities,
+    })
+}
+
+pub(super) async fn import_backup(pool: &SqlitePool, backup: &Backup) -> Result<(), DatabaseError> {
+    if backup.version != BACKUP_VERSION {
+        return Err(invalid(format!(
+            "backup format version {} is not supported",
+            backup.version
+        )));
+    }
+    let mut tx = pool.begin().await?;
+    sqlx::query("PRAGMA defer_foreign_keys = ON")
+        .execute(&mut *tx)
+        .await?;
+    let tables = backup_tables(&mut tx).await?;
+    for table in &tables {
+        let (count,): (i64,) = sqlx::query_as(&format!("SELECT count(*) FROM \"{table}\""))
+            .fetch_one(&mut *tx)
+            .await?;
+        if count > 0 {
+            return Err(DatabaseError::Other(
+                format!("the database isn't empty; table {table} has {count} rows").into(),
+            ));
+        }
+    }
+    for (kind, records) in &backup.entities {
+        let Some(table) = tables.iter().find(|&table| table == kind) else {
+            return Err(invalid(format!("unknown kind of entity `{kind}`")));
+        };
+        let columns = columns(&mut tx, table).await?;
+        for record in records {
+            import_record(&mut tx, table, &columns, record).await?;
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Inserts a record into the given table. Fields missing from the record take their default
+/// values, so that backups taken before a column was added can still be restored.
+async fn import_record(
+    conn: &mut SqliteConnection,
+    table: &str,
+    columns: &[Column],
+    record: &BackupRecord,
+) -> Result<(), DatabaseError> {
+    if let Some(field) = record
+        .keys()
+        .find(|field| !columns.iter().any(|column| column.name == **field))
+    {
+        return Err(invalid(format!("unknown field `{field}` of `{table}`")));
+    }
+    let present: Vec<_> = columns
+        .iter()
+        .filter_map(|column| Some((column, record.get(&column.name)?)))
+        .collect();
+    let names = present
+        .iter()
+        .map(|(column, _)| format!("\"{}\"", column.name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let placeholders = vec!["?"; present.len()].join(", ");
+    let query = format!("INSERT INTO \"{table}\" ({names}) VALUES ({placeholders})");
+    let mut query = sqlx::query(&query);
+    for (column, value) in present {
+        let field = || format!("`{table}.{}`", column.name);
+        query = match (column.column_type, value) {
+            (_, Value::Null) => query.bind(None::<i64>),
+            (ColumnType::Integer, Value::Number(number)) => query.bind(
+                number
+                    .as_i64()
+                    .ok_or_else(|| invalid(format!("{} must be an integer", field())))?,
+            ),
+            (ColumnType::Text, Value::String(text)) => query.bind(text.as_str()),
+            (ColumnType::Blob, Value::String(text)) => query.bind(
+                BASE64_STANDARD
+                    .decode(text)
+                    .map_err(|_| invalid(format!("{} must be base64", field())))?,
+            ),
+            _ => return Err(invalid(format!("{} has the wrong type", field()))),
+        };
+    }
+    query.execute(conn).await?;
+    Ok(())
+}
+
+fn invalid(message: impl Display) -> DatabaseError {
+    DatabaseError::Other(format!("invalid backup: {message}").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::db::clients::sqlite::SqliteClient;
+
+    #[tokio::test]
+    async fn test_every_table_is_backed_up() {
+        let client = SqliteClient::new_memory().await.unwrap();
+        let backup = export_backup(&client.pool).await.unwrap();
+        let tables: Vec<String> = sqlx::query_scalar(
+            "SELECT name FROM sqlite_schema
+            WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT LIKE 'users_fts%'
+            ORDER BY name",
+        )
+        .fetch_all(&client.pool)
+        .await
+        .unwrap();
+        let missing: Vec<_> = tables
+            .iter()
+            .filter(|&table| {
+                table != "_sqlx_migrations"
+                    && !EPHEMERAL_TABLES.contains(&table.as_str())
+                    && !backup.entities.contains_key(table)
+            })
+            .collect();
+        assert!(
+            missing.is_empty(),
+            "tables missing from backups: {missing:?}"
+        );
+        // Renamed or dropped tables must be removed from the list
+        for table in EPHEMERAL_TABLES {
+            assert!(
+                tables.iter().any(|name| name == table),
+                "{table} doesn't exist"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_tables_are_backed_up() {
+        let client = SqliteClient::new_memory().await.unwrap();
+        let migration = "CREATE TABLE widgets (id TEXT PRIMARY KEY, size INTEGER NOT NULL) STRICT";
+        sqlx::query(migration).execute(&client.pool).await.unwrap();
+        sqlx::query("INSERT INTO widgets (id, size) VALUES ('w1', 3)")
+            .execute(&client.pool)
+            .await
+            .unwrap();
+        let backup = export_backup(&client.pool).await.unwrap();
+        assert_eq!(
+            backup.entities["widgets"],
+            [json!({ "id": "w1", "size": 3 })
+                .as_object()
+                .unwrap()
+                .clone()]
+        );
+
+        let restored = SqliteClient::new_memory().await.unwrap();
+        sqlx::query(migration)
+            .execute(&restored.pool)
+            .await
+            .unwrap();
+        import_backup(&restored.pool, &backup).await.unwrap();
+        let (size,): (i64,) = sqlx::query_as("SELECT size FROM widgets WHERE id = 'w1'")
+            .fetch_one(&restored.pool)
+            .await
+            .unwrap();
+        assert_eq!(size, 3);
+    }
+}
@@ -0,0 +1,93 @@
+//! # Stored email address normalization
+//!
+//! Email addresses are stored [normalized][normalize_email], so that lookups can compare them
+//! exactly. Normalization folds case with Unicode rules and depends on the configured
+//! [Unicode normalization][crate::models::set_email_unicode_normalization], neither of which a
+//! SQL migration can do, so the stored addresses are normalized whenever the database is opened.
+//! Addresses which are already normalized are left alone, so this only changes anything after an
+//! upgrade or a change of configuration.
+//!
+//! Users whose addresses would be the same once normalized are left alone and logged, so that an
+//! administrator can merge the accounts or change one of their addresses.
+
+use std::collections::BTreeMap;
+
+use sqlx::SqlitePool;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::models::normalize_email;
+
+/// Normalizes the addresses of users and of email suppressions.
+pub(super) async fn normalize_stored_emails(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    let users: Vec<(Uuid, String)> = sqlx::query_as("SELECT id, email FROM users")
+        .fetch_all(&mut *tx)
+        .await?;
+    let mut by_address: BTreeMap<String, Vec<(Uuid, String)>> = BTreeMap::new();
+    for (id, email) in users {
+        by_address
+            .entry(normalize_email(&email))
+            .or_default()
+            .push((id, email));
+    }
+    let mut normalized = 0;
+    for (address, users) in by_address {
+        match users.as_slice() {
+            [(_, email)] if *email == address => {}
+            [(id, email)] => {
+                // Pending authentications reference the address
+                sqlx::query("DELETE FROM passkey_authentications WHERE email = $1")
+                    .bind(email)
+                    .execute(&mut *tx)
+                    .await?;
+                sqlx::query("UPDATE users SET email = $1 WHERE id = $2")
+                    .bind(&address)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+                normalized += 1;
+            }
+            _ => {
+                let ids: Vec<_> = users.iter().map(|(id, _)| id.to_string()).collect();
+                warn!(
+                    %address,
+                    users = %ids.join(", "),
+                    "users' email addresses are the same once normalized; merge the accounts \
+                    or change their addresses",
+                );
+            }
+        }
+    }
+
+    let suppressions: Vec<(String,)> = sqlx::query_as("SELECT address FROM email_suppressions")
+        .fetch_all(&mut *tx)
+        .await?;
+    for (address,) in suppressions {
+        let normalized_address = normalize_email(&address);
+        if normalized_address == address {
+            continue;
+        }
+        // Keeps the suppression of the normalized address if there already is one
+        sqlx::query(
+            "INSERT INTO email_suppressions (address, reason, created_at)
+            SELECT $2, reason, created_at FROM email_suppressions WHERE address = $1
+            ON CONFLICT (address) DO NOTHING",
+        )
+        .bind(&address)
+        .bind(&normalized_address)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM email_suppressions WHERE address = $1")
+            .bind(&address)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+    if normalized > 0 {
+        info!(count = normalized, "normalized stored email addresses");
+    }
+    Ok(())
+}
@@ -0,0 +1,168 @@
+//! # SQLite3 schema migrations
+//!
+//! The migrations in the `migrations` directory are embedded in the binary. By default they are
+//! applied whenever the database is opened, but they can also be listed, applied, and reverted
+//! explicitly, for operators who want to control when the schema changes. Only migrations with a
+//! `.down.sql` script can be reverted.
+
+use std::collections::HashSet;
+
+use sqlx::{
+    SqlitePool,
+    migrate::{Migrate, MigrateError, Migrator},
+};
+
+use super::{CreateSqliteClientError, SqliteClient};
+
+/// Migrations embedded in the binary
+static MIGRATOR: Migrator = sqlx::migrate!("src/db/clients/sqlite/migrations");
+
+/// What is done with pending migrations when the database is opened
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum PendingMigrations {
+    /// Apply them
+    #[default]
+    Apply,
+    /// Fail to open the database if there are any
+    Refuse,
+    /// Leave them pending, e.g. to manage them with the methods of [`SqliteClient`]
+    Ignore,
+}
+
+/// Status of a migration, as listed by [`SqliteClient::migrations()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    /// Whether the migration has been applied to the database
+    pub applied: bool,
+    /// Whether the migration can be reverted
+    pub reversible: bool,
+}
+
+/// Error from managing migrations explicitly
+#[derive(Debug, thiserror::Error)]
+pub enum MigrationError {
+    /// Listing, applying, or reverting migrations failed.
+    #[error(transparent)]
+    Migrate(#[from] MigrateError),
+
+    /// A migration which would have to be reverted has no `.down.sql` script.
+    #[error("migration {version} ({description}) can't be reverted")]
+    Irreversible { version: i64, description: String },
+
+    /// The target version of a revert isn't a known migration.
+    #[error("no migration has version {0}")]
+    UnknownVersion(i64),
+}
+
+impl SqliteClient {
+    /// Lists every migration embedded in the binary, oldest first, along with whether it has
+    /// been applied.
+    pub async fn migrations(&self) -> Result<Vec<MigrationStatus>, MigrateError> {
+        migrations(&self.pool).await
+    }
+
+    /// Applies the pending migrations, returning the ones which were applied. If `dry_run` is
+    /// set, nothing is changed and the migrations which would be applied are returned.
+    pub async fn migrate_up(&self, dry_run: bool) -> Result<Vec<MigrationStatus>, MigrateError> {
+        let pending: Vec<_> = migrations(&self.pool)
+            .await?
+            .into_iter()
+            .filter(|migration| !migration.applied)
+            .collect();
+        if !dry_run && !pending.is_empty() {
+            MIGRATOR.run(&self.pool).await?;
+        }
+        Ok(pending)
+    }
+
+    /// Reverts the applied migrations newer than `target`, newest first, returning the ones which
+    /// were reverted. If `target` is [`None`], only the newest applied migration is reverted. If
+    /// `dry_run` is set, nothing is changed and the migrations which would be reverted are
+    /// returned. Nothing is reverted if any of them can't be.
+    pub async fn migrate_down(
+        &self,
+        target: Option<i64>,
+        dry_run: bool,
+    ) -> Result<Vec<MigrationStatus>, MigrationError> {
+        let migrations = migrations(&self.pool).await?;
+        if let Some(target) = target
+            && !migrations
+                .iter()
+                .any(|migration| migration.version == target)
+        {
+            return Err(MigrationError::UnknownVersion(target));
+        }
+        let mut applied = migrations.into_iter().rev().filter(|m| m.applied);
+        let reverted: Vec<_> = match target {
+            Some(target) => applied.take_while(|m| m.version > target).collect(),
+            None => applied.next().into_iter().collect(),
+        };
+        if let Some(migration) = reverted.iter().find(|m| !m.reversible) {
+            return Err(MigrationError::Irreversible {
+                version: migration.version,
+                description: migration.description.clone(),
+            });
+        }
+        if let Some(last) = reverted.last()
+            && !dry_run
+        {
+            // Reverts every reversible migration newer than the target, which we've checked are
+            // exactly the ones listed
+            MIGRATOR.undo(&self.pool, last.version - 1).await?;
+        }
+        Ok(reverted)
+    }
+}
+
+/// Runs or checks for pending migrations as given, after the database is opened.
+pub(super) async fn handle_pending(
+    pool: &SqlitePool,
+    pending: PendingMigrations,
+) -> Result<(), CreateSqliteClientError> {
+    match pending {
+        PendingMigrations::Apply => MIGRATOR.run(pool).await?,
+        PendingMigrations::Refuse => {
+            let count = migrations(pool)
+                .await?
+                .iter()
+                .filter(|m| !m.applied)
+                .count();
+            if count > 0 {
+                return Err(CreateSqliteClientError::PendingMigrations(count));
+            }
+        }
+        PendingMigrations::Ignore => {}
+    }
+    Ok(())
+}
+
+async fn migrations(pool: &SqlitePool) -> Result<Vec<MigrationStatus>, MigrateError> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    if let Some(version) = conn.dirty_version().await? {
+        return Err(MigrateError::Dirty(version));
+    }
+    let applied: HashSet<_> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect();
+    let reversible: HashSet<_> = MIGRATOR
+        .iter()
+        .filter(|migration| migration.migration_type.is_down_migration())
+        .map(|migration| migration.version)
+        .collect();
+    Ok(MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied: applied.contains(&migration.version),
+            reversible: reversible.contains(&migration.version),
+        })
+        .collect())
+}
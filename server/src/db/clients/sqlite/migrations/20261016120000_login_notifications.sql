@@ -0,0 +1,20 @@
+ALTER TABLE users ADD COLUMN login_notifications INTEGER NOT NULL DEFAULT 1;
+
+CREATE TABLE user_devices (
+    user_id BLOB NOT NULL,
+    id_hash BLOB NOT NULL,
+    first_seen_at INTEGER NOT NULL,
+    last_seen_at INTEGER NOT NULL,
+    PRIMARY KEY (user_id, id_hash),
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+) STRICT;
+
+CREATE TABLE session_revocation_tokens (
+    token_hash BLOB PRIMARY KEY,
+    session_id_hash BLOB NOT NULL,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY (session_id_hash) REFERENCES sessions (id_hash) ON DELETE CASCADE
+) STRICT;
+
+CREATE INDEX session_revocation_tokens_session_id_hash_index
+    ON session_revocation_tokens (session_id_hash);
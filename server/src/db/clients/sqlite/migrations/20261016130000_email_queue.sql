@@ -0,0 +1,15 @@
+CREATE TABLE email_queue (
+    id BLOB PRIMARY KEY,
+    sender TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    html TEXT NOT NULL,
+    text TEXT NOT NULL,
+    state INTEGER NOT NULL,
+    attempts INTEGER NOT NULL,
+    last_error TEXT,
+    created_at INTEGER NOT NULL,
+    next_attempt_at INTEGER NOT NULL
+) STRICT;
+
+CREATE INDEX email_queue_state_next_attempt_at_index ON email_queue (state, next_attempt_at);
@@ -0,0 +1,5 @@
+CREATE TABLE email_suppressions (
+    address TEXT PRIMARY KEY,
+    reason TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+) STRICT;
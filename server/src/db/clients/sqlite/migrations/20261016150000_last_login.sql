@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN last_login_at INTEGER;
+
+CREATE INDEX users_last_login_at_index ON users (last_login_at);
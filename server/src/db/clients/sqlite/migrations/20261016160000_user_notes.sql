@@ -0,0 +1,11 @@
+CREATE TABLE user_notes (
+    id BLOB PRIMARY KEY,
+    user_id BLOB NOT NULL,
+    author_id BLOB,
+    body TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (author_id) REFERENCES users (id) ON DELETE SET NULL
+) STRICT;
+
+CREATE INDEX user_notes_user_id_index ON user_notes (user_id, created_at);
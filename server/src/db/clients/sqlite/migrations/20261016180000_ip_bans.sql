@@ -0,0 +1,8 @@
+CREATE TABLE ip_bans (
+    id BLOB PRIMARY KEY,
+    network TEXT NOT NULL UNIQUE,
+    reason TEXT NOT NULL,
+    source INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER
+) STRICT;
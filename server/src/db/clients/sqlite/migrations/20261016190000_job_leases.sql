@@ -0,0 +1,5 @@
+CREATE TABLE job_leases (
+    name TEXT PRIMARY KEY,
+    holder BLOB NOT NULL,
+    expires_at INTEGER NOT NULL
+) STRICT;
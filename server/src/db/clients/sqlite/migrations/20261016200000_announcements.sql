@@ -0,0 +1,9 @@
+-- At most one announcement exists at a time
+CREATE TABLE announcements (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    message TEXT NOT NULL,
+    severity INTEGER NOT NULL,
+    starts_at INTEGER,
+    ends_at INTEGER,
+    updated_at INTEGER NOT NULL
+) STRICT;
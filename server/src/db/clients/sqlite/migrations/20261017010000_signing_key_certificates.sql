@@ -0,0 +1 @@
+ALTER TABLE signing_keys ADD COLUMN certificate TEXT;
@@ -0,0 +1,9 @@
+CREATE TABLE saml_service_providers (
+    id BLOB PRIMARY KEY,
+    entity_id TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    acs_url TEXT NOT NULL,
+    name_id_format INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+) STRICT;
@@ -0,0 +1,36 @@
+CREATE TABLE federated_providers (
+    id BLOB PRIMARY KEY,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    issuer TEXT NOT NULL,
+    client_id TEXT NOT NULL,
+    client_secret TEXT NOT NULL,
+    auto_provision INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+) STRICT;
+
+CREATE TABLE federated_identities (
+    provider_id BLOB NOT NULL,
+    subject TEXT NOT NULL,
+    user_id BLOB NOT NULL,
+    email TEXT,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (provider_id, subject),
+    FOREIGN KEY (provider_id) REFERENCES federated_providers (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+) STRICT;
+
+CREATE INDEX federated_identities_user_id_index ON federated_identities (user_id);
+
+CREATE TABLE federated_login_states (
+    state_hash BLOB PRIMARY KEY,
+    provider_id BLOB NOT NULL,
+    nonce TEXT NOT NULL,
+    code_verifier TEXT NOT NULL,
+    link_user_id BLOB,
+    next TEXT,
+    expires_at INTEGER NOT NULL,
+    FOREIGN KEY (provider_id) REFERENCES federated_providers (id) ON DELETE CASCADE,
+    FOREIGN KEY (link_user_id) REFERENCES users (id) ON DELETE CASCADE
+) STRICT;
@@ -0,0 +1,3 @@
+ALTER TABLE sessions ADD COLUMN authenticated_at INTEGER NOT NULL DEFAULT 0;
+
+UPDATE sessions SET authenticated_at = created_at;
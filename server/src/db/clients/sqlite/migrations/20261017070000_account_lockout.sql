@@ -0,0 +1,12 @@
+ALTER TABLE users ADD COLUMN locked_until INTEGER;
+
+CREATE TABLE login_failures (
+    id INTEGER PRIMARY KEY,
+    user_id BLOB NOT NULL,
+    passkey_id BLOB,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (passkey_id) REFERENCES passkeys (id) ON DELETE SET NULL
+) STRICT;
+
+CREATE INDEX login_failures_user_id_index ON login_failures (user_id, created_at);
@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN status INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,48 @@
+CREATE TABLE organizations (
+    id BLOB PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+) STRICT;
+
+CREATE UNIQUE INDEX organizations_name_index ON organizations (name);
+
+CREATE TABLE organization_members (
+    organization_id BLOB NOT NULL,
+    user_id BLOB NOT NULL,
+    role INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (organization_id, user_id),
+    FOREIGN KEY (organization_id) REFERENCES organizations (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+) STRICT;
+
+CREATE INDEX organization_members_user_id_index ON organization_members (user_id);
+
+-- Tag names are only unique within an organization, so the table must be rebuilt without the
+-- UNIQUE constraint. Dropping the old table deletes its users through the cascading foreign key,
+-- so they are saved and restored.
+CREATE TABLE tags_new (
+    id BLOB PRIMARY KEY,
+    name TEXT NOT NULL,
+    organization_id BLOB,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    FOREIGN KEY (organization_id) REFERENCES organizations (id) ON DELETE CASCADE
+) STRICT;
+
+INSERT INTO tags_new (id, name, organization_id, created_at, updated_at)
+SELECT id, name, NULL, created_at, updated_at FROM tags;
+
+CREATE TEMPORARY TABLE users_tags_saved AS SELECT user_id, tag_id FROM users_tags;
+DROP TABLE tags;
+ALTER TABLE tags_new RENAME TO tags;
+INSERT INTO users_tags (user_id, tag_id) SELECT user_id, tag_id FROM users_tags_saved;
+DROP TABLE users_tags_saved;
+
+CREATE UNIQUE INDEX tags_name_index ON tags (name) WHERE organization_id IS NULL;
+CREATE UNIQUE INDEX tags_organization_name_index ON tags (organization_id, name)
+WHERE organization_id IS NOT NULL;
+
+ALTER TABLE sessions ADD COLUMN organization_id BLOB
+REFERENCES organizations (id) ON DELETE SET NULL;
@@ -0,0 +1,40 @@
+CREATE TABLE user_groups (
+    id BLOB PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+) STRICT;
+
+CREATE UNIQUE INDEX user_groups_name_index ON user_groups (name);
+
+CREATE TABLE user_groups_users (
+    group_id BLOB NOT NULL,
+    user_id BLOB NOT NULL,
+    PRIMARY KEY (group_id, user_id),
+    FOREIGN KEY (group_id) REFERENCES user_groups (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+) STRICT;
+
+CREATE INDEX user_groups_users_user_id_index ON user_groups_users (user_id);
+
+-- Members of a subgroup are also members of its parent groups
+CREATE TABLE user_groups_subgroups (
+    parent_id BLOB NOT NULL,
+    child_id BLOB NOT NULL,
+    PRIMARY KEY (parent_id, child_id),
+    FOREIGN KEY (parent_id) REFERENCES user_groups (id) ON DELETE CASCADE,
+    FOREIGN KEY (child_id) REFERENCES user_groups (id) ON DELETE CASCADE,
+    CHECK (parent_id != child_id)
+) STRICT;
+
+CREATE INDEX user_groups_subgroups_child_id_index ON user_groups_subgroups (child_id);
+
+CREATE TABLE user_groups_tags (
+    group_id BLOB NOT NULL,
+    tag_id BLOB NOT NULL,
+    PRIMARY KEY (group_id, tag_id),
+    FOREIGN KEY (group_id) REFERENCES user_groups (id) ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE
+) STRICT;
+
+CREATE INDEX user_groups_tags_tag_id_index ON user_groups_tags (tag_id);
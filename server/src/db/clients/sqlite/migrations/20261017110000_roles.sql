@@ -0,0 +1,20 @@
+CREATE TABLE roles (
+    id BLOB PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- JSON array of permission names
+    permissions TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+) STRICT;
+
+CREATE UNIQUE INDEX roles_name_index ON roles (name);
+
+CREATE TABLE tags_roles (
+    tag_id BLOB NOT NULL,
+    role_id BLOB NOT NULL,
+    PRIMARY KEY (tag_id, role_id),
+    FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE,
+    FOREIGN KEY (role_id) REFERENCES roles (id) ON DELETE CASCADE
+) STRICT;
+
+CREATE INDEX tags_roles_role_id_index ON tags_roles (role_id);
@@ -0,0 +1,21 @@
+CREATE TABLE policies (
+    id BLOB PRIMARY KEY,
+    name TEXT NOT NULL,
+    enabled INTEGER NOT NULL,
+    version INTEGER NOT NULL,
+    document TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL
+) STRICT;
+
+CREATE UNIQUE INDEX policies_name_index ON policies (name);
+
+-- Every version of each policy's document, including the current one
+CREATE TABLE policy_versions (
+    policy_id BLOB NOT NULL,
+    version INTEGER NOT NULL,
+    document TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (policy_id, version),
+    FOREIGN KEY (policy_id) REFERENCES policies (id) ON DELETE CASCADE
+) STRICT;
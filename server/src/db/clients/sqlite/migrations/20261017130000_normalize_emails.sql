@@ -0,0 +1,15 @@
+-- Email addresses are now stored trimmed and lowercased. SQLite's lower() only folds ASCII
+-- letters, which covers nearly all addresses in practice.
+
+-- Pending authentications reference the address, so drop those of users whose address changes
+DELETE FROM passkey_authentications
+WHERE email IN (SELECT email FROM users WHERE email <> lower(trim(email)));
+
+-- Addresses which would collide with another user's are left alone, so that the duplicate
+-- accounts can be merged by an administrator
+UPDATE users SET email = lower(trim(email))
+WHERE email <> lower(trim(email))
+    AND NOT EXISTS (
+        SELECT 1 FROM users AS other
+        WHERE other.id <> users.id AND lower(trim(other.email)) = lower(trim(users.email))
+    );
@@ -0,0 +1,28 @@
+-- Full-text index of users' email addresses and display names, used by user search. Addresses are
+-- split into words at punctuation, so e.g. `alice.smith@example.com` matches `smith`.
+CREATE VIRTUAL TABLE users_fts USING fts5 (
+    email,
+    display_name,
+    content = 'users',
+    content_rowid = 'rowid',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+INSERT INTO users_fts (users_fts) VALUES ('rebuild');
+
+CREATE TRIGGER users_fts_insert AFTER INSERT ON users BEGIN
+    INSERT INTO users_fts (rowid, email, display_name)
+    VALUES (new.rowid, new.email, new.display_name);
+END;
+
+CREATE TRIGGER users_fts_delete AFTER DELETE ON users BEGIN
+    INSERT INTO users_fts (users_fts, rowid, email, display_name)
+    VALUES ('delete', old.rowid, old.email, old.display_name);
+END;
+
+CREATE TRIGGER users_fts_update AFTER UPDATE OF email, display_name ON users BEGIN
+    INSERT INTO users_fts (users_fts, rowid, email, display_name)
+    VALUES ('delete', old.rowid, old.email, old.display_name);
+    INSERT INTO users_fts (rowid, email, display_name)
+    VALUES (new.rowid, new.email, new.display_name);
+END;
@@ -0,0 +1,2 @@
+ALTER TABLE passkeys DROP COLUMN attestation_format;
+ALTER TABLE passkeys DROP COLUMN aaguid;
@@ -0,0 +1,7 @@
+ALTER TABLE passkeys ADD COLUMN aaguid BLOB;
+ALTER TABLE passkeys ADD COLUMN attestation_format TEXT NOT NULL DEFAULT 'none';
+
+-- Fill in the formats of existing passkeys from their stored credentials. Their AAGUIDs are left
+-- unknown, since attestation wasn't requested when they were registered.
+UPDATE passkeys
+SET attestation_format = coalesce(passkey ->> '$.cred.attestation_format', 'none');
@@ -0,0 +1,5 @@
+CREATE TABLE session_cookie_keys (
+    kid TEXT PRIMARY KEY,
+    key TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+) STRICT;
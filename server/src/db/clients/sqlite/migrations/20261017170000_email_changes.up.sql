@@ -0,0 +1,12 @@
+-- Time at which the user proved that they own their email address, if they have
+ALTER TABLE users ADD COLUMN email_verified_at INTEGER;
+
+-- Email address changes which wait for the user to confirm that they own the new address. A user
+-- has at most one pending change.
+CREATE TABLE email_changes (
+    token_hash BLOB PRIMARY KEY,
+    user_id BLOB NOT NULL UNIQUE,
+    email TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+) STRICT;
@@ -0,0 +1 @@
+ALTER TABLE passkey_registrations DROP COLUMN display_name;
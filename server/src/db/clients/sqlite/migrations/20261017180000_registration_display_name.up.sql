@@ -0,0 +1,2 @@
+-- New users are created from the details their registration was started with
+ALTER TABLE passkey_registrations ADD COLUMN display_name TEXT NOT NULL DEFAULT '';
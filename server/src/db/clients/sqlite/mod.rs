@@ -5,25 +5,45 @@
 //! A [`DatabaseClient`] which uses a SQLite3 database as the backend. Either memory-backed or
 //! file-backed databases can be used.
 
-use std::{env::VarError, pin::Pin, time::Duration};
+use std::{collections::HashMap, env::VarError, path::Path, time::Duration};
 
+use async_trait::async_trait;
 use sqlx::{
-    SqlitePool,
+    FromRow, QueryBuilder, Row, Sqlite, SqliteConnection, SqliteExecutor, SqlitePool,
     sqlite::{SqliteConnectOptions, SqliteSynchronous},
 };
-use tokio::task::{AbortHandle, JoinHandle};
-use tracing::error;
 use uuid::Uuid;
 
 use crate::{
-    db::interface::{DatabaseClient, DatabaseError},
+    db::interface::{BoxedTransaction, DatabaseClient, DatabaseError},
     models::{
-        EncodableHash, NewPasskeyCredential, PasskeyAuthenticationState, PasskeyCredential,
-        PasskeyCredentialUpdate, PasskeyRegistrationState, Session, SessionUpdate, Tag, TagUpdate,
-        User, UserCreate, UserUpdate,
+        Announcement, ApiToken, AuthorizationCode, Backup, EmailSuppression, EncodableHash,
+        FederatedIdentity, FederatedLoginState, FederatedProvider, FederatedProviderUpdate, Group,
+        GroupUpdate, IpBan, MaintenanceTask, NewPasskeyCredential, OAuthClient, OAuthClientUpdate,
+        Organization, OrganizationMember, OrganizationRole, OrganizationUpdate, PasskeyAttestation,
+        PasskeyAuthenticationState, PasskeyCredential, PasskeyCredentialUpdate,
+        PasskeyRegistrationState, Policy, PolicyUpdate, PolicyVersion, QueuedEmail,
+        QueuedEmailState, QueuedEmailUpdate, RetentionPolicy, RetentionReport, Role, RoleUpdate,
+        SamlServiceProvider, SamlServiceProviderUpdate, Session, SessionState, SessionUpdate,
+        SortDirection, StoredSessionCookieKey, StoredSigningKey, Tag, TagUpdate, User, UserCreate,
+        UserCursor, UserFilter, UserList, UserListQuery, UserMerge, UserNote, UserSort, UserStatus,
+        UserUpdate, ViaJson, Webhook, WebhookUpdate, normalize_email,
     },
 };
 
+mod backup;
+mod emails;
+mod migrate;
+mod transaction;
+mod update;
+
+pub use migrate::{MigrationError, MigrationStatus, PendingMigrations};
+pub use transaction::SqliteTransaction;
+use update::UpdateQuery;
+
+/// Maximum number of IDs bound in a single query, well below SQLite's limit on parameters
+const MAX_BOUND_IDS: usize = 1000;
+
 /// Represents errors that can occur when creating a new SQLite3 client, e.g. with
 /// [`SqliteClient::open()`] or [`SqliteClient::new_memory()`].
 #[derive(Debug, thiserror::Error)]
@@ -41,72 +61,91 @@ pub enum CreateSqliteClientError {
     #[error("failed to migrate database to current version: {0}")]
     Migration(#[from] sqlx::migrate::MigrateError),
 
+    /// The database was opened with [`PendingMigrations::Refuse`], but the given number of
+    /// migrations haven't been applied to it.
+    #[error("{0} database migrations are pending; apply them with `iam-server migrate up`")]
+    PendingMigrations(usize),
+
     /// Some other database error occurred. The [upstream error][sqlx::Error] is contained in the
     /// tuple field.
     #[error("database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
 }
 
+impl CreateSqliteClientError {
+    /// Returns whether opening the database may succeed if it is retried, e.g. because the
+    /// volume holding it wasn't mounted yet or another process held a lock on it.
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::DatabaseError(err) => matches!(
+                err,
+                sqlx::Error::Io(_)
+                    | sqlx::Error::Database(_)
+                    | sqlx::Error::PoolTimedOut
+                    | sqlx::Error::WorkerCrashed
+            ),
+            Self::MissingEnv(_)
+            | Self::EnvNotUtf8(_)
+            | Self::Migration(_)
+            | Self::PendingMigrations(_) => false,
+        }
+    }
+}
+
 /// # SQLite3 database backend
 ///
 /// See [the module-level documentation][crate::db::clients::sqlite] for details.
 #[derive(Debug, Clone)]
 pub struct SqliteClient {
     pool: SqlitePool,
-    cleanup_task_abort_handle: AbortHandle,
 }
 
 impl SqliteClient {
     /// Opens or creates the database at the path given by the `DB_PATH` environment variable.
     pub async fn open() -> Result<Self, CreateSqliteClientError> {
-        let pool = match std::env::var("DB_PATH") {
-            Ok(path) => {
-                Self::do_open(
-                    SqliteConnectOptions::new()
-                        .create_if_missing(true)
-                        .filename(&path),
-                )
-                .await?
-            }
-            Err(VarError::NotPresent) => {
-                return Err(CreateSqliteClientError::MissingEnv("DB_PATH"));
-            }
-            Err(VarError::NotUnicode(_)) => {
-                return Err(CreateSqliteClientError::EnvNotUtf8("DB_PATH"));
-            }
-        };
-        let cleanup_task = Self::spawn_cleanup_task(pool.clone());
-        Ok(Self {
-            pool,
-            cleanup_task_abort_handle: cleanup_task.abort_handle(),
-        })
+        match std::env::var("DB_PATH") {
+            Ok(path) => Self::open_path(Path::new(&path)).await,
+            Err(VarError::NotPresent) => Err(CreateSqliteClientError::MissingEnv("DB_PATH")),
+            Err(VarError::NotUnicode(_)) => Err(CreateSqliteClientError::EnvNotUtf8("DB_PATH")),
+        }
+    }
+
+    /// Opens or creates the database at the given path, applying any pending migrations.
+    pub async fn open_path(path: &Path) -> Result<Self, CreateSqliteClientError> {
+        Self::open_path_with(path, PendingMigrations::Apply).await
+    }
+
+    /// Opens or creates the database at the given path, handling pending migrations as given.
+    pub async fn open_path_with(
+        path: &Path,
+        pending: PendingMigrations,
+    ) -> Result<Self, CreateSqliteClientError> {
+        let pool = Self::do_open(
+            SqliteConnectOptions::new()
+                .create_if_missing(true)
+                .filename(path),
+            pending,
+        )
+        .await?;
+        Ok(Self { pool })
     }
 
     /// Creates a client that uses a new in-memory database.
     pub async fn new_memory() -> Result<Self, CreateSqliteClientError> {
         // sqlx has some special handling for the in-memory database which only
         // happens when parsing from a URL string
-        let pool = Self::do_open("sqlite://:memory:".parse().unwrap()).await?;
-        let cleanup_task = Self::spawn_cleanup_task(pool.clone());
-        Ok(Self {
-            pool,
-            cleanup_task_abort_handle: cleanup_task.abort_handle(),
-        })
-    }
-
-    /// Creates a task that runs in the background and cleans up expired passkey registrations and authentications every 5 minutes.
-    /// Returns the [`JoinHandle`] for the task.
-    fn spawn_cleanup_task(pool: SqlitePool) -> JoinHandle<()> {
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(Duration::from_secs(5 * 60)).await;
-                do_cleanup(&pool).await;
-            }
-        })
+        let pool = Self::do_open(
+            "sqlite://:memory:".parse().unwrap(),
+            PendingMigrations::Apply,
+        )
+        .await?;
+        Ok(Self { pool })
     }
 
     async fn do_open(
         base_options: SqliteConnectOptions,
+        pending: PendingMigrations,
     ) -> Result<SqlitePool, CreateSqliteClientError> {
         let options = base_options
             .synchronous(SqliteSynchronous::Normal)
@@ -114,9 +153,10 @@ impl SqliteClient {
             .pragma("foreign_keys", "ON");
         let pool = SqlitePool::connect_with(options).await?;
 
-        sqlx::migrate!("src/db/clients/sqlite/migrations")
-            .run(&pool)
-            .await?;
+        migrate::handle_pending(&pool, pending).await?;
+        if pending != PendingMigrations::Ignore {
+            emails::normalize_stored_emails(&pool).await?;
+        }
 
         Ok(pool)
     }
@@ -124,632 +164,2827 @@ impl SqliteClient {
 
 impl Drop for SqliteClient {
     fn drop(&mut self) {
-        self.cleanup_task_abort_handle.abort();
         _ = self.pool.close();
     }
 }
 
+#[async_trait]
 impl DatabaseClient for SqliteClient {
-    fn create_user<'user>(
-        &self,
-        id: &'user Uuid,
-        user: &'user UserCreate,
-    ) -> Pin<Box<dyn Future<Output = Result<User, DatabaseError>> + Send + 'user>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            Ok(sqlx::query_as::<_, User>(
-                "INSERT INTO users (id, email, display_name, created_at, updated_at)
-                VALUES ($1, $2, $3, unixepoch(), unixepoch())
-                RETURNING *",
-            )
-            .bind(id)
-            .bind(&user.email)
-            .bind(&user.display_name)
-            .fetch_one(&pool)
-            .await?)
-        })
+    async fn create_user(&self, id: &Uuid, user: &UserCreate) -> Result<User, DatabaseError> {
+        insert_user(&self.pool, id, user).await
     }
 
-    fn get_user_by_id<'id>(
-        &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<User, DatabaseError>> + Send + 'id>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let user: User = sqlx::query_as(
-                "SELECT id, email, display_name, created_at, updated_at FROM users WHERE id = $1",
-            )
+    async fn get_user_by_id(&self, id: &Uuid) -> Result<User, DatabaseError> {
+        let user: User = sqlx::query_as("SELECT * FROM users WHERE id = $1")
             .bind(id)
-            .fetch_one(&pool)
+            .fetch_one(&self.pool)
             .await?;
-            Ok(user)
-        })
+        Ok(user)
     }
 
-    fn get_user_by_email<'email>(
-        &self,
-        email: &'email str,
-    ) -> Pin<Box<dyn Future<Output = Result<User, DatabaseError>> + Send + 'email>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let user: User = sqlx::query_as(
-                "SELECT id, email, display_name, created_at, updated_at FROM users WHERE email = $1",
-            )
-            .bind(email)
-            .fetch_one(&pool)
+    async fn get_user_by_email(&self, email: &str) -> Result<User, DatabaseError> {
+        let user: User = sqlx::query_as("SELECT * FROM users WHERE email = $1")
+            .bind(normalize_email(email))
+            .fetch_one(&self.pool)
             .await?;
-            Ok(user)
-        })
+        Ok(user)
     }
 
-    fn update_user<'arg>(
-        &self,
-        id: &'arg Uuid,
-        update: &'arg UserUpdate,
-    ) -> Pin<Box<dyn Future<Output = Result<User, DatabaseError>> + Send + 'arg>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            if update.is_empty() {
-                return Err(DatabaseError::EmptyUpdate);
-            }
-
-            let mut query_parts = Vec::new();
-            let mut has_email = false;
-            let mut has_display_name = false;
-
-            if update.email.is_some() {
-                query_parts.push("email = ?");
-                has_email = true;
+    async fn get_user_with_relations(&self, id: &Uuid) -> Result<User, DatabaseError> {
+        // Users have few passkeys and tags, so joining both at once is cheaper than a query for
+        // each, even though each passkey is repeated for every tag
+        let rows = sqlx::query(
+            "SELECT u.*,
+                p.id AS passkey_id, p.passkey AS passkey_data,
+                p.display_name AS passkey_display_name, p.aaguid AS passkey_aaguid,
+                p.attestation_format AS passkey_attestation_format,
+                p.created_at AS passkey_created_at, p.last_used_at AS passkey_last_used_at,
+                t.id AS tag_id, t.name AS tag_name, t.organization_id AS tag_organization_id,
+                t.created_at AS tag_created_at, t.updated_at AS tag_updated_at
+            FROM users u
+            LEFT JOIN passkeys p ON p.user_id = u.id
+            LEFT JOIN users_tags ut ON ut.user_id = u.id
+            LEFT JOIN tags t ON t.id = ut.tag_id
+            WHERE u.id = $1
+            ORDER BY p.created_at, p.id, t.name",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        let Some(first) = rows.first() else {
+            return Err(DatabaseError::NotFound);
+        };
+        let mut user = User::from_row(first)?;
+        let mut passkeys: Vec<PasskeyCredential> = Vec::new();
+        let mut tags: Vec<Tag> = Vec::new();
+        for row in &rows {
+            if let Some(passkey_id) = row.try_get::<Option<Uuid>, _>("passkey_id")?
+                && !passkeys.iter().any(|passkey| passkey.id == passkey_id)
+            {
+                passkeys.push(PasskeyCredential {
+                    id: passkey_id,
+                    user_id: *id,
+                    display_name: row.try_get("passkey_display_name")?,
+                    passkey: row.try_get("passkey_data")?,
+                    aaguid: row.try_get("passkey_aaguid")?,
+                    attestation_format: row.try_get("passkey_attestation_format")?,
+                    created_at: row.try_get("passkey_created_at")?,
+                    last_used_at: row.try_get("passkey_last_used_at")?,
+                });
             }
-
-            if update.display_name.is_some() {
-                query_parts.push("display_name = ?");
-                has_display_name = true;
+            if let Some(tag_id) = row.try_get::<Option<Uuid>, _>("tag_id")?
+                && !tags.iter().any(|tag| tag.id == tag_id)
+            {
+                tags.push(Tag {
+                    id: tag_id,
+                    name: row.try_get("tag_name")?,
+                    organization_id: row.try_get("tag_organization_id")?,
+                    created_at: row.try_get("tag_created_at")?,
+                    updated_at: row.try_get("tag_updated_at")?,
+                    users: None,
+                });
             }
+        }
+        user.set_passkeys(passkeys);
+        user.set_tags(tags);
+        Ok(user)
+    }
 
-            // Always update the updated_at timestamp using SQLite's unixepoch function
-            query_parts.push("updated_at = unixepoch()");
-
-            let query = format!(
-                "UPDATE users SET {} WHERE id = ? RETURNING id, email, display_name, created_at, updated_at",
-                query_parts.join(", ")
-            );
-
-            let mut sql_query = sqlx::query_as::<_, User>(&query);
-
-            // Bind parameters in order
-            if has_email {
-                sql_query = sql_query.bind(update.email.as_ref().unwrap());
-            }
-            if has_display_name {
-                sql_query = sql_query.bind(update.display_name.as_ref().unwrap());
-            }
-            sql_query = sql_query.bind(id);
+    async fn update_user(&self, id: &Uuid, update: &UserUpdate) -> Result<User, DatabaseError> {
+        if update.is_empty() {
+            return Err(DatabaseError::EmptyUpdate);
+        }
+        let mut query = UpdateQuery::new("users");
+        query
+            .set("email", update.email.as_deref().map(normalize_email))
+            .set("display_name", update.display_name.as_deref())
+            .set("login_notifications", update.login_notifications)
+            .set_expr("updated_at", "unixepoch()");
+        if update.email.is_some() {
+            query.set_expr("email_verified_at", "NULL");
+        }
+        let user: User = query
+            .finish("id", id, "*")?
+            .build_query_as()
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(user)
+    }
 
-            let user = sql_query.fetch_one(&pool).await?;
-            Ok(user)
-        })
+    async fn create_email_change(
+        &self,
+        user_id: &Uuid,
+        email: &str,
+        token_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query(
+            "INSERT INTO email_changes (token_hash, user_id, email, created_at)
+            VALUES ($1, $2, $3, unixepoch())
+            ON CONFLICT (user_id) DO UPDATE SET
+                token_hash = excluded.token_hash,
+                email = excluded.email,
+                created_at = excluded.created_at",
+        )
+        .bind(token_hash)
+        .bind(user_id)
+        .bind(normalize_email(email))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
     }
 
-    fn delete_user_by_id<'id>(
+    async fn confirm_email_change(
         &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'id>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            sqlx::query("DELETE FROM users WHERE id = $1")
-                .bind(id)
-                .execute(&pool)
-                .await?;
-            Ok(())
-        })
+        token_hash: &EncodableHash,
+        max_age: Duration,
+    ) -> Result<User, DatabaseError> {
+        let max_age = i64::try_from(max_age.as_secs()).unwrap_or(i64::MAX);
+        let mut tx = self.pool.begin().await?;
+        // Expired changes are consumed too, since they can't be confirmed anymore
+        let (user_id, email, created_at): (Uuid, String, i64) = sqlx::query_as(
+            "DELETE FROM email_changes WHERE token_hash = $1
+            RETURNING user_id, email, created_at",
+        )
+        .bind(token_hash)
+        .fetch_one(&mut *tx)
+        .await?;
+        if created_at < chrono::Utc::now().timestamp().saturating_sub(max_age) {
+            tx.commit().await?;
+            return Err(DatabaseError::NotFound);
+        }
+        let user = sqlx::query_as(
+            "UPDATE users SET
+                email = $1,
+                email_verified_at = unixepoch(),
+                updated_at = unixepoch()
+            WHERE id = $2
+            RETURNING *",
+        )
+        .bind(email)
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(user)
+    }
+
+    async fn record_user_login(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = sqlx::query("UPDATE users SET last_login_at = unixepoch() WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
     }
 
-    fn add_tag_to_user<'arg>(
+    async fn record_login_failure(
         &self,
-        user_id: &'arg Uuid,
-        tag: &'arg Tag,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'arg>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            sqlx::query("INSERT INTO users_tags (user_id, tag_id) VALUES ($1, $2)")
+        user_id: &Uuid,
+        passkey_id: Option<&Uuid>,
+        window: Duration,
+    ) -> Result<u64, DatabaseError> {
+        let window = i64::try_from(window.as_secs()).unwrap_or(i64::MAX);
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "DELETE FROM login_failures WHERE user_id = $1 AND created_at < unixepoch() - $2",
+        )
+        .bind(user_id)
+        .bind(window)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO login_failures (user_id, passkey_id, created_at)
+            VALUES ($1, $2, unixepoch())",
+        )
+        .bind(user_id)
+        .bind(passkey_id)
+        .execute(&mut *tx)
+        .await?;
+        let count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM login_failures WHERE user_id = $1")
                 .bind(user_id)
-                .bind(tag.id)
-                .execute(&pool)
+                .fetch_one(&mut *tx)
                 .await?;
-            Ok(())
-        })
+        tx.commit().await?;
+        Ok(count.try_into().unwrap_or_default())
+    }
+
+    async fn clear_login_failures(&self, user_id: &Uuid) -> Result<(), DatabaseError> {
+        sqlx::query("DELETE FROM login_failures WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
     }
 
-    fn remove_tag_from_user<'arg>(
+    async fn set_user_locked_until(
         &self,
-        user_id: &'arg Uuid,
-        tag: &'arg Tag,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'arg>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            sqlx::query("DELETE FROM users_tags WHERE user_id = $1 AND tag_id = $2")
-                .bind(user_id)
-                .bind(tag.id)
-                .execute(&pool)
-                .await?;
-            Ok(())
-        })
+        id: &Uuid,
+        until: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query("UPDATE users SET locked_until = $1 WHERE id = $2")
+            .bind(until.map(|time| time.timestamp()))
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        sqlx::query("DELETE FROM login_failures WHERE user_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn set_user_status(&self, id: &Uuid, status: UserStatus) -> Result<User, DatabaseError> {
+        let user: User = sqlx::query_as(
+            "UPDATE users SET status = $1, updated_at = unixepoch() WHERE id = $2 RETURNING *",
+        )
+        .bind(status)
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(DatabaseError::NotFound)?;
+        Ok(user)
     }
 
-    fn get_users_by_tag_id<'id>(
+    async fn merge_users(
         &self,
-        tag_id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<Vec<User>, DatabaseError>> + Send + 'id>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let users: Vec<User> = sqlx::query_as(
-                "SELECT u.id, u.email, u.display_name, u.created_at, u.updated_at
-                 FROM users u
-                 INNER JOIN users_tags ut
-                 ON u.id = ut.user_id
-                 WHERE ut.tag_id = $1",
+        source_id: &Uuid,
+        target_id: &Uuid,
+        dry_run: bool,
+    ) -> Result<UserMerge, DatabaseError> {
+        // A dry run performs the merge in a transaction which is rolled back, so it reports
+        // exactly what a real merge would do.
+        let mut tx = self.pool.begin().await?;
+        let source: User = sqlx::query_as("SELECT * FROM users WHERE id = $1")
+            .bind(source_id)
+            .fetch_one(&mut *tx)
+            .await?;
+        sqlx::query("SELECT 1 FROM users WHERE id = $1")
+            .bind(target_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let passkeys = sqlx::query("UPDATE passkeys SET user_id = $2 WHERE user_id = $1")
+            .bind(source_id)
+            .bind(target_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        let tags = sqlx::query(
+            "INSERT INTO users_tags (user_id, tag_id)
+            SELECT DISTINCT $2, tag_id FROM users_tags
+            WHERE user_id = $1
+                AND tag_id NOT IN (SELECT tag_id FROM users_tags WHERE user_id = $2)",
+        )
+        .bind(source_id)
+        .bind(target_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        copy_memberships(&mut tx, source_id, target_id).await?;
+        let sessions = sqlx::query("UPDATE sessions SET user_id = $2 WHERE user_id = $1")
+            .bind(source_id)
+            .bind(target_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        // `WHERE true` is needed to disambiguate the upsert clause from a join condition
+        let devices = sqlx::query(
+            "INSERT INTO user_devices (user_id, id_hash, first_seen_at, last_seen_at)
+            SELECT $2, id_hash, first_seen_at, last_seen_at FROM user_devices
+            WHERE user_id = $1 AND true
+            ON CONFLICT (user_id, id_hash) DO UPDATE SET
+                first_seen_at = min(first_seen_at, excluded.first_seen_at),
+                last_seen_at = max(last_seen_at, excluded.last_seen_at)",
+        )
+        .bind(source_id)
+        .bind(target_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        let notes = sqlx::query("UPDATE user_notes SET user_id = $2 WHERE user_id = $1")
+            .bind(source_id)
+            .bind(target_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        sqlx::query("UPDATE user_notes SET author_id = $2 WHERE author_id = $1")
+            .bind(source_id)
+            .bind(target_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE federated_identities SET user_id = $2 WHERE user_id = $1")
+            .bind(source_id)
+            .bind(target_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "UPDATE users SET last_login_at = (
+                SELECT max(last_login_at) FROM users WHERE id IN ($1, $2)
             )
-            .bind(tag_id)
-            .fetch_all(&pool)
+            WHERE id = $2",
+        )
+        .bind(source_id)
+        .bind(target_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(source_id)
+            .execute(&mut *tx)
             .await?;
-            Ok(users)
+
+        if dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+        }
+        Ok(UserMerge {
+            source,
+            target_id: *target_id,
+            dry_run,
+            passkeys,
+            tags,
+            sessions,
+            devices,
+            notes,
         })
     }
 
-    fn create_tag<'tag>(
-        &self,
-        id: &'tag Uuid,
-        tag: &'tag TagUpdate,
-    ) -> Pin<Box<dyn Future<Output = Result<Tag, DatabaseError>> + Send + 'tag>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            Ok(sqlx::query_as::<_, Tag>(
-                "INSERT INTO tags (id, name, created_at, updated_at)
-            VALUES ($1, $2, unixepoch(), unixepoch())
-            RETURNING id, name, created_at, updated_at",
-            )
+    async fn anonymize_user(&self, id: &Uuid) -> Result<User, DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        let user: User = sqlx::query_as("SELECT * FROM users WHERE id = $1")
             .bind(id)
-            .bind(&tag.name)
-            .fetch_one(&pool)
-            .await?)
-        })
+            .fetch_one(&mut *tx)
+            .await?;
+        // Pending authentications reference the email address, so must be deleted before it
+        // is changed
+        sqlx::query("DELETE FROM passkey_authentications WHERE email = $1")
+            .bind(user.email())
+            .execute(&mut *tx)
+            .await?;
+        // Queued emails contain personal data such as the IP addresses of logins
+        sqlx::query("DELETE FROM email_queue WHERE recipient = $1")
+            .bind(user.email())
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM email_suppressions WHERE address = $1")
+            .bind(user.email())
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM passkeys WHERE user_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM user_devices WHERE user_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM api_tokens WHERE user_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM federated_identities WHERE user_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM email_changes WHERE user_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE sessions SET state = $2 WHERE user_id = $1 AND state = $3")
+            .bind(id)
+            .bind(SessionState::Revoked)
+            .bind(SessionState::Active)
+            .execute(&mut *tx)
+            .await?;
+        // Sessions are kept for statistics, but not where they were used from
+        sqlx::query("UPDATE sessions SET ip = NULL, user_agent = NULL WHERE user_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("UPDATE user_notes SET body = '[redacted]' WHERE user_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        // The placeholder address is derived from the ID so it stays unique
+        let user = sqlx::query_as(
+            "UPDATE users SET
+                email = 'anonymized-' || lower(hex(id)) || '@invalid',
+                display_name = 'Anonymized user',
+                email_verified_at = NULL,
+                login_notifications = 0,
+                updated_at = unixepoch(),
+                anonymized_at = coalesce(anonymized_at, unixepoch())
+            WHERE id = $1
+            RETURNING *",
+        )
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(user)
     }
 
-    fn get_tag_by_id<'id>(
+    async fn list_users(
         &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<Tag, DatabaseError>> + Send + 'id>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let tag: Tag =
-                sqlx::query_as("SELECT id, name, created_at, updated_at FROM tags WHERE id = $1")
-                    .bind(id)
-                    .fetch_one(&pool)
-                    .await?;
-            Ok(tag)
+        query: &UserListQuery,
+        after: Option<&UserCursor>,
+        limit: u32,
+    ) -> Result<UserList, DatabaseError> {
+        let column = match query.sort.field {
+            UserSort::CreatedAt => "created_at",
+            UserSort::Email => "email",
+            UserSort::DisplayName => "display_name",
+        };
+        let (direction, comparison) = match query.sort.direction {
+            SortDirection::Asc => ("ASC", ">"),
+            SortDirection::Desc => ("DESC", "<"),
+        };
+        let mut builder = QueryBuilder::new("SELECT * FROM users WHERE TRUE");
+        push_user_filter(&mut builder, &query.filter);
+        if let Some(after) = after {
+            builder.push(format_args!(" AND ({column}, id) {comparison} ("));
+            match after {
+                UserCursor::CreatedAt(time, id) => {
+                    builder.push_bind(time.timestamp()).push(", ").push_bind(id)
+                }
+                UserCursor::Email(value, id) | UserCursor::DisplayName(value, id) => {
+                    builder.push_bind(value).push(", ").push_bind(id)
+                }
+            };
+            builder.push(")");
+        }
+        // Fetch one extra user to find out whether there's another page
+        builder
+            .push(format_args!(
+                " ORDER BY {column} {direction}, id {direction} LIMIT "
+            ))
+            .push_bind(i64::from(limit) + 1);
+        let mut users: Vec<User> = builder.build_query_as().fetch_all(&self.pool).await?;
+        let next = if users.len() > limit as usize {
+            users.truncate(limit as usize);
+            users
+                .last()
+                .map(|user| UserCursor::at(user, query.sort.field))
+        } else {
+            None
+        };
+        let mut builder = QueryBuilder::new("SELECT COUNT(*) FROM users WHERE TRUE");
+        push_user_filter(&mut builder, &query.filter);
+        let total: i64 = builder.build_query_scalar().fetch_one(&self.pool).await?;
+        Ok(UserList {
+            users,
+            total: total.try_into().unwrap_or_default(),
+            next,
         })
     }
 
-    fn get_tag_by_name<'name>(
-        &self,
-        name: &'name str,
-    ) -> Pin<Box<dyn Future<Output = Result<Tag, DatabaseError>> + Send + 'name>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let tag: Tag =
-                sqlx::query_as("SELECT id, name, created_at, updated_at FROM tags WHERE name = $1")
-                    .bind(name)
-                    .fetch_one(&pool)
-                    .await?;
-            Ok(tag)
-        })
+    async fn search_users(&self, query: &str, limit: u32) -> Result<Vec<User>, DatabaseError> {
+        let Some(query) = fts_prefix_query(query) else {
+            return Ok(Vec::new());
+        };
+        let users: Vec<User> = sqlx::query_as(
+            "SELECT users.* FROM users_fts
+            INNER JOIN users ON users.rowid = users_fts.rowid
+            WHERE users_fts MATCH $1
+            ORDER BY rank
+            LIMIT $2",
+        )
+        .bind(query)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(users)
+    }
+
+    async fn delete_user_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        // Unlink the user's sessions from each other so they can be deleted in any order
+        sqlx::query("UPDATE sessions SET parent_id_hash = NULL WHERE user_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn add_tag_to_user(&self, user_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        insert_user_tag(&self.pool, user_id, tag).await
+    }
+
+    async fn remove_tag_from_user(&self, user_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        sqlx::query("DELETE FROM users_tags WHERE user_id = $1 AND tag_id = $2")
+            .bind(user_id)
+            .bind(tag.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_users_by_tag_id(&self, tag_id: &Uuid) -> Result<Vec<User>, DatabaseError> {
+        let users: Vec<User> = sqlx::query_as(
+            "SELECT u.*
+             FROM users u
+             INNER JOIN users_tags ut
+             ON u.id = ut.user_id
+             WHERE ut.tag_id = $1",
+        )
+        .bind(tag_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(users)
+    }
+
+    async fn create_user_note(&self, note: &UserNote) -> Result<UserNote, DatabaseError> {
+        let note: UserNote = sqlx::query_as(
+            "INSERT INTO user_notes (id, user_id, author_id, body, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING *",
+        )
+        .bind(note.id)
+        .bind(note.user_id)
+        .bind(note.author_id)
+        .bind(&note.body)
+        .bind(note.created_at.timestamp())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(note)
+    }
+
+    async fn get_user_notes(&self, user_id: &Uuid) -> Result<Vec<UserNote>, DatabaseError> {
+        let notes: Vec<UserNote> = sqlx::query_as(
+            "SELECT * FROM user_notes WHERE user_id = $1 ORDER BY created_at DESC, rowid DESC",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(notes)
     }
 
-    fn update_tag<'arg>(
+    async fn create_tag(
         &self,
-        id: &'arg Uuid,
-        update: &'arg TagUpdate,
-    ) -> Pin<Box<dyn Future<Output = Result<Tag, DatabaseError>> + Send + 'arg>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            if update.is_empty() {
-                return Err(DatabaseError::EmptyUpdate);
-            }
+        id: &Uuid,
+        organization_id: Option<&Uuid>,
+        tag: &TagUpdate,
+    ) -> Result<Tag, DatabaseError> {
+        Ok(sqlx::query_as::<_, Tag>(
+            "INSERT INTO tags (id, name, organization_id, created_at, updated_at)
+        VALUES ($1, $2, $3, unixepoch(), unixepoch())
+        RETURNING id, name, organization_id, created_at, updated_at",
+        )
+        .bind(id)
+        .bind(&tag.name)
+        .bind(organization_id)
+        .fetch_one(&self.pool)
+        .await?)
+    }
 
-            let mut query_parts = Vec::new();
-            let mut has_name = false;
+    async fn get_tag_by_id(&self, id: &Uuid) -> Result<Tag, DatabaseError> {
+        let tag: Tag = sqlx::query_as(
+            "SELECT id, name, organization_id, created_at, updated_at FROM tags WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(tag)
+    }
 
-            if update.name.is_some() {
-                query_parts.push("name = ?");
-                has_name = true;
-            }
+    async fn get_tag_by_name(&self, name: &str) -> Result<Tag, DatabaseError> {
+        let tag: Tag = sqlx::query_as(
+            "SELECT id, name, organization_id, created_at, updated_at FROM tags
+                WHERE name = $1 AND organization_id IS NULL",
+        )
+        .bind(name)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(tag)
+    }
 
-            // Always update the updated_at timestamp using SQLite's unixepoch function
-            query_parts.push("updated_at = unixepoch()");
+    async fn list_tags(&self) -> Result<Vec<Tag>, DatabaseError> {
+        let tags: Vec<Tag> = sqlx::query_as(
+            "SELECT id, name, organization_id, created_at, updated_at FROM tags
+                ORDER BY name, id",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tags)
+    }
 
-            let query = format!(
-                "UPDATE tags SET {} WHERE id = $1 RETURNING id, name, created_at, updated_at",
-                query_parts.join(", ")
-            );
+    async fn update_tag(&self, id: &Uuid, update: &TagUpdate) -> Result<Tag, DatabaseError> {
+        if update.is_empty() {
+            return Err(DatabaseError::EmptyUpdate);
+        }
+        let mut query = UpdateQuery::new("tags");
+        query
+            .set("name", update.name.as_deref())
+            .set_expr("updated_at", "unixepoch()");
+        let tag: Tag = query
+            .finish(
+                "id",
+                id,
+                "id, name, organization_id, created_at, updated_at",
+            )?
+            .build_query_as()
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(tag)
+    }
+
+    async fn delete_tag_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM tags WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
 
-            let mut sql_query = sqlx::query_as::<_, Tag>(&query);
+    async fn get_tags_by_user_id(&self, user_id: &Uuid) -> Result<Vec<Tag>, DatabaseError> {
+        let tags: Vec<Tag> = sqlx::query_as(
+            "SELECT t.id, t.name, t.organization_id, t.created_at, t.updated_at
+             FROM tags t
+             INNER JOIN users_tags ut
+             ON t.id = ut.tag_id
+             WHERE ut.user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tags)
+    }
 
-            // Bind parameters in order
-            if has_name {
-                sql_query = sql_query.bind(update.name.as_ref().unwrap());
+    async fn get_tags_by_user_ids(
+        &self,
+        user_ids: &[Uuid],
+    ) -> Result<HashMap<Uuid, Vec<Tag>>, DatabaseError> {
+        let mut tags: HashMap<Uuid, Vec<Tag>> =
+            user_ids.iter().map(|id| (*id, Vec::new())).collect();
+        for chunk in user_ids.chunks(MAX_BOUND_IDS) {
+            let mut builder = QueryBuilder::new(
+                "SELECT ut.user_id, t.id, t.name, t.organization_id, t.created_at, t.updated_at
+                FROM tags t
+                INNER JOIN users_tags ut
+                ON t.id = ut.tag_id
+                WHERE ut.user_id IN (",
+            );
+            let mut ids = builder.separated(", ");
+            for id in chunk {
+                ids.push_bind(id);
             }
-            sql_query = sql_query.bind(id);
+            builder.push(") ORDER BY t.name");
+            for row in builder.build().fetch_all(&self.pool).await? {
+                let user_id: Uuid = row.try_get("user_id")?;
+                tags.entry(user_id).or_default().push(Tag::from_row(&row)?);
+            }
+        }
+        Ok(tags)
+    }
 
-            let tag = sql_query.fetch_one(&pool).await?;
-            Ok(tag)
-        })
+    async fn get_tags_by_organization_id(
+        &self,
+        organization_id: &Uuid,
+    ) -> Result<Vec<Tag>, DatabaseError> {
+        let tags: Vec<Tag> = sqlx::query_as(
+            "SELECT id, name, organization_id, created_at, updated_at FROM tags
+            WHERE organization_id = $1
+            ORDER BY name",
+        )
+        .bind(organization_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tags)
     }
 
-    fn delete_tag_by_id<'id>(
+    async fn create_organization(
         &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'id>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            sqlx::query("DELETE FROM tags WHERE id = $1")
+        id: &Uuid,
+        organization: &OrganizationUpdate,
+    ) -> Result<Organization, DatabaseError> {
+        let organization: Organization = sqlx::query_as(
+            "INSERT INTO organizations (id, name, created_at, updated_at)
+            VALUES ($1, $2, unixepoch(), unixepoch())
+            RETURNING *",
+        )
+        .bind(id)
+        .bind(&organization.name)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(organization)
+    }
+
+    async fn get_organization_by_id(&self, id: &Uuid) -> Result<Organization, DatabaseError> {
+        let organization: Organization =
+            sqlx::query_as("SELECT * FROM organizations WHERE id = $1")
                 .bind(id)
-                .execute(&pool)
+                .fetch_one(&self.pool)
                 .await?;
-            Ok(())
-        })
+        Ok(organization)
     }
 
-    fn get_tags_by_user_id<'id>(
+    async fn list_organizations(&self) -> Result<Vec<Organization>, DatabaseError> {
+        let organizations: Vec<Organization> =
+            sqlx::query_as("SELECT * FROM organizations ORDER BY name")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(organizations)
+    }
+
+    async fn update_organization(
         &self,
-        user_id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<Vec<Tag>, DatabaseError>> + Send + 'id>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let tags: Vec<Tag> = sqlx::query_as(
-                "SELECT t.id, t.name, t.created_at, t.updated_at
-                 FROM tags t
-                 INNER JOIN users_tags ut
-                 ON t.id = ut.tag_id
-                 WHERE ut.user_id = $1",
-            )
-            .bind(user_id)
-            .fetch_all(&pool)
+        id: &Uuid,
+        update: &OrganizationUpdate,
+    ) -> Result<Organization, DatabaseError> {
+        if update.is_empty() {
+            return Err(DatabaseError::EmptyUpdate);
+        }
+        let mut query = UpdateQuery::new("organizations");
+        query
+            .set("name", update.name.as_deref())
+            .set_expr("updated_at", "unixepoch()");
+        let organization: Organization = query
+            .finish("id", id, "*")?
+            .build_query_as()
+            .fetch_one(&self.pool)
             .await?;
-            Ok(tags)
-        })
+        Ok(organization)
     }
 
-    fn create_passkey<'a>(
-        &self,
-        id: &'a Uuid,
-        user_id: &'a Uuid,
-        passkey: &'a NewPasskeyCredential,
-    ) -> Pin<Box<dyn Future<Output = Result<PasskeyCredential, DatabaseError>> + Send + 'a>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let passkey: PasskeyCredential = sqlx::query_as(
-                "INSERT INTO passkeys (id, user_id, passkey, credential_id, display_name, created_at, last_used_at)
-                 VALUES ($1, $2, $3, $4, $5, unixepoch(), unixepoch())
-                 RETURNING *",
-            )
+    async fn delete_organization_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM organizations WHERE id = $1")
             .bind(id)
-            .bind(user_id)
-            .bind(sqlx::types::Json(&passkey.passkey))
-            .bind(passkey.passkey.cred_id().as_ref())
-            .bind(&passkey.display_name)
-            .fetch_one(&pool)
+            .execute(&self.pool)
             .await?;
-            Ok(passkey)
-        })
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
     }
 
-    fn get_passkey_by_id<'id>(
+    async fn get_organizations_by_user_id(
         &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<PasskeyCredential, DatabaseError>> + Send + 'id>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let passkey: PasskeyCredential = sqlx::query_as(
-                "SELECT id, user_id, passkey, display_name, created_at, last_used_at
-                 FROM passkeys WHERE id = $1",
-            )
-            .bind(id)
-            .fetch_one(&pool)
-            .await?;
-            Ok(passkey)
-        })
+        user_id: &Uuid,
+    ) -> Result<Vec<Organization>, DatabaseError> {
+        let organizations: Vec<Organization> = sqlx::query_as(
+            "SELECT o.*
+            FROM organizations o
+            INNER JOIN organization_members m
+            ON o.id = m.organization_id
+            WHERE m.user_id = $1
+            ORDER BY o.name",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(organizations)
     }
 
-    fn get_passkey_by_credential_id<'id>(
+    async fn get_organization_members(
         &self,
-        credential_id: &'id [u8],
-    ) -> Pin<Box<dyn Future<Output = Result<PasskeyCredential, DatabaseError>> + Send + 'id>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let passkey: PasskeyCredential = sqlx::query_as(
-                "SELECT id, user_id, passkey, display_name, created_at, last_used_at
-                 FROM passkeys WHERE credential_id = $1",
-            )
-            .bind(credential_id)
-            .fetch_one(&pool)
-            .await?;
-            Ok(passkey)
-        })
+        organization_id: &Uuid,
+    ) -> Result<Vec<OrganizationMember>, DatabaseError> {
+        let members: Vec<OrganizationMember> = sqlx::query_as(
+            "SELECT u.*, m.organization_id, m.role, m.created_at AS joined_at
+            FROM users u
+            INNER JOIN organization_members m
+            ON u.id = m.user_id
+            WHERE m.organization_id = $1
+            ORDER BY u.email",
+        )
+        .bind(organization_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(members)
     }
 
-    fn get_passkeys_by_user_id<'id>(
+    async fn get_organization_member(
         &self,
-        user_id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<Vec<PasskeyCredential>, DatabaseError>> + Send + 'id>>
-    {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let passkeys: Vec<PasskeyCredential> = sqlx::query_as(
-                "SELECT id, user_id, passkey, display_name, created_at, last_used_at
-                 FROM passkeys WHERE user_id = $1",
-            )
-            .bind(user_id)
-            .fetch_all(&pool)
-            .await?;
-            Ok(passkeys)
-        })
+        organization_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<OrganizationMember, DatabaseError> {
+        fetch_organization_member(&self.pool, organization_id, user_id).await
     }
 
-    fn get_passkeys_by_user_email<'email>(
+    async fn set_organization_member(
         &self,
-        email: &'email str,
-    ) -> Pin<Box<dyn Future<Output = Result<Vec<PasskeyCredential>, DatabaseError>> + Send + 'email>>
-    {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let passkeys: Vec<PasskeyCredential> = sqlx::query_as(
-                "SELECT p.id, p.user_id, p.passkey, p.display_name, p.created_at, p.last_used_at
-                FROM passkeys p
-                INNER JOIN users ON p.user_id = users.id
-                WHERE users.email = $1",
-            )
-            .bind(email)
-            .fetch_all(&pool)
-            .await?;
-            Ok(passkeys)
-        })
+        organization_id: &Uuid,
+        user_id: &Uuid,
+        role: OrganizationRole,
+    ) -> Result<OrganizationMember, DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO organization_members (organization_id, user_id, role, created_at)
+            VALUES ($1, $2, $3, unixepoch())
+            ON CONFLICT (organization_id, user_id) DO UPDATE SET role = excluded.role",
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .bind(role)
+        .execute(&mut *tx)
+        .await?;
+        let member = fetch_organization_member(&mut *tx, organization_id, user_id).await?;
+        tx.commit().await?;
+        Ok(member)
     }
 
-    fn update_passkey<'key>(
+    async fn remove_organization_member(
         &self,
-        id: &'key Uuid,
-        passkey: &'key PasskeyCredentialUpdate,
-    ) -> Pin<Box<dyn Future<Output = Result<PasskeyCredential, DatabaseError>> + Send + 'key>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            if passkey.is_empty() {
-                return Err(DatabaseError::EmptyUpdate);
-            }
+        organization_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        let result = sqlx::query(
+            "DELETE FROM organization_members WHERE organization_id = $1 AND user_id = $2",
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        sqlx::query(
+            "DELETE FROM users_tags
+            WHERE user_id = $2
+                AND tag_id IN (SELECT id FROM tags WHERE organization_id = $1)",
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "UPDATE sessions SET organization_id = NULL
+            WHERE organization_id = $1 AND user_id = $2",
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
 
-            let mut query_parts = Vec::new();
-            let mut has_display_name = false;
-            let mut has_passkey = false;
-            if passkey.display_name.is_some() {
-                query_parts.push("display_name = ?");
-                has_display_name = true;
-            }
-            if passkey.passkey.is_some() {
-                query_parts.push("passkey = ?");
-                has_passkey = true;
-            }
+    async fn create_group(&self, id: &Uuid, group: &GroupUpdate) -> Result<Group, DatabaseError> {
+        let group: Group = sqlx::query_as(
+            "INSERT INTO user_groups (id, name, created_at, updated_at)
+            VALUES ($1, $2, unixepoch(), unixepoch())
+            RETURNING *",
+        )
+        .bind(id)
+        .bind(&group.name)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(group)
+    }
 
-            let query_str = format!(
-                "UPDATE passkeys SET {}
-                WHERE id = ?
-                RETURNING id, user_id, passkey, display_name, created_at, last_used_at",
-                query_parts.join(", ")
-            );
-            let mut query = sqlx::query_as::<_, PasskeyCredential>(&query_str);
-            if has_display_name {
-                query = query.bind(passkey.display_name.as_ref().unwrap().as_deref());
-            }
-            if has_passkey {
-                query = query.bind(passkey.passkey.as_ref().unwrap());
-            }
-            query = query.bind(id);
+    async fn get_group_by_id(&self, id: &Uuid) -> Result<Group, DatabaseError> {
+        let group: Group = sqlx::query_as("SELECT * FROM user_groups WHERE id = $1")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(group)
+    }
 
-            let passkey: PasskeyCredential = query.fetch_one(&pool).await?;
-            Ok(passkey)
-        })
+    async fn list_groups(&self) -> Result<Vec<Group>, DatabaseError> {
+        let groups: Vec<Group> = sqlx::query_as("SELECT * FROM user_groups ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(groups)
     }
 
-    fn delete_passkey_by_id<'id>(
-        &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'id>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            sqlx::query("DELETE FROM passkeys WHERE id = $1")
-                .bind(id)
-                .execute(&pool)
-                .await?;
-            Ok(())
-        })
+    async fn update_group(&self, id: &Uuid, update: &GroupUpdate) -> Result<Group, DatabaseError> {
+        if update.is_empty() {
+            return Err(DatabaseError::EmptyUpdate);
+        }
+        let mut query = UpdateQuery::new("user_groups");
+        query
+            .set("name", update.name.as_deref())
+            .set_expr("updated_at", "unixepoch()");
+        let group: Group = query
+            .finish("id", id, "*")?
+            .build_query_as()
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(group)
     }
 
-    fn create_passkey_registration<'a>(
-        &self,
-        registration: &'a PasskeyRegistrationState,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'a>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            sqlx::query(
-                "INSERT INTO passkey_registrations (id, user_id, email, registration, created_at)
-                VALUES ($1, $2, $3, $4, $5)",
-            )
-            .bind(registration.id)
-            .bind(registration.user_id)
-            .bind(&registration.email)
-            .bind(&registration.registration)
-            .bind(registration.created_at.timestamp())
-            .execute(&pool)
-            .await?;
-            Ok(())
-        })
+    async fn delete_group_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM user_groups WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
     }
 
-    fn get_passkey_registration_by_id<'id>(
+    async fn get_users_by_group_id(
         &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<PasskeyRegistrationState, DatabaseError>> + Send + 'id>>
-    {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let registration: PasskeyRegistrationState =
-                sqlx::query_as("SELECT * FROM passkey_registrations WHERE id = $1")
-                    .bind(id)
-                    .fetch_one(&pool)
-                    .await?;
-            Ok(registration)
-        })
+        group_id: &Uuid,
+        include_subgroups: bool,
+    ) -> Result<Vec<User>, DatabaseError> {
+        // UNION rather than UNION ALL stops the recursion even if the groups form a cycle
+        let users: Vec<User> = sqlx::query_as(
+            "WITH RECURSIVE member_groups (id) AS (
+                SELECT $1
+                UNION
+                SELECT s.child_id
+                FROM user_groups_subgroups s
+                INNER JOIN member_groups g
+                ON s.parent_id = g.id
+                WHERE $2
+            )
+            SELECT u.*
+            FROM users u
+            WHERE u.id IN (
+                SELECT user_id FROM user_groups_users
+                WHERE group_id IN (SELECT id FROM member_groups)
+            )
+            ORDER BY u.email",
+        )
+        .bind(group_id)
+        .bind(include_subgroups)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(users)
     }
 
-    fn create_passkey_authentication<'a>(
-        &self,
-        state: &'a PasskeyAuthenticationState,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'a>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let result = sqlx::query("INSERT INTO passkey_authentications (id, email, state, created_at) VALUES ($1, $2, $3, $4)")
-                .bind(state.id)
-                .bind(&state.email)
-                .bind(&state.state)
-                .bind(state.created_at.timestamp())
-                .execute(&pool)
-                .await;
-            if let Err(e) = result {
-                if e.as_database_error()
-                    .is_some_and(sqlx::error::DatabaseError::is_foreign_key_violation)
-                {
-                    return Err(DatabaseError::UserNotFound);
-                }
-                return Err(e.into());
-            }
-            Ok(())
-        })
+    async fn get_groups_by_user_id(&self, user_id: &Uuid) -> Result<Vec<Group>, DatabaseError> {
+        let groups: Vec<Group> = sqlx::query_as(
+            "SELECT g.*
+            FROM user_groups g
+            INNER JOIN user_groups_users gu
+            ON g.id = gu.group_id
+            WHERE gu.user_id = $1
+            ORDER BY g.name",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(groups)
     }
 
-    fn get_passkey_authentication_by_id<'id>(
+    async fn add_user_to_group(
         &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<PasskeyAuthenticationState, DatabaseError>> + Send + 'id>>
-    {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let state: PasskeyAuthenticationState =
-                sqlx::query_as("SELECT * FROM passkey_authentications WHERE id = $1")
-                    .bind(id)
-                    .fetch_one(&pool)
-                    .await?;
-            Ok(state)
-        })
+        group_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query(
+            "INSERT INTO user_groups_users (group_id, user_id) VALUES ($1, $2)
+            ON CONFLICT DO NOTHING",
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
     }
 
-    fn create_session<'a>(
+    async fn remove_user_from_group(
         &self,
-        session: &'a Session,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'a>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            sqlx::query(
-                "INSERT INTO sessions (id_hash, user_id, created_at, expires_at, state, is_admin, parent_id_hash)
-                VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        group_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        let result =
+            sqlx::query("DELETE FROM user_groups_users WHERE group_id = $1 AND user_id = $2")
+                .bind(group_id)
+                .bind(user_id)
+                .execute(&self.pool)
+                .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn get_subgroups(&self, group_id: &Uuid) -> Result<Vec<Group>, DatabaseError> {
+        let groups: Vec<Group> = sqlx::query_as(
+            "SELECT g.*
+            FROM user_groups g
+            INNER JOIN user_groups_subgroups s
+            ON g.id = s.child_id
+            WHERE s.parent_id = $1
+            ORDER BY g.name",
+        )
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(groups)
+    }
+
+    async fn get_supergroup_ids(&self, group_id: &Uuid) -> Result<Vec<Uuid>, DatabaseError> {
+        let ids: Vec<Uuid> = sqlx::query_scalar(
+            "WITH RECURSIVE supergroups (id) AS (
+                SELECT parent_id FROM user_groups_subgroups WHERE child_id = $1
+                UNION
+                SELECT s.parent_id
+                FROM user_groups_subgroups s
+                INNER JOIN supergroups g
+                ON s.child_id = g.id
             )
-            .bind(session.id_hash)
-            .bind(session.user_id)
-            .bind(session.created_at.timestamp())
-            .bind(session.expires_at.timestamp())
-            .bind(session.state)
-            .bind(session.is_admin)
-            .bind(session.parent_id_hash)
-            .execute(&pool)
-            .await?;
-            Ok(())
-        })
+            SELECT id FROM supergroups",
+        )
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(ids)
     }
 
-    fn get_session_by_id_hash<'id>(
+    async fn add_subgroup(&self, parent_id: &Uuid, child_id: &Uuid) -> Result<(), DatabaseError> {
+        sqlx::query(
+            "INSERT INTO user_groups_subgroups (parent_id, child_id) VALUES ($1, $2)
+            ON CONFLICT DO NOTHING",
+        )
+        .bind(parent_id)
+        .bind(child_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_subgroup(
         &self,
-        id_hash: &'id EncodableHash,
-    ) -> Pin<Box<dyn Future<Output = Result<Session, DatabaseError>> + Send + 'id>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            let session: Session = sqlx::query_as("SELECT * FROM sessions WHERE id_hash = $1")
-                .bind(id_hash)
-                .fetch_one(&pool)
+        parent_id: &Uuid,
+        child_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        let result =
+            sqlx::query("DELETE FROM user_groups_subgroups WHERE parent_id = $1 AND child_id = $2")
+                .bind(parent_id)
+                .bind(child_id)
+                .execute(&self.pool)
                 .await?;
-            Ok(session)
-        })
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn add_tag_to_group(&self, group_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        sqlx::query(
+            "INSERT INTO user_groups_tags (group_id, tag_id) VALUES ($1, $2)
+            ON CONFLICT DO NOTHING",
+        )
+        .bind(group_id)
+        .bind(tag.id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_tag_from_group(&self, group_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        sqlx::query("DELETE FROM user_groups_tags WHERE group_id = $1 AND tag_id = $2")
+            .bind(group_id)
+            .bind(tag.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
     }
 
-    fn update_session<'a>(
+    async fn get_tags_by_group_id(&self, group_id: &Uuid) -> Result<Vec<Tag>, DatabaseError> {
+        let tags: Vec<Tag> = sqlx::query_as(
+            "SELECT t.id, t.name, t.organization_id, t.created_at, t.updated_at
+            FROM tags t
+            INNER JOIN user_groups_tags gt
+            ON t.id = gt.tag_id
+            WHERE gt.group_id = $1
+            ORDER BY t.name",
+        )
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tags)
+    }
+
+    async fn get_effective_tags_by_user_id(
         &self,
-        id_hash: &'a EncodableHash,
-        update: &'a SessionUpdate,
-    ) -> Pin<Box<dyn Future<Output = Result<Session, DatabaseError>> + Send + 'a>> {
-        let pool = self.pool.clone();
-        Box::pin(async move {
-            if update.is_empty() {
-                return Err(DatabaseError::EmptyUpdate);
-            }
+        user_id: &Uuid,
+    ) -> Result<Vec<Tag>, DatabaseError> {
+        let tags: Vec<Tag> = sqlx::query_as(
+            "WITH RECURSIVE member_groups (id) AS (
+                SELECT group_id FROM user_groups_users WHERE user_id = $1
+                UNION
+                SELECT s.parent_id
+                FROM user_groups_subgroups s
+                INNER JOIN member_groups g
+                ON s.child_id = g.id
+            )
+            SELECT id, name, organization_id, created_at, updated_at
+            FROM tags
+            WHERE id IN (SELECT tag_id FROM users_tags WHERE user_id = $1)
+                OR id IN (
+                    SELECT tag_id FROM user_groups_tags
+                    WHERE group_id IN (SELECT id FROM member_groups)
+                )
+            ORDER BY name",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tags)
+    }
 
-            let mut query_parts = Vec::new();
-            let mut has_state = false;
-            let mut has_expires_at = false;
+    async fn create_role(&self, id: &Uuid, role: &RoleUpdate) -> Result<Role, DatabaseError> {
+        let role: Role = sqlx::query_as(
+            "INSERT INTO roles (id, name, permissions, created_at, updated_at)
+            VALUES ($1, $2, $3, unixepoch(), unixepoch())
+            RETURNING *",
+        )
+        .bind(id)
+        .bind(&role.name)
+        .bind(ViaJson(role.permissions.clone().unwrap_or_default()))
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(role)
+    }
 
-            if update.state.is_some() {
-                query_parts.push("state = ?");
-                has_state = true;
-            }
+    async fn get_role_by_id(&self, id: &Uuid) -> Result<Role, DatabaseError> {
+        let role: Role = sqlx::query_as("SELECT * FROM roles WHERE id = $1")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(role)
+    }
 
-            if update.expires_at.is_some() {
-                query_parts.push("expires_at = ?");
-                has_expires_at = true;
-            }
+    async fn list_roles(&self) -> Result<Vec<Role>, DatabaseError> {
+        let roles: Vec<Role> = sqlx::query_as("SELECT * FROM roles ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(roles)
+    }
 
-            let query_str = format!(
-                "UPDATE sessions SET {}
-                WHERE id_hash = ?
-                RETURNING *",
-                query_parts.join(", ")
-            );
+    async fn update_role(&self, id: &Uuid, update: &RoleUpdate) -> Result<Role, DatabaseError> {
+        if update.is_empty() {
+            return Err(DatabaseError::EmptyUpdate);
+        }
+        let mut query = UpdateQuery::new("roles");
+        query
+            .set("name", update.name.as_deref())
+            .set("permissions", update.permissions.clone().map(ViaJson))
+            .set_expr("updated_at", "unixepoch()");
+        let role: Role = query
+            .finish("id", id, "*")?
+            .build_query_as()
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(role)
+    }
 
-            let mut query = sqlx::query_as::<_, Session>(&query_str);
-            if has_state {
-                query = query.bind(update.state.as_ref().unwrap());
-            }
-            if has_expires_at {
-                query = query.bind(update.expires_at.as_ref().unwrap().timestamp());
-            }
-            query = query.bind(id_hash);
+    async fn delete_role_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM roles WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
 
-            let session: Session = query.fetch_one(&pool).await?;
-            Ok(session)
-        })
+    async fn get_roles_by_tag_id(&self, tag_id: &Uuid) -> Result<Vec<Role>, DatabaseError> {
+        let roles: Vec<Role> = sqlx::query_as(
+            "SELECT r.*
+            FROM roles r
+            INNER JOIN tags_roles tr
+            ON r.id = tr.role_id
+            WHERE tr.tag_id = $1
+            ORDER BY r.name",
+        )
+        .bind(tag_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(roles)
+    }
+
+    async fn add_role_to_tag(&self, tag_id: &Uuid, role_id: &Uuid) -> Result<(), DatabaseError> {
+        sqlx::query(
+            "INSERT INTO tags_roles (tag_id, role_id) VALUES ($1, $2)
+            ON CONFLICT DO NOTHING",
+        )
+        .bind(tag_id)
+        .bind(role_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_role_from_tag(
+        &self,
+        tag_id: &Uuid,
+        role_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM tags_roles WHERE tag_id = $1 AND role_id = $2")
+            .bind(tag_id)
+            .bind(role_id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
     }
+
+    async fn create_policy(
+        &self,
+        id: &Uuid,
+        policy: &PolicyUpdate,
+    ) -> Result<Policy, DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        let policy: Policy = sqlx::query_as(
+            "INSERT INTO policies (id, name, enabled, version, document, created_at, updated_at)
+            VALUES ($1, $2, $3, 1, $4, unixepoch(), unixepoch())
+            RETURNING *",
+        )
+        .bind(id)
+        .bind(&policy.name)
+        .bind(policy.enabled.unwrap_or(true))
+        .bind(policy.document.clone().map(ViaJson))
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO policy_versions (policy_id, version, document, created_at)
+            VALUES ($1, $2, $3, unixepoch())",
+        )
+        .bind(policy.id)
+        .bind(policy.version)
+        .bind(&policy.document)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(policy)
+    }
+
+    async fn get_policy_by_id(&self, id: &Uuid) -> Result<Policy, DatabaseError> {
+        let policy: Policy = sqlx::query_as("SELECT * FROM policies WHERE id = $1")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(policy)
+    }
+
+    async fn list_policies(&self) -> Result<Vec<Policy>, DatabaseError> {
+        let policies: Vec<Policy> = sqlx::query_as("SELECT * FROM policies ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(policies)
+    }
+
+    async fn update_policy(
+        &self,
+        id: &Uuid,
+        update: &PolicyUpdate,
+    ) -> Result<Policy, DatabaseError> {
+        if update.is_empty() {
+            return Err(DatabaseError::EmptyUpdate);
+        }
+        let mut query = UpdateQuery::new("policies");
+        query
+            .set("name", update.name.as_deref())
+            .set("enabled", update.enabled)
+            .set("document", update.document.clone().map(ViaJson));
+        if update.document.is_some() {
+            query.set_expr("version", "version + 1");
+        }
+        query.set_expr("updated_at", "unixepoch()");
+        let mut tx = self.pool.begin().await?;
+        let policy: Policy = query
+            .finish("id", id, "*")?
+            .build_query_as()
+            .fetch_one(&mut *tx)
+            .await?;
+        if update.document.is_some() {
+            sqlx::query(
+                "INSERT INTO policy_versions (policy_id, version, document, created_at)
+                VALUES ($1, $2, $3, unixepoch())",
+            )
+            .bind(policy.id)
+            .bind(policy.version)
+            .bind(&policy.document)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(policy)
+    }
+
+    async fn delete_policy_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM policies WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn list_policy_versions(
+        &self,
+        policy_id: &Uuid,
+    ) -> Result<Vec<PolicyVersion>, DatabaseError> {
+        let versions: Vec<PolicyVersion> = sqlx::query_as(
+            "SELECT * FROM policy_versions WHERE policy_id = $1 ORDER BY version DESC",
+        )
+        .bind(policy_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(versions)
+    }
+
+    async fn get_policy_version(
+        &self,
+        policy_id: &Uuid,
+        version: i64,
+    ) -> Result<PolicyVersion, DatabaseError> {
+        let version: PolicyVersion =
+            sqlx::query_as("SELECT * FROM policy_versions WHERE policy_id = $1 AND version = $2")
+                .bind(policy_id)
+                .bind(version)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(version)
+    }
+
+    async fn create_passkey(
+        &self,
+        id: &Uuid,
+        user_id: &Uuid,
+        passkey: &NewPasskeyCredential,
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        insert_passkey(&self.pool, id, user_id, passkey).await
+    }
+
+    async fn get_passkey_by_id(&self, id: &Uuid) -> Result<PasskeyCredential, DatabaseError> {
+        let passkey: PasskeyCredential = sqlx::query_as(
+            "SELECT id, user_id, passkey, display_name, aaguid, attestation_format, created_at,
+                last_used_at
+             FROM passkeys WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(passkey)
+    }
+
+    async fn get_passkey_by_credential_id(
+        &self,
+        credential_id: &[u8],
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        let passkey: PasskeyCredential = sqlx::query_as(
+            "SELECT id, user_id, passkey, display_name, aaguid, attestation_format, created_at,
+                last_used_at
+             FROM passkeys WHERE credential_id = $1",
+        )
+        .bind(credential_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(passkey)
+    }
+
+    async fn get_passkeys_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<PasskeyCredential>, DatabaseError> {
+        let passkeys: Vec<PasskeyCredential> = sqlx::query_as(
+            "SELECT id, user_id, passkey, display_name, aaguid, attestation_format, created_at,
+                last_used_at
+             FROM passkeys WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(passkeys)
+    }
+
+    async fn get_passkeys_by_user_email(
+        &self,
+        email: &str,
+    ) -> Result<Vec<PasskeyCredential>, DatabaseError> {
+        let passkeys: Vec<PasskeyCredential> = sqlx::query_as(
+            "SELECT p.id, p.user_id, p.passkey, p.display_name, p.aaguid, p.attestation_format,
+                p.created_at, p.last_used_at
+            FROM passkeys p
+            INNER JOIN users ON p.user_id = users.id
+            WHERE users.email = $1",
+        )
+        .bind(normalize_email(email))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(passkeys)
+    }
+
+    async fn update_passkey(
+        &self,
+        id: &Uuid,
+        passkey: &PasskeyCredentialUpdate,
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        let mut query = UpdateQuery::new("passkeys");
+        query
+            .set(
+                "display_name",
+                passkey.display_name.as_ref().map(Option::as_deref),
+            )
+            .set("passkey", passkey.passkey.as_ref());
+        let passkey: PasskeyCredential = query
+            .finish(
+                "id",
+                id,
+                "id, user_id, passkey, display_name, aaguid, attestation_format, created_at, \
+                last_used_at",
+            )?
+            .build_query_as()
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(passkey)
+    }
+
+    async fn delete_passkey_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        sqlx::query("DELETE FROM passkeys WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_passkey_unless_last(&self, id: &Uuid) -> Result<bool, DatabaseError> {
+        let result = sqlx::query(
+            "DELETE FROM passkeys
+            WHERE id = $1
+                AND (SELECT count(*) FROM passkeys AS other WHERE other.user_id = passkeys.user_id)
+                    > 1",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn create_passkey_registration(
+        &self,
+        registration: &PasskeyRegistrationState,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query(
+            "INSERT INTO passkey_registrations
+                (id, user_id, email, display_name, registration, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(registration.id)
+        .bind(registration.user_id)
+        .bind(&registration.email)
+        .bind(&registration.display_name)
+        .bind(&registration.registration)
+        .bind(registration.created_at.timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_passkey_registration_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<PasskeyRegistrationState, DatabaseError> {
+        let registration: PasskeyRegistrationState =
+            sqlx::query_as("SELECT * FROM passkey_registrations WHERE id = $1")
+                .bind(id)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(registration)
+    }
+
+    async fn delete_passkey_registration_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM passkey_registrations WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn create_passkey_authentication(
+        &self,
+        state: &PasskeyAuthenticationState,
+    ) -> Result<(), DatabaseError> {
+        let result = sqlx::query("INSERT INTO passkey_authentications (id, email, state, created_at) VALUES ($1, $2, $3, $4)")
+            .bind(state.id)
+            .bind(&state.email)
+            .bind(&state.state)
+            .bind(state.created_at.timestamp())
+            .execute(&self.pool)
+            .await;
+        if let Err(e) = result {
+            if e.as_database_error()
+                .is_some_and(sqlx::error::DatabaseError::is_foreign_key_violation)
+            {
+                return Err(DatabaseError::UserNotFound);
+            }
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    async fn get_passkey_authentication_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<PasskeyAuthenticationState, DatabaseError> {
+        let state: PasskeyAuthenticationState =
+            sqlx::query_as("SELECT * FROM passkey_authentications WHERE id = $1")
+                .bind(id)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(state)
+    }
+
+    async fn delete_passkey_authentication_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM passkey_authentications WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn delete_expired_ceremonies(&self, age: Duration) -> Result<u64, DatabaseError> {
+        let age = i64::try_from(age.as_secs()).unwrap_or(i64::MAX);
+        let mut tx = self.pool.begin().await?;
+        let mut deleted = 0;
+        for table in ["passkey_registrations", "passkey_authentications"] {
+            deleted += sqlx::query(&format!(
+                "DELETE FROM {table} WHERE created_at < unixepoch() - $1"
+            ))
+            .bind(age)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn create_session(&self, session: &Session) -> Result<(), DatabaseError> {
+        insert_session(&self.pool, session).await
+    }
+
+    async fn get_session_by_id_hash(
+        &self,
+        id_hash: &EncodableHash,
+    ) -> Result<Session, DatabaseError> {
+        let session: Session = sqlx::query_as("SELECT * FROM sessions WHERE id_hash = $1")
+            .bind(id_hash)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(session)
+    }
+
+    async fn get_active_sessions_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<Session>, DatabaseError> {
+        let sessions = sqlx::query_as(
+            "SELECT * FROM sessions
+            WHERE user_id = $1 AND state = $2 AND expires_at > unixepoch()
+            ORDER BY last_seen_at DESC, id_hash",
+        )
+        .bind(user_id)
+        .bind(SessionState::Active)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(sessions)
+    }
+
+    async fn update_session(
+        &self,
+        id_hash: &EncodableHash,
+        update: &SessionUpdate,
+    ) -> Result<Session, DatabaseError> {
+        apply_session_update(&self.pool, id_hash, update).await
+    }
+
+    async fn create_session_revocation_token(
+        &self,
+        token_hash: &EncodableHash,
+        session_id_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query(
+            "INSERT INTO session_revocation_tokens (token_hash, session_id_hash, created_at)
+            VALUES ($1, $2, unixepoch())",
+        )
+        .bind(token_hash)
+        .bind(session_id_hash)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn revoke_session_by_token(
+        &self,
+        token_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        let session_id_hash: EncodableHash = sqlx::query_scalar(
+            "DELETE FROM session_revocation_tokens WHERE token_hash = $1
+            RETURNING session_id_hash",
+        )
+        .bind(token_hash)
+        .fetch_one(&mut *tx)
+        .await?;
+        // Upgrading or downgrading a session creates a child session, so revoke the whole
+        // chain of sessions started by the login.
+        sqlx::query(
+            "WITH RECURSIVE chain (id_hash) AS (
+                SELECT $1
+                UNION
+                SELECT s.id_hash FROM sessions s
+                INNER JOIN chain c ON s.parent_id_hash = c.id_hash
+            )
+            UPDATE sessions SET state = $2
+            WHERE id_hash IN (SELECT id_hash FROM chain) AND state = $3",
+        )
+        .bind(session_id_hash)
+        .bind(SessionState::Revoked)
+        .bind(SessionState::Active)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_ended_sessions(&self, age: Duration) -> Result<u64, DatabaseError> {
+        let age = i64::try_from(age.as_secs()).unwrap_or(i64::MAX);
+        delete_ended_sessions(
+            &self.pool,
+            chrono::Utc::now().timestamp().saturating_sub(age),
+        )
+        .await
+    }
+
+    async fn revoke_all_sessions(
+        &self,
+        except: Option<&EncodableHash>,
+    ) -> Result<u64, DatabaseError> {
+        let result = sqlx::query(
+            "UPDATE sessions SET state = $1
+            WHERE state = $2 AND expires_at > unixepoch() AND id_hash IS NOT $3",
+        )
+        .bind(SessionState::Revoked)
+        .bind(SessionState::Active)
+        .bind(except)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn revoke_sessions_by_user_id(&self, user_id: &Uuid) -> Result<u64, DatabaseError> {
+        let result = sqlx::query(
+            "UPDATE sessions SET state = $1
+            WHERE user_id = $2 AND state = $3 AND expires_at > unixepoch()",
+        )
+        .bind(SessionState::Revoked)
+        .bind(user_id)
+        .bind(SessionState::Active)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn create_api_token(&self, token: &ApiToken) -> Result<ApiToken, DatabaseError> {
+        let token: ApiToken = sqlx::query_as(
+            "INSERT INTO api_tokens (id, user_id, name, token_hash, scopes, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING *",
+        )
+        .bind(token.id)
+        .bind(token.user_id)
+        .bind(&token.name)
+        .bind(token.token_hash)
+        .bind(&token.scopes)
+        .bind(token.created_at.timestamp())
+        .bind(token.expires_at.map(|time| time.timestamp()))
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(token)
+    }
+
+    async fn get_api_token_by_hash(
+        &self,
+        token_hash: &EncodableHash,
+    ) -> Result<ApiToken, DatabaseError> {
+        let token: ApiToken = sqlx::query_as("SELECT * FROM api_tokens WHERE token_hash = $1")
+            .bind(token_hash)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(token)
+    }
+
+    async fn get_api_tokens_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<ApiToken>, DatabaseError> {
+        let tokens: Vec<ApiToken> =
+            sqlx::query_as("SELECT * FROM api_tokens WHERE user_id = $1 ORDER BY created_at, id")
+                .bind(user_id)
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(tokens)
+    }
+
+    async fn delete_api_token(&self, user_id: &Uuid, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM api_tokens WHERE id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn record_user_device(
+        &self,
+        user_id: &Uuid,
+        device_id_hash: &EncodableHash,
+    ) -> Result<bool, DatabaseError> {
+        let inserted = sqlx::query(
+            "INSERT INTO user_devices (user_id, id_hash, first_seen_at, last_seen_at)
+            VALUES ($1, $2, unixepoch(), unixepoch())
+            ON CONFLICT (user_id, id_hash) DO NOTHING",
+        )
+        .bind(user_id)
+        .bind(device_id_hash)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        let is_new = inserted == 1;
+        if !is_new {
+            sqlx::query(
+                "UPDATE user_devices SET last_seen_at = unixepoch()
+                WHERE user_id = $1 AND id_hash = $2",
+            )
+            .bind(user_id)
+            .bind(device_id_hash)
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(is_new)
+    }
+
+    async fn create_queued_email(&self, email: &QueuedEmail) -> Result<(), DatabaseError> {
+        sqlx::query(
+            "INSERT INTO email_queue (id, sender, recipient, subject, html, text, state, attempts,
+                last_error, created_at, next_attempt_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+        )
+        .bind(email.id)
+        .bind(&email.sender)
+        .bind(&email.recipient)
+        .bind(&email.subject)
+        .bind(&email.html)
+        .bind(&email.text)
+        .bind(email.state)
+        .bind(email.attempts)
+        .bind(&email.last_error)
+        .bind(email.created_at.timestamp())
+        .bind(email.next_attempt_at.timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_due_queued_emails(&self, limit: u32) -> Result<Vec<QueuedEmail>, DatabaseError> {
+        let emails: Vec<QueuedEmail> = sqlx::query_as(
+            "SELECT * FROM email_queue
+            WHERE state = $1 AND next_attempt_at <= unixepoch()
+            ORDER BY next_attempt_at
+            LIMIT $2",
+        )
+        .bind(QueuedEmailState::Pending)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(emails)
+    }
+
+    async fn update_queued_email(
+        &self,
+        id: &Uuid,
+        update: &QueuedEmailUpdate,
+    ) -> Result<QueuedEmail, DatabaseError> {
+        let mut query = UpdateQuery::new("email_queue");
+        query
+            .set("state", update.state)
+            .set("attempts", update.attempts)
+            .set("last_error", update.last_error.as_deref())
+            .set(
+                "next_attempt_at",
+                update.next_attempt_at.map(|time| time.timestamp()),
+            );
+        let email: QueuedEmail = query
+            .finish("id", id, "*")?
+            .build_query_as()
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(email)
+    }
+
+    async fn delete_queued_email_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        sqlx::query("DELETE FROM email_queue WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn create_email_suppression(
+        &self,
+        address: &str,
+        reason: &str,
+    ) -> Result<EmailSuppression, DatabaseError> {
+        let suppression: EmailSuppression = sqlx::query_as(
+            "INSERT INTO email_suppressions (address, reason, created_at)
+            VALUES ($1, $2, unixepoch())
+            ON CONFLICT (address) DO UPDATE SET reason = excluded.reason
+            RETURNING *",
+        )
+        .bind(normalize_email(address))
+        .bind(reason)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(suppression)
+    }
+
+    async fn get_email_suppression(
+        &self,
+        address: &str,
+    ) -> Result<EmailSuppression, DatabaseError> {
+        let suppression: EmailSuppression =
+            sqlx::query_as("SELECT * FROM email_suppressions WHERE address = $1")
+                .bind(normalize_email(address))
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(suppression)
+    }
+
+    async fn list_email_suppressions(&self) -> Result<Vec<EmailSuppression>, DatabaseError> {
+        let suppressions: Vec<EmailSuppression> =
+            sqlx::query_as("SELECT * FROM email_suppressions ORDER BY created_at DESC")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(suppressions)
+    }
+
+    async fn delete_email_suppression(&self, address: &str) -> Result<(), DatabaseError> {
+        sqlx::query("DELETE FROM email_suppressions WHERE address = $1")
+            .bind(normalize_email(address))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn create_ip_ban(&self, ban: &IpBan) -> Result<IpBan, DatabaseError> {
+        let ban: IpBan = sqlx::query_as(
+            "INSERT INTO ip_bans (id, network, reason, source, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (network) DO UPDATE SET
+                reason = excluded.reason,
+                source = excluded.source,
+                created_at = excluded.created_at,
+                expires_at = excluded.expires_at
+            RETURNING *",
+        )
+        .bind(ban.id)
+        .bind(&ban.network)
+        .bind(&ban.reason)
+        .bind(ban.source)
+        .bind(ban.created_at.timestamp())
+        .bind(ban.expires_at.map(|time| time.timestamp()))
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(ban)
+    }
+
+    async fn list_ip_bans(&self) -> Result<Vec<IpBan>, DatabaseError> {
+        let bans: Vec<IpBan> = sqlx::query_as(
+            "SELECT * FROM ip_bans
+            WHERE expires_at IS NULL OR expires_at > unixepoch()
+            ORDER BY created_at DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(bans)
+    }
+
+    async fn delete_ip_ban_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM ip_bans WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn try_acquire_lease(
+        &self,
+        name: &str,
+        holder: &Uuid,
+        ttl: Duration,
+    ) -> Result<bool, DatabaseError> {
+        let ttl = i64::try_from(ttl.as_secs()).unwrap_or(i64::MAX);
+        // The update only happens if this holder already has the lease or it has expired
+        let result = sqlx::query(
+            "INSERT INTO job_leases (name, holder, expires_at)
+            VALUES ($1, $2, unixepoch() + $3)
+            ON CONFLICT (name) DO UPDATE SET
+                holder = excluded.holder,
+                expires_at = excluded.expires_at
+            WHERE job_leases.holder = excluded.holder OR job_leases.expires_at <= unixepoch()",
+        )
+        .bind(name)
+        .bind(holder)
+        .bind(ttl)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_announcement(&self) -> Result<Announcement, DatabaseError> {
+        let announcement: Announcement = sqlx::query_as(
+            "SELECT message, severity, starts_at, ends_at, updated_at FROM announcements",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(announcement)
+    }
+
+    async fn set_announcement(&self, announcement: &Announcement) -> Result<(), DatabaseError> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO announcements
+            (id, message, severity, starts_at, ends_at, updated_at)
+            VALUES (1, $1, $2, $3, $4, $5)",
+        )
+        .bind(&announcement.message)
+        .bind(announcement.severity)
+        .bind(announcement.starts_at.map(|time| time.timestamp()))
+        .bind(announcement.ends_at.map(|time| time.timestamp()))
+        .bind(announcement.updated_at.timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_announcement(&self) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM announcements")
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<Webhook, DatabaseError> {
+        let webhook: Webhook = sqlx::query_as(
+            "INSERT INTO webhooks (id, url, events, secret, enabled, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING *",
+        )
+        .bind(webhook.id)
+        .bind(&webhook.url)
+        .bind(&webhook.events)
+        .bind(&webhook.secret)
+        .bind(webhook.enabled)
+        .bind(webhook.created_at.timestamp())
+        .bind(webhook.updated_at.timestamp())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(webhook)
+    }
+
+    async fn list_webhooks(&self) -> Result<Vec<Webhook>, DatabaseError> {
+        let webhooks: Vec<Webhook> =
+            sqlx::query_as("SELECT * FROM webhooks ORDER BY created_at, id")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(webhooks)
+    }
+
+    async fn get_webhook_by_id(&self, id: &Uuid) -> Result<Webhook, DatabaseError> {
+        let webhook: Webhook = sqlx::query_as("SELECT * FROM webhooks WHERE id = $1")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(webhook)
+    }
+
+    async fn update_webhook(
+        &self,
+        id: &Uuid,
+        update: &WebhookUpdate,
+    ) -> Result<Webhook, DatabaseError> {
+        if update.is_empty() {
+            return Err(DatabaseError::EmptyUpdate);
+        }
+        let mut query = UpdateQuery::new("webhooks");
+        query
+            .set("url", update.url.as_deref())
+            .set("events", update.events.clone().map(ViaJson))
+            .set("enabled", update.enabled)
+            .set_expr("updated_at", "unixepoch()");
+        let webhook: Webhook = query
+            .finish("id", id, "*")?
+            .build_query_as()
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(webhook)
+    }
+
+    async fn delete_webhook_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM webhooks WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn create_oauth_client(
+        &self,
+        client: &OAuthClient,
+    ) -> Result<OAuthClient, DatabaseError> {
+        let client: OAuthClient = sqlx::query_as(
+            "INSERT INTO oauth_clients (id, name, secret_hash, redirect_uris, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *",
+        )
+        .bind(client.id)
+        .bind(&client.name)
+        .bind(client.secret_hash)
+        .bind(&client.redirect_uris)
+        .bind(client.created_at.timestamp())
+        .bind(client.updated_at.timestamp())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(client)
+    }
+
+    async fn list_oauth_clients(&self) -> Result<Vec<OAuthClient>, DatabaseError> {
+        let clients: Vec<OAuthClient> =
+            sqlx::query_as("SELECT * FROM oauth_clients ORDER BY name, id")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(clients)
+    }
+
+    async fn get_oauth_client_by_id(&self, id: &Uuid) -> Result<OAuthClient, DatabaseError> {
+        let client: OAuthClient = sqlx::query_as("SELECT * FROM oauth_clients WHERE id = $1")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(client)
+    }
+
+    async fn update_oauth_client(
+        &self,
+        id: &Uuid,
+        update: &OAuthClientUpdate,
+    ) -> Result<OAuthClient, DatabaseError> {
+        if update.is_empty() {
+            return Err(DatabaseError::EmptyUpdate);
+        }
+        let mut query = UpdateQuery::new("oauth_clients");
+        query
+            .set("name", update.name.as_deref())
+            .set("redirect_uris", update.redirect_uris.clone().map(ViaJson))
+            .set_expr("updated_at", "unixepoch()");
+        let client: OAuthClient = query
+            .finish("id", id, "*")?
+            .build_query_as()
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(client)
+    }
+
+    async fn delete_oauth_client_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM oauth_clients WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn create_authorization_code(
+        &self,
+        code: &AuthorizationCode,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query(
+            "INSERT INTO oauth_authorization_codes
+                (code_hash, client_id, user_id, redirect_uri, scope, nonce, code_challenge,
+                auth_time, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(code.code_hash)
+        .bind(code.client_id)
+        .bind(code.user_id)
+        .bind(&code.redirect_uri)
+        .bind(&code.scope)
+        .bind(code.nonce.as_deref())
+        .bind(code.code_challenge.as_deref())
+        .bind(code.auth_time.timestamp())
+        .bind(code.expires_at.timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn consume_authorization_code(
+        &self,
+        code_hash: &EncodableHash,
+    ) -> Result<AuthorizationCode, DatabaseError> {
+        sqlx::query("DELETE FROM oauth_authorization_codes WHERE expires_at <= unixepoch()")
+            .execute(&self.pool)
+            .await?;
+        let code: AuthorizationCode = sqlx::query_as(
+            "DELETE FROM oauth_authorization_codes WHERE code_hash = $1 RETURNING *",
+        )
+        .bind(code_hash)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(code)
+    }
+
+    async fn create_saml_service_provider(
+        &self,
+        provider: &SamlServiceProvider,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        let provider: SamlServiceProvider = sqlx::query_as(
+            "INSERT INTO saml_service_providers
+                (id, entity_id, name, acs_url, name_id_format, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING *",
+        )
+        .bind(provider.id)
+        .bind(&provider.entity_id)
+        .bind(&provider.name)
+        .bind(&provider.acs_url)
+        .bind(provider.name_id_format)
+        .bind(provider.created_at.timestamp())
+        .bind(provider.updated_at.timestamp())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(provider)
+    }
+
+    async fn list_saml_service_providers(&self) -> Result<Vec<SamlServiceProvider>, DatabaseError> {
+        let providers: Vec<SamlServiceProvider> =
+            sqlx::query_as("SELECT * FROM saml_service_providers ORDER BY name, id")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(providers)
+    }
+
+    async fn get_saml_service_provider_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        let provider: SamlServiceProvider =
+            sqlx::query_as("SELECT * FROM saml_service_providers WHERE id = $1")
+                .bind(id)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(provider)
+    }
+
+    async fn get_saml_service_provider_by_entity_id(
+        &self,
+        entity_id: &str,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        let provider: SamlServiceProvider =
+            sqlx::query_as("SELECT * FROM saml_service_providers WHERE entity_id = $1")
+                .bind(entity_id)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(provider)
+    }
+
+    async fn update_saml_service_provider(
+        &self,
+        id: &Uuid,
+        update: &SamlServiceProviderUpdate,
+    ) -> Result<SamlServiceProvider, DatabaseError> {
+        if update.is_empty() {
+            return Err(DatabaseError::EmptyUpdate);
+        }
+        let mut query = UpdateQuery::new("saml_service_providers");
+        query
+            .set("entity_id", update.entity_id.as_deref())
+            .set("name", update.name.as_deref())
+            .set("acs_url", update.acs_url.as_deref())
+            .set("name_id_format", update.name_id_format)
+            .set_expr("updated_at", "unixepoch()");
+        let provider: SamlServiceProvider = query
+            .finish("id", id, "*")?
+            .build_query_as()
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(provider)
+    }
+
+    async fn delete_saml_service_provider_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM saml_service_providers WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn create_federated_provider(
+        &self,
+        provider: &FederatedProvider,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        let provider: FederatedProvider = sqlx::query_as(
+            "INSERT INTO federated_providers
+                (id, slug, name, issuer, client_id, client_secret, auto_provision, created_at,
+                updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            RETURNING *",
+        )
+        .bind(provider.id)
+        .bind(&provider.slug)
+        .bind(&provider.name)
+        .bind(&provider.issuer)
+        .bind(&provider.client_id)
+        .bind(&provider.client_secret)
+        .bind(provider.auto_provision)
+        .bind(provider.created_at.timestamp())
+        .bind(provider.updated_at.timestamp())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(provider)
+    }
+
+    async fn list_federated_providers(&self) -> Result<Vec<FederatedProvider>, DatabaseError> {
+        let providers: Vec<FederatedProvider> =
+            sqlx::query_as("SELECT * FROM federated_providers ORDER BY name, id")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(providers)
+    }
+
+    async fn get_federated_provider_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        let provider: FederatedProvider =
+            sqlx::query_as("SELECT * FROM federated_providers WHERE id = $1")
+                .bind(id)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(provider)
+    }
+
+    async fn get_federated_provider_by_slug(
+        &self,
+        slug: &str,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        let provider: FederatedProvider =
+            sqlx::query_as("SELECT * FROM federated_providers WHERE slug = $1")
+                .bind(slug)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(provider)
+    }
+
+    async fn update_federated_provider(
+        &self,
+        id: &Uuid,
+        update: &FederatedProviderUpdate,
+    ) -> Result<FederatedProvider, DatabaseError> {
+        if update.is_empty() {
+            return Err(DatabaseError::EmptyUpdate);
+        }
+        let mut query = UpdateQuery::new("federated_providers");
+        query
+            .set("name", update.name.as_deref())
+            .set("issuer", update.issuer.as_deref())
+            .set("client_id", update.client_id.as_deref())
+            .set("client_secret", update.client_secret.clone().map(ViaJson))
+            .set("auto_provision", update.auto_provision)
+            .set_expr("updated_at", "unixepoch()");
+        let provider: FederatedProvider = query
+            .finish("id", id, "*")?
+            .build_query_as()
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(provider)
+    }
+
+    async fn delete_federated_provider_by_id(&self, id: &Uuid) -> Result<(), DatabaseError> {
+        let result = sqlx::query("DELETE FROM federated_providers WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn create_federated_login_state(
+        &self,
+        state: &FederatedLoginState,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query(
+            "INSERT INTO federated_login_states
+                (state_hash, provider_id, nonce, code_verifier, link_user_id, next, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(state.state_hash)
+        .bind(state.provider_id)
+        .bind(&state.nonce)
+        .bind(&state.code_verifier)
+        .bind(state.link_user_id)
+        .bind(state.next.as_deref())
+        .bind(state.expires_at.timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn consume_federated_login_state(
+        &self,
+        state_hash: &EncodableHash,
+    ) -> Result<FederatedLoginState, DatabaseError> {
+        sqlx::query("DELETE FROM federated_login_states WHERE expires_at <= unixepoch()")
+            .execute(&self.pool)
+            .await?;
+        let state: FederatedLoginState =
+            sqlx::query_as("DELETE FROM federated_login_states WHERE state_hash = $1 RETURNING *")
+                .bind(state_hash)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(state)
+    }
+
+    async fn get_federated_identity(
+        &self,
+        provider_id: &Uuid,
+        subject: &str,
+    ) -> Result<FederatedIdentity, DatabaseError> {
+        let identity: FederatedIdentity = sqlx::query_as(
+            "SELECT * FROM federated_identities WHERE provider_id = $1 AND subject = $2",
+        )
+        .bind(provider_id)
+        .bind(subject)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(identity)
+    }
+
+    async fn create_federated_identity(
+        &self,
+        identity: &FederatedIdentity,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query(
+            "INSERT INTO federated_identities (provider_id, subject, user_id, email, created_at)
+            VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(identity.provider_id)
+        .bind(&identity.subject)
+        .bind(identity.user_id)
+        .bind(identity.email.as_deref())
+        .bind(identity.created_at.timestamp())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_federated_identities_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<FederatedIdentity>, DatabaseError> {
+        let identities: Vec<FederatedIdentity> = sqlx::query_as(
+            "SELECT * FROM federated_identities WHERE user_id = $1 ORDER BY created_at",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(identities)
+    }
+
+    async fn delete_federated_identities(
+        &self,
+        user_id: &Uuid,
+        provider_id: &Uuid,
+    ) -> Result<(), DatabaseError> {
+        let result =
+            sqlx::query("DELETE FROM federated_identities WHERE user_id = $1 AND provider_id = $2")
+                .bind(user_id)
+                .bind(provider_id)
+                .execute(&self.pool)
+                .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn create_signing_key(&self, key: &StoredSigningKey) -> Result<(), DatabaseError> {
+        sqlx::query(
+            "INSERT INTO signing_keys (kid, private_key, certificate, created_at, retired_at)
+            VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(&key.kid)
+        .bind(&key.private_key)
+        .bind(key.certificate.as_deref())
+        .bind(key.created_at.timestamp())
+        .bind(key.retired_at.map(|retired_at| retired_at.timestamp()))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_signing_keys(&self) -> Result<Vec<StoredSigningKey>, DatabaseError> {
+        let keys: Vec<StoredSigningKey> =
+            sqlx::query_as("SELECT * FROM signing_keys ORDER BY created_at DESC, kid")
+                .fetch_all(&self.pool)
+                .await?;
+        Ok(keys)
+    }
+
+    async fn retire_signing_keys_older_than(&self, kid: &str) -> Result<u64, DatabaseError> {
+        let result = sqlx::query(
+            "UPDATE signing_keys SET retired_at = unixepoch()
+            WHERE retired_at IS NULL
+                AND kid != $1
+                AND created_at <= (SELECT created_at FROM signing_keys WHERE kid = $1)",
+        )
+        .bind(kid)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_retired_signing_keys(&self, age: Duration) -> Result<u64, DatabaseError> {
+        let age = i64::try_from(age.as_secs()).unwrap_or(i64::MAX);
+        let result = sqlx::query("DELETE FROM signing_keys WHERE retired_at < unixepoch() - $1")
+            .bind(age)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn get_or_create_session_cookie_key(
+        &self,
+        key: &StoredSessionCookieKey,
+    ) -> Result<StoredSessionCookieKey, DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO session_cookie_keys (kid, key, created_at)
+            SELECT $1, $2, $3
+            WHERE NOT EXISTS (SELECT 1 FROM session_cookie_keys)",
+        )
+        .bind(&key.kid)
+        .bind(&key.key)
+        .bind(key.created_at.timestamp())
+        .execute(&mut *tx)
+        .await?;
+        let key = sqlx::query_as("SELECT * FROM session_cookie_keys ORDER BY created_at, kid")
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(key)
+    }
+
+    async fn purge_retained_data(
+        &self,
+        policy: &RetentionPolicy,
+    ) -> Result<RetentionReport, DatabaseError> {
+        let now = chrono::Utc::now();
+        // Timestamp before which data is purged. Windows too long to represent purge nothing.
+        let cutoff = |window: std::time::Duration| {
+            chrono::Duration::from_std(window)
+                .ok()
+                .and_then(|window| now.checked_sub_signed(window))
+                .map_or(i64::MIN, |cutoff| cutoff.timestamp())
+        };
+        let mut report = RetentionReport::default();
+        if let Some(window) = policy.terminated_sessions {
+            report.sessions = delete_ended_sessions(&self.pool, cutoff(window)).await?;
+        }
+        if let Some(window) = policy.login_history {
+            report.devices = sqlx::query("DELETE FROM user_devices WHERE last_seen_at < $1")
+                .bind(cutoff(window))
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+        }
+        if let Some(window) = policy.anonymized_users {
+            let mut tx = self.pool.begin().await?;
+            // Unlink the users' sessions from each other so they can be deleted in any order
+            sqlx::query(
+                "UPDATE sessions SET parent_id_hash = NULL
+                WHERE user_id IN (SELECT id FROM users WHERE anonymized_at < $1)",
+            )
+            .bind(cutoff(window))
+            .execute(&mut *tx)
+            .await?;
+            report.users = sqlx::query("DELETE FROM users WHERE anonymized_at < $1")
+                .bind(cutoff(window))
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            tx.commit().await?;
+        }
+        if let Some(window) = policy.dead_emails {
+            report.emails =
+                sqlx::query("DELETE FROM email_queue WHERE state = $1 AND created_at < $2")
+                    .bind(QueuedEmailState::Dead)
+                    .bind(cutoff(window))
+                    .execute(&self.pool)
+                    .await?
+                    .rows_affected();
+        }
+        Ok(report)
+    }
+
+    async fn run_maintenance(&self, task: MaintenanceTask) -> Result<(), DatabaseError> {
+        let statement = match task {
+            MaintenanceTask::Optimize => "PRAGMA optimize",
+            MaintenanceTask::Analyze => "ANALYZE",
+            MaintenanceTask::Vacuum => "VACUUM",
+        };
+        sqlx::query(statement).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn export_backup(&self) -> Result<Backup, DatabaseError> {
+        backup::export_backup(&self.pool).await
+    }
+
+    async fn import_backup(&self, backup: &Backup) -> Result<(), DatabaseError> {
+        backup::import_backup(&self.pool, backup).await
+    }
+
+    async fn begin(&self) -> Result<BoxedTransaction, DatabaseError> {
+        let tx: BoxedTransaction = Box::new(SqliteTransaction::new(self.pool.begin().await?));
+        Ok(tx)
+    }
+}
+
+// Queries shared between [`SqliteClient`] and [`SqliteTransaction`]
+
+/// Turns text entered by a user into an FTS5 query matching rows with words starting with each
+/// of its words. Each word is quoted, so that FTS5 operators in it are matched literally. Returns
+/// [`None`] if the text contains no words.
+fn fts_prefix_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Adds the conditions of the given filter to a query on the `users` table, which must already
+/// have a `WHERE` clause.
+fn push_user_filter<'args>(builder: &mut QueryBuilder<'args, Sqlite>, filter: &'args UserFilter) {
+    if let Some(email) = &filter.email {
+        builder
+            .push(" AND email LIKE ")
+            .push_bind(like_pattern(email))
+            .push(" ESCAPE '\\'");
+    }
+    if let Some(display_name) = &filter.display_name {
+        builder
+            .push(" AND display_name LIKE ")
+            .push_bind(like_pattern(display_name))
+            .push(" ESCAPE '\\'");
+    }
+    if let Some(status) = filter.status {
+        builder.push(" AND status = ").push_bind(status);
+    }
+}
+
+/// Returns a `LIKE` pattern matching values which contain the given text, escaping wildcards in it
+/// with backslashes.
+fn like_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{escaped}%")
+}
+
+async fn insert_user(
+    executor: impl SqliteExecutor<'_>,
+    id: &Uuid,
+    user: &UserCreate,
+) -> Result<User, DatabaseError> {
+    Ok(sqlx::query_as::<_, User>(
+        "INSERT INTO users (id, email, display_name, created_at, updated_at)
+        VALUES ($1, $2, $3, unixepoch(), unixepoch())
+        RETURNING *",
+    )
+    .bind(id)
+    .bind(normalize_email(&user.email))
+    .bind(&user.display_name)
+    .fetch_one(executor)
+    .await?)
+}
+
+async fn insert_user_tag(
+    executor: impl SqliteExecutor<'_>,
+    user_id: &Uuid,
+    tag: &Tag,
+) -> Result<(), DatabaseError> {
+    sqlx::query("INSERT INTO users_tags (user_id, tag_id) VALUES ($1, $2)")
+        .bind(user_id)
+        .bind(tag.id)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+async fn insert_passkey(
+    executor: impl SqliteExecutor<'_>,
+    id: &Uuid,
+    user_id: &Uuid,
+    passkey: &NewPasskeyCredential,
+) -> Result<PasskeyCredential, DatabaseError> {
+    let attestation = PasskeyAttestation::from(&passkey.passkey);
+    let passkey: PasskeyCredential = sqlx::query_as(
+        "INSERT INTO passkeys (id, user_id, passkey, credential_id, display_name, aaguid,
+            attestation_format, created_at, last_used_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, unixepoch(), unixepoch())
+         RETURNING *",
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(ViaJson(&passkey.passkey))
+    .bind(passkey.passkey.cred_id().as_ref())
+    .bind(&passkey.display_name)
+    .bind(attestation.aaguid)
+    .bind(attestation.format_name())
+    .fetch_one(executor)
+    .await?;
+    Ok(passkey)
+}
+
+async fn insert_session(
+    executor: impl SqliteExecutor<'_>,
+    session: &Session,
+) -> Result<(), DatabaseError> {
+    sqlx::query(
+        "INSERT INTO sessions (id_hash, user_id, created_at, expires_at, state, is_admin, parent_id_hash,
+            ip, user_agent, last_seen_at, impersonator_id, authenticated_at, organization_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
+    )
+    .bind(session.id_hash)
+    .bind(session.user_id)
+    .bind(session.created_at.timestamp())
+    .bind(session.expires_at.timestamp())
+    .bind(session.state)
+    .bind(session.is_admin)
+    .bind(session.parent_id_hash)
+    .bind(&session.ip)
+    .bind(&session.user_agent)
+    .bind(session.last_seen_at.timestamp())
+    .bind(session.impersonator_id)
+    .bind(session.authenticated_at.timestamp())
+    .bind(session.organization_id)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Makes the user with the UUID `target_id` a member of the organizations and groups of which the
+/// user with the UUID `source_id` is a member, with the same organization roles. The target keeps
+/// their role in organizations of which they are already a member.
+async fn copy_memberships(
+    conn: &mut SqliteConnection,
+    source_id: &Uuid,
+    target_id: &Uuid,
+) -> Result<(), DatabaseError> {
+    sqlx::query(
+        "INSERT INTO organization_members (organization_id, user_id, role, created_at)
+        SELECT organization_id, $2, role, created_at FROM organization_members
+        WHERE user_id = $1
+        ON CONFLICT (organization_id, user_id) DO NOTHING",
+    )
+    .bind(source_id)
+    .bind(target_id)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(
+        "INSERT INTO user_groups_users (group_id, user_id)
+        SELECT group_id, $2 FROM user_groups_users
+        WHERE user_id = $1
+        ON CONFLICT (group_id, user_id) DO NOTHING",
+    )
+    .bind(source_id)
+    .bind(target_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Fetches the membership of a user in an organization. See
+/// [`DatabaseClient::get_organization_member()`].
+async fn fetch_organization_member(
+    executor: impl SqliteExecutor<'_>,
+    organization_id: &Uuid,
+    user_id: &Uuid,
+) -> Result<OrganizationMember, DatabaseError> {
+    let member: OrganizationMember = sqlx::query_as(
+        "SELECT u.*, m.organization_id, m.role, m.created_at AS joined_at
+        FROM users u
+        INNER JOIN organization_members m
+        ON u.id = m.user_id
+        WHERE m.organization_id = $1 AND m.user_id = $2",
+    )
+    .bind(organization_id)
+    .bind(user_id)
+    .fetch_one(executor)
+    .await?;
+    Ok(member)
+}
+
+async fn apply_session_update(
+    executor: impl SqliteExecutor<'_>,
+    id_hash: &EncodableHash,
+    update: &SessionUpdate,
+) -> Result<Session, DatabaseError> {
+    let mut query = UpdateQuery::new("sessions");
+    query
+        .set("state", update.state)
+        .set("expires_at", update.expires_at.map(|time| time.timestamp()))
+        .set(
+            "last_seen_at",
+            update.last_seen_at.map(|time| time.timestamp()),
+        )
+        .set("ip", update.ip.as_deref())
+        .set("user_agent", update.user_agent.as_deref())
+        .set(
+            "authenticated_at",
+            update.authenticated_at.map(|time| time.timestamp()),
+        )
+        .set("organization_id", update.organization_id);
+    let session: Session = query
+        .finish("id_hash", id_hash, "*")?
+        .build_query_as()
+        .fetch_one(executor)
+        .await?;
+    Ok(session)
 }
 
-/// Cleans up expired passkey registrations and authentications.
-async fn do_cleanup(pool: &SqlitePool) {
-    if let Err(err) =
-        sqlx::query("DELETE FROM passkey_registrations WHERE created_at < unixepoch() - 300")
-            .execute(pool)
-            .await
-    {
-        error!(%err, "failed to cleanup passkey registrations");
-    }
-    if let Err(err) =
-        sqlx::query("DELETE FROM passkey_authentications WHERE created_at < unixepoch() - 300")
-            .execute(pool)
-            .await
-    {
-        error!(%err, "failed to cleanup passkey authentications");
+/// Deletes the sessions which ended before the given timestamp. See
+/// [`DatabaseClient::delete_ended_sessions()`].
+async fn delete_ended_sessions(pool: &SqlitePool, cutoff: i64) -> Result<u64, DatabaseError> {
+    let mut total = 0;
+    // Sessions which are the parent of another session can't be deleted until their children
+    // are, so repeat until no more can be deleted
+    loop {
+        let deleted = sqlx::query(
+            "DELETE FROM sessions
+            WHERE (expires_at < $1 OR (state != $2 AND last_seen_at < $1))
+                AND id_hash NOT IN (
+                    SELECT parent_id_hash FROM sessions
+                    WHERE parent_id_hash IS NOT NULL
+                )",
+        )
+        .bind(cutoff)
+        .bind(SessionState::Active)
+        .execute(pool)
+        .await?
+        .rows_affected();
+        if deleted == 0 {
+            return Ok(total);
+        }
+        total += deleted;
     }
 }
 
@@ -2,6 +2,10 @@
 //!
 //! *TODO: extract these into a common UT suite that can be run on all [`DatabaseClient`]s*
 
+mod proptests;
+
+use std::time::Duration;
+
 use chrono::SubsecRound;
 use uuid::Uuid;
 use webauthn_rs::{
@@ -9,13 +13,23 @@ use webauthn_rs::{
     prelude::{Passkey, Url},
 };
 
-use super::SqliteClient;
+use super::{
+    CreateSqliteClientError, MigrationError, MigrationStatus, PendingMigrations, SqliteClient,
+};
 use crate::{
-    db::interface::DatabaseClient,
+    db::interface::{DatabaseClient, DatabaseError},
     models::{
-        NewPasskeyCredential, PasskeyAuthenticationState, PasskeyAuthenticationStateType,
-        PasskeyCredentialUpdate, PasskeyRegistrationState, Session, SessionState, SessionUpdate,
-        UserCreate, ViaJson,
+        Announcement, AnnouncementSeverity, ApiToken, ApiTokenScope, AuthorizationCode, Backup,
+        EncodableHash, FederatedIdentity, FederatedLoginState, FederatedProvider,
+        FederatedProviderUpdate, Group, GroupUpdate, IpBan, IpBanSource, MaintenanceTask,
+        NewPasskeyCredential, OAuthClient, OAuthClientUpdate, Organization, OrganizationRole,
+        OrganizationUpdate, PasskeyAuthenticationState, PasskeyAuthenticationStateType,
+        PasskeyCredentialUpdate, PasskeyRegistrationState, Permission, PolicyDocument,
+        PolicyEffect, PolicyStatement, PolicyUpdate, RetentionPolicy, RetentionReport, RoleUpdate,
+        SamlNameIdFormat, SamlServiceProvider, SamlServiceProviderUpdate, Session, SessionState,
+        SessionUpdate, Sort, StoredSessionCookieKey, StoredSigningKey, TagUpdate, User, UserCreate,
+        UserFilter, UserListQuery, UserNote, UserSort, UserStatus, UserUpdate, ViaJson, Webhook,
+        WebhookEventType, WebhookUpdate,
     },
 };
 
@@ -60,6 +74,43 @@ async fn test_create_user() {
     assert_eq!(user.display_name(), "Test User");
 }
 
+#[tokio::test]
+async fn test_user_emails_are_normalized() {
+    let Tools { client, .. } = tools().await;
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: " Test@Example.COM ".to_string(),
+                display_name: "Test User".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(user.email(), "test@example.com");
+    let found = client.get_user_by_email("TEST@example.com").await.unwrap();
+    assert_eq!(found.id(), user.id());
+
+    // Addresses differing only in case belong to the same user
+    let result = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "test@EXAMPLE.com".to_string(),
+                display_name: "Other User".to_string(),
+            },
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(DatabaseError::UniquenessViolation { .. })
+    ));
+
+    let update = UserUpdate::new().with_email("New@Example.com".to_string());
+    let user = client.update_user(user.id(), &update).await.unwrap();
+    assert_eq!(user.email(), "new@example.com");
+}
+
 #[tokio::test]
 async fn test_create_passkey_registration() {
     let Tools { client, webauthn } = tools().await;
@@ -73,6 +124,7 @@ async fn test_create_passkey_registration() {
         id: Uuid::new_v4(),
         user_id,
         email: email.to_string(),
+        display_name: display_name.to_string(),
         registration: ViaJson(reg),
         created_at: chrono::Utc::now(),
     };
@@ -97,6 +149,7 @@ async fn test_get_passkey_registration_by_id() {
         id,
         user_id,
         email: email.to_string(),
+        display_name: display_name.to_string(),
         registration: ViaJson(reg),
         created_at: chrono::Utc::now(),
     };
@@ -109,6 +162,40 @@ async fn test_get_passkey_registration_by_id() {
     let registration = client.get_passkey_registration_by_id(&id).await.unwrap();
     assert_eq!(registration.user_id, user_id);
     assert_eq!(registration.email, email);
+    assert_eq!(registration.display_name, display_name);
+}
+
+#[tokio::test]
+async fn test_delete_passkey_registration_by_id() {
+    let Tools { client, webauthn } = tools().await;
+    let id = Uuid::new_v4();
+    let user_id = Uuid::new_v4();
+    let (_, reg) = webauthn
+        .start_passkey_registration(user_id, "test@kasad.com", "Test User", None)
+        .unwrap();
+    let registration = PasskeyRegistrationState {
+        id,
+        user_id,
+        email: "test@kasad.com".to_string(),
+        display_name: "Test User".to_string(),
+        registration: ViaJson(reg),
+        created_at: chrono::Utc::now(),
+    };
+    client
+        .create_passkey_registration(&registration)
+        .await
+        .unwrap();
+
+    client.delete_passkey_registration_by_id(&id).await.unwrap();
+    assert!(matches!(
+        client.get_passkey_registration_by_id(&id).await,
+        Err(DatabaseError::NotFound)
+    ));
+    // A state can only be consumed once
+    assert!(matches!(
+        client.delete_passkey_registration_by_id(&id).await,
+        Err(DatabaseError::NotFound)
+    ));
 }
 
 #[tokio::test]
@@ -137,6 +224,12 @@ async fn test_create_session() {
         expires_at: chrono::Utc::now() + chrono::Duration::days(1),
         is_admin: false,
         parent_id_hash: None,
+        ip: None,
+        user_agent: None,
+        last_seen_at: chrono::Utc::now(),
+        impersonator_id: None,
+        authenticated_at: chrono::Utc::now(),
+        organization_id: None,
     };
     client.create_session(&session).await.unwrap();
 }
@@ -167,6 +260,12 @@ async fn test_get_session_by_id_hash() {
         expires_at: chrono::Utc::now() + chrono::Duration::days(1),
         is_admin: false,
         parent_id_hash: None,
+        ip: None,
+        user_agent: None,
+        last_seen_at: chrono::Utc::now(),
+        impersonator_id: Some(Uuid::nil()),
+        authenticated_at: chrono::Utc::now(),
+        organization_id: None,
     };
     client.create_session(&session).await.unwrap();
 
@@ -180,6 +279,7 @@ async fn test_get_session_by_id_hash() {
     assert_eq!(session.state, SessionState::Active);
     assert_eq!(session.created_at, session.created_at);
     assert_eq!(session.expires_at, session.expires_at);
+    assert_eq!(session.impersonator_id, Some(Uuid::nil()));
 }
 
 #[tokio::test]
@@ -211,6 +311,65 @@ async fn test_create_passkey() {
         .unwrap();
 }
 
+#[tokio::test]
+async fn test_get_user_with_relations() {
+    let Tools { client, .. } = tools().await;
+    let user_id = Uuid::new_v4();
+    client
+        .create_user(
+            &user_id,
+            &UserCreate {
+                email: "test@kasad.com".to_string(),
+                display_name: "Test User".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+
+    // Relations are empty, rather than unpopulated, for users without any
+    let mut user = client.get_user_with_relations(&user_id).await.unwrap();
+    assert!(user.tags().unwrap().is_empty());
+
+    let passkey: Passkey =
+        serde_json::from_str(include_str!("tests/resources/passkey.json")).unwrap();
+    let passkey = client
+        .create_passkey(
+            &Uuid::new_v4(),
+            &user_id,
+            &NewPasskeyCredential {
+                display_name: Some("Laptop".to_string()),
+                passkey,
+            },
+        )
+        .await
+        .unwrap();
+    for name in ["beta", "alpha"] {
+        let tag = client
+            .create_tag(
+                &Uuid::new_v4(),
+                None,
+                &TagUpdate::new().with_name(name.to_string()),
+            )
+            .await
+            .unwrap();
+        client.add_tag_to_user(&user_id, &tag).await.unwrap();
+    }
+
+    let mut user = client.get_user_with_relations(&user_id).await.unwrap();
+    assert_eq!(user.email(), "test@kasad.com");
+    let passkeys = user.fetch_passkeys(&client).await.unwrap();
+    assert_eq!(passkeys.len(), 1);
+    assert_eq!(passkeys[0].id, passkey.id);
+    assert_eq!(passkeys[0].display_name.as_deref(), Some("Laptop"));
+    let names: Vec<_> = user.tags().unwrap().iter().map(|tag| &tag.name).collect();
+    assert_eq!(names, ["alpha", "beta"]);
+
+    assert!(matches!(
+        client.get_user_with_relations(&Uuid::new_v4()).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
 #[tokio::test]
 async fn test_non_discoverable_passkey_authentication() {
     let Tools { client, webauthn } = tools().await;
@@ -376,6 +535,12 @@ async fn test_update_session() {
         expires_at: chrono::Utc::now() + chrono::Duration::days(1),
         is_admin: false,
         parent_id_hash: None,
+        ip: None,
+        user_agent: None,
+        last_seen_at: chrono::Utc::now(),
+        impersonator_id: None,
+        authenticated_at: chrono::Utc::now(),
+        organization_id: None,
     };
     client.create_session(&session).await.unwrap();
 
@@ -398,43 +563,2618 @@ async fn test_update_session() {
 }
 
 #[tokio::test]
-async fn test_cleanup_registrations() {
+async fn test_update_user_login_notifications() {
     let Tools { client, .. } = tools().await;
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "test@kasad.com".to_string(),
+                display_name: "Test User".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    assert!(user.login_notifications());
 
-    // Create expired registration
-    sqlx::query("INSERT INTO passkey_registrations (id, user_id, email, registration, created_at) VALUES (?, ?, ?, ?, unixepoch() - 3600)")
-        .bind(Uuid::new_v4())
-        .bind(Uuid::new_v4())
-        .bind("test@kasad.com")
-        .bind("{}")
-        .execute(&client.pool)
+    let update = UserUpdate::new().with_login_notifications(false);
+    let user = client.update_user(user.id(), &update).await.unwrap();
+    assert!(!user.login_notifications());
+    assert_eq!(user.display_name(), "Test User");
+}
+
+#[tokio::test]
+async fn test_record_user_login() {
+    let Tools { client, .. } = tools().await;
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "test@kasad.com".to_string(),
+                display_name: "Test User".to_string(),
+            },
+        )
         .await
         .unwrap();
+    assert!(user.last_login_at().is_none());
 
-    // Create non-expired registration
-    sqlx::query("INSERT INTO passkey_registrations (id, user_id, email, registration, created_at) VALUES (?, ?, ?, ?, unixepoch())")
-        .bind(Uuid::new_v4())
-        .bind(Uuid::new_v4())
-        .bind("test@kasad.com")
-        .bind("{}")
+    client.record_user_login(user.id()).await.unwrap();
+    let updated = client.get_user_by_id(user.id()).await.unwrap();
+    assert!(updated.last_login_at().unwrap() >= user.created_at());
+    // Logging in isn't a change to the user's profile
+    assert_eq!(updated.updated_at(), user.updated_at());
+
+    assert!(matches!(
+        client.record_user_login(&Uuid::new_v4()).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test_login_failures_and_locks() {
+    let Tools { client, .. } = tools().await;
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "test@kasad.com".to_string(),
+                display_name: "Test User".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    assert!(user.locked_until().is_none());
+    assert!(!user.is_locked());
+
+    let window = Duration::from_secs(60);
+    for expected in 1..=3 {
+        let count = client
+            .record_login_failure(user.id(), None, window)
+            .await
+            .unwrap();
+        assert_eq!(count, expected);
+    }
+
+    // Failures older than the window are forgotten
+    sqlx::query("UPDATE login_failures SET created_at = created_at - 3600")
         .execute(&client.pool)
         .await
         .unwrap();
+    let count = client
+        .record_login_failure(user.id(), None, window)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+    client.clear_login_failures(user.id()).await.unwrap();
+    let count = client
+        .record_login_failure(user.id(), None, window)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
 
-    // Verify creations worked
-    let registrations: u32 = sqlx::query_scalar("SELECT COUNT(*) FROM passkey_registrations")
-        .fetch_one(&client.pool)
+    // Locking and unlocking forget the failures
+    let until = (chrono::Utc::now() + chrono::Duration::minutes(5)).trunc_subsecs(0);
+    client
+        .set_user_locked_until(user.id(), Some(until))
         .await
         .unwrap();
-    assert_eq!(registrations, 2);
+    let locked = client.get_user_by_id(user.id()).await.unwrap();
+    assert_eq!(locked.locked_until(), Some(until));
+    assert!(locked.is_locked());
+    let count = client
+        .record_login_failure(user.id(), None, window)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+    client.set_user_locked_until(user.id(), None).await.unwrap();
+    let unlocked = client.get_user_by_id(user.id()).await.unwrap();
+    assert!(unlocked.locked_until().is_none());
+    let count = client
+        .record_login_failure(user.id(), None, window)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
 
-    // Cleanup
-    super::do_cleanup(&client.pool).await;
+    assert!(matches!(
+        client.set_user_locked_until(&Uuid::new_v4(), None).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
 
-    // Verify cleanup worked
-    let registrations: u32 = sqlx::query_scalar("SELECT COUNT(*) FROM passkey_registrations")
-        .fetch_one(&client.pool)
+#[tokio::test]
+async fn test_user_status() {
+    let Tools { client, .. } = tools().await;
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "test@kasad.com".to_string(),
+                display_name: "Test User".to_string(),
+            },
+        )
         .await
         .unwrap();
-    assert_eq!(registrations, 1);
+    assert_eq!(user.status(), UserStatus::Active);
+
+    for status in [
+        UserStatus::Suspended,
+        UserStatus::Deactivated,
+        UserStatus::Active,
+    ] {
+        let updated = client.set_user_status(user.id(), status).await.unwrap();
+        assert_eq!(updated.status(), status);
+        let fetched = client.get_user_by_id(user.id()).await.unwrap();
+        assert_eq!(fetched.status(), status);
+    }
+
+    assert!(matches!(
+        client
+            .set_user_status(&Uuid::new_v4(), UserStatus::Suspended)
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test_user_notes() {
+    let Tools { client, .. } = tools().await;
+    let mut users = Vec::new();
+    for email in ["test@kasad.com", "admin@kasad.com"] {
+        let user = client
+            .create_user(
+                &Uuid::new_v4(),
+                &UserCreate {
+                    email: email.to_string(),
+                    display_name: "Test User".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        users.push(user);
+    }
+    let [user, admin] = users.as_slice() else {
+        unreachable!()
+    };
+    assert!(client.get_user_notes(user.id()).await.unwrap().is_empty());
+
+    // Revisions with equal timestamps are still ordered newest first
+    let now = chrono::Utc::now().trunc_subsecs(0);
+    for body in ["First", "Second"] {
+        let note = UserNote {
+            id: Uuid::new_v4(),
+            user_id: *user.id(),
+            author_id: Some(*admin.id()),
+            body: body.to_string(),
+            created_at: now,
+        };
+        let created = client.create_user_note(&note).await.unwrap();
+        assert_eq!(created.body, body);
+        assert_eq!(created.created_at, now);
+    }
+    let notes = client.get_user_notes(user.id()).await.unwrap();
+    let bodies: Vec<_> = notes.iter().map(|note| note.body.as_str()).collect();
+    assert_eq!(bodies, ["Second", "First"]);
+
+    // Deleting the author keeps their notes
+    client.delete_user_by_id(admin.id()).await.unwrap();
+    let notes = client.get_user_notes(user.id()).await.unwrap();
+    assert_eq!(notes.len(), 2);
+    assert!(notes.iter().all(|note| note.author_id.is_none()));
+
+    client.delete_user_by_id(user.id()).await.unwrap();
+    assert!(client.get_user_notes(user.id()).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_revoke_session_by_token() {
+    let Tools { client, .. } = tools().await;
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "test@kasad.com".to_string(),
+                display_name: "Test User".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+
+    // Create a session which was upgraded to a child session
+    let parent = Session {
+        user_id: *user.id(),
+        id_hash: blake3::hash(b"parent").into(),
+        state: SessionState::Superseded,
+        created_at: chrono::Utc::now(),
+        expires_at: chrono::Utc::now() + chrono::Duration::days(1),
+        is_admin: false,
+        parent_id_hash: None,
+        ip: None,
+        user_agent: None,
+        last_seen_at: chrono::Utc::now(),
+        impersonator_id: None,
+        authenticated_at: chrono::Utc::now(),
+        organization_id: None,
+    };
+    client.create_session(&parent).await.unwrap();
+    let child = Session {
+        id_hash: blake3::hash(b"child").into(),
+        state: SessionState::Active,
+        is_admin: true,
+        parent_id_hash: Some(parent.id_hash),
+        ..parent.clone()
+    };
+    client.create_session(&child).await.unwrap();
+
+    let token_hash = blake3::hash(b"token").into();
+    client
+        .create_session_revocation_token(&token_hash, &parent.id_hash)
+        .await
+        .unwrap();
+    client.revoke_session_by_token(&token_hash).await.unwrap();
+
+    // Only active sessions are revoked
+    let parent = client
+        .get_session_by_id_hash(&parent.id_hash)
+        .await
+        .unwrap();
+    assert_eq!(parent.state, SessionState::Superseded);
+    let child = client.get_session_by_id_hash(&child.id_hash).await.unwrap();
+    assert_eq!(child.state, SessionState::Revoked);
+
+    // Tokens are single-use
+    assert!(matches!(
+        client.revoke_session_by_token(&token_hash).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test_email_changes() {
+    let Tools { client, .. } = tools().await;
+    let mut users = Vec::new();
+    for email in ["test@kasad.com", "other@kasad.com"] {
+        let user = UserCreate {
+            email: email.to_string(),
+            display_name: "Test User".to_string(),
+        };
+        users.push(client.create_user(&Uuid::new_v4(), &user).await.unwrap());
+    }
+    let user = &users[0];
+    assert!(user.email_verified_at().is_none());
+    let max_age = Duration::from_secs(60);
+
+    // A new request replaces the pending change
+    let first = blake3::hash(b"first").into();
+    client
+        .create_email_change(user.id(), "first@kasad.com", &first)
+        .await
+        .unwrap();
+    let second = blake3::hash(b"second").into();
+    client
+        .create_email_change(user.id(), "Second@Kasad.com", &second)
+        .await
+        .unwrap();
+    assert!(matches!(
+        client.confirm_email_change(&first, max_age).await,
+        Err(DatabaseError::NotFound)
+    ));
+    let confirmed = client.confirm_email_change(&second, max_age).await.unwrap();
+    assert_eq!(confirmed.email(), "second@kasad.com");
+    assert!(confirmed.email_verified_at().is_some());
+    assert!(matches!(
+        client.confirm_email_change(&second, max_age).await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // Addresses taken in the meantime can't be confirmed
+    client
+        .create_email_change(user.id(), "other@kasad.com", &first)
+        .await
+        .unwrap();
+    assert!(matches!(
+        client.confirm_email_change(&first, max_age).await,
+        Err(DatabaseError::UniquenessViolation { .. })
+    ));
+    let unchanged = client.get_user_by_id(user.id()).await.unwrap();
+    assert_eq!(unchanged.email(), "second@kasad.com");
+
+    // Changing the address by other means makes it unverified
+    let update = UserUpdate::new().with_email("third@kasad.com".to_string());
+    let updated = client.update_user(user.id(), &update).await.unwrap();
+    assert!(updated.email_verified_at().is_none());
+}
+
+#[tokio::test]
+async fn test_revoke_all_sessions() {
+    let Tools { client, .. } = tools().await;
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "test@kasad.com".to_string(),
+                display_name: "Test User".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    let session = |name: &[u8], state| Session {
+        user_id: *user.id(),
+        id_hash: blake3::hash(name).into(),
+        state,
+        created_at: chrono::Utc::now(),
+        expires_at: chrono::Utc::now() + chrono::Duration::days(1),
+        is_admin: false,
+        parent_id_hash: None,
+        ip: None,
+        user_agent: None,
+        last_seen_at: chrono::Utc::now(),
+        impersonator_id: None,
+        authenticated_at: chrono::Utc::now(),
+        organization_id: None,
+    };
+    let sessions = [
+        session(b"caller", SessionState::Active),
+        session(b"other", SessionState::Active),
+        session(b"logged-out", SessionState::LoggedOut),
+    ];
+    for session in &sessions {
+        client.create_session(session).await.unwrap();
+    }
+
+    // Only active sessions other than the excluded one are revoked
+    let revoked = client
+        .revoke_all_sessions(Some(&sessions[0].id_hash))
+        .await
+        .unwrap();
+    assert_eq!(revoked, 1);
+    let states = [
+        SessionState::Active,
+        SessionState::Revoked,
+        SessionState::LoggedOut,
+    ];
+    for (session, state) in sessions.iter().zip(states) {
+        let session = client
+            .get_session_by_id_hash(&session.id_hash)
+            .await
+            .unwrap();
+        assert_eq!(session.state, state);
+    }
+
+    assert_eq!(client.revoke_all_sessions(None).await.unwrap(), 1);
+    let caller = client
+        .get_session_by_id_hash(&sessions[0].id_hash)
+        .await
+        .unwrap();
+    assert_eq!(caller.state, SessionState::Revoked);
+}
+
+#[tokio::test]
+async fn test_revoke_sessions_by_user_id() {
+    let Tools { client, .. } = tools().await;
+    let mut users = Vec::new();
+    for email in ["a@kasad.com", "b@kasad.com"] {
+        let user = UserCreate {
+            email: email.to_string(),
+            display_name: "Test User".to_string(),
+        };
+        users.push(client.create_user(&Uuid::new_v4(), &user).await.unwrap());
+    }
+    let session = |user: &User, name: &[u8]| Session {
+        user_id: *user.id(),
+        id_hash: blake3::hash(name).into(),
+        state: SessionState::Active,
+        created_at: chrono::Utc::now(),
+        expires_at: chrono::Utc::now() + chrono::Duration::days(1),
+        is_admin: false,
+        parent_id_hash: None,
+        ip: None,
+        user_agent: None,
+        last_seen_at: chrono::Utc::now(),
+        impersonator_id: None,
+        authenticated_at: chrono::Utc::now(),
+        organization_id: None,
+    };
+    let sessions = [
+        session(&users[0], b"a1"),
+        session(&users[0], b"a2"),
+        session(&users[1], b"b"),
+    ];
+    for session in &sessions {
+        client.create_session(session).await.unwrap();
+    }
+
+    let revoked = client
+        .revoke_sessions_by_user_id(users[0].id())
+        .await
+        .unwrap();
+    assert_eq!(revoked, 2);
+    let states = [
+        SessionState::Revoked,
+        SessionState::Revoked,
+        SessionState::Active,
+    ];
+    for (session, state) in sessions.iter().zip(states) {
+        let session = client
+            .get_session_by_id_hash(&session.id_hash)
+            .await
+            .unwrap();
+        assert_eq!(session.state, state);
+    }
+}
+
+#[tokio::test]
+async fn test_record_user_device() {
+    let Tools { client, .. } = tools().await;
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "test@kasad.com".to_string(),
+                display_name: "Test User".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    let device = blake3::hash(b"device").into();
+    assert!(client.record_user_device(user.id(), &device).await.unwrap());
+    assert!(!client.record_user_device(user.id(), &device).await.unwrap());
+    assert!(
+        client
+            .record_user_device(user.id(), &blake3::hash(b"other device").into())
+            .await
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_stored_emails_are_normalized() {
+    let Tools { client, .. } = tools().await;
+    let mut ids = Vec::new();
+    for email in ["emile@example.com", "zoe@example.com"] {
+        let user = UserCreate {
+            email: email.to_string(),
+            display_name: "Test User".to_string(),
+        };
+        ids.push(
+            *client
+                .create_user(&Uuid::new_v4(), &user)
+                .await
+                .unwrap()
+                .id(),
+        );
+    }
+    // Addresses stored before normalization, which SQLite's lower() can't fold
+    for (id, email) in ids.iter().zip(["ÉMILE@Example.com", "ZOË@example.com"]) {
+        sqlx::query("UPDATE users SET email = $1 WHERE id = $2")
+            .bind(email)
+            .bind(id)
+            .execute(&client.pool)
+            .await
+            .unwrap();
+    }
+    let third = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO users (id, email, display_name, created_at, updated_at)
+        SELECT $1, 'Zoë@example.com', display_name, created_at, updated_at FROM users WHERE id = $2",
+    )
+    .bind(third)
+    .bind(ids[1])
+    .execute(&client.pool)
+    .await
+    .unwrap();
+    for address in [
+        "Bounce@Example.com",
+        "BOUNCE@example.com",
+        "ÉMILE@example.com",
+    ] {
+        sqlx::query(
+            "INSERT INTO email_suppressions (address, reason, created_at)
+            VALUES ($1, 'bounced', unixepoch())",
+        )
+        .bind(address)
+        .execute(&client.pool)
+        .await
+        .unwrap();
+    }
+
+    super::emails::normalize_stored_emails(&client.pool)
+        .await
+        .unwrap();
+    let user = client.get_user_by_email("Émile@example.com").await.unwrap();
+    assert_eq!(user.id(), &ids[0]);
+    assert_eq!(user.email(), "émile@example.com");
+
+    // Addresses which would collide are left alone
+    assert_eq!(
+        client.get_user_by_id(&ids[1]).await.unwrap().email(),
+        "ZOË@example.com"
+    );
+    assert_eq!(
+        client.get_user_by_id(&third).await.unwrap().email(),
+        "Zoë@example.com"
+    );
+
+    let mut addresses: Vec<_> = client
+        .list_email_suppressions()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|suppression| suppression.address)
+        .collect();
+    addresses.sort();
+    assert_eq!(addresses, ["bounce@example.com", "émile@example.com"]);
+    assert!(
+        client
+            .get_email_suppression("Émile@Example.com")
+            .await
+            .is_ok()
+    );
+}
+
+#[tokio::test]
+async fn test_email_suppressions() {
+    let Tools { client, .. } = tools().await;
+    assert!(matches!(
+        client.get_email_suppression("test@kasad.com").await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    client
+        .create_email_suppression("test@kasad.com", "mailbox full")
+        .await
+        .unwrap();
+    // Suppressing again replaces the reason
+    let suppression = client
+        .create_email_suppression("test@kasad.com", "no such user")
+        .await
+        .unwrap();
+    assert_eq!(suppression.reason, "no such user");
+    let fetched = client
+        .get_email_suppression("test@kasad.com")
+        .await
+        .unwrap();
+    assert_eq!(fetched.reason, "no such user");
+    assert_eq!(client.list_email_suppressions().await.unwrap().len(), 1);
+
+    client
+        .delete_email_suppression("test@kasad.com")
+        .await
+        .unwrap();
+    assert!(client.list_email_suppressions().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_ip_bans() {
+    let Tools { client, .. } = tools().await;
+    let ban = |network: &str, expires_at| IpBan {
+        id: Uuid::new_v4(),
+        network: network.to_string(),
+        reason: "abuse".to_string(),
+        source: IpBanSource::Manual,
+        created_at: chrono::Utc::now(),
+        expires_at,
+    };
+    let created = client
+        .create_ip_ban(&ban("192.0.2.0/24", None))
+        .await
+        .unwrap();
+    // Banning the same network again replaces the ban but keeps its ID
+    let mut update = ban("192.0.2.0/24", None);
+    update.source = IpBanSource::Automatic;
+    let replaced = client.create_ip_ban(&update).await.unwrap();
+    assert_eq!(replaced.id, created.id);
+    assert_eq!(replaced.source, IpBanSource::Automatic);
+    // Expired bans aren't listed
+    let past = chrono::Utc::now() - chrono::Duration::hours(1);
+    client
+        .create_ip_ban(&ban("2001:db8::/32", Some(past)))
+        .await
+        .unwrap();
+    let bans = client.list_ip_bans().await.unwrap();
+    assert_eq!(bans.len(), 1);
+    assert_eq!(bans[0].network, "192.0.2.0/24");
+
+    client.delete_ip_ban_by_id(&created.id).await.unwrap();
+    assert!(client.list_ip_bans().await.unwrap().is_empty());
+    assert!(matches!(
+        client.delete_ip_ban_by_id(&created.id).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test_job_leases() {
+    let Tools { client, .. } = tools().await;
+    let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+    let ttl = Duration::from_secs(60);
+
+    assert!(client.try_acquire_lease("job", &first, ttl).await.unwrap());
+    // The holder can renew its lease, but nobody else can take it
+    assert!(client.try_acquire_lease("job", &first, ttl).await.unwrap());
+    assert!(!client.try_acquire_lease("job", &second, ttl).await.unwrap());
+    // Leases for other jobs are independent
+    assert!(
+        client
+            .try_acquire_lease("other", &second, ttl)
+            .await
+            .unwrap()
+    );
+
+    // Once the lease expires, another holder can take it over
+    sqlx::query("UPDATE job_leases SET expires_at = unixepoch() - 1 WHERE name = 'job'")
+        .execute(&client.pool)
+        .await
+        .unwrap();
+    assert!(client.try_acquire_lease("job", &second, ttl).await.unwrap());
+    assert!(!client.try_acquire_lease("job", &first, ttl).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_announcement() {
+    let Tools { client, .. } = tools().await;
+    assert!(matches!(
+        client.get_announcement().await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    let mut announcement = Announcement {
+        message: "Maintenance tonight".to_string(),
+        severity: AnnouncementSeverity::Warning,
+        starts_at: None,
+        ends_at: Some(chrono::Utc::now().trunc_subsecs(0) + chrono::Duration::hours(1)),
+        updated_at: chrono::Utc::now().trunc_subsecs(0),
+    };
+    client.set_announcement(&announcement).await.unwrap();
+    // Setting an announcement replaces the previous one
+    announcement.message = "Maintenance postponed".to_string();
+    client.set_announcement(&announcement).await.unwrap();
+    let fetched = client.get_announcement().await.unwrap();
+    assert_eq!(fetched.message, announcement.message);
+    assert_eq!(fetched.severity, AnnouncementSeverity::Warning);
+    assert_eq!(fetched.ends_at, announcement.ends_at);
+
+    client.delete_announcement().await.unwrap();
+    assert!(matches!(
+        client.delete_announcement().await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test_run_maintenance() {
+    let Tools { client, .. } = tools().await;
+    for task in [
+        MaintenanceTask::Optimize,
+        MaintenanceTask::Analyze,
+        MaintenanceTask::Vacuum,
+    ] {
+        client.run_maintenance(task).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_cleanup_registrations() {
+    let Tools { client, .. } = tools().await;
+
+    // Create expired registration
+    sqlx::query("INSERT INTO passkey_registrations (id, user_id, email, registration, created_at) VALUES (?, ?, ?, ?, unixepoch() - 3600)")
+        .bind(Uuid::new_v4())
+        .bind(Uuid::new_v4())
+        .bind("test@kasad.com")
+        .bind("{}")
+        .execute(&client.pool)
+        .await
+        .unwrap();
+
+    // Create non-expired registration
+    sqlx::query("INSERT INTO passkey_registrations (id, user_id, email, registration, created_at) VALUES (?, ?, ?, ?, unixepoch())")
+        .bind(Uuid::new_v4())
+        .bind(Uuid::new_v4())
+        .bind("test@kasad.com")
+        .bind("{}")
+        .execute(&client.pool)
+        .await
+        .unwrap();
+
+    // Verify creations worked
+    let registrations: u32 = sqlx::query_scalar("SELECT COUNT(*) FROM passkey_registrations")
+        .fetch_one(&client.pool)
+        .await
+        .unwrap();
+    assert_eq!(registrations, 2);
+
+    // Cleanup
+    let deleted = client
+        .delete_expired_ceremonies(Duration::from_secs(5 * 60))
+        .await
+        .unwrap();
+    assert_eq!(deleted, 1);
+
+    // Verify cleanup worked
+    let registrations: u32 = sqlx::query_scalar("SELECT COUNT(*) FROM passkey_registrations")
+        .fetch_one(&client.pool)
+        .await
+        .unwrap();
+    assert_eq!(registrations, 1);
+}
+
+#[tokio::test]
+async fn test_merge_users() {
+    let Tools { client, .. } = tools().await;
+    let mut users = Vec::new();
+    for email in ["test@kasad.com", "duplicate@kasad.com"] {
+        let user = client
+            .create_user(
+                &Uuid::new_v4(),
+                &UserCreate {
+                    email: email.to_string(),
+                    display_name: "Test User".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        users.push(user);
+    }
+    let [target, source] = users.as_slice() else {
+        unreachable!()
+    };
+    let passkey: Passkey =
+        serde_json::from_str(include_str!("tests/resources/passkey.json")).unwrap();
+    client
+        .create_passkey(
+            &Uuid::new_v4(),
+            source.id(),
+            &NewPasskeyCredential {
+                display_name: None,
+                passkey,
+            },
+        )
+        .await
+        .unwrap();
+    // The tag which both users have is not duplicated
+    for name in ["shared", "other"] {
+        let tag = client
+            .create_tag(
+                &Uuid::new_v4(),
+                None,
+                &TagUpdate::new().with_name(name.to_string()),
+            )
+            .await
+            .unwrap();
+        client.add_tag_to_user(source.id(), &tag).await.unwrap();
+        if name == "shared" {
+            client.add_tag_to_user(target.id(), &tag).await.unwrap();
+        }
+    }
+    let device = blake3::hash(b"device").into();
+    client
+        .record_user_device(source.id(), &device)
+        .await
+        .unwrap();
+    client
+        .record_user_device(target.id(), &device)
+        .await
+        .unwrap();
+    client.record_user_login(source.id()).await.unwrap();
+
+    // A dry run reports the changes without making them
+    let merge = client
+        .merge_users(source.id(), target.id(), true)
+        .await
+        .unwrap();
+    assert!(merge.dry_run);
+    assert_eq!(merge.source.id(), source.id());
+    assert_eq!((merge.passkeys, merge.tags, merge.devices), (1, 1, 1));
+    assert_eq!(
+        client
+            .get_passkeys_by_user_id(source.id())
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(client.get_user_by_id(source.id()).await.is_ok());
+
+    let merge = client
+        .merge_users(source.id(), target.id(), false)
+        .await
+        .unwrap();
+    assert!(!merge.dry_run);
+    assert_eq!((merge.passkeys, merge.tags, merge.devices), (1, 1, 1));
+    assert!(matches!(
+        client.get_user_by_id(source.id()).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert_eq!(
+        client
+            .get_passkeys_by_user_id(target.id())
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        client.get_tags_by_user_id(target.id()).await.unwrap().len(),
+        2
+    );
+    let target = client.get_user_by_id(target.id()).await.unwrap();
+    assert!(target.last_login_at().is_some());
+
+    assert!(matches!(
+        client.merge_users(source.id(), target.id(), true).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test_anonymize_user() {
+    let Tools { client, webauthn } = tools().await;
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "test@kasad.com".to_string(),
+                display_name: "Test User".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    let passkey: Passkey =
+        serde_json::from_str(include_str!("tests/resources/passkey.json")).unwrap();
+    client
+        .create_passkey(
+            &Uuid::new_v4(),
+            user.id(),
+            &NewPasskeyCredential {
+                display_name: None,
+                passkey: passkey.clone(),
+            },
+        )
+        .await
+        .unwrap();
+    let (_, auth_state) = webauthn.start_passkey_authentication(&[passkey]).unwrap();
+    let authentication = PasskeyAuthenticationState {
+        id: Uuid::new_v4(),
+        email: Some(user.email().to_string()),
+        state: ViaJson(PasskeyAuthenticationStateType::Regular(auth_state)),
+        created_at: chrono::Utc::now(),
+    };
+    client
+        .create_passkey_authentication(&authentication)
+        .await
+        .unwrap();
+    let now = chrono::Utc::now().trunc_subsecs(0);
+    let session = Session {
+        id_hash: blake3::hash(b"session").into(),
+        user_id: *user.id(),
+        state: SessionState::Active,
+        created_at: now,
+        expires_at: now + chrono::Duration::days(1),
+        is_admin: false,
+        parent_id_hash: None,
+        ip: Some("192.0.2.1".to_string()),
+        user_agent: Some("Test Agent".to_string()),
+        last_seen_at: now,
+        impersonator_id: None,
+        authenticated_at: now,
+        organization_id: None,
+    };
+    client.create_session(&session).await.unwrap();
+    client.record_user_login(user.id()).await.unwrap();
+
+    let anonymized = client.anonymize_user(user.id()).await.unwrap();
+    assert_eq!(anonymized.id(), user.id());
+    assert_ne!(anonymized.email(), user.email());
+    assert_ne!(anonymized.display_name(), user.display_name());
+    assert!(anonymized.anonymized_at().is_some());
+    // Statistics are preserved
+    assert_eq!(anonymized.created_at(), user.created_at());
+    assert!(anonymized.last_login_at().is_some());
+    assert!(matches!(
+        client.get_user_by_email("test@kasad.com").await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(
+        client
+            .get_passkeys_by_user_id(user.id())
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(matches!(
+        client
+            .get_passkey_authentication_by_id(&authentication.id)
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+    let session = client
+        .get_session_by_id_hash(&session.id_hash)
+        .await
+        .unwrap();
+    assert_eq!(session.state, SessionState::Revoked);
+    assert!(session.ip.is_none());
+    assert!(session.user_agent.is_none());
+
+    // Anonymizing again keeps the original time
+    let again = client.anonymize_user(user.id()).await.unwrap();
+    assert_eq!(again.email(), anonymized.email());
+    assert_eq!(again.anonymized_at(), anonymized.anonymized_at());
+}
+
+#[tokio::test]
+async fn test_purge_retained_data() {
+    let Tools { client, .. } = tools().await;
+    let mut users = Vec::new();
+    for email in ["test@kasad.com", "anonymized@kasad.com"] {
+        let user = client
+            .create_user(
+                &Uuid::new_v4(),
+                &UserCreate {
+                    email: email.to_string(),
+                    display_name: "Test User".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        users.push(user);
+    }
+    let [user, anonymized] = users.as_slice() else {
+        unreachable!()
+    };
+    let days_ago = |days| chrono::Utc::now().trunc_subsecs(0) - chrono::Duration::days(days);
+    let new_session =
+        |name: &str, user: &User, expired_days_ago, parent: Option<&Session>| Session {
+            id_hash: blake3::hash(name.as_bytes()).into(),
+            user_id: *user.id(),
+            state: SessionState::Superseded,
+            created_at: days_ago(expired_days_ago + 1),
+            expires_at: days_ago(expired_days_ago),
+            is_admin: false,
+            parent_id_hash: parent.map(|parent| parent.id_hash),
+            ip: None,
+            user_agent: None,
+            last_seen_at: days_ago(expired_days_ago + 1),
+            impersonator_id: None,
+            authenticated_at: days_ago(expired_days_ago + 1),
+            organization_id: None,
+        };
+    // An old session whose child was upgraded from it is deleted along with the child
+    let parent = new_session("parent", user, 60, None);
+    let child = new_session("child", user, 60, Some(&parent));
+    let recent = new_session("recent", user, 1, None);
+    let anonymized_session = new_session("anonymized", anonymized, 1, None);
+    for session in [&parent, &child, &recent, &anonymized_session] {
+        client.create_session(session).await.unwrap();
+    }
+    let old_device: EncodableHash = blake3::hash(b"old device").into();
+    for device in [&old_device, &blake3::hash(b"new device").into()] {
+        client.record_user_device(user.id(), device).await.unwrap();
+    }
+    sqlx::query("UPDATE user_devices SET last_seen_at = $1 WHERE id_hash = $2")
+        .bind(days_ago(60).timestamp())
+        .bind(old_device)
+        .execute(&client.pool)
+        .await
+        .unwrap();
+    client.anonymize_user(anonymized.id()).await.unwrap();
+    sqlx::query("UPDATE users SET anonymized_at = $1 WHERE id = $2")
+        .bind(days_ago(60).timestamp())
+        .bind(anonymized.id())
+        .execute(&client.pool)
+        .await
+        .unwrap();
+
+    // Without retention windows, nothing is purged
+    let report = client
+        .purge_retained_data(&RetentionPolicy::default())
+        .await
+        .unwrap();
+    assert_eq!(report.total(), 0);
+
+    let month = Duration::from_secs(30 * 24 * 60 * 60);
+    let policy = RetentionPolicy {
+        terminated_sessions: Some(month),
+        login_history: Some(month),
+        anonymized_users: Some(month),
+        dead_emails: Some(month),
+    };
+    let report = client.purge_retained_data(&policy).await.unwrap();
+    assert_eq!(
+        report,
+        RetentionReport {
+            sessions: 2,
+            devices: 1,
+            users: 1,
+            emails: 0,
+        }
+    );
+    assert!(client.get_session_by_id_hash(&recent.id_hash).await.is_ok());
+    assert!(matches!(
+        client.get_session_by_id_hash(&parent.id_hash).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(matches!(
+        client.get_user_by_id(anonymized.id()).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(client.get_user_by_id(user.id()).await.is_ok());
+    assert_eq!(
+        client.purge_retained_data(&policy).await.unwrap().total(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn test_delete_ended_sessions() {
+    let Tools { client, .. } = tools().await;
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "test@kasad.com".to_string(),
+                display_name: "Test User".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    let days_ago = |days| chrono::Utc::now().trunc_subsecs(0) - chrono::Duration::days(days);
+    let new_session = |name: &str, state, last_seen_days_ago, parent: Option<&Session>| Session {
+        id_hash: blake3::hash(name.as_bytes()).into(),
+        user_id: *user.id(),
+        state,
+        created_at: days_ago(last_seen_days_ago + 1),
+        expires_at: days_ago(-1),
+        is_admin: false,
+        parent_id_hash: parent.map(|parent| parent.id_hash),
+        ip: None,
+        user_agent: None,
+        last_seen_at: days_ago(last_seen_days_ago),
+        impersonator_id: None,
+        authenticated_at: days_ago(last_seen_days_ago),
+        organization_id: None,
+    };
+    // Sessions which haven't expired yet end when they're revoked or superseded, but active ones
+    // are kept however long ago they were last used
+    let revoked = new_session("revoked", SessionState::Revoked, 60, None);
+    let idle = new_session("idle", SessionState::Active, 60, None);
+    let recently_revoked = new_session("recently revoked", SessionState::Revoked, 1, None);
+    // A superseded parent is kept as long as its child is
+    let parent = new_session("parent", SessionState::Superseded, 60, None);
+    let child = new_session("child", SessionState::Active, 1, Some(&parent));
+    for session in [&revoked, &idle, &recently_revoked, &parent, &child] {
+        client.create_session(session).await.unwrap();
+    }
+
+    let month = Duration::from_secs(30 * 24 * 60 * 60);
+    assert_eq!(client.delete_ended_sessions(month).await.unwrap(), 1);
+    assert!(matches!(
+        client.get_session_by_id_hash(&revoked.id_hash).await,
+        Err(DatabaseError::NotFound)
+    ));
+    for session in [&idle, &recently_revoked, &parent, &child] {
+        client
+            .get_session_by_id_hash(&session.id_hash)
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_transactions() {
+    let Tools { client, .. } = tools().await;
+    let user = UserCreate {
+        email: "test@kasad.com".to_string(),
+        display_name: "Test User".to_string(),
+    };
+    let passkey = NewPasskeyCredential {
+        display_name: None,
+        passkey: serde_json::from_str(include_str!("tests/resources/passkey.json")).unwrap(),
+    };
+
+    // Dropping a transaction rolls it back
+    let user_id = Uuid::new_v4();
+    let mut tx = client.begin().await.unwrap();
+    tx.create_user(&user_id, &user).await.unwrap();
+    drop(tx);
+    assert!(matches!(
+        client.get_user_by_id(&user_id).await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // A failed operation doesn't leave the earlier ones behind
+    let mut tx = client.begin().await.unwrap();
+    tx.create_user(&user_id, &user).await.unwrap();
+    tx.create_passkey(&Uuid::new_v4(), &Uuid::new_v4(), &passkey)
+        .await
+        .unwrap_err();
+    drop(tx);
+    assert!(matches!(
+        client.get_user_by_id(&user_id).await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // Committed changes are visible outside the transaction
+    let session = Session {
+        user_id,
+        id_hash: blake3::hash(b"session").into(),
+        state: SessionState::Active,
+        created_at: chrono::Utc::now(),
+        expires_at: chrono::Utc::now() + chrono::Duration::days(1),
+        is_admin: false,
+        parent_id_hash: None,
+        ip: None,
+        user_agent: None,
+        last_seen_at: chrono::Utc::now(),
+        impersonator_id: None,
+        authenticated_at: chrono::Utc::now(),
+        organization_id: None,
+    };
+    let mut tx = client.begin().await.unwrap();
+    tx.create_user(&user_id, &user).await.unwrap();
+    tx.create_passkey(&Uuid::new_v4(), &user_id, &passkey)
+        .await
+        .unwrap();
+    tx.create_session(&session).await.unwrap();
+    let updated = tx
+        .update_session(
+            &session.id_hash,
+            &SessionUpdate::new().with_state(SessionState::Superseded),
+        )
+        .await
+        .unwrap();
+    assert_eq!(updated.state, SessionState::Superseded);
+    tx.commit().await.unwrap();
+    client.get_user_by_id(&user_id).await.unwrap();
+    assert_eq!(
+        client
+            .get_passkeys_by_user_id(&user_id)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        client
+            .get_session_by_id_hash(&session.id_hash)
+            .await
+            .unwrap()
+            .state,
+        SessionState::Superseded
+    );
+}
+
+#[tokio::test]
+async fn test_search_users() {
+    let Tools { client, .. } = tools().await;
+    let mut ids = Vec::new();
+    for (email, display_name) in [
+        ("alice.smith@kasad.com", "Alice Smith"),
+        ("smithers@kasad.com", "Waylon"),
+        ("bob@example.com", "Bob Jones"),
+    ] {
+        let user = client
+            .create_user(
+                &Uuid::new_v4(),
+                &UserCreate {
+                    email: email.to_string(),
+                    display_name: display_name.to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        ids.push(*user.id());
+    }
+
+    // Users matching in both fields rank first
+    let users = client.search_users("smith", 10).await.unwrap();
+    let found: Vec<_> = users.iter().map(User::id).copied().collect();
+    assert_eq!(found, [ids[0], ids[1]]);
+    let users = client.search_users("ALICE sm", 10).await.unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].id(), &ids[0]);
+    let users = client.search_users("example.com", 10).await.unwrap();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].id(), &ids[2]);
+    assert_eq!(client.search_users("smith", 1).await.unwrap().len(), 1);
+
+    // Operators are matched literally
+    assert!(client.search_users("\"OR*", 10).await.unwrap().is_empty());
+    assert!(client.search_users("  ", 10).await.unwrap().is_empty());
+
+    // The index follows changes to users
+    let update = UserUpdate::new().with_display_name("Robert".to_string());
+    client.update_user(&ids[2], &update).await.unwrap();
+    assert!(
+        client
+            .search_users("bob jones", 10)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(client.search_users("rob", 10).await.unwrap().len(), 1);
+    client.delete_user_by_id(&ids[2]).await.unwrap();
+    assert!(client.search_users("rob", 10).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_list_users() {
+    let Tools { client, .. } = tools().await;
+    for (email, display_name) in [
+        ("c@kasad.com", "Alice"),
+        ("a@kasad.com", "Bob"),
+        ("b@kasad.com", "Bob"),
+    ] {
+        client
+            .create_user(
+                &Uuid::new_v4(),
+                &UserCreate {
+                    email: email.to_string(),
+                    display_name: display_name.to_string(),
+                },
+            )
+            .await
+            .unwrap();
+    }
+
+    let by_email = UserListQuery::sorted(Sort::asc(UserSort::Email));
+    let page = client.list_users(&by_email, None, 2).await.unwrap();
+    assert_eq!(page.total, 3);
+    let emails: Vec<_> = page.users.iter().map(User::email).collect();
+    assert_eq!(emails, ["a@kasad.com", "b@kasad.com"]);
+    let next = page.next.unwrap();
+    let page = client.list_users(&by_email, Some(&next), 2).await.unwrap();
+    let emails: Vec<_> = page.users.iter().map(User::email).collect();
+    assert_eq!(emails, ["c@kasad.com"]);
+    assert!(page.next.is_none());
+
+    // Users with equal sort values are ordered by ID, so none are skipped or repeated
+    let by_name = UserListQuery::sorted(Sort::asc(UserSort::DisplayName));
+    let first = client.list_users(&by_name, None, 2).await.unwrap();
+    let rest = client
+        .list_users(&by_name, first.next.as_ref(), 2)
+        .await
+        .unwrap();
+    let mut emails: Vec<_> = first
+        .users
+        .iter()
+        .chain(&rest.users)
+        .map(User::email)
+        .collect();
+    assert_eq!(emails[0], "c@kasad.com");
+    emails.sort_unstable();
+    assert_eq!(emails, ["a@kasad.com", "b@kasad.com", "c@kasad.com"]);
+
+    let by_email_desc = UserListQuery::sorted(Sort::desc(UserSort::Email));
+    let page = client.list_users(&by_email_desc, None, 2).await.unwrap();
+    let emails: Vec<_> = page.users.iter().map(User::email).collect();
+    assert_eq!(emails, ["c@kasad.com", "b@kasad.com"]);
+    let page = client
+        .list_users(&by_email_desc, page.next.as_ref(), 2)
+        .await
+        .unwrap();
+    let emails: Vec<_> = page.users.iter().map(User::email).collect();
+    assert_eq!(emails, ["a@kasad.com"]);
+
+    // Filters match parts of values, ignoring case, and wildcards in them are literal
+    let bobs = by_email.clone().with_filter(UserFilter {
+        display_name: Some("BO".to_string()),
+        ..UserFilter::default()
+    });
+    let page = client.list_users(&bobs, None, 10).await.unwrap();
+    assert_eq!(page.total, 2);
+    let emails: Vec<_> = page.users.iter().map(User::email).collect();
+    assert_eq!(emails, ["a@kasad.com", "b@kasad.com"]);
+    let wildcard = by_email.clone().with_filter(UserFilter {
+        email: Some("%".to_string()),
+        ..UserFilter::default()
+    });
+    let page = client.list_users(&wildcard, None, 10).await.unwrap();
+    assert_eq!(page.total, 0);
+    assert!(page.users.is_empty());
+}
+
+#[tokio::test]
+async fn test_tags() {
+    let Tools { client, .. } = tools().await;
+    for name in ["beta", "alpha"] {
+        client
+            .create_tag(
+                &Uuid::new_v4(),
+                None,
+                &TagUpdate::new().with_name(name.to_string()),
+            )
+            .await
+            .unwrap();
+    }
+    let tags = client.list_tags().await.unwrap();
+    let names: Vec<&str> = tags.iter().map(|tag| tag.name.as_str()).collect();
+    assert_eq!(names, ["alpha", "beta"]);
+
+    assert!(matches!(
+        client
+            .create_tag(
+                &Uuid::new_v4(),
+                None,
+                &TagUpdate::new().with_name("alpha".to_string()),
+            )
+            .await,
+        Err(DatabaseError::UniquenessViolation { .. })
+    ));
+
+    client.delete_tag_by_id(&tags[0].id).await.unwrap();
+    assert_eq!(client.list_tags().await.unwrap().len(), 1);
+    assert!(matches!(
+        client.delete_tag_by_id(&tags[0].id).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+/// Creates two users and the organizations named `Beta` and `Alpha`, in that order.
+async fn organization_fixture(client: &SqliteClient) -> (Vec<User>, Vec<Organization>) {
+    let mut users = Vec::new();
+    for email in ["a@kasad.com", "b@kasad.com"] {
+        let user = client
+            .create_user(
+                &Uuid::new_v4(),
+                &UserCreate {
+                    email: email.to_string(),
+                    display_name: "Test User".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        users.push(user);
+    }
+    let mut organizations = Vec::new();
+    for name in ["Beta", "Alpha"] {
+        let organization = client
+            .create_organization(
+                &Uuid::new_v4(),
+                &OrganizationUpdate::new().with_name(name.to_string()),
+            )
+            .await
+            .unwrap();
+        organizations.push(organization);
+    }
+    (users, organizations)
+}
+
+#[tokio::test]
+async fn test_organizations() {
+    let Tools { client, .. } = tools().await;
+    let (users, organizations) = organization_fixture(&client).await;
+    let [beta, alpha] = organizations.as_slice() else {
+        unreachable!()
+    };
+    let names = |organizations: Vec<Organization>| -> Vec<String> {
+        organizations
+            .into_iter()
+            .map(|organization| organization.name)
+            .collect()
+    };
+    assert_eq!(
+        names(client.list_organizations().await.unwrap()),
+        ["Alpha", "Beta"]
+    );
+    assert!(matches!(
+        client
+            .create_organization(
+                &Uuid::new_v4(),
+                &OrganizationUpdate::new().with_name("Alpha".to_string()),
+            )
+            .await,
+        Err(DatabaseError::UniquenessViolation { .. })
+    ));
+    let renamed = client
+        .update_organization(
+            &beta.id,
+            &OrganizationUpdate::new().with_name("Gamma".to_string()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(renamed.name, "Gamma");
+
+    // Setting a member again changes their role
+    let member = client
+        .set_organization_member(&alpha.id, users[0].id(), OrganizationRole::Member)
+        .await
+        .unwrap();
+    assert_eq!(member.user.id(), users[0].id());
+    assert_eq!(member.role, OrganizationRole::Member);
+    let member = client
+        .set_organization_member(&alpha.id, users[0].id(), OrganizationRole::Admin)
+        .await
+        .unwrap();
+    assert_eq!(member.role, OrganizationRole::Admin);
+    client
+        .set_organization_member(&alpha.id, users[1].id(), OrganizationRole::Member)
+        .await
+        .unwrap();
+    client
+        .set_organization_member(&beta.id, users[0].id(), OrganizationRole::Member)
+        .await
+        .unwrap();
+    let members = client.get_organization_members(&alpha.id).await.unwrap();
+    let emails: Vec<&str> = members.iter().map(|member| member.user.email()).collect();
+    assert_eq!(emails, ["a@kasad.com", "b@kasad.com"]);
+    let organizations = client
+        .get_organizations_by_user_id(users[0].id())
+        .await
+        .unwrap();
+    assert_eq!(names(organizations), ["Alpha", "Gamma"]);
+    assert!(matches!(
+        client
+            .get_organization_member(&beta.id, users[1].id())
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // Deleting an organization deletes its memberships
+    client.delete_organization_by_id(&alpha.id).await.unwrap();
+    let organizations = client
+        .get_organizations_by_user_id(users[0].id())
+        .await
+        .unwrap();
+    assert_eq!(names(organizations), ["Gamma"]);
+    assert!(matches!(
+        client.delete_organization_by_id(&alpha.id).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test_get_tags_by_user_ids() {
+    let Tools { client, .. } = tools().await;
+    let mut users = Vec::new();
+    for i in 0..3 {
+        let user = client
+            .create_user(
+                &Uuid::new_v4(),
+                &UserCreate {
+                    email: format!("user{i}@kasad.com"),
+                    display_name: format!("User {i}"),
+                },
+            )
+            .await
+            .unwrap();
+        users.push(user);
+    }
+    let mut tags = Vec::new();
+    for name in ["beta", "alpha"] {
+        let tag = client
+            .create_tag(
+                &Uuid::new_v4(),
+                None,
+                &TagUpdate::new().with_name(name.to_string()),
+            )
+            .await
+            .unwrap();
+        tags.push(tag);
+    }
+    for tag in &tags {
+        client.add_tag_to_user(users[0].id(), tag).await.unwrap();
+    }
+    client
+        .add_tag_to_user(users[1].id(), &tags[0])
+        .await
+        .unwrap();
+
+    let missing = Uuid::new_v4();
+    let ids = [*users[0].id(), *users[1].id(), *users[2].id(), missing];
+    let by_user = client.get_tags_by_user_ids(&ids).await.unwrap();
+    let names =
+        |id: &Uuid| -> Vec<String> { by_user[id].iter().map(|tag| tag.name.clone()).collect() };
+    assert_eq!(by_user.len(), 4);
+    assert_eq!(names(users[0].id()), ["alpha", "beta"]);
+    assert_eq!(names(users[1].id()), ["beta"]);
+    assert!(names(users[2].id()).is_empty());
+    assert!(names(&missing).is_empty());
+
+    assert!(client.get_tags_by_user_ids(&[]).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_organization_tags() {
+    let Tools { client, .. } = tools().await;
+    let (users, organizations) = organization_fixture(&client).await;
+    let [beta, alpha] = organizations.as_slice() else {
+        unreachable!()
+    };
+    client
+        .set_organization_member(&alpha.id, users[1].id(), OrganizationRole::Member)
+        .await
+        .unwrap();
+
+    // Tag names are unique per organization
+    let mut tags = Vec::new();
+    for organization_id in [None, Some(&alpha.id), Some(&beta.id)] {
+        let tag = client
+            .create_tag(
+                &Uuid::new_v4(),
+                organization_id,
+                &TagUpdate::new().with_name("staff".to_string()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(tag.organization_id.as_ref(), organization_id);
+        tags.push(tag);
+    }
+    assert!(matches!(
+        client
+            .create_tag(
+                &Uuid::new_v4(),
+                Some(&alpha.id),
+                &TagUpdate::new().with_name("staff".to_string()),
+            )
+            .await,
+        Err(DatabaseError::UniquenessViolation { .. })
+    ));
+    assert_eq!(
+        client.get_tag_by_name("staff").await.unwrap().id,
+        tags[0].id
+    );
+    let alpha_tags = client.get_tags_by_organization_id(&alpha.id).await.unwrap();
+    assert_eq!(alpha_tags.len(), 1);
+    assert_eq!(alpha_tags[0].id, tags[1].id);
+
+    // Removing a member removes the organization's tags from them
+    client
+        .add_tag_to_user(users[1].id(), &tags[0])
+        .await
+        .unwrap();
+    client
+        .add_tag_to_user(users[1].id(), &tags[1])
+        .await
+        .unwrap();
+    client
+        .remove_organization_member(&alpha.id, users[1].id())
+        .await
+        .unwrap();
+    let user_tags = client.get_tags_by_user_id(users[1].id()).await.unwrap();
+    assert_eq!(user_tags.len(), 1);
+    assert_eq!(user_tags[0].id, tags[0].id);
+    assert!(matches!(
+        client
+            .remove_organization_member(&alpha.id, users[1].id())
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // Deleting an organization deletes its tags
+    client.delete_organization_by_id(&alpha.id).await.unwrap();
+    assert!(matches!(
+        client.get_tag_by_id(&tags[1].id).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+/// Creates three users and the groups named `Staff`, `Engineering`, and `Backend`, in that order.
+async fn group_fixture(client: &SqliteClient) -> (Vec<User>, Vec<Group>) {
+    let mut users = Vec::new();
+    for email in ["a@kasad.com", "b@kasad.com", "c@kasad.com"] {
+        let user = client
+            .create_user(
+                &Uuid::new_v4(),
+                &UserCreate {
+                    email: email.to_string(),
+                    display_name: "Test User".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        users.push(user);
+    }
+    let mut groups = Vec::new();
+    for name in ["Staff", "Engineering", "Backend"] {
+        let group = client
+            .create_group(
+                &Uuid::new_v4(),
+                &GroupUpdate::new().with_name(name.to_string()),
+            )
+            .await
+            .unwrap();
+        groups.push(group);
+    }
+    (users, groups)
+}
+
+fn group_names(groups: Vec<Group>) -> Vec<String> {
+    groups.into_iter().map(|group| group.name).collect()
+}
+
+#[tokio::test]
+async fn test_groups() {
+    let Tools { client, .. } = tools().await;
+    let (users, groups) = group_fixture(&client).await;
+    let [staff, engineering, _] = groups.as_slice() else {
+        unreachable!()
+    };
+    assert_eq!(
+        group_names(client.list_groups().await.unwrap()),
+        ["Backend", "Engineering", "Staff"]
+    );
+    assert!(matches!(
+        client
+            .create_group(
+                &Uuid::new_v4(),
+                &GroupUpdate::new().with_name("Staff".to_string()),
+            )
+            .await,
+        Err(DatabaseError::UniquenessViolation { .. })
+    ));
+    let renamed = client
+        .update_group(
+            &staff.id,
+            &GroupUpdate::new().with_name("Everyone".to_string()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(renamed.name, "Everyone");
+
+    // Adding a member twice does nothing
+    for _ in 0..2 {
+        client
+            .add_user_to_group(&engineering.id, users[1].id())
+            .await
+            .unwrap();
+    }
+    client
+        .add_user_to_group(&engineering.id, users[0].id())
+        .await
+        .unwrap();
+    client
+        .add_user_to_group(&staff.id, users[0].id())
+        .await
+        .unwrap();
+    let members = client
+        .get_users_by_group_id(&engineering.id, false)
+        .await
+        .unwrap();
+    let emails: Vec<&str> = members.iter().map(User::email).collect();
+    assert_eq!(emails, ["a@kasad.com", "b@kasad.com"]);
+    assert_eq!(
+        group_names(client.get_groups_by_user_id(users[0].id()).await.unwrap()),
+        ["Engineering", "Everyone"]
+    );
+    client
+        .remove_user_from_group(&engineering.id, users[0].id())
+        .await
+        .unwrap();
+    assert!(matches!(
+        client
+            .remove_user_from_group(&engineering.id, users[0].id())
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // Deleting a group deletes its memberships
+    client.delete_group_by_id(&engineering.id).await.unwrap();
+    assert!(
+        client
+            .get_groups_by_user_id(users[1].id())
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(matches!(
+        client.delete_group_by_id(&engineering.id).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+/// Nests the groups created by [`group_fixture()`] as `Staff` > `Engineering` > `Backend`, and
+/// adds one of its users to each of them.
+async fn nest_groups(client: &SqliteClient, users: &[User], groups: &[Group]) {
+    client
+        .add_subgroup(&groups[0].id, &groups[1].id)
+        .await
+        .unwrap();
+    client
+        .add_subgroup(&groups[1].id, &groups[2].id)
+        .await
+        .unwrap();
+    for (group, user) in groups.iter().zip(users) {
+        client
+            .add_user_to_group(&group.id, user.id())
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_nested_groups() {
+    let Tools { client, .. } = tools().await;
+    let (users, groups) = group_fixture(&client).await;
+    let [staff, engineering, backend] = groups.as_slice() else {
+        unreachable!()
+    };
+    nest_groups(&client, &users, &groups).await;
+    // Adding an existing subgroup does nothing
+    client
+        .add_subgroup(&staff.id, &engineering.id)
+        .await
+        .unwrap();
+    assert_eq!(
+        group_names(client.get_subgroups(&staff.id).await.unwrap()),
+        ["Engineering"]
+    );
+    let mut supergroup_ids = client.get_supergroup_ids(&backend.id).await.unwrap();
+    supergroup_ids.sort();
+    let mut expected = vec![staff.id, engineering.id];
+    expected.sort();
+    assert_eq!(supergroup_ids, expected);
+    let count = |include_subgroups| {
+        let client = &client;
+        async move {
+            client
+                .get_users_by_group_id(&staff.id, include_subgroups)
+                .await
+                .unwrap()
+                .len()
+        }
+    };
+    assert_eq!(count(false).await, 1);
+    assert_eq!(count(true).await, 3);
+
+    client
+        .remove_subgroup(&engineering.id, &backend.id)
+        .await
+        .unwrap();
+    assert!(matches!(
+        client.remove_subgroup(&engineering.id, &backend.id).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert_eq!(count(true).await, 2);
+
+    // Deleting a group keeps its subgroups
+    client.delete_group_by_id(&staff.id).await.unwrap();
+    assert!(client.get_group_by_id(&engineering.id).await.is_ok());
+    assert!(
+        client
+            .get_supergroup_ids(&engineering.id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn test_group_tags() {
+    let Tools { client, .. } = tools().await;
+    let (users, groups) = group_fixture(&client).await;
+    let [staff, engineering, backend] = groups.as_slice() else {
+        unreachable!()
+    };
+    nest_groups(&client, &users, &groups).await;
+
+    // Tags of a group apply to the members of its subgroups
+    let mut tags = Vec::new();
+    for name in ["vpn", "deploy"] {
+        let tag = client
+            .create_tag(
+                &Uuid::new_v4(),
+                None,
+                &TagUpdate::new().with_name(name.to_string()),
+            )
+            .await
+            .unwrap();
+        tags.push(tag);
+    }
+    client.add_tag_to_group(&staff.id, &tags[0]).await.unwrap();
+    client
+        .add_tag_to_group(&engineering.id, &tags[1])
+        .await
+        .unwrap();
+    client
+        .add_tag_to_user(users[2].id(), &tags[0])
+        .await
+        .unwrap();
+    let tag_names = |user: &User| {
+        let client = &client;
+        let id = *user.id();
+        async move {
+            client
+                .get_effective_tags_by_user_id(&id)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|tag| tag.name)
+                .collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(tag_names(&users[0]).await, ["vpn"]);
+    assert_eq!(tag_names(&users[2]).await, ["deploy", "vpn"]);
+    assert_eq!(
+        client
+            .get_tags_by_user_id(users[2].id())
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // Removing a subgroup removes its members' tags
+    client
+        .remove_subgroup(&engineering.id, &backend.id)
+        .await
+        .unwrap();
+    assert_eq!(tag_names(&users[2]).await, ["vpn"]);
+    client
+        .remove_tag_from_group(&engineering.id, &tags[1])
+        .await
+        .unwrap();
+    assert!(
+        client
+            .get_tags_by_group_id(&engineering.id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(tag_names(&users[1]).await, ["vpn"]);
+    client.delete_group_by_id(&staff.id).await.unwrap();
+    assert!(tag_names(&users[1]).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_roles() {
+    let Tools { client, .. } = tools().await;
+    let mut roles = Vec::new();
+    for (name, permissions) in [
+        ("support", vec![Permission::UsersRead]),
+        ("auditor", vec![Permission::UsersRead, Permission::TagsRead]),
+    ] {
+        let role = client
+            .create_role(
+                &Uuid::new_v4(),
+                &RoleUpdate::new()
+                    .with_name(name.to_string())
+                    .with_permissions(permissions),
+            )
+            .await
+            .unwrap();
+        roles.push(role);
+    }
+    let names: Vec<String> = client
+        .list_roles()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|role| role.name)
+        .collect();
+    assert_eq!(names, ["auditor", "support"]);
+    assert!(matches!(
+        client
+            .create_role(
+                &Uuid::new_v4(),
+                &RoleUpdate::new().with_name("support".to_string()),
+            )
+            .await,
+        Err(DatabaseError::UniquenessViolation { .. })
+    ));
+    let role = client
+        .update_role(
+            &roles[0].id,
+            &RoleUpdate::new().with_permissions(vec![Permission::UsersWrite]),
+        )
+        .await
+        .unwrap();
+    assert_eq!(role.name, "support");
+    assert_eq!(*role.permissions, [Permission::UsersWrite]);
+
+    // Assigning a role twice does nothing
+    let tag = client
+        .create_tag(
+            &Uuid::new_v4(),
+            None,
+            &TagUpdate::new().with_name("staff".to_string()),
+        )
+        .await
+        .unwrap();
+    for role in roles.iter().chain(&roles) {
+        client.add_role_to_tag(&tag.id, &role.id).await.unwrap();
+    }
+    let tag_roles = client.get_roles_by_tag_id(&tag.id).await.unwrap();
+    assert_eq!(tag_roles.len(), 2);
+    client
+        .remove_role_from_tag(&tag.id, &roles[1].id)
+        .await
+        .unwrap();
+    assert!(matches!(
+        client.remove_role_from_tag(&tag.id, &roles[1].id).await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // Deleting a role removes it from its tags
+    client.delete_role_by_id(&roles[0].id).await.unwrap();
+    assert!(
+        client
+            .get_roles_by_tag_id(&tag.id)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    assert!(matches!(
+        client.get_role_by_id(&roles[0].id).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test_policies() {
+    let Tools { client, .. } = tools().await;
+    let statement = |actions: &[&str]| PolicyStatement {
+        effect: PolicyEffect::Permit,
+        actions: actions.iter().map(ToString::to_string).collect(),
+        users: Vec::new(),
+        tags: Vec::new(),
+        clients: Vec::new(),
+    };
+    let first = PolicyDocument {
+        statements: vec![statement(&["users:read"])],
+    };
+    let second = PolicyDocument {
+        statements: vec![statement(&["users:*"])],
+    };
+    let id = Uuid::new_v4();
+    let policy = client
+        .create_policy(
+            &id,
+            &PolicyUpdate::new()
+                .with_name("readers".to_string())
+                .with_document(first.clone()),
+        )
+        .await
+        .unwrap();
+    assert!(policy.enabled);
+    assert_eq!(policy.version, 1);
+    assert!(matches!(
+        client
+            .create_policy(
+                &Uuid::new_v4(),
+                &PolicyUpdate::new()
+                    .with_name("readers".to_string())
+                    .with_document(first.clone()),
+            )
+            .await,
+        Err(DatabaseError::UniquenessViolation { .. })
+    ));
+
+    // Only changing the document creates a new version
+    let policy = client
+        .update_policy(&id, &PolicyUpdate::new().with_enabled(false))
+        .await
+        .unwrap();
+    assert!(!policy.enabled);
+    assert_eq!(policy.version, 1);
+    let policy = client
+        .update_policy(&id, &PolicyUpdate::new().with_document(second.clone()))
+        .await
+        .unwrap();
+    assert_eq!(policy.version, 2);
+    assert_eq!(*policy.document, second);
+    let versions = client.list_policy_versions(&id).await.unwrap();
+    let numbers: Vec<i64> = versions.iter().map(|version| version.version).collect();
+    assert_eq!(numbers, [2, 1]);
+    let version = client.get_policy_version(&id, 1).await.unwrap();
+    assert_eq!(*version.document, first);
+
+    // Deleting a policy deletes its versions
+    client.delete_policy_by_id(&id).await.unwrap();
+    assert!(client.list_policy_versions(&id).await.unwrap().is_empty());
+    assert!(matches!(
+        client.get_policy_version(&id, 1).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(matches!(
+        client.delete_policy_by_id(&id).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test_webhooks() {
+    let Tools { client, .. } = tools().await;
+    let now = chrono::Utc::now().trunc_subsecs(0);
+    let webhook = Webhook {
+        id: Uuid::new_v4(),
+        url: "https://example.com/hook".to_string(),
+        events: ViaJson(vec![WebhookEventType::UserCreated]),
+        secret: ViaJson("secret".to_string()),
+        enabled: true,
+        created_at: now,
+        updated_at: now,
+    };
+    let created = client.create_webhook(&webhook).await.unwrap();
+    assert_eq!(created.secret.0, "secret");
+    assert_eq!(created.events.0, [WebhookEventType::UserCreated]);
+    assert_eq!(client.list_webhooks().await.unwrap().len(), 1);
+
+    let updated = client
+        .update_webhook(
+            &webhook.id,
+            &WebhookUpdate::new()
+                .with_events(Vec::new())
+                .with_enabled(false),
+        )
+        .await
+        .unwrap();
+    assert!(!updated.enabled);
+    assert!(!updated.wants(WebhookEventType::PasskeyDeleted));
+    assert!(updated.events.is_empty());
+    assert_eq!(updated.url, webhook.url);
+    assert_eq!(
+        client
+            .get_webhook_by_id(&webhook.id)
+            .await
+            .unwrap()
+            .secret
+            .0,
+        "secret"
+    );
+
+    client.delete_webhook_by_id(&webhook.id).await.unwrap();
+    assert!(matches!(
+        client.delete_webhook_by_id(&webhook.id).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(matches!(
+        client.get_webhook_by_id(&webhook.id).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test_api_tokens() {
+    let Tools { client, .. } = tools().await;
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "test@kasad.com".to_string(),
+                display_name: "Test User".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    let token = ApiToken {
+        id: Uuid::new_v4(),
+        user_id: *user.id(),
+        name: "CI".to_string(),
+        token_hash: blake3::hash(b"token").into(),
+        scopes: ViaJson(vec![ApiTokenScope::Read]),
+        created_at: chrono::Utc::now().trunc_subsecs(0),
+        expires_at: None,
+    };
+    client.create_api_token(&token).await.unwrap();
+    let fetched = client
+        .get_api_token_by_hash(&blake3::hash(b"token").into())
+        .await
+        .unwrap();
+    assert_eq!(fetched.id, token.id);
+    assert_eq!(fetched.user_id, *user.id());
+    assert!(fetched.expires_at.is_none());
+    assert!(matches!(
+        client
+            .get_api_token_by_hash(&blake3::hash(b"other").into())
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert_eq!(
+        client
+            .get_api_tokens_by_user_id(user.id())
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // Tokens can only be deleted by their owner
+    assert!(matches!(
+        client.delete_api_token(&Uuid::new_v4(), &token.id).await,
+        Err(DatabaseError::NotFound)
+    ));
+    client.delete_api_token(user.id(), &token.id).await.unwrap();
+    assert!(
+        client
+            .get_api_tokens_by_user_id(user.id())
+            .await
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[tokio::test]
+async fn test_oauth() {
+    let Tools { client, .. } = tools().await;
+    let now = chrono::Utc::now().trunc_subsecs(0);
+    let oauth_client = client
+        .create_oauth_client(&OAuthClient {
+            id: Uuid::new_v4(),
+            name: "Wiki".to_string(),
+            secret_hash: blake3::hash(b"secret").into(),
+            redirect_uris: ViaJson(vec!["https://wiki.example.com/callback".to_string()]),
+            created_at: now,
+            updated_at: now,
+        })
+        .await
+        .unwrap();
+    assert_eq!(client.list_oauth_clients().await.unwrap().len(), 1);
+    let updated = client
+        .update_oauth_client(
+            &oauth_client.id,
+            &OAuthClientUpdate::new().with_name("Docs".to_string()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(updated.name, "Docs");
+    assert_eq!(*updated.redirect_uris, *oauth_client.redirect_uris);
+    assert_eq!(*updated.secret_hash, *oauth_client.secret_hash);
+
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "test@kasad.com".to_string(),
+                display_name: "Test User".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    let code = AuthorizationCode {
+        code_hash: blake3::hash(b"code").into(),
+        client_id: oauth_client.id,
+        user_id: *user.id(),
+        redirect_uri: "https://wiki.example.com/callback".to_string(),
+        scope: "openid".to_string(),
+        nonce: Some("nonce".to_string()),
+        code_challenge: None,
+        auth_time: now,
+        expires_at: now + chrono::Duration::minutes(5),
+    };
+    client.create_authorization_code(&code).await.unwrap();
+
+    // Codes can only be consumed once
+    let consumed = client
+        .consume_authorization_code(&code.code_hash)
+        .await
+        .unwrap();
+    assert_eq!(consumed.user_id, *user.id());
+    assert_eq!(consumed.nonce.as_deref(), Some("nonce"));
+    assert!(matches!(
+        client.consume_authorization_code(&code.code_hash).await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // Expired codes can't be consumed
+    client
+        .create_authorization_code(&AuthorizationCode {
+            expires_at: now - chrono::Duration::minutes(1),
+            ..code.clone()
+        })
+        .await
+        .unwrap();
+    assert!(matches!(
+        client.consume_authorization_code(&code.code_hash).await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // Deleting a client deletes its codes
+    client.create_authorization_code(&code).await.unwrap();
+    client
+        .delete_oauth_client_by_id(&oauth_client.id)
+        .await
+        .unwrap();
+    assert!(matches!(
+        client.consume_authorization_code(&code.code_hash).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(matches!(
+        client.delete_oauth_client_by_id(&oauth_client.id).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test_saml_service_providers() {
+    let Tools { client, .. } = tools().await;
+    let now = chrono::Utc::now().trunc_subsecs(0);
+    let provider = SamlServiceProvider {
+        id: Uuid::new_v4(),
+        entity_id: "https://wiki.example.com/saml".to_string(),
+        name: "Wiki".to_string(),
+        acs_url: "https://wiki.example.com/saml/acs".to_string(),
+        name_id_format: SamlNameIdFormat::EmailAddress,
+        created_at: now,
+        updated_at: now,
+    };
+    let created = client
+        .create_saml_service_provider(&provider)
+        .await
+        .unwrap();
+    assert_eq!(created.name_id_format, SamlNameIdFormat::EmailAddress);
+    assert!(matches!(
+        client
+            .create_saml_service_provider(&SamlServiceProvider {
+                id: Uuid::new_v4(),
+                ..provider.clone()
+            })
+            .await,
+        Err(DatabaseError::UniquenessViolation { .. })
+    ));
+    assert_eq!(client.list_saml_service_providers().await.unwrap().len(), 1);
+    let found = client
+        .get_saml_service_provider_by_entity_id(&provider.entity_id)
+        .await
+        .unwrap();
+    assert_eq!(found.id, provider.id);
+
+    let updated = client
+        .update_saml_service_provider(
+            &provider.id,
+            &SamlServiceProviderUpdate::new()
+                .with_name("Docs".to_string())
+                .with_name_id_format(SamlNameIdFormat::Persistent),
+        )
+        .await
+        .unwrap();
+    assert_eq!(updated.name, "Docs");
+    assert_eq!(updated.name_id_format, SamlNameIdFormat::Persistent);
+    assert_eq!(updated.acs_url, provider.acs_url);
+
+    client
+        .delete_saml_service_provider_by_id(&provider.id)
+        .await
+        .unwrap();
+    assert!(matches!(
+        client.get_saml_service_provider_by_id(&provider.id).await,
+        Err(DatabaseError::NotFound)
+    ));
+    assert!(matches!(
+        client
+            .delete_saml_service_provider_by_id(&provider.id)
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+fn federated_provider() -> FederatedProvider {
+    let now = chrono::Utc::now().trunc_subsecs(0);
+    FederatedProvider {
+        id: Uuid::new_v4(),
+        slug: "google".to_string(),
+        name: "Google".to_string(),
+        issuer: "https://accounts.google.com".to_string(),
+        client_id: "client".to_string(),
+        client_secret: ViaJson("secret".to_string()),
+        auto_provision: false,
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+#[tokio::test]
+async fn test_federated_providers() {
+    let Tools { client, .. } = tools().await;
+    let now = chrono::Utc::now().trunc_subsecs(0);
+    let provider = federated_provider();
+    client.create_federated_provider(&provider).await.unwrap();
+    assert!(matches!(
+        client
+            .create_federated_provider(&FederatedProvider {
+                id: Uuid::new_v4(),
+                ..provider.clone()
+            })
+            .await,
+        Err(DatabaseError::UniquenessViolation { .. })
+    ));
+    let found = client
+        .get_federated_provider_by_slug("google")
+        .await
+        .unwrap();
+    assert_eq!(*found.client_secret, "secret");
+    let updated = client
+        .update_federated_provider(
+            &provider.id,
+            &FederatedProviderUpdate::new()
+                .with_client_secret("rotated".to_string())
+                .with_auto_provision(true),
+        )
+        .await
+        .unwrap();
+    assert_eq!(*updated.client_secret, "rotated");
+    assert!(updated.auto_provision);
+    assert_eq!(updated.issuer, provider.issuer);
+
+    // Login states can only be consumed once, and not after they expire
+    let login = |hash: &[u8], expires_in: i64| FederatedLoginState {
+        state_hash: blake3::hash(hash).into(),
+        provider_id: provider.id,
+        nonce: "nonce".to_string(),
+        code_verifier: "verifier".to_string(),
+        link_user_id: None,
+        next: Some("/account".to_string()),
+        expires_at: now + chrono::Duration::minutes(expires_in),
+    };
+    client
+        .create_federated_login_state(&login(b"valid", 10))
+        .await
+        .unwrap();
+    client
+        .create_federated_login_state(&login(b"expired", -1))
+        .await
+        .unwrap();
+    let consumed = client
+        .consume_federated_login_state(&blake3::hash(b"valid").into())
+        .await
+        .unwrap();
+    assert_eq!(consumed.next.as_deref(), Some("/account"));
+    for hash in [b"valid".as_slice(), b"expired"] {
+        assert!(matches!(
+            client
+                .consume_federated_login_state(&blake3::hash(hash).into())
+                .await,
+            Err(DatabaseError::NotFound)
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_federated_identities() {
+    let Tools { client, .. } = tools().await;
+    let now = chrono::Utc::now().trunc_subsecs(0);
+    let provider = federated_provider();
+    client.create_federated_provider(&provider).await.unwrap();
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "test@kasad.com".to_string(),
+                display_name: "Test".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    let identity = FederatedIdentity {
+        provider_id: provider.id,
+        subject: "123".to_string(),
+        user_id: *user.id(),
+        email: Some("test@kasad.com".to_string()),
+        created_at: now,
+    };
+    client.create_federated_identity(&identity).await.unwrap();
+    assert!(matches!(
+        client.create_federated_identity(&identity).await,
+        Err(DatabaseError::UniquenessViolation { .. })
+    ));
+    let found = client
+        .get_federated_identity(&provider.id, "123")
+        .await
+        .unwrap();
+    assert_eq!(found.user_id, *user.id());
+    assert_eq!(
+        client
+            .list_federated_identities_by_user_id(user.id())
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    client
+        .delete_federated_identities(user.id(), &provider.id)
+        .await
+        .unwrap();
+    assert!(matches!(
+        client
+            .delete_federated_identities(user.id(), &provider.id)
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    // Deleting a provider unlinks its identities
+    client.create_federated_identity(&identity).await.unwrap();
+    client
+        .delete_federated_provider_by_id(&provider.id)
+        .await
+        .unwrap();
+    assert!(matches!(
+        client.get_federated_identity(&provider.id, "123").await,
+        Err(DatabaseError::NotFound)
+    ));
+}
+
+#[tokio::test]
+async fn test_signing_keys() {
+    let Tools { client, .. } = tools().await;
+    let now = chrono::Utc::now().trunc_subsecs(0);
+    let key = |kid: &str, age: i64, retired: Option<i64>| StoredSigningKey {
+        kid: kid.to_string(),
+        private_key: ViaJson(format!("pem-{kid}")),
+        certificate: None,
+        created_at: now - chrono::Duration::days(age),
+        retired_at: retired.map(|days| now - chrono::Duration::days(days)),
+    };
+    client
+        .create_signing_key(&key("expired", 40, Some(10)))
+        .await
+        .unwrap();
+    client
+        .create_signing_key(&key("old", 20, None))
+        .await
+        .unwrap();
+    client
+        .create_signing_key(&key("new", 0, None))
+        .await
+        .unwrap();
+
+    let kids =
+        |keys: Vec<StoredSigningKey>| keys.into_iter().map(|key| key.kid).collect::<Vec<_>>();
+    assert_eq!(
+        kids(client.list_signing_keys().await.unwrap()),
+        ["new", "old", "expired"]
+    );
+    assert_eq!(
+        *client.list_signing_keys().await.unwrap()[0].private_key,
+        "pem-new"
+    );
+
+    assert_eq!(
+        client.retire_signing_keys_older_than("new").await.unwrap(),
+        1
+    );
+    let keys = client.list_signing_keys().await.unwrap();
+    assert!(keys[0].retired_at.is_none());
+    assert!(keys[1].retired_at.is_some());
+
+    let day = Duration::from_secs(24 * 60 * 60);
+    assert_eq!(
+        client.delete_retired_signing_keys(day * 5).await.unwrap(),
+        1
+    );
+    assert_eq!(
+        kids(client.list_signing_keys().await.unwrap()),
+        ["new", "old"]
+    );
+}
+
+#[tokio::test]
+async fn test_session_cookie_key() {
+    let Tools { client, .. } = tools().await;
+    let key = |kid: &str| StoredSessionCookieKey {
+        kid: kid.to_string(),
+        key: ViaJson(format!("key-{kid}")),
+        created_at: chrono::Utc::now().trunc_subsecs(0),
+    };
+    let stored = client
+        .get_or_create_session_cookie_key(&key("first"))
+        .await
+        .unwrap();
+    assert_eq!(stored.kid, "first");
+    assert_eq!(*stored.key, "key-first");
+
+    // Once a key is stored, it is kept
+    let stored = client
+        .get_or_create_session_cookie_key(&key("second"))
+        .await
+        .unwrap();
+    assert_eq!(stored.kid, "first");
+    assert_eq!(*stored.key, "key-first");
+}
+
+#[tokio::test]
+async fn test_migrations() {
+    let path = std::env::temp_dir().join(format!("iam-db-{}.sqlite3", Uuid::new_v4()));
+    let client = SqliteClient::open_path_with(&path, PendingMigrations::Ignore)
+        .await
+        .unwrap();
+    let migrations = client.migrations().await.unwrap();
+    assert!(migrations.iter().all(|migration| !migration.applied));
+    let newest = migrations.last().unwrap().clone();
+    assert!(newest.reversible);
+    drop(client);
+    assert!(matches!(
+        SqliteClient::open_path_with(&path, PendingMigrations::Refuse).await,
+        Err(CreateSqliteClientError::PendingMigrations(count)) if count == migrations.len()
+    ));
+
+    let client = SqliteClient::open_path(&path).await.unwrap();
+    assert!(client.migrate_up(false).await.unwrap().is_empty());
+    let version =
+        |list: Vec<MigrationStatus>| list.into_iter().map(|m| m.version).collect::<Vec<_>>();
+
+    // Dry runs change nothing
+    assert_eq!(
+        version(client.migrate_down(None, true).await.unwrap()),
+        [newest.version]
+    );
+    assert!(client.migrations().await.unwrap().iter().all(|m| m.applied));
+
+    assert_eq!(
+        version(client.migrate_down(None, false).await.unwrap()),
+        [newest.version]
+    );
+    assert!(!client.migrations().await.unwrap().last().unwrap().applied);
+
+    // Older migrations can't be reverted
+    let first = migrations[0].version;
+    assert!(matches!(
+        client.migrate_down(Some(first), false).await,
+        Err(MigrationError::Irreversible { .. })
+    ));
+    assert!(matches!(
+        client.migrate_down(Some(1), false).await,
+        Err(MigrationError::UnknownVersion(1))
+    ));
+
+    assert_eq!(
+        version(client.migrate_up(true).await.unwrap()),
+        [newest.version]
+    );
+    assert_eq!(
+        version(client.migrate_up(false).await.unwrap()),
+        [newest.version]
+    );
+    drop(client);
+    SqliteClient::open_path_with(&path, PendingMigrations::Refuse)
+        .await
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_backup() {
+    let Tools { client, .. } = tools().await;
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "alice@example.com".to_string(),
+                display_name: "Alice".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    let tag = client
+        .create_tag(
+            &Uuid::new_v4(),
+            None,
+            &TagUpdate::new().with_name("staff".to_string()),
+        )
+        .await
+        .unwrap();
+    client.add_tag_to_user(user.id(), &tag).await.unwrap();
+    let backup = client.export_backup().await.unwrap();
+    assert_eq!(backup.entities["users"].len(), 1);
+    assert_eq!(backup.entities["users_tags"].len(), 1);
+
+    // Backups survive a round trip through JSON
+    let backup: Backup = serde_json::from_str(&serde_json::to_string(&backup).unwrap()).unwrap();
+    let restored = SqliteClient::new_memory().await.unwrap();
+    restored.import_backup(&backup).await.unwrap();
+    assert_eq!(
+        restored.export_backup().await.unwrap().entities,
+        backup.entities
+    );
+    let tags = restored.get_tags_by_user_id(user.id()).await.unwrap();
+    assert_eq!(tags.len(), 1);
+    assert_eq!(tags[0].id, tag.id);
+    // The search index is rebuilt as users are restored
+    assert_eq!(restored.search_users("alice", 10).await.unwrap().len(), 1);
+
+    // Backups are only restored into empty databases
+    assert!(restored.import_backup(&backup).await.is_err());
+
+    // Nothing is restored from invalid backups
+    let mut invalid = backup.clone();
+    invalid.entities.get_mut("users").unwrap().push(
+        serde_json::json!({ "id": "not base64!" })
+            .as_object()
+            .unwrap()
+            .clone(),
+    );
+    let empty = SqliteClient::new_memory().await.unwrap();
+    assert!(empty.import_backup(&invalid).await.is_err());
+    let mut unknown = backup.clone();
+    unknown.entities.insert("widgets".to_string(), Vec::new());
+    assert!(empty.import_backup(&unknown).await.is_err());
+    assert!(empty.export_backup().await.unwrap().is_empty());
 }
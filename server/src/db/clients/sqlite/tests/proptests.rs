@@ -0,0 +1,266 @@
+//! # Property tests for partial updates
+//!
+//! Applies arbitrary combinations of update fields and checks that exactly the given fields are
+//! changed, which exercises every shape of the dynamically built `UPDATE` queries.
+
+use chrono::{DateTime, Utc};
+use proptest::{option, prelude::*};
+use uuid::Uuid;
+use webauthn_rs::prelude::Passkey;
+
+use super::super::SqliteClient;
+use crate::{
+    db::interface::{DatabaseClient, DatabaseError},
+    models::{
+        NewPasskeyCredential, PasskeyCredentialUpdate, QueuedEmail, QueuedEmailState,
+        QueuedEmailUpdate, Session, SessionState, SessionUpdate, User, UserCreate, UserUpdate,
+        ViaJson,
+    },
+};
+
+/// Runs the future to completion on a new runtime, since property tests can't be async.
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+async fn setup() -> (SqliteClient, User) {
+    let client = SqliteClient::new_memory().await.unwrap();
+    let user = client
+        .create_user(
+            &Uuid::new_v4(),
+            &UserCreate {
+                email: "test@kasad.com".to_string(),
+                display_name: "Test User".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+    (client, user)
+}
+
+fn passkeys() -> [Passkey; 2] {
+    [
+        serde_json::from_str(include_str!("resources/passkey.json")).unwrap(),
+        serde_json::from_str(include_str!("resources/passkey-incremented.json")).unwrap(),
+    ]
+}
+
+/// Timestamps with second precision, as stored in the database
+fn timestamp() -> impl Strategy<Value = DateTime<Utc>> {
+    (0i64..4_000_000_000).prop_map(|secs| DateTime::from_timestamp(secs, 0).unwrap())
+}
+
+fn session_state() -> impl Strategy<Value = SessionState> {
+    prop_oneof![
+        Just(SessionState::Active),
+        Just(SessionState::Revoked),
+        Just(SessionState::LoggedOut),
+        Just(SessionState::Superseded),
+    ]
+}
+
+fn queued_email_state() -> impl Strategy<Value = QueuedEmailState> {
+    prop_oneof![
+        Just(QueuedEmailState::Pending),
+        Just(QueuedEmailState::Dead)
+    ]
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn prop_update_user(
+        email in option::of("[a-z]{1,10}@example\\.com"),
+        display_name in option::of("\\PC{0,20}"),
+        login_notifications in option::of(any::<bool>()),
+    ) {
+        let update = UserUpdate {
+            email,
+            display_name,
+            login_notifications,
+        };
+        let (original, result) = block_on(async {
+            let (client, user) = setup().await;
+            let result = client.update_user(user.id(), &update).await;
+            (user, result)
+        });
+        if update.is_empty() {
+            prop_assert!(matches!(result, Err(DatabaseError::EmptyUpdate)));
+            return Ok(());
+        }
+        let updated = result.unwrap();
+        prop_assert_eq!(updated.id(), original.id());
+        prop_assert_eq!(
+            updated.email(),
+            update.email.as_deref().unwrap_or(original.email())
+        );
+        prop_assert_eq!(
+            updated.display_name(),
+            update.display_name.as_deref().unwrap_or(original.display_name())
+        );
+        prop_assert_eq!(
+            updated.login_notifications(),
+            update.login_notifications.unwrap_or(original.login_notifications())
+        );
+    }
+
+    #[test]
+    fn prop_update_session(
+        state in option::of(session_state()),
+        expires_at in option::of(timestamp()),
+        last_seen_at in option::of(timestamp()),
+        ip in option::of("[0-9.]{1,15}"),
+        user_agent in option::of("\\PC{0,20}"),
+        authenticated_at in option::of(timestamp()),
+    ) {
+        let update = SessionUpdate {
+            state,
+            expires_at,
+            last_seen_at,
+            ip,
+            user_agent,
+            authenticated_at,
+            // Sessions can only act within organizations which exist
+            organization_id: None,
+        };
+        let (original, result) = block_on(async {
+            let (client, user) = setup().await;
+            let session = Session {
+                id_hash: blake3::hash(b"session").into(),
+                user_id: *user.id(),
+                state: SessionState::Active,
+                created_at: DateTime::from_timestamp(1_000, 0).unwrap(),
+                expires_at: DateTime::from_timestamp(2_000, 0).unwrap(),
+                is_admin: false,
+                parent_id_hash: None,
+                ip: None,
+                user_agent: None,
+                last_seen_at: DateTime::from_timestamp(1_000, 0).unwrap(),
+                impersonator_id: None,
+                authenticated_at: DateTime::from_timestamp(1_000, 0).unwrap(),
+                organization_id: None,
+            };
+            client.create_session(&session).await.unwrap();
+            let result = client.update_session(&session.id_hash, &update).await;
+            (session, result)
+        });
+        if update.is_empty() {
+            prop_assert!(matches!(result, Err(DatabaseError::EmptyUpdate)));
+            return Ok(());
+        }
+        let updated = result.unwrap();
+        prop_assert_eq!(updated.id_hash.0, original.id_hash.0);
+        prop_assert_eq!(updated.state, update.state.unwrap_or(original.state));
+        prop_assert_eq!(
+            updated.expires_at,
+            update.expires_at.unwrap_or(original.expires_at)
+        );
+        prop_assert_eq!(
+            updated.last_seen_at,
+            update.last_seen_at.unwrap_or(original.last_seen_at)
+        );
+        prop_assert_eq!(updated.ip, update.ip.or(original.ip));
+        prop_assert_eq!(updated.user_agent, update.user_agent.or(original.user_agent));
+        prop_assert_eq!(
+            updated.authenticated_at,
+            update.authenticated_at.unwrap_or(original.authenticated_at)
+        );
+        prop_assert_eq!(updated.created_at, original.created_at);
+    }
+
+    #[test]
+    fn prop_update_passkey(
+        display_name in option::of(option::of("\\PC{0,20}")),
+        passkey_index in option::of(0usize..2),
+    ) {
+        let update = PasskeyCredentialUpdate {
+            display_name,
+            passkey: passkey_index.map(|i| ViaJson(passkeys()[i].clone())),
+        };
+        let (original, result) = block_on(async {
+            let (client, user) = setup().await;
+            let [passkey, _] = passkeys();
+            let original = client
+                .create_passkey(
+                    &Uuid::new_v4(),
+                    user.id(),
+                    &NewPasskeyCredential {
+                        display_name: Some("Original".to_string()),
+                        passkey,
+                    },
+                )
+                .await
+                .unwrap();
+            let result = client.update_passkey(&original.id, &update).await;
+            (original, result)
+        });
+        if update.is_empty() {
+            prop_assert!(matches!(result, Err(DatabaseError::EmptyUpdate)));
+            return Ok(());
+        }
+        let updated = result.unwrap();
+        prop_assert_eq!(updated.id, original.id);
+        prop_assert_eq!(
+            updated.display_name,
+            update.display_name.unwrap_or(original.display_name)
+        );
+        let expected_passkey = update.passkey.unwrap_or(original.passkey);
+        prop_assert_eq!(
+            serde_json::to_value(&updated.passkey.0).unwrap(),
+            serde_json::to_value(&expected_passkey.0).unwrap()
+        );
+    }
+
+    #[test]
+    fn prop_update_queued_email(
+        state in option::of(queued_email_state()),
+        attempts in option::of(any::<u32>()),
+        last_error in option::of("\\PC{0,20}"),
+        next_attempt_at in option::of(timestamp()),
+    ) {
+        let update = QueuedEmailUpdate {
+            state,
+            attempts,
+            last_error,
+            next_attempt_at,
+        };
+        let (original, result) = block_on(async {
+            let client = SqliteClient::new_memory().await.unwrap();
+            let email = QueuedEmail {
+                id: Uuid::new_v4(),
+                sender: "iam@example.com".to_string(),
+                recipient: "test@kasad.com".to_string(),
+                subject: "Subject".to_string(),
+                html: "<p>Body</p>".to_string(),
+                text: "Body".to_string(),
+                state: QueuedEmailState::Pending,
+                attempts: 0,
+                last_error: None,
+                created_at: DateTime::from_timestamp(1_000, 0).unwrap(),
+                next_attempt_at: DateTime::from_timestamp(2_000, 0).unwrap(),
+            };
+            client.create_queued_email(&email).await.unwrap();
+            let result = client.update_queued_email(&email.id, &update).await;
+            (email, result)
+        });
+        if update.is_empty() {
+            prop_assert!(matches!(result, Err(DatabaseError::EmptyUpdate)));
+            return Ok(());
+        }
+        let updated = result.unwrap();
+        prop_assert_eq!(updated.id, original.id);
+        prop_assert_eq!(updated.state, update.state.unwrap_or(original.state));
+        prop_assert_eq!(updated.attempts, update.attempts.unwrap_or(original.attempts));
+        prop_assert_eq!(updated.last_error, update.last_error.or(original.last_error));
+        prop_assert_eq!(
+            updated.next_attempt_at,
+            update.next_attempt_at.unwrap_or(original.next_attempt_at)
+        );
+        prop_assert_eq!(updated.subject, original.subject);
+    }
+}
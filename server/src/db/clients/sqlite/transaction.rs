@@ -0,0 +1,67 @@
+//! # SQLite3 database transactions
+
+use async_trait::async_trait;
+use sqlx::{Sqlite, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    db::interface::{DatabaseError, DatabaseTransaction},
+    models::{
+        EncodableHash, NewPasskeyCredential, PasskeyCredential, Session, SessionUpdate, Tag, User,
+        UserCreate,
+    },
+};
+
+use super::{apply_session_update, insert_passkey, insert_session, insert_user, insert_user_tag};
+
+/// # SQLite3 database transaction
+///
+/// Returned by [`SqliteClient::begin()`][1]. Wraps a [`sqlx::Transaction`], which is rolled back
+/// if it's dropped without being committed.
+///
+/// [1]: crate::db::interface::DatabaseClient::begin
+pub struct SqliteTransaction {
+    tx: Transaction<'static, Sqlite>,
+}
+
+impl SqliteTransaction {
+    pub(super) fn new(tx: Transaction<'static, Sqlite>) -> Self {
+        Self { tx }
+    }
+}
+
+#[async_trait]
+impl DatabaseTransaction for SqliteTransaction {
+    async fn create_user(&mut self, id: &Uuid, user: &UserCreate) -> Result<User, DatabaseError> {
+        insert_user(&mut *self.tx, id, user).await
+    }
+
+    async fn add_tag_to_user(&mut self, user_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError> {
+        insert_user_tag(&mut *self.tx, user_id, tag).await
+    }
+
+    async fn create_passkey(
+        &mut self,
+        id: &Uuid,
+        user_id: &Uuid,
+        passkey: &NewPasskeyCredential,
+    ) -> Result<PasskeyCredential, DatabaseError> {
+        insert_passkey(&mut *self.tx, id, user_id, passkey).await
+    }
+
+    async fn create_session(&mut self, session: &Session) -> Result<(), DatabaseError> {
+        insert_session(&mut *self.tx, session).await
+    }
+
+    async fn update_session(
+        &mut self,
+        id_hash: &EncodableHash,
+        update: &SessionUpdate,
+    ) -> Result<Session, DatabaseError> {
+        apply_session_update(&mut *self.tx, id_hash, update).await
+    }
+
+    async fn commit(self: Box<Self>) -> Result<(), DatabaseError> {
+        Ok(self.tx.commit().await?)
+    }
+}
@@ -0,0 +1,113 @@
+//! # Dynamic `UPDATE` query builder
+//!
+//! Partial updates (e.g. [`UserUpdate`][crate::models::UserUpdate]) only assign the fields which
+//! have a value, so their SQL must be assembled at runtime. [`UpdateQuery`] binds each value as
+//! soon as its assignment is added, so placeholders and arguments can't get out of order.
+
+use sqlx::{Encode, QueryBuilder, Sqlite, Type};
+
+use crate::db::interface::DatabaseError;
+
+/// Builder for queries of the form `UPDATE <table> SET <column> = ?, ... WHERE <key> = ?
+/// RETURNING <columns>`
+pub(super) struct UpdateQuery<'args> {
+    builder: QueryBuilder<'args, Sqlite>,
+    assignments: usize,
+}
+
+impl<'args> UpdateQuery<'args> {
+    /// Starts an update of the given table. The table name is inserted verbatim, so it must not
+    /// come from user input.
+    pub fn new(table: &str) -> Self {
+        let mut builder = QueryBuilder::new("UPDATE ");
+        builder.push(table).push(" SET ");
+        Self {
+            builder,
+            assignments: 0,
+        }
+    }
+
+    /// Adds `<column> = ` to the query, preceded by a separator if needed.
+    fn push_column(&mut self, column: &str) {
+        if self.assignments > 0 {
+            self.builder.push(", ");
+        }
+        self.assignments += 1;
+        self.builder.push(column).push(" = ");
+    }
+
+    /// Assigns the given value to the column if the value is [`Some`].
+    pub fn set<T>(&mut self, column: &str, value: Option<T>) -> &mut Self
+    where
+        T: 'args + Encode<'args, Sqlite> + Type<Sqlite>,
+    {
+        if let Some(value) = value {
+            self.push_column(column);
+            self.builder.push_bind(value);
+        }
+        self
+    }
+
+    /// Assigns the result of the given SQL expression to the column, e.g. `unixepoch()`. The
+    /// expression is inserted verbatim.
+    pub fn set_expr(&mut self, column: &str, expr: &str) -> &mut Self {
+        self.push_column(column);
+        self.builder.push(expr);
+        self
+    }
+
+    /// Adds the `WHERE` and `RETURNING` clauses, returning the finished query. Returns
+    /// [`DatabaseError::EmptyUpdate`] if no columns are assigned.
+    pub fn finish<K>(
+        mut self,
+        key_column: &str,
+        key: K,
+        returning: &str,
+    ) -> Result<QueryBuilder<'args, Sqlite>, DatabaseError>
+    where
+        K: 'args + Encode<'args, Sqlite> + Type<Sqlite>,
+    {
+        if self.assignments == 0 {
+            return Err(DatabaseError::EmptyUpdate);
+        }
+        self.builder
+            .push(" WHERE ")
+            .push(key_column)
+            .push(" = ")
+            .push_bind(key)
+            .push(" RETURNING ")
+            .push(returning);
+        Ok(self.builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sql() {
+        let mut query = UpdateQuery::new("users");
+        query
+            .set("email", Some("a@example.com"))
+            .set::<&str>("display_name", None)
+            .set("login_notifications", Some(true))
+            .set_expr("updated_at", "unixepoch()");
+        let query = query.finish("id", 1, "*").unwrap();
+        assert_eq!(
+            query.sql(),
+            "UPDATE users SET email = ?, login_notifications = ?, updated_at = unixepoch() \
+            WHERE id = ? RETURNING *"
+        );
+    }
+
+    #[test]
+    fn test_empty() {
+        let mut query = UpdateQuery::new("users");
+        query.set::<&str>("email", None);
+        assert!(matches!(
+            query.finish("id", 1, "*"),
+            Err(DatabaseError::EmptyUpdate)
+        ));
+    }
+}
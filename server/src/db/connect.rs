@@ -0,0 +1,130 @@
+//! # Retrying database connections
+//!
+//! When the server is started alongside its database, e.g. by a container orchestrator, the
+//! database may not accept connections yet. Rather than exiting immediately, connections are
+//! retried with exponential backoff, up to a configured number of times. Only errors which may go
+//! away on their own are retried, so that e.g. a failed migration is still reported at once.
+
+use std::{fmt::Display, time::Duration};
+
+use tracing::warn;
+
+/// Options for retrying failed database connections
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectRetryOptions {
+    /// Number of times a failed connection is retried before giving up
+    pub retries: u32,
+    /// Delay before the first retry, which doubles with each further retry
+    pub base_delay: Duration,
+    /// Longest delay between retries
+    pub max_delay: Duration,
+}
+
+impl Default for ConnectRetryOptions {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl ConnectRetryOptions {
+    /// Returns the delay before the given retry, counting from 1.
+    fn retry_delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.base_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+
+    /// Calls `connect` until it succeeds, it fails with an error for which `is_transient` returns
+    /// `false`, or the retries run out. Returns the result of the last call. `what` names the
+    /// connection in the logged warnings.
+    pub async fn retry<T, E, F>(
+        &self,
+        what: &str,
+        mut connect: impl FnMut() -> F,
+        is_transient: impl Fn(&E) -> bool,
+    ) -> Result<T, E>
+    where
+        F: Future<Output = Result<T, E>>,
+        E: Display,
+    {
+        let mut retry = 0;
+        loop {
+            match connect().await {
+                Err(err) if retry < self.retries && is_transient(&err) => {
+                    retry += 1;
+                    let delay = self.retry_delay(retry);
+                    warn!(%err, retry, retries = self.retries, ?delay, "failed to connect to {what}; will retry");
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        let options = ConnectRetryOptions::default();
+        assert_eq!(options.retry_delay(1), Duration::from_secs(1));
+        assert_eq!(options.retry_delay(2), Duration::from_secs(2));
+        assert_eq!(options.retry_delay(5), Duration::from_secs(16));
+        assert_eq!(options.retry_delay(100), options.max_delay);
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let options = ConnectRetryOptions {
+            retries: 3,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        };
+        let calls = Cell::new(0);
+        let connect = || {
+            calls.set(calls.get() + 1);
+            let call = calls.get();
+            async move { if call < 3 { Err("down") } else { Ok(call) } }
+        };
+        assert_eq!(options.retry("test", connect, |_| true).await, Ok(3));
+
+        // Retries run out
+        calls.set(0);
+        let result: Result<(), _> = options
+            .retry(
+                "test",
+                || {
+                    calls.set(calls.get() + 1);
+                    async { Err("down") }
+                },
+                |_| true,
+            )
+            .await;
+        assert_eq!(result, Err("down"));
+        assert_eq!(calls.get(), 4);
+
+        // Permanent errors aren't retried
+        calls.set(0);
+        let result: Result<(), _> = options
+            .retry(
+                "test",
+                || {
+                    calls.set(calls.get() + 1);
+                    async { Err("broken") }
+                },
+                |err| *err != "broken",
+            )
+            .await;
+        assert_eq!(result, Err("broken"));
+        assert_eq!(calls.get(), 1);
+    }
+}
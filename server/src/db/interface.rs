@@ -2,14 +2,22 @@
 //!
 //! See [`DatabaseClient`] for details.
 
-use std::{borrow::Cow, future::Future, pin::Pin};
+use std::{borrow::Cow, collections::HashMap, time::Duration};
 
+use async_trait::async_trait;
 use uuid::Uuid;
 
 use crate::models::{
-    EncodableHash, NewPasskeyCredential, PasskeyAuthenticationState, PasskeyCredential,
-    PasskeyCredentialUpdate, PasskeyRegistrationState, Session, SessionUpdate, Tag, TagUpdate,
-    User, UserCreate, UserUpdate,
+    Announcement, ApiToken, AuthorizationCode, Backup, EmailSuppression, EncodableHash,
+    FederatedIdentity, FederatedLoginState, FederatedProvider, FederatedProviderUpdate, Group,
+    GroupUpdate, IpBan, MaintenanceTask, NewPasskeyCredential, OAuthClient, OAuthClientUpdate,
+    Organization, OrganizationMember, OrganizationRole, OrganizationUpdate,
+    PasskeyAuthenticationState, PasskeyCredential, PasskeyCredentialUpdate,
+    PasskeyRegistrationState, Policy, PolicyUpdate, PolicyVersion, QueuedEmail, QueuedEmailUpdate,
+    RetentionPolicy, RetentionReport, Role, RoleUpdate, SamlServiceProvider,
+    SamlServiceProviderUpdate, Session, SessionUpdate, StoredSessionCookieKey, StoredSigningKey,
+    Tag, TagUpdate, User, UserCreate, UserCursor, UserList, UserListQuery, UserMerge, UserNote,
+    UserStatus, UserUpdate, Webhook, WebhookUpdate,
 };
 
 /// # Database abstraction layer interface
@@ -24,198 +32,928 @@ use crate::models::{
 /// function is not part of this trait.
 ///
 /// [`SqliteClient`]: crate::db::clients::sqlite::SqliteClient
+#[async_trait]
 pub trait DatabaseClient: Send + Sync + 'static {
     // User repository
 
     /// Creates a new [`User`] with the given ID and initial information and returns a result
     /// containing the created [`User`] or an error.
-    fn create_user<'user>(
-        &self,
-        id: &'user Uuid,
-        user: &'user UserCreate,
-    ) -> Pin<Box<dyn Future<Output = Result<User, DatabaseError>> + Send + 'user>>;
+    async fn create_user(&self, id: &Uuid, user: &UserCreate) -> Result<User, DatabaseError>;
 
     /// Fetches the [`User`] with the given user ID.
-    fn get_user_by_id<'id>(
-        &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<User, DatabaseError>> + Send + 'id>>;
+    async fn get_user_by_id(&self, id: &Uuid) -> Result<User, DatabaseError>;
 
     /// Fetches the [`User`] with the given email address.
-    fn get_user_by_email<'email>(
-        &self,
-        email: &'email str,
-    ) -> Pin<Box<dyn Future<Output = Result<User, DatabaseError>> + Send + 'email>>;
+    async fn get_user_by_email(&self, email: &str) -> Result<User, DatabaseError>;
+
+    /// Fetches the [`User`] with the given user ID along with the user's passkeys and tags, which
+    /// are populated in the returned [`User`].
+    async fn get_user_with_relations(&self, id: &Uuid) -> Result<User, DatabaseError>;
 
     /// Alters the [`User`] with the given UUID, returning the updated [`User`] on success.
-    fn update_user<'arg>(
+    /// Setting the email address marks it as unverified.
+    async fn update_user(&self, id: &Uuid, update: &UserUpdate) -> Result<User, DatabaseError>;
+
+    /// Stores a pending change of the email address of the [`User`] with the UUID `user_id` to
+    /// `email`, which [`DatabaseClient::confirm_email_change()`] applies given the token with the
+    /// given hash. Replaces any change the user requested before.
+    async fn create_email_change(
+        &self,
+        user_id: &Uuid,
+        email: &str,
+        token_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError>;
+
+    /// Consumes the pending email address change with the given token hash, changing the
+    /// [`User`]'s email address and marking it as verified, and returns the updated [`User`].
+    /// Returns [`DatabaseError::NotFound`] if no such change exists or it was requested more than
+    /// `max_age` ago, or [`DatabaseError::UniquenessViolation`] if another user has the address.
+    async fn confirm_email_change(
+        &self,
+        token_hash: &EncodableHash,
+        max_age: Duration,
+    ) -> Result<User, DatabaseError>;
+
+    /// Sets the last login time of the [`User`] with the given UUID to the current time.
+    async fn record_user_login(&self, id: &Uuid) -> Result<(), DatabaseError>;
+
+    /// Records a failed login to the account of the [`User`] with the UUID `user_id`, made with
+    /// the passkey with the UUID `passkey_id` if it is known, and forgets the user's failed logins
+    /// which are older than `window`. Returns the number of failed logins within the window,
+    /// including this one.
+    async fn record_login_failure(
         &self,
-        id: &'arg Uuid,
-        update: &'arg UserUpdate,
-    ) -> Pin<Box<dyn Future<Output = Result<User, DatabaseError>> + Send + 'arg>>;
+        user_id: &Uuid,
+        passkey_id: Option<&Uuid>,
+        window: Duration,
+    ) -> Result<u64, DatabaseError>;
 
-    /// Deletes the [`User`] with the given UUID.
-    fn delete_user_by_id<'id>(
+    /// Forgets the failed logins to the account of the [`User`] with the given UUID.
+    async fn clear_login_failures(&self, user_id: &Uuid) -> Result<(), DatabaseError>;
+
+    /// Locks the account of the [`User`] with the given UUID until the given time, or unlocks it
+    /// if `until` is [`None`]. Either way, the user's failed logins are forgotten.
+    async fn set_user_locked_until(
+        &self,
+        id: &Uuid,
+        until: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<(), DatabaseError>;
+
+    /// Sets the [status][UserStatus] of the [`User`] with the given UUID and returns the updated
+    /// user.
+    async fn set_user_status(&self, id: &Uuid, status: UserStatus) -> Result<User, DatabaseError>;
+
+    /// Merges the [`User`] with the UUID `source_id` into the one with the UUID `target_id`,
+    /// moving the source user's passkeys, tags, organization and group memberships, sessions,
+    /// devices, notes, and federated identities to the target user and then deleting the source
+    /// user. If `dry_run` is `true`, nothing is changed, but the returned [`UserMerge`] still
+    /// describes what would be moved.
+    async fn merge_users(
         &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'id>>;
+        source_id: &Uuid,
+        target_id: &Uuid,
+        dry_run: bool,
+    ) -> Result<UserMerge, DatabaseError>;
+
+    /// Scrubs the personal data of the [`User`] with the given UUID while keeping the user's row,
+    /// so that references to it and aggregate statistics remain intact. The user's email address
+    /// and display name are replaced by placeholders, and their passkeys, known devices, federated
+    /// identities, pending authentications, and queued emails are deleted. Their active sessions
+    /// are revoked, the IP addresses and user agents of all their sessions are removed, and the
+    /// bodies of administrator notes about them are redacted. Returns the anonymized user.
+    async fn anonymize_user(&self, id: &Uuid) -> Result<User, DatabaseError>;
+
+    /// Lists up to `limit` of the [`User`]s matching the query's filter, sorted as it requests,
+    /// starting after the position `after` if one is given. The cursor must point into a list with
+    /// the same sort order. The total is the number of users matching the filter.
+    async fn list_users(
+        &self,
+        query: &UserListQuery,
+        after: Option<&UserCursor>,
+        limit: u32,
+    ) -> Result<UserList, DatabaseError>;
+
+    /// Searches for up to `limit` [`User`]s whose email address or display name contain words
+    /// starting with each word of `query`, best matches first. Returns no users if `query`
+    /// contains no words.
+    async fn search_users(&self, query: &str, limit: u32) -> Result<Vec<User>, DatabaseError>;
+
+    /// Deletes the [`User`] with the given UUID.
+    async fn delete_user_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
 
     /// Adds the given [`Tag`] to the user with the given UUID.
-    fn add_tag_to_user<'arg>(
-        &self,
-        user_id: &'arg Uuid,
-        tag: &'arg Tag,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'arg>>;
+    async fn add_tag_to_user(&self, user_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError>;
 
     /// Removes the given [`Tag`] from the user with the given UUID.
-    fn remove_tag_from_user<'arg>(
-        &self,
-        user_id: &'arg Uuid,
-        tag: &'arg Tag,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'arg>>;
+    async fn remove_tag_from_user(&self, user_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError>;
 
     /// Fetches a list of users who belong to the [`Tag`] with the given UUID.
-    fn get_users_by_tag_id<'id>(
-        &self,
-        tag_id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<Vec<User>, DatabaseError>> + Send + 'id>>;
+    async fn get_users_by_tag_id(&self, tag_id: &Uuid) -> Result<Vec<User>, DatabaseError>;
+
+    // User note repository
+
+    /// Stores a new revision of a user's administrator notes. Returns the stored [`UserNote`].
+    async fn create_user_note(&self, note: &UserNote) -> Result<UserNote, DatabaseError>;
+
+    /// Fetches every revision of the notes on the [`User`] with the given UUID, newest first.
+    async fn get_user_notes(&self, user_id: &Uuid) -> Result<Vec<UserNote>, DatabaseError>;
 
     // Tag repository
 
-    /// Creates a new [`Tag`] with the given ID and initial information. Returns the newly
-    /// created [`Tag`] on success.
-    fn create_tag<'tag>(
+    /// Creates a new [`Tag`] with the given ID and initial information, belonging to the
+    /// [`Organization`] with the UUID `organization_id` if one is given. Returns the newly created
+    /// [`Tag`] on success.
+    async fn create_tag(
         &self,
-        id: &'tag Uuid,
-        tag: &'tag TagUpdate,
-    ) -> Pin<Box<dyn Future<Output = Result<Tag, DatabaseError>> + Send + 'tag>>;
+        id: &Uuid,
+        organization_id: Option<&Uuid>,
+        tag: &TagUpdate,
+    ) -> Result<Tag, DatabaseError>;
 
     /// Fetches the [`Tag`] with the given UUID.
-    fn get_tag_by_id<'id>(
+    async fn get_tag_by_id(&self, id: &Uuid) -> Result<Tag, DatabaseError>;
+
+    /// Fetches the global [`Tag`] (i.e. one which doesn't belong to an [`Organization`]) with the
+    /// given name.
+    async fn get_tag_by_name(&self, name: &str) -> Result<Tag, DatabaseError>;
+
+    /// Fetches all [`Tag`]s, ordered by name.
+    async fn list_tags(&self) -> Result<Vec<Tag>, DatabaseError>;
+
+    /// Alters the [`Tag`] with the given UUID, returning the updated [`Tag`] on success.
+    async fn update_tag(&self, id: &Uuid, update: &TagUpdate) -> Result<Tag, DatabaseError>;
+
+    /// Deletes the [`Tag`] with the given UUID, removing it from all users to which it is applied.
+    async fn delete_tag_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
+
+    /// Fetches a list of tags to which the [`User`] with the given UUID belongs.
+    async fn get_tags_by_user_id(&self, user_id: &Uuid) -> Result<Vec<Tag>, DatabaseError>;
+
+    /// Fetches the tags of each of the [`User`]s with the given UUIDs at once, ordered by name.
+    /// Every given UUID is a key of the returned map, including those of users without tags or
+    /// which don't exist, whose lists are empty.
+    async fn get_tags_by_user_ids(
         &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<Tag, DatabaseError>> + Send + 'id>>;
+        user_ids: &[Uuid],
+    ) -> Result<HashMap<Uuid, Vec<Tag>>, DatabaseError>;
 
-    /// Fetches the [`Tag`] with the given name.
-    fn get_tag_by_name<'name>(
+    /// Fetches the [`Tag`]s of the [`Organization`] with the given UUID, ordered by name.
+    async fn get_tags_by_organization_id(
         &self,
-        name: &'name str,
-    ) -> Pin<Box<dyn Future<Output = Result<Tag, DatabaseError>> + Send + 'name>>;
+        organization_id: &Uuid,
+    ) -> Result<Vec<Tag>, DatabaseError>;
 
-    /// Alters the [`Tag`] with the given UUID, returning the updated [`Tag`] on success.
-    fn update_tag<'arg>(
+    // Organization repository
+
+    /// Creates a new [`Organization`] with the given ID and initial information. Returns the
+    /// newly created [`Organization`] on success.
+    async fn create_organization(
         &self,
-        id: &'arg Uuid,
-        update: &'arg TagUpdate,
-    ) -> Pin<Box<dyn Future<Output = Result<Tag, DatabaseError>> + Send + 'arg>>;
+        id: &Uuid,
+        organization: &OrganizationUpdate,
+    ) -> Result<Organization, DatabaseError>;
 
-    /// Deletes the [`Tag`] with the given UUID.
-    fn delete_tag_by_id<'id>(
+    /// Fetches the [`Organization`] with the given UUID.
+    async fn get_organization_by_id(&self, id: &Uuid) -> Result<Organization, DatabaseError>;
+
+    /// Fetches all [`Organization`]s, ordered by name.
+    async fn list_organizations(&self) -> Result<Vec<Organization>, DatabaseError>;
+
+    /// Alters the [`Organization`] with the given UUID, returning the updated [`Organization`] on
+    /// success.
+    async fn update_organization(
         &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'id>>;
+        id: &Uuid,
+        update: &OrganizationUpdate,
+    ) -> Result<Organization, DatabaseError>;
 
-    /// Fetches a list of tags to which the [`User`] with the given UUID belongs.
-    fn get_tags_by_user_id<'id>(
+    /// Deletes the [`Organization`] with the given UUID, along with its memberships and tags.
+    /// Sessions acting within it no longer act within any organization.
+    async fn delete_organization_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
+
+    /// Fetches the [`Organization`]s of which the [`User`] with the given UUID is a member,
+    /// ordered by name.
+    async fn get_organizations_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<Organization>, DatabaseError>;
+
+    /// Fetches the members of the [`Organization`] with the given UUID, ordered by email address.
+    async fn get_organization_members(
+        &self,
+        organization_id: &Uuid,
+    ) -> Result<Vec<OrganizationMember>, DatabaseError>;
+
+    /// Fetches the membership of the [`User`] with the UUID `user_id` in the [`Organization`]
+    /// with the UUID `organization_id`. Returns [`DatabaseError::NotFound`] if the user isn't a
+    /// member.
+    async fn get_organization_member(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<OrganizationMember, DatabaseError>;
+
+    /// Makes the [`User`] with the UUID `user_id` a member of the [`Organization`] with the UUID
+    /// `organization_id` with the given role, or changes their role if they already are one.
+    async fn set_organization_member(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+        role: OrganizationRole,
+    ) -> Result<OrganizationMember, DatabaseError>;
+
+    /// Removes the [`User`] with the UUID `user_id` from the [`Organization`] with the UUID
+    /// `organization_id`, along with the organization's tags. Returns [`DatabaseError::NotFound`]
+    /// if the user isn't a member.
+    async fn remove_organization_member(
         &self,
-        user_id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<Vec<Tag>, DatabaseError>> + Send + 'id>>;
+        organization_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<(), DatabaseError>;
+
+    // Group repository
+
+    /// Creates a new [`Group`] with the given ID and initial information. Returns the newly
+    /// created [`Group`] on success.
+    async fn create_group(&self, id: &Uuid, group: &GroupUpdate) -> Result<Group, DatabaseError>;
+
+    /// Fetches the [`Group`] with the given UUID.
+    async fn get_group_by_id(&self, id: &Uuid) -> Result<Group, DatabaseError>;
+
+    /// Fetches all [`Group`]s, ordered by name.
+    async fn list_groups(&self) -> Result<Vec<Group>, DatabaseError>;
+
+    /// Alters the [`Group`] with the given UUID, returning the updated [`Group`] on success.
+    async fn update_group(&self, id: &Uuid, update: &GroupUpdate) -> Result<Group, DatabaseError>;
+
+    /// Deletes the [`Group`] with the given UUID, along with its memberships and tags. Its
+    /// subgroups are kept, but no longer belong to it.
+    async fn delete_group_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
+
+    /// Fetches the members of the [`Group`] with the given UUID, ordered by email address. If
+    /// `include_subgroups` is true, the members of its subgroups, at any depth, are included.
+    async fn get_users_by_group_id(
+        &self,
+        group_id: &Uuid,
+        include_subgroups: bool,
+    ) -> Result<Vec<User>, DatabaseError>;
+
+    /// Fetches the [`Group`]s of which the [`User`] with the given UUID is a direct member,
+    /// ordered by name.
+    async fn get_groups_by_user_id(&self, user_id: &Uuid) -> Result<Vec<Group>, DatabaseError>;
+
+    /// Adds the [`User`] with the UUID `user_id` to the [`Group`] with the UUID `group_id`.
+    /// Adding a user who is already a member does nothing.
+    async fn add_user_to_group(&self, group_id: &Uuid, user_id: &Uuid)
+    -> Result<(), DatabaseError>;
+
+    /// Removes the [`User`] with the UUID `user_id` from the [`Group`] with the UUID `group_id`.
+    /// Returns [`DatabaseError::NotFound`] if the user isn't a direct member.
+    async fn remove_user_from_group(
+        &self,
+        group_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<(), DatabaseError>;
+
+    /// Fetches the direct subgroups of the [`Group`] with the given UUID, ordered by name.
+    async fn get_subgroups(&self, group_id: &Uuid) -> Result<Vec<Group>, DatabaseError>;
+
+    /// Fetches the UUIDs of the [`Group`]s which contain the group with the given UUID, directly
+    /// or through other subgroups.
+    async fn get_supergroup_ids(&self, group_id: &Uuid) -> Result<Vec<Uuid>, DatabaseError>;
+
+    /// Makes the [`Group`] with the UUID `child_id` a subgroup of the one with the UUID
+    /// `parent_id`. Adding an existing subgroup does nothing. Callers must make sure that this
+    /// doesn't create a cycle, i.e. that the parent isn't a subgroup of the child.
+    async fn add_subgroup(&self, parent_id: &Uuid, child_id: &Uuid) -> Result<(), DatabaseError>;
+
+    /// Removes the [`Group`] with the UUID `child_id` from the one with the UUID `parent_id`.
+    /// Returns [`DatabaseError::NotFound`] if it isn't a direct subgroup.
+    async fn remove_subgroup(&self, parent_id: &Uuid, child_id: &Uuid)
+    -> Result<(), DatabaseError>;
+
+    /// Applies the given [`Tag`] to the [`Group`] with the given UUID. Applying a tag which the
+    /// group already has does nothing.
+    async fn add_tag_to_group(&self, group_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError>;
+
+    /// Removes the given [`Tag`] from the [`Group`] with the given UUID.
+    async fn remove_tag_from_group(&self, group_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError>;
+
+    /// Fetches the [`Tag`]s applied to the [`Group`] with the given UUID, ordered by name.
+    async fn get_tags_by_group_id(&self, group_id: &Uuid) -> Result<Vec<Tag>, DatabaseError>;
+
+    /// Fetches the [`Tag`]s which apply to the [`User`] with the given UUID, ordered by name:
+    /// those applied to them directly, and those applied to the groups of which they are members,
+    /// directly or through subgroups.
+    async fn get_effective_tags_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<Tag>, DatabaseError>;
+
+    // Role repository
+
+    /// Creates a new [`Role`] with the given ID and initial information. Returns the newly
+    /// created [`Role`] on success. If no permissions are given, the role grants none.
+    async fn create_role(&self, id: &Uuid, role: &RoleUpdate) -> Result<Role, DatabaseError>;
+
+    /// Fetches the [`Role`] with the given UUID.
+    async fn get_role_by_id(&self, id: &Uuid) -> Result<Role, DatabaseError>;
+
+    /// Fetches all [`Role`]s, ordered by name.
+    async fn list_roles(&self) -> Result<Vec<Role>, DatabaseError>;
+
+    /// Alters the [`Role`] with the given UUID, returning the updated [`Role`] on success.
+    async fn update_role(&self, id: &Uuid, update: &RoleUpdate) -> Result<Role, DatabaseError>;
+
+    /// Deletes the [`Role`] with the given UUID, removing it from all tags.
+    async fn delete_role_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
+
+    /// Fetches the [`Role`]s assigned to the [`Tag`] with the given UUID, ordered by name.
+    async fn get_roles_by_tag_id(&self, tag_id: &Uuid) -> Result<Vec<Role>, DatabaseError>;
+
+    /// Assigns the [`Role`] with the UUID `role_id` to the [`Tag`] with the UUID `tag_id`.
+    /// Assigning a role which the tag already has does nothing.
+    async fn add_role_to_tag(&self, tag_id: &Uuid, role_id: &Uuid) -> Result<(), DatabaseError>;
+
+    /// Removes the [`Role`] with the UUID `role_id` from the [`Tag`] with the UUID `tag_id`.
+    /// Returns [`DatabaseError::NotFound`] if the role isn't assigned to the tag.
+    async fn remove_role_from_tag(
+        &self,
+        tag_id: &Uuid,
+        role_id: &Uuid,
+    ) -> Result<(), DatabaseError>;
+
+    // Policy repository
+
+    /// Creates a new [`Policy`] with the given ID and initial information, as version 1. Returns
+    /// the newly created [`Policy`] on success. Policies are enabled unless stated otherwise.
+    async fn create_policy(
+        &self,
+        id: &Uuid,
+        policy: &PolicyUpdate,
+    ) -> Result<Policy, DatabaseError>;
+
+    /// Fetches the [`Policy`] with the given UUID.
+    async fn get_policy_by_id(&self, id: &Uuid) -> Result<Policy, DatabaseError>;
+
+    /// Fetches all [`Policy`]s, ordered by name.
+    async fn list_policies(&self) -> Result<Vec<Policy>, DatabaseError>;
+
+    /// Alters the [`Policy`] with the given UUID, returning the updated [`Policy`] on success.
+    /// Replacing the document increments the policy's version and records the new version.
+    async fn update_policy(
+        &self,
+        id: &Uuid,
+        update: &PolicyUpdate,
+    ) -> Result<Policy, DatabaseError>;
+
+    /// Deletes the [`Policy`] with the given UUID, along with its versions.
+    async fn delete_policy_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
+
+    /// Fetches the versions of the [`Policy`] with the given UUID, newest first.
+    async fn list_policy_versions(
+        &self,
+        policy_id: &Uuid,
+    ) -> Result<Vec<PolicyVersion>, DatabaseError>;
+
+    /// Fetches a version of the [`Policy`] with the given UUID.
+    async fn get_policy_version(
+        &self,
+        policy_id: &Uuid,
+        version: i64,
+    ) -> Result<PolicyVersion, DatabaseError>;
 
     // Passkey repository
 
     /// Creates a new [`PasskeyCredential`] with the given UUID and initial information for the
     /// user with the given user UUID. Returns the newly created [`PasskeyCredential`] on success.
-    fn create_passkey<'a>(
+    async fn create_passkey(
         &self,
-        id: &'a Uuid,
-        user_id: &'a Uuid,
-        passkey: &'a NewPasskeyCredential,
-    ) -> Pin<Box<dyn Future<Output = Result<PasskeyCredential, DatabaseError>> + Send + 'a>>;
+        id: &Uuid,
+        user_id: &Uuid,
+        passkey: &NewPasskeyCredential,
+    ) -> Result<PasskeyCredential, DatabaseError>;
 
     /// Fetches a [`PasskeyCredential`] by its UUID.
-    fn get_passkey_by_id<'id>(
-        &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<PasskeyCredential, DatabaseError>> + Send + 'id>>;
+    async fn get_passkey_by_id(&self, id: &Uuid) -> Result<PasskeyCredential, DatabaseError>;
 
     /// Fetches a [`PasskeyCredential`] by its credential ID.
-    fn get_passkey_by_credential_id<'id>(
+    async fn get_passkey_by_credential_id(
         &self,
-        credential_id: &'id [u8],
-    ) -> Pin<Box<dyn Future<Output = Result<PasskeyCredential, DatabaseError>> + Send + 'id>>;
+        credential_id: &[u8],
+    ) -> Result<PasskeyCredential, DatabaseError>;
 
     /// Fetches a list of [`PasskeyCredential`]s belonging to the [`User`] with the given UUID.
-    fn get_passkeys_by_user_id<'id>(
+    async fn get_passkeys_by_user_id(
         &self,
-        user_id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<Vec<PasskeyCredential>, DatabaseError>> + Send + 'id>>;
+        user_id: &Uuid,
+    ) -> Result<Vec<PasskeyCredential>, DatabaseError>;
 
     /// Fetches a list of [`PasskeyCredential`]s belonging to the [`User`] with the given email.
-    fn get_passkeys_by_user_email<'email>(
+    async fn get_passkeys_by_user_email(
         &self,
-        email: &'email str,
-    ) -> Pin<Box<dyn Future<Output = Result<Vec<PasskeyCredential>, DatabaseError>> + Send + 'email>>;
+        email: &str,
+    ) -> Result<Vec<PasskeyCredential>, DatabaseError>;
 
     /// Alters the [`PasskeyCredential`] with the given UUID. Returns the updated
     /// [`PasskeyCredential`] on success.
-    fn update_passkey<'key>(
+    async fn update_passkey(
         &self,
-        id: &'key Uuid,
-        passkey: &'key PasskeyCredentialUpdate,
-    ) -> Pin<Box<dyn Future<Output = Result<PasskeyCredential, DatabaseError>> + Send + 'key>>;
+        id: &Uuid,
+        passkey: &PasskeyCredentialUpdate,
+    ) -> Result<PasskeyCredential, DatabaseError>;
 
     /// Deletes the [`PasskeyCredential`] with the given UUID.
-    fn delete_passkey_by_id<'id>(
-        &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'id>>;
+    async fn delete_passkey_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
+
+    /// Deletes the [`PasskeyCredential`] with the given UUID, unless it is its user's last
+    /// passkey. The check and the deletion are atomic, so concurrent requests can't delete all of
+    /// a user's passkeys. Returns whether the passkey was deleted.
+    async fn delete_passkey_unless_last(&self, id: &Uuid) -> Result<bool, DatabaseError>;
 
     // Authentication repository
+    //
+    // Ceremony state must live in the database rather than in process memory, because a ceremony
+    // may be started on one server instance and finished on another.
 
     /// Stores a [passkey registration state object][PasskeyRegistrationState].
-    fn create_passkey_registration<'a>(
+    async fn create_passkey_registration(
         &self,
-        registration: &'a PasskeyRegistrationState,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'a>>;
+        registration: &PasskeyRegistrationState,
+    ) -> Result<(), DatabaseError>;
 
     /// Fetches the [`PasskeyRegistrationState`] with the given UUID.
-    fn get_passkey_registration_by_id<'id>(
+    async fn get_passkey_registration_by_id(
         &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<PasskeyRegistrationState, DatabaseError>> + Send + 'id>>;
+        id: &Uuid,
+    ) -> Result<PasskeyRegistrationState, DatabaseError>;
+
+    /// Deletes the [`PasskeyRegistrationState`] with the given UUID, so that it can't be used to
+    /// finish a registration again.
+    ///
+    /// Returns [`DatabaseError::NotFound`] if no such state exists, e.g. because it was already
+    /// consumed by a concurrent request.
+    async fn delete_passkey_registration_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
 
     /// Stores a [passkey authentication state object][PasskeyAuthenticationState].
-    fn create_passkey_authentication<'a>(
+    async fn create_passkey_authentication(
         &self,
-        state: &'a PasskeyAuthenticationState,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'a>>;
+        state: &PasskeyAuthenticationState,
+    ) -> Result<(), DatabaseError>;
 
     /// Fetches the [`PasskeyAuthenticationState`] with the given UUID.
-    fn get_passkey_authentication_by_id<'id>(
+    async fn get_passkey_authentication_by_id(
         &self,
-        id: &'id Uuid,
-    ) -> Pin<Box<dyn Future<Output = Result<PasskeyAuthenticationState, DatabaseError>> + Send + 'id>>;
+        id: &Uuid,
+    ) -> Result<PasskeyAuthenticationState, DatabaseError>;
+
+    /// Deletes the [`PasskeyAuthenticationState`] with the given UUID, so that it can't be used
+    /// to finish an authentication again.
+    ///
+    /// Returns [`DatabaseError::NotFound`] if no such state exists, e.g. because it was already
+    /// consumed by a concurrent request.
+    async fn delete_passkey_authentication_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
+
+    /// Deletes the passkey registration and authentication states which were created more than
+    /// `age` ago, since ceremonies that old can't be finished anymore. Returns the number of
+    /// states deleted.
+    async fn delete_expired_ceremonies(&self, age: Duration) -> Result<u64, DatabaseError>;
 
     // Session repository
 
     /// Creatse a new authentication [`Session`].
-    fn create_session<'a>(
-        &self,
-        session: &'a Session,
-    ) -> Pin<Box<dyn Future<Output = Result<(), DatabaseError>> + Send + 'a>>;
+    async fn create_session(&self, session: &Session) -> Result<(), DatabaseError>;
 
     /// Fetches the [`Session`] with the given ID hash.
-    fn get_session_by_id_hash<'id>(
+    async fn get_session_by_id_hash(
         &self,
-        id_hash: &'id EncodableHash,
-    ) -> Pin<Box<dyn Future<Output = Result<Session, DatabaseError>> + Send + 'id>>;
+        id_hash: &EncodableHash,
+    ) -> Result<Session, DatabaseError>;
+
+    /// Fetches the active, unexpired [`Session`]s of the [`User`] with the given UUID, most
+    /// recently used first.
+    async fn get_active_sessions_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<Session>, DatabaseError>;
 
     /// Alters the [`Session`] with the given ID hash. Returns the updated [`Session`] on success.
-    fn update_session<'a>(
+    async fn update_session(
+        &self,
+        id_hash: &EncodableHash,
+        update: &SessionUpdate,
+    ) -> Result<Session, DatabaseError>;
+
+    /// Stores a token which can be used to revoke the [`Session`] with the given ID hash (and any
+    /// sessions descending from it) with [`DatabaseClient::revoke_session_by_token()`].
+    async fn create_session_revocation_token(
+        &self,
+        token_hash: &EncodableHash,
+        session_id_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError>;
+
+    /// Consumes the session revocation token with the given hash, marking the [`Session`] it
+    /// belongs to and all active sessions descending from it as
+    /// [revoked][crate::models::SessionState::Revoked]. Returns [`DatabaseError::NotFound`] if the
+    /// token doesn't exist.
+    async fn revoke_session_by_token(
+        &self,
+        token_hash: &EncodableHash,
+    ) -> Result<(), DatabaseError>;
+
+    /// Deletes the [`Session`]s which ended more than `age` ago, and returns the number of
+    /// sessions deleted. A session ends when it expires or, if it was revoked, logged out, or
+    /// superseded, when it was last used. Sessions are kept while a session superseding them is.
+    async fn delete_ended_sessions(&self, age: Duration) -> Result<u64, DatabaseError>;
+
+    /// Marks all active [`Session`]s of all users as
+    /// [revoked][crate::models::SessionState::Revoked], except the one with the ID hash given in
+    /// `except`, if any. Returns the number of sessions revoked.
+    async fn revoke_all_sessions(
+        &self,
+        except: Option<&EncodableHash>,
+    ) -> Result<u64, DatabaseError>;
+
+    /// Marks all active [`Session`]s of the [`User`] with the given UUID as
+    /// [revoked][crate::models::SessionState::Revoked]. Returns the number of sessions revoked.
+    async fn revoke_sessions_by_user_id(&self, user_id: &Uuid) -> Result<u64, DatabaseError>;
+
+    // API token repository
+
+    /// Stores the given [`ApiToken`] and returns it.
+    async fn create_api_token(&self, token: &ApiToken) -> Result<ApiToken, DatabaseError>;
+
+    /// Fetches the [`ApiToken`] with the given token hash.
+    async fn get_api_token_by_hash(
+        &self,
+        token_hash: &EncodableHash,
+    ) -> Result<ApiToken, DatabaseError>;
+
+    /// Fetches all [`ApiToken`]s of the [`User`] with the given UUID, oldest first.
+    async fn get_api_tokens_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<ApiToken>, DatabaseError>;
+
+    /// Deletes the [`ApiToken`] with the given UUID if it belongs to the [`User`] with the given
+    /// UUID. Returns [`DatabaseError::NotFound`] if no such token exists.
+    async fn delete_api_token(&self, user_id: &Uuid, id: &Uuid) -> Result<(), DatabaseError>;
+
+    // Device repository
+
+    /// Records a login by the [`User`] with the given UUID from the device with the given ID
+    /// hash. Returns `true` if the user had not logged in from that device before.
+    async fn record_user_device(
+        &self,
+        user_id: &Uuid,
+        device_id_hash: &EncodableHash,
+    ) -> Result<bool, DatabaseError>;
+
+    // Email queue repository
+
+    /// Adds the given [`QueuedEmail`] to the outgoing email queue.
+    async fn create_queued_email(&self, email: &QueuedEmail) -> Result<(), DatabaseError>;
+
+    /// Fetches up to `limit` [pending][crate::models::QueuedEmailState::Pending] emails whose next
+    /// delivery attempt is due, oldest first.
+    async fn get_due_queued_emails(&self, limit: u32) -> Result<Vec<QueuedEmail>, DatabaseError>;
+
+    /// Alters the [`QueuedEmail`] with the given UUID. Returns the updated [`QueuedEmail`] on
+    /// success.
+    async fn update_queued_email(
+        &self,
+        id: &Uuid,
+        update: &QueuedEmailUpdate,
+    ) -> Result<QueuedEmail, DatabaseError>;
+
+    /// Removes the [`QueuedEmail`] with the given UUID from the queue.
+    async fn delete_queued_email_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
+
+    // Email suppression repository
+
+    /// Adds the given address to the email suppression list, replacing any existing entry.
+    async fn create_email_suppression(
+        &self,
+        address: &str,
+        reason: &str,
+    ) -> Result<EmailSuppression, DatabaseError>;
+
+    /// Fetches the [`EmailSuppression`] for the given address. Returns
+    /// [`DatabaseError::NotFound`] if the address is not suppressed.
+    async fn get_email_suppression(&self, address: &str)
+    -> Result<EmailSuppression, DatabaseError>;
+
+    /// Fetches all [`EmailSuppression`]s, newest first.
+    async fn list_email_suppressions(&self) -> Result<Vec<EmailSuppression>, DatabaseError>;
+
+    /// Removes the given address from the email suppression list.
+    async fn delete_email_suppression(&self, address: &str) -> Result<(), DatabaseError>;
+
+    // IP ban repository
+
+    /// Stores the given [`IpBan`]. If the network is already banned, the existing ban is replaced
+    /// but keeps its ID.
+    async fn create_ip_ban(&self, ban: &IpBan) -> Result<IpBan, DatabaseError>;
+
+    /// Fetches all [`IpBan`]s which haven't expired, newest first.
+    async fn list_ip_bans(&self) -> Result<Vec<IpBan>, DatabaseError>;
+
+    /// Deletes the [`IpBan`] with the given UUID. Returns [`DatabaseError::NotFound`] if no such
+    /// ban exists.
+    async fn delete_ip_ban_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
+
+    // Job coordination
+
+    /// Acquires or renews the lease named `name` for `holder`, so that it expires `ttl` from now.
+    /// Returns `false` if the lease is held by another holder and hasn't expired yet.
+    async fn try_acquire_lease(
+        &self,
+        name: &str,
+        holder: &Uuid,
+        ttl: Duration,
+    ) -> Result<bool, DatabaseError>;
+
+    // Announcement repository
+
+    /// Fetches the current [`Announcement`], regardless of whether it is active. Returns
+    /// [`DatabaseError::NotFound`] if there is none.
+    async fn get_announcement(&self) -> Result<Announcement, DatabaseError>;
+
+    /// Stores the given [`Announcement`], replacing the current one if there is one.
+    async fn set_announcement(&self, announcement: &Announcement) -> Result<(), DatabaseError>;
+
+    /// Deletes the current [`Announcement`]. Returns [`DatabaseError::NotFound`] if there is none.
+    async fn delete_announcement(&self) -> Result<(), DatabaseError>;
+
+    // Webhook repository
+
+    /// Stores the given [`Webhook`] and returns it.
+    async fn create_webhook(&self, webhook: &Webhook) -> Result<Webhook, DatabaseError>;
+
+    /// Fetches all [`Webhook`]s, oldest first.
+    async fn list_webhooks(&self) -> Result<Vec<Webhook>, DatabaseError>;
+
+    /// Fetches the [`Webhook`] with the given UUID.
+    async fn get_webhook_by_id(&self, id: &Uuid) -> Result<Webhook, DatabaseError>;
+
+    /// Alters the [`Webhook`] with the given UUID, returning the updated [`Webhook`] on success.
+    async fn update_webhook(
+        &self,
+        id: &Uuid,
+        update: &WebhookUpdate,
+    ) -> Result<Webhook, DatabaseError>;
+
+    /// Deletes the [`Webhook`] with the given UUID. Returns [`DatabaseError::NotFound`] if no such
+    /// webhook exists.
+    async fn delete_webhook_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
+
+    // OAuth repository
+
+    /// Stores the given [`OAuthClient`] and returns it.
+    async fn create_oauth_client(&self, client: &OAuthClient)
+    -> Result<OAuthClient, DatabaseError>;
+
+    /// Fetches all [`OAuthClient`]s, ordered by name.
+    async fn list_oauth_clients(&self) -> Result<Vec<OAuthClient>, DatabaseError>;
+
+    /// Fetches the [`OAuthClient`] with the given UUID.
+    async fn get_oauth_client_by_id(&self, id: &Uuid) -> Result<OAuthClient, DatabaseError>;
+
+    /// Alters the [`OAuthClient`] with the given UUID, returning the updated [`OAuthClient`] on
+    /// success.
+    async fn update_oauth_client(
+        &self,
+        id: &Uuid,
+        update: &OAuthClientUpdate,
+    ) -> Result<OAuthClient, DatabaseError>;
+
+    /// Deletes the [`OAuthClient`] with the given UUID, along with its unused authorization codes.
+    /// Returns [`DatabaseError::NotFound`] if no such client exists.
+    async fn delete_oauth_client_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
+
+    /// Stores the given [`AuthorizationCode`].
+    async fn create_authorization_code(
+        &self,
+        code: &AuthorizationCode,
+    ) -> Result<(), DatabaseError>;
+
+    /// Deletes the unexpired [`AuthorizationCode`] with the given hash and returns it, so that it
+    /// can't be used again. Returns [`DatabaseError::NotFound`] if no such code exists. Expired
+    /// codes are deleted as well.
+    async fn consume_authorization_code(
+        &self,
+        code_hash: &EncodableHash,
+    ) -> Result<AuthorizationCode, DatabaseError>;
+
+    // SAML repository
+
+    /// Stores the given [`SamlServiceProvider`] and returns it. Returns
+    /// [`DatabaseError::UniquenessViolation`] if a service provider with the same entity ID exists.
+    async fn create_saml_service_provider(
         &self,
-        id_hash: &'a EncodableHash,
-        update: &'a SessionUpdate,
-    ) -> Pin<Box<dyn Future<Output = Result<Session, DatabaseError>> + Send + 'a>>;
+        provider: &SamlServiceProvider,
+    ) -> Result<SamlServiceProvider, DatabaseError>;
+
+    /// Fetches all [`SamlServiceProvider`]s, ordered by name.
+    async fn list_saml_service_providers(&self) -> Result<Vec<SamlServiceProvider>, DatabaseError>;
+
+    /// Fetches the [`SamlServiceProvider`] with the given UUID.
+    async fn get_saml_service_provider_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<SamlServiceProvider, DatabaseError>;
+
+    /// Fetches the [`SamlServiceProvider`] with the given entity ID.
+    async fn get_saml_service_provider_by_entity_id(
+        &self,
+        entity_id: &str,
+    ) -> Result<SamlServiceProvider, DatabaseError>;
+
+    /// Alters the [`SamlServiceProvider`] with the given UUID, returning the updated
+    /// [`SamlServiceProvider`] on success.
+    async fn update_saml_service_provider(
+        &self,
+        id: &Uuid,
+        update: &SamlServiceProviderUpdate,
+    ) -> Result<SamlServiceProvider, DatabaseError>;
+
+    /// Deletes the [`SamlServiceProvider`] with the given UUID. Returns
+    /// [`DatabaseError::NotFound`] if no such service provider exists.
+    async fn delete_saml_service_provider_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
+
+    // Federation repository
+
+    /// Stores the given [`FederatedProvider`] and returns it. Returns
+    /// [`DatabaseError::UniquenessViolation`] if a provider with the same slug exists.
+    async fn create_federated_provider(
+        &self,
+        provider: &FederatedProvider,
+    ) -> Result<FederatedProvider, DatabaseError>;
+
+    /// Fetches all [`FederatedProvider`]s, ordered by name.
+    async fn list_federated_providers(&self) -> Result<Vec<FederatedProvider>, DatabaseError>;
+
+    /// Fetches the [`FederatedProvider`] with the given UUID.
+    async fn get_federated_provider_by_id(
+        &self,
+        id: &Uuid,
+    ) -> Result<FederatedProvider, DatabaseError>;
+
+    /// Fetches the [`FederatedProvider`] with the given slug.
+    async fn get_federated_provider_by_slug(
+        &self,
+        slug: &str,
+    ) -> Result<FederatedProvider, DatabaseError>;
+
+    /// Alters the [`FederatedProvider`] with the given UUID, returning the updated
+    /// [`FederatedProvider`] on success.
+    async fn update_federated_provider(
+        &self,
+        id: &Uuid,
+        update: &FederatedProviderUpdate,
+    ) -> Result<FederatedProvider, DatabaseError>;
+
+    /// Deletes the [`FederatedProvider`] with the given UUID, along with the identities linked
+    /// with it. Returns [`DatabaseError::NotFound`] if no such provider exists.
+    async fn delete_federated_provider_by_id(&self, id: &Uuid) -> Result<(), DatabaseError>;
+
+    /// Stores the given [`FederatedLoginState`].
+    async fn create_federated_login_state(
+        &self,
+        state: &FederatedLoginState,
+    ) -> Result<(), DatabaseError>;
+
+    /// Deletes the unexpired [`FederatedLoginState`] with the given hash and returns it, so that it
+    /// can't be used again. Returns [`DatabaseError::NotFound`] if no such state exists. Expired
+    /// states are deleted as well.
+    async fn consume_federated_login_state(
+        &self,
+        state_hash: &EncodableHash,
+    ) -> Result<FederatedLoginState, DatabaseError>;
+
+    /// Fetches the [`FederatedIdentity`] for the given account at the given provider.
+    async fn get_federated_identity(
+        &self,
+        provider_id: &Uuid,
+        subject: &str,
+    ) -> Result<FederatedIdentity, DatabaseError>;
+
+    /// Stores the given [`FederatedIdentity`]. Returns [`DatabaseError::UniquenessViolation`] if
+    /// the account is already linked to a user.
+    async fn create_federated_identity(
+        &self,
+        identity: &FederatedIdentity,
+    ) -> Result<(), DatabaseError>;
+
+    /// Fetches the [`FederatedIdentity`]s linked to the given user.
+    async fn list_federated_identities_by_user_id(
+        &self,
+        user_id: &Uuid,
+    ) -> Result<Vec<FederatedIdentity>, DatabaseError>;
+
+    /// Unlinks the given user's identities at the given provider. Returns
+    /// [`DatabaseError::NotFound`] if the user has none.
+    async fn delete_federated_identities(
+        &self,
+        user_id: &Uuid,
+        provider_id: &Uuid,
+    ) -> Result<(), DatabaseError>;
+
+    // Signing key repository
+
+    /// Stores the given [`StoredSigningKey`].
+    async fn create_signing_key(&self, key: &StoredSigningKey) -> Result<(), DatabaseError>;
+
+    /// Fetches all [`StoredSigningKey`]s, newest first.
+    async fn list_signing_keys(&self) -> Result<Vec<StoredSigningKey>, DatabaseError>;
+
+    /// Retires the unretired [`StoredSigningKey`]s, other than the key with the given ID, which
+    /// were created no later than that key. Returns the number of keys retired.
+    async fn retire_signing_keys_older_than(&self, kid: &str) -> Result<u64, DatabaseError>;
+
+    /// Deletes the [`StoredSigningKey`]s which were retired more than `age` ago. Returns the
+    /// number of keys deleted.
+    async fn delete_retired_signing_keys(&self, age: Duration) -> Result<u64, DatabaseError>;
+
+    // Session cookie key repository
+
+    /// Fetches the [`StoredSessionCookieKey`], storing the given key first if there is none, so
+    /// that instances which start at the same time agree on the key.
+    async fn get_or_create_session_cookie_key(
+        &self,
+        key: &StoredSessionCookieKey,
+    ) -> Result<StoredSessionCookieKey, DatabaseError>;
+
+    // Data retention
+
+    /// Deletes all data which is older than allowed by the given [`RetentionPolicy`], and returns
+    /// the number of records deleted from each data class.
+    async fn purge_retained_data(
+        &self,
+        policy: &RetentionPolicy,
+    ) -> Result<RetentionReport, DatabaseError>;
+
+    // Maintenance
+
+    /// Runs the given [`MaintenanceTask`], or the backend's closest equivalent. Backends for which
+    /// a task doesn't apply should do nothing.
+    async fn run_maintenance(&self, task: MaintenanceTask) -> Result<(), DatabaseError>;
+
+    // Backups
+
+    /// Exports every stored entity, except short-lived state, as a [`Backup`].
+    async fn export_backup(&self) -> Result<Backup, DatabaseError>;
+
+    /// Restores a [`Backup`] into the database, which must not hold any of the entities which
+    /// are backed up. Either the whole backup is restored or, on error, nothing is.
+    async fn import_backup(&self, backup: &Backup) -> Result<(), DatabaseError>;
+
+    // Transactions
+
+    /// Begins a [`DatabaseTransaction`], through which several operations can be applied
+    /// atomically.
+    async fn begin(&self) -> Result<BoxedTransaction, DatabaseError>;
+}
+
+/// A [`DatabaseTransaction`] of any backend, as returned by [`DatabaseClient::begin()`]
+pub type BoxedTransaction = Box<dyn DatabaseTransaction>;
+
+/// # Database transaction
+///
+/// Operations performed through a transaction take effect together when it's
+/// [committed][Self::commit], or not at all. Dropping a transaction without committing it rolls it
+/// back.
+///
+/// Only operations which need to be combined with others are available here; the methods behave
+/// like the [`DatabaseClient`] methods of the same names.
+#[async_trait]
+pub trait DatabaseTransaction: Send {
+    /// See [`DatabaseClient::create_user()`].
+    async fn create_user(&mut self, id: &Uuid, user: &UserCreate) -> Result<User, DatabaseError>;
+
+    /// See [`DatabaseClient::add_tag_to_user()`].
+    async fn add_tag_to_user(&mut self, user_id: &Uuid, tag: &Tag) -> Result<(), DatabaseError>;
+
+    /// See [`DatabaseClient::create_passkey()`].
+    async fn create_passkey(
+        &mut self,
+        id: &Uuid,
+        user_id: &Uuid,
+        passkey: &NewPasskeyCredential,
+    ) -> Result<PasskeyCredential, DatabaseError>;
+
+    /// See [`DatabaseClient::create_session()`].
+    async fn create_session(&mut self, session: &Session) -> Result<(), DatabaseError>;
+
+    /// See [`DatabaseClient::update_session()`].
+    async fn update_session(
+        &mut self,
+        id_hash: &EncodableHash,
+        update: &SessionUpdate,
+    ) -> Result<Session, DatabaseError>;
+
+    /// Applies the operations performed through the transaction.
+    async fn commit(self: Box<Self>) -> Result<(), DatabaseError>;
 }
 
 /// Error type for database operations
@@ -266,3 +1004,11 @@ impl From<sqlx::Error> for DatabaseError {
         }
     }
 }
+
+#[cfg(feature = "redis")]
+impl From<redis::RedisError> for DatabaseError {
+    /// Converts a [`redis::RedisError`] into a [`DatabaseError::Other`].
+    fn from(error: redis::RedisError) -> Self {
+        Self::Other(Box::new(error))
+    }
+}
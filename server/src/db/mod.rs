@@ -1,4 +1,5 @@
 //! Database utilities
 
 pub mod clients;
+pub mod connect;
 pub mod interface;
@@ -0,0 +1,136 @@
+//! # Email subsystem
+//!
+//! The [`Mailer`] renders [email templates][templates] and delivers the results using a
+//! [`Transport`], optionally through a persistent [queue][queue]. Besides the [`LogTransport`],
+//! which is useful during development, messages can be delivered through an [SMTP relay][smtp] or
+//! the APIs of several [HTTP email providers][providers].
+
+use std::sync::Arc;
+
+use serde::Serialize;
+use tracing::error;
+
+use crate::db::interface::DatabaseError;
+
+pub mod providers;
+pub mod queue;
+pub mod smtp;
+pub mod templates;
+pub mod transport;
+
+pub use providers::{AwsCredentials, MailgunTransport, SendGridTransport, SesTransport};
+pub use queue::{EmailQueue, QueueOptions};
+pub use smtp::{SmtpCredentials, SmtpOptions, SmtpSecurity, SmtpTransport};
+pub use templates::{EmailTemplates, RenderedEmail, TemplateError};
+pub use transport::{LogTransport, Message, Transport, TransportError};
+
+/// Error type for [`Mailer::send()`]
+#[derive(Debug, thiserror::Error)]
+pub enum MailError {
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+
+    #[error(transparent)]
+    Transport(#[from] TransportError),
+
+    /// Adding the email to the [queue][EmailQueue] failed.
+    #[error("failed to queue email: {0}")]
+    Queue(#[from] DatabaseError),
+}
+
+/// # Email sender
+///
+/// Renders emails from templates and delivers them using a [`Transport`], or adds them to an
+/// [`EmailQueue`] if one is set with [`Mailer::with_queue()`].
+pub struct Mailer {
+    transport: Arc<dyn Transport>,
+    queue: Option<Arc<EmailQueue>>,
+    templates: EmailTemplates,
+    from: String,
+}
+
+impl Mailer {
+    /// Creates a mailer which renders emails using `templates` and delivers them using `transport`
+    /// with the sender address `from`.
+    #[must_use]
+    pub fn new(transport: Arc<dyn Transport>, templates: EmailTemplates, from: String) -> Self {
+        Self {
+            transport,
+            queue: None,
+            templates,
+            from,
+        }
+    }
+
+    /// Makes the mailer add emails to the given queue instead of sending them immediately.
+    #[must_use]
+    pub fn with_queue(mut self, queue: Arc<EmailQueue>) -> Self {
+        self.queue = Some(queue);
+        self
+    }
+
+    /// Renders the email template with the given name and context (see
+    /// [`EmailTemplates::render()`]) and sends or queues it for the given address.
+    pub async fn send<C>(&self, to: &str, template: &str, context: &C) -> Result<(), MailError>
+    where
+        C: Serialize + ?Sized,
+    {
+        let message = self.render(to, template, context)?;
+        match &self.queue {
+            Some(queue) => queue.enqueue(message).await?,
+            None => self.transport.send(&message).await?,
+        }
+        Ok(())
+    }
+
+    /// Like [`Mailer::send()`], but always sends the email immediately using the transport, even
+    /// if the mailer has a queue. Useful to check whether delivery works, e.g. for test emails.
+    pub async fn send_now<C>(&self, to: &str, template: &str, context: &C) -> Result<(), MailError>
+    where
+        C: Serialize + ?Sized,
+    {
+        let message = self.render(to, template, context)?;
+        self.transport.send(&message).await?;
+        Ok(())
+    }
+
+    fn render<C>(&self, to: &str, template: &str, context: &C) -> Result<Message, TemplateError>
+    where
+        C: Serialize + ?Sized,
+    {
+        let RenderedEmail {
+            subject,
+            html,
+            text,
+        } = self.templates.render(template, context)?;
+        Ok(Message {
+            from: self.from.clone(),
+            to: to.to_string(),
+            subject,
+            html,
+            text,
+        })
+    }
+
+    /// Like [`Mailer::send()`], but sends the email from a background task so the caller doesn't
+    /// have to wait for delivery. Errors are logged.
+    pub fn send_in_background<C>(self: &Arc<Self>, to: String, template: &'static str, context: &C)
+    where
+        C: Serialize + ?Sized,
+    {
+        // Serialize the context up front so the task doesn't borrow from the caller
+        let context = match serde_json::to_value(context) {
+            Ok(context) => context,
+            Err(err) => {
+                error!(%err, template, "failed to serialize email context");
+                return;
+            }
+        };
+        let mailer = Arc::clone(self);
+        tokio::spawn(async move {
+            if let Err(err) = mailer.send(&to, template, &context).await {
+                error!(%err, %to, template, "failed to send email");
+            }
+        });
+    }
+}
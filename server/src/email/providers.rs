@@ -0,0 +1,379 @@
+//! # HTTP email provider transports
+//!
+//! [`Transport`]s which deliver messages using the HTTP APIs of hosted email providers, for
+//! environments where outbound SMTP is blocked.
+//!
+//! Provider responses are mapped to [`TransportError`]s as follows: network errors, server errors,
+//! timeouts, rate limiting, and authentication errors (which are usually configuration mistakes
+//! that an operator can fix) are [temporary][TransportError::Temporary]. Other client errors mean
+//! the provider rejected the message and are [permanent][TransportError::Permanent].
+
+use std::{future::Future, pin::Pin, time::Duration};
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Response, StatusCode};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::email::{Message, Transport, TransportError};
+
+/// Timeout for requests to email providers
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Creates the HTTP client used by provider transports.
+///
+/// # Panics
+///
+/// Panics if the client can't be created, e.g. because the TLS backend fails to initialize.
+fn http_client() -> Client {
+    Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .expect("failed to create HTTP client")
+}
+
+/// Converts the result of a request to a provider's API into the result of a delivery attempt.
+async fn check_response(result: Result<Response, reqwest::Error>) -> Result<(), TransportError> {
+    let response = result.map_err(|err| TransportError::Temporary(err.into()))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = response.text().await.unwrap_or_default();
+    let err = format!("HTTP {status}: {}", body.trim()).into();
+    if status.is_client_error()
+        && !matches!(
+            status,
+            StatusCode::UNAUTHORIZED
+                | StatusCode::FORBIDDEN
+                | StatusCode::REQUEST_TIMEOUT
+                | StatusCode::TOO_MANY_REQUESTS
+        )
+    {
+        Err(TransportError::Permanent(err))
+    } else {
+        Err(TransportError::Temporary(err))
+    }
+}
+
+/// Splits an address of the form `Name <user@example.com>` into its display name and address.
+/// Returns no name if the address is a bare address.
+pub(super) fn split_address(address: &str) -> (Option<&str>, &str) {
+    match address
+        .trim()
+        .strip_suffix('>')
+        .and_then(|s| s.rsplit_once('<'))
+    {
+        Some((name, email)) => {
+            let name = name.trim().trim_matches('"');
+            ((!name.is_empty()).then_some(name), email.trim())
+        }
+        None => (None, address.trim()),
+    }
+}
+
+/// # `SendGrid` transport
+///
+/// Delivers messages using the [`SendGrid` v3 mail send API][1].
+///
+/// [1]: https://www.twilio.com/docs/sendgrid/api-reference/mail-send/mail-send
+pub struct SendGridTransport {
+    http: Client,
+    api_key: String,
+}
+
+impl SendGridTransport {
+    /// Creates a transport which authenticates with the given API key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the HTTP client can't be created.
+    #[must_use]
+    pub fn new(api_key: String) -> Self {
+        Self {
+            http: http_client(),
+            api_key,
+        }
+    }
+}
+
+impl Transport for SendGridTransport {
+    fn send<'a>(
+        &'a self,
+        message: &'a Message,
+    ) -> Pin<Box<dyn Future<Output = Result<(), TransportError>> + Send + 'a>> {
+        Box::pin(async move {
+            let (from_name, from_email) = split_address(&message.from);
+            let body = json!({
+                "personalizations": [{ "to": [{ "email": message.to }] }],
+                "from": { "email": from_email, "name": from_name },
+                "subject": message.subject,
+                "content": [
+                    { "type": "text/plain", "value": message.text },
+                    { "type": "text/html", "value": message.html },
+                ],
+            });
+            let result = self
+                .http
+                .post("https://api.sendgrid.com/v3/mail/send")
+                .bearer_auth(&self.api_key)
+                .json(&body)
+                .send()
+                .await;
+            check_response(result).await
+        })
+    }
+}
+
+/// # Mailgun transport
+///
+/// Delivers messages using the [Mailgun messages API][1].
+///
+/// [1]: https://documentation.mailgun.com/docs/mailgun/api-reference/send/mailgun/messages
+pub struct MailgunTransport {
+    http: Client,
+    api_key: String,
+    endpoint: String,
+}
+
+impl MailgunTransport {
+    /// Default base URL of the Mailgun API (US region)
+    pub const DEFAULT_API_BASE: &str = "https://api.mailgun.net";
+
+    /// Creates a transport which sends from the given domain using the API at `api_base` (e.g.
+    /// [`MailgunTransport::DEFAULT_API_BASE`] or `https://api.eu.mailgun.net`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the HTTP client can't be created.
+    #[must_use]
+    pub fn new(api_key: String, domain: &str, api_base: &str) -> Self {
+        Self {
+            http: http_client(),
+            api_key,
+            endpoint: format!("{}/v3/{domain}/messages", api_base.trim_end_matches('/')),
+        }
+    }
+}
+
+impl Transport for MailgunTransport {
+    fn send<'a>(
+        &'a self,
+        message: &'a Message,
+    ) -> Pin<Box<dyn Future<Output = Result<(), TransportError>> + Send + 'a>> {
+        Box::pin(async move {
+            let form = [
+                ("from", &message.from),
+                ("to", &message.to),
+                ("subject", &message.subject),
+                ("text", &message.text),
+                ("html", &message.html),
+            ];
+            let result = self
+                .http
+                .post(&self.endpoint)
+                .basic_auth("api", Some(&self.api_key))
+                .form(&form)
+                .send()
+                .await;
+            check_response(result).await
+        })
+    }
+}
+
+/// AWS credentials used to sign requests to SES
+#[derive(Clone)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Session token for temporary credentials
+    pub session_token: Option<String>,
+}
+
+/// # Amazon SES transport
+///
+/// Delivers messages using the [SES v2 `SendEmail` API][1]. Requests are signed with
+/// [AWS Signature Version 4][2].
+///
+/// [1]: https://docs.aws.amazon.com/ses/latest/APIReference-V2/API_SendEmail.html
+/// [2]: https://docs.aws.amazon.com/IAM/latest/UserGuide/reference_sigv.html
+pub struct SesTransport {
+    http: Client,
+    region: String,
+    credentials: AwsCredentials,
+}
+
+impl SesTransport {
+    const SERVICE: &str = "ses";
+    const PATH: &str = "/v2/email/outbound-emails";
+
+    /// Creates a transport which uses SES in the given region (e.g. `us-east-1`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the HTTP client can't be created.
+    #[must_use]
+    pub fn new(region: String, credentials: AwsCredentials) -> Self {
+        Self {
+            http: http_client(),
+            region,
+            credentials,
+        }
+    }
+
+    fn host(&self) -> String {
+        format!("email.{}.amazonaws.com", self.region)
+    }
+}
+
+impl Transport for SesTransport {
+    fn send<'a>(
+        &'a self,
+        message: &'a Message,
+    ) -> Pin<Box<dyn Future<Output = Result<(), TransportError>> + Send + 'a>> {
+        Box::pin(async move {
+            let body = json!({
+                "FromEmailAddress": message.from,
+                "Destination": { "ToAddresses": [message.to] },
+                "Content": {
+                    "Simple": {
+                        "Subject": { "Data": message.subject, "Charset": "UTF-8" },
+                        "Body": {
+                            "Text": { "Data": message.text, "Charset": "UTF-8" },
+                            "Html": { "Data": message.html, "Charset": "UTF-8" },
+                        },
+                    },
+                },
+            })
+            .to_string();
+
+            let host = self.host();
+            let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+            let mut headers = vec![
+                ("content-type", "application/json"),
+                ("host", host.as_str()),
+                ("x-amz-date", amz_date.as_str()),
+            ];
+            if let Some(token) = &self.credentials.session_token {
+                headers.push(("x-amz-security-token", token));
+            }
+            let authorization = sigv4_authorization(
+                &self.credentials,
+                &self.region,
+                Self::SERVICE,
+                "POST",
+                Self::PATH,
+                &headers,
+                body.as_bytes(),
+                &amz_date,
+            );
+
+            let mut request = self
+                .http
+                .post(format!("https://{host}{}", Self::PATH))
+                .header("authorization", authorization)
+                .body(body);
+            for (name, value) in headers {
+                if name != "host" {
+                    request = request.header(name, value);
+                }
+            }
+            check_response(request.send().await).await
+        })
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Derives the AWS Signature Version 4 signing key for the given date (`YYYYMMDD`), region, and
+/// service.
+fn sigv4_signing_key(secret: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac_sha256(format!("AWS4{secret}").as_bytes(), date.as_bytes());
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, service.as_bytes());
+    hmac_sha256(&key, b"aws4_request")
+}
+
+/// Returns the `Authorization` header value for a request without a query string, signed with AWS
+/// Signature Version 4. `headers` must contain every header to sign, with lowercase names and
+/// sorted by name, and include `host` and `x-amz-date` (`amz_date`).
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sigv4_authorization(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    amz_date: &str,
+) -> String {
+    let date = &amz_date[..8];
+    let canonical_headers = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+        .collect::<Vec<_>>()
+        .concat();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{method}\n{path}\n\n{canonical_headers}\n{signed_headers}\n{}",
+        hex::encode(Sha256::digest(body)),
+    );
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical_request.as_bytes())),
+    );
+    let key = sigv4_signing_key(&credentials.secret_access_key, date, region, service);
+    let signature = hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()));
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+        credentials.access_key_id,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_address() {
+        assert_eq!(
+            split_address("IAM <iam@example.com>"),
+            (Some("IAM"), "iam@example.com")
+        );
+        assert_eq!(
+            split_address("\"Example IAM\" <iam@example.com>"),
+            (Some("Example IAM"), "iam@example.com")
+        );
+        assert_eq!(
+            split_address("<iam@example.com>"),
+            (None, "iam@example.com")
+        );
+        assert_eq!(split_address("iam@example.com"), (None, "iam@example.com"));
+    }
+
+    /// Example from the AWS Signature Version 4 documentation
+    #[test]
+    fn test_sigv4_signing_key() {
+        let key = sigv4_signing_key(
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "20150830",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "c4afb1cc5771d871763a393e44b703571b55cc28424d1a5e86da6ed3c154a4b9"
+        );
+    }
+}
@@ -0,0 +1,322 @@
+//! # Persistent outgoing email queue
+//!
+//! When a [`Mailer`][super::Mailer] has an [`EmailQueue`], rendered emails are stored in the
+//! database instead of being sent immediately. A background worker delivers due emails using the
+//! queue's [`Transport`]. Emails which fail to send are retried with exponential backoff, and
+//! are dead-lettered (kept in the queue as [`QueuedEmailState::Dead`] but no longer retried)
+//! after a permanent failure or too many attempts.
+//!
+//! Recipients whose emails fail permanently (e.g. because the address doesn't exist) are added to
+//! the [suppression list][crate::models::EmailSuppression], and emails to suppressed addresses are
+//! dead-lettered without being sent.
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use tracing::{debug, error, warn};
+
+use crate::{
+    coordination::Coordinator,
+    db::interface::{DatabaseClient, DatabaseError},
+    email::{Message, Transport, TransportError},
+    models::{QueuedEmail, QueuedEmailState, QueuedEmailUpdate, new_uuid},
+};
+
+/// Name of the worker's [coordinated][Coordinator] job
+const JOB_NAME: &str = "email-queue";
+
+/// Maximum number of emails delivered per pass of the worker
+const BATCH_SIZE: u32 = 50;
+
+/// Options for the [`EmailQueue`]
+#[derive(Debug, Clone)]
+pub struct QueueOptions {
+    /// Time between checks for due emails
+    pub poll_interval: Duration,
+    /// Number of delivery attempts after which an email is dead-lettered
+    pub max_attempts: u32,
+    /// Delay before the first retry. Each following retry doubles the delay.
+    pub base_delay: Duration,
+    /// Maximum delay between retries
+    pub max_delay: Duration,
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        Self {
+            poll_interval: Duration::from_secs(5),
+            max_attempts: 8,
+            base_delay: Duration::from_secs(30),
+            max_delay: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl QueueOptions {
+    /// Returns the delay before the next attempt after the given number of failed attempts.
+    fn retry_delay(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.base_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+/// # Outgoing email queue
+///
+/// See the [module-level documentation][self] for details.
+pub struct EmailQueue {
+    db: Arc<dyn DatabaseClient>,
+    transport: Arc<dyn Transport>,
+    options: QueueOptions,
+}
+
+impl EmailQueue {
+    /// Creates a queue which stores emails using `db` and delivers them using `transport`.
+    #[must_use]
+    pub fn new(
+        db: Arc<dyn DatabaseClient>,
+        transport: Arc<dyn Transport>,
+        options: QueueOptions,
+    ) -> Self {
+        Self {
+            db,
+            transport,
+            options,
+        }
+    }
+
+    /// Adds a message to the queue. It will be sent by the worker on its next pass.
+    pub async fn enqueue(&self, message: Message) -> Result<(), DatabaseError> {
+        let now = Utc::now();
+        let email = QueuedEmail {
+            id: new_uuid(),
+            sender: message.from,
+            recipient: message.to,
+            subject: message.subject,
+            html: message.html,
+            text: message.text,
+            state: QueuedEmailState::Pending,
+            attempts: 0,
+            last_error: None,
+            created_at: now,
+            next_attempt_at: now,
+        };
+        self.db.create_queued_email(&email).await?;
+        debug!(id = %email.id, to = %email.recipient, "email queued");
+        Ok(())
+    }
+
+    /// Spawns a task which delivers due emails every [poll interval][QueueOptions::poll_interval].
+    /// When several instances share the database, only the one elected by the `coordinator`
+    /// delivers emails, so that none are sent twice.
+    pub fn spawn_worker(self: &Arc<Self>, coordinator: Arc<Coordinator>) {
+        let queue = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(queue.options.poll_interval);
+            loop {
+                interval.tick().await;
+                if !coordinator
+                    .should_run(JOB_NAME, queue.options.poll_interval)
+                    .await
+                {
+                    continue;
+                }
+                if let Err(err) = queue.process_due().await {
+                    error!(%err, "failed to process email queue");
+                }
+            }
+        });
+    }
+
+    /// Attempts to deliver all emails which are currently due.
+    pub async fn process_due(&self) -> Result<(), DatabaseError> {
+        loop {
+            let emails = self.db.get_due_queued_emails(BATCH_SIZE).await?;
+            let is_last_batch = emails.len() < BATCH_SIZE as usize;
+            for email in emails {
+                self.deliver(email).await?;
+            }
+            if is_last_batch {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Attempts to deliver a single email, then removes it from the queue, reschedules it, or
+    /// dead-letters it depending on the outcome.
+    async fn deliver(&self, email: QueuedEmail) -> Result<(), DatabaseError> {
+        match self.db.get_email_suppression(&email.recipient).await {
+            Ok(suppression) => {
+                warn!(id = %email.id, to = %email.recipient, reason = %suppression.reason, "not sending email to suppressed address");
+                let update = QueuedEmailUpdate::new()
+                    .with_state(QueuedEmailState::Dead)
+                    .with_last_error(format!("address suppressed: {}", suppression.reason));
+                self.db.update_queued_email(&email.id, &update).await?;
+                return Ok(());
+            }
+            Err(DatabaseError::NotFound) => (),
+            Err(err) => return Err(err),
+        }
+
+        let message = Message {
+            from: email.sender,
+            to: email.recipient,
+            subject: email.subject,
+            html: email.html,
+            text: email.text,
+        };
+        let err = match self.transport.send(&message).await {
+            Ok(()) => {
+                debug!(id = %email.id, to = %message.to, "queued email sent");
+                return self.db.delete_queued_email_by_id(&email.id).await;
+            }
+            Err(err) => err,
+        };
+
+        let attempts = email.attempts + 1;
+        let mut update = QueuedEmailUpdate::new()
+            .with_attempts(attempts)
+            .with_last_error(err.to_string());
+        if let TransportError::Permanent(reason) = &err {
+            error!(%err, id = %email.id, to = %message.to, attempts, "giving up on email; suppressing address");
+            update = update.with_state(QueuedEmailState::Dead);
+            self.db
+                .create_email_suppression(&message.to, &reason.to_string())
+                .await?;
+        } else if attempts >= self.options.max_attempts {
+            error!(%err, id = %email.id, to = %message.to, attempts, "giving up on email");
+            update = update.with_state(QueuedEmailState::Dead);
+        } else {
+            let delay = self.options.retry_delay(attempts);
+            warn!(%err, id = %email.id, to = %message.to, attempts, ?delay, "email delivery failed; will retry");
+            update = update.with_next_attempt_at(
+                Utc::now() + chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::MAX),
+            );
+        }
+        self.db.update_queued_email(&email.id, &update).await?;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "sqlite3"))]
+mod tests {
+    use std::{future::Future, pin::Pin};
+
+    use super::*;
+    use crate::db::clients::sqlite::SqliteClient;
+
+    /// Transport which fails every delivery with the given kind of error
+    struct FailingTransport {
+        permanent: bool,
+    }
+
+    impl Transport for FailingTransport {
+        fn send<'a>(
+            &'a self,
+            _message: &'a Message,
+        ) -> Pin<Box<dyn Future<Output = Result<(), TransportError>> + Send + 'a>> {
+            Box::pin(async move {
+                if self.permanent {
+                    Err(TransportError::Permanent("rejected".into()))
+                } else {
+                    Err(TransportError::Temporary("unreachable".into()))
+                }
+            })
+        }
+    }
+
+    fn message() -> Message {
+        Message {
+            from: "iam@example.com".to_string(),
+            to: "user@example.com".to_string(),
+            subject: "Subject".to_string(),
+            html: "<p>Body</p>".to_string(),
+            text: "Body".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let options = QueueOptions::default();
+        assert_eq!(options.retry_delay(1), Duration::from_secs(30));
+        assert_eq!(options.retry_delay(2), Duration::from_secs(60));
+        assert_eq!(options.retry_delay(3), Duration::from_secs(120));
+        assert_eq!(options.retry_delay(100), options.max_delay);
+    }
+
+    #[tokio::test]
+    async fn test_delivered_email_is_removed() {
+        let db = Arc::new(SqliteClient::new_memory().await.unwrap());
+        let queue = EmailQueue::new(
+            db.clone(),
+            Arc::new(crate::email::LogTransport),
+            QueueOptions::default(),
+        );
+        queue.enqueue(message()).await.unwrap();
+        queue.process_due().await.unwrap();
+        assert!(db.get_due_queued_emails(10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_email_is_retried_then_dead_lettered() {
+        let db = Arc::new(SqliteClient::new_memory().await.unwrap());
+        let options = QueueOptions {
+            max_attempts: 2,
+            base_delay: Duration::ZERO,
+            ..Default::default()
+        };
+        let queue = EmailQueue::new(
+            db.clone(),
+            Arc::new(FailingTransport { permanent: false }),
+            options,
+        );
+        queue.enqueue(message()).await.unwrap();
+
+        // First failure is rescheduled
+        queue.process_due().await.unwrap();
+        let email = db.get_due_queued_emails(10).await.unwrap().remove(0);
+        assert_eq!(email.attempts, 1);
+        assert_eq!(
+            email.last_error.as_deref(),
+            Some("temporary delivery failure: unreachable")
+        );
+        assert_eq!(email.state, QueuedEmailState::Pending);
+
+        // Second failure reaches the attempt limit
+        queue.deliver(email).await.unwrap();
+        assert!(db.get_due_queued_emails(10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_permanent_failure_is_dead_lettered() {
+        let db = Arc::new(SqliteClient::new_memory().await.unwrap());
+        let queue = EmailQueue::new(
+            db.clone(),
+            Arc::new(FailingTransport { permanent: true }),
+            QueueOptions::default(),
+        );
+        queue.enqueue(message()).await.unwrap();
+        queue.process_due().await.unwrap();
+        assert!(db.get_due_queued_emails(10).await.unwrap().is_empty());
+        let suppression = db.get_email_suppression("user@example.com").await.unwrap();
+        assert_eq!(suppression.reason, "rejected");
+    }
+
+    #[tokio::test]
+    async fn test_suppressed_address_is_not_sent() {
+        let db = Arc::new(SqliteClient::new_memory().await.unwrap());
+        db.create_email_suppression("user@example.com", "bounced")
+            .await
+            .unwrap();
+        // Sending would fail temporarily and be retried if attempted
+        let queue = EmailQueue::new(
+            db.clone(),
+            Arc::new(FailingTransport { permanent: false }),
+            QueueOptions::default(),
+        );
+        queue.enqueue(message()).await.unwrap();
+        queue.process_due().await.unwrap();
+        assert!(db.get_due_queued_emails(10).await.unwrap().is_empty());
+    }
+}
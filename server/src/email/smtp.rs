@@ -0,0 +1,352 @@
+//! # SMTP transport
+//!
+//! [`SmtpTransport`] delivers messages through an SMTP relay, such as an organization's mail
+//! server or the SMTP endpoint of an email provider, using [`lettre`]. Each message is sent in its
+//! own session, as a `multipart/alternative` MIME message containing both the text and the HTML
+//! body.
+//!
+//! Credentials are only ever sent over encrypted connections: with [`SmtpSecurity::StartTls`],
+//! the session fails before authenticating if the relay doesn't support `STARTTLS`, and
+//! [`SmtpSecurity::None`] can't be combined with credentials.
+//!
+//! Replies are mapped to [`TransportError`]s as follows: connection and TLS errors, timeouts,
+//! `4xx` replies, and authentication failures (which are usually configuration mistakes that an
+//! operator can fix) are [temporary][TransportError::Temporary]. Other `5xx` replies mean the
+//! relay rejected the message and are [permanent][TransportError::Permanent].
+
+use std::{future::Future, pin::Pin, str::FromStr, time::Duration};
+
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+    message::{Mailbox, MultiPart},
+    transport::smtp::{
+        AsyncSmtpTransportBuilder,
+        authentication::Credentials,
+        client::{Tls, TlsParameters},
+        extension::ClientId,
+    },
+};
+use uuid::Uuid;
+
+use crate::email::{Message, Transport, TransportError};
+
+/// Timeout for a whole SMTP session
+const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How the connection to the relay is secured
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    /// Connect in plain text and upgrade the connection with `STARTTLS`, failing if the relay
+    /// doesn't support it
+    #[default]
+    StartTls,
+    /// Connect using TLS from the start ("implicit TLS")
+    Tls,
+    /// Don't encrypt the connection. Only suitable for relays on the same host or network which
+    /// don't require authentication.
+    None,
+}
+
+impl SmtpSecurity {
+    /// Returns the port usually used with this kind of security.
+    #[must_use]
+    pub fn default_port(self) -> u16 {
+        match self {
+            Self::StartTls => 587,
+            Self::Tls => 465,
+            Self::None => 25,
+        }
+    }
+}
+
+impl FromStr for SmtpSecurity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "starttls" => Ok(Self::StartTls),
+            "tls" => Ok(Self::Tls),
+            "none" => Ok(Self::None),
+            _ => Err(format!(
+                "invalid SMTP security `{s}`; expected `starttls`, `tls`, or `none`"
+            )),
+        }
+    }
+}
+
+/// Credentials with which to authenticate to the relay
+#[derive(Clone)]
+pub struct SmtpCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// Relay to which an [`SmtpTransport`] delivers messages
+#[derive(Clone)]
+pub struct SmtpOptions {
+    pub host: String,
+    pub port: u16,
+    pub security: SmtpSecurity,
+    /// Credentials used to log in with `AUTH PLAIN` or `AUTH LOGIN`, or [`None`] if the relay
+    /// accepts messages without authentication. Requires an encrypted connection.
+    pub credentials: Option<SmtpCredentials>,
+}
+
+/// # SMTP transport
+///
+/// See the [module-level documentation][self] for details.
+pub struct SmtpTransport {
+    relay: AsyncSmtpTransportBuilder,
+    /// Whether credentials were configured for an unencrypted connection, in which case no
+    /// messages are sent
+    insecure_credentials: bool,
+}
+
+impl SmtpTransport {
+    /// Creates a transport which delivers messages through the given relay.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the TLS backend fails to initialize.
+    #[must_use]
+    pub fn new(options: SmtpOptions) -> Self {
+        let tls = || TlsParameters::new(options.host.clone()).expect("failed to configure TLS");
+        let tls = match options.security {
+            SmtpSecurity::StartTls => Tls::Required(tls()),
+            SmtpSecurity::Tls => Tls::Wrapper(tls()),
+            SmtpSecurity::None => Tls::None,
+        };
+        let mut relay = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&options.host)
+            .port(options.port)
+            .tls(tls)
+            .timeout(Some(SESSION_TIMEOUT));
+        let insecure_credentials =
+            options.security == SmtpSecurity::None && options.credentials.is_some();
+        if let Some(credentials) = options.credentials
+            && !insecure_credentials
+        {
+            relay = relay.credentials(Credentials::new(credentials.username, credentials.password));
+        }
+        Self {
+            relay,
+            insecure_credentials,
+        }
+    }
+
+    /// Runs an SMTP session which delivers the message.
+    async fn deliver(&self, message: &Message) -> Result<(), TransportError> {
+        if self.insecure_credentials {
+            return Err(TransportError::Temporary(
+                "refusing to send SMTP credentials over an unencrypted connection".into(),
+            ));
+        }
+        let mailbox = |address: &str| {
+            address.parse::<Mailbox>().map_err(|err| {
+                TransportError::Permanent(format!("invalid address `{address}`: {err}").into())
+            })
+        };
+        let from = mailbox(&message.from)?;
+        // Identify ourselves by the sender's domain, since the server's host name isn't known
+        let domain = from.email.domain().to_string();
+        let email = lettre::Message::builder()
+            .from(from)
+            .to(mailbox(&message.to)?)
+            .subject(&message.subject)
+            .message_id(Some(format!("<{}@{domain}>", Uuid::new_v4().simple())))
+            .multipart(MultiPart::alternative_plain_html(
+                message.text.clone(),
+                message.html.clone(),
+            ))
+            .map_err(|err| TransportError::Permanent(err.into()))?;
+        self.relay
+            .clone()
+            .hello_name(ClientId::Domain(domain))
+            .build::<Tokio1Executor>()
+            .send(email)
+            .await
+            .map_err(transport_error)?;
+        Ok(())
+    }
+}
+
+impl Transport for SmtpTransport {
+    fn send<'a>(
+        &'a self,
+        message: &'a Message,
+    ) -> Pin<Box<dyn Future<Output = Result<(), TransportError>> + Send + 'a>> {
+        Box::pin(async move {
+            tokio::time::timeout(SESSION_TIMEOUT, self.deliver(message))
+                .await
+                .unwrap_or_else(|_| Err(TransportError::Temporary("SMTP session timed out".into())))
+        })
+    }
+}
+
+/// Maps an SMTP error to a [`TransportError`] as described in the
+/// [module-level documentation][self].
+fn transport_error(err: lettre::transport::smtp::Error) -> TransportError {
+    match err.status() {
+        Some(code) if is_permanent_reply(code.into()) => TransportError::Permanent(err.into()),
+        _ => TransportError::Temporary(err.into()),
+    }
+}
+
+/// Returns whether a reply with the given code means the message will never be accepted.
+/// `53x` replies are about authentication.
+fn is_permanent_reply(code: u16) -> bool {
+    (500..600).contains(&code) && code / 10 != 53
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    use super::*;
+
+    /// Starts an SMTP server which accepts one session, replying to `RCPT` and `AUTH` commands
+    /// with the given replies, and returns its port and a handle resolving to the commands it
+    /// received. It doesn't support `STARTTLS`.
+    async fn fake_relay(
+        rcpt_reply: &'static str,
+        auth_reply: &'static str,
+    ) -> (u16, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut received = Vec::new();
+            stream.write_all(b"220 relay ready\r\n").await.unwrap();
+            loop {
+                let mut line = String::new();
+                if stream.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                let reply = match line.split_whitespace().next().unwrap_or_default() {
+                    "EHLO" => "250-relay\r\n250 AUTH PLAIN\r\n",
+                    "AUTH" => auth_reply,
+                    "MAIL" => "250 ok\r\n",
+                    "RCPT" => rcpt_reply,
+                    "DATA" => {
+                        received.push(line);
+                        stream.write_all(b"354 go ahead\r\n").await.unwrap();
+                        let mut data = Vec::new();
+                        while !data.ends_with(b"\r\n.\r\n") {
+                            data.push(stream.read_u8().await.unwrap());
+                        }
+                        line = String::from_utf8(data).unwrap();
+                        "250 queued\r\n"
+                    }
+                    "QUIT" => {
+                        received.push(line);
+                        stream.write_all(b"221 bye\r\n").await.unwrap();
+                        break;
+                    }
+                    _ => "500 unknown command\r\n",
+                };
+                received.push(line);
+                stream.write_all(reply.as_bytes()).await.unwrap();
+            }
+            received
+        });
+        (port, handle)
+    }
+
+    fn transport(port: u16, security: SmtpSecurity, credentials: bool) -> SmtpTransport {
+        SmtpTransport::new(SmtpOptions {
+            host: "127.0.0.1".to_string(),
+            port,
+            security,
+            credentials: credentials.then(|| SmtpCredentials {
+                username: "iam".to_string(),
+                password: "hunter2".to_string(),
+            }),
+        })
+    }
+
+    fn message() -> Message {
+        Message {
+            from: "IAM <iam@kasad.com>".to_string(),
+            to: "kian@kasad.com".to_string(),
+            subject: "Welcome".to_string(),
+            html: "<p>Hello</p>".to_string(),
+            text: ".Hello".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send() {
+        let (port, relay) = fake_relay("250 ok\r\n", "235 authenticated\r\n").await;
+        transport(port, SmtpSecurity::None, false)
+            .send(&message())
+            .await
+            .unwrap();
+        let received = relay.await.unwrap();
+        assert_eq!(received[0], "EHLO kasad.com\r\n");
+        assert_eq!(received[1], "MAIL FROM:<iam@kasad.com>\r\n");
+        assert_eq!(received[2], "RCPT TO:<kian@kasad.com>\r\n");
+        assert_eq!(received[3], "DATA\r\n");
+        let data = &received[4];
+        assert!(data.contains("From: IAM <iam@kasad.com>\r\n"), "{data}");
+        assert!(data.contains("To: kian@kasad.com\r\n"), "{data}");
+        assert!(data.contains("Subject: Welcome\r\n"), "{data}");
+        assert!(
+            data.contains("Content-Type: multipart/alternative;"),
+            "{data}"
+        );
+        // Dots at the start of lines are escaped
+        assert!(data.contains("\r\n..Hello\r\n"), "{data}");
+        assert!(data.ends_with("\r\n.\r\n"));
+        assert_eq!(received[5], "QUIT\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_credentials_require_encryption() {
+        // Credentials aren't sent in plain text, so nothing is sent at all
+        let result = transport(1, SmtpSecurity::None, true)
+            .send(&message())
+            .await;
+        assert!(matches!(result, Err(TransportError::Temporary(_))));
+
+        // A relay which can't upgrade the connection never sees the credentials
+        let (port, relay) = fake_relay("250 ok\r\n", "235 authenticated\r\n").await;
+        let result = transport(port, SmtpSecurity::StartTls, true)
+            .send(&message())
+            .await;
+        assert!(matches!(result, Err(TransportError::Temporary(_))));
+        let received = relay.await.unwrap();
+        assert!(
+            received.iter().all(|line| !line.starts_with("AUTH")),
+            "{received:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejected_recipient() {
+        let (port, _relay) = fake_relay("550 no such user\r\n", "").await;
+        let result = transport(port, SmtpSecurity::None, false)
+            .send(&message())
+            .await;
+        assert!(matches!(result, Err(TransportError::Permanent(_))));
+
+        let (port, _relay) = fake_relay("451 try again later\r\n", "").await;
+        let result = transport(port, SmtpSecurity::None, false)
+            .send(&message())
+            .await;
+        assert!(matches!(result, Err(TransportError::Temporary(_))));
+    }
+
+    #[test]
+    fn test_permanent_replies() {
+        assert!(is_permanent_reply(550));
+        assert!(is_permanent_reply(554));
+        assert!(!is_permanent_reply(451));
+        // Authentication failures can be fixed by the operator
+        assert!(!is_permanent_reply(530));
+        assert!(!is_permanent_reply(535));
+    }
+}
@@ -0,0 +1,307 @@
+//! # Email templates
+//!
+//! Every email consists of three templates, which are rendered using [Tera][tera]:
+//!
+//! | File                   | Contents                  |
+//! |------------------------|---------------------------|
+//! | `<name>.subject.txt`   | Subject line              |
+//! | `<name>.html`          | HTML body                 |
+//! | `<name>.txt`           | Plain text body           |
+//!
+//! Default templates for all emails are compiled into the server. Operators can override any of
+//! them (including the shared `layout.html` and `layout.txt` templates which the defaults extend)
+//! by placing a file with the same name in a template directory.
+//!
+//! All templates have access to the following variables in addition to the ones specific to each
+//! email:
+//!
+//! - `config`: the [`AppConfig`], for branding (e.g. `{{ config.instanceName }}`)
+//! - `app_url`: the absolute URL of the app, without a trailing slash, for building links
+//! - `subject`: the rendered subject line (not available in the subject template itself)
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use tera::{Context, Tera};
+use tracing::debug;
+
+use crate::models::AppConfig;
+
+/// Default templates compiled into the server, as `(name, contents)` pairs
+const DEFAULT_TEMPLATES: &[(&str, &str)] = &[
+    (
+        "email_change.subject.txt",
+        include_str!("templates/email_change.subject.txt"),
+    ),
+    (
+        "email_change.html",
+        include_str!("templates/email_change.html"),
+    ),
+    (
+        "email_change.txt",
+        include_str!("templates/email_change.txt"),
+    ),
+    ("layout.html", include_str!("templates/layout.html")),
+    ("layout.txt", include_str!("templates/layout.txt")),
+    (
+        "login.subject.txt",
+        include_str!("templates/login.subject.txt"),
+    ),
+    ("login.html", include_str!("templates/login.html")),
+    ("login.txt", include_str!("templates/login.txt")),
+    (
+        "passkey_added.subject.txt",
+        include_str!("templates/passkey_added.subject.txt"),
+    ),
+    (
+        "passkey_added.html",
+        include_str!("templates/passkey_added.html"),
+    ),
+    (
+        "passkey_added.txt",
+        include_str!("templates/passkey_added.txt"),
+    ),
+    (
+        "passkey_removed.subject.txt",
+        include_str!("templates/passkey_removed.subject.txt"),
+    ),
+    (
+        "passkey_removed.html",
+        include_str!("templates/passkey_removed.html"),
+    ),
+    (
+        "passkey_removed.txt",
+        include_str!("templates/passkey_removed.txt"),
+    ),
+    (
+        "registration_attempt.subject.txt",
+        include_str!("templates/registration_attempt.subject.txt"),
+    ),
+    (
+        "registration_attempt.html",
+        include_str!("templates/registration_attempt.html"),
+    ),
+    (
+        "registration_attempt.txt",
+        include_str!("templates/registration_attempt.txt"),
+    ),
+    (
+        "security_alert.subject.txt",
+        include_str!("templates/security_alert.subject.txt"),
+    ),
+    (
+        "security_alert.html",
+        include_str!("templates/security_alert.html"),
+    ),
+    (
+        "security_alert.txt",
+        include_str!("templates/security_alert.txt"),
+    ),
+    (
+        "test.subject.txt",
+        include_str!("templates/test.subject.txt"),
+    ),
+    ("test.html", include_str!("templates/test.html")),
+    ("test.txt", include_str!("templates/test.txt")),
+];
+
+/// Error type for loading and rendering email templates
+#[derive(Debug, thiserror::Error)]
+pub enum TemplateError {
+    /// Reading the template directory or a template file failed.
+    #[error("failed to read email template(s) from {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A template is invalid or rendering it failed.
+    #[error("email template error: {0}")]
+    Tera(#[from] tera::Error),
+
+    /// Serializing the app configuration into the template context failed.
+    #[error("failed to serialize app configuration: {0}")]
+    Config(#[from] serde_json::Error),
+}
+
+/// Subject and bodies of a rendered email
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// # Email template collection
+///
+/// See the [module-level documentation][self] for details.
+#[derive(Debug, Clone)]
+pub struct EmailTemplates {
+    tera: Tera,
+    config: serde_json::Value,
+    app_url: String,
+}
+
+impl EmailTemplates {
+    /// Loads the default templates, overridden by any templates in `override_dir` if given.
+    /// `app_url` is the absolute URL at which the app is served.
+    ///
+    /// Every `.html` and `.txt` file in `override_dir` is loaded as a template, so overrides can
+    /// also add new templates for the overridden ones to include or extend.
+    pub fn new(
+        config: &AppConfig,
+        app_url: &str,
+        override_dir: Option<&Path>,
+    ) -> Result<Self, TemplateError> {
+        let mut templates: HashMap<String, String> = DEFAULT_TEMPLATES
+            .iter()
+            .map(|&(name, contents)| (name.to_string(), contents.to_string()))
+            .collect();
+        if let Some(dir) = override_dir {
+            for (name, contents) in read_template_dir(dir)? {
+                debug!(template = %name, "using email template override");
+                templates.insert(name, contents);
+            }
+        }
+
+        // Templates must be added all at once so that inheritance can be resolved regardless
+        // of order
+        let mut tera = Tera::default();
+        tera.add_raw_templates(templates)?;
+        Ok(Self {
+            tera,
+            config: serde_json::to_value(config)?,
+            app_url: app_url.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Renders the email with the given name, using the fields of `context` as template
+    /// variables. `context` must serialize to a map, e.g. a struct.
+    pub fn render<C>(&self, name: &str, context: &C) -> Result<RenderedEmail, TemplateError>
+    where
+        C: Serialize + ?Sized,
+    {
+        let mut context = Context::from_serialize(context)?;
+        context.insert("config", &self.config);
+        context.insert("app_url", &self.app_url);
+        let subject = self
+            .tera
+            .render(&format!("{name}.subject.txt"), &context)?
+            .trim()
+            .to_string();
+        context.insert("subject", &subject);
+        Ok(RenderedEmail {
+            html: self.tera.render(&format!("{name}.html"), &context)?,
+            text: self.tera.render(&format!("{name}.txt"), &context)?,
+            subject,
+        })
+    }
+}
+
+/// Reads all `.html` and `.txt` files in the given directory (non-recursively), returning
+/// `(file name, contents)` pairs.
+fn read_template_dir(dir: &Path) -> Result<Vec<(String, String)>, TemplateError> {
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| TemplateError::Io { path, source }
+    };
+    let mut templates = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(io_error(dir))? {
+        let path = entry.map_err(io_error(dir))?.path();
+        let is_template = path
+            .extension()
+            .is_some_and(|ext| ext == "html" || ext == "txt");
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if is_template && path.is_file() {
+            let contents = std::fs::read_to_string(&path).map_err(io_error(&path))?;
+            templates.push((name.to_string(), contents));
+        }
+    }
+    Ok(templates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Branding, FeatureFlags, RegistrationMode, SessionLifetimes};
+
+    fn config() -> AppConfig {
+        AppConfig {
+            instance_name: "Example IAM".to_string(),
+            base_path: String::new(),
+            branding: Branding::default(),
+            features: FeatureFlags::default(),
+            registration_mode: RegistrationMode::default(),
+            allowed_email_domains: Vec::new(),
+            session_lifetimes: SessionLifetimes::default(),
+        }
+    }
+
+    #[test]
+    fn test_render_default() {
+        let templates = EmailTemplates::new(&config(), "https://example.com/", None).unwrap();
+        let email = templates.render("test", &serde_json::json!({})).unwrap();
+        assert_eq!(email.subject, "Test message from Example IAM");
+        assert!(
+            email
+                .html
+                .contains("<title>Test message from Example IAM</title>")
+        );
+        assert!(email.text.starts_with("Example IAM"));
+    }
+
+    #[test]
+    fn test_render_login() {
+        let templates = EmailTemplates::new(&config(), "https://example.com/", None).unwrap();
+        let email = templates
+            .render(
+                "login",
+                &serde_json::json!({
+                    "display_name": "Bob",
+                    "time": "2025-01-01 00:00:00 UTC",
+                    "new_device": true,
+                    "revoke_token": "abc",
+                }),
+            )
+            .unwrap();
+        assert_eq!(
+            email.subject,
+            "New device signed in to your Example IAM account"
+        );
+        assert!(email.text.contains("IP address: Unknown"));
+        assert!(
+            email
+                .text
+                .contains("https://example.com/revoke-session?token=abc")
+        );
+    }
+
+    #[test]
+    fn test_override() {
+        let dir = std::env::temp_dir().join(format!("iam-templates-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(dir.join("test.subject.txt"), "Hello {{ name }}").unwrap();
+        std::fs::write(
+            dir.join("layout.txt"),
+            "Custom {% block content %}{% endblock %}",
+        )
+        .unwrap();
+
+        let templates = EmailTemplates::new(&config(), "https://example.com/", Some(&dir)).unwrap();
+        let email = templates
+            .render("test", &serde_json::json!({ "name": "<Bob>" }))
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(email.subject, "Hello <Bob>");
+        assert!(email.text.starts_with("Custom This is a test message."));
+        // Default HTML template is still used
+        assert!(email.html.contains("<title>Hello &lt;Bob&gt;</title>"));
+    }
+}
@@ -0,0 +1,65 @@
+//! # Email transports
+//!
+//! A [`Transport`] delivers rendered [`Message`]s to their recipients, e.g. via an external mail
+//! server. The [`Mailer`][super::Mailer] renders emails and hands them to its transport.
+
+use std::{future::Future, pin::Pin};
+
+use tracing::{debug, info};
+
+/// An email message ready to be delivered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// Sender address, e.g. `IAM <iam@example.com>`
+    pub from: String,
+    /// Recipient address
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// Error type for [`Transport::send()`]
+#[derive(Debug, thiserror::Error)]
+pub enum TransportError {
+    /// Delivery failed in a way that won't be fixed by retrying, e.g. the recipient address was
+    /// rejected.
+    #[error("permanent delivery failure: {0}")]
+    Permanent(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+
+    /// Delivery failed but may succeed if retried later, e.g. the mail server was unreachable.
+    #[error("temporary delivery failure: {0}")]
+    Temporary(#[source] Box<dyn std::error::Error + Send + Sync + 'static>),
+}
+
+/// # Email transport interface
+///
+/// Implementations deliver messages to their recipients. See the
+/// [module-level documentation][self] for details.
+pub trait Transport: Send + Sync + 'static {
+    /// Delivers the given message.
+    fn send<'a>(
+        &'a self,
+        message: &'a Message,
+    ) -> Pin<Box<dyn Future<Output = Result<(), TransportError>> + Send + 'a>>;
+}
+
+/// # Logging transport
+///
+/// A [`Transport`] which doesn't deliver messages, but instead writes them to the log. Useful for
+/// development and testing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogTransport;
+
+impl Transport for LogTransport {
+    fn send<'a>(
+        &'a self,
+        message: &'a Message,
+    ) -> Pin<Box<dyn Future<Output = Result<(), TransportError>> + Send + 'a>> {
+        Box::pin(async move {
+            info!(from = %message.from, to = %message.to, subject = %message.subject, "email sent");
+            debug!(body = %message.text, "email body");
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,293 @@
+//! # Identity federation
+//!
+//! Lets users log in with an account at an upstream OIDC provider, such as Google Workspace,
+//! which is useful while an organization migrates to passkeys. Providers are registered by
+//! administrators as [`FederatedProvider`]s.
+//!
+//! The [`FederationClient`] implements the relying party side of the authorization code flow:
+//! it discovers the provider's endpoints, builds the authorization request to which users are
+//! redirected, and exchanges the authorization code which they bring back for an ID token. PKCE
+//! is always used.
+//!
+//! The ID token is received directly from the provider's token endpoint over a connection
+//! authenticated by TLS, so its signature isn't checked (as permitted by OIDC Core, section
+//! 3.1.3.7). Its issuer, audience, expiry, and nonce are checked.
+
+use std::time::Duration;
+
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::models::FederatedProvider;
+
+/// Timeout for requests to upstream providers
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Scopes requested from upstream providers
+const SCOPES: &str = "openid email profile";
+
+/// Errors which can occur while talking to an upstream provider
+#[derive(Debug, thiserror::Error)]
+pub enum FederationError {
+    #[error("request to provider failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("provider metadata is invalid: {0}")]
+    InvalidMetadata(&'static str),
+
+    #[error("provider returned an invalid ID token: {0}")]
+    InvalidIdToken(&'static str),
+}
+
+/// Endpoints of an upstream provider, from its discovery document
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+}
+
+/// Claims about the user from an upstream provider's ID token
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdTokenClaims {
+    /// Identifier of the user's account at the provider
+    pub sub: String,
+    pub email: Option<String>,
+    /// Whether the provider has verified that the user owns [`email`][Self::email]
+    #[serde(default)]
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+/// Claims which are checked but not returned
+#[derive(Deserialize)]
+struct ValidatedClaims {
+    iss: String,
+    aud: Audience,
+    exp: i64,
+    nonce: Option<String>,
+    #[serde(flatten)]
+    claims: IdTokenClaims,
+}
+
+/// The `aud` claim, which may be a single value or an array
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Self::One(aud) => aud == client_id,
+            Self::Many(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// Returns the PKCE code challenge for the given verifier, using the `S256` method.
+#[must_use]
+pub fn code_challenge(verifier: &str) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+/// # Upstream OIDC client
+///
+/// See the [module-level documentation][self] for details.
+pub struct FederationClient {
+    http: reqwest::Client,
+}
+
+impl Default for FederationClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FederationClient {
+    /// Creates a new client.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the HTTP client can't be created, e.g. because the TLS backend fails to
+    /// initialize.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("failed to create HTTP client"),
+        }
+    }
+
+    /// Fetches the provider's discovery document, checking that it belongs to the provider's
+    /// issuer.
+    pub async fn discover(
+        &self,
+        provider: &FederatedProvider,
+    ) -> Result<ProviderMetadata, FederationError> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            provider.issuer.trim_end_matches('/')
+        );
+        let metadata: ProviderMetadata = self
+            .http
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if metadata.issuer != provider.issuer {
+            return Err(FederationError::InvalidMetadata("issuer mismatch"));
+        }
+        Ok(metadata)
+    }
+
+    /// Returns the URL to which the user is redirected to log in at the provider.
+    pub fn authorization_url(
+        metadata: &ProviderMetadata,
+        provider: &FederatedProvider,
+        redirect_uri: &str,
+        state: &str,
+        nonce: &str,
+        code_verifier: &str,
+    ) -> Result<Url, FederationError> {
+        let mut url = Url::parse(&metadata.authorization_endpoint)
+            .map_err(|_| FederationError::InvalidMetadata("invalid authorization endpoint"))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &provider.client_id)
+            .append_pair("redirect_uri", redirect_uri)
+            .append_pair("scope", SCOPES)
+            .append_pair("state", state)
+            .append_pair("nonce", nonce)
+            .append_pair("code_challenge", &code_challenge(code_verifier))
+            .append_pair("code_challenge_method", "S256");
+        Ok(url)
+    }
+
+    /// Exchanges an authorization code for an ID token, and returns the token's claims once they
+    /// have been validated.
+    pub async fn exchange_code(
+        &self,
+        metadata: &ProviderMetadata,
+        provider: &FederatedProvider,
+        redirect_uri: &str,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<IdTokenClaims, FederationError> {
+        let response: TokenResponse = self
+            .http
+            .post(&metadata.token_endpoint)
+            .basic_auth(&provider.client_id, Some(&*provider.client_secret))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", redirect_uri),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        validate_id_token(&response.id_token, provider, nonce)
+    }
+}
+
+/// Decodes an ID token received from the provider's token endpoint and validates its claims.
+fn validate_id_token(
+    token: &str,
+    provider: &FederatedProvider,
+    nonce: &str,
+) -> Result<IdTokenClaims, FederationError> {
+    let claims = token
+        .split('.')
+        .nth(1)
+        .ok_or(FederationError::InvalidIdToken("malformed token"))?;
+    let claims = BASE64_URL_SAFE_NO_PAD
+        .decode(claims)
+        .map_err(|_| FederationError::InvalidIdToken("malformed token"))?;
+    let claims: ValidatedClaims = serde_json::from_slice(&claims)
+        .map_err(|_| FederationError::InvalidIdToken("malformed claims"))?;
+    if claims.iss != provider.issuer {
+        return Err(FederationError::InvalidIdToken("issuer mismatch"));
+    }
+    if !claims.aud.contains(&provider.client_id) {
+        return Err(FederationError::InvalidIdToken("audience mismatch"));
+    }
+    if claims.exp <= chrono::Utc::now().timestamp() {
+        return Err(FederationError::InvalidIdToken("token has expired"));
+    }
+    if claims.nonce.as_deref() != Some(nonce) {
+        return Err(FederationError::InvalidIdToken("nonce mismatch"));
+    }
+    Ok(claims.claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ViaJson;
+
+    fn token(claims: &serde_json::Value) -> String {
+        format!(
+            "e30.{}.c2ln",
+            BASE64_URL_SAFE_NO_PAD.encode(claims.to_string())
+        )
+    }
+
+    #[test]
+    fn test_validate_id_token() {
+        let now = chrono::Utc::now();
+        let provider = FederatedProvider {
+            id: uuid::Uuid::new_v4(),
+            slug: "google".to_string(),
+            name: "Google".to_string(),
+            issuer: "https://accounts.google.com".to_string(),
+            client_id: "client".to_string(),
+            client_secret: ViaJson("secret".to_string()),
+            auto_provision: false,
+            created_at: now,
+            updated_at: now,
+        };
+        let valid = serde_json::json!({
+            "iss": "https://accounts.google.com",
+            "aud": ["other", "client"],
+            "exp": now.timestamp() + 60,
+            "nonce": "n",
+            "sub": "123",
+            "email": "test@kasad.com",
+            "email_verified": true,
+        });
+        let claims = validate_id_token(&token(&valid), &provider, "n").unwrap();
+        assert_eq!(claims.sub, "123");
+        assert!(claims.email_verified);
+        assert!(validate_id_token(&token(&valid), &provider, "other").is_err());
+
+        for (claim, value) in [
+            ("iss", serde_json::json!("https://evil.example.com")),
+            ("aud", serde_json::json!("other")),
+            ("exp", serde_json::json!(now.timestamp() - 1)),
+        ] {
+            let mut invalid = valid.clone();
+            invalid[claim] = value;
+            assert!(
+                validate_id_token(&token(&invalid), &provider, "n").is_err(),
+                "{claim}"
+            );
+        }
+    }
+}
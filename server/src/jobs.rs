@@ -0,0 +1,365 @@
+//! # Background job scheduler
+//!
+//! Periodic work, such as enforcing the [retention policy][crate::retention::RetentionJob] or
+//! [cleaning up](CeremonyCleanupJob) abandoned passkey ceremonies, is done by [`Job`]s
+//! registered with a [`Scheduler`]. The scheduler runs each job on its own [`Schedule`], keeps
+//! [metrics](JobMetrics) about its runs, and stops all jobs when the server
+//! [shuts down](Scheduler::shutdown).
+//!
+//! Jobs which must only run on one instance at a time are
+//! [coordinated](Schedule::coordinated) using the [`Coordinator`]'s leases. A random
+//! [jitter](Schedule::jitter) can be added to each run so that instances started together don't
+//! all hit the database at the same moment.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use tokio::{sync::watch, task::JoinHandle};
+use tracing::{debug, error, info};
+
+use crate::{coordination::Coordinator, db::interface::DatabaseClient};
+
+/// Error returned by a [`Job`]
+pub type JobError = Box<dyn std::error::Error + Send + Sync>;
+
+/// # Scheduled job
+///
+/// A unit of periodic work which can be registered with a [`Scheduler`].
+pub trait Job: Send + Sync + 'static {
+    /// Returns the name of the job, which identifies it in logs, [metrics](JobMetrics), and
+    /// [leases](Coordinator). It must be unique among the jobs of a scheduler.
+    fn name(&self) -> &'static str;
+
+    /// Runs the job once.
+    fn run(&self) -> Pin<Box<dyn Future<Output = Result<(), JobError>> + Send + '_>>;
+}
+
+/// When a [`Job`] runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Schedule {
+    /// Time between the starts of consecutive runs
+    pub interval: Duration,
+    /// Maximum random delay added to each run
+    pub jitter: Duration,
+    /// Whether the first run happens right away rather than after one interval
+    pub run_at_start: bool,
+    /// Whether only the instance elected by the [`Coordinator`] runs the job
+    pub coordinated: bool,
+}
+
+impl Schedule {
+    /// Creates a schedule which runs a job on every instance every `interval`, starting after one
+    /// interval.
+    #[must_use]
+    pub fn every(interval: Duration) -> Self {
+        Self {
+            interval,
+            jitter: Duration::ZERO,
+            run_at_start: false,
+            coordinated: false,
+        }
+    }
+
+    /// Delays each run by a random duration of up to `jitter`.
+    #[must_use]
+    pub fn jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Makes the first run happen right away.
+    #[must_use]
+    pub fn run_at_start(mut self) -> Self {
+        self.run_at_start = true;
+        self
+    }
+
+    /// Only runs the job on the instance elected by the [`Coordinator`].
+    #[must_use]
+    pub fn coordinated(mut self) -> Self {
+        self.coordinated = true;
+        self
+    }
+
+    /// Returns the delay before the next run, which is `interval` (or nothing, for the first run
+    /// of jobs which run at start) plus a random jitter.
+    fn delay(&self, first: bool) -> Duration {
+        let base = if first && self.run_at_start {
+            Duration::ZERO
+        } else {
+            self.interval
+        };
+        if self.jitter.is_zero() {
+            return base;
+        }
+        base + rand::rng().random_range(Duration::ZERO..=self.jitter)
+    }
+}
+
+/// Statistics about the runs of a [`Job`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JobMetrics {
+    /// Number of runs, including failed ones
+    pub runs: u64,
+    /// Number of failed runs
+    pub failures: u64,
+    /// Number of runs skipped because another instance holds the job's lease
+    pub skipped: u64,
+    /// Time at which the last run started
+    pub last_run_at: Option<DateTime<Utc>>,
+    /// Duration of the last run
+    pub last_duration: Option<Duration>,
+    /// Error of the last run, if it failed
+    pub last_error: Option<String>,
+}
+
+/// # Job scheduler
+///
+/// See the [module-level documentation][self] for details.
+pub struct Scheduler {
+    coordinator: Option<Arc<Coordinator>>,
+    metrics: Arc<Mutex<HashMap<&'static str, JobMetrics>>>,
+    shutdown: watch::Sender<bool>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self {
+            coordinator: None,
+            metrics: Arc::default(),
+            shutdown: watch::Sender::new(false),
+            tasks: Mutex::default(),
+        }
+    }
+}
+
+impl Scheduler {
+    /// Creates a scheduler without any jobs. Without a [coordinator](Self::with_coordinator),
+    /// coordinated jobs run on every instance.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Coordinates the [coordinated](Schedule::coordinated) jobs with other instances using the
+    /// given coordinator.
+    #[must_use]
+    pub fn with_coordinator(mut self, coordinator: Arc<Coordinator>) -> Self {
+        self.coordinator = Some(coordinator);
+        self
+    }
+
+    /// Starts running the given job on the given schedule.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a job with the same name was already registered.
+    pub fn register(&self, job: Arc<dyn Job>, schedule: Schedule) {
+        let name = job.name();
+        {
+            let mut metrics = self.metrics.lock().unwrap();
+            assert!(
+                !metrics.contains_key(name),
+                "job `{name}` is already registered"
+            );
+            metrics.insert(name, JobMetrics::default());
+        }
+        debug!(job = name, ?schedule, "scheduling job");
+        let coordinator = schedule
+            .coordinated
+            .then(|| self.coordinator.clone())
+            .flatten();
+        let metrics = Arc::clone(&self.metrics);
+        let mut shutdown = self.shutdown.subscribe();
+        let task = tokio::spawn(async move {
+            let mut first = true;
+            loop {
+                // Stop waiting as soon as the scheduler shuts down
+                let delay = schedule.delay(first);
+                first = false;
+                if tokio::time::timeout(delay, shutdown.wait_for(|&stop| stop))
+                    .await
+                    .is_ok()
+                {
+                    return;
+                }
+                if let Some(coordinator) = &coordinator
+                    && !coordinator.should_run(name, schedule.interval).await
+                {
+                    update_metrics(&metrics, name, |metrics| metrics.skipped += 1);
+                    continue;
+                }
+                run_job(job.as_ref(), &metrics).await;
+            }
+        });
+        self.tasks.lock().unwrap().push(task);
+    }
+
+    /// Returns the metrics of all registered jobs, by name.
+    #[must_use]
+    pub fn metrics(&self) -> HashMap<&'static str, JobMetrics> {
+        self.metrics.lock().unwrap().clone()
+    }
+
+    /// Stops all jobs, waiting for the ones which are running to finish.
+    pub async fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+        for task in tasks {
+            if let Err(err) = task.await {
+                error!(%err, "job task panicked");
+            }
+        }
+        info!("stopped background jobs");
+    }
+}
+
+/// Runs the job once, recording the outcome in its metrics.
+async fn run_job(job: &dyn Job, metrics: &Mutex<HashMap<&'static str, JobMetrics>>) {
+    let name = job.name();
+    let started_at = Utc::now();
+    let start = Instant::now();
+    let result = job.run().await;
+    let duration = start.elapsed();
+    match &result {
+        Ok(()) => debug!(job = name, ?duration, "job finished"),
+        Err(err) => error!(%err, job = name, ?duration, "job failed"),
+    }
+    update_metrics(metrics, name, |metrics| {
+        metrics.runs += 1;
+        metrics.last_run_at = Some(started_at);
+        metrics.last_duration = Some(duration);
+        metrics.last_error = result.err().map(|err| err.to_string());
+        if metrics.last_error.is_some() {
+            metrics.failures += 1;
+        }
+    });
+}
+
+/// Applies `update` to the metrics of the job named `name`.
+fn update_metrics(
+    metrics: &Mutex<HashMap<&'static str, JobMetrics>>,
+    name: &'static str,
+    update: impl FnOnce(&mut JobMetrics),
+) {
+    update(metrics.lock().unwrap().entry(name).or_default());
+}
+
+/// # Passkey ceremony cleanup job
+///
+/// Deletes [passkey ceremony state][DatabaseClient::delete_expired_ceremonies] which is older
+/// than [`Self::MAX_AGE`], i.e. of registrations and logins which were started but never
+/// finished. Deleting is idempotent, so the job doesn't need to be coordinated.
+pub struct CeremonyCleanupJob {
+    db: Arc<dyn DatabaseClient>,
+}
+
+impl CeremonyCleanupJob {
+    /// Age after which ceremony state is deleted
+    pub const MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+    /// Creates a job which cleans up ceremony state in the given database.
+    #[must_use]
+    pub fn new(db: Arc<dyn DatabaseClient>) -> Self {
+        Self { db }
+    }
+}
+
+impl Job for CeremonyCleanupJob {
+    fn name(&self) -> &'static str {
+        "ceremony-cleanup"
+    }
+
+    fn run(&self) -> Pin<Box<dyn Future<Output = Result<(), JobError>> + Send + '_>> {
+        Box::pin(async move {
+            let deleted = self.db.delete_expired_ceremonies(Self::MAX_AGE).await?;
+            if deleted > 0 {
+                debug!(deleted, "deleted expired passkey ceremony state");
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    /// Job which counts its runs, failing every other run
+    #[derive(Default)]
+    struct CountingJob {
+        runs: AtomicU64,
+    }
+
+    impl Job for CountingJob {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        fn run(&self) -> Pin<Box<dyn Future<Output = Result<(), JobError>> + Send + '_>> {
+            Box::pin(async move {
+                if self.runs.fetch_add(1, Ordering::SeqCst) % 2 == 1 {
+                    return Err("odd run".into());
+                }
+                Ok(())
+            })
+        }
+    }
+
+    #[test]
+    fn test_schedule_delay() {
+        let interval = Duration::from_secs(60);
+        let schedule = Schedule::every(interval);
+        assert_eq!(schedule.delay(true), interval);
+        assert_eq!(schedule.run_at_start().delay(true), Duration::ZERO);
+        assert_eq!(schedule.run_at_start().delay(false), interval);
+        let jitter = Duration::from_secs(10);
+        for _ in 0..100 {
+            let delay = schedule.jitter(jitter).delay(false);
+            assert!(delay >= interval && delay <= interval + jitter);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scheduler_runs_jobs_and_shuts_down() {
+        let scheduler = Scheduler::new();
+        let job = Arc::new(CountingJob::default());
+        scheduler.register(
+            Arc::clone(&job) as Arc<dyn Job>,
+            Schedule::every(Duration::from_millis(20)).run_at_start(),
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        scheduler.shutdown().await;
+
+        let runs = job.runs.load(Ordering::SeqCst);
+        assert!(runs >= 2);
+        let metrics = &scheduler.metrics()["counting"];
+        assert_eq!(metrics.runs, runs);
+        assert_eq!(metrics.failures, runs / 2);
+        assert!(metrics.last_run_at.is_some());
+        assert_eq!(metrics.skipped, 0);
+
+        // No more runs happen after shutdown
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(job.runs.load(Ordering::SeqCst), runs);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "already registered")]
+    async fn test_duplicate_job_names_are_rejected() {
+        let scheduler = Scheduler::new();
+        let schedule = Schedule::every(Duration::from_secs(60));
+        scheduler.register(Arc::new(CountingJob::default()), schedule);
+        scheduler.register(Arc::new(CountingJob::default()), schedule);
+    }
+}
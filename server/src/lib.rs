@@ -1,4 +1,27 @@
+pub mod admin;
+pub mod alerts;
 pub mod api;
+pub mod authenticators;
+pub mod bans;
+pub mod client_ip;
+pub mod config;
+pub mod coordination;
+pub mod crypto;
 pub mod db;
+pub mod email;
+pub mod federation;
+pub mod jobs;
+pub mod lockout;
+pub mod maintenance;
 pub mod models;
+pub mod policy;
+pub mod rate_limit;
+pub mod relying_party;
+pub mod retention;
+pub mod saml;
+pub mod session_cookie;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod ttl_cache;
 pub mod ui;
+pub mod webhooks;
@@ -0,0 +1,61 @@
+//! # Account lockout
+//!
+//! After too many failed logins to an account within a window (see [`LockoutOptions`]), the
+//! account is locked for a while, during which logins to it are refused even with a valid passkey.
+//! Unlike [automatic IP bans][crate::bans::AutoBanOptions], this also stops attackers who spread
+//! their attempts across many addresses.
+//!
+//! Failed logins and locks are stored in the database, so that all instances share them.
+//! Administrators can unlock accounts before their lock ends.
+
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::db::interface::{DatabaseClient, DatabaseError};
+
+/// Options for locking accounts to which logins repeatedly fail
+#[derive(Debug, Clone)]
+pub struct LockoutOptions {
+    /// Number of failed logins to the same account within [`window`][Self::window] which locks
+    /// it
+    pub threshold: u64,
+    /// Window in which failed logins are counted
+    pub window: Duration,
+    /// How long locks last
+    pub duration: Duration,
+}
+
+impl Default for LockoutOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 10,
+            window: Duration::from_secs(15 * 60),
+            duration: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+/// Records a failed login to the account of the user with the given UUID, made with the passkey
+/// with the UUID `passkey_id` if it is known, and locks the account if the
+/// [threshold][LockoutOptions::threshold] is reached. Returns the time at which the lock ends, if
+/// the account was locked.
+pub async fn record_failure(
+    db: &dyn DatabaseClient,
+    options: &LockoutOptions,
+    user_id: &Uuid,
+    passkey_id: Option<&Uuid>,
+) -> Result<Option<DateTime<Utc>>, DatabaseError> {
+    let failures = db
+        .record_login_failure(user_id, passkey_id, options.window)
+        .await?;
+    if failures < options.threshold {
+        return Ok(None);
+    }
+    let until = Utc::now() + TimeDelta::from_std(options.duration).unwrap_or(TimeDelta::MAX);
+    warn!(user = %user_id, failures, %until, "locking account after repeated failed logins");
+    db.set_user_locked_until(user_id, Some(until)).await?;
+    Ok(Some(until))
+}
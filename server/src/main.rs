@@ -1,3 +1,9 @@
+//! # IAM server
+//!
+//! Runs the server, or one of the maintenance tasks given as subcommands. Settings are read from
+//! the environment and an optional configuration file (see [`iam_server::config`]), and some can
+//! be overridden by arguments.
+
 use axum::{
     Router,
     http::{
@@ -6,102 +12,638 @@ use axum::{
             CONTENT_SECURITY_POLICY, REFERRER_POLICY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
         },
     },
+    response::Redirect,
+    routing::get,
 };
+use clap::{Args, Parser, Subcommand};
+#[cfg(feature = "chaos")]
+use iam_server::db::clients::chaos::{ChaosClient, ChaosOptions};
 #[cfg(feature = "sqlite3")]
-use iam_server::db::clients::sqlite::SqliteClient;
+use iam_server::db::clients::sqlite::{
+    CreateSqliteClientError, MigrationError, MigrationStatus, PendingMigrations, SqliteClient,
+};
 use iam_server::{
-    api::new_api_router, db::interface::DatabaseClient, models::AppConfig, ui::new_ui_server,
+    admin::{grant_admin, revoke_admin},
+    alerts::{AlertOptions, Alerter},
+    api::{ApiOptions, generate_api_specs, new_api_router, new_well_known_router},
+    config::{
+        ConfigProblem, DbBackend, DbSettings, EmailSettings, KeySettings, MaintenanceSettings,
+        RetentionSettings, ServerConfig, vars,
+    },
+    coordination::Coordinator,
+    crypto::{
+        KeyManager, set_keyring,
+        signing::{SigningKeyManager, SigningKeyOptions},
+    },
+    db::{
+        clients::cached::CachedClient,
+        interface::{DatabaseClient, DatabaseError},
+    },
+    email::{EmailQueue, Mailer},
+    jobs::{CeremonyCleanupJob, Schedule, Scheduler},
+    maintenance::MaintenanceJob,
+    models::{Backup, set_email_unicode_normalization, set_uuid_version},
+    rate_limit::{MemoryStore, RateLimitOptions, RateLimiter},
+    relying_party::{RelyingParties, RelyingPartyConfig},
+    retention::RetentionJob,
+    session_cookie::SessionCookieKeys,
+    ui::new_ui_server,
+};
+#[cfg(feature = "redis")]
+use iam_server::{
+    config::RedisSettings,
+    db::{clients::redis::RedisSessionClient, connect::ConnectRetryOptions},
+    rate_limit::RedisStore,
+};
+use std::{
+    ffi::OsString,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+    time::Duration,
 };
-use std::{env::VarError, ffi::OsString, path::PathBuf, process::ExitCode, sync::Arc};
 use tokio::net::TcpListener;
 use tower_http::set_header::SetResponseHeaderLayer;
-use tracing::{error, info, warn};
-use webauthn_rs::{WebauthnBuilder, prelude::Url};
+#[cfg(feature = "chaos")]
+use tracing::warn;
+use tracing::{error, info};
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    settings: SettingArgs,
+
+    /// Task to run; runs the server if none is given
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-mod vars {
-    pub const STATIC_DIR: &str = "STATIC_DIR";
-    pub const ORIGIN: &str = "ORIGIN";
-    pub const SERVER_NAME: &str = "SERVER_NAME";
-    pub const RP_ID: &str = "RP_ID";
-    pub const DB_BACKEND: &str = "DB_BACKEND";
+#[derive(Subcommand)]
+enum Command {
+    /// Run the server
+    Serve,
+    /// Manage database migrations, then exit; applies pending migrations if no command is given
+    Migrate {
+        /// Only print the migrations which would be applied or reverted
+        #[arg(long, global = true)]
+        dry_run: bool,
+        #[command(subcommand)]
+        command: Option<MigrateCommand>,
+    },
+    /// Check the configuration and report all problems with it, then exit
+    CheckConfig,
+    /// Print the API specifications as JSON, one per line
+    GenerateOpenapi,
+    /// Grant or revoke administrator privileges, e.g. to create the first administrator
+    Admin {
+        #[command(subcommand)]
+        command: AdminCommand,
+    },
+    /// Write a backup of the database to a file, which any database backend can restore
+    Backup {
+        /// File to write
+        path: PathBuf,
+    },
+    /// Restore a backup into a new database, before the server is first started with it
+    Restore {
+        /// Backup file to read
+        path: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum AdminCommand {
+    /// Make a user an administrator
+    Grant {
+        /// Email address of the user
+        email: String,
+    },
+    /// Stop a user from being an administrator, and log them out everywhere
+    Revoke {
+        /// Email address of the user
+        email: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum MigrateCommand {
+    /// List the migrations and whether each has been applied
+    Status,
+    /// Create the database or migrate it to the current version
+    Up,
+    /// Revert the newest applied migration, or all migrations newer than a version
+    Down {
+        /// Version to revert to, which is kept applied
+        #[arg(long, value_name = "VERSION")]
+        to: Option<i64>,
+    },
 }
 
-mod defaults {
-    pub const STATIC_DIR: &str = "./ui/build";
-    pub const LISTEN_ADDR: &str = "0.0.0.0:3000";
+/// Settings which can be given as arguments, overriding both the environment and the
+/// configuration file
+// Doc comments are shown as help text, in which backticks would appear literally
+#[expect(clippy::doc_markdown)]
+#[derive(Args)]
+struct SettingArgs {
+    /// Configuration file to read (TOML, or YAML if it ends in .yaml or .yml)
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+    /// Address on which to listen [env: LISTEN_ADDR] [default: 0.0.0.0:3000]
+    #[arg(long, global = true, value_name = "ADDRESS")]
+    listen: Option<OsString>,
+    /// Directory containing the built UI [env: STATIC_DIR]
+    #[arg(long, global = true, value_name = "PATH")]
+    static_dir: Option<OsString>,
+    /// Database backend to use [env: DB_BACKEND]
+    #[arg(long, global = true, value_name = "BACKEND")]
+    db_backend: Option<OsString>,
+    /// Database file used by the sqlite3 backend [env: DB_PATH]
+    #[arg(long, global = true, value_name = "PATH")]
+    db_path: Option<OsString>,
+    /// Redis server in which to store sessions [env: REDIS_URL]
+    #[cfg(feature = "redis")]
+    #[arg(long, global = true, value_name = "URL")]
+    redis_url: Option<OsString>,
+}
+
+impl SettingArgs {
+    /// Returns the settings which were given, by name.
+    fn overrides(&self) -> Vec<(&'static str, OsString)> {
+        [
+            (vars::LISTEN_ADDR, &self.listen),
+            (vars::STATIC_DIR, &self.static_dir),
+            (vars::DB_BACKEND, &self.db_backend),
+            (vars::DB_PATH, &self.db_path),
+            #[cfg(feature = "redis")]
+            (vars::REDIS_URL, &self.redis_url),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value.clone()?)))
+        .collect()
+    }
 }
 
 #[tokio::main]
 async fn main() -> ExitCode {
     tracing_subscriber::fmt().init();
 
-    // Create server config
-    let origin = getenv_or_exit(vars::ORIGIN);
-    let parsed_origin = match Url::parse(&origin) {
-        Ok(origin) => origin,
-        Err(err) => {
-            error!(%origin, %err, "failed to create URL from given origin");
+    let cli = Cli::parse();
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(&cli.settings).await,
+        Command::Migrate { dry_run, command } => {
+            migrate(
+                &cli.settings,
+                command.unwrap_or(MigrateCommand::Up),
+                dry_run,
+            )
+            .await
+        }
+        Command::CheckConfig => check_config(&cli.settings),
+        Command::GenerateOpenapi => generate_openapi().await,
+        Command::Admin { command } => admin(&cli.settings, command).await,
+        Command::Backup { path } => backup(&cli.settings, &path).await,
+        Command::Restore { path } => restore(&cli.settings, &path).await,
+    }
+}
+
+/// Runs the server.
+async fn serve(settings: &SettingArgs) -> ExitCode {
+    // Read and validate the whole configuration before starting anything, so that all problems
+    // with it are reported at once
+    let config = match ServerConfig::load(settings.config.as_deref(), settings.overrides()) {
+        Ok(config) => config,
+        Err(problems) => {
+            report_problems(&problems);
             return ExitCode::FAILURE;
         }
     };
-    let config = AppConfig {
-        instance_name: match std::env::var(vars::SERVER_NAME) {
-            Ok(name) => name,
-            Err(VarError::NotPresent) => {
-                let default = parsed_origin.authority();
-                warn!(
-                    var = %vars::SERVER_NAME,
-                    %default,
-                    "variable not set; using default",
-                );
-                default.to_string()
+    set_uuid_version(config.uuid_version);
+    set_email_unicode_normalization(config.email_unicode_normalization);
+    // The listener is only bound once the database is open, so health checks fail and no
+    // requests are routed to the server while it waits for the database to come up
+    let Some(db) = open_db(&config.db).await else {
+        return ExitCode::FAILURE;
+    };
+    let db = wrap_cached_client(db, config.db.cache_ttl);
+    if let Some(keys) = config.keys
+        && !start_key_manager(keys).await
+    {
+        return ExitCode::FAILURE;
+    }
+    let Some(signing_keys) = load_signing_keys(&db, config.signing_keys).await else {
+        return ExitCode::FAILURE;
+    };
+    let mut session_cookies = config.session_cookies;
+    if session_cookies.keys.is_none() {
+        let Some(keys) = load_session_cookie_keys(&db).await else {
+            return ExitCode::FAILURE;
+        };
+        session_cookies.keys = Some(keys);
+    }
+    #[cfg(feature = "chaos")]
+    let db = wrap_chaos_client(db, config.chaos);
+
+    let default_party = RelyingPartyConfig {
+        origin: config.origin.clone(),
+        rp_id: config.rp_id.clone(),
+        name: config.app.instance_name.clone(),
+    };
+    let parties: Vec<_> = std::iter::once(default_party)
+        .chain(config.relying_parties)
+        .collect();
+    let Some(relying_parties) = build_relying_parties(&parties) else {
+        return ExitCode::FAILURE;
+    };
+    let Some(rate_limiter) = create_rate_limiter(config.rate_limit, &config.db).await else {
+        return ExitCode::FAILURE;
+    };
+
+    let (scheduler, mailer) = start_jobs(
+        &db,
+        config.retention,
+        config.maintenance,
+        config.email,
+        &signing_keys,
+    );
+    let options = ApiOptions {
+        alerter: create_alerter(config.alerts, mailer.clone()),
+        mailer,
+        login_notifications: config.login_notifications,
+        email_webhook_secret: config.email_webhook_secret,
+        user_deletion: config.user_deletion,
+        auto_ban: config.auto_ban,
+        lockout: config.lockout,
+        session_cache_ttl: config.session_cache_ttl,
+        session_cookies,
+        signing_keys: Some(signing_keys.keys()),
+        rate_limiter: Some(rate_limiter),
+        enumeration_protection: config.enumeration_protection,
+        passkey_policy: config.passkey_policy,
+        trusted_proxies: config.trusted_proxies,
+    };
+    let (api, _) = new_api_router(db, relying_parties, &config.app, options);
+
+    let ui = new_ui_server(&config.static_dir, &config.app, &config.ui);
+    let well_known = new_well_known_router(signing_keys.keys());
+    let router = create_router(api, well_known, ui, &config.app.base_path);
+
+    let listener = TcpListener::bind(config.listen_addr)
+        .await
+        .unwrap_or_exit(|err| {
+            error!(%err, address = %config.listen_addr, "failed to start listener");
+        });
+    info!(address = %config.listen_addr, "listening");
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap_or_exit(|err| {
+        error!(%err, "failed to start server");
+    });
+    scheduler.shutdown().await;
+
+    ExitCode::SUCCESS
+}
+
+/// Lists, applies, or reverts database migrations.
+// Allow lints that happen when all database backend features are disabled.
+#[allow(clippy::unused_async, unreachable_code, unused_variables)]
+async fn migrate(settings: &SettingArgs, command: MigrateCommand, dry_run: bool) -> ExitCode {
+    let db_settings = match DbSettings::load(settings.config.as_deref(), settings.overrides()) {
+        Ok(db_settings) => db_settings,
+        Err(problems) => {
+            report_problems(&problems);
+            return ExitCode::FAILURE;
+        }
+    };
+    let retry = &db_settings.connect_retry;
+    match &db_settings.backend {
+        #[cfg(feature = "sqlite3")]
+        DbBackend::Sqlite { path } => {
+            let open = || SqliteClient::open_path_with(path, PendingMigrations::Ignore);
+            match retry
+                .retry("database", open, CreateSqliteClientError::is_transient)
+                .await
+            {
+                Ok(db) => migrate_sqlite(&db, command, dry_run).await,
+                Err(err) => {
+                    error!(%err, "failed to open database");
+                    ExitCode::FAILURE
+                }
             }
-            Err(VarError::NotUnicode(_)) => {
-                error!(var = %vars::SERVER_NAME, "environment variable is not valid UTF-8");
-                return ExitCode::FAILURE;
+        }
+    }
+}
+
+/// Runs a migration command on the database of the sqlite3 backend.
+#[cfg(feature = "sqlite3")]
+async fn migrate_sqlite(db: &SqliteClient, command: MigrateCommand, dry_run: bool) -> ExitCode {
+    let result = match command {
+        MigrateCommand::Status => db.migrations().await.map_err(MigrationError::from),
+        MigrateCommand::Up => db.migrate_up(dry_run).await.map_err(MigrationError::from),
+        MigrateCommand::Down { to } => db.migrate_down(to, dry_run).await,
+    };
+    let migrations = match result {
+        Ok(migrations) => migrations,
+        Err(err) => {
+            error!(%err, "failed to migrate database");
+            return ExitCode::FAILURE;
+        }
+    };
+    let action = match (&command, dry_run) {
+        (MigrateCommand::Status, _) => None,
+        (MigrateCommand::Up, false) => Some("applied"),
+        (MigrateCommand::Up, true) => Some("would apply"),
+        (MigrateCommand::Down { .. }, false) => Some("reverted"),
+        (MigrateCommand::Down { .. }, true) => Some("would revert"),
+    };
+    for migration in &migrations {
+        let MigrationStatus {
+            version,
+            description,
+            ..
+        } = migration;
+        let action = action.unwrap_or(if migration.applied {
+            "applied"
+        } else {
+            "pending"
+        });
+        let reversible = if migration.reversible {
+            ""
+        } else {
+            " (irreversible)"
+        };
+        println!("{version} {action:<12} {description}{reversible}");
+    }
+    if matches!(command, MigrateCommand::Up) && migrations.is_empty() {
+        info!("database is up to date");
+    }
+    ExitCode::SUCCESS
+}
+
+/// Grants or revokes administrator privileges.
+async fn admin(settings: &SettingArgs, command: AdminCommand) -> ExitCode {
+    let Some(db) = open_configured_db(settings).await else {
+        return ExitCode::FAILURE;
+    };
+    let (AdminCommand::Grant { email } | AdminCommand::Revoke { email }) = &command;
+    let result = async {
+        let user = db.get_user_by_email(email).await?;
+        let changed = match &command {
+            AdminCommand::Grant { .. } => grant_admin(&*db, user.id()).await?,
+            AdminCommand::Revoke { .. } => revoke_admin(&*db, user.id()).await?,
+        };
+        Ok::<_, DatabaseError>((user, changed))
+    }
+    .await;
+    match result {
+        Ok((user, changed)) => {
+            let message = match (&command, changed) {
+                (AdminCommand::Grant { .. }, true) => "administrator privileges granted",
+                (AdminCommand::Grant { .. }, false) => "user is already an administrator",
+                (AdminCommand::Revoke { .. }, true) => "administrator privileges revoked",
+                (AdminCommand::Revoke { .. }, false) => "user is not an administrator",
+            };
+            if changed {
+                info!(user = %user.id(), %email, "{message} from the command line");
             }
-        },
+            println!("{email}: {message}");
+            ExitCode::SUCCESS
+        }
+        Err(DatabaseError::NotFound) => {
+            error!(%email, "no user has this email address; they must register first");
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            error!(%err, "failed to update administrator privileges");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Writes a backup of the database to the given file. The backup is written to a temporary file
+/// first, so that an existing backup is only replaced by a complete one.
+async fn backup(settings: &SettingArgs, path: &Path) -> ExitCode {
+    let Some(db) = open_configured_db(settings).await else {
+        return ExitCode::FAILURE;
+    };
+    let backup = match db.export_backup().await {
+        Ok(backup) => backup,
+        Err(err) => {
+            error!(%err, "failed to read the database");
+            return ExitCode::FAILURE;
+        }
     };
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+    let result = async {
+        let json = serde_json::to_vec(&backup).map_err(std::io::Error::other)?;
+        tokio::fs::write(&temp_path, json).await?;
+        tokio::fs::rename(&temp_path, path).await
+    }
+    .await;
+    if let Err(err) = result {
+        error!(%err, path = %path.display(), "failed to write backup");
+        _ = tokio::fs::remove_file(&temp_path).await;
+        return ExitCode::FAILURE;
+    }
+    info!(path = %path.display(), records = backup.len(), "backup written");
+    ExitCode::SUCCESS
+}
 
-    // Create database client
-    let db = match get_db_client().await {
-        Ok(db) => db,
-        Err(choice_str) => {
-            error!(choice = %choice_str, "invalid database backend choice");
+/// Restores the backup in the given file into the database, which must be empty.
+async fn restore(settings: &SettingArgs, path: &Path) -> ExitCode {
+    let backup = match tokio::fs::read(path).await {
+        Ok(json) => serde_json::from_slice::<Backup>(&json).map_err(std::io::Error::other),
+        Err(err) => Err(err),
+    };
+    let backup = match backup {
+        Ok(backup) => backup,
+        Err(err) => {
+            error!(%err, path = %path.display(), "failed to read backup");
             return ExitCode::FAILURE;
         }
     };
+    let Some(db) = open_configured_db(settings).await else {
+        return ExitCode::FAILURE;
+    };
+    if let Err(err) = db.import_backup(&backup).await {
+        error!(%err, "failed to restore backup");
+        return ExitCode::FAILURE;
+    }
+    info!(
+        path = %path.display(),
+        records = backup.len(),
+        created_at = %backup.created_at,
+        "backup restored",
+    );
+    ExitCode::SUCCESS
+}
+
+/// Opens the database for tasks other than running the server. Only the database settings are
+/// read, so this works before the rest of the configuration is ready.
+async fn open_configured_db(settings: &SettingArgs) -> Option<Arc<dyn DatabaseClient>> {
+    match DbSettings::load(settings.config.as_deref(), settings.overrides()) {
+        Ok(db) => open_db(&db).await,
+        Err(problems) => {
+            report_problems(&problems);
+            None
+        }
+    }
+}
 
-    // Create WebAuthn client
-    let rp_id = std::env::var(vars::RP_ID).unwrap_or_else(|err| match err {
-        VarError::NotPresent => parsed_origin.to_string(),
-        VarError::NotUnicode(_) => {
-            error!(var = %vars::RP_ID, "environment variable is not valid UTF-8");
-            std::process::exit(1);
+/// Reads the configuration and reports any problems with it, without starting anything.
+fn check_config(settings: &SettingArgs) -> ExitCode {
+    match ServerConfig::load(settings.config.as_deref(), settings.overrides()) {
+        Ok(_) => {
+            info!("configuration is valid");
+            ExitCode::SUCCESS
         }
+        Err(problems) => {
+            report_problems(&problems);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Logs the problems found in the configuration.
+fn report_problems(problems: &[ConfigProblem]) {
+    for problem in problems {
+        error!(setting = %problem.setting, "{}", problem.message);
+    }
+    error!(
+        count = problems.len(),
+        "invalid configuration; fix the problems above and restart",
+    );
+}
+
+/// Prints the API specifications generated from the handlers to the standard output.
+async fn generate_openapi() -> ExitCode {
+    aide::generate::on_error(|err| {
+        eprintln!("Error: {err}");
+        std::process::exit(1);
     });
-    info!(%rp_id, origin = %parsed_origin, "creating WebAuthn manager");
-    let webauthn = WebauthnBuilder::new(&rp_id, &parsed_origin)
-        .unwrap()
-        .rp_name(&config.instance_name)
-        .build()
-        .unwrap_or_exit(|err| error!(%err, "failed to build WebAuthn manager"));
-
-    let (api, _) = new_api_router(db, webauthn, &config);
-
-    let static_dir = PathBuf::from(std::env::var_os(vars::STATIC_DIR).unwrap_or_else(|| {
-        warn!(
-            var = %vars::STATIC_DIR,
-            default = %defaults::STATIC_DIR,
-            "variable not set; using default",
+    let specs = generate_api_specs().await;
+    for spec in specs.to_vec() {
+        println!("{}", serde_json::to_string(&spec).unwrap());
+    }
+    ExitCode::SUCCESS
+}
+
+/// Starts the background jobs which are enabled, including the email queue worker if email is
+/// configured and signing key rotation, coordinating them with other instances sharing the
+/// database. Returns the scheduler running the periodic jobs, and the mailer if email is
+/// configured.
+fn start_jobs(
+    db: &Arc<dyn DatabaseClient>,
+    retention: RetentionSettings,
+    maintenance: Option<MaintenanceSettings>,
+    email: Option<EmailSettings>,
+    signing_keys: &Arc<SigningKeyManager>,
+) -> (Scheduler, Option<Arc<Mailer>>) {
+    let coordinator = Arc::new(Coordinator::new(Arc::clone(db)));
+    info!(instance_id = %coordinator.instance_id(), "coordinating background jobs");
+    signing_keys.spawn(Arc::clone(&coordinator));
+    let scheduler = Scheduler::new().with_coordinator(Arc::clone(&coordinator));
+    scheduler.register(
+        Arc::new(CeremonyCleanupJob::new(Arc::clone(db))),
+        Schedule::every(CeremonyCleanupJob::MAX_AGE).jitter(Duration::from_secs(30)),
+    );
+    info!(
+        policy = ?retention.policy,
+        interval = ?retention.interval,
+        "enforcing data retention policy",
+    );
+    let job = RetentionJob::new(Arc::clone(db), retention.policy, retention.interval);
+    let schedule = job.schedule();
+    scheduler.register(Arc::new(job), schedule);
+    if let Some(maintenance) = maintenance {
+        info!(
+            tasks = ?maintenance.tasks,
+            interval = ?maintenance.interval,
+            "scheduling database maintenance",
         );
-        OsString::from(defaults::STATIC_DIR)
-    }));
-    let ui = new_ui_server(&static_dir);
+        let job = MaintenanceJob::new(Arc::clone(db), maintenance.tasks, maintenance.interval);
+        let schedule = job.schedule();
+        scheduler.register(Arc::new(job), schedule);
+    }
+    let mailer = email.map(|email| start_mailer(email, db, &coordinator));
+    (scheduler, mailer)
+}
 
-    let router = Router::new()
+/// Creates the mailer and starts the worker for its email queue.
+fn start_mailer(
+    email: EmailSettings,
+    db: &Arc<dyn DatabaseClient>,
+    coordinator: &Arc<Coordinator>,
+) -> Arc<Mailer> {
+    let queue = Arc::new(EmailQueue::new(
+        Arc::clone(db),
+        Arc::clone(&email.transport),
+        email.queue,
+    ));
+    queue.spawn_worker(Arc::clone(coordinator));
+    Arc::new(Mailer::new(email.transport, email.templates, email.from).with_queue(queue))
+}
+
+/// Loads and installs the encryption keys, then keeps them up to date. Returns `false` if the
+/// keys can't be loaded.
+async fn start_key_manager(keys: KeySettings) -> bool {
+    let manager = Arc::new(
+        KeyManager::new(keys.provider).on_rotate(|keyring| set_keyring(Some(Arc::clone(keyring)))),
+    );
+    if let Err(err) = manager.refresh().await {
+        error!(%err, "failed to load encryption keys");
+        return false;
+    }
+    if let Some(keyring) = manager.keyring() {
+        info!(key_id = %keyring.active_key_id(), "encrypting stored credentials");
+    }
+    if let Some(interval) = keys.refresh_interval {
+        manager.spawn(interval);
+    }
+    true
+}
+
+/// Creates the `WebAuthn` clients, the first of which is the default. Returns [`None`] if any
+/// relying party is invalid.
+fn build_relying_parties(parties: &[RelyingPartyConfig]) -> Option<RelyingParties> {
+    for party in parties {
+        info!(rp_id = %party.rp_id, origin = %party.origin, "creating WebAuthn manager");
+    }
+    RelyingParties::build(parties)
+        .map_err(|err| error!(%err, "failed to build WebAuthn manager"))
+        .ok()
+}
+
+/// Combines the API, `/.well-known`, and UI routers, serving them under the given base path.
+fn create_router(api: Router, well_known: Router, ui: Router, base_path: &str) -> Router {
+    let mut router = Router::new()
         .nest("/api", api)
-        .fallback_service(ui)
+        .merge(well_known)
+        .fallback_service(ui);
+    if !base_path.is_empty() {
+        info!(%base_path, "serving app under base path");
+        router = Router::new()
+            .nest(base_path, router)
+            // Nesting doesn't match the base path with a trailing slash, so redirect to the
+            // canonical form. Also redirect the root so the app is easy to find.
+            .route(
+                &format!("{base_path}/"),
+                get(Redirect::permanent(base_path)),
+            )
+            .route("/", get(Redirect::temporary(base_path)));
+    }
+    add_security_headers(router)
+}
+
+/// Adds security-related headers to all responses which don't already set them.
+fn add_security_headers(router: Router) -> Router {
+    router
         .layer(SetResponseHeaderLayer::if_not_present(
             X_CONTENT_TYPE_OPTIONS,
             HeaderValue::from_static("nosniff"),
@@ -117,39 +659,159 @@ async fn main() -> ExitCode {
         .layer(SetResponseHeaderLayer::if_not_present(
             CONTENT_SECURITY_POLICY,
             HeaderValue::from_static("frame-ancestors 'none'"),
-        ));
+        ))
+}
 
-    let listener = TcpListener::bind(defaults::LISTEN_ADDR)
-        .await
-        .unwrap_or_exit(|err| {
-            error!(%err, address = %defaults::LISTEN_ADDR, "failed to start listener");
-        });
-    axum::serve(listener, router).await.unwrap_or_exit(|err| {
-        error!(%err, "failed to start server");
-    });
+/// Loads the keys with which tokens are signed, generating the first key if there is none.
+/// Returns [`None`] if they can't be loaded.
+async fn load_signing_keys(
+    db: &Arc<dyn DatabaseClient>,
+    options: SigningKeyOptions,
+) -> Option<Arc<SigningKeyManager>> {
+    match SigningKeyManager::load(Arc::clone(db), options).await {
+        Ok(manager) => Some(Arc::new(manager)),
+        Err(err) => {
+            error!(%err, "failed to load token signing keys");
+            None
+        }
+    }
+}
 
-    ExitCode::SUCCESS
+/// Loads the key with which session cookies are signed from the database, for when no keys are
+/// configured. Returns [`None`] if it can't be loaded.
+async fn load_session_cookie_keys(db: &Arc<dyn DatabaseClient>) -> Option<Arc<SessionCookieKeys>> {
+    match SessionCookieKeys::load(db.as_ref()).await {
+        Ok(keys) => Some(Arc::new(keys)),
+        Err(err) => {
+            error!(%err, "failed to load session cookie key");
+            None
+        }
+    }
+}
+
+/// Creates the security alerter. Returns [`None`] if no alert recipients or webhook are
+/// configured.
+fn create_alerter(options: AlertOptions, mailer: Option<Arc<Mailer>>) -> Option<Arc<Alerter>> {
+    if options.email_recipients.is_empty() && options.webhook_url.is_none() {
+        return None;
+    }
+    Some(Arc::new(Alerter::new(options, mailer)))
 }
 
-/// Calls [`std::env::var(name)`][std::env::var] and if that fails, exits the program after printing an error message.
-fn getenv_or_exit(name: &str) -> String {
-    std::env::var(name).unwrap_or_exit(|_| {
-        error!(var = %name, "environment variable is not set");
-    })
+/// Creates the rate limiter for login and registration requests. Requests are counted in the
+/// configured Redis server if there is one, so that the limits apply across instances, and in
+/// memory otherwise. Returns [`None`] if Redis can't be reached.
+// Allow lints that happen when the `redis` feature is disabled.
+#[allow(clippy::unused_async, unused_variables)]
+async fn create_rate_limiter(
+    options: RateLimitOptions,
+    db_settings: &DbSettings,
+) -> Option<Arc<RateLimiter>> {
+    #[cfg(feature = "redis")]
+    if let Some(redis) = &db_settings.redis {
+        return match RedisStore::connect(&redis.url, &redis.key_prefix).await {
+            Ok(store) => Some(Arc::new(RateLimiter::new(Arc::new(store), options))),
+            Err(err) => {
+                error!(%err, "failed to connect to Redis");
+                None
+            }
+        };
+    }
+    Some(Arc::new(RateLimiter::new(
+        Arc::new(MemoryStore::new()),
+        options,
+    )))
 }
 
+/// Opens the configured database, wrapping it in any configured stores. Failed connections are
+/// retried as configured. Returns [`None`] if the database can't be opened.
 // Allow lints that happen when all database backend features are disabled.
-#[allow(clippy::unused_async, unused_variables, unreachable_code)]
-async fn get_db_client() -> Result<Arc<dyn DatabaseClient>, String> {
-    let db_choice = getenv_or_exit(vars::DB_BACKEND);
-    let db: Arc<dyn DatabaseClient> = match db_choice.as_str() {
+#[allow(clippy::unused_async, unreachable_code)]
+async fn open_db(settings: &DbSettings) -> Option<Arc<dyn DatabaseClient>> {
+    let retry = &settings.connect_retry;
+    let db: Arc<dyn DatabaseClient> = match &settings.backend {
         #[cfg(feature = "sqlite3")]
-        "sqlite3" | "sqlite" => Arc::new(SqliteClient::open().await.unwrap_or_exit(|err| {
-            error!(%err, "failed to open database");
-        })),
-        _ => return Err(db_choice),
+        DbBackend::Sqlite { path } => match retry
+            .retry(
+                "database",
+                || SqliteClient::open_path_with(path, pending_migrations(settings)),
+                CreateSqliteClientError::is_transient,
+            )
+            .await
+        {
+            Ok(db) => Arc::new(db),
+            Err(err) => {
+                error!(%err, "failed to open database");
+                return None;
+            }
+        },
+    };
+    #[cfg(feature = "redis")]
+    if let Some(redis) = &settings.redis {
+        return wrap_redis_client(db, redis, retry).await;
+    }
+    Some(db)
+}
+
+/// Returns what to do with pending migrations when the server or an administrative task opens the
+/// database.
+#[cfg(feature = "sqlite3")]
+fn pending_migrations(settings: &DbSettings) -> PendingMigrations {
+    if settings.require_migrated {
+        PendingMigrations::Refuse
+    } else {
+        PendingMigrations::Apply
+    }
+}
+
+/// Wraps the database client in a [`RedisSessionClient`], which keeps sessions and passkey
+/// ceremony state in the configured Redis server.
+#[cfg(feature = "redis")]
+async fn wrap_redis_client(
+    db: Arc<dyn DatabaseClient>,
+    settings: &RedisSettings,
+    retry: &ConnectRetryOptions,
+) -> Option<Arc<dyn DatabaseClient>> {
+    let prefix = &settings.key_prefix;
+    let connect = || RedisSessionClient::connect(Arc::clone(&db), &settings.url, prefix);
+    let is_transient = |err: &redis::RedisError| {
+        err.is_io_error() || err.is_connection_refusal() || err.is_timeout()
     };
-    Ok(db)
+    match retry.retry("Redis", connect, is_transient).await {
+        Ok(client) => {
+            // Don't log the URL, since it may contain a password
+            info!(%prefix, "storing sessions in Redis");
+            Some(Arc::new(client))
+        }
+        Err(err) => {
+            error!(%err, "failed to connect to Redis");
+            None
+        }
+    }
+}
+
+/// Wraps the database client in a [`CachedClient`] unless caching is disabled.
+fn wrap_cached_client(db: Arc<dyn DatabaseClient>, ttl: Duration) -> Arc<dyn DatabaseClient> {
+    if ttl.is_zero() {
+        return db;
+    }
+    Arc::new(CachedClient::new(db, ttl))
+}
+
+/// Wraps the database client in a [`ChaosClient`] if the options enable fault injection.
+#[cfg(feature = "chaos")]
+fn wrap_chaos_client(
+    db: Arc<dyn DatabaseClient>,
+    options: ChaosOptions,
+) -> Arc<dyn DatabaseClient> {
+    if options.error_rate <= 0.0 && options.max_latency.max(options.min_latency).is_zero() {
+        return db;
+    }
+    warn!(
+        ?options,
+        "injecting database faults; do not use this in production"
+    );
+    Arc::new(ChaosClient::new(db, options))
 }
 
 trait UnwrapOrExit<T, E> {
@@ -0,0 +1,83 @@
+//! # Database maintenance
+//!
+//! A [`MaintenanceJob`] periodically runs [maintenance tasks][MaintenanceTask] such as updating
+//! the query planner's statistics or reclaiming unused space, so that long-running databases
+//! don't slow down or grow without bounds. The duration of each task is logged.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tracing::{error, info};
+
+use crate::{
+    db::interface::{DatabaseClient, DatabaseError},
+    jobs::{Job, JobError, Schedule},
+    models::MaintenanceTask,
+};
+
+/// # Scheduled database maintenance job
+///
+/// See the [module-level documentation][self] for details.
+pub struct MaintenanceJob {
+    db: Arc<dyn DatabaseClient>,
+    tasks: Vec<MaintenanceTask>,
+    interval: Duration,
+}
+
+impl MaintenanceJob {
+    /// Creates a job which runs the given tasks, in order, every `interval`.
+    #[must_use]
+    pub fn new(
+        db: Arc<dyn DatabaseClient>,
+        tasks: Vec<MaintenanceTask>,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            db,
+            tasks,
+            interval,
+        }
+    }
+
+    /// Returns the schedule of the job: every interval, starting after one interval so that
+    /// restarts don't trigger expensive tasks. When several instances share the database, only
+    /// one of them runs it.
+    #[must_use]
+    pub fn schedule(&self) -> Schedule {
+        Schedule::every(self.interval).coordinated()
+    }
+
+    /// Runs all tasks in order, stopping at the first failure. Returns how long each task took.
+    pub async fn run(&self) -> Result<Vec<(MaintenanceTask, Duration)>, DatabaseError> {
+        let mut durations = Vec::with_capacity(self.tasks.len());
+        for &task in &self.tasks {
+            let start = Instant::now();
+            let result = self.db.run_maintenance(task).await;
+            let duration = start.elapsed();
+            if let Err(err) = result {
+                error!(%err, %task, ?duration, "database maintenance task failed");
+                return Err(err);
+            }
+            info!(%task, ?duration, "database maintenance task finished");
+            durations.push((task, duration));
+        }
+        Ok(durations)
+    }
+}
+
+impl Job for MaintenanceJob {
+    fn name(&self) -> &'static str {
+        "maintenance"
+    }
+
+    fn run(&self) -> Pin<Box<dyn Future<Output = Result<(), JobError>> + Send + '_>> {
+        Box::pin(async move {
+            MaintenanceJob::run(self).await?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,43 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// How prominently an [`Announcement`] is displayed
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "kebab-case")]
+#[repr(u8)]
+pub enum AnnouncementSeverity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+/// # Instance announcement
+///
+/// A message from the operators which is shown to all users in the login UI, e.g. to warn about
+/// a maintenance window. There is at most one announcement at a time.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct Announcement {
+    pub message: String,
+    pub severity: AnnouncementSeverity,
+    /// Time from which the announcement is shown, or [`None`] to show it immediately
+    pub starts_at: Option<DateTime<Utc>>,
+    /// Time after which the announcement is no longer shown, or [`None`] to show it until it is
+    /// removed
+    pub ends_at: Option<DateTime<Utc>>,
+    /// Time at which the announcement was last changed
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Announcement {
+    /// Returns whether the announcement should be shown at the given time.
+    #[must_use]
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.starts_at.is_none_or(|starts_at| starts_at <= now)
+            && self.ends_at.is_none_or(|ends_at| now < ends_at)
+    }
+}
@@ -0,0 +1,63 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::{EncodableHash, ViaJson};
+
+/// Permission granted to an [`ApiToken`]
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum ApiTokenScope {
+    /// Allows requests which don't modify anything, i.e. `GET` and `HEAD` requests
+    Read,
+    /// Allows requests which modify data
+    Write,
+}
+
+/// # API token
+///
+/// A personal access token with which programmatic clients can call the API on behalf of a user,
+/// by sending it in an `Authorization: Bearer <token>` header. Only a hash of the token is stored.
+///
+/// API tokens never grant administrator privileges.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct ApiToken {
+    /// Unique ID
+    pub id: Uuid,
+    /// UUID of the [`User`][super::User] to which this token belongs
+    #[serde(skip)]
+    pub user_id: Uuid,
+    /// Name given to the token by its owner
+    pub name: String,
+    /// [`blake3`] hash of the token
+    #[serde(skip)]
+    pub token_hash: EncodableHash,
+    /// Permissions granted to the token
+    pub scopes: ViaJson<Vec<ApiTokenScope>>,
+    /// Time at which the token was created
+    pub created_at: DateTime<Utc>,
+    /// Time at which the token expires, if it does
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl ApiToken {
+    /// Returns whether the token has expired.
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at < Utc::now())
+    }
+
+    /// Returns whether the token has been granted the given scope. The
+    /// [`Write`][ApiTokenScope::Write] scope implies [`Read`][ApiTokenScope::Read].
+    #[must_use]
+    pub fn has_scope(&self, scope: ApiTokenScope) -> bool {
+        self.scopes.contains(&scope)
+            || (scope == ApiTokenScope::Read && self.scopes.contains(&ApiTokenScope::Write))
+    }
+}
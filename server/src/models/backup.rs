@@ -0,0 +1,49 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Version of the backup format written by this version of the server
+pub const BACKUP_VERSION: u32 = 1;
+
+/// # Logical backup of the database
+///
+/// Written by [`DatabaseClient::export_backup()`][1] and restored by
+/// [`DatabaseClient::import_backup()`][2]. Records are grouped by the kind of entity they hold,
+/// e.g. `users` or `users_tags`, and map field names to values. Values are stored as JSON
+/// scalars: text as strings; integers, timestamps (in seconds since the Unix epoch), and booleans
+/// (as `0` or `1`) as numbers; and binary values, including UUIDs, as base64 strings. Backends map
+/// kinds and fields onto their own schema, so a backup taken from one backend can be restored into
+/// another.
+///
+/// Short-lived state, such as passkey ceremonies and authorization codes, isn't backed up.
+///
+/// [1]: crate::db::interface::DatabaseClient::export_backup
+/// [2]: crate::db::interface::DatabaseClient::import_backup
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Backup {
+    /// Version of the backup format; see [`BACKUP_VERSION`]
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    /// Records of each kind of entity
+    pub entities: BTreeMap<String, Vec<BackupRecord>>,
+}
+
+/// A record in a [`Backup`], mapping field names to values
+pub type BackupRecord = Map<String, Value>;
+
+impl Backup {
+    /// Returns the total number of records in the backup.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entities.values().map(Vec::len).sum()
+    }
+
+    /// Returns `true` if the backup holds no records.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
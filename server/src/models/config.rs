@@ -9,4 +9,322 @@ use serde::{Deserialize, Serialize};
 pub struct AppConfig {
     /// Name of this IAM server instance, used as a title in the UI
     pub instance_name: String,
+    /// Path prefix under which the app is served, e.g. `/iam`. Empty if the app is served from the
+    /// root of its origin.
+    pub base_path: String,
+    /// Customizations of the UI's appearance
+    #[serde(default)]
+    pub branding: Branding,
+    /// Optional capabilities which are enabled, so the UI can hide those which aren't
+    #[serde(default)]
+    pub features: FeatureFlags,
+    /// Who may create new accounts
+    #[serde(default)]
+    pub registration_mode: RegistrationMode,
+    /// Domains of the email addresses with which users may register themselves, in lowercase.
+    /// Empty if addresses at any domain are allowed.
+    #[serde(default)]
+    pub allowed_email_domains: Vec<String>,
+    /// How long new sessions last
+    #[serde(default)]
+    pub session_lifetimes: SessionLifetimes,
+}
+
+impl AppConfig {
+    /// Returns whether users may register themselves with the given email address, according to
+    /// the [allowed domains][Self::allowed_email_domains]. Domains are matched exactly, so
+    /// subdomains of allowed domains aren't allowed.
+    #[must_use]
+    pub fn is_email_domain_allowed(&self, email: &str) -> bool {
+        if self.allowed_email_domains.is_empty() {
+            return true;
+        }
+        email.rsplit_once('@').is_some_and(|(_, domain)| {
+            self.allowed_email_domains
+                .iter()
+                .any(|allowed| domain.trim().eq_ignore_ascii_case(allowed))
+        })
+    }
+}
+
+/// # Session lifetimes
+///
+/// Sessions upgraded to administrator privileges usually have a shorter lifetime than regular
+/// sessions, to limit the damage a stolen administrator session can do.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionLifetimes {
+    /// Lifetime of regular sessions, in seconds
+    pub session_secs: u64,
+    /// Lifetime of sessions with administrator privileges, in seconds
+    pub admin_session_secs: u64,
+}
+
+impl SessionLifetimes {
+    /// Returns the lifetime of new sessions with the given privileges.
+    #[must_use]
+    pub fn for_session(&self, is_admin: bool) -> chrono::Duration {
+        let secs = if is_admin {
+            self.admin_session_secs
+        } else {
+            self.session_secs
+        };
+        i64::try_from(secs)
+            .ok()
+            .and_then(chrono::Duration::try_seconds)
+            .unwrap_or(chrono::Duration::MAX)
+    }
+}
+
+impl Default for SessionLifetimes {
+    /// Returns lifetimes of a day for regular sessions and an hour for administrator sessions.
+    fn default() -> Self {
+        Self {
+            session_secs: 24 * 60 * 60,
+            admin_session_secs: 60 * 60,
+        }
+    }
+}
+
+/// Who may create new accounts
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegistrationMode {
+    /// Anyone may register, subject to the allowed email domains
+    #[default]
+    Open,
+    /// Only administrators may create accounts
+    InviteOnly,
+    /// No new accounts may be created through the API
+    Closed,
+}
+
+impl std::str::FromStr for RegistrationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(Self::Open),
+            "invite-only" => Ok(Self::InviteOnly),
+            "closed" => Ok(Self::Closed),
+            _ => Err(format!(
+                "invalid registration mode `{s}`; expected `open`, `invite-only`, or `closed`"
+            )),
+        }
+    }
+}
+
+/// # Feature flags
+///
+/// Indicates which optional capabilities are enabled on this server.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::struct_excessive_bools)] // The flags are independent
+pub struct FeatureFlags {
+    /// Whether new users can register
+    pub registration: bool,
+    /// Whether users can log in with a link sent to their email address
+    pub magic_links: bool,
+    /// Whether users can use time-based one-time passwords as a second factor
+    pub totp: bool,
+    /// Whether this server acts as an `OpenID` Connect provider
+    pub oidc: bool,
+    /// Whether the interactive API documentation is served
+    pub docs: bool,
+}
+
+impl Default for FeatureFlags {
+    /// Returns the flags for a server with the default configuration, i.e. with open registration.
+    /// The flags which depend on how the server was built are set when the configuration is read.
+    fn default() -> Self {
+        Self {
+            registration: true,
+            magic_links: false,
+            totp: false,
+            oidc: false,
+            docs: false,
+        }
+    }
+}
+
+/// # Branding options
+///
+/// Lets operators customize the UI without modifying it. Every field is optional; the UI's
+/// defaults are used for fields which aren't set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Branding {
+    /// URL of a logo image, shown instead of the default icon. Must be an `https://` URL or an
+    /// absolute path on this server.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logo_url: Option<String>,
+    /// Primary theme color, as a hex color (e.g. `#1d4ed8`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary_color: Option<String>,
+    /// Accent theme color, as a hex color
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accent_color: Option<String>,
+    /// Address users can contact for help
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub support_email: Option<String>,
+    /// URL of the privacy policy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub privacy_policy_url: Option<String>,
+    /// URL of the terms of service
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub terms_url: Option<String>,
+    /// Message shown on the login page, e.g. to say who may use the service
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub login_message: Option<String>,
+}
+
+impl Branding {
+    /// Checks that the URLs and colors are in the accepted formats, since they are used in the
+    /// UI's markup and styles.
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(url) = &self.logo_url
+            && !(url.starts_with("https://") || (url.starts_with('/') && !url.starts_with("//")))
+        {
+            return Err(format!(
+                "invalid logo URL `{url}`; expected an `https://` URL or an absolute path"
+            ));
+        }
+        for url in [&self.privacy_policy_url, &self.terms_url]
+            .into_iter()
+            .flatten()
+        {
+            if !(url.starts_with("https://") || url.starts_with("http://")) {
+                return Err(format!("invalid link URL `{url}`; expected an HTTP(S) URL"));
+            }
+        }
+        for color in [&self.primary_color, &self.accent_color]
+            .into_iter()
+            .flatten()
+        {
+            let is_hex_color = color.strip_prefix('#').is_some_and(|hex| {
+                matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit())
+            });
+            if !is_hex_color {
+                return Err(format!(
+                    "invalid color `{color}`; expected a hex color such as `#1d4ed8`"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// When to email users about successful logins to their account
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoginNotificationPolicy {
+    /// Never send login notifications
+    #[default]
+    Off,
+    /// Notify users of every login
+    Always,
+    /// Notify users only of logins from devices they haven't logged in from before
+    NewDevice,
+}
+
+impl std::str::FromStr for LoginNotificationPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "always" => Ok(Self::Always),
+            "new-device" => Ok(Self::NewDevice),
+            _ => Err(format!(
+                "invalid login notification policy `{s}`; expected `off`, `always`, or `new-device`"
+            )),
+        }
+    }
+}
+
+/// What happens to a user's data when they are deleted
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum UserDeletionStrategy {
+    /// Delete the user and everything belonging to them
+    #[default]
+    Delete,
+    /// Scrub the user's personal data but keep their record, preserving references to it and
+    /// aggregate statistics. See [`DatabaseClient::anonymize_user()`][1].
+    ///
+    /// [1]: crate::db::interface::DatabaseClient::anonymize_user
+    Anonymize,
+}
+
+impl std::str::FromStr for UserDeletionStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "delete" => Ok(Self::Delete),
+            "anonymize" => Ok(Self::Anonymize),
+            _ => Err(format!(
+                "invalid user deletion strategy `{s}`; expected `delete` or `anonymize`"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_branding() {
+        let valid = Branding {
+            logo_url: Some("/static/logo.svg".to_string()),
+            primary_color: Some("#1d4ed8".to_string()),
+            accent_color: Some("#fff".to_string()),
+            privacy_policy_url: Some("https://example.com/privacy".to_string()),
+            ..Branding::default()
+        };
+        assert!(valid.validate().is_ok());
+        assert!(Branding::default().validate().is_ok());
+
+        for invalid in [
+            Branding {
+                logo_url: Some("javascript:alert(1)".to_string()),
+                ..Branding::default()
+            },
+            Branding {
+                logo_url: Some("//example.com/logo.svg".to_string()),
+                ..Branding::default()
+            },
+            Branding {
+                primary_color: Some("red; background: url(x)".to_string()),
+                ..Branding::default()
+            },
+            Branding {
+                terms_url: Some("javascript:alert(1)".to_string()),
+                ..Branding::default()
+            },
+        ] {
+            assert!(invalid.validate().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn test_is_email_domain_allowed() {
+        let mut config = AppConfig {
+            instance_name: "IAM".to_string(),
+            base_path: String::new(),
+            branding: Branding::default(),
+            features: FeatureFlags::default(),
+            registration_mode: RegistrationMode::Open,
+            allowed_email_domains: Vec::new(),
+            session_lifetimes: SessionLifetimes::default(),
+        };
+        assert!(config.is_email_domain_allowed("user@example.com"));
+        config.allowed_email_domains = vec!["kasad.com".to_string()];
+        assert!(config.is_email_domain_allowed("user@kasad.com"));
+        assert!(config.is_email_domain_allowed("user@Kasad.COM"));
+        assert!(!config.is_email_domain_allowed("user@example.com"));
+        assert!(!config.is_email_domain_allowed("user@mail.kasad.com"));
+        assert!(!config.is_email_domain_allowed("kasad.com"));
+    }
 }
@@ -0,0 +1,141 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Delivery state of a [`QueuedEmail`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "kebab-case")]
+#[repr(u8)]
+pub enum QueuedEmailState {
+    /// Email is waiting to be (re)sent
+    Pending,
+    /// Delivery failed permanently or too many times; the email won't be retried
+    Dead,
+}
+
+/// # Queued outgoing email
+///
+/// A rendered email waiting in the outgoing queue. Emails are removed from the queue once
+/// delivered.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedEmail {
+    /// Unique ID
+    pub id: Uuid,
+    /// Sender address
+    pub sender: String,
+    /// Recipient address
+    pub recipient: String,
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+    /// Delivery state
+    pub state: QueuedEmailState,
+    /// Number of failed delivery attempts so far
+    pub attempts: u32,
+    /// Error message from the last failed delivery attempt, if any
+    pub last_error: Option<String>,
+    /// Time at which the email was queued
+    pub created_at: DateTime<Utc>,
+    /// Time after which the next delivery attempt should be made
+    pub next_attempt_at: DateTime<Utc>,
+}
+
+/// Data used to update a queued email
+///
+/// Fields with a value will replace the corresponding field's value in the [`QueuedEmail`]
+/// to which the update is applied (via [`DatabaseClient::update_queued_email()`][1]).
+///
+/// [1]: crate::db::interface::DatabaseClient::update_queued_email
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct QueuedEmailUpdate {
+    pub state: Option<QueuedEmailState>,
+    pub attempts: Option<u32>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+}
+
+impl QueuedEmailUpdate {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_state(mut self, state: QueuedEmailState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    #[must_use]
+    pub fn with_attempts(mut self, attempts: u32) -> Self {
+        self.attempts = Some(attempts);
+        self
+    }
+
+    #[must_use]
+    pub fn with_last_error(mut self, last_error: String) -> Self {
+        self.last_error = Some(last_error);
+        self
+    }
+
+    #[must_use]
+    pub fn with_next_attempt_at(mut self, next_attempt_at: DateTime<Utc>) -> Self {
+        self.next_attempt_at = Some(next_attempt_at);
+        self
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.state.is_none()
+            && self.attempts.is_none()
+            && self.last_error.is_none()
+            && self.next_attempt_at.is_none()
+    }
+}
+
+/// # Email suppression list entry
+///
+/// Emails are not sent to suppressed addresses, e.g. because they bounced.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct EmailSuppression {
+    /// Suppressed email address
+    pub address: String,
+    /// Why the address was suppressed, e.g. the bounce message
+    pub reason: String,
+    /// Time at which the address was suppressed
+    pub created_at: DateTime<Utc>,
+}
+
+/// Deliverability of a [`User`][super::User]'s email address
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum EmailStatus {
+    /// Emails can be sent to the address
+    Deliverable,
+    /// The address is on the suppression list, so emails are not sent to it
+    #[serde(rename_all = "camelCase")]
+    Undeliverable {
+        /// Why the address was suppressed
+        reason: String,
+        /// Time at which the address was suppressed
+        since: DateTime<Utc>,
+    },
+}
+
+impl From<Option<EmailSuppression>> for EmailStatus {
+    fn from(suppression: Option<EmailSuppression>) -> Self {
+        match suppression {
+            Some(suppression) => Self::Undeliverable {
+                reason: suppression.reason,
+                since: suppression.created_at,
+            },
+            None => Self::Deliverable,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::{EncodableHash, ViaJson};
+
+/// # Federated identity provider
+///
+/// An upstream OIDC provider, such as Google Workspace, with which users can log in instead of
+/// using a passkey. Providers are registered by administrators, with client credentials obtained
+/// from the provider.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct FederatedProvider {
+    /// Unique ID
+    pub id: Uuid,
+    /// Unique short name of the provider, used in the URLs of its login endpoints
+    pub slug: String,
+    /// Name of the provider, shown on the login page
+    pub name: String,
+    /// Issuer identifier of the provider, from which its endpoints are discovered
+    pub issuer: String,
+    /// Client ID issued by the provider
+    pub client_id: String,
+    /// Client secret issued by the provider
+    #[serde(skip)]
+    pub client_secret: ViaJson<String>,
+    /// Whether a new [`User`][super::User] is created when someone logs in for the first time
+    /// with an identity which can't be linked to an existing user
+    pub auto_provision: bool,
+    /// Time at which the provider was registered
+    pub created_at: DateTime<Utc>,
+    /// Time at which the provider was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Data used to update a [`FederatedProvider`]
+///
+/// Fields with a value will replace the corresponding field's value in the [`FederatedProvider`]
+/// to which the update is applied (via [`DatabaseClient::update_federated_provider()`][1]).
+///
+/// [1]: crate::db::interface::DatabaseClient::update_federated_provider
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FederatedProviderUpdate {
+    pub name: Option<String>,
+    pub issuer: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub auto_provision: Option<bool>,
+}
+
+impl FederatedProviderUpdate {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    #[must_use]
+    pub fn with_issuer(mut self, issuer: String) -> Self {
+        self.issuer = Some(issuer);
+        self
+    }
+
+    #[must_use]
+    pub fn with_client_id(mut self, client_id: String) -> Self {
+        self.client_id = Some(client_id);
+        self
+    }
+
+    #[must_use]
+    pub fn with_client_secret(mut self, client_secret: String) -> Self {
+        self.client_secret = Some(client_secret);
+        self
+    }
+
+    #[must_use]
+    pub fn with_auto_provision(mut self, auto_provision: bool) -> Self {
+        self.auto_provision = Some(auto_provision);
+        self
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.issuer.is_none()
+            && self.client_id.is_none()
+            && self.client_secret.is_none()
+            && self.auto_provision.is_none()
+    }
+}
+
+/// # Federated identity
+///
+/// Links an account at a [`FederatedProvider`] to a [`User`][super::User], so that logging in with
+/// the account logs in as the user.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct FederatedIdentity {
+    /// UUID of the [`FederatedProvider`]
+    pub provider_id: Uuid,
+    /// Identifier of the account at the provider, i.e. the `sub` claim of its ID tokens
+    pub subject: String,
+    /// UUID of the linked [`User`][super::User]
+    pub user_id: Uuid,
+    /// Email address of the account at the time it was linked
+    pub email: Option<String>,
+    /// Time at which the account was linked
+    pub created_at: DateTime<Utc>,
+}
+
+/// # Federated login state
+///
+/// Stored when a user is sent to a [`FederatedProvider`] to log in, and consumed when they return.
+/// The state can only be used once, and expires shortly.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct FederatedLoginState {
+    /// [`blake3`] hash of the `state` parameter sent to the provider
+    pub state_hash: EncodableHash,
+    /// UUID of the [`FederatedProvider`]
+    pub provider_id: Uuid,
+    /// Value of the `nonce` parameter sent to the provider, which must be repeated in the ID token
+    pub nonce: String,
+    /// PKCE code verifier, which is sent along with the authorization code
+    pub code_verifier: String,
+    /// UUID of the logged-in [`User`][super::User] to whom the identity should be linked, if the
+    /// user started the login to link an account
+    pub link_user_id: Option<Uuid>,
+    /// Path to which the user is sent after logging in
+    pub next: Option<String>,
+    /// Time at which the state expires
+    pub expires_at: DateTime<Utc>,
+}
@@ -0,0 +1,63 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::{MAX_NAME_LENGTH, not_blank};
+
+/// # Group model
+///
+/// A group collects [`User`][super::User]s, e.g. the members of a team, so that they can be
+/// managed together. Unlike [tags][super::Tag], which grant privileges, groups only describe who
+/// belongs together: tags can be applied to a group, and then apply to all of its members.
+///
+/// Groups can contain other groups. The members of a subgroup are also members of the groups
+/// which contain it, so they get the tags of those groups too.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct Group {
+    /// Unique identifier
+    pub id: Uuid,
+    /// Group name (must also be unique)
+    pub name: String,
+    /// Time at which the group was created
+    pub created_at: DateTime<Utc>,
+    /// Time at which the group was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Data used to create or update a group
+///
+/// Fields with a value will replace the corresponding field's value in the [`Group`] to which the
+/// update is applied (via [`DatabaseClient::update_group()`][1]).
+///
+/// [1]: crate::db::interface::DatabaseClient::update_group
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupUpdate {
+    #[validate(
+        custom(function = not_blank),
+        length(max = MAX_NAME_LENGTH, message = "must be at most 100 characters long")
+    )]
+    pub name: Option<String>,
+}
+
+impl GroupUpdate {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+    }
+}
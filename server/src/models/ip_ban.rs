@@ -0,0 +1,37 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// How an [`IpBan`] was created
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "kebab-case")]
+#[repr(u8)]
+pub enum IpBanSource {
+    /// Added by an administrator
+    Manual,
+    /// Added after too many failed logins from the address
+    Automatic,
+}
+
+/// # Banned IP address or network
+///
+/// Requests from clients in a banned network are rejected before they reach any endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct IpBan {
+    /// Unique ID
+    pub id: Uuid,
+    /// Banned network in CIDR notation, e.g. `192.0.2.0/24`. Single addresses have a prefix
+    /// length of 32 (IPv4) or 128 (IPv6).
+    pub network: String,
+    /// Why the network was banned
+    pub reason: String,
+    pub source: IpBanSource,
+    /// Time at which the ban was created
+    pub created_at: DateTime<Utc>,
+    /// Time at which the ban is lifted, or [`None`] if it is permanent
+    pub expires_at: Option<DateTime<Utc>>,
+}
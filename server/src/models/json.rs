@@ -9,8 +9,9 @@ use serde::{Deserialize, Serialize};
 /// to the wrapped type's implementation.
 ///
 /// Depending on database features enabled, [`sqlx::Decode`], [`sqlx::Encode`], and [`sqlx::Type`]
-/// are implemented by delegating to [`sqlx::types::Json<&T>`]'s implementation.
-#[derive(Debug, Clone, Copy)]
+/// are implemented by storing the JSON as text. The text is [encrypted][crate::crypto] if a
+/// keyring is installed, since the wrapped values are usually credential material.
+#[derive(Debug, Clone, Copy, Default)]
 pub struct ViaJson<T>(pub T);
 
 impl<T> From<T> for ViaJson<T> {
@@ -79,44 +80,47 @@ where
     }
 }
 
-/// Delegate decoding to [`sqlx::types::Json`].
+/// Decode from (possibly encrypted) JSON text.
 #[cfg(feature = "sqlx")]
 impl<'r, T, DB> sqlx::Decode<'r, DB> for ViaJson<T>
 where
-    sqlx::types::Json<T>: sqlx::Decode<'r, DB>,
+    T: serde::de::DeserializeOwned,
+    String: sqlx::Decode<'r, DB>,
     DB: sqlx::Database,
 {
     fn decode(
         value: <DB as sqlx::Database>::ValueRef<'r>,
     ) -> Result<Self, sqlx::error::BoxDynError> {
-        let sqlx_json = <sqlx::types::Json<T> as sqlx::Decode<'r, DB>>::decode(value)?;
-        Ok(ViaJson(sqlx_json.0))
+        let stored = <String as sqlx::Decode<'r, DB>>::decode(value)?;
+        let json = crate::crypto::decrypt_stored(&stored)?;
+        Ok(ViaJson(serde_json::from_str(&json)?))
     }
 }
 
-/// Delegate type to [`sqlx::types::Json`].
+/// Store as text.
 #[cfg(feature = "sqlx")]
 impl<T, DB: sqlx::Database> sqlx::Type<DB> for ViaJson<T>
 where
-    sqlx::types::Json<T>: sqlx::Type<DB>,
+    String: sqlx::Type<DB>,
 {
     fn type_info() -> DB::TypeInfo {
-        <sqlx::types::Json<T> as sqlx::Type<DB>>::type_info()
+        <String as sqlx::Type<DB>>::type_info()
     }
 }
 
-/// Delegate encoding to [`sqlx::types::Json`].
+/// Encode as (possibly encrypted) JSON text.
 #[cfg(feature = "sqlx")]
 impl<'q, T, DB> sqlx::Encode<'q, DB> for ViaJson<T>
 where
+    T: Serialize,
     DB: sqlx::Database,
-    for<'a> sqlx::types::Json<&'a T>: sqlx::Encode<'q, DB>,
+    String: sqlx::Encode<'q, DB>,
 {
     fn encode_by_ref(
         &self,
         buf: &mut <DB as sqlx::Database>::ArgumentBuffer<'q>,
     ) -> Result<sqlx::encode::IsNull, sqlx::error::BoxDynError> {
-        let sqlx_json = sqlx::types::Json(&self.0);
-        <sqlx::types::Json<&T> as sqlx::Encode<'q, DB>>::encode_by_ref(&sqlx_json, buf)
+        let stored = crate::crypto::encrypt_stored(serde_json::to_string(&self.0)?)?;
+        <String as sqlx::Encode<'q, DB>>::encode(stored, buf)
     }
 }
@@ -0,0 +1,146 @@
+//! # Sorting and filtering of lists
+
+use std::{borrow::Cow, str::FromStr};
+
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{DeserializeOwned, IntoDeserializer, value::StrDeserializer},
+};
+
+/// Direction in which a list is sorted
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    /// Smallest value first
+    #[default]
+    Asc,
+    /// Largest value first
+    Desc,
+}
+
+/// # Sort order of a list
+///
+/// Parsed from a string of the form `field` or `field:direction`, e.g. `created_at:desc`. The
+/// field is parsed by deserializing `F` from its name. Lists are sorted in
+/// [ascending][SortDirection::Asc] order if no direction is given.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Sort<F> {
+    /// Field by which the list is sorted
+    pub field: F,
+    pub direction: SortDirection,
+}
+
+impl<F> Sort<F> {
+    #[must_use]
+    pub fn new(field: F, direction: SortDirection) -> Self {
+        Self { field, direction }
+    }
+
+    #[must_use]
+    pub fn asc(field: F) -> Self {
+        Self::new(field, SortDirection::Asc)
+    }
+
+    #[must_use]
+    pub fn desc(field: F) -> Self {
+        Self::new(field, SortDirection::Desc)
+    }
+}
+
+impl<F: DeserializeOwned> FromStr for Sort<F> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        fn parse<T: DeserializeOwned>(value: &str) -> Result<T, serde::de::value::Error> {
+            let deserializer: StrDeserializer<'_, serde::de::value::Error> =
+                value.into_deserializer();
+            T::deserialize(deserializer)
+        }
+        let (field, direction) = match s.split_once(':') {
+            Some((field, direction)) => (
+                field,
+                parse(direction).map_err(|_| {
+                    format!("invalid sort direction `{direction}`; expected `asc` or `desc`")
+                })?,
+            ),
+            None => (s, SortDirection::Asc),
+        };
+        let field = parse(field).map_err(|err| format!("invalid sort field `{field}`: {err}"))?;
+        Ok(Self { field, direction })
+    }
+}
+
+impl<'de, F: DeserializeOwned> Deserialize<'de> for Sort<F> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Cow::<str>::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl<F: JsonSchema> JsonSchema for Sort<F> {
+    fn schema_name() -> Cow<'static, str> {
+        format!("Sort_for_{}", F::schema_name()).into()
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "string",
+            "description": "Field by which to sort, optionally followed by `:asc` or `:desc`",
+        })
+    }
+}
+
+/// # Sorting and filtering of a list
+///
+/// Given to [`DatabaseClient`][crate::db::interface::DatabaseClient] methods which list items, so
+/// that they only return the items matching `filter`, in the requested order. `F` is the type of
+/// the fields by which the list can be sorted, and `T` the type of its filter.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListQuery<F, T> {
+    pub sort: Sort<F>,
+    pub filter: T,
+}
+
+impl<F, T: Default> ListQuery<F, T> {
+    /// Creates a query for the whole list, sorted as given.
+    #[must_use]
+    pub fn sorted(sort: Sort<F>) -> Self {
+        Self {
+            sort,
+            filter: T::default(),
+        }
+    }
+}
+
+impl<F, T> ListQuery<F, T> {
+    #[must_use]
+    pub fn with_filter(mut self, filter: T) -> Self {
+        self.filter = filter;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::UserSort;
+
+    #[test]
+    fn test_parse_sort() {
+        assert_eq!(
+            "created_at:desc".parse::<Sort<UserSort>>(),
+            Ok(Sort::desc(UserSort::CreatedAt))
+        );
+        assert_eq!(
+            "email".parse::<Sort<UserSort>>(),
+            Ok(Sort::asc(UserSort::Email))
+        );
+        assert_eq!(
+            "display_name:asc".parse::<Sort<UserSort>>(),
+            Ok(Sort::asc(UserSort::DisplayName))
+        );
+        assert!("email:up".parse::<Sort<UserSort>>().is_err());
+        assert!("password".parse::<Sort<UserSort>>().is_err());
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt::Display;
+
+/// A database maintenance task, run periodically by the
+/// [maintenance job][crate::maintenance::MaintenanceJob] with
+/// [`DatabaseClient::run_maintenance()`][1]
+///
+/// [1]: crate::db::interface::DatabaseClient::run_maintenance
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MaintenanceTask {
+    /// Lets the database update the statistics and indexes which it considers outdated. Cheap
+    /// enough to run often.
+    Optimize,
+    /// Recomputes the query planner's statistics for all tables
+    Analyze,
+    /// Rebuilds the database to reclaim space left by deleted data. Blocks writes while it runs,
+    /// which may take a while for large databases.
+    Vacuum,
+}
+
+impl MaintenanceTask {
+    /// Returns the name of the task, as accepted by [`FromStr`][std::str::FromStr].
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Optimize => "optimize",
+            Self::Analyze => "analyze",
+            Self::Vacuum => "vacuum",
+        }
+    }
+}
+
+impl Display for MaintenanceTask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for MaintenanceTask {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "optimize" => Ok(Self::Optimize),
+            "analyze" => Ok(Self::Analyze),
+            "vacuum" => Ok(Self::Vacuum),
+            _ => Err(format!(
+                "invalid maintenance task `{s}`; expected `optimize`, `analyze`, or `vacuum`"
+            )),
+        }
+    }
+}
@@ -1,26 +1,101 @@
 //! # Data models
 
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use uuid::Uuid;
 
+mod announcement;
+mod api_token;
+mod backup;
 mod config;
+mod email;
+mod federation;
+mod group;
+mod ip_ban;
 mod json;
+mod list;
+mod maintenance;
+mod note;
+mod oauth;
+mod organization;
 mod passkey;
+mod policy;
+mod retention;
+mod role;
+mod saml;
 mod session;
+mod signing_key;
 mod tag;
 mod user;
+mod validation;
+mod webhook;
 
+pub use announcement::*;
+pub use api_token::*;
+pub use backup::*;
 pub use config::*;
+pub use email::*;
+pub use federation::*;
+pub use group::*;
+pub use ip_ban::*;
 pub use json::*;
+pub use list::*;
+pub use maintenance::*;
+pub use note::*;
+pub use oauth::*;
+pub use organization::*;
 pub use passkey::*;
+pub use policy::*;
+pub use retention::*;
+pub use role::*;
+pub use saml::*;
 pub use session::*;
+pub use signing_key::*;
 pub use tag::*;
 pub use user::*;
+pub use validation::*;
+pub use webhook::*;
+
+/// Whether [`new_uuid()`] generates version 7 UUIDs
+static USE_UUID_V7: AtomicBool = AtomicBool::new(false);
+
+/// Version of the UUIDs generated by [`new_uuid()`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum UuidVersion {
+    /// Random UUIDs
+    #[default]
+    V4,
+    /// Time-ordered UUIDs. New IDs sort after older ones, which improves the locality of primary
+    /// key indexes and makes IDs roughly sortable by creation time.
+    V7,
+}
+
+impl std::str::FromStr for UuidVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "4" | "v4" => Ok(Self::V4),
+            "7" | "v7" => Ok(Self::V7),
+            _ => Err(format!("invalid UUID version `{s}`; expected `4` or `7`")),
+        }
+    }
+}
+
+/// Sets the version of the UUIDs generated by [`new_uuid()`] for the rest of the program.
+pub fn set_uuid_version(version: UuidVersion) {
+    USE_UUID_V7.store(version == UuidVersion::V7, Ordering::Relaxed);
+}
 
-/// Helper function to generate a new UUID.
-/// This allows us to easily switch out the UUID version if needed.
+/// Helper function to generate a new UUID, for use as an ID.
+/// Generates version 4 UUIDs unless [configured otherwise][set_uuid_version].
 #[must_use]
 pub fn new_uuid() -> Uuid {
-    Uuid::new_v4()
+    if USE_UUID_V7.load(Ordering::Relaxed) {
+        Uuid::now_v7()
+    } else {
+        Uuid::new_v4()
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, thiserror::Error)]
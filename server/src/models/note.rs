@@ -0,0 +1,27 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// # Administrator note on a user
+///
+/// Notes record context about a [`User`][super::User] for other administrators, e.g. why their
+/// account was disabled. They are only visible to administrators.
+///
+/// Notes are never modified. Editing a user's notes creates a new revision, so the history of
+/// who changed the notes and when is kept. The newest revision holds the current notes.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct UserNote {
+    /// Unique ID
+    pub id: Uuid,
+    /// ID of the user the note is about
+    pub user_id: Uuid,
+    /// ID of the administrator who wrote this revision, or [`None`] if they have been deleted
+    pub author_id: Option<Uuid>,
+    /// Text of the notes
+    pub body: String,
+    /// Time at which this revision was written
+    pub created_at: DateTime<Utc>,
+}
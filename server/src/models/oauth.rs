@@ -0,0 +1,97 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::{EncodableHash, ViaJson};
+
+/// # OAuth client
+///
+/// An application which lets users log in with their IAM account via OIDC. Clients are
+/// registered by administrators, and authenticate to the token endpoint with their ID and secret.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct OAuthClient {
+    /// Unique ID, used as the OAuth `client_id`
+    pub id: Uuid,
+    /// Name of the application
+    pub name: String,
+    /// [`blake3`] hash of the client secret
+    #[serde(skip)]
+    pub secret_hash: EncodableHash,
+    /// URLs to which users may be redirected after authorizing the client. Redirect URLs given in
+    /// authorization requests must match one of these exactly.
+    pub redirect_uris: ViaJson<Vec<String>>,
+    /// Time at which the client was registered
+    pub created_at: DateTime<Utc>,
+    /// Time at which the client was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Data used to update an [`OAuthClient`]
+///
+/// Fields with a value will replace the corresponding field's value in the [`OAuthClient`]
+/// to which the update is applied (via [`DatabaseClient::update_oauth_client()`][1]).
+///
+/// [1]: crate::db::interface::DatabaseClient::update_oauth_client
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OAuthClientUpdate {
+    pub name: Option<String>,
+    pub redirect_uris: Option<Vec<String>>,
+}
+
+impl OAuthClientUpdate {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    #[must_use]
+    pub fn with_redirect_uris(mut self, redirect_uris: Vec<String>) -> Self {
+        self.redirect_uris = Some(redirect_uris);
+        self
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.redirect_uris.is_none()
+    }
+}
+
+/// # OAuth authorization code
+///
+/// Issued to an [`OAuthClient`] when a user authorizes it, and exchanged by the client for tokens.
+/// Codes are short-lived and can only be used once.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct AuthorizationCode {
+    /// [`blake3`] hash of the code
+    pub code_hash: EncodableHash,
+    /// UUID of the [`OAuthClient`] to which the code was issued
+    pub client_id: Uuid,
+    /// UUID of the [`User`][super::User] who authorized the client
+    pub user_id: Uuid,
+    /// Redirect URL given in the authorization request, which must be repeated when redeeming the
+    /// code
+    pub redirect_uri: String,
+    /// Space-separated scopes granted to the client
+    pub scope: String,
+    /// Value of the `nonce` parameter of the authorization request, which is included in the ID
+    /// token
+    pub nonce: Option<String>,
+    /// PKCE code challenge of the authorization request, which must be answered when redeeming the
+    /// code
+    pub code_challenge: Option<String>,
+    /// Time at which the user logged in
+    pub auth_time: DateTime<Utc>,
+    /// Time at which the code expires
+    pub expires_at: DateTime<Utc>,
+}
@@ -0,0 +1,94 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::{MAX_NAME_LENGTH, User, not_blank};
+
+/// # Organization model
+///
+/// An organization groups [`User`]s, e.g. the members of one product team, so that one IAM
+/// deployment can serve several teams. Organizations have their own [tags][super::Tag], and
+/// their administrators manage their members and tags without seeing other organizations' users.
+///
+/// Users can belong to several organizations. A [`Session`][super::Session] acts within at most
+/// one of them at a time.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct Organization {
+    /// Unique identifier
+    pub id: Uuid,
+    /// Organization name (must also be unique)
+    pub name: String,
+    /// Time at which the organization was created
+    pub created_at: DateTime<Utc>,
+    /// Time at which the organization was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Data used to create or update an organization
+///
+/// Fields with a value will replace the corresponding field's value in the [`Organization`]
+/// to which the update is applied (via [`DatabaseClient::update_organization()`][1]).
+///
+/// [1]: crate::db::interface::DatabaseClient::update_organization
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizationUpdate {
+    #[validate(
+        custom(function = not_blank),
+        length(max = MAX_NAME_LENGTH, message = "must be at most 100 characters long")
+    )]
+    pub name: Option<String>,
+}
+
+impl OrganizationUpdate {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+    }
+}
+
+/// Role of a member of an [`Organization`]. Roles are ordered by privilege.
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "kebab-case")]
+#[repr(u8)]
+pub enum OrganizationRole {
+    /// Can see the organization's members and tags
+    Member,
+    /// Can also manage the organization's members and tags
+    Admin,
+}
+
+/// A [`User`] who is a member of an [`Organization`], with their role in it
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct OrganizationMember {
+    /// The member
+    #[serde(flatten)]
+    #[cfg_attr(feature = "sqlx", sqlx(flatten))]
+    pub user: User,
+    /// UUID of the organization
+    pub organization_id: Uuid,
+    /// The member's role in the organization
+    pub role: OrganizationRole,
+    /// Time at which the user joined the organization
+    pub joined_at: DateTime<Utc>,
+}
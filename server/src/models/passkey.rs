@@ -4,10 +4,11 @@ use serde::{Deserialize, Serialize};
 use sqlx::prelude::FromRow;
 use uuid::Uuid;
 use webauthn_rs::prelude::{
-    DiscoverableAuthentication, Passkey, PasskeyAuthentication, PasskeyRegistration,
+    AttestationFormat, AttestationMetadata, Credential, DiscoverableAuthentication,
+    ParsedAttestationData, Passkey, PasskeyAuthentication, PasskeyRegistration,
 };
 
-use crate::models::ViaJson;
+use crate::models::{EncodableHash, ViaJson};
 
 /// # Passkey credential
 ///
@@ -25,6 +26,12 @@ pub struct PasskeyCredential {
     /// Opaque [`Passkey`] data from [`webauthn_rs`]
     #[schemars(skip)]
     pub passkey: ViaJson<Passkey>,
+    /// AAGUID identifying the model of the authenticator which created this passkey, if its
+    /// attestation certified one
+    pub aaguid: Option<Uuid>,
+    /// Format of the attestation statement with which this passkey was registered, e.g. `packed`,
+    /// or `none` if it wasn't attested
+    pub attestation_format: String,
     /// Time at which this passkey was created
     pub created_at: chrono::DateTime<chrono::Utc>,
     /// Time at which this passkey was last used to log in
@@ -89,6 +96,57 @@ pub struct NewPasskeyCredential {
     pub passkey: Passkey,
 }
 
+/// # Attestation of a passkey's authenticator
+///
+/// Describes the attestation statement which was verified when the passkey was registered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasskeyAttestation {
+    pub format: AttestationFormat,
+    /// Whether a certificate vouches for the authenticator, as opposed to the passkey attesting
+    /// to itself or not being attested at all
+    pub certified: bool,
+    /// AAGUID identifying the authenticator's model, if the attestation certifies one
+    pub aaguid: Option<Uuid>,
+}
+
+impl PasskeyAttestation {
+    /// Returns the name of the attestation statement's format, as registered with IANA.
+    #[must_use]
+    pub fn format_name(&self) -> &'static str {
+        match self.format {
+            AttestationFormat::Packed => "packed",
+            AttestationFormat::Tpm => "tpm",
+            AttestationFormat::AndroidKey => "android-key",
+            AttestationFormat::AndroidSafetyNet => "android-safetynet",
+            AttestationFormat::FIDOU2F => "fido-u2f",
+            AttestationFormat::AppleAnonymous => "apple",
+            AttestationFormat::None => "none",
+        }
+    }
+}
+
+impl From<&Passkey> for PasskeyAttestation {
+    fn from(passkey: &Passkey) -> Self {
+        let credential = Credential::from(passkey.clone());
+        let aaguid = match credential.attestation.metadata {
+            AttestationMetadata::Packed { aaguid } | AttestationMetadata::Tpm { aaguid, .. } => {
+                Some(aaguid)
+            }
+            _ => None,
+        };
+        Self {
+            format: credential.attestation_format,
+            certified: matches!(
+                credential.attestation.data,
+                ParsedAttestationData::Basic(_)
+                    | ParsedAttestationData::AttCa(_)
+                    | ParsedAttestationData::AnonCa(_)
+            ),
+            aaguid,
+        }
+    }
+}
+
 /// Object storing the server-side state for an in-progress passkey registration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(feature = "sqlx", derive(FromRow))]
@@ -97,6 +155,8 @@ pub struct PasskeyRegistrationState {
     pub id: Uuid,
     pub user_id: Uuid,
     pub email: String,
+    /// Display name of the user, from which a new user is created when the registration finishes
+    pub display_name: String,
     pub registration: ViaJson<PasskeyRegistration>,
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
@@ -117,4 +177,13 @@ pub struct PasskeyAuthenticationState {
 pub enum PasskeyAuthenticationStateType {
     Discoverable(DiscoverableAuthentication),
     Regular(PasskeyAuthentication),
+    /// Re-authentication of the user of an existing session, which can only be finished with
+    /// that session
+    Reauthentication {
+        session_id_hash: EncodableHash,
+        state: PasskeyAuthentication,
+    },
+    /// Login for an email address without an account, started with a decoy challenge when
+    /// [enumeration protection][crate::api::EnumerationProtection] is enabled. It always fails.
+    Decoy,
 }
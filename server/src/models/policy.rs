@@ -0,0 +1,142 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::models::{MAX_NAME_LENGTH, ViaJson, not_blank};
+
+/// # Policy model
+///
+/// A policy is a document of [statements][PolicyStatement] which permit or forbid actions, on top
+/// of the [permissions][super::Permission] granted by [roles][super::Role]. See
+/// [`crate::policy`] for how policies are evaluated.
+///
+/// Policies are versioned: each change to a policy's document gets a new version number, and
+/// earlier versions are kept as [`PolicyVersion`]s.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct Policy {
+    /// Unique identifier
+    pub id: Uuid,
+    /// Policy name (must also be unique)
+    pub name: String,
+    /// Whether the policy is evaluated. Disabled policies are kept but have no effect.
+    pub enabled: bool,
+    /// Version of the policy's document, starting at 1
+    pub version: i64,
+    /// Current document
+    pub document: ViaJson<PolicyDocument>,
+    /// Time at which the policy was created
+    pub created_at: DateTime<Utc>,
+    /// Time at which the policy was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Version of a [`Policy`]'s document
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyVersion {
+    /// UUID of the policy
+    pub policy_id: Uuid,
+    /// Version number
+    pub version: i64,
+    /// Document of this version
+    pub document: ViaJson<PolicyDocument>,
+    /// Time at which this version was created
+    pub created_at: DateTime<Utc>,
+}
+
+/// Data used to create or update a policy
+///
+/// Fields with a value will replace the corresponding field's value in the [`Policy`] to which the
+/// update is applied (via [`DatabaseClient::update_policy()`][1]). Replacing the document creates a
+/// new version of the policy.
+///
+/// [1]: crate::db::interface::DatabaseClient::update_policy
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyUpdate {
+    #[validate(
+        custom(function = not_blank),
+        length(max = MAX_NAME_LENGTH, message = "must be at most 100 characters long")
+    )]
+    pub name: Option<String>,
+    pub enabled: Option<bool>,
+    pub document: Option<PolicyDocument>,
+}
+
+impl PolicyUpdate {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    #[must_use]
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = Some(enabled);
+        self
+    }
+
+    #[must_use]
+    pub fn with_document(mut self, document: PolicyDocument) -> Self {
+        self.document = Some(document);
+        self
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.enabled.is_none() && self.document.is_none()
+    }
+}
+
+/// Contents of a [`Policy`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PolicyDocument {
+    /// Statements of the policy, which are evaluated together
+    pub statements: Vec<PolicyStatement>,
+}
+
+/// Statement in a [`PolicyDocument`], which permits or forbids some actions to some users
+///
+/// A statement applies to users who are listed in [`users`][Self::users] or have one of the
+/// [`tags`][Self::tags], or to all users if both are empty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PolicyStatement {
+    /// Whether the statement permits or forbids the actions
+    pub effect: PolicyEffect,
+    /// Actions to which the statement applies: permission names like `users:read`, or
+    /// `oauth:authorize` for authorizing OAuth clients. `*` matches every action, and a prefix
+    /// followed by `:*` (like `users:*`) matches every action with that prefix.
+    pub actions: Vec<String>,
+    /// UUIDs of the users to whom the statement applies
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<Uuid>,
+    /// UUIDs of the tags whose users the statement applies to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<Uuid>,
+    /// UUIDs of the OAuth clients to which the statement is limited. Statements which list
+    /// clients only apply to authorizing those clients.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clients: Vec<Uuid>,
+}
+
+/// Effect of a [`PolicyStatement`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum PolicyEffect {
+    /// Allow the actions
+    Permit,
+    /// Deny the actions, even if other statements or roles allow them
+    Forbid,
+}
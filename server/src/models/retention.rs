@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// # Data retention windows
+///
+/// How long each class of data is kept after it stops being used. Data classes without a window
+/// are kept forever. Enforced by [`DatabaseClient::purge_retained_data()`][1].
+///
+/// [1]: crate::db::interface::DatabaseClient::purge_retained_data
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// How long sessions are kept after they end, i.e. after they expire or, for sessions which
+    /// were revoked, logged out, or superseded, after they were last used. See
+    /// [`DatabaseClient::delete_ended_sessions()`][2].
+    ///
+    /// [2]: crate::db::interface::DatabaseClient::delete_ended_sessions
+    pub terminated_sessions: Option<Duration>,
+    /// How long a user's known login devices are kept after the user last logged in from them
+    pub login_history: Option<Duration>,
+    /// How long anonymized users are kept before being deleted entirely
+    pub anonymized_users: Option<Duration>,
+    /// How long dead-lettered emails are kept in the email queue after they were created
+    pub dead_emails: Option<Duration>,
+}
+
+impl RetentionPolicy {
+    /// Returns `true` if every data class is kept forever, i.e. nothing would be purged.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.terminated_sessions.is_none()
+            && self.login_history.is_none()
+            && self.anonymized_users.is_none()
+            && self.dead_emails.is_none()
+    }
+}
+
+/// # Report of purged data
+///
+/// Number of records of each class deleted by one application of a [`RetentionPolicy`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionReport {
+    pub sessions: u64,
+    pub devices: u64,
+    pub users: u64,
+    pub emails: u64,
+}
+
+impl RetentionReport {
+    /// Returns the total number of records purged.
+    #[must_use]
+    pub fn total(&self) -> u64 {
+        self.sessions + self.devices + self.users + self.emails
+    }
+}
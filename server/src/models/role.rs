@@ -0,0 +1,276 @@
+use std::{collections::BTreeSet, fmt};
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    db::interface::{DatabaseClient, DatabaseError},
+    models::{ADMIN_TAG, MAX_NAME_LENGTH, Tag, ViaJson, not_blank},
+    policy::{PolicySet, Principal},
+};
+
+/// Fine-grained privilege which a [`Role`] grants
+///
+/// Permissions only apply to administrator sessions: users with any permission can upgrade their
+/// session to one, and each administrative endpoint checks that the session's user has the
+/// permission which it requires.
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
+pub enum Permission {
+    /// Every permission, including those added in the future. Endpoints which aren't covered by a
+    /// finer-grained permission require this one.
+    #[serde(rename = "*")]
+    All,
+    /// View users
+    #[serde(rename = "users:read")]
+    UsersRead,
+    /// Create, update, and delete users
+    #[serde(rename = "users:write")]
+    UsersWrite,
+    /// Start sessions impersonating other users
+    #[serde(rename = "users:impersonate")]
+    UsersImpersonate,
+    /// View tags
+    #[serde(rename = "tags:read")]
+    TagsRead,
+    /// Create, update, delete, and apply tags
+    #[serde(rename = "tags:write")]
+    TagsWrite,
+    /// View groups and their members
+    #[serde(rename = "groups:read")]
+    GroupsRead,
+    /// Create, update, and delete groups, and manage their members and tags
+    #[serde(rename = "groups:write")]
+    GroupsWrite,
+    /// View roles
+    #[serde(rename = "roles:read")]
+    RolesRead,
+    /// Create, update, and delete roles, and assign them to tags
+    #[serde(rename = "roles:write")]
+    RolesWrite,
+}
+
+impl Permission {
+    /// Every permission
+    pub const VALUES: [Permission; 10] = [
+        Permission::All,
+        Permission::UsersRead,
+        Permission::UsersWrite,
+        Permission::UsersImpersonate,
+        Permission::TagsRead,
+        Permission::TagsWrite,
+        Permission::GroupsRead,
+        Permission::GroupsWrite,
+        Permission::RolesRead,
+        Permission::RolesWrite,
+    ];
+
+    /// Returns the permission's name, as used in the API.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Permission::All => "*",
+            Permission::UsersRead => "users:read",
+            Permission::UsersWrite => "users:write",
+            Permission::UsersImpersonate => "users:impersonate",
+            Permission::TagsRead => "tags:read",
+            Permission::TagsWrite => "tags:write",
+            Permission::GroupsRead => "groups:read",
+            Permission::GroupsWrite => "groups:write",
+            Permission::RolesRead => "roles:read",
+            Permission::RolesWrite => "roles:write",
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// # Role model
+///
+/// A role is a named set of [`Permission`]s. Roles are assigned to [tags][Tag], and grant their
+/// permissions to every user to whom the tag applies, directly or through a
+/// [group][super::Group].
+///
+/// The built-in `iam::admin` tag grants [every permission][Permission::All] without a role.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct Role {
+    /// Unique identifier
+    pub id: Uuid,
+    /// Role name (must also be unique)
+    pub name: String,
+    /// Permissions granted by the role
+    pub permissions: ViaJson<Vec<Permission>>,
+    /// Time at which the role was created
+    pub created_at: DateTime<Utc>,
+    /// Time at which the role was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Data used to create or update a role
+///
+/// Fields with a value will replace the corresponding field's value in the [`Role`] to which the
+/// update is applied (via [`DatabaseClient::update_role()`][1]).
+///
+/// [1]: crate::db::interface::DatabaseClient::update_role
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleUpdate {
+    #[validate(
+        custom(function = not_blank),
+        length(max = MAX_NAME_LENGTH, message = "must be at most 100 characters long")
+    )]
+    pub name: Option<String>,
+    pub permissions: Option<Vec<Permission>>,
+}
+
+impl RoleUpdate {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    #[must_use]
+    pub fn with_permissions(mut self, permissions: Vec<Permission>) -> Self {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.permissions.is_none()
+    }
+}
+
+/// Set of [`Permission`]s held by a user, or granted by tags or roles
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Permissions(BTreeSet<Permission>);
+
+impl Permissions {
+    /// Fetches the permissions granted by the given tags, through their roles.
+    pub async fn for_tags(
+        client: &dyn DatabaseClient,
+        tags: &[Tag],
+    ) -> Result<Self, DatabaseError> {
+        let mut permissions = Self::default();
+        for tag in tags {
+            if tag.name == ADMIN_TAG {
+                permissions.0.insert(Permission::All);
+                continue;
+            }
+            for role in client.get_roles_by_tag_id(&tag.id).await? {
+                permissions.0.extend(role.permissions.iter());
+            }
+        }
+        Ok(permissions)
+    }
+
+    /// Fetches the permissions of the user with the given UUID, i.e. those granted by the tags
+    /// which apply to them, directly or through groups, as adjusted by [policies][crate::policy].
+    pub async fn for_user(
+        client: &dyn DatabaseClient,
+        user_id: &Uuid,
+    ) -> Result<Self, DatabaseError> {
+        let tags = client.get_effective_tags_by_user_id(user_id).await?;
+        let mut permissions = Self::for_tags(client, &tags).await?;
+        let principal = Principal {
+            user_id,
+            tags: &tags,
+        };
+        PolicySet::load(client)
+            .await?
+            .apply(principal, &mut permissions);
+        Ok(permissions)
+    }
+
+    /// Returns whether the set grants the given permission.
+    #[must_use]
+    pub fn contains(&self, permission: Permission) -> bool {
+        self.0.contains(&Permission::All) || self.0.contains(&permission)
+    }
+
+    /// Adds a permission to the set.
+    pub fn insert(&mut self, permission: Permission) {
+        self.0.insert(permission);
+    }
+
+    /// Removes a permission from the set. Removing any permission from a set which has
+    /// [`Permission::All`] replaces it with each of the other permissions.
+    pub fn remove(&mut self, permission: Permission) {
+        if self.0.remove(&Permission::All) {
+            self.0.extend(Permission::VALUES);
+        }
+        self.0.remove(&permission);
+        self.0.remove(&Permission::All);
+    }
+
+    /// Returns a permission in `other` which this set doesn't grant, if any.
+    #[must_use]
+    pub fn first_missing(&self, other: &Permissions) -> Option<Permission> {
+        other
+            .0
+            .iter()
+            .copied()
+            .find(|permission| !self.contains(*permission))
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the permissions in the set, in a stable order.
+    #[must_use]
+    pub fn to_vec(&self) -> Vec<Permission> {
+        self.0.iter().copied().collect()
+    }
+}
+
+impl FromIterator<Permission> for Permissions {
+    fn from_iter<I: IntoIterator<Item = Permission>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permissions() {
+        let permissions: Permissions = [Permission::UsersRead, Permission::TagsWrite]
+            .into_iter()
+            .collect();
+        assert!(permissions.contains(Permission::UsersRead));
+        assert!(!permissions.contains(Permission::UsersWrite));
+        let required = [Permission::UsersRead, Permission::RolesWrite]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            permissions.first_missing(&required),
+            Some(Permission::RolesWrite)
+        );
+        let all: Permissions = [Permission::All].into_iter().collect();
+        assert!(all.contains(Permission::RolesWrite));
+        assert_eq!(all.first_missing(&required), None);
+        assert_eq!(
+            serde_json::to_string(&permissions.to_vec()).unwrap(),
+            r#"["users:read","tags:write"]"#
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Format of the `NameID` which identifies users to a [`SamlServiceProvider`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "kebab-case")]
+#[repr(u8)]
+pub enum SamlNameIdFormat {
+    /// The user's UUID, which never changes
+    #[default]
+    Persistent,
+    /// The user's email address
+    EmailAddress,
+}
+
+impl SamlNameIdFormat {
+    /// Returns the URI identifying the format in SAML messages.
+    #[must_use]
+    pub fn uri(self) -> &'static str {
+        match self {
+            Self::Persistent => "urn:oasis:names:tc:SAML:2.0:nameid-format:persistent",
+            Self::EmailAddress => "urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress",
+        }
+    }
+}
+
+/// # SAML service provider
+///
+/// An application which lets users log in with their IAM account via SAML 2.0. Service providers
+/// are registered by administrators, and are identified in authentication requests by their
+/// entity ID.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct SamlServiceProvider {
+    /// Unique ID
+    pub id: Uuid,
+    /// Unique SAML entity ID of the service provider, which it sends as the `Issuer` of its
+    /// authentication requests
+    pub entity_id: String,
+    /// Name of the application
+    pub name: String,
+    /// URL of the service provider's assertion consumer service, to which responses are posted
+    pub acs_url: String,
+    /// Format of the `NameID` sent to the service provider
+    pub name_id_format: SamlNameIdFormat,
+    /// Time at which the service provider was registered
+    pub created_at: DateTime<Utc>,
+    /// Time at which the service provider was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Data used to update a [`SamlServiceProvider`]
+///
+/// Fields with a value will replace the corresponding field's value in the
+/// [`SamlServiceProvider`] to which the update is applied (via
+/// [`DatabaseClient::update_saml_service_provider()`][1]).
+///
+/// [1]: crate::db::interface::DatabaseClient::update_saml_service_provider
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SamlServiceProviderUpdate {
+    pub entity_id: Option<String>,
+    pub name: Option<String>,
+    pub acs_url: Option<String>,
+    pub name_id_format: Option<SamlNameIdFormat>,
+}
+
+impl SamlServiceProviderUpdate {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_entity_id(mut self, entity_id: String) -> Self {
+        self.entity_id = Some(entity_id);
+        self
+    }
+
+    #[must_use]
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    #[must_use]
+    pub fn with_acs_url(mut self, acs_url: String) -> Self {
+        self.acs_url = Some(acs_url);
+        self
+    }
+
+    #[must_use]
+    pub fn with_name_id_format(mut self, name_id_format: SamlNameIdFormat) -> Self {
+        self.name_id_format = Some(name_id_format);
+        self
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entity_id.is_none()
+            && self.name.is_none()
+            && self.acs_url.is_none()
+            && self.name_id_format.is_none()
+    }
+}
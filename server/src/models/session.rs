@@ -11,7 +11,7 @@ use uuid::Uuid;
 pub enum SessionState {
     /// Session is active and usable
     Active,
-    /// Session was revoked by the user from another device
+    /// Session was revoked by the user from another device, or by an administrator
     Revoked,
     /// Session was canceled due to the user logging out
     LoggedOut,
@@ -41,6 +41,22 @@ pub struct Session {
     /// [`blake3`] hash of the session ID of this session's parent, if it has one
     #[serde(skip)]
     pub parent_id_hash: Option<EncodableHash>,
+    /// IP address from which the session was last used, if known
+    pub ip: Option<String>,
+    /// User agent of the client which last used the session, if known
+    pub user_agent: Option<String>,
+    /// Time at which the session was last used. Only updated every few minutes, so it may lag
+    /// behind the actual time of last use.
+    pub last_seen_at: DateTime<Utc>,
+    /// UUID of the administrator acting as the user, if this session was started by
+    /// impersonating them
+    pub impersonator_id: Option<Uuid>,
+    /// Time at which the user last proved their identity for this session, by logging in or by
+    /// re-authenticating. Sessions which replace another keep its value.
+    pub authenticated_at: DateTime<Utc>,
+    /// UUID of the [organization][super::Organization] within which the session acts, if the
+    /// user selected one
+    pub organization_id: Option<Uuid>,
 }
 
 /// Data used to update a session
@@ -54,6 +70,11 @@ pub struct Session {
 pub struct SessionUpdate {
     pub state: Option<SessionState>,
     pub expires_at: Option<DateTime<Utc>>,
+    pub last_seen_at: Option<DateTime<Utc>>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub authenticated_at: Option<DateTime<Utc>>,
+    pub organization_id: Option<Option<Uuid>>,
 }
 
 impl SessionUpdate {
@@ -74,9 +95,43 @@ impl SessionUpdate {
         self
     }
 
+    /// Records that the session was used at the given time by a client with the given IP
+    /// address and user agent. Unknown values keep their current value.
+    #[must_use]
+    pub fn with_activity(
+        mut self,
+        last_seen_at: DateTime<Utc>,
+        ip: Option<String>,
+        user_agent: Option<String>,
+    ) -> Self {
+        self.last_seen_at = Some(last_seen_at);
+        self.ip = ip;
+        self.user_agent = user_agent;
+        self
+    }
+
+    #[must_use]
+    pub fn with_authenticated_at(mut self, authenticated_at: DateTime<Utc>) -> Self {
+        self.authenticated_at = Some(authenticated_at);
+        self
+    }
+
+    /// Makes the session act within the organization with the given UUID, or within none.
+    #[must_use]
+    pub fn with_organization(mut self, organization_id: Option<Uuid>) -> Self {
+        self.organization_id = Some(organization_id);
+        self
+    }
+
     #[must_use]
     pub fn is_empty(&self) -> bool {
-        self.state.is_none() && self.expires_at.is_none()
+        self.state.is_none()
+            && self.expires_at.is_none()
+            && self.last_seen_at.is_none()
+            && self.ip.is_none()
+            && self.user_agent.is_none()
+            && self.authenticated_at.is_none()
+            && self.organization_id.is_none()
     }
 }
 
@@ -85,7 +140,10 @@ mod encodable_hash {
 
     #[cfg(feature = "sqlx")]
     use std::borrow::Cow;
-    use std::ops::{Deref, DerefMut};
+    use std::{
+        ops::{Deref, DerefMut},
+        str::FromStr,
+    };
 
     use serde::{Deserialize, Serialize};
     #[cfg(feature = "sqlx")]
@@ -157,5 +215,14 @@ mod encodable_hash {
             hash.0
         }
     }
+
+    /// Parses a hash from its hexadecimal representation, e.g. from a session ID cookie.
+    impl FromStr for EncodableHash {
+        type Err = blake3::HexError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            blake3::Hash::from_hex(s).map(Self)
+        }
+    }
 }
 pub use encodable_hash::EncodableHash;
@@ -0,0 +1,63 @@
+use chrono::{DateTime, Utc};
+
+use crate::models::ViaJson;
+
+/// # Stored signing key
+///
+/// An asymmetric key with which the server signs tokens, as stored in the database. Keys are
+/// managed by the [`SigningKeyManager`][crate::crypto::signing::SigningKeyManager].
+#[derive(Clone)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct StoredSigningKey {
+    /// Key ID, which is included in the header of the tokens signed with the key
+    pub kid: String,
+    /// PEM-encoded private key. Stored via [`ViaJson`] so that it is encrypted if a keyring is
+    /// installed.
+    pub private_key: ViaJson<String>,
+    /// PEM-encoded self-signed certificate for the key. Missing for keys generated before
+    /// certificates were added, for which a certificate is generated whenever they are loaded.
+    pub certificate: Option<String>,
+    /// Time at which the key was generated
+    pub created_at: DateTime<Utc>,
+    /// Time at which the key stopped being used to sign new tokens, if it has. Retired keys are
+    /// still published so that tokens signed with them can be verified until they expire.
+    pub retired_at: Option<DateTime<Utc>>,
+}
+
+impl std::fmt::Debug for StoredSigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Don't leak the key into logs
+        f.debug_struct("StoredSigningKey")
+            .field("kid", &self.kid)
+            .field("created_at", &self.created_at)
+            .field("retired_at", &self.retired_at)
+            .finish_non_exhaustive()
+    }
+}
+
+/// # Stored session cookie key
+///
+/// A key with which session cookies are signed when no keys are configured, as stored in the
+/// database so that every instance of the server uses the same key across restarts. See
+/// [`SessionCookieKeys`][crate::session_cookie::SessionCookieKeys].
+#[derive(Clone)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+pub struct StoredSessionCookieKey {
+    /// Key ID, which is included in the cookie values signed with the key
+    pub kid: String,
+    /// Base64-encoded key. Stored via [`ViaJson`] so that it is encrypted if a keyring is
+    /// installed.
+    pub key: ViaJson<String>,
+    /// Time at which the key was generated
+    pub created_at: DateTime<Utc>,
+}
+
+impl std::fmt::Debug for StoredSessionCookieKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Don't leak the key into logs
+        f.debug_struct("StoredSessionCookieKey")
+            .field("kid", &self.kid)
+            .field("created_at", &self.created_at)
+            .finish_non_exhaustive()
+    }
+}
@@ -1,12 +1,16 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use validator::Validate;
 
 use crate::{
     db::interface::{DatabaseClient, DatabaseError},
-    models::User,
+    models::{MAX_NAME_LENGTH, User, not_blank},
 };
 
+/// Name of the built-in tag which allows users to act as administrators
+pub const ADMIN_TAG: &str = "iam::admin";
+
 /// # Tag model
 ///
 /// A tag is a marker which can be applied to [`User`]s.
@@ -20,8 +24,12 @@ use crate::{
 pub struct Tag {
     /// Unique identifier
     pub id: Uuid,
-    /// Tag name (must also be unique)
+    /// Tag name (must also be unique among the tags of the same organization, or among the global
+    /// tags)
     pub name: String,
+    /// UUID of the [organization][super::Organization] to which the tag belongs, or [`None`] for
+    /// global tags. Organization tags can only be applied to the organization's members.
+    pub organization_id: Option<Uuid>,
     /// Time at which the tag was created
     pub created_at: chrono::DateTime<chrono::Utc>,
     /// Time at which the tag was last updated
@@ -56,9 +64,13 @@ impl Tag {
 /// to which the update is applied (via [`DatabaseClient::update_tag()`][1]).
 ///
 /// [1]: crate::db::interface::DatabaseClient::update_tag
-#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
 #[serde(rename_all = "camelCase")]
 pub struct TagUpdate {
+    #[validate(
+        custom(function = not_blank),
+        length(max = MAX_NAME_LENGTH, message = "must be at most 100 characters long")
+    )]
     pub name: Option<String>,
 }
 
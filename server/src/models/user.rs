@@ -1,10 +1,56 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use crate::{
     db::interface::{DatabaseClient, DatabaseError},
-    models::{ErrNotPopulated, PasskeyCredential, Tag},
+    models::{
+        EmailStatus, ErrNotPopulated, ListQuery, MAX_DISPLAY_NAME_LENGTH, MAX_EMAIL_LENGTH,
+        PasskeyCredential, Tag, not_blank, valid_email,
+    },
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
 use uuid::Uuid;
+use validator::Validate;
+
+/// Whether [`normalize_email()`] applies Unicode normalization
+static NORMALIZE_EMAIL_UNICODE: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether [`normalize_email()`] applies Unicode normalization for the rest of the program.
+pub fn set_email_unicode_normalization(enabled: bool) {
+    NORMALIZE_EMAIL_UNICODE.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns the canonical form of an email address, in which it is stored and looked up.
+///
+/// Surrounding whitespace is removed and the address is lowercased, so that addresses which only
+/// differ in case refer to the same user. If [enabled][set_email_unicode_normalization], the
+/// address is also converted to Unicode normalization form C first, so that differently composed
+/// but equivalent characters compare equal.
+#[must_use]
+pub fn normalize_email(email: &str) -> String {
+    let email = email.trim();
+    if NORMALIZE_EMAIL_UNICODE.load(Ordering::Relaxed) {
+        email.nfc().collect::<String>().to_lowercase()
+    } else {
+        email.to_lowercase()
+    }
+}
+
+/// Whether a [`User`] may use their account
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[serde(rename_all = "kebab-case")]
+#[repr(u8)]
+pub enum UserStatus {
+    /// The user can log in and use their sessions
+    #[default]
+    Active,
+    /// An administrator blocked the account for the time being, e.g. while investigating abuse
+    Suspended,
+    /// The account was closed, e.g. because the user left the organization
+    Deactivated,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
@@ -12,9 +58,25 @@ use uuid::Uuid;
 pub struct User {
     id: Uuid,
     email: String,
+    /// Time at which the user confirmed that they own their email address, if they have. Cleared
+    /// whenever the address changes by other means.
+    email_verified_at: Option<chrono::DateTime<chrono::Utc>>,
     display_name: String,
+    /// Whether the user may log in. Users who aren't [active][UserStatus::Active] can't log in,
+    /// and their sessions can't be used.
+    status: UserStatus,
     created_at: chrono::DateTime<chrono::Utc>,
     updated_at: chrono::DateTime<chrono::Utc>,
+    /// Whether the user wants to be emailed about logins to their account
+    login_notifications: bool,
+    /// Time at which the user last completed authentication, if ever
+    last_login_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Time at which the user's personal data was scrubbed, if it has been. Anonymized users keep
+    /// their ID, tags, and session history, but can no longer log in.
+    anonymized_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Time until which logins to the user's account are refused because of too many failed
+    /// attempts, if it has been locked
+    locked_until: Option<chrono::DateTime<chrono::Utc>>,
 
     /// List of tags applied to this user. Depending on the database, this can be more expensive to
     /// retrieve than just the base user information, so it is not fetched by default, and will
@@ -29,6 +91,12 @@ pub struct User {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[cfg_attr(feature = "sqlx", sqlx(skip))]
     passkeys: Option<Vec<PasskeyCredential>>,
+
+    /// Whether emails can be delivered to this user's address. Not fetched by default, and will
+    /// have a value of [`None`]. If needed, use [`User::fetch_email_status()`] to populate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "sqlx", sqlx(skip))]
+    email_status: Option<EmailStatus>,
 }
 
 impl User {
@@ -42,11 +110,21 @@ impl User {
         &self.email
     }
 
+    #[must_use]
+    pub fn email_verified_at(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.email_verified_at
+    }
+
     #[must_use]
     pub fn display_name(&self) -> &str {
         &self.display_name
     }
 
+    #[must_use]
+    pub fn status(&self) -> UserStatus {
+        self.status
+    }
+
     #[must_use]
     pub fn created_at(&self) -> chrono::DateTime<chrono::Utc> {
         self.created_at
@@ -57,10 +135,42 @@ impl User {
         self.updated_at
     }
 
+    #[must_use]
+    pub fn login_notifications(&self) -> bool {
+        self.login_notifications
+    }
+
+    #[must_use]
+    pub fn last_login_at(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.last_login_at
+    }
+
+    #[must_use]
+    pub fn anonymized_at(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.anonymized_at
+    }
+
+    #[must_use]
+    pub fn locked_until(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.locked_until
+    }
+
+    /// Returns whether logins to the user's account are currently refused.
+    #[must_use]
+    pub fn is_locked(&self) -> bool {
+        self.locked_until
+            .is_some_and(|until| until > chrono::Utc::now())
+    }
+
     pub fn tags(&mut self) -> Result<&[Tag], ErrNotPopulated> {
         self.tags.as_deref().ok_or(ErrNotPopulated)
     }
 
+    /// Populates the user's tags, e.g. with tags fetched along with the user.
+    pub fn set_tags(&mut self, tags: Vec<Tag>) {
+        self.tags = Some(tags);
+    }
+
     pub async fn fetch_tags(
         &mut self,
         client: &dyn DatabaseClient,
@@ -74,6 +184,11 @@ impl User {
         }
     }
 
+    /// Populates the user's passkeys, e.g. with passkeys fetched along with the user.
+    pub fn set_passkeys(&mut self, passkeys: Vec<PasskeyCredential>) {
+        self.passkeys = Some(passkeys);
+    }
+
     pub async fn fetch_passkeys(
         &mut self,
         client: &dyn DatabaseClient,
@@ -86,6 +201,21 @@ impl User {
             Ok(self.passkeys.as_deref().unwrap())
         }
     }
+
+    pub async fn fetch_email_status(
+        &mut self,
+        client: &dyn DatabaseClient,
+    ) -> Result<&EmailStatus, DatabaseError> {
+        if self.email_status.is_none() {
+            let suppression = match client.get_email_suppression(&self.email).await {
+                Ok(suppression) => Some(suppression),
+                Err(DatabaseError::NotFound) => None,
+                Err(err) => return Err(err),
+            };
+            self.email_status = Some(suppression.into());
+        }
+        Ok(self.email_status.as_ref().unwrap())
+    }
 }
 
 /// Data used to update a user
@@ -94,11 +224,24 @@ impl User {
 /// to which the update is applied (via [`DatabaseClient::update_user()`][1]).
 ///
 /// [1]: crate::db::interface::DatabaseClient::update_user
-#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema, Validate)]
 #[serde(rename_all = "camelCase")]
 pub struct UserUpdate {
+    #[validate(
+        custom(function = valid_email),
+        length(max = MAX_EMAIL_LENGTH, message = "must be at most 254 characters long")
+    )]
+    #[schemars(email)]
     pub email: Option<String>,
+    #[validate(
+        custom(function = not_blank),
+        length(
+            max = MAX_DISPLAY_NAME_LENGTH,
+            message = "must be at most 100 characters long"
+        )
+    )]
     pub display_name: Option<String>,
+    pub login_notifications: Option<bool>,
 }
 
 impl UserUpdate {
@@ -107,6 +250,7 @@ impl UserUpdate {
         Self {
             email: None,
             display_name: None,
+            login_notifications: None,
         }
     }
 
@@ -122,17 +266,141 @@ impl UserUpdate {
         self
     }
 
+    #[must_use]
+    pub fn with_login_notifications(mut self, login_notifications: bool) -> Self {
+        self.login_notifications = Some(login_notifications);
+        self
+    }
+
     #[must_use]
     pub fn is_empty(&self) -> bool {
-        self.email.is_none() && self.display_name.is_none()
+        self.email.is_none() && self.display_name.is_none() && self.login_notifications.is_none()
     }
 }
 
-/// Data used to create a user with [`DatabaseClient::create_user()`]
+/// # Result of merging users
+///
+/// Describes what was (or, for a dry run, would be) moved from the duplicate user to the user it
+/// was merged into by [`DatabaseClient::merge_users()`]. The duplicate user is deleted after a
+/// merge.
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UserMerge {
+    /// The duplicate user, as it was before the merge
+    pub source: User,
+    /// ID of the user into which the duplicate was merged
+    pub target_id: Uuid,
+    /// Whether this was a dry run, in which case nothing was changed
+    pub dry_run: bool,
+    /// Number of passkeys moved
+    pub passkeys: u64,
+    /// Number of tags added to the target user, excluding those it already had
+    pub tags: u64,
+    /// Number of sessions moved
+    pub sessions: u64,
+    /// Number of known login devices moved or combined
+    pub devices: u64,
+    /// Number of administrator note revisions moved
+    pub notes: u64,
+}
+
+/// Data used to create a user with [`DatabaseClient::create_user()`]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, Validate)]
 #[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
 #[serde(rename_all = "camelCase")]
 pub struct UserCreate {
+    #[validate(
+        custom(function = valid_email),
+        length(max = MAX_EMAIL_LENGTH, message = "must be at most 254 characters long")
+    )]
+    #[schemars(email)]
     pub email: String,
+    #[validate(
+        custom(function = not_blank),
+        length(
+            max = MAX_DISPLAY_NAME_LENGTH,
+            message = "must be at most 100 characters long"
+        )
+    )]
     pub display_name: String,
 }
+
+/// Field by which users are sorted when listed with [`DatabaseClient::list_users()`]. Users with
+/// equal values are sorted by ID.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UserSort {
+    /// By creation time
+    #[default]
+    #[serde(alias = "created-at")]
+    CreatedAt,
+    /// Alphabetically by email address
+    Email,
+    /// Alphabetically by display name
+    #[serde(alias = "display-name")]
+    DisplayName,
+}
+
+/// # Filter for a list of users
+///
+/// Restricts the users listed by [`DatabaseClient::list_users()`] to those matching every given
+/// condition.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserFilter {
+    /// Part of the email address, matched case-insensitively
+    pub email: Option<String>,
+    /// Part of the display name, matched case-insensitively
+    pub display_name: Option<String>,
+    pub status: Option<UserStatus>,
+}
+
+/// Sorting and filtering of a list of users
+pub type UserListQuery = ListQuery<UserSort, UserFilter>;
+
+/// # Position in a list of users
+///
+/// Identifies a user in a list returned by [`DatabaseClient::list_users()`] by the value the list
+/// is sorted by and the user's ID, so that the next page can start after it even if users are
+/// added or removed in the meantime.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UserCursor {
+    CreatedAt(chrono::DateTime<chrono::Utc>, Uuid),
+    Email(String, Uuid),
+    DisplayName(String, Uuid),
+}
+
+impl UserCursor {
+    /// Returns the position of the given user in a list sorted by the given field.
+    #[must_use]
+    pub fn at(user: &User, sort: UserSort) -> Self {
+        match sort {
+            UserSort::CreatedAt => Self::CreatedAt(user.created_at, user.id),
+            UserSort::Email => Self::Email(user.email.clone(), user.id),
+            UserSort::DisplayName => Self::DisplayName(user.display_name.clone(), user.id),
+        }
+    }
+
+    /// Returns the field by which the list this cursor points into is sorted.
+    #[must_use]
+    pub fn sort(&self) -> UserSort {
+        match self {
+            Self::CreatedAt(..) => UserSort::CreatedAt,
+            Self::Email(..) => UserSort::Email,
+            Self::DisplayName(..) => UserSort::DisplayName,
+        }
+    }
+}
+
+/// # Page of users
+///
+/// Returned by [`DatabaseClient::list_users()`].
+#[derive(Debug, Clone)]
+pub struct UserList {
+    /// Users on this page
+    pub users: Vec<User>,
+    /// Total number of users
+    pub total: u64,
+    /// Position of the last user on this page, if there are more users after it
+    pub next: Option<UserCursor>,
+}
@@ -0,0 +1,66 @@
+//! # Request body validation
+//!
+//! Models which clients send to the API derive [`validator::Validate`], and are checked by the
+//! `ValidatedJson` extractor before they reach handlers. The same attributes are read by
+//! [`schemars`], so the constraints also show up in the API specification.
+
+use email_address::{EmailAddress, Options};
+use validator::ValidationError;
+
+use crate::models::normalize_email;
+
+/// Maximum length of display names, in characters
+pub const MAX_DISPLAY_NAME_LENGTH: u64 = 100;
+
+/// Maximum length of the names of tags, groups, roles, organizations, and policies, in characters
+pub const MAX_NAME_LENGTH: u64 = 100;
+
+/// Maximum length of email addresses, in characters (see RFC 5321)
+pub const MAX_EMAIL_LENGTH: u64 = 254;
+
+/// Checks that the value contains something other than whitespace.
+pub fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        return Err(ValidationError::new("blank").with_message("must not be blank".into()));
+    }
+    Ok(())
+}
+
+/// Checks that the value is an email address as defined by RFC 5322, without display text or a
+/// domain literal. The address is checked after [normalization][normalize_email], so surrounding
+/// whitespace is allowed.
+pub fn valid_email(value: &str) -> Result<(), ValidationError> {
+    let options = Options::default()
+        .without_display_text()
+        .without_domain_literal();
+    if EmailAddress::parse_with_options(&normalize_email(value), options).is_err() {
+        return Err(
+            ValidationError::new("email").with_message("must be a valid email address".into())
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_email() {
+        assert!(valid_email("user@example.com").is_ok());
+        assert!(valid_email(" User.Name+tag@Example.COM ").is_ok());
+        assert!(valid_email("\"quoted local\"@example.com").is_ok());
+        assert!(valid_email("user").is_err());
+        assert!(valid_email("user@").is_err());
+        assert!(valid_email("@example.com").is_err());
+        assert!(valid_email("user@@example.com").is_err());
+        assert!(valid_email("User <user@example.com>").is_err());
+        assert!(valid_email("user@[127.0.0.1]").is_err());
+    }
+
+    #[test]
+    fn test_normalize_email() {
+        assert_eq!(normalize_email("  User@Example.COM\n"), "user@example.com");
+        assert_eq!(normalize_email("ÄNDERS@example.com"), "änders@example.com");
+    }
+}
@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::models::ViaJson;
+
+/// Type of event which can be delivered to a [`Webhook`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+pub enum WebhookEventType {
+    /// A user was created, either by registering or by an administrator
+    #[serde(rename = "user.created")]
+    UserCreated,
+    /// A user was deleted or anonymized
+    #[serde(rename = "user.deleted")]
+    UserDeleted,
+    /// A session was created, by logging in or by upgrading or downgrading a session
+    #[serde(rename = "session.created")]
+    SessionCreated,
+    /// A passkey was added to a user's account
+    #[serde(rename = "passkey.created")]
+    PasskeyCreated,
+    /// A passkey was removed from a user's account
+    #[serde(rename = "passkey.deleted")]
+    PasskeyDeleted,
+}
+
+/// # Webhook
+///
+/// A URL to which identity events are `POST`ed as JSON, so that downstream systems can stay in
+/// sync. Deliveries are signed with the webhook's secret; see [`crate::webhooks`] for details.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::FromRow))]
+#[serde(rename_all = "camelCase")]
+pub struct Webhook {
+    /// Unique ID
+    pub id: Uuid,
+    /// URL to which events are delivered
+    pub url: String,
+    /// Types of events delivered to this webhook. If empty, all events are delivered.
+    pub events: ViaJson<Vec<WebhookEventType>>,
+    /// Key with which deliveries are signed. It is only revealed when the webhook is created.
+    #[serde(skip_serializing, default)]
+    #[schemars(skip)]
+    pub secret: ViaJson<String>,
+    /// Whether events are delivered to this webhook
+    pub enabled: bool,
+    /// Time at which the webhook was created
+    pub created_at: DateTime<Utc>,
+    /// Time at which the webhook was last updated
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Webhook {
+    /// Returns whether events of the given type should be delivered to this webhook.
+    #[must_use]
+    pub fn wants(&self, event_type: WebhookEventType) -> bool {
+        self.enabled && (self.events.is_empty() || self.events.contains(&event_type))
+    }
+}
+
+/// Data used to update a [`Webhook`]
+///
+/// Fields with a value will replace the corresponding field's value in the [`Webhook`]
+/// to which the update is applied (via [`DatabaseClient::update_webhook()`][1]).
+///
+/// [1]: crate::db::interface::DatabaseClient::update_webhook
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookUpdate {
+    pub url: Option<String>,
+    pub events: Option<Vec<WebhookEventType>>,
+    pub enabled: Option<bool>,
+}
+
+impl WebhookUpdate {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn with_url(mut self, url: String) -> Self {
+        self.url = Some(url);
+        self
+    }
+
+    #[must_use]
+    pub fn with_events(mut self, events: Vec<WebhookEventType>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    #[must_use]
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = Some(enabled);
+        self
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.url.is_none() && self.events.is_none() && self.enabled.is_none()
+    }
+}
@@ -0,0 +1,294 @@
+//! # Policy engine
+//!
+//! [`Policy`] documents let administrators adjust authorization beyond what [roles][Role] can
+//! express, in the style of Cedar: statements [permit][PolicyEffect::Permit] or
+//! [forbid][PolicyEffect::Forbid] actions to users, and a forbidding statement always wins over
+//! permitting ones.
+//!
+//! Policies are evaluated in two places:
+//!
+//! - When computing a user's [`Permissions`]: permitted permissions are added to those granted by
+//!   the user's roles, and forbidden ones are removed. Policies don't apply to users with the
+//!   `iam::admin` tag, so that a policy can't lock every administrator out.
+//! - In the OAuth authorization endpoint, for the [`AUTHORIZE_ACTION`]: users who are forbidden
+//!   from authorizing a client are sent back to it with an error. Authorization is allowed unless
+//!   a policy forbids it.
+//!
+//! [Role]: crate::models::Role
+
+use uuid::Uuid;
+
+use crate::{
+    db::interface::{DatabaseClient, DatabaseError},
+    models::{
+        ADMIN_TAG, Permission, Permissions, Policy, PolicyDocument, PolicyEffect, PolicyStatement,
+        Tag,
+    },
+};
+
+/// Action of authorizing an OAuth client, whose resource is the client
+pub const AUTHORIZE_ACTION: &str = "oauth:authorize";
+
+/// User whose request is being authorized
+#[derive(Debug, Clone, Copy)]
+pub struct Principal<'a> {
+    /// UUID of the user
+    pub user_id: &'a Uuid,
+    /// Tags which apply to the user, directly or through groups
+    pub tags: &'a [Tag],
+}
+
+/// Outcome of evaluating policies for a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// A statement permits the request and none forbids it
+    Permit,
+    /// A statement forbids the request
+    Forbid,
+    /// No statement applies to the request
+    NotApplicable,
+}
+
+/// Statements of all enabled policies, ready to be evaluated
+#[derive(Debug, Clone, Default)]
+pub struct PolicySet {
+    statements: Vec<PolicyStatement>,
+}
+
+impl PolicySet {
+    /// Fetches the enabled policies from the database.
+    pub async fn load(client: &dyn DatabaseClient) -> Result<Self, DatabaseError> {
+        Ok(client.list_policies().await?.into_iter().collect())
+    }
+
+    /// Decides whether the principal may perform the action on the resource, if it has one.
+    #[must_use]
+    pub fn evaluate(
+        &self,
+        principal: Principal<'_>,
+        action: &str,
+        resource: Option<&Uuid>,
+    ) -> Decision {
+        let mut decision = Decision::NotApplicable;
+        for statement in &self.statements {
+            if !statement_applies(statement, principal, action, resource) {
+                continue;
+            }
+            match statement.effect {
+                PolicyEffect::Forbid => return Decision::Forbid,
+                PolicyEffect::Permit => decision = Decision::Permit,
+            }
+        }
+        decision
+    }
+
+    /// Adds the permissions which the policies permit to the principal to `permissions`, and
+    /// removes those which they forbid. Users with the `iam::admin` tag are exempt.
+    pub fn apply(&self, principal: Principal<'_>, permissions: &mut Permissions) {
+        if principal.tags.iter().any(|tag| tag.name == ADMIN_TAG) {
+            return;
+        }
+        let mut forbidden = Vec::new();
+        for permission in Permission::VALUES {
+            match self.evaluate(principal, permission.as_str(), None) {
+                Decision::Permit => permissions.insert(permission),
+                Decision::Forbid => forbidden.push(permission),
+                Decision::NotApplicable => {}
+            }
+        }
+        for permission in forbidden {
+            permissions.remove(permission);
+        }
+    }
+}
+
+impl FromIterator<Policy> for PolicySet {
+    fn from_iter<I: IntoIterator<Item = Policy>>(iter: I) -> Self {
+        let statements = iter
+            .into_iter()
+            .filter(|policy| policy.enabled)
+            .flat_map(|policy| policy.document.0.statements)
+            .collect();
+        Self { statements }
+    }
+}
+
+fn statement_applies(
+    statement: &PolicyStatement,
+    principal: Principal<'_>,
+    action: &str,
+    resource: Option<&Uuid>,
+) -> bool {
+    if !statement
+        .actions
+        .iter()
+        .any(|pattern| action_matches(pattern, action))
+    {
+        return false;
+    }
+    if !statement.clients.is_empty() && !resource.is_some_and(|id| statement.clients.contains(id)) {
+        return false;
+    }
+    (statement.users.is_empty() && statement.tags.is_empty())
+        || statement.users.contains(principal.user_id)
+        || principal
+            .tags
+            .iter()
+            .any(|tag| statement.tags.contains(&tag.id))
+}
+
+/// Returns whether the action pattern from a statement matches the action.
+fn action_matches(pattern: &str, action: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some("") => true,
+        Some(prefix) if prefix.ends_with(':') => action.starts_with(prefix),
+        _ => pattern == action,
+    }
+}
+
+/// Returns the names of all actions which policies can permit or forbid.
+#[must_use]
+pub fn actions() -> Vec<&'static str> {
+    Permission::VALUES
+        .iter()
+        .map(|permission| permission.as_str())
+        .chain([AUTHORIZE_ACTION])
+        .collect()
+}
+
+/// Checks that a policy document makes sense, returning a description of each problem found.
+#[must_use]
+pub fn validate(document: &PolicyDocument) -> Vec<String> {
+    let mut errors = Vec::new();
+    if document.statements.is_empty() {
+        errors.push("the policy has no statements".to_string());
+    }
+    let actions = actions();
+    for (i, statement) in document.statements.iter().enumerate() {
+        let mut error = |message: &str| errors.push(format!("statement {i}: {message}"));
+        if statement.actions.is_empty() {
+            error("no actions are given");
+        }
+        for pattern in &statement.actions {
+            if !actions.iter().any(|action| action_matches(pattern, action)) {
+                error(&format!("`{pattern}` matches no action"));
+            }
+        }
+        if !statement.clients.is_empty()
+            && !statement
+                .actions
+                .iter()
+                .any(|pattern| action_matches(pattern, AUTHORIZE_ACTION))
+        {
+            error("clients are given, but the statement doesn't apply to `oauth:authorize`");
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::models::ViaJson;
+
+    fn statement(effect: PolicyEffect, actions: &[&str]) -> PolicyStatement {
+        PolicyStatement {
+            effect,
+            actions: actions.iter().map(ToString::to_string).collect(),
+            users: Vec::new(),
+            tags: Vec::new(),
+            clients: Vec::new(),
+        }
+    }
+
+    fn policy_set(statements: Vec<PolicyStatement>) -> PolicySet {
+        [Policy {
+            id: Uuid::new_v4(),
+            name: "test".to_string(),
+            enabled: true,
+            version: 1,
+            document: ViaJson(PolicyDocument { statements }),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let user_id = Uuid::new_v4();
+        let principal = Principal {
+            user_id: &user_id,
+            tags: &[],
+        };
+        let mut forbid = statement(PolicyEffect::Forbid, &["users:write"]);
+        forbid.users.push(Uuid::new_v4());
+        let policies = policy_set(vec![
+            statement(PolicyEffect::Permit, &["users:*"]),
+            forbid.clone(),
+        ]);
+        assert_eq!(
+            policies.evaluate(principal, "users:write", None),
+            Decision::Permit
+        );
+        assert_eq!(
+            policies.evaluate(principal, "tags:write", None),
+            Decision::NotApplicable
+        );
+        forbid.users.push(user_id);
+        let policies = policy_set(vec![statement(PolicyEffect::Permit, &["*"]), forbid]);
+        assert_eq!(
+            policies.evaluate(principal, "users:write", None),
+            Decision::Forbid
+        );
+
+        // Forbidding one permission takes it out of `*`
+        let mut permissions: Permissions = [Permission::All].into_iter().collect();
+        policies.apply(principal, &mut permissions);
+        assert!(!permissions.contains(Permission::All));
+        assert!(!permissions.contains(Permission::UsersWrite));
+        assert!(permissions.contains(Permission::UsersRead));
+
+        let client_id = Uuid::new_v4();
+        let mut statement = statement(PolicyEffect::Forbid, &[AUTHORIZE_ACTION]);
+        statement.clients.push(client_id);
+        let policies = policy_set(vec![statement]);
+        assert_eq!(
+            policies.evaluate(principal, AUTHORIZE_ACTION, Some(&client_id)),
+            Decision::Forbid
+        );
+        assert_eq!(
+            policies.evaluate(principal, AUTHORIZE_ACTION, Some(&Uuid::new_v4())),
+            Decision::NotApplicable
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut document = PolicyDocument {
+            statements: vec![statement(PolicyEffect::Permit, &["users:*", "oauth:*"])],
+        };
+        assert!(validate(&document).is_empty());
+        document.statements[0].clients.push(Uuid::new_v4());
+        assert!(validate(&document).is_empty());
+        document
+            .statements
+            .push(statement(PolicyEffect::Forbid, &["user:read", "tags:read"]));
+        document.statements[1].clients.push(Uuid::new_v4());
+        assert_eq!(
+            validate(&document),
+            [
+                "statement 1: `user:read` matches no action",
+                "statement 1: clients are given, but the statement doesn't apply to \
+                `oauth:authorize`",
+            ]
+        );
+        assert_eq!(
+            validate(&PolicyDocument::default()),
+            ["the policy has no statements"]
+        );
+    }
+}
@@ -0,0 +1,356 @@
+//! # Rate limiting
+//!
+//! The [`RateLimiter`] limits how often clients may start logins and registrations, both per IP
+//! address and per email address, so that neither a single client nor a distributed attack on
+//! one account can hammer the authentication endpoints. See [`RateLimitOptions`] for the limits.
+//!
+//! Requests are counted in fixed windows, which start with the first request for a key. The
+//! counts live in a [`RateLimitStore`]: [`MemoryStore`] counts per server instance, while
+//! `RedisStore` (only available with the `redis` feature) shares the counts between all
+//! instances using the same Redis server.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::IpAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use ipnet::Ipv6Net;
+use tracing::error;
+
+use crate::models::normalize_email;
+
+/// Error returned by a [`RateLimitStore`]
+pub type StoreError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Maximum number of requests allowed within a window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Number of requests allowed per window
+    pub max_requests: u64,
+    /// Length of the window
+    pub window: Duration,
+}
+
+/// Limits applied by a [`RateLimiter`]. A [`None`] limit is not enforced.
+#[derive(Debug, Clone)]
+pub struct RateLimitOptions {
+    /// Limit on requests from the same IP address. IPv6 addresses are limited per `/64` network,
+    /// since clients usually control a whole network of that size.
+    pub per_ip: Option<RateLimit>,
+    /// Limit on requests for the same email address, whichever client they come from
+    pub per_email: Option<RateLimit>,
+}
+
+impl RateLimit {
+    /// Default [per-IP limit][RateLimitOptions::per_ip]
+    pub const DEFAULT_PER_IP: Self = Self {
+        max_requests: 30,
+        window: Duration::from_secs(60),
+    };
+    /// Default [per-email limit][RateLimitOptions::per_email]
+    pub const DEFAULT_PER_EMAIL: Self = Self {
+        max_requests: 10,
+        window: Duration::from_secs(5 * 60),
+    };
+}
+
+impl Default for RateLimitOptions {
+    fn default() -> Self {
+        Self {
+            per_ip: Some(RateLimit::DEFAULT_PER_IP),
+            per_email: Some(RateLimit::DEFAULT_PER_EMAIL),
+        }
+    }
+}
+
+/// Counter for a key in the current window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hit {
+    /// Number of requests in the current window, including this one
+    pub count: u64,
+    /// Time until the current window ends
+    pub resets_in: Duration,
+}
+
+/// # Rate limit counter store
+///
+/// Stores the number of requests per key in the current window.
+pub trait RateLimitStore: Send + Sync + 'static {
+    /// Counts a request for the given key and returns its counter. If no window is running for
+    /// the key, a new one of the given length is started.
+    fn hit<'a>(
+        &'a self,
+        key: &'a str,
+        window: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<Hit, StoreError>> + Send + 'a>>;
+}
+
+/// # In-memory rate limit store
+///
+/// Counts requests in the memory of this server instance, so each instance enforces the limits
+/// separately.
+#[derive(Default)]
+pub struct MemoryStore {
+    counters: Mutex<Counters>,
+}
+
+/// Counters of a [`MemoryStore`]
+#[derive(Default)]
+struct Counters {
+    /// Counts and window end times, by key
+    windows: HashMap<String, (u64, Instant)>,
+    /// When ended windows were last removed
+    swept_at: Option<Instant>,
+}
+
+impl MemoryStore {
+    /// How often ended windows are removed. Sweeping takes time proportional to the number of
+    /// keys, so it isn't done on every request.
+    const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// Creates an empty store.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn hit<'a>(
+        &'a self,
+        key: &'a str,
+        window: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<Hit, StoreError>> + Send + 'a>> {
+        let now = Instant::now();
+        let mut counters = self.counters.lock().unwrap();
+        // Forget about ended windows now and then so memory use stays bounded
+        if counters
+            .swept_at
+            .is_none_or(|swept_at| now - swept_at >= Self::SWEEP_INTERVAL)
+        {
+            counters.windows.retain(|_, (_, ends_at)| *ends_at > now);
+            counters.swept_at = Some(now);
+        }
+        let (count, ends_at) = counters
+            .windows
+            .entry(key.to_string())
+            .or_insert((0, now + window));
+        // The key's window may have ended since the last sweep
+        if *ends_at <= now {
+            *count = 0;
+            *ends_at = now + window;
+        }
+        *count += 1;
+        let hit = Hit {
+            count: *count,
+            resets_in: *ends_at - now,
+        };
+        Box::pin(async move { Ok(hit) })
+    }
+}
+
+/// # Redis-backed rate limit store
+///
+/// Counts requests in Redis, so that all server instances using the same Redis server share the
+/// counts. Counters are stored under `<prefix>rate-limit:<key>` and expire with their window.
+///
+/// Only available with the `redis` feature.
+#[cfg(feature = "redis")]
+pub struct RedisStore {
+    conn: redis::aio::ConnectionManager,
+    prefix: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisStore {
+    /// Connects to the Redis server at the given URL. All keys are prefixed with `prefix`.
+    pub async fn connect(url: &str, prefix: &str) -> Result<Self, redis::RedisError> {
+        let conn = redis::aio::ConnectionManager::new(redis::Client::open(url)?).await?;
+        Ok(Self {
+            conn,
+            prefix: prefix.into(),
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+impl RateLimitStore for RedisStore {
+    fn hit<'a>(
+        &'a self,
+        key: &'a str,
+        window: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<Hit, StoreError>> + Send + 'a>> {
+        Box::pin(async move {
+            let key = format!("{}rate-limit:{key}", self.prefix);
+            let window_ms = u64::try_from(window.as_millis()).unwrap_or(u64::MAX);
+            // `NX` only sets the expiry when the counter was just created, i.e. when the window
+            // starts
+            let (count, ttl_ms): (u64, i64) = redis::pipe()
+                .atomic()
+                .incr(&key, 1)
+                .cmd("PEXPIRE")
+                .arg(&key)
+                .arg(window_ms)
+                .arg("NX")
+                .ignore()
+                .pttl(&key)
+                .query_async(&mut self.conn.clone())
+                .await?;
+            Ok(Hit {
+                count,
+                resets_in: u64::try_from(ttl_ms).map_or(window, Duration::from_millis),
+            })
+        })
+    }
+}
+
+/// # Rate limiter
+///
+/// See the [module-level documentation][self] for details.
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    options: RateLimitOptions,
+}
+
+impl RateLimiter {
+    /// Creates a rate limiter which enforces the given limits, counting requests in the given
+    /// store.
+    #[must_use]
+    pub fn new(store: Arc<dyn RateLimitStore>, options: RateLimitOptions) -> Self {
+        Self { store, options }
+    }
+
+    /// Counts a request from the given IP address, concerning the given email address if any.
+    /// Returns how long the client must wait before retrying if a limit is exceeded.
+    ///
+    /// If the store fails, the request is allowed, so that a store outage doesn't lock everyone
+    /// out.
+    pub async fn check(&self, ip: Option<IpAddr>, email: Option<&str>) -> Option<Duration> {
+        let ip_key = ip.map(|ip| match ip {
+            IpAddr::V4(_) => format!("ip:{ip}"),
+            IpAddr::V6(ip) => format!(
+                "ip:{}",
+                Ipv6Net::new(ip, 64)
+                    .expect("prefix length is valid")
+                    .trunc()
+            ),
+        });
+        let email_key = email.map(|email| format!("email:{}", normalize_email(email)));
+        let mut retry_after = None;
+        for (key, limit) in [
+            (ip_key, self.options.per_ip),
+            (email_key, self.options.per_email),
+        ] {
+            let (Some(key), Some(limit)) = (key, limit) else {
+                continue;
+            };
+            match self.store.hit(&key, limit.window).await {
+                Ok(hit) if hit.count > limit.max_requests => {
+                    retry_after = retry_after.max(Some(hit.resets_in));
+                }
+                Ok(_) => (),
+                Err(err) => error!(%err, "failed to check rate limit"),
+            }
+        }
+        retry_after
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        max_requests: 2,
+        window: Duration::from_secs(60),
+    };
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(
+            Arc::new(MemoryStore::new()),
+            RateLimitOptions {
+                per_ip: Some(LIMIT),
+                per_email: Some(LIMIT),
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_limits_per_ip() {
+        let limiter = limiter();
+        let ip = "192.0.2.1".parse().unwrap();
+        assert!(limiter.check(Some(ip), None).await.is_none());
+        assert!(limiter.check(Some(ip), None).await.is_none());
+        let retry_after = limiter.check(Some(ip), None).await.unwrap();
+        assert!(retry_after <= LIMIT.window);
+
+        // Other addresses have their own counters, but addresses in the same IPv6 /64 share one
+        assert!(
+            limiter
+                .check(Some("192.0.2.2".parse().unwrap()), None)
+                .await
+                .is_none()
+        );
+        for ip in ["2001:db8::1", "2001:db8::2"] {
+            assert!(
+                limiter
+                    .check(Some(ip.parse().unwrap()), None)
+                    .await
+                    .is_none()
+            );
+        }
+        assert!(
+            limiter
+                .check(Some("2001:db8::3".parse().unwrap()), None)
+                .await
+                .is_some()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_limits_per_email() {
+        let limiter = limiter();
+        // Requests for the same email address count together, whichever address they come from
+        for (i, email) in ["kian@kasad.com", " Kian@Kasad.com"]
+            .into_iter()
+            .enumerate()
+        {
+            let ip = IpAddr::from([192, 0, 2, u8::try_from(i).unwrap()]);
+            assert!(limiter.check(Some(ip), Some(email)).await.is_none());
+        }
+        assert!(limiter.check(None, Some("kian@kasad.com")).await.is_some());
+        assert!(limiter.check(None, Some("other@kasad.com")).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_window_resets() {
+        let store = MemoryStore::new();
+        let window = Duration::from_millis(50);
+        assert_eq!(store.hit("key", window).await.unwrap().count, 1);
+        assert_eq!(store.hit("key", window).await.unwrap().count, 2);
+        tokio::time::sleep(window).await;
+        assert_eq!(store.hit("key", window).await.unwrap().count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_sweep() {
+        let store = MemoryStore::new();
+        let window = Duration::from_millis(50);
+        store.hit("old", window).await.unwrap();
+        tokio::time::sleep(window).await;
+
+        // Ended windows are kept until the next sweep is due
+        store.hit("new", window).await.unwrap();
+        assert_eq!(store.counters.lock().unwrap().windows.len(), 2);
+
+        store.counters.lock().unwrap().swept_at =
+            Instant::now().checked_sub(MemoryStore::SWEEP_INTERVAL);
+        store.hit("new", window).await.unwrap();
+        let counters = store.counters.lock().unwrap();
+        assert_eq!(counters.windows.keys().collect::<Vec<_>>(), ["new"]);
+    }
+}
@@ -0,0 +1,374 @@
+//! # `WebAuthn` relying parties
+//!
+//! One server can serve logins for several domains, e.g. `login.example.com` and
+//! `login.example.org`. Each domain is a separate `WebAuthn` relying party, with its own origin,
+//! RP ID, and name, since browsers only use a passkey with the relying party which registered it.
+//!
+//! [`RelyingParties`] holds a [`Webauthn`] client for each of them and picks the one for a request
+//! by its `Host` header. Requests for other hosts use the default relying party, i.e. the one at
+//! the server's [origin][crate::config::ServerConfig::origin].
+//!
+//! The [`PasskeyPolicy`] sets what is asked of authenticators in the ceremonies of every relying
+//! party, and which authenticators may register passkeys.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use reqwest::Url;
+use serde::{Serialize, de::DeserializeOwned};
+use uuid::Uuid;
+use webauthn_rs::{
+    Webauthn, WebauthnBuilder,
+    prelude::{CreationChallengeResponse, RequestChallengeResponse, WebauthnError},
+};
+use webauthn_rs_proto::{
+    AttestationConveyancePreference, AuthenticatorAttachment, AuthenticatorSelectionCriteria,
+    PublicKeyCredentialHints, ResidentKeyRequirement, UserVerificationPolicy,
+};
+
+use crate::models::PasskeyAttestation;
+
+/// Origin, RP ID, and name of a `WebAuthn` relying party
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelyingPartyConfig {
+    /// Origin at which the app is served for this relying party
+    pub origin: Url,
+    /// `WebAuthn` relying party ID, which must be the origin's host or a parent domain of it
+    pub rp_id: String,
+    /// Name of the relying party shown by authenticators
+    pub name: String,
+}
+
+impl RelyingPartyConfig {
+    /// Creates the `WebAuthn` client for this relying party.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the RP ID or origin is invalid.
+    pub fn build(&self) -> Result<Webauthn, WebauthnError> {
+        WebauthnBuilder::new(&self.rp_id, &self.origin)?
+            .rp_name(&self.name)
+            .build()
+    }
+}
+
+/// # Set of `WebAuthn` relying parties
+///
+/// See the [module-level documentation][self] for details.
+#[derive(Debug, Clone)]
+pub struct RelyingParties {
+    default: Arc<Webauthn>,
+    /// Relying parties by the host (and port, if not the default) of their origin
+    by_host: HashMap<String, Arc<Webauthn>>,
+}
+
+impl RelyingParties {
+    /// Creates a set containing only the given default relying party.
+    #[must_use]
+    pub fn new(default: Webauthn) -> Self {
+        let default = Arc::new(default);
+        let mut by_host = HashMap::new();
+        insert_hosts(&mut by_host, &default);
+        Self { default, by_host }
+    }
+
+    /// Adds the given relying party, which is used for requests to its origin's host.
+    #[must_use]
+    pub fn with(mut self, webauthn: Webauthn) -> Self {
+        insert_hosts(&mut self.by_host, &Arc::new(webauthn));
+        self
+    }
+
+    /// Creates the clients for the given relying parties. The first one is the default.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any relying party is invalid.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `configs` is empty.
+    pub fn build(configs: &[RelyingPartyConfig]) -> Result<Self, WebauthnError> {
+        let (default, others) = configs.split_first().expect("no relying parties given");
+        others
+            .iter()
+            .try_fold(Self::new(default.build()?), |parties, config| {
+                Ok(parties.with(config.build()?))
+            })
+    }
+
+    /// Returns the default relying party.
+    #[must_use]
+    pub fn default_party(&self) -> &Arc<Webauthn> {
+        &self.default
+    }
+
+    /// Returns the relying party for requests with the given `Host` header, or the default one if
+    /// none is served at that host.
+    #[must_use]
+    pub fn for_host(&self, host: Option<&str>) -> &Arc<Webauthn> {
+        host.and_then(|host| self.by_host.get(&host.to_ascii_lowercase()))
+            .unwrap_or(&self.default)
+    }
+}
+
+impl From<Webauthn> for RelyingParties {
+    fn from(webauthn: Webauthn) -> Self {
+        Self::new(webauthn)
+    }
+}
+
+/// # Requirements on passkeys
+///
+/// Applied to the challenges of registration and authentication ceremonies. By default, users
+/// must be verified, e.g. with a PIN or biometric, resident keys are preferred, and any kind of
+/// authenticator may be used.
+///
+/// Whether a passkey was registered with user verification required is stored with it, so
+/// relaxing the policy doesn't relax it for passkeys registered before.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasskeyPolicy {
+    /// Whether authenticators must verify the user, or only should if they can
+    pub user_verification: UserVerificationPolicy,
+    /// Whether authenticators should create resident, i.e. discoverable, keys
+    pub resident_key: ResidentKeyRequirement,
+    /// Kind of authenticator which should be used, or [`None`] for any
+    pub authenticator_attachment: Option<AuthenticatorAttachment>,
+    /// Which authenticators may register passkeys
+    pub attestation: AttestationPolicy,
+}
+
+impl Default for PasskeyPolicy {
+    fn default() -> Self {
+        Self {
+            user_verification: UserVerificationPolicy::Required,
+            resident_key: ResidentKeyRequirement::Preferred,
+            authenticator_attachment: None,
+            attestation: AttestationPolicy::default(),
+        }
+    }
+}
+
+impl PasskeyPolicy {
+    /// Applies the policy to a registration challenge and the state in which the ceremony is
+    /// finished.
+    #[must_use]
+    pub fn apply_to_registration<S>(&self, challenge: &mut CreationChallengeResponse, state: S) -> S
+    where
+        S: Serialize + DeserializeOwned,
+    {
+        challenge.public_key.authenticator_selection = Some(AuthenticatorSelectionCriteria {
+            authenticator_attachment: self.authenticator_attachment,
+            resident_key: Some(self.resident_key),
+            require_resident_key: self.resident_key == ResidentKeyRequirement::Required,
+            user_verification: self.user_verification,
+        });
+        if self.attestation.is_restricted() {
+            challenge.public_key.attestation = Some(AttestationConveyancePreference::Direct);
+        }
+        self.apply_to_state(state)
+    }
+
+    /// Applies the policy to an authentication challenge and the state in which the ceremony is
+    /// finished.
+    #[must_use]
+    pub fn apply_to_authentication<S>(
+        &self,
+        challenge: &mut RequestChallengeResponse,
+        state: S,
+    ) -> S
+    where
+        S: Serialize + DeserializeOwned,
+    {
+        self.apply_to_request(challenge);
+        self.apply_to_state(state)
+    }
+
+    /// Applies the policy to an authentication challenge only, e.g. to one which can't be
+    /// finished.
+    pub fn apply_to_request(&self, challenge: &mut RequestChallengeResponse) {
+        challenge.public_key.user_verification = self.user_verification;
+        challenge.public_key.hints =
+            self.authenticator_attachment
+                .map(|attachment| match attachment {
+                    AuthenticatorAttachment::Platform => {
+                        vec![PublicKeyCredentialHints::ClientDevice]
+                    }
+                    AuthenticatorAttachment::CrossPlatform => vec![
+                        PublicKeyCredentialHints::SecurityKey,
+                        PublicKeyCredentialHints::Hybrid,
+                    ],
+                });
+    }
+
+    /// Sets the user verification policy with which a ceremony's state is verified. `WebAuthn`
+    /// clients always start ceremonies requiring user verification and don't expose the policy of
+    /// their states, so it is replaced in the states' serialized form, in which it is the `policy`
+    /// field of the inner state.
+    fn apply_to_state<S>(&self, state: S) -> S
+    where
+        S: Serialize + DeserializeOwned,
+    {
+        if self.user_verification == UserVerificationPolicy::Required {
+            return state;
+        }
+        let mut value = serde_json::to_value(state).expect("ceremony states are serializable");
+        let policy =
+            serde_json::to_value(self.user_verification).expect("policies are serializable");
+        if let Some(fields) = value.as_object_mut() {
+            for inner in fields
+                .values_mut()
+                .filter_map(serde_json::Value::as_object_mut)
+            {
+                if let Some(field) = inner.get_mut("policy") {
+                    *field = policy.clone();
+                }
+            }
+        }
+        serde_json::from_value(value).expect("ceremony states are deserializable")
+    }
+}
+
+/// # Attestation policy
+///
+/// Restricts which authenticators may register passkeys, by the attestation statements with which
+/// they vouch for their make and model. By default, any authenticator may, and none is asked for
+/// an attestation. Otherwise, authenticators are asked for one, and registrations whose
+/// attestation isn't [allowed][Self::allows()] fail.
+///
+/// Attestation signatures are verified, but their certificates aren't checked against the
+/// authenticator vendors' roots. The policy keeps out authenticators which honestly report their
+/// model, not ones which were built to lie about it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttestationPolicy {
+    /// Whether passkeys must come with a certificate vouching for their authenticator
+    pub required: bool,
+    /// AAGUIDs of the only authenticator models which may register passkeys. If empty, any model
+    /// may, except the denied ones.
+    pub allowed_aaguids: HashSet<Uuid>,
+    /// AAGUIDs of authenticator models which may not register passkeys
+    pub denied_aaguids: HashSet<Uuid>,
+}
+
+impl AttestationPolicy {
+    /// Returns `true` if the policy restricts which authenticators may register passkeys.
+    #[must_use]
+    pub fn is_restricted(&self) -> bool {
+        self.required || !self.allowed_aaguids.is_empty() || !self.denied_aaguids.is_empty()
+    }
+
+    /// Returns `true` if a passkey with the given attestation may be registered. Only certified
+    /// AAGUIDs are known, so with an allowlist, passkeys without one are refused.
+    #[must_use]
+    pub fn allows(&self, attestation: &PasskeyAttestation) -> bool {
+        if self.required && !attestation.certified {
+            return false;
+        }
+        match attestation.aaguid {
+            Some(aaguid) => {
+                !self.denied_aaguids.contains(&aaguid)
+                    && (self.allowed_aaguids.is_empty() || self.allowed_aaguids.contains(&aaguid))
+            }
+            None => self.allowed_aaguids.is_empty(),
+        }
+    }
+}
+
+/// Maps the hosts of the given relying party's origins to it. Hosts which are already mapped keep
+/// their relying party.
+fn insert_hosts(by_host: &mut HashMap<String, Arc<Webauthn>>, webauthn: &Arc<Webauthn>) {
+    for origin in webauthn.get_allowed_origins() {
+        if let Some(host) = origin.host_str() {
+            let host = match origin.port() {
+                Some(port) => format!("{host}:{port}"),
+                None => host.to_string(),
+            };
+            by_host
+                .entry(host.to_ascii_lowercase())
+                .or_insert_with(|| Arc::clone(webauthn));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use webauthn_rs::prelude::AttestationFormat;
+
+    use super::*;
+
+    fn config(origin: &str, rp_id: &str) -> RelyingPartyConfig {
+        RelyingPartyConfig {
+            origin: origin.parse().unwrap(),
+            rp_id: rp_id.to_string(),
+            name: rp_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_attestation_policy() {
+        let yubikey = Uuid::from_u128(1);
+        let other = Uuid::from_u128(2);
+        let attestation = |certified, aaguid| PasskeyAttestation {
+            format: AttestationFormat::Packed,
+            certified,
+            aaguid,
+        };
+
+        let policy = AttestationPolicy::default();
+        assert!(!policy.is_restricted());
+        assert!(policy.allows(&attestation(false, None)));
+
+        let policy = AttestationPolicy {
+            required: true,
+            ..AttestationPolicy::default()
+        };
+        assert!(policy.allows(&attestation(true, Some(other))));
+        assert!(!policy.allows(&attestation(false, None)));
+
+        let policy = AttestationPolicy {
+            allowed_aaguids: HashSet::from([yubikey]),
+            ..AttestationPolicy::default()
+        };
+        assert!(policy.allows(&attestation(true, Some(yubikey))));
+        assert!(!policy.allows(&attestation(true, Some(other))));
+        assert!(!policy.allows(&attestation(false, None)));
+
+        let policy = AttestationPolicy {
+            denied_aaguids: HashSet::from([yubikey]),
+            ..AttestationPolicy::default()
+        };
+        assert!(!policy.allows(&attestation(true, Some(yubikey))));
+        assert!(policy.allows(&attestation(true, Some(other))));
+        assert!(policy.allows(&attestation(false, None)));
+    }
+
+    #[test]
+    fn test_relying_parties_by_host() {
+        let parties = RelyingParties::build(&[
+            config("https://login.example.com", "example.com"),
+            config("https://login.example.org", "login.example.org"),
+            config("http://localhost:3000", "localhost"),
+        ])
+        .unwrap();
+        let origin = |host| parties.for_host(host).get_allowed_origins()[0].to_string();
+        assert_eq!(
+            origin(Some("login.example.com")),
+            "https://login.example.com/"
+        );
+        assert_eq!(
+            origin(Some("LOGIN.example.org")),
+            "https://login.example.org/"
+        );
+        assert_eq!(origin(Some("localhost:3000")), "http://localhost:3000/");
+        // The port must match, and unknown hosts get the default
+        assert_eq!(origin(Some("localhost")), "https://login.example.com/");
+        assert_eq!(origin(Some("example.net")), "https://login.example.com/");
+        assert_eq!(origin(None), "https://login.example.com/");
+        assert_eq!(
+            parties.default_party().get_allowed_origins()[0].as_str(),
+            "https://login.example.com/"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+//! # Data retention enforcement
+//!
+//! A [`RetentionJob`] periodically deletes data which is older than allowed by the configured
+//! [`RetentionPolicy`], and logs a [report][RetentionReport] of what was purged.
+
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use tracing::{debug, info};
+
+use crate::{
+    db::interface::{DatabaseClient, DatabaseError},
+    jobs::{Job, JobError, Schedule},
+    models::{RetentionPolicy, RetentionReport},
+};
+
+/// # Scheduled data retention job
+///
+/// See the [module-level documentation][self] for details.
+pub struct RetentionJob {
+    db: Arc<dyn DatabaseClient>,
+    policy: RetentionPolicy,
+    interval: Duration,
+}
+
+impl RetentionJob {
+    /// Creates a job which enforces the given policy every `interval`.
+    #[must_use]
+    pub fn new(db: Arc<dyn DatabaseClient>, policy: RetentionPolicy, interval: Duration) -> Self {
+        Self {
+            db,
+            policy,
+            interval,
+        }
+    }
+
+    /// Returns the policy enforced by this job.
+    #[must_use]
+    pub fn policy(&self) -> &RetentionPolicy {
+        &self.policy
+    }
+
+    /// Returns the schedule of the job: every interval, starting immediately. When several
+    /// instances share the database, only one of them runs it.
+    #[must_use]
+    pub fn schedule(&self) -> Schedule {
+        Schedule::every(self.interval).run_at_start().coordinated()
+    }
+
+    /// Purges all data which is older than allowed by the policy, and returns a report of what
+    /// was purged.
+    pub async fn run(&self) -> Result<RetentionReport, DatabaseError> {
+        let report = self.db.purge_retained_data(&self.policy).await?;
+        if report.total() > 0 {
+            info!(
+                sessions = report.sessions,
+                devices = report.devices,
+                users = report.users,
+                emails = report.emails,
+                "purged data past its retention window"
+            );
+        } else {
+            debug!("no data past its retention window");
+        }
+        Ok(report)
+    }
+}
+
+impl Job for RetentionJob {
+    fn name(&self) -> &'static str {
+        "retention"
+    }
+
+    fn run(&self) -> Pin<Box<dyn Future<Output = Result<(), JobError>> + Send + '_>> {
+        Box::pin(async move {
+            RetentionJob::run(self).await?;
+            Ok(())
+        })
+    }
+}
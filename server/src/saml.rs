@@ -0,0 +1,578 @@
+//! # SAML 2.0 identity provider
+//!
+//! Parses authentication requests from SAML service providers and builds the signed responses
+//! with which users are logged in to them. Only the HTTP-POST binding is supported, so requests
+//! and responses are base64-encoded but not compressed.
+//!
+//! Responses are built directly in [exclusive canonical form][exc-c14n]: elements are never
+//! self-closing, namespace declarations precede attributes, attributes are sorted, and there is no
+//! whitespace between elements. This lets assertions be signed without a full XML implementation.
+//! Assertions are signed with the server's [signing key][crate::crypto::jwt::SigningKey], using
+//! ECDSA with SHA-256, and carry the key's certificate.
+//!
+//! Requests are parsed by a minimal parser which only understands what authentication requests
+//! contain. Document type declarations are rejected, so entity expansion attacks aren't possible.
+//! Signed requests are accepted, but their signatures aren't checked; responses are only ever sent
+//! to the registered assertion consumer service of the requesting service provider.
+//!
+//! [exc-c14n]: https://www.w3.org/TR/xml-exc-c14n/
+
+use std::{borrow::Cow, fmt::Write, time::Duration};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chrono::{DateTime, SecondsFormat, Utc};
+use openssl::sha::sha256;
+
+use crate::{
+    crypto::{CryptoError, jwt::SigningKey},
+    models::new_uuid,
+};
+
+const PROTOCOL_NS: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+const ASSERTION_NS: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+const METADATA_NS: &str = "urn:oasis:names:tc:SAML:2.0:metadata";
+const DSIG_NS: &str = "http://www.w3.org/2000/09/xmldsig#";
+const EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+const ENVELOPED_SIGNATURE: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+const ECDSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha256";
+const SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+const POST_BINDING: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST";
+const BEARER: &str = "urn:oasis:names:tc:SAML:2.0:cm:bearer";
+const SUCCESS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+const AUTHN_CONTEXT_UNSPECIFIED: &str = "urn:oasis:names:tc:SAML:2.0:ac:classes:unspecified";
+const ATTRNAME_FORMAT_BASIC: &str = "urn:oasis:names:tc:SAML:2.0:attrname-format:basic";
+
+/// How long an assertion can be used after it is issued
+const ASSERTION_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+/// How far before its issue time an assertion is valid, to allow for clocks which are behind
+const CLOCK_SKEW: Duration = Duration::from_secs(60);
+
+/// Error returned when a SAML message can't be parsed
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("malformed SAML message: {0}")]
+pub struct SamlError(pub &'static str);
+
+/// Authentication request sent by a service provider
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthnRequest {
+    /// ID of the request, to which the response refers
+    pub id: String,
+    /// Entity ID of the service provider
+    pub issuer: String,
+    /// URL to which the service provider asked for the response to be sent, if it did
+    pub acs_url: Option<String>,
+}
+
+impl AuthnRequest {
+    /// Parses a base64-encoded `AuthnRequest`, as sent in the `SAMLRequest` parameter of the
+    /// HTTP-POST binding.
+    pub fn decode(encoded: &str) -> Result<Self, SamlError> {
+        let encoded: String = encoded.split_ascii_whitespace().collect();
+        let xml = BASE64_STANDARD
+            .decode(encoded)
+            .map_err(|_| SamlError("invalid base64"))?;
+        let xml = String::from_utf8(xml).map_err(|_| SamlError("invalid UTF-8"))?;
+        Self::parse(&xml)
+    }
+
+    /// Parses an `AuthnRequest` document.
+    pub fn parse(xml: &str) -> Result<Self, SamlError> {
+        let mut tokens = Tokenizer { rest: xml };
+        let (name, attributes, empty) = loop {
+            match tokens.next_token()? {
+                Some(Token::Start {
+                    name,
+                    attributes,
+                    empty,
+                }) => break (name, attributes, empty),
+                Some(Token::Text(text)) if text.trim().is_empty() => {}
+                _ => return Err(SamlError("missing root element")),
+            }
+        };
+        if local_name(name) != "AuthnRequest" {
+            return Err(SamlError("not an AuthnRequest"));
+        }
+        let attribute = |wanted: &str| {
+            attributes
+                .iter()
+                .find(|(name, _)| *name == wanted)
+                .map(|(_, value)| value.clone())
+        };
+        let id = attribute("ID").ok_or(SamlError("missing request ID"))?;
+        let acs_url = attribute("AssertionConsumerServiceURL");
+
+        let mut issuer = None;
+        let mut depth = usize::from(!empty);
+        while depth > 0 {
+            match tokens.next_token()? {
+                Some(Token::Start { name, empty, .. }) => {
+                    if depth == 1 && !empty && local_name(name) == "Issuer" {
+                        issuer = Some(tokens.text_content()?);
+                    } else if !empty {
+                        depth += 1;
+                    }
+                }
+                Some(Token::End) => depth -= 1,
+                Some(Token::Text(_)) => {}
+                None => return Err(SamlError("unexpected end of document")),
+            }
+        }
+        let issuer = issuer.ok_or(SamlError("missing issuer"))?;
+        Ok(Self {
+            id,
+            issuer: issuer.trim().to_string(),
+            acs_url,
+        })
+    }
+}
+
+/// Information asserted about a user in a [response][SamlResponse]
+#[derive(Debug, Clone)]
+pub struct SamlResponse<'a> {
+    /// Entity ID of this identity provider
+    pub issuer: &'a str,
+    /// The request being answered
+    pub request: &'a AuthnRequest,
+    /// Entity ID of the service provider, to which the assertion is restricted
+    pub audience: &'a str,
+    /// URL of the service provider's assertion consumer service
+    pub acs_url: &'a str,
+    /// URI of the format of [`name_id`][Self::name_id]
+    pub name_id_format: &'a str,
+    /// Identifier of the user
+    pub name_id: &'a str,
+    /// Time at which the user logged in
+    pub authn_instant: DateTime<Utc>,
+    /// Attributes of the user, each with one or more values
+    pub attributes: &'a [(&'a str, Vec<String>)],
+}
+
+impl SamlResponse<'_> {
+    /// Builds the response, with the assertion signed by the given key, and returns it as XML.
+    pub fn sign(&self, key: &SigningKey) -> Result<String, CryptoError> {
+        let now = Utc::now();
+        let issue_instant = timestamp(now);
+        let not_on_or_after = timestamp(now + ASSERTION_LIFETIME);
+        let assertion_id = random_id();
+
+        // The assertion is split around the signature, which is inserted after the issuer
+        let mut head = String::new();
+        write!(
+            head,
+            r#"<saml:Assertion xmlns:saml="{ASSERTION_NS}" ID="{assertion_id}" IssueInstant="{issue_instant}" Version="2.0"><saml:Issuer>{}</saml:Issuer>"#,
+            escape_text(self.issuer),
+        )
+        .unwrap();
+        let mut tail = String::new();
+        write!(
+            tail,
+            r#"<saml:Subject><saml:NameID Format="{}">{}</saml:NameID><saml:SubjectConfirmation Method="{BEARER}"><saml:SubjectConfirmationData InResponseTo="{}" NotOnOrAfter="{not_on_or_after}" Recipient="{}"></saml:SubjectConfirmationData></saml:SubjectConfirmation></saml:Subject>"#,
+            escape_attribute(self.name_id_format),
+            escape_text(self.name_id),
+            escape_attribute(&self.request.id),
+            escape_attribute(self.acs_url),
+        )
+        .unwrap();
+        write!(
+            tail,
+            r#"<saml:Conditions NotBefore="{}" NotOnOrAfter="{not_on_or_after}"><saml:AudienceRestriction><saml:Audience>{}</saml:Audience></saml:AudienceRestriction></saml:Conditions>"#,
+            timestamp(now - CLOCK_SKEW),
+            escape_text(self.audience),
+        )
+        .unwrap();
+        write!(
+            tail,
+            r#"<saml:AuthnStatement AuthnInstant="{}"><saml:AuthnContext><saml:AuthnContextClassRef>{AUTHN_CONTEXT_UNSPECIFIED}</saml:AuthnContextClassRef></saml:AuthnContext></saml:AuthnStatement>"#,
+            timestamp(self.authn_instant),
+        )
+        .unwrap();
+        tail.push_str("<saml:AttributeStatement>");
+        for (name, values) in self.attributes {
+            write!(
+                tail,
+                r#"<saml:Attribute Name="{}" NameFormat="{ATTRNAME_FORMAT_BASIC}">"#,
+                escape_attribute(name),
+            )
+            .unwrap();
+            for value in values {
+                write!(
+                    tail,
+                    "<saml:AttributeValue>{}</saml:AttributeValue>",
+                    escape_text(value)
+                )
+                .unwrap();
+            }
+            tail.push_str("</saml:Attribute>");
+        }
+        tail.push_str("</saml:AttributeStatement></saml:Assertion>");
+
+        // The enveloped signature transform removes the signature before digesting
+        let digest = BASE64_STANDARD.encode(sha256(format!("{head}{tail}").as_bytes()));
+        // Declaring the namespace on `SignedInfo` as well makes it already canonical
+        let signed_info = format!(
+            r##"<ds:SignedInfo xmlns:ds="{DSIG_NS}"><ds:CanonicalizationMethod Algorithm="{EXC_C14N}"></ds:CanonicalizationMethod><ds:SignatureMethod Algorithm="{ECDSA_SHA256}"></ds:SignatureMethod><ds:Reference URI="#{assertion_id}"><ds:Transforms><ds:Transform Algorithm="{ENVELOPED_SIGNATURE}"></ds:Transform><ds:Transform Algorithm="{EXC_C14N}"></ds:Transform></ds:Transforms><ds:DigestMethod Algorithm="{SHA256}"></ds:DigestMethod><ds:DigestValue>{digest}</ds:DigestValue></ds:Reference></ds:SignedInfo>"##,
+        );
+        let signature = BASE64_STANDARD.encode(key.sign_bytes(signed_info.as_bytes())?);
+        let certificate = BASE64_STANDARD.encode(key.certificate_der()?);
+
+        let mut response = String::new();
+        write!(
+            response,
+            r#"<samlp:Response xmlns:saml="{ASSERTION_NS}" xmlns:samlp="{PROTOCOL_NS}" Destination="{}" ID="{}" InResponseTo="{}" IssueInstant="{issue_instant}" Version="2.0"><saml:Issuer>{}</saml:Issuer><samlp:Status><samlp:StatusCode Value="{SUCCESS}"></samlp:StatusCode></samlp:Status>"#,
+            escape_attribute(self.acs_url),
+            random_id(),
+            escape_attribute(&self.request.id),
+            escape_text(self.issuer),
+        )
+        .unwrap();
+        write!(
+            response,
+            r#"{head}<ds:Signature xmlns:ds="{DSIG_NS}">{signed_info}<ds:SignatureValue>{signature}</ds:SignatureValue><ds:KeyInfo><ds:X509Data><ds:X509Certificate>{certificate}</ds:X509Certificate></ds:X509Data></ds:KeyInfo></ds:Signature>{tail}</samlp:Response>"#,
+        )
+        .unwrap();
+        Ok(response)
+    }
+}
+
+/// Builds the metadata document describing this identity provider, which service providers use
+/// to configure it. `certificates` are the DER-encoded certificates of the keys with which
+/// assertions may be signed.
+#[must_use]
+pub fn metadata(entity_id: &str, sso_url: &str, certificates: &[Vec<u8>]) -> String {
+    let mut xml = String::new();
+    write!(
+        xml,
+        r#"<md:EntityDescriptor xmlns:md="{METADATA_NS}" entityID="{}"><md:IDPSSODescriptor WantAuthnRequestsSigned="false" protocolSupportEnumeration="{PROTOCOL_NS}">"#,
+        escape_attribute(entity_id),
+    )
+    .unwrap();
+    for certificate in certificates {
+        write!(
+            xml,
+            r#"<md:KeyDescriptor use="signing"><ds:KeyInfo xmlns:ds="{DSIG_NS}"><ds:X509Data><ds:X509Certificate>{}</ds:X509Certificate></ds:X509Data></ds:KeyInfo></md:KeyDescriptor>"#,
+            BASE64_STANDARD.encode(certificate),
+        )
+        .unwrap();
+    }
+    for format in [
+        crate::models::SamlNameIdFormat::Persistent,
+        crate::models::SamlNameIdFormat::EmailAddress,
+    ] {
+        write!(xml, "<md:NameIDFormat>{}</md:NameIDFormat>", format.uri()).unwrap();
+    }
+    write!(
+        xml,
+        r#"<md:SingleSignOnService Binding="{POST_BINDING}" Location="{}"></md:SingleSignOnService></md:IDPSSODescriptor></md:EntityDescriptor>"#,
+        escape_attribute(sso_url),
+    )
+    .unwrap();
+    xml
+}
+
+/// Builds an HTML page which posts the given base64-encoded response to the service provider's
+/// assertion consumer service as soon as it loads.
+#[must_use]
+pub fn post_form(acs_url: &str, response: &str, relay_state: Option<&str>) -> String {
+    let relay_state = relay_state.map_or_else(String::new, |relay_state| {
+        format!(
+            r#"<input type="hidden" name="RelayState" value="{}">"#,
+            escape_attribute(relay_state)
+        )
+    });
+    format!(
+        r#"<!DOCTYPE html><html><head><meta charset="utf-8"><title>Signing in</title></head><body onload="document.forms[0].submit()"><form method="post" action="{}"><input type="hidden" name="SAMLResponse" value="{}">{relay_state}<noscript><button type="submit">Continue</button></noscript></form></body></html>"#,
+        escape_attribute(acs_url),
+        escape_attribute(response),
+    )
+}
+
+/// Returns a random ID for a message or assertion. IDs must not start with a digit.
+fn random_id() -> String {
+    format!("_{}", new_uuid().simple())
+}
+
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Escapes text content as done by canonicalization
+fn escape_text(text: &str) -> Cow<'_, str> {
+    escape(text, |c| match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '\r' => Some("&#xD;"),
+        _ => None,
+    })
+}
+
+/// Escapes an attribute value as done by canonicalization. The result is also safe to use in
+/// double-quoted HTML attributes.
+fn escape_attribute(value: &str) -> Cow<'_, str> {
+    escape(value, |c| match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '"' => Some("&quot;"),
+        '\t' => Some("&#x9;"),
+        '\n' => Some("&#xA;"),
+        '\r' => Some("&#xD;"),
+        _ => None,
+    })
+}
+
+fn escape(value: &str, replacement: impl Fn(char) -> Option<&'static str>) -> Cow<'_, str> {
+    if !value.chars().any(|c| replacement(c).is_some()) {
+        return Cow::Borrowed(value);
+    }
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match replacement(c) {
+            Some(replacement) => escaped.push_str(replacement),
+            None => escaped.push(c),
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// Returns the name of an element without its namespace prefix.
+fn local_name(name: &str) -> &str {
+    name.rsplit_once(':').map_or(name, |(_, local)| local)
+}
+
+enum Token<'a> {
+    Start {
+        name: &'a str,
+        attributes: Vec<(&'a str, String)>,
+        empty: bool,
+    },
+    End,
+    Text(String),
+}
+
+/// Splits an XML document into tags and text, skipping comments and processing instructions
+struct Tokenizer<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn next_token(&mut self) -> Result<Option<Token<'a>>, SamlError> {
+        loop {
+            if self.rest.is_empty() {
+                return Ok(None);
+            } else if let Some(rest) = self.rest.strip_prefix("<?") {
+                self.rest = skip_past(rest, "?>")?;
+            } else if let Some(rest) = self.rest.strip_prefix("<!--") {
+                self.rest = skip_past(rest, "-->")?;
+            } else if self.rest.starts_with("<!") {
+                return Err(SamlError(
+                    "document type declarations and CDATA are not supported",
+                ));
+            } else if let Some(rest) = self.rest.strip_prefix("</") {
+                self.rest = skip_past(rest, ">")?;
+                return Ok(Some(Token::End));
+            } else if let Some(rest) = self.rest.strip_prefix('<') {
+                self.rest = rest;
+                return self.start_tag().map(Some);
+            } else {
+                let end = self.rest.find('<').unwrap_or(self.rest.len());
+                let (text, rest) = self.rest.split_at(end);
+                self.rest = rest;
+                return Ok(Some(Token::Text(decode_entities(text)?)));
+            }
+        }
+    }
+
+    /// Parses a start tag, after its opening `<`
+    fn start_tag(&mut self) -> Result<Token<'a>, SamlError> {
+        let end = self
+            .rest
+            .find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+            .ok_or(SamlError("unclosed tag"))?;
+        let (name, mut rest) = self.rest.split_at(end);
+        let mut attributes = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix("/>") {
+                self.rest = after;
+                return Ok(Token::Start {
+                    name,
+                    attributes,
+                    empty: true,
+                });
+            } else if let Some(after) = rest.strip_prefix('>') {
+                self.rest = after;
+                return Ok(Token::Start {
+                    name,
+                    attributes,
+                    empty: false,
+                });
+            }
+            let (attribute, after) = rest.split_once('=').ok_or(SamlError("invalid attribute"))?;
+            let after = after.trim_start();
+            let quote = after
+                .chars()
+                .next()
+                .filter(|c| *c == '"' || *c == '\'')
+                .ok_or(SamlError("unquoted attribute value"))?;
+            let (value, after) = after[1..]
+                .split_once(quote)
+                .ok_or(SamlError("unterminated attribute value"))?;
+            attributes.push((attribute.trim(), decode_entities(value)?));
+            rest = after;
+        }
+    }
+
+    /// Reads the text content of the current element, up to and including its end tag.
+    fn text_content(&mut self) -> Result<String, SamlError> {
+        let mut content = String::new();
+        loop {
+            match self.next_token()? {
+                Some(Token::Text(text)) => content.push_str(&text),
+                Some(Token::End) => return Ok(content),
+                Some(Token::Start { .. }) => return Err(SamlError("unexpected element")),
+                None => return Err(SamlError("unexpected end of document")),
+            }
+        }
+    }
+}
+
+fn skip_past<'a>(text: &'a str, terminator: &str) -> Result<&'a str, SamlError> {
+    text.split_once(terminator)
+        .map(|(_, rest)| rest)
+        .ok_or(SamlError("unterminated markup"))
+}
+
+/// Replaces the predefined entities and character references in the given text.
+fn decode_entities(text: &str) -> Result<String, SamlError> {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some((before, after)) = rest.split_once('&') {
+        decoded.push_str(before);
+        let (entity, after) = after
+            .split_once(';')
+            .ok_or(SamlError("unterminated entity reference"))?;
+        let c = match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32)
+                .ok_or(SamlError("unknown entity"))?,
+        };
+        decoded.push(c);
+        rest = after;
+    }
+    decoded.push_str(rest);
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<samlp:AuthnRequest xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol"
+    xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion"
+    ID="_r&amp;1" Version="2.0" IssueInstant="2026-10-17T00:00:00Z"
+    AssertionConsumerServiceURL='https://sp.example.com/acs?a=1&amp;b=2'>
+  <!-- comment -->
+  <saml:Issuer> https://sp.example.com </saml:Issuer>
+  <samlp:NameIDPolicy AllowCreate="true"/>
+  <samlp:Extensions><saml:Issuer>nested</saml:Issuer></samlp:Extensions>
+</samlp:AuthnRequest>"#;
+
+    #[test]
+    fn test_parse_authn_request() {
+        let request = AuthnRequest::decode(&BASE64_STANDARD.encode(REQUEST)).unwrap();
+        assert_eq!(
+            request,
+            AuthnRequest {
+                id: "_r&1".to_string(),
+                issuer: "https://sp.example.com".to_string(),
+                acs_url: Some("https://sp.example.com/acs?a=1&b=2".to_string()),
+            }
+        );
+
+        let doctype = format!(
+            "<!DOCTYPE x [<!ENTITY e \"e\">]>{}",
+            REQUEST.replace("<!-- comment -->", "")
+        );
+        assert!(AuthnRequest::parse(&doctype).is_err());
+        assert!(AuthnRequest::parse(&REQUEST.replace("&amp;", "&e;")).is_err());
+        assert!(AuthnRequest::parse(&REQUEST.replace("</samlp:AuthnRequest>", "")).is_err());
+        assert!(AuthnRequest::parse("<samlp:LogoutRequest ID=\"x\"/>").is_err());
+    }
+
+    /// Extracts the text of the first element with the given tag.
+    fn element_text<'a>(xml: &'a str, tag: &str) -> &'a str {
+        let start = xml.find(&format!("<{tag}>")).unwrap() + tag.len() + 2;
+        let end = start + xml[start..].find(&format!("</{tag}>")).unwrap();
+        &xml[start..end]
+    }
+
+    #[test]
+    fn test_signed_response() {
+        let key = SigningKey::generate().unwrap();
+        let request = AuthnRequest {
+            id: "_request".to_string(),
+            issuer: "sp".to_string(),
+            acs_url: None,
+        };
+        let attributes = [("tags", vec!["a<b".to_string(), "c".to_string()])];
+        let xml = SamlResponse {
+            issuer: "https://idp.example.com",
+            request: &request,
+            audience: "sp",
+            acs_url: "https://sp.example.com/acs",
+            name_id_format: crate::models::SamlNameIdFormat::EmailAddress.uri(),
+            name_id: "user@example.com",
+            authn_instant: Utc::now(),
+            attributes: &attributes,
+        }
+        .sign(&key)
+        .unwrap();
+        assert!(xml.contains(r#"InResponseTo="_request""#));
+        assert!(xml.contains("<saml:AttributeValue>a&lt;b</saml:AttributeValue>"));
+
+        // The digest covers the assertion without its signature
+        let start = xml.find("<saml:Assertion").unwrap();
+        let end = xml.find("</saml:Assertion>").unwrap() + "</saml:Assertion>".len();
+        let signature_start = xml.find("<ds:Signature").unwrap();
+        let signature_end = xml.find("</ds:Signature>").unwrap() + "</ds:Signature>".len();
+        let unsigned = format!(
+            "{}{}",
+            &xml[start..signature_start],
+            &xml[signature_end..end]
+        );
+        assert_eq!(
+            element_text(&xml, "ds:DigestValue"),
+            BASE64_STANDARD.encode(sha256(unsigned.as_bytes()))
+        );
+
+        let signed_info_start = xml.find("<ds:SignedInfo").unwrap();
+        let signed_info_end = xml.find("</ds:SignedInfo>").unwrap() + "</ds:SignedInfo>".len();
+        let signature = BASE64_STANDARD
+            .decode(element_text(&xml, "ds:SignatureValue"))
+            .unwrap();
+        assert!(
+            key.verify_bytes(
+                &xml.as_bytes()[signed_info_start..signed_info_end],
+                &signature
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            BASE64_STANDARD
+                .decode(element_text(&xml, "ds:X509Certificate"))
+                .unwrap(),
+            key.certificate_der().unwrap()
+        );
+    }
+}
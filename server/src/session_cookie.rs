@@ -0,0 +1,478 @@
+//! # Session cookies
+//!
+//! A session is identified by a random secret which only the client's session cookie holds. The
+//! database stores the secret's hash, so the sessions in a leaked database can't be used: a
+//! cookie holding a stored hash is rejected, since its hash doesn't match any session.
+//!
+//! Cookie values are also signed with HMAC-SHA256, so that values which the server didn't issue
+//! are rejected before the database is queried. Values have the form
+//! `<version>.<key ID>.<base64 secret>.<base64 signature>`, where the version names the format
+//! of the rest of the value, so that the format can change without misreading old cookies. The
+//! only version is currently [`TOKEN_VERSION`].
+//!
+//! Values are always signed with the active key of the [`SessionCookieKeys`], but are accepted
+//! if they are signed by any of its keys, so keys can be rotated by adding a new active key and
+//! keeping the old ones until the sessions issued before the rotation have expired.
+//!
+//! If no keys are configured, the server [loads][SessionCookieKeys::load] a key from the database,
+//! generating it the first time, so that sessions survive restarts and are shared by instances.
+//!
+//! The cookie can be given the `__Host-` prefix, with which browsers only accept it if it is
+//! secure, has no domain, and has the path `/`, so that other hosts and paths can't overwrite it.
+
+use std::{collections::HashMap, sync::Arc};
+
+use base64::{
+    Engine,
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+};
+use chrono::Utc;
+use cookie::{Cookie, SameSite, time::Duration};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use tracing::info;
+
+use crate::{
+    crypto::{CryptoError, KEY_LEN, decode_key, split_key_spec},
+    db::interface::{DatabaseClient, DatabaseError},
+    models::{EncodableHash, StoredSessionCookieKey, ViaJson},
+};
+
+/// Name of the session cookie, without the `__Host-` prefix
+pub const SESSION_COOKIE: &str = "session_id";
+
+/// Prefix of the session cookie's name when [`SessionCookieOptions::host_prefix`] is set
+const HOST_PREFIX: &str = "__Host-";
+
+/// Version of the format of the cookie values issued by this version of the server
+pub const TOKEN_VERSION: &str = "v1";
+
+/// Length of session secrets in bytes
+const SECRET_LEN: usize = 32;
+
+/// Errors which can occur while loading the stored session cookie key
+#[derive(Debug, thiserror::Error)]
+pub enum SessionCookieKeyError {
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+
+    #[error(transparent)]
+    Crypto(#[from] CryptoError),
+}
+
+/// # Secret session ID
+///
+/// Held only by the client's session cookie. Sessions are stored under the [hash][Self::hash] of
+/// their secret.
+pub struct SessionSecret([u8; SECRET_LEN]);
+
+impl SessionSecret {
+    /// Generates a new random secret.
+    #[must_use]
+    pub fn generate() -> Self {
+        let mut secret = [0u8; SECRET_LEN]; // 256 bits
+        rand::rng().fill_bytes(&mut secret);
+        Self(secret)
+    }
+
+    /// Returns the hash under which the session is stored.
+    #[must_use]
+    pub fn hash(&self) -> EncodableHash {
+        EncodableHash(blake3::hash(&self.0))
+    }
+}
+
+impl std::fmt::Debug for SessionSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Don't leak the secret into logs
+        f.debug_tuple("SessionSecret").field(&self.hash()).finish()
+    }
+}
+
+/// # Keys with which session cookies are signed
+///
+/// See the [module-level documentation][self] for details.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SessionCookieKeys {
+    /// ID of the key used for signing
+    active: String,
+    keys: HashMap<String, [u8; KEY_LEN]>,
+}
+
+impl std::fmt::Debug for SessionCookieKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Don't leak the keys into logs
+        f.debug_struct("SessionCookieKeys")
+            .field("active", &self.active)
+            .field("keys", &self.keys.keys())
+            .finish()
+    }
+}
+
+impl SessionCookieKeys {
+    /// Creates a key set which signs values with the given key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key ID isn't [valid][is_valid_key_id].
+    #[must_use]
+    pub fn new(id: impl Into<String>, key: [u8; KEY_LEN]) -> Self {
+        let id = id.into();
+        assert!(is_valid_key_id(&id), "invalid session cookie key ID `{id}`");
+        Self {
+            keys: HashMap::from([(id.clone(), key)]),
+            active: id,
+        }
+    }
+
+    /// Creates a key set with a single random key, e.g. for a server whose sessions needn't
+    /// outlive it.
+    #[must_use]
+    pub fn generate() -> Self {
+        let mut key = [0u8; KEY_LEN];
+        rand::rng().fill_bytes(&mut key);
+        Self::new("generated", key)
+    }
+
+    /// Loads the key stored in the given database, storing a random key first if there is none.
+    pub async fn load(db: &dyn DatabaseClient) -> Result<Self, SessionCookieKeyError> {
+        let mut key = [0u8; KEY_LEN];
+        rand::rng().fill_bytes(&mut key);
+        let generated = StoredSessionCookieKey {
+            kid: "stored".to_string(),
+            key: ViaJson(BASE64_STANDARD.encode(key)),
+            created_at: Utc::now(),
+        };
+        let stored = db.get_or_create_session_cookie_key(&generated).await?;
+        if !is_valid_key_id(&stored.kid) {
+            return Err(CryptoError::InvalidKeySpec(format!(
+                "stored key ID `{}` is invalid",
+                stored.kid
+            ))
+            .into());
+        }
+        let key = decode_key(&stored.kid, &stored.key)?;
+        info!(kid = %stored.kid, "loaded stored session cookie key");
+        Ok(Self::new(stored.kid, key))
+    }
+
+    /// Adds a key which is only used to verify values, e.g. a key which was rotated out.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key ID isn't [valid][is_valid_key_id].
+    #[must_use]
+    pub fn with_key(mut self, id: impl Into<String>, key: [u8; KEY_LEN]) -> Self {
+        let id = id.into();
+        assert!(is_valid_key_id(&id), "invalid session cookie key ID `{id}`");
+        self.keys.entry(id).or_insert(key);
+        self
+    }
+
+    /// Returns the ID of the key used for signing.
+    #[must_use]
+    pub fn active_key_id(&self) -> &str {
+        &self.active
+    }
+
+    /// Parses a key set from a comma-separated list of `<key ID>:<base64 key>` pairs. The first
+    /// key is the active one.
+    pub fn parse(spec: &str) -> Result<Self, CryptoError> {
+        let mut keyring: Option<Self> = None;
+        for (id, key) in split_key_spec(spec)? {
+            if !is_valid_key_id(id) {
+                return Err(CryptoError::InvalidKeySpec(format!(
+                    "key ID `{id}` may only contain letters, digits, `-`, and `_`"
+                )));
+            }
+            let key = decode_key(id, key)?;
+            keyring = Some(match keyring {
+                None => Self::new(id, key),
+                Some(keyring) => keyring.with_key(id, key),
+            });
+        }
+        keyring.ok_or_else(|| CryptoError::InvalidKeySpec("no keys given".to_string()))
+    }
+
+    /// Returns the MAC of the given signed part of a cookie value with the given key.
+    fn mac(key: &[u8; KEY_LEN], signed: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(signed.as_bytes());
+        mac
+    }
+}
+
+/// Returns whether the given string can be the ID of a [session cookie key][SessionCookieKeys].
+/// IDs are part of cookie values, so they are limited to ASCII letters, digits, `-`, and `_`.
+#[must_use]
+pub fn is_valid_key_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')
+}
+
+/// Options for issuing session cookies
+#[derive(Debug, Clone, Default)]
+pub struct SessionCookieOptions {
+    /// Whether the cookie's name has the `__Host-` prefix. The cookie's path is then `/`, even if
+    /// the app is served under a base path.
+    pub host_prefix: bool,
+    /// Keys with which cookie values are signed. If [`None`], a key is generated and kept in
+    /// memory, so the server should [load][SessionCookieKeys::load] the stored key instead.
+    pub keys: Option<Arc<SessionCookieKeys>>,
+}
+
+/// Reason for which a session cookie's value was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum InvalidSessionCookie {
+    /// The value isn't of any known format, e.g. because it was issued by an older version of
+    /// the server.
+    #[error("session cookie has an unknown format")]
+    UnknownFormat,
+
+    /// The value was signed with a key which isn't in the key set, e.g. one which was retired.
+    #[error("session cookie was signed with an unknown key")]
+    UnknownKey,
+
+    /// The value's signature doesn't match its contents.
+    #[error("session cookie has an invalid signature")]
+    BadSignature,
+}
+
+/// # Session cookie issuer
+///
+/// Creates and verifies session cookies. See the [module-level documentation][self] for details.
+#[derive(Debug, Clone)]
+pub struct SessionCookies {
+    name: String,
+    path: String,
+    keys: Arc<SessionCookieKeys>,
+}
+
+impl SessionCookies {
+    /// Creates an issuer with the given options for cookies scoped to the given path. If the
+    /// options have no keys, a key is generated.
+    #[must_use]
+    pub fn new(options: &SessionCookieOptions, path: &str) -> Self {
+        let (name, path) = if options.host_prefix {
+            (format!("{HOST_PREFIX}{SESSION_COOKIE}"), "/")
+        } else {
+            (SESSION_COOKIE.to_string(), path)
+        };
+        Self {
+            name,
+            path: path.to_string(),
+            keys: options
+                .keys
+                .clone()
+                .unwrap_or_else(|| Arc::new(SessionCookieKeys::generate())),
+        }
+    }
+
+    /// Returns the name of the session cookie.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the session cookie holding the given secret, which lasts for the given time.
+    #[must_use]
+    pub fn cookie(&self, secret: &SessionSecret, max_age: Duration) -> Cookie<'static> {
+        let mut cookie = self.removal_cookie();
+        cookie.set_value(self.encode(secret));
+        cookie.set_max_age(max_age);
+        cookie
+    }
+
+    /// Returns a cookie with which to remove the session cookie from a cookie jar.
+    #[must_use]
+    pub fn removal_cookie(&self) -> Cookie<'static> {
+        Cookie::build((self.name.clone(), ""))
+            .same_site(SameSite::Strict)
+            .http_only(true)
+            .secure(true)
+            .path(self.path.clone())
+            .build()
+    }
+
+    /// Encodes and signs the given secret as a cookie value.
+    #[must_use]
+    pub fn encode(&self, secret: &SessionSecret) -> String {
+        let signed = format!(
+            "{TOKEN_VERSION}.{}.{}",
+            self.keys.active,
+            BASE64_URL_SAFE_NO_PAD.encode(secret.0)
+        );
+        let mac = SessionCookieKeys::mac(&self.keys.keys[&self.keys.active], &signed);
+        let signature = BASE64_URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        format!("{signed}.{signature}")
+    }
+
+    /// Verifies the given cookie value and returns the hash of the session secret which it holds.
+    pub fn verify(&self, value: &str) -> Result<EncodableHash, InvalidSessionCookie> {
+        let (signed, signature) = value
+            .rsplit_once('.')
+            .ok_or(InvalidSessionCookie::UnknownFormat)?;
+        let mut parts = signed.split('.');
+        let (Some(TOKEN_VERSION), Some(key_id), Some(secret), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(InvalidSessionCookie::UnknownFormat);
+        };
+        let secret = BASE64_URL_SAFE_NO_PAD
+            .decode(secret)
+            .ok()
+            .and_then(|secret| <[u8; SECRET_LEN]>::try_from(secret).ok())
+            .ok_or(InvalidSessionCookie::UnknownFormat)?;
+        let signature = BASE64_URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| InvalidSessionCookie::UnknownFormat)?;
+        let key = self
+            .keys
+            .keys
+            .get(key_id)
+            .ok_or(InvalidSessionCookie::UnknownKey)?;
+        // Compares the signatures in constant time
+        SessionCookieKeys::mac(key, signed)
+            .verify_slice(&signature)
+            .map_err(|_| InvalidSessionCookie::BadSignature)?;
+        Ok(SessionSecret(secret).hash())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::clients::sqlite::SqliteClient;
+
+    fn cookies(keys: SessionCookieKeys) -> SessionCookies {
+        SessionCookies::new(
+            &SessionCookieOptions {
+                host_prefix: false,
+                keys: Some(Arc::new(keys)),
+            },
+            "/app",
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let cookies = cookies(SessionCookieKeys::new("k1", [1; KEY_LEN]));
+        let secret = SessionSecret::generate();
+        let value = cookies.encode(&secret);
+        assert!(value.starts_with("v1.k1."), "{value}");
+        assert_eq!(*cookies.verify(&value).unwrap(), *secret.hash());
+
+        let cookie = cookies.cookie(&secret, Duration::hours(1));
+        assert_eq!(cookie.name(), "session_id");
+        assert_eq!(cookie.path(), Some("/app"));
+        assert_eq!(cookie.max_age(), Some(Duration::hours(1)));
+        assert_eq!(*cookies.verify(cookie.value()).unwrap(), *secret.hash());
+    }
+
+    #[test]
+    fn test_rejects_forged_values() {
+        let cookies = cookies(SessionCookieKeys::new("k1", [1; KEY_LEN]));
+        let secret = SessionSecret::generate();
+        let value = cookies.encode(&secret);
+
+        // The hash of a session, as stored in the database, isn't a valid cookie
+        assert_eq!(
+            cookies.verify(&secret.hash().0.to_string()).err(),
+            Some(InvalidSessionCookie::UnknownFormat),
+        );
+
+        // Nor is a value signed with another key, or whose secret was replaced
+        let other = SessionCookies::new(
+            &SessionCookieOptions {
+                host_prefix: false,
+                keys: Some(Arc::new(SessionCookieKeys::new("k1", [2; KEY_LEN]))),
+            },
+            "/",
+        );
+        assert_eq!(
+            cookies.verify(&other.encode(&secret)).err(),
+            Some(InvalidSessionCookie::BadSignature),
+        );
+        let (signed, signature) = value.rsplit_once('.').unwrap();
+        let forged = format!(
+            "{}.{}.{signature}",
+            signed.rsplit_once('.').unwrap().0,
+            BASE64_URL_SAFE_NO_PAD.encode([0; SECRET_LEN]),
+        );
+        assert_eq!(
+            cookies.verify(&forged).err(),
+            Some(InvalidSessionCookie::BadSignature),
+        );
+        assert_eq!(
+            cookies.verify(&value.replacen("v1.", "v2.", 1)).err(),
+            Some(InvalidSessionCookie::UnknownFormat),
+        );
+        assert_eq!(
+            cookies.verify(&value.replacen(".k1.", ".k2.", 1)).err(),
+            Some(InvalidSessionCookie::UnknownKey),
+        );
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let old = cookies(SessionCookieKeys::new("k1", [1; KEY_LEN]));
+        let secret = SessionSecret::generate();
+        let value = old.encode(&secret);
+
+        // After a new key is added, values signed with the old key are still accepted, but new
+        // values are signed with the new key
+        let keys = SessionCookieKeys::parse(&format!(
+            "k2:{},k1:{}",
+            base64::prelude::BASE64_STANDARD.encode([2; KEY_LEN]),
+            base64::prelude::BASE64_STANDARD.encode([1; KEY_LEN]),
+        ))
+        .unwrap();
+        let new = cookies(keys);
+        assert_eq!(*new.verify(&value).unwrap(), *secret.hash());
+        assert!(new.encode(&secret).starts_with("v1.k2."));
+
+        // Once the old key is removed, its values are rejected
+        let new = cookies(SessionCookieKeys::new("k2", [2; KEY_LEN]));
+        assert_eq!(
+            new.verify(&value).err(),
+            Some(InvalidSessionCookie::UnknownKey),
+        );
+
+        assert!(SessionCookieKeys::parse("k.1:AAAA").is_err());
+        assert!(SessionCookieKeys::parse("").is_err());
+    }
+
+    #[test]
+    fn test_host_prefix() {
+        let cookies = SessionCookies::new(
+            &SessionCookieOptions {
+                host_prefix: true,
+                keys: None,
+            },
+            "/app",
+        );
+        let cookie = cookies.cookie(&SessionSecret::generate(), Duration::hours(1));
+        assert_eq!(cookie.name(), "__Host-session_id");
+        assert_eq!(cookie.path(), Some("/"));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.domain(), None);
+        assert_eq!(cookies.removal_cookie().name(), "__Host-session_id");
+    }
+
+    #[tokio::test]
+    async fn test_load() {
+        let db = SqliteClient::new_memory().await.unwrap();
+        let first = cookies(SessionCookieKeys::load(&db).await.unwrap());
+        let second = cookies(SessionCookieKeys::load(&db).await.unwrap());
+
+        // Instances which load the stored key accept each other's values
+        let secret = SessionSecret::generate();
+        assert_eq!(
+            second.verify(&first.encode(&secret)).unwrap().0,
+            secret.hash().0
+        );
+    }
+}
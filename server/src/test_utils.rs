@@ -0,0 +1,568 @@
+//! # Integration test utilities
+//!
+//! [`TestApp`] runs the full API router against an in-memory database, and provides helpers for
+//! creating users, minting sessions without going through a `WebAuthn` ceremony, and calling
+//! endpoints. Requests are handled in-process, so no network listener is needed.
+//!
+//! Only available with the `test-utils` feature.
+//!
+//! ```no_run
+//! # async fn example() {
+//! use iam_server::test_utils::TestApp;
+//!
+//! let app = TestApp::new().await;
+//! let user = app.create_user("user@example.com", "User").await;
+//! let session = app.create_session(&user, false).await;
+//! let response = app.get("/api/v1/users/me", Some(&session)).await;
+//! assert!(response.status.is_success());
+//! # }
+//! ```
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    http::{
+        HeaderMap, Method, Request, StatusCode,
+        header::{AUTHORIZATION, CONTENT_TYPE, COOKIE, SET_COOKIE},
+    },
+};
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use cookie::Cookie;
+use rand::RngCore;
+use serde::{Serialize, de::DeserializeOwned};
+use tower::ServiceExt;
+use webauthn_rs::{Webauthn, WebauthnBuilder};
+
+use crate::{
+    admin::grant_admin,
+    api::{ApiOptions, new_api_router},
+    db::{clients::sqlite::SqliteClient, interface::DatabaseClient},
+    email::{EmailTemplates, Mailer, Message, Transport, TransportError},
+    models::{
+        AppConfig, Branding, FeatureFlags, NewPasskeyCredential, PasskeyCredential,
+        RegistrationMode, Session, SessionLifetimes, SessionState, User, UserCreate, new_uuid,
+    },
+    relying_party::RelyingParties,
+    session_cookie::{SessionCookieKeys, SessionCookies, SessionSecret},
+};
+
+/// Origin used for `WebAuthn` in test apps
+pub const TEST_ORIGIN: &str = "http://localhost:3000";
+
+/// # In-process test application
+///
+/// See the [module-level documentation][self] for details.
+pub struct TestApp {
+    db: Arc<dyn DatabaseClient>,
+    router: Router,
+    session_cookies: SessionCookies,
+}
+
+impl TestApp {
+    /// Creates an app with default [options][ApiOptions], i.e. without email or alerts.
+    pub async fn new() -> Self {
+        Self::with_options(ApiOptions::default()).await
+    }
+
+    /// Creates an app with the given options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the in-memory database can't be created.
+    pub async fn with_options(options: ApiOptions) -> Self {
+        Self::with_config(&test_config(), options).await
+    }
+
+    /// Creates an app with the given app configuration and options.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the in-memory database can't be created.
+    pub async fn with_config(config: &AppConfig, options: ApiOptions) -> Self {
+        let db: Arc<dyn DatabaseClient> = Arc::new(
+            SqliteClient::new_memory()
+                .await
+                .expect("failed to create in-memory database"),
+        );
+        Self::build(db, test_webauthn().into(), config, options)
+    }
+
+    /// Creates an app which serves the given `WebAuthn` relying parties.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the in-memory database can't be created.
+    pub async fn with_relying_parties(relying_parties: RelyingParties) -> Self {
+        let db: Arc<dyn DatabaseClient> = Arc::new(
+            SqliteClient::new_memory()
+                .await
+                .expect("failed to create in-memory database"),
+        );
+        Self::build(db, relying_parties, &test_config(), ApiOptions::default())
+    }
+
+    /// Creates an app which uses the given database client.
+    #[must_use]
+    pub fn with_db(db: Arc<dyn DatabaseClient>, options: ApiOptions) -> Self {
+        Self::build(db, test_webauthn().into(), &test_config(), options)
+    }
+
+    fn build(
+        db: Arc<dyn DatabaseClient>,
+        relying_parties: RelyingParties,
+        config: &AppConfig,
+        mut options: ApiOptions,
+    ) -> Self {
+        // Share the app's keys, so that sessions created by the test can be used with it
+        options
+            .session_cookies
+            .keys
+            .get_or_insert_with(|| Arc::new(SessionCookieKeys::generate()));
+        let session_cookies = SessionCookies::new(&options.session_cookies, "/");
+        let (api, _) = new_api_router(Arc::clone(&db), relying_parties, config, options);
+        Self {
+            db,
+            router: Router::new().nest("/api", api),
+            session_cookies,
+        }
+    }
+
+    /// Returns the app's database client.
+    #[must_use]
+    pub fn db(&self) -> &Arc<dyn DatabaseClient> {
+        &self.db
+    }
+
+    /// Returns the issuer of the app's session cookies, e.g. to verify a session cookie set by a
+    /// response.
+    #[must_use]
+    pub fn session_cookies(&self) -> &SessionCookies {
+        &self.session_cookies
+    }
+
+    /// Creates a user with the given email address and display name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the user can't be created, e.g. because the email address is taken.
+    pub async fn create_user(&self, email: &str, display_name: &str) -> User {
+        self.db
+            .create_user(
+                &new_uuid(),
+                &UserCreate {
+                    email: email.to_string(),
+                    display_name: display_name.to_string(),
+                },
+            )
+            .await
+            .expect("failed to create user")
+    }
+
+    /// Marks the user's email address as verified, as if they had confirmed it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a database operation fails.
+    pub async fn verify_email(&self, user: &User) -> User {
+        let token_hash = blake3::hash(new_uuid().as_bytes()).into();
+        self.db
+            .create_email_change(user.id(), user.email(), &token_hash)
+            .await
+            .expect("failed to create email change");
+        self.db
+            .confirm_email_change(&token_hash, Duration::from_secs(60))
+            .await
+            .expect("failed to confirm email change")
+    }
+
+    /// Gives the user the administrator tag, creating the tag if it doesn't exist.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a database operation fails.
+    pub async fn make_admin(&self, user: &User) {
+        grant_admin(&*self.db, user.id())
+            .await
+            .expect("failed to add admin tag");
+    }
+
+    /// Adds a passkey to the given user's account. The passkey has a random credential ID, but
+    /// can't actually be used to log in.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the passkey can't be stored.
+    pub async fn create_passkey(
+        &self,
+        user: &User,
+        display_name: Option<&str>,
+    ) -> PasskeyCredential {
+        let mut passkey: serde_json::Value = serde_json::from_str(include_str!(
+            "db/clients/sqlite/tests/resources/passkey.json"
+        ))
+        .expect("invalid passkey resource");
+        let mut credential_id = [0u8; 16];
+        rand::rng().fill_bytes(&mut credential_id);
+        passkey["cred"]["cred_id"] = BASE64_URL_SAFE_NO_PAD.encode(credential_id).into();
+        self.db
+            .create_passkey(
+                &new_uuid(),
+                user.id(),
+                &NewPasskeyCredential {
+                    display_name: display_name.map(ToString::to_string),
+                    passkey: serde_json::from_value(passkey).expect("invalid passkey resource"),
+                },
+            )
+            .await
+            .expect("failed to create passkey")
+    }
+
+    /// Creates an active session for the given user, as if they had logged in (and, if
+    /// `is_admin` is set, upgraded to an administrator session).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the session can't be stored.
+    pub async fn create_session(&self, user: &User, is_admin: bool) -> TestSession {
+        let secret = SessionSecret::generate();
+        let now = chrono::Utc::now();
+        let session = Session {
+            id_hash: secret.hash(),
+            user_id: *user.id(),
+            state: SessionState::Active,
+            created_at: now,
+            expires_at: now + chrono::Duration::days(1),
+            is_admin,
+            parent_id_hash: None,
+            ip: None,
+            user_agent: None,
+            last_seen_at: now,
+            impersonator_id: None,
+            authenticated_at: now,
+            organization_id: None,
+        };
+        self.db
+            .create_session(&session)
+            .await
+            .expect("failed to create session");
+        TestSession {
+            cookie: format!(
+                "{}={}",
+                self.session_cookies.name(),
+                self.session_cookies.encode(&secret)
+            ),
+            session,
+        }
+    }
+
+    /// Sends the given request to the app and returns its response.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the response body can't be read.
+    pub async fn request(&self, request: Request<Body>) -> TestResponse {
+        let response = self
+            .router
+            .clone()
+            .oneshot(request)
+            .await
+            .unwrap_or_else(|err| match err {});
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .expect("failed to read response body");
+        TestResponse {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    /// Sends a request with the given method, path, and optional JSON body, authenticated with
+    /// the given session if there is one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the body can't be serialized.
+    pub async fn send<B>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+        session: Option<&TestSession>,
+    ) -> TestResponse
+    where
+        B: Serialize + ?Sized,
+    {
+        let cookie = session.map(|session| session.cookie.as_str());
+        self.send_with_cookie(method, path, body, cookie).await
+    }
+
+    /// Sends a request with the given method, path, and optional JSON body, with the given value
+    /// for the `Cookie` header if there is one. Used for endpoints which depend on cookies other
+    /// than the session cookie, e.g. `WebAuthn` ceremonies.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the body can't be serialized.
+    pub async fn send_with_cookie<B>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+        cookie: Option<&str>,
+    ) -> TestResponse
+    where
+        B: Serialize + ?Sized,
+    {
+        let mut request = Request::builder().method(method).uri(path);
+        if let Some(cookie) = cookie {
+            request = request.header(COOKIE, cookie);
+        }
+        let request = match body {
+            Some(body) => request
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    serde_json::to_vec(body).expect("failed to serialize request body"),
+                )),
+            None => request.body(Body::empty()),
+        };
+        self.request(request.expect("invalid request")).await
+    }
+
+    /// Sends a request with the given method, path, and optional JSON body, authenticated with
+    /// the given API token.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the body can't be serialized.
+    pub async fn send_with_token<B>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+        token: &str,
+    ) -> TestResponse
+    where
+        B: Serialize + ?Sized,
+    {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(AUTHORIZATION, format!("Bearer {token}"));
+        let request = match body {
+            Some(body) => request
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from(
+                    serde_json::to_vec(body).expect("failed to serialize request body"),
+                )),
+            None => request.body(Body::empty()),
+        };
+        self.request(request.expect("invalid request")).await
+    }
+
+    /// Sends a `POST` request with the given fields as a URL-encoded form body, with the given
+    /// value for the `Authorization` header if there is one. Used for OAuth endpoints.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the request can't be built.
+    pub async fn post_form(
+        &self,
+        path: &str,
+        fields: &[(&str, &str)],
+        authorization: Option<&str>,
+    ) -> TestResponse {
+        let mut encoder = reqwest::Url::parse("http://localhost/").expect("valid URL");
+        encoder.query_pairs_mut().extend_pairs(fields);
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(path)
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded");
+        if let Some(authorization) = authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+        let request = request
+            .body(Body::from(encoder.query().unwrap_or_default().to_string()))
+            .expect("invalid request");
+        self.request(request).await
+    }
+
+    /// Sends a `GET` request. See [`TestApp::send()`].
+    pub async fn get(&self, path: &str, session: Option<&TestSession>) -> TestResponse {
+        self.send::<()>(Method::GET, path, None, session).await
+    }
+
+    /// Sends a `POST` request with a JSON body. See [`TestApp::send()`].
+    pub async fn post<B>(&self, path: &str, body: &B, session: Option<&TestSession>) -> TestResponse
+    where
+        B: Serialize + ?Sized,
+    {
+        self.send(Method::POST, path, Some(body), session).await
+    }
+
+    /// Sends a `PATCH` request with a JSON body. See [`TestApp::send()`].
+    pub async fn patch<B>(
+        &self,
+        path: &str,
+        body: &B,
+        session: Option<&TestSession>,
+    ) -> TestResponse
+    where
+        B: Serialize + ?Sized,
+    {
+        self.send(Method::PATCH, path, Some(body), session).await
+    }
+
+    /// Sends a `DELETE` request. See [`TestApp::send()`].
+    pub async fn delete(&self, path: &str, session: Option<&TestSession>) -> TestResponse {
+        self.send::<()>(Method::DELETE, path, None, session).await
+    }
+}
+
+/// Session created by [`TestApp::create_session()`]
+#[derive(Debug, Clone)]
+pub struct TestSession {
+    pub session: Session,
+    /// Value of the `Cookie` header which authenticates requests with this session
+    pub cookie: String,
+}
+
+/// Response returned by [`TestApp`] requests
+#[derive(Debug, Clone)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl TestResponse {
+    /// Deserializes the body as JSON.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the body isn't valid JSON for the type `T`.
+    #[must_use]
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body).unwrap_or_else(|err| {
+            panic!(
+                "invalid JSON response body ({err}): {}",
+                String::from_utf8_lossy(&self.body)
+            )
+        })
+    }
+
+    /// Returns the body as text.
+    #[must_use]
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    /// Returns the value of the cookie with the given name set by the response, if any.
+    #[must_use]
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.headers
+            .get_all(SET_COOKIE)
+            .iter()
+            .filter_map(|value| Cookie::parse(value.to_str().ok()?).ok())
+            .find(|cookie| cookie.name() == name)
+            .map(|cookie| cookie.value().to_string())
+    }
+}
+
+/// # Recording email transport
+///
+/// A [`Transport`] which keeps the messages it is given instead of delivering them, so that tests
+/// can read them, e.g. to follow the links in them.
+#[derive(Debug, Clone, Default)]
+pub struct TestMailbox {
+    messages: Arc<Mutex<Vec<Message>>>,
+}
+
+impl TestMailbox {
+    /// Returns a mailer which delivers emails to this mailbox, for use as the
+    /// [mailer][ApiOptions::mailer] of a test app.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the default email templates can't be loaded.
+    #[must_use]
+    pub fn mailer(&self) -> Arc<Mailer> {
+        let templates = EmailTemplates::new(&test_config(), TEST_ORIGIN, None)
+            .expect("failed to load email templates");
+        Arc::new(Mailer::new(
+            Arc::new(self.clone()),
+            templates,
+            "IAM <iam@localhost>".to_string(),
+        ))
+    }
+
+    /// Waits for a message to the given address and takes it out of the mailbox. Most emails are
+    /// sent in the background, so they may arrive after the response to the request which sent
+    /// them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no message to the address arrives within a second.
+    pub async fn receive(&self, to: &str) -> Message {
+        for _ in 0..100 {
+            {
+                let mut messages = self.messages.lock().unwrap();
+                if let Some(i) = messages.iter().position(|message| message.to == to) {
+                    return messages.remove(i);
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("no email to {to} arrived");
+    }
+}
+
+impl Transport for TestMailbox {
+    fn send<'a>(
+        &'a self,
+        message: &'a Message,
+    ) -> Pin<Box<dyn Future<Output = Result<(), TransportError>> + Send + 'a>> {
+        self.messages.lock().unwrap().push(message.clone());
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Returns the app configuration used by test apps.
+#[must_use]
+pub fn test_config() -> AppConfig {
+    AppConfig {
+        instance_name: "IAM".to_string(),
+        base_path: String::new(),
+        branding: Branding::default(),
+        features: FeatureFlags {
+            oidc: true,
+            ..FeatureFlags::default()
+        },
+        registration_mode: RegistrationMode::default(),
+        allowed_email_domains: Vec::new(),
+        session_lifetimes: SessionLifetimes::default(),
+    }
+}
+
+/// Returns a `WebAuthn` client for [`TEST_ORIGIN`].
+///
+/// # Panics
+///
+/// Panics if the client can't be built.
+#[must_use]
+pub fn test_webauthn() -> Webauthn {
+    WebauthnBuilder::new("localhost", &TEST_ORIGIN.parse().unwrap())
+        .unwrap()
+        .rp_name("IAM")
+        .build()
+        .unwrap()
+}
@@ -0,0 +1,155 @@
+//! # Expiring in-memory cache
+//!
+//! [`TtlCache`] is the map behind the server's in-process caches: the
+//! [caching database client][crate::db::clients::cached] and the API's session cache. Entries
+//! expire a fixed time after they're inserted, and the number of entries is bounded.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+/// Maximum number of entries in a cache. When a cache is full, expired entries are evicted, and
+/// if that doesn't free any space, the whole cache is cleared.
+const MAX_ENTRIES: usize = 10_000;
+
+/// # Expiring in-memory cache
+///
+/// See the [module-level documentation][self] for details.
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    inner: RwLock<TtlCacheInner<K, V>>,
+}
+
+struct TtlCacheInner<K, V> {
+    entries: HashMap<K, (Instant, V)>,
+    /// Number of invalidations so far, used to avoid caching values read before an invalidation
+    generation: u64,
+}
+
+impl<K: Hash + Eq, V: Clone> TtlCache<K, V> {
+    /// Creates a cache whose entries expire after `ttl`. A zero `ttl` disables the cache.
+    #[must_use]
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            inner: RwLock::new(TtlCacheInner {
+                entries: HashMap::new(),
+                generation: 0,
+            }),
+        }
+    }
+
+    /// Returns the cached value for the given key, if it hasn't expired.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let inner = self.inner.read().unwrap();
+        let (cached_at, value) = inner.entries.get(key)?;
+        (cached_at.elapsed() < self.ttl).then(|| value.clone())
+    }
+
+    /// Caches the given value under the given key.
+    pub fn insert(&self, key: K, value: V) {
+        let mut inner = self.inner.write().unwrap();
+        self.insert_locked(&mut inner, key, value);
+    }
+
+    /// Returns the cached value for the given key, or fetches and caches it. The fetched value
+    /// isn't cached if the cache was invalidated while it was being fetched, since it may
+    /// predate the change which caused the invalidation. Errors aren't cached.
+    pub async fn get_or_fetch<E>(
+        &self,
+        key: K,
+        fetch: impl Future<Output = Result<V, E>>,
+    ) -> Result<V, E> {
+        let generation = {
+            let inner = self.inner.read().unwrap();
+            if let Some((cached_at, value)) = inner.entries.get(&key)
+                && cached_at.elapsed() < self.ttl
+            {
+                return Ok(value.clone());
+            }
+            inner.generation
+        };
+        let value = fetch.await?;
+        let mut inner = self.inner.write().unwrap();
+        if inner.generation == generation {
+            self.insert_locked(&mut inner, key, value.clone());
+        }
+        Ok(value)
+    }
+
+    /// Removes the entry with the given key.
+    pub fn remove(&self, key: &K) {
+        let mut inner = self.inner.write().unwrap();
+        inner.generation += 1;
+        inner.entries.remove(key);
+    }
+
+    /// Removes the entries for which the predicate returns `false`.
+    pub fn retain(&self, mut predicate: impl FnMut(&K, &V) -> bool) {
+        let mut inner = self.inner.write().unwrap();
+        inner.generation += 1;
+        inner
+            .entries
+            .retain(|key, (_, value)| predicate(key, value));
+    }
+
+    /// Removes all entries.
+    pub fn clear(&self) {
+        self.retain(|_, _| false);
+    }
+
+    fn insert_locked(&self, inner: &mut TtlCacheInner<K, V>, key: K, value: V) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        if inner.entries.len() >= MAX_ENTRIES {
+            inner
+                .entries
+                .retain(|_, (cached_at, _)| cached_at.elapsed() < self.ttl);
+            if inner.entries.len() >= MAX_ENTRIES {
+                inner.entries.clear();
+            }
+        }
+        inner.entries.insert(key, (Instant::now(), value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_get_or_fetch() {
+        let cache = TtlCache::new(Duration::from_secs(60));
+        let fetched: Result<_, ()> = cache.get_or_fetch(1, async { Ok("fetched") }).await;
+        assert_eq!(fetched, Ok("fetched"));
+        let cached: Result<_, ()> = cache.get_or_fetch(1, async { Ok("refetched") }).await;
+        assert_eq!(cached, Ok("fetched"));
+        cache.remove(&1);
+        assert_eq!(cache.get(&1), None);
+
+        // Values fetched across an invalidation aren't cached
+        let fetch = async {
+            cache.clear();
+            Ok::<_, ()>("stale")
+        };
+        assert_eq!(cache.get_or_fetch(1, fetch).await, Ok("stale"));
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[tokio::test]
+    async fn test_expiry() {
+        let cache = TtlCache::new(Duration::from_millis(50));
+        cache.insert(1, "value");
+        assert_eq!(cache.get(&1), Some("value"));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(cache.get(&1), None);
+
+        let disabled = TtlCache::new(Duration::ZERO);
+        disabled.insert(1, "value");
+        assert_eq!(disabled.get(&1), None);
+    }
+}
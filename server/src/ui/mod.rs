@@ -0,0 +1,317 @@
+//! # Static UI file server
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    Router,
+    extract::{Request, State},
+    http::{
+        HeaderValue, StatusCode,
+        header::{CACHE_CONTROL, CONTENT_SECURITY_POLICY},
+    },
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use rand::RngCore;
+use tower_http::services::ServeDir;
+use tracing::{error, info, warn};
+
+use crate::models::AppConfig;
+
+mod pages;
+
+pub use pages::ErrorPages;
+
+/// Path prefix under which `SvelteKit` places assets whose file names contain a content hash.
+/// Since their names change whenever their contents do, these files can be cached forever.
+const IMMUTABLE_ASSETS_PREFIX: &str = "/_app/immutable/";
+
+/// `Cache-Control` value for content-hashed assets (one year, the de-facto maximum)
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// `Cache-Control` value for everything else, including `index.html`. Clients must revalidate
+/// these before each use so that new UI builds are picked up immediately.
+const REVALIDATE_CACHE_CONTROL: &str = "no-cache";
+
+/// Placeholder in `index.html` which is replaced with a per-response CSP nonce.
+///
+/// The UI can use this as e.g. `<meta property="csp-nonce" nonce="%iam.nonce%">`. Inline
+/// `<script>` elements are given the nonce automatically.
+const NONCE_PLACEHOLDER: &str = "%iam.nonce%";
+
+/// ID of the `<script>` element containing the injected [`AppConfig`] JSON.
+const CONFIG_ELEMENT_ID: &str = "iam-config";
+
+/// Interval at which `index.html` is checked for changes when [`UiOptions::watch`] is enabled
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Additional options for [`new_ui_server`]
+#[derive(Debug, Clone, Default)]
+pub struct UiOptions {
+    /// Directory containing custom error/maintenance pages. See [`ErrorPages`] for details.
+    pub pages_dir: Option<PathBuf>,
+    /// If `true`, the maintenance page is served for all UI paths instead of the UI itself.
+    pub maintenance: bool,
+    /// If `true`, the static directory is watched so that new UI builds are picked up without
+    /// restarting the server.
+    pub watch: bool,
+}
+
+/// Error returned by [`validate_static_dir()`]
+#[derive(Debug, thiserror::Error)]
+pub enum StaticDirError {
+    /// The static directory does not exist.
+    #[error("static directory {} does not exist", .0.display())]
+    NotFound(PathBuf),
+
+    /// The static directory path exists but is not a directory.
+    #[error("static directory path {} is not a directory", .0.display())]
+    NotADirectory(PathBuf),
+
+    /// The static directory does not contain an `index.html` file.
+    #[error("static directory {} does not contain index.html; has the UI been built?", .0.display())]
+    MissingIndex(PathBuf),
+
+    /// Some other I/O error occurred while inspecting the static directory.
+    #[error("failed to access static directory {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// Verifies that the given static directory exists and contains an `index.html` file.
+pub fn validate_static_dir(static_dir: &Path) -> Result<(), StaticDirError> {
+    let to_error = |err: std::io::Error, not_found: StaticDirError| {
+        if err.kind() == ErrorKind::NotFound {
+            not_found
+        } else {
+            StaticDirError::Io {
+                path: static_dir.to_path_buf(),
+                source: err,
+            }
+        }
+    };
+    let metadata = std::fs::metadata(static_dir)
+        .map_err(|err| to_error(err, StaticDirError::NotFound(static_dir.to_path_buf())))?;
+    if !metadata.is_dir() {
+        return Err(StaticDirError::NotADirectory(static_dir.to_path_buf()));
+    }
+    let index = std::fs::metadata(static_dir.join("index.html"))
+        .map_err(|err| to_error(err, StaticDirError::MissingIndex(static_dir.to_path_buf())))?;
+    if index.is_file() {
+        Ok(())
+    } else {
+        Err(StaticDirError::MissingIndex(static_dir.to_path_buf()))
+    }
+}
+
+/// Creates a new service to serve the static UI content from the given directory.
+///
+/// If a pre-compressed variant of a requested file (i.e. `<file>.br` or `<file>.gz`) exists and
+/// the client accepts that encoding, it is served instead of the uncompressed file.
+///
+/// `index.html` is loaded once and served as a template: the given [`AppConfig`] is injected
+/// into it as a JSON `<script>` element, and each response gets a fresh CSP nonce, which its
+/// `Content-Security-Policy` header requires inline scripts to carry. If the file can't be read,
+/// the error page is served instead. If [`UiOptions::watch`] is set, the template is reloaded
+/// whenever the file changes.
+///
+/// Not-found and server error responses are replaced with the corresponding [`ErrorPages`].
+pub fn new_ui_server(static_dir: &Path, config: &AppConfig, options: &UiOptions) -> Router {
+    let pages = Arc::new(
+        options
+            .pages_dir
+            .as_deref()
+            .map(ErrorPages::load)
+            .unwrap_or_default(),
+    );
+    if options.maintenance {
+        warn!("maintenance mode is active; the UI will not be served");
+        return Router::new()
+            .fallback(pages::serve_maintenance_page)
+            .with_state(pages);
+    }
+
+    let index = Arc::new(IndexState::new(static_dir.join("index.html"), config));
+    if options.watch {
+        info!(path = %static_dir.display(), "watching static directory for changes");
+        tokio::spawn(watch_index(index.clone()));
+    }
+    let files = ServeDir::new(static_dir)
+        .precompressed_br()
+        .precompressed_gzip()
+        .append_index_html_on_directories(false);
+    let router = Router::new()
+        .route("/index.html", get(serve_index))
+        .fallback_service(files.fallback(get(serve_index).with_state(index.clone())))
+        .with_state(index);
+    router
+        .layer(middleware::from_fn_with_state(
+            pages,
+            pages::replace_error_responses,
+        ))
+        .layer(middleware::from_fn(set_cache_control))
+}
+
+/// Shared state holding the current [`IndexTemplate`], if it could be loaded.
+#[derive(Debug)]
+struct IndexState {
+    path: PathBuf,
+    config: AppConfig,
+    template: RwLock<Option<IndexTemplate>>,
+}
+
+impl IndexState {
+    /// Creates a new state and loads the template from the given path.
+    fn new(path: PathBuf, config: &AppConfig) -> Self {
+        let state = Self {
+            path,
+            config: config.clone(),
+            template: RwLock::new(None),
+        };
+        state.reload();
+        state
+    }
+
+    /// (Re-)loads the template from disk. If loading fails, the previous template is kept.
+    fn reload(&self) {
+        match IndexTemplate::load(&self.path, &self.config) {
+            Ok(template) => *self.template.write().unwrap() = Some(template),
+            Err(err) => {
+                error!(%err, path = %self.path.display(), "failed to load index.html template");
+            }
+        }
+    }
+}
+
+/// Polls the `index.html` file's modification time and reloads the template when it changes.
+async fn watch_index(state: Arc<IndexState>) {
+    let modified_time = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let mut last_modified: Option<SystemTime> = modified_time(&state.path);
+    let mut interval = tokio::time::interval(WATCH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let modified = modified_time(&state.path);
+        if modified.is_some() && modified != last_modified {
+            info!(path = %state.path.display(), "index.html changed; reloading");
+            state.reload();
+        }
+        last_modified = modified;
+    }
+}
+
+/// # `index.html` template
+///
+/// Holds the contents of `index.html` with the app configuration already injected, so that only
+/// the nonce needs to be substituted for each response.
+#[derive(Debug)]
+struct IndexTemplate {
+    html: String,
+}
+
+impl IndexTemplate {
+    /// Reads the `index.html` file at the given path and injects the given configuration.
+    fn load(path: &Path, config: &AppConfig) -> std::io::Result<Self> {
+        let html = std::fs::read_to_string(path)?;
+        let config_json = serde_json::to_string(config)?;
+        Ok(Self::new(&html, &config_json))
+    }
+
+    /// Creates a template from the given HTML, injecting the given configuration JSON as the last
+    /// element of the `<head>` and giving the nonce to inline scripts, such as the one with which
+    /// `SvelteKit` starts the app.
+    fn new(html: &str, config_json: &str) -> Self {
+        let html = html.replace(
+            "<script>",
+            &format!(r#"<script nonce="{NONCE_PLACEHOLDER}">"#),
+        );
+        // Escape characters that could terminate the <script> element early. The escaped forms are
+        // equivalent in JSON.
+        let config_json = config_json
+            .replace('<', "\\u003c")
+            .replace('>', "\\u003e")
+            .replace('&', "\\u0026");
+        let element = format!(
+            "<script id=\"{CONFIG_ELEMENT_ID}\" type=\"application/json\" \
+             nonce=\"{NONCE_PLACEHOLDER}\">{config_json}</script>"
+        );
+        let html = match html.find("</head>") {
+            Some(index) => format!("{}{element}{}", &html[..index], &html[index..]),
+            None => format!("{element}{html}"),
+        };
+        Self { html }
+    }
+
+    /// Renders the template using the given nonce.
+    fn render(&self, nonce: &str) -> String {
+        self.html.replace(NONCE_PLACEHOLDER, nonce)
+    }
+}
+
+/// Generates a random CSP nonce.
+fn new_nonce() -> String {
+    let mut bytes = [0u8; 16]; // 128 bits
+    rand::rng().fill_bytes(&mut bytes);
+    BASE64_STANDARD.encode(bytes)
+}
+
+/// Returns the `Content-Security-Policy` header value for a page rendered with the given nonce.
+/// Only scripts from this origin, and inline scripts carrying the nonce, may run.
+fn content_security_policy(nonce: &str) -> String {
+    format!(
+        "script-src 'self' 'nonce-{nonce}'; object-src 'none'; base-uri 'self'; \
+        frame-ancestors 'none'"
+    )
+}
+
+/// Serves the rendered `index.html` template, or a `503 Service Unavailable` error if the
+/// template is not loaded.
+async fn serve_index(State(index): State<Arc<IndexState>>) -> Response {
+    let nonce = new_nonce();
+    let Some(html) = index
+        .template
+        .read()
+        .unwrap()
+        .as_ref()
+        .map(|template| template.render(&nonce))
+    else {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+    // The nonce changes on every response, so the page must never be reused from a cache.
+    (
+        [
+            (CACHE_CONTROL, "no-store".to_string()),
+            (CONTENT_SECURITY_POLICY, content_security_policy(&nonce)),
+        ],
+        Html(html),
+    )
+        .into_response()
+}
+
+/// Middleware which sets the `Cache-Control` header on UI responses based on the request path.
+///
+/// Successful responses for content-hashed assets are marked as immutable. All other responses
+/// (including the `index.html` fallback and errors) must be revalidated by the client.
+async fn set_cache_control(request: Request, next: Next) -> Response {
+    let is_immutable_asset = request.uri().path().starts_with(IMMUTABLE_ASSETS_PREFIX);
+    let mut response = next.run(request).await;
+    let value = if is_immutable_asset && response.status().is_success() {
+        IMMUTABLE_CACHE_CONTROL
+    } else {
+        REVALIDATE_CACHE_CONTROL
+    };
+    response
+        .headers_mut()
+        .entry(CACHE_CONTROL)
+        .or_insert(HeaderValue::from_static(value));
+    response
+}
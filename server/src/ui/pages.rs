@@ -0,0 +1,98 @@
+//! # Error and maintenance pages
+//!
+//! Operators can override the built-in pages by placing the following files in a directory and
+//! passing it as [`UiOptions::pages_dir`][super::UiOptions::pages_dir]:
+//!
+//! | File               | Served when                                                |
+//! |--------------------|------------------------------------------------------------|
+//! | `404.html`         | A UI path does not exist                                   |
+//! | `50x.html`         | A server error occurs or the UI bundle is missing          |
+//! | `maintenance.html` | [Maintenance mode][super::UiOptions::maintenance] is active |
+
+use std::{io::ErrorKind, path::Path, sync::Arc};
+
+use axum::{
+    body::Bytes,
+    extract::{Request, State},
+    http::{StatusCode, header::CACHE_CONTROL},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+};
+use tracing::warn;
+
+const DEFAULT_NOT_FOUND: &str = include_str!("pages/404.html");
+const DEFAULT_SERVER_ERROR: &str = include_str!("pages/50x.html");
+const DEFAULT_MAINTENANCE: &str = include_str!("pages/maintenance.html");
+
+/// Set of HTML pages served in place of error responses from the UI server.
+#[derive(Debug, Clone)]
+pub struct ErrorPages {
+    not_found: Bytes,
+    server_error: Bytes,
+    maintenance: Bytes,
+}
+
+impl Default for ErrorPages {
+    fn default() -> Self {
+        Self {
+            not_found: Bytes::from_static(DEFAULT_NOT_FOUND.as_bytes()),
+            server_error: Bytes::from_static(DEFAULT_SERVER_ERROR.as_bytes()),
+            maintenance: Bytes::from_static(DEFAULT_MAINTENANCE.as_bytes()),
+        }
+    }
+}
+
+impl ErrorPages {
+    /// Loads the pages from the given directory. Pages which don't exist in the directory or
+    /// which can't be read are replaced by the built-in defaults.
+    #[must_use]
+    pub fn load(dir: &Path) -> Self {
+        let defaults = Self::default();
+        Self {
+            not_found: load_page(dir, "404.html").unwrap_or(defaults.not_found),
+            server_error: load_page(dir, "50x.html").unwrap_or(defaults.server_error),
+            maintenance: load_page(dir, "maintenance.html").unwrap_or(defaults.maintenance),
+        }
+    }
+}
+
+/// Reads the page with the given file name from the given directory, logging a warning if the
+/// file exists but can't be read.
+fn load_page(dir: &Path, name: &str) -> Option<Bytes> {
+    let path = dir.join(name);
+    match std::fs::read(&path) {
+        Ok(contents) => Some(Bytes::from(contents)),
+        Err(err) if err.kind() == ErrorKind::NotFound => None,
+        Err(err) => {
+            warn!(%err, path = %path.display(), "failed to read custom page; using default");
+            None
+        }
+    }
+}
+
+/// Builds an HTML response with the given status and page contents.
+fn page_response(status: StatusCode, page: Bytes) -> Response {
+    (status, [(CACHE_CONTROL, "no-store")], Html(page)).into_response()
+}
+
+/// Middleware which replaces 404 and 5xx responses with the corresponding [`ErrorPages`] page.
+pub async fn replace_error_responses(
+    State(pages): State<Arc<ErrorPages>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    let status = response.status();
+    if status == StatusCode::NOT_FOUND {
+        page_response(status, pages.not_found.clone())
+    } else if status.is_server_error() {
+        page_response(status, pages.server_error.clone())
+    } else {
+        response
+    }
+}
+
+/// Handler which serves the maintenance page with a `503 Service Unavailable` status.
+pub async fn serve_maintenance_page(State(pages): State<Arc<ErrorPages>>) -> Response {
+    page_response(StatusCode::SERVICE_UNAVAILABLE, pages.maintenance.clone())
+}
@@ -0,0 +1,3551 @@
+//! # API integration tests
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::ConnectInfo,
+    http::{
+        HeaderMap, Method, Request, StatusCode,
+        header::{
+            ACCEPT, CACHE_CONTROL, CONTENT_TYPE, COOKIE, ETAG, IF_NONE_MATCH, LOCATION, SET_COOKIE,
+            USER_AGENT, VARY,
+        },
+    },
+};
+use base64::{
+    Engine,
+    prelude::{BASE64_STANDARD, BASE64_URL_SAFE_NO_PAD},
+};
+use iam_server::{
+    api::{ApiOptions, EnumerationProtection, new_well_known_router},
+    crypto::jwt::{KeySet, SigningKey},
+    db::clients::{
+        chaos::{ChaosClient, ChaosOptions, InjectedError},
+        sqlite::SqliteClient,
+    },
+    models::{
+        Announcement, AppConfig, IpBan, PasskeyCredential, RegistrationMode, SessionState,
+        SessionUpdate, Tag, User,
+    },
+    rate_limit::{MemoryStore, RateLimit, RateLimitOptions, RateLimiter},
+    session_cookie::SessionCookieOptions,
+    test_utils::{TestApp, TestMailbox, TestResponse, TestSession, test_config},
+    webhooks::{self, DELIVERY_HEADER, EVENT_HEADER, SIGNATURE_HEADER},
+};
+use reqwest::Url;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn test_health() {
+    let app = TestApp::new().await;
+    let response = app.get("/api/v1/health", None).await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_get_current_user() {
+    let app = TestApp::new().await;
+    let response = app.get("/api/v1/users/me", None).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let session = app.create_session(&user, false).await;
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let fetched: User = response.json();
+    assert_eq!(fetched.id(), user.id());
+}
+
+#[tokio::test]
+async fn test_error_response() {
+    let app = TestApp::new().await;
+    let response = app.get("/api/v1/users/me", None).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.json::<serde_json::Value>(),
+        json!({ "error": { "code": "not_logged_in", "message": "Not logged in" } })
+    );
+
+    // Some errors carry structured details
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin = app.create_session(&admin, true).await;
+    let response = app
+        .post(
+            "/api/v1/ip-bans",
+            &json!({ "network": "not an IP" }),
+            Some(&admin),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "invalid_network");
+    assert_eq!(body["error"]["details"], json!({ "network": "not an IP" }));
+}
+
+#[tokio::test]
+async fn test_unknown_api_path() {
+    let app = TestApp::new().await;
+    for path in [
+        "/api/v1/nonexistent",
+        "/api/v1/users/me/x/y",
+        "/api/v2/users",
+        "/api",
+    ] {
+        let response = app.get(path, None).await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND, "{path}");
+        assert_eq!(response.headers[CONTENT_TYPE], "application/json", "{path}");
+        assert_eq!(
+            response.json::<serde_json::Value>()["error"]["code"],
+            "not_found"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_problem_details() {
+    let app = TestApp::new().await;
+    let request = Request::get("/api/v1/users/me")
+        .header(ACCEPT, "application/problem+json")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.request(request).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers[CONTENT_TYPE], "application/problem+json");
+    assert_eq!(
+        response.json::<serde_json::Value>(),
+        json!({
+            "type": "urn:iam:problem:not_logged_in",
+            "title": "Unauthorized",
+            "status": 401,
+            "detail": "Not logged in",
+            "code": "not_logged_in",
+        })
+    );
+
+    // Clients which don't ask for problem details get the usual format
+    let response = app.get("/api/v1/users/me", None).await;
+    assert_eq!(response.headers[CONTENT_TYPE], "application/json");
+    assert!(
+        response
+            .headers
+            .get_all(VARY)
+            .iter()
+            .any(|value| value == "Accept")
+    );
+}
+
+#[tokio::test]
+async fn test_update_preferences() {
+    let app = TestApp::new().await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let session = app.create_session(&user, false).await;
+    let response = app
+        .patch(
+            "/api/v1/users/me/preferences",
+            &json!({ "loginNotifications": false }),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(!response.json::<User>().login_notifications());
+}
+
+#[tokio::test]
+async fn test_get_user_requires_admin() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let path = format!("/api/v1/users/{}", user.id());
+
+    let session = app.create_session(&admin, false).await;
+    let response = app.get(&path, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    app.make_admin(&admin).await;
+    let session = app.create_session(&admin, true).await;
+    let response = app.get(&path, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["emailStatus"]["status"], "deliverable");
+}
+
+#[tokio::test]
+async fn test_test_email_without_mailer() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let session = app.create_session(&admin, true).await;
+    let response = app
+        .post("/api/v1/email/test", &json!({}), Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_database_failures() {
+    let db = Arc::new(ChaosClient::new(
+        Arc::new(SqliteClient::new_memory().await.unwrap()),
+        ChaosOptions::default(),
+    ));
+    let app = TestApp::with_db(db.clone(), ApiOptions::default());
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let session = app.create_session(&user, false).await;
+
+    db.set_options(ChaosOptions {
+        error_rate: 1.0,
+        ..ChaosOptions::default()
+    });
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+
+    // A missing session means the user isn't logged in, not that a resource is missing
+    db.set_options(ChaosOptions {
+        error_rate: 1.0,
+        errors: vec![InjectedError::NotFound],
+        ..ChaosOptions::default()
+    });
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    db.set_options(ChaosOptions::default());
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_config_features() {
+    let app = TestApp::new().await;
+    let response = app.get("/api/v1/config", None).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let config: serde_json::Value = response.json();
+    assert_eq!(config["features"]["registration"], true);
+    assert_eq!(config["features"]["oidc"], true);
+}
+
+#[tokio::test]
+async fn test_conditional_requests() {
+    let app = TestApp::new().await;
+    for path in ["/api/v1/config", "/api/v1/docs/openapi.json"] {
+        let response = app.get(path, None).await;
+        assert_eq!(response.status, StatusCode::OK);
+        let etag = response.headers[ETAG].clone();
+        assert!(etag.to_str().unwrap().starts_with('"'));
+
+        let conditional = |if_none_match: &str| {
+            Request::get(path)
+                .header(IF_NONE_MATCH, if_none_match)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app.request(conditional(etag.to_str().unwrap())).await;
+        assert_eq!(response.status, StatusCode::NOT_MODIFIED);
+        assert!(response.body.is_empty());
+        assert_eq!(response.headers[ETAG], etag);
+        assert!(response.headers.contains_key(CACHE_CONTROL));
+
+        let response = app
+            .request(conditional(&format!(
+                "\"other\", W/{}",
+                etag.to_str().unwrap()
+            )))
+            .await;
+        assert_eq!(response.status, StatusCode::NOT_MODIFIED);
+        let response = app.request(conditional("\"other\"")).await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert!(!response.body.is_empty());
+    }
+}
+
+#[tokio::test]
+async fn test_registration_mode() {
+    let user = json!({ "email": "user@kasad.com", "displayName": "User" });
+
+    let mut config = test_config();
+    config.allowed_email_domains = vec!["example.com".to_string()];
+    let app = TestApp::with_config(&config, ApiOptions::default()).await;
+    let response = app.post("/api/v1/register/start", &user, None).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.json::<serde_json::Value>()["error"]["code"],
+        "email_domain_not_allowed"
+    );
+    let allowed = json!({ "email": "user@Example.com", "displayName": "User" });
+    let response = app.post("/api/v1/register/start", &allowed, None).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    for mode in [RegistrationMode::InviteOnly, RegistrationMode::Closed] {
+        let config = AppConfig {
+            registration_mode: mode,
+            ..test_config()
+        };
+        let app = TestApp::with_config(&config, ApiOptions::default()).await;
+        let response = app.get("/api/v1/config", None).await;
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["registrationMode"], json!(mode));
+        let response = app.post("/api/v1/register/start", &user, None).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.json::<serde_json::Value>()["error"]["code"],
+            "registration_closed"
+        );
+
+        // Administrators can still create accounts unless registration is closed entirely
+        let admin = app.create_user("admin@kasad.com", "Admin").await;
+        app.make_admin(&admin).await;
+        let admin = app.create_session(&admin, true).await;
+        let response = app.post("/api/v1/users", &user, Some(&admin)).await;
+        let expected = match mode {
+            RegistrationMode::Closed => StatusCode::FORBIDDEN,
+            _ => StatusCode::OK,
+        };
+        assert_eq!(response.status, expected, "{mode:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_user_notes() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let user_session = app.create_session(&user, false).await;
+    let path = format!("/api/v1/users/{}/notes", user.id());
+
+    for body in ["Disabled pending HR review", "Re-enabled"] {
+        let response = app
+            .send(
+                Method::PUT,
+                &path,
+                Some(&json!({ "body": body })),
+                Some(&admin_session),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+    }
+    let response = app.get(&path, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let notes: Vec<serde_json::Value> = response.json();
+    assert_eq!(notes.len(), 2);
+    assert_eq!(notes[0]["body"], "Re-enabled");
+    assert_eq!(notes[0]["authorId"], admin.id().to_string());
+
+    // Notes are only visible to administrators
+    let response = app.get(&path, Some(&user_session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = app.get("/api/v1/users/me", Some(&user_session)).await;
+    assert!(!response.text().contains("HR review"));
+
+    let response = app
+        .get(
+            "/api/v1/users/00000000-0000-0000-0000-000000000000/notes",
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_merge_user() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let duplicate = app.create_user("test2@kasad.com", "Test User").await;
+    let duplicate_session = app.create_session(&duplicate, false).await;
+    let path = format!("/api/v1/users/{}/merge", duplicate.id());
+
+    let response = app
+        .post(
+            &path,
+            &json!({ "into": user.id(), "dryRun": true }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let merge: serde_json::Value = response.json();
+    assert_eq!(merge["sessions"], 1);
+    assert_eq!(merge["dryRun"], true);
+    let response = app.get("/api/v1/users/me", Some(&duplicate_session)).await;
+    assert_eq!(response.json::<User>().id(), duplicate.id());
+
+    // After merging, the duplicate's session belongs to the remaining user
+    let response = app
+        .post(&path, &json!({ "into": user.id() }), Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.get("/api/v1/users/me", Some(&duplicate_session)).await;
+    assert_eq!(response.json::<User>().id(), user.id());
+    let response = app
+        .get(
+            &format!("/api/v1/users/{}/notes", user.id()),
+            Some(&admin_session),
+        )
+        .await;
+    let notes: Vec<serde_json::Value> = response.json();
+    assert!(
+        notes[0]["body"]
+            .as_str()
+            .unwrap()
+            .contains("test2@kasad.com")
+    );
+
+    let response = app
+        .post(&path, &json!({ "into": user.id() }), Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let response = app
+        .post(
+            &format!("/api/v1/users/{}/merge", user.id()),
+            &json!({ "into": user.id() }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_session_cache() {
+    let app = TestApp::with_options(ApiOptions {
+        session_cache_ttl: Duration::from_secs(60),
+        ..Default::default()
+    })
+    .await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let session = app.create_session(&user, false).await;
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    // Changes made behind the API's back aren't seen until the cached session expires
+    app.db()
+        .update_session(
+            &session.session.id_hash,
+            &SessionUpdate::new().with_state(SessionState::Revoked),
+        )
+        .await
+        .unwrap();
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    // Logging out invalidates the cached session
+    let session = app.create_session(&user, false).await;
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.post("/api/v1/logout", &(), Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_session_cookies() {
+    let app = TestApp::new().await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let session = app.create_session(&user, false).await;
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    // A cookie holding the stored hash of a session, as would be found in a leaked database, can't
+    // be used to authenticate
+    let forged = TestSession {
+        cookie: format!("session_id={}", session.session.id_hash.0),
+        ..session.clone()
+    };
+    let response = app.get("/api/v1/users/me", Some(&forged)).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    // Nor can a cookie signed by another server
+    let other = TestApp::new().await;
+    let other_user = other.create_user("test@kasad.com", "Test User").await;
+    let session = other.create_session(&other_user, false).await;
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    // With the `__Host-` prefix, only the prefixed cookie is accepted, and it is removed from
+    // every path on logout
+    let app = TestApp::with_options(ApiOptions {
+        session_cookies: SessionCookieOptions {
+            host_prefix: true,
+            keys: None,
+        },
+        ..Default::default()
+    })
+    .await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let session = app.create_session(&user, false).await;
+    assert!(session.cookie.starts_with("__Host-session_id="));
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let unprefixed = TestSession {
+        cookie: session.cookie.replace("__Host-", ""),
+        ..session.clone()
+    };
+    let response = app.get("/api/v1/users/me", Some(&unprefixed)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = app.post("/api/v1/logout", &(), Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let set_cookie = response.headers[SET_COOKIE].to_str().unwrap();
+    assert!(
+        set_cookie.starts_with("__Host-session_id=;"),
+        "{set_cookie}"
+    );
+    assert!(set_cookie.contains("Path=/;"), "{set_cookie}");
+}
+
+#[tokio::test]
+async fn test_list_sessions() {
+    let app = TestApp::new().await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let stale = app.create_session(&user, false).await;
+    let current = app.create_session(&user, false).await;
+    let revoked = app.create_session(&user, false).await;
+    app.db()
+        .update_session(
+            &revoked.session.id_hash,
+            &SessionUpdate::new().with_state(SessionState::Revoked),
+        )
+        .await
+        .unwrap();
+    let last_seen_at = chrono::Utc::now() - chrono::Duration::hours(1);
+    app.db()
+        .update_session(
+            &stale.session.id_hash,
+            &SessionUpdate::new().with_activity(last_seen_at, None, None),
+        )
+        .await
+        .unwrap();
+    // Seen recently enough not to be recorded again, but before the stale session is used
+    let last_seen_at = chrono::Utc::now() - chrono::Duration::minutes(1);
+    app.db()
+        .update_session(
+            &current.session.id_hash,
+            &SessionUpdate::new().with_activity(last_seen_at, None, None),
+        )
+        .await
+        .unwrap();
+
+    // Using a session which hasn't been seen for a while records the client
+    let mut request = Request::get("/api/v1/users/me")
+        .header(COOKIE, &stale.cookie)
+        .header(USER_AGENT, "Test Browser")
+        .body(Body::empty())
+        .unwrap();
+    request
+        .extensions_mut()
+        .insert(ConnectInfo("192.0.2.1:1234".parse::<SocketAddr>().unwrap()));
+    let response = app.request(request).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let response = app.get("/api/v1/users/me/sessions", Some(&current)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let page: serde_json::Value = response.json();
+    assert_eq!(page["total"], 2);
+    let sessions = page["items"].as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions[0]["ip"], "192.0.2.1");
+    assert_eq!(sessions[0]["userAgent"], "Test Browser");
+    assert_eq!(sessions[1]["ip"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn test_impersonation() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let user_session = app.create_session(&user, false).await;
+    let impersonate = json!({ "target": "User", "userId": user.id() });
+
+    // Only administrator sessions can impersonate, and not their own user
+    let response = app
+        .post("/api/v1/auth/upgrade", &impersonate, Some(&user_session))
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = app
+        .post(
+            "/api/v1/auth/upgrade",
+            &json!({ "target": "User", "userId": admin.id() }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    let response = app
+        .post("/api/v1/auth/upgrade", &impersonate, Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let cookie = format!("session_id={}", response.cookie("session_id").unwrap());
+    let response = app
+        .send_with_cookie::<()>(Method::GET, "/api/v1/auth/session", None, Some(&cookie))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let info: serde_json::Value = response.json();
+    assert_eq!(info["user"]["email"], "test@kasad.com");
+    assert_eq!(info["session"]["isAdmin"], false);
+    assert_eq!(info["session"]["impersonatorId"], admin.id().to_string());
+
+    // Impersonating sessions can't be upgraded, even if the user is an administrator
+    app.make_admin(&user).await;
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/auth/upgrade",
+            Some(&json!({ "target": "Admin" })),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+    // Downgrading returns to the administrator's own identity
+    let response = app
+        .send_with_cookie::<()>(Method::POST, "/api/v1/auth/downgrade", None, Some(&cookie))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let cookie = format!("session_id={}", response.cookie("session_id").unwrap());
+    let response = app
+        .send_with_cookie::<()>(Method::GET, "/api/v1/auth/session", None, Some(&cookie))
+        .await;
+    let info: serde_json::Value = response.json();
+    assert_eq!(info["user"]["email"], "admin@kasad.com");
+    assert_eq!(info["session"]["isAdmin"], true);
+    assert_eq!(info["session"]["impersonatorId"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn test_revoke_all_sessions() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let user_session = app.create_session(&user, false).await;
+
+    let body = json!({ "keepCurrent": true });
+    let response = app
+        .post("/api/v1/auth/revoke-all", &body, Some(&user_session))
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    // The caller's session can be kept
+    let response = app
+        .post("/api/v1/auth/revoke-all", &body, Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(response.json::<serde_json::Value>()["revoked"], 1);
+    let response = app.get("/api/v1/users/me", Some(&user_session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = app.get("/api/v1/users/me", Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let response = app
+        .post("/api/v1/auth/revoke-all", &json!({}), Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.get("/api/v1/users/me", Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_announcement() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let session = app.create_session(&admin, true).await;
+    let response = app.get("/api/v1/announcement", None).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.json::<Option<Announcement>>().is_none());
+
+    let response = app
+        .send(
+            Method::PUT,
+            "/api/v1/announcement",
+            Some(&json!({ "message": "Maintenance tonight", "severity": "warning" })),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.get("/api/v1/announcement", None).await;
+    let announcement: Option<Announcement> = response.json();
+    assert_eq!(announcement.unwrap().message, "Maintenance tonight");
+
+    // Scheduled announcements aren't shown before they start
+    let starts_at = chrono::Utc::now() + chrono::Duration::hours(1);
+    let body = json!({ "message": "Later", "startsAt": starts_at });
+    let response = app
+        .send(
+            Method::PUT,
+            "/api/v1/announcement",
+            Some(&body),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.get("/api/v1/announcement", None).await;
+    assert!(response.json::<Option<Announcement>>().is_none());
+
+    let body = json!({ "message": "Backwards", "startsAt": starts_at, "endsAt": starts_at });
+    let response = app
+        .send(
+            Method::PUT,
+            "/api/v1/announcement",
+            Some(&body),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    let response = app.delete("/api/v1/announcement", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.delete("/api/v1/announcement", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+/// Sends a `GET` request to the health endpoint as if it came from the given address.
+async fn get_health_from(app: &TestApp, addr: &str) -> TestResponse {
+    let mut request = Request::get("/api/v1/health").body(Body::empty()).unwrap();
+    let addr: SocketAddr = addr.parse().unwrap();
+    request.extensions_mut().insert(ConnectInfo(addr));
+    app.request(request).await
+}
+
+/// Starts a login for the given email address as if it came from the given address.
+async fn start_login_from(app: &TestApp, addr: &str, email: &str) -> TestResponse {
+    let mut request = Request::post("/api/v1/auth/start")
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "email": email }).to_string()))
+        .unwrap();
+    let addr: SocketAddr = addr.parse().unwrap();
+    request.extensions_mut().insert(ConnectInfo(addr));
+    app.request(request).await
+}
+
+#[tokio::test]
+async fn test_rate_limit() {
+    let limit = Some(RateLimit {
+        max_requests: 2,
+        window: Duration::from_secs(60),
+    });
+    let limiter = RateLimiter::new(
+        Arc::new(MemoryStore::new()),
+        RateLimitOptions {
+            per_ip: limit,
+            per_email: limit,
+        },
+    );
+    let app = TestApp::with_options(ApiOptions {
+        rate_limiter: Some(Arc::new(limiter)),
+        ..ApiOptions::default()
+    })
+    .await;
+    app.create_user("test@kasad.com", "Test User").await;
+
+    // Requests for the same account are limited even when they come from different addresses
+    for addr in ["192.0.2.1:1234", "198.51.100.1:1234"] {
+        let response = start_login_from(&app, addr, "test@kasad.com").await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    }
+    let response = start_login_from(&app, "203.0.113.1:1234", "test@kasad.com").await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(
+        response.json::<serde_json::Value>()["error"]["code"],
+        "rate_limited"
+    );
+    let retry_after: u64 = response.headers["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=60).contains(&retry_after));
+
+    // Requests from the same address are limited whichever account they are for
+    let response = start_login_from(&app, "192.0.2.1:1234", "other@kasad.com").await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let response = start_login_from(&app, "192.0.2.1:1234", "third@kasad.com").await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+
+    // Endpoints other than the login and registration ceremonies aren't limited
+    let response = get_health_from(&app, "192.0.2.1:1234").await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_rate_limit_behind_proxy() {
+    let limiter = RateLimiter::new(
+        Arc::new(MemoryStore::new()),
+        RateLimitOptions {
+            per_ip: Some(RateLimit {
+                max_requests: 1,
+                window: Duration::from_secs(60),
+            }),
+            per_email: None,
+        },
+    );
+    let app = TestApp::with_options(ApiOptions {
+        rate_limiter: Some(Arc::new(limiter)),
+        trusted_proxies: "10.0.0.0/8".parse().unwrap(),
+        ..ApiOptions::default()
+    })
+    .await;
+
+    let start_login = |forwarded_for: &str| {
+        let mut request = Request::post("/api/v1/auth/start")
+            .header(CONTENT_TYPE, "application/json")
+            .header("x-forwarded-for", forwarded_for)
+            .body(Body::from(json!({ "email": "test@kasad.com" }).to_string()))
+            .unwrap();
+        let proxy: SocketAddr = "10.0.0.1:1234".parse().unwrap();
+        request.extensions_mut().insert(ConnectInfo(proxy));
+        app.request(request)
+    };
+
+    // Clients behind the same proxy are limited separately
+    let response = start_login("192.0.2.1").await;
+    assert_ne!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    let response = start_login("198.51.100.1").await;
+    assert_ne!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    let response = start_login("192.0.2.1").await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn test_ip_bans() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin = app.create_session(&admin, true).await;
+
+    let response = app
+        .post(
+            "/api/v1/ip-bans",
+            &json!({ "network": "not an IP" }),
+            Some(&admin),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    // Bans apply to the whole network
+    let response = app
+        .post(
+            "/api/v1/ip-bans",
+            &json!({ "network": "192.0.2.1/24", "reason": "abuse" }),
+            Some(&admin),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let ban: IpBan = response.json();
+    assert_eq!(ban.network, "192.0.2.0/24");
+    let response = get_health_from(&app, "192.0.2.7:1234").await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.json::<serde_json::Value>()["error"]["code"],
+        "address_banned"
+    );
+    let response = get_health_from(&app, "198.51.100.7:1234").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let response = app.get("/api/v1/ip-bans", Some(&admin)).await;
+    assert_eq!(response.json::<Vec<IpBan>>().len(), 1);
+
+    // Lifting the ban takes effect immediately
+    let response = app
+        .delete(&format!("/api/v1/ip-bans/{}", ban.id), Some(&admin))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = get_health_from(&app, "192.0.2.7:1234").await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_ip_bans_behind_proxy() {
+    let app = TestApp::with_options(ApiOptions {
+        trusted_proxies: "10.0.0.0/8".parse().unwrap(),
+        ..ApiOptions::default()
+    })
+    .await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin = app.create_session(&admin, true).await;
+    let response = app
+        .post(
+            "/api/v1/ip-bans",
+            &json!({ "network": "192.0.2.0/24" }),
+            Some(&admin),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    let get_health = |peer: &str, forwarded_for: &str| {
+        let mut request = Request::get("/api/v1/health")
+            .header("x-forwarded-for", forwarded_for)
+            .body(Body::empty())
+            .unwrap();
+        let peer: SocketAddr = peer.parse().unwrap();
+        request.extensions_mut().insert(ConnectInfo(peer));
+        app.request(request)
+    };
+
+    // Trusted proxies forward the client's address
+    let response = get_health("10.0.0.1:1234", "192.0.2.7").await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let response = get_health("10.0.0.1:1234", "192.0.2.7, 10.0.0.2").await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let response = get_health("10.0.0.1:1234", "192.0.2.7, 198.51.100.7").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    // Other clients can't choose their address
+    let response = get_health("192.0.2.7:1234", "198.51.100.7").await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let response = get_health("198.51.100.7:1234", "192.0.2.7").await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_list_users() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let session = app.create_session(&admin, true).await;
+    for email in ["d@kasad.com", "b@kasad.com", "c@kasad.com", "a@kasad.com"] {
+        app.create_user(email, "User").await;
+    }
+
+    // Follow the cursors through all pages
+    let mut emails = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let path = match &cursor {
+            Some(cursor) => format!("/api/v1/users?sort=email&limit=2&cursor={cursor}"),
+            None => "/api/v1/users?sort=email&limit=2".to_string(),
+        };
+        let response = app.get(&path, Some(&session)).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+        let page: serde_json::Value = response.json();
+        assert_eq!(page["total"], 5);
+        let users: Vec<User> = serde_json::from_value(page["items"].clone()).unwrap();
+        assert!(users.len() <= 2);
+        emails.extend(users.iter().map(|user| user.email().to_string()));
+        match page["nextCursor"].as_str() {
+            Some(next) => cursor = Some(next.to_string()),
+            None => break,
+        }
+    }
+    assert_eq!(
+        emails,
+        [
+            "a@kasad.com",
+            "admin@kasad.com",
+            "b@kasad.com",
+            "c@kasad.com",
+            "d@kasad.com"
+        ]
+    );
+
+    // Sort in descending order and filter
+    let response = app
+        .get(
+            "/api/v1/users?sort=email:desc&filter[email]=KASAD&limit=2",
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let page: serde_json::Value = response.json();
+    assert_eq!(page["total"], 5);
+    assert_eq!(page["items"][0]["email"], "d@kasad.com");
+    assert_eq!(page["items"][1]["email"], "c@kasad.com");
+    let response = app
+        .get(
+            "/api/v1/users?filter[email]=admin&filter[status]=active",
+            Some(&session),
+        )
+        .await;
+    let page: serde_json::Value = response.json();
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["items"][0]["email"], "admin@kasad.com");
+    let response = app.get("/api/v1/users?sort=password", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    // Cursors are only valid for the same sort order
+    let response = app
+        .get("/api/v1/users?sort=email&limit=1", Some(&session))
+        .await;
+    let page: serde_json::Value = response.json();
+    let cursor = page["nextCursor"].as_str().unwrap();
+    let response = app
+        .get(
+            &format!("/api/v1/users?sort=created-at&cursor={cursor}"),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    let response = app
+        .get(
+            &format!("/api/v1/users?sort=email:desc&cursor={cursor}"),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    let response = app
+        .get("/api/v1/users?cursor=garbage", Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    // Only administrators can list users
+    let user_session = app.create_session(&admin, false).await;
+    let response = app.get("/api/v1/users", Some(&user_session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_search_users() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let session = app.create_session(&admin, true).await;
+    let user = app.create_user("jane.doe@kasad.com", "Jane Doe").await;
+    app.create_user("john@kasad.com", "John Doe").await;
+
+    let response = app
+        .get("/api/v1/users/search?q=jane%20do", Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let users: Vec<User> = response.json();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].id(), user.id());
+    let response = app
+        .get("/api/v1/users/search?q=doe&limit=1", Some(&session))
+        .await;
+    assert_eq!(response.json::<Vec<User>>().len(), 1);
+
+    let response = app.get("/api/v1/users/search?q=%20", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    // Only administrators can search for users
+    let user_session = app.create_session(&admin, false).await;
+    let response = app
+        .get("/api/v1/users/search?q=doe", Some(&user_session))
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+/// Sends a user import file with the given query string, authenticated with the given session.
+async fn import_users(
+    app: &TestApp,
+    query: &str,
+    body: impl Into<Body>,
+    session: &TestSession,
+) -> TestResponse {
+    let request = Request::post(format!("/api/v1/users/import?{query}"))
+        .header(COOKIE, &session.cookie)
+        .body(body.into())
+        .unwrap();
+    app.request(request).await
+}
+
+#[tokio::test]
+async fn test_import_users_csv() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let session = app.create_session(&admin, true).await;
+    app.create_user("taken@kasad.com", "Taken").await;
+    let response = app
+        .post("/api/v1/tags", &json!({ "name": "staff" }), Some(&session))
+        .await;
+    let tag: Tag = response.json();
+
+    let csv = "\u{FEFF}display_name,email\r\n\
+        \"Doe, Jane\",jane@kasad.com\r\n\
+        \r\n\
+        John,JOHN@kasad.com\n\
+        Taken,taken@kasad.com\n\
+        Nobody,not an address\n\
+        Short\n\
+        \"Last\",last@kasad.com";
+    let query = format!("format=csv&tags={}", tag.id);
+    let response = import_users(&app, &query, csv, &session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let report: serde_json::Value = response.json();
+    assert_eq!(report["imported"], 3);
+    let lines: Vec<_> = report["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["line"].as_u64().unwrap())
+        .collect();
+    assert_eq!(lines, [5, 6, 7]);
+    assert_eq!(
+        report["errors"][0]["message"],
+        "Email address is already in use"
+    );
+
+    let response = app
+        .get(&format!("/api/v1/tags/{}/users", tag.id), Some(&session))
+        .await;
+    let mut emails: Vec<_> = response
+        .json::<Vec<User>>()
+        .iter()
+        .map(|user| user.email().to_string())
+        .collect();
+    emails.sort();
+    assert_eq!(
+        emails,
+        ["jane@kasad.com", "john@kasad.com", "last@kasad.com"]
+    );
+
+    // Nothing can be imported without the required columns
+    let response = import_users(&app, "format=csv", "name,mail\nA,a@kasad.com", &session).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    // Only administrators can import users
+    let user_session = app.create_session(&admin, false).await;
+    let response = import_users(&app, "format=csv", "email,displayName", &user_session).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_import_users_jsonl() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let session = app.create_session(&admin, true).await;
+
+    // Import files may be much larger than other request bodies
+    let mut jsonl = (0..500)
+        .map(|i| {
+            json!({ "email": format!("user{i}@kasad.com"), "displayName": "User" }).to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    jsonl.push('\n');
+    jsonl.push_str("{ \"email\": \"broken\n");
+    jsonl.push_str("{ \"email\": \"blank@kasad.com\", \"displayName\": \" \" }\n");
+    assert!(jsonl.len() > 8 * 1024);
+    let response = import_users(&app, "format=jsonl", jsonl, &session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let report: serde_json::Value = response.json();
+    assert_eq!(report["imported"], 500);
+    assert_eq!(report["errors"][0]["line"], 501);
+    assert_eq!(report["errors"][1]["line"], 502);
+    assert_eq!(
+        report["errors"][1]["message"],
+        "displayName: must not be blank"
+    );
+
+    // Other endpoints keep the usual limit
+    let response = app
+        .post(
+            "/api/v1/users",
+            &json!({ "email": "big@kasad.com", "displayName": "x".repeat(10_000) }),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+
+    // Bodies without a `Content-Length` are cut off once they reach the limit
+    let body = vec![b' '; 65 * 1024 * 1024];
+    let response = import_users(&app, "format=jsonl", body, &session).await;
+    assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn test_export_users() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let session = app.create_session(&admin, true).await;
+    app.create_user("jane@kasad.com", "Doe, \"Jane\"").await;
+    // More users than fit in one page of the export
+    for i in 0..250 {
+        app.create_user(&format!("user{i}@kasad.com"), "User").await;
+    }
+
+    let response = app
+        .get("/api/v1/users/export?format=csv", Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(response.headers[CONTENT_TYPE], "text/csv; charset=utf-8");
+    let text = response.text();
+    let lines: Vec<_> = text.lines().collect();
+    assert_eq!(lines.len(), 253);
+    assert_eq!(
+        lines[0],
+        "id,email,displayName,status,createdAt,lastLoginAt,tags"
+    );
+    let admin_line = format!("{},admin@kasad.com,Admin,active,", admin.id());
+    let admin_line = lines
+        .iter()
+        .find(|line| line.starts_with(&admin_line))
+        .unwrap();
+    assert!(admin_line.ends_with(",iam::admin"));
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.contains(",jane@kasad.com,\"Doe, \"\"Jane\"\"\",active,"))
+    );
+
+    let response = app
+        .get(
+            "/api/v1/users/export?format=jsonl&fields=email,tags",
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let users: Vec<serde_json::Value> = response
+        .text()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(users.len(), 252);
+    assert!(users.contains(&json!({ "email": "admin@kasad.com", "tags": ["iam::admin"] })));
+    assert!(users.contains(&json!({ "email": "user249@kasad.com", "tags": [] })));
+
+    let response = app
+        .get(
+            "/api/v1/users/export?format=csv&fields=password",
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    // Only administrators can export users
+    let user_session = app.create_session(&admin, false).await;
+    let response = app
+        .get("/api/v1/users/export?format=csv", Some(&user_session))
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_logout_user_everywhere() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let sessions = [
+        app.create_session(&user, false).await,
+        app.create_session(&user, false).await,
+    ];
+    let path = format!("/api/v1/users/{}/logout-all", user.id());
+
+    let response = app.post(&path, &json!({}), Some(&sessions[0])).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let response = app.post(&path, &json!({}), Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(response.json::<serde_json::Value>()["revoked"], 2);
+    for session in &sessions {
+        let response = app.get("/api/v1/users/me", Some(session)).await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    }
+    // Other users' sessions are unaffected
+    let response = app.get("/api/v1/users/me", Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let path = format!("/api/v1/users/{}/logout-all", uuid::Uuid::new_v4());
+    let response = app.post(&path, &json!({}), Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_tags() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let session = app.create_session(&admin, true).await;
+
+    let response = app
+        .post("/api/v1/tags", &json!({ "name": "staff" }), Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let tag: Tag = response.json();
+    assert_eq!(tag.name, "staff");
+    let path = format!("/api/v1/tags/{}", tag.id);
+
+    // Names must be unique and can't be in the built-in namespace
+    let response = app
+        .post("/api/v1/tags", &json!({ "name": "staff" }), Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    let response = app
+        .post("/api/v1/tags", &json!({ "name": "iam::x" }), Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    let response = app
+        .patch(&path, &json!({ "name": "employees" }), Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(response.json::<Tag>().name, "employees");
+    let response = app.get(&path, Some(&session)).await;
+    assert_eq!(response.json::<Tag>().name, "employees");
+
+    // The admin tag was created by `make_admin()`
+    let response = app.get("/api/v1/tags", Some(&session)).await;
+    let tags: Vec<Tag> =
+        serde_json::from_value(response.json::<serde_json::Value>()["items"].clone()).unwrap();
+    let names: Vec<&str> = tags.iter().map(|tag| tag.name.as_str()).collect();
+    assert_eq!(names, ["employees", "iam::admin"]);
+    let response = app.get("/api/v1/tags?limit=1", Some(&session)).await;
+    let page: serde_json::Value = response.json();
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["items"][0]["name"], "employees");
+    let cursor = page["nextCursor"].as_str().unwrap();
+    let response = app
+        .get(
+            &format!("/api/v1/tags?limit=1&cursor={cursor}"),
+            Some(&session),
+        )
+        .await;
+    let page: serde_json::Value = response.json();
+    assert_eq!(page["items"][0]["name"], "iam::admin");
+    assert!(page["nextCursor"].is_null());
+    let admin_tag = tags.iter().find(|tag| tag.name == "iam::admin").unwrap();
+    let admin_path = format!("/api/v1/tags/{}", admin_tag.id);
+    let response = app
+        .get(&format!("{admin_path}/users"), Some(&session))
+        .await;
+    let users: Vec<User> = response.json();
+    assert_eq!(users.len(), 1);
+    assert_eq!(users[0].id(), admin.id());
+    let response = app.delete(&admin_path, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    let response = app.delete(&path, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.get(&format!("{path}/users"), Some(&session)).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let response = app.delete(&path, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    // Only administrators can manage tags
+    let user_session = app.create_session(&admin, false).await;
+    let response = app.get("/api/v1/tags", Some(&user_session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_user_tags() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let user_session = app.create_session(&user, false).await;
+
+    let response = app.get("/api/v1/tags", Some(&admin_session)).await;
+    let admin_tag: Tag =
+        serde_json::from_value(response.json::<serde_json::Value>()["items"][0].clone()).unwrap();
+    let path = format!("/api/v1/users/{}/tags/{}", user.id(), admin_tag.id);
+    let users_path = format!("/api/v1/tags/{}/users", admin_tag.id);
+
+    let response = app
+        .send(Method::PUT, &path, None::<&()>, Some(&user_session))
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    // Granting a tag twice doesn't apply it twice
+    for _ in 0..2 {
+        let response = app
+            .send(Method::PUT, &path, None::<&()>, Some(&admin_session))
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    }
+    let response = app.get(&users_path, Some(&admin_session)).await;
+    assert_eq!(response.json::<Vec<User>>().len(), 2);
+
+    // Taking away the admin tag logs the user out
+    let response = app.delete(&path, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.get(&users_path, Some(&admin_session)).await;
+    assert_eq!(response.json::<Vec<User>>().len(), 1);
+    let response = app.get("/api/v1/users/me", Some(&user_session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let path = format!(
+        "/api/v1/users/{}/tags/{}",
+        uuid::Uuid::new_v4(),
+        admin_tag.id
+    );
+    let response = app
+        .send(Method::PUT, &path, None::<&()>, Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_passkey_management() {
+    let app = TestApp::new().await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let session = app.create_session(&user, false).await;
+    let first = app.create_passkey(&user, Some("Laptop")).await;
+    let second = app.create_passkey(&user, None).await;
+    let other = app.create_user("other@kasad.com", "Other User").await;
+    let other_passkey = app.create_passkey(&other, None).await;
+
+    let response = app.get("/api/v1/users/me/passkeys", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(response.json::<Vec<PasskeyCredential>>().len(), 2);
+
+    let response = app
+        .patch(
+            &format!("/api/v1/passkeys/{}", second.id),
+            &json!({ "displayName": " Phone " }),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let renamed: PasskeyCredential = response.json();
+    assert_eq!(renamed.display_name.as_deref(), Some("Phone"));
+
+    // Other users' passkeys are hidden
+    let path = format!("/api/v1/passkeys/{}", other_passkey.id);
+    let response = app
+        .patch(&path, &json!({ "displayName": "Mine" }), Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let response = app.delete(&path, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    // The last passkey can't be removed
+    let response = app
+        .delete(&format!("/api/v1/passkeys/{}", first.id), Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app
+        .delete(&format!("/api/v1/passkeys/{}", second.id), Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    let response = app.get("/api/v1/users/me/passkeys", Some(&session)).await;
+    let passkeys: Vec<PasskeyCredential> = response.json();
+    assert_eq!(passkeys.len(), 1);
+    assert_eq!(passkeys[0].id, second.id);
+}
+
+#[tokio::test]
+async fn test_concurrent_passkey_deletion() {
+    let app = TestApp::new().await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let session = app.create_session(&user, false).await;
+    let first = app.create_passkey(&user, None).await;
+    let second = app.create_passkey(&user, None).await;
+
+    // Only one of two concurrent deletions succeeds, so the user keeps a passkey
+    let first_path = format!("/api/v1/passkeys/{}", first.id);
+    let second_path = format!("/api/v1/passkeys/{}", second.id);
+    let (first_response, second_response) = tokio::join!(
+        app.delete(&first_path, Some(&session)),
+        app.delete(&second_path, Some(&session)),
+    );
+    let mut statuses = [first_response.status, second_response.status];
+    statuses.sort();
+    assert_eq!(statuses, [StatusCode::OK, StatusCode::CONFLICT]);
+    let passkeys = app.db().get_passkeys_by_user_id(user.id()).await.unwrap();
+    assert_eq!(passkeys.len(), 1);
+}
+
+#[tokio::test]
+async fn test_update_and_delete_user() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let path = format!("/api/v1/users/{}", user.id());
+
+    let response = app
+        .patch(
+            &path,
+            &json!({ "displayName": "Renamed", "email": "renamed@kasad.com" }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let updated: User = response.json();
+    assert_eq!(updated.display_name(), "Renamed");
+    assert_eq!(updated.email(), "renamed@kasad.com");
+
+    let response = app
+        .patch(
+            &path,
+            &json!({ "email": "admin@kasad.com" }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+
+    // Upgrading the user's session gives them a chain of sessions, which is deleted with them
+    app.make_admin(&user).await;
+    let session = app.create_session(&user, false).await;
+    let response = app
+        .post(
+            "/api/v1/auth/upgrade",
+            &json!({ "target": "Admin" }),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.delete(&path, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.get(&path, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let response = app.delete(&path, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_anonymize_user() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let session = app.create_session(&user, false).await;
+    let update = SessionUpdate::new().with_activity(
+        chrono::Utc::now(),
+        Some("192.0.2.1".to_string()),
+        Some("Test Agent".to_string()),
+    );
+    app.db()
+        .update_session(&session.session.id_hash, &update)
+        .await
+        .unwrap();
+    let path = format!("/api/v1/users/{}/anonymize", user.id());
+
+    let response = app.post(&path, &json!({}), Some(&session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    // The user is kept, but without personal data, and logged out
+    let response = app.post(&path, &json!({}), Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let anonymized: User = response.json();
+    assert_eq!(anonymized.id(), user.id());
+    assert_ne!(anonymized.email(), user.email());
+    assert!(anonymized.anonymized_at().is_some());
+    let response = app
+        .get(
+            &format!("/api/v1/users/{}", user.id()),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let stored = app
+        .db()
+        .get_session_by_id_hash(&session.session.id_hash)
+        .await
+        .unwrap();
+    assert_eq!(stored.state, SessionState::Revoked);
+    assert!(stored.ip.is_none());
+    assert!(stored.user_agent.is_none());
+
+    let path = format!("/api/v1/users/{}/anonymize", uuid::Uuid::new_v4());
+    let response = app.post(&path, &json!({}), Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_update_current_user() {
+    let mailbox = TestMailbox::default();
+    let config = AppConfig {
+        allowed_email_domains: vec!["kasad.com".to_string()],
+        ..test_config()
+    };
+    let options = ApiOptions {
+        mailer: Some(mailbox.mailer()),
+        ..ApiOptions::default()
+    };
+    let app = TestApp::with_config(&config, options).await;
+    app.create_user("other@kasad.com", "Other User").await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let session = app.create_session(&user, false).await;
+
+    // The display name changes right away, but the email address only once it is confirmed
+    let response = app
+        .patch(
+            "/api/v1/users/me",
+            &json!({ "displayName": " New Name ", "email": "New@kasad.com" }),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let updated: User = response.json();
+    assert_eq!(updated.display_name(), "New Name");
+    assert_eq!(updated.email(), "test@kasad.com");
+    let message = mailbox.receive("new@kasad.com").await;
+    let token = message
+        .text
+        .split("/confirm-email?token=")
+        .nth(1)
+        .and_then(|rest| rest.split_whitespace().next())
+        .expect("confirmation email lacks link")
+        .to_string();
+
+    let response = app
+        .patch(
+            "/api/v1/users/me",
+            &json!({ "email": "Other@Kasad.com" }),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    let response = app
+        .patch(
+            "/api/v1/users/me",
+            &json!({ "email": "test@example.com" }),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.json::<serde_json::Value>()["error"]["code"],
+        "email_domain_not_allowed"
+    );
+
+    for (body, field) in [
+        (json!({ "displayName": "  " }), "displayName"),
+        (json!({ "email": "not an email" }), "email"),
+        (json!({ "email": "@kasad.com" }), "email"),
+        (json!({ "email": "Test User <test@kasad.com>" }), "email"),
+    ] {
+        let response = app.patch("/api/v1/users/me", &body, Some(&session)).await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY, "{body}");
+        let error: serde_json::Value = response.json();
+        assert!(
+            error["error"]["details"]["fields"][field].is_array(),
+            "{error}"
+        );
+    }
+
+    // Following the link changes the address, which counts as verified from then on
+    let response = app
+        .post(
+            "/api/v1/auth/confirm-email",
+            &json!({ "token": "abc" }),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let response = app
+        .post(
+            "/api/v1/auth/confirm-email",
+            &json!({ "token": token }),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    let updated: User = response.json();
+    assert_eq!(updated.email(), "new@kasad.com");
+    assert!(updated.email_verified_at().is_some());
+    let response = app
+        .post(
+            "/api/v1/auth/confirm-email",
+            &json!({ "token": token }),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_email_change_enumeration_protection() {
+    let mailbox = TestMailbox::default();
+    let app = TestApp::with_options(ApiOptions {
+        mailer: Some(mailbox.mailer()),
+        enumeration_protection: Some(EnumerationProtection {
+            secret: b"secret".as_slice().into(),
+        }),
+        ..ApiOptions::default()
+    })
+    .await;
+    app.create_user("other@kasad.com", "Other User").await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let session = app.create_session(&user, false).await;
+
+    // Taken and free addresses get the same response
+    let mut bodies = Vec::new();
+    for email in ["other@kasad.com", "new@kasad.com"] {
+        let response = app
+            .patch(
+                "/api/v1/users/me",
+                &json!({ "email": email }),
+                Some(&session),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+        bodies.push(response.text());
+        mailbox.receive(email).await;
+    }
+    assert_eq!(bodies[0], bodies[1]);
+}
+
+#[tokio::test]
+async fn test_request_validation() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let session = app.create_session(&admin, true).await;
+
+    let body = json!({ "email": "not an email", "displayName": "x".repeat(101) });
+    let response = app.post("/api/v1/users", &body, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let error: serde_json::Value = response.json();
+    assert_eq!(error["error"]["code"], "validation_failed");
+    assert_eq!(
+        error["error"]["details"]["fields"],
+        json!({
+            "displayName": ["must be at most 100 characters long"],
+            "email": ["must be a valid email address"],
+        })
+    );
+    assert_eq!(
+        error["error"]["message"],
+        "Invalid request body: displayName: must be at most 100 characters long; \
+        email: must be a valid email address"
+    );
+
+    let response = app
+        .post("/api/v1/tags", &json!({ "name": " " }), Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.json::<serde_json::Value>()["error"]["details"]["fields"]["name"],
+        json!(["must not be blank"])
+    );
+
+    // Bodies which don't deserialize are still rejected by the JSON extractor
+    let response = app
+        .post("/api/v1/tags", &json!({ "title": "x" }), Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn test_webhooks() {
+    let app = TestApp::new().await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let admin = app.create_user("admin@kasad.com", "Admin User").await;
+    app.make_admin(&admin).await;
+    let user_session = app.create_session(&user, false).await;
+    let admin_session = app.create_session(&admin, true).await;
+
+    let response = app.get("/api/v1/webhooks", Some(&user_session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = app
+        .post(
+            "/api/v1/webhooks",
+            &json!({ "url": "ftp://example.com/hook" }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    // Receiver which records the deliveries it gets
+    let deliveries = Arc::new(Mutex::new(Vec::new()));
+    let receiver = axum::Router::new().route(
+        "/hook",
+        axum::routing::post({
+            let deliveries = Arc::clone(&deliveries);
+            move |headers: HeaderMap, body: Bytes| async move {
+                deliveries.lock().unwrap().push((headers, body));
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let receiver_addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, receiver).await });
+
+    let response = app
+        .post(
+            "/api/v1/webhooks",
+            &json!({ "url": format!("http://{receiver_addr}/hook"), "events": ["user.created"] }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let created: serde_json::Value = response.json();
+    let id = created["id"].as_str().unwrap().to_string();
+    let secret = created["secret"].as_str().unwrap().to_string();
+
+    // The secret isn't exposed after creation
+    let response = app
+        .get(&format!("/api/v1/webhooks/{id}"), Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let fetched: serde_json::Value = response.json();
+    assert!(fetched.get("secret").is_none());
+    assert_eq!(fetched["events"], json!(["user.created"]));
+
+    let response = app
+        .post(
+            "/api/v1/users",
+            &json!({ "email": "new@kasad.com", "displayName": "New User" }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let new_user: User = response.json();
+
+    let mut attempts = 0;
+    while deliveries.lock().unwrap().is_empty() {
+        attempts += 1;
+        assert!(attempts < 100, "webhook was never delivered");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let (headers, body) = deliveries.lock().unwrap().remove(0);
+    assert_eq!(headers[EVENT_HEADER], "user.created");
+    let signature = headers[SIGNATURE_HEADER].to_str().unwrap();
+    let (timestamp, signature) = signature
+        .strip_prefix("t=")
+        .and_then(|rest| rest.split_once(",v1="))
+        .unwrap();
+    assert_eq!(
+        signature,
+        webhooks::sign(secret.as_bytes(), timestamp.parse().unwrap(), &body)
+    );
+    let delivery: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(delivery["type"], "user.created");
+    assert_eq!(delivery["data"]["userId"], json!(new_user.id()));
+    assert_eq!(headers[DELIVERY_HEADER], delivery["id"].as_str().unwrap());
+
+    let response = app
+        .patch(
+            &format!("/api/v1/webhooks/{id}"),
+            &json!({ "enabled": false }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json::<serde_json::Value>()["enabled"], false);
+
+    let response = app
+        .delete(&format!("/api/v1/webhooks/{id}"), Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app
+        .delete(&format!("/api/v1/webhooks/{id}"), Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert!(deliveries.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_api_tokens() {
+    let app = TestApp::new().await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let session = app.create_session(&user, false).await;
+
+    for body in [
+        json!({ "name": " " }),
+        json!({ "name": "CI", "scopes": [] }),
+        json!({ "name": "CI", "expiresAt": "2000-01-01T00:00:00Z" }),
+    ] {
+        let response = app
+            .post("/api/v1/users/me/tokens", &body, Some(&session))
+            .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST, "{body}");
+    }
+
+    let response = app
+        .post(
+            "/api/v1/users/me/tokens",
+            &json!({ "name": "CI" }),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let created: serde_json::Value = response.json();
+    let read_token = created["token"].as_str().unwrap().to_string();
+    assert!(read_token.starts_with("iam_"));
+    assert_eq!(created["scopes"], json!(["read"]));
+
+    let response = app
+        .post(
+            "/api/v1/users/me/tokens",
+            &json!({ "name": "Deploy", "scopes": ["write"] }),
+            Some(&session),
+        )
+        .await;
+    let created: serde_json::Value = response.json();
+    let write_id = created["id"].as_str().unwrap().to_string();
+    let write_token = created["token"].as_str().unwrap().to_string();
+
+    let response = app.get("/api/v1/users/me/tokens", Some(&session)).await;
+    let tokens: Vec<serde_json::Value> = response.json();
+    assert_eq!(tokens.len(), 2);
+    assert!(tokens.iter().all(|token| token.get("token").is_none()));
+
+    let response = app
+        .send_with_token(Method::GET, "/api/v1/users/me", None::<&()>, &read_token)
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(response.json::<User>().id(), user.id());
+
+    // Read-only tokens can't modify anything
+    let rename = json!({ "displayName": "Renamed" });
+    let response = app
+        .send_with_token(
+            Method::PATCH,
+            "/api/v1/users/me",
+            Some(&rename),
+            &read_token,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = app
+        .send_with_token(
+            Method::PATCH,
+            "/api/v1/users/me",
+            Some(&rename),
+            &write_token,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    // Tokens can't be used to manage tokens, or on endpoints which require a session
+    let response = app
+        .send_with_token(
+            Method::GET,
+            "/api/v1/users/me/tokens",
+            None::<&()>,
+            &write_token,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = app
+        .send_with_token(Method::GET, "/api/v1/users/me", None::<&()>, "iam_bogus")
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let response = app
+        .delete(
+            &format!("/api/v1/users/me/tokens/{write_id}"),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app
+        .send_with_token(Method::GET, "/api/v1/users/me", None::<&()>, &write_token)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    // Other users can't revoke the token
+    let other = app.create_user("other@kasad.com", "Other User").await;
+    let other_session = app.create_session(&other, false).await;
+    let id = tokens[0]["id"].as_str().unwrap();
+    let response = app
+        .delete(
+            &format!("/api/v1/users/me/tokens/{id}"),
+            Some(&other_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_oauth_clients() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let session = app.create_session(&admin, true).await;
+
+    for body in [
+        json!({ "name": "App", "redirectUris": [] }),
+        json!({ "name": "App", "redirectUris": ["/callback"] }),
+        json!({ "name": "App", "redirectUris": ["https://app.example.com/cb#frag"] }),
+        json!({ "name": " ", "redirectUris": ["https://app.example.com/cb"] }),
+    ] {
+        let response = app
+            .post("/api/v1/oauth/clients", &body, Some(&session))
+            .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST, "{body}");
+    }
+    let body = json!({ "name": "App", "redirectUris": ["https://app.example.com/cb"] });
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let user_session = app.create_session(&user, false).await;
+    let response = app
+        .post("/api/v1/oauth/clients", &body, Some(&user_session))
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let response = app
+        .post("/api/v1/oauth/clients", &body, Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let created: serde_json::Value = response.json();
+    assert!(created["clientSecret"].is_string());
+    let path = format!("/api/v1/oauth/clients/{}", created["id"].as_str().unwrap());
+    let response = app.get(&path, Some(&session)).await;
+    let fetched: serde_json::Value = response.json();
+    assert!(fetched.get("clientSecret").is_none());
+    assert!(fetched.get("secretHash").is_none());
+
+    let response = app
+        .patch(&path, &json!({ "name": "Renamed" }), Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(response.json::<serde_json::Value>()["name"], "Renamed");
+    let response = app.get("/api/v1/oauth/clients", Some(&session)).await;
+    assert_eq!(response.json::<Vec<serde_json::Value>>().len(), 1);
+
+    let response = app.delete(&path, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.delete(&path, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    let response = app
+        .get("/api/v1/oauth/.well-known/openid-configuration", None)
+        .await;
+    let metadata: serde_json::Value = response.json();
+    assert_eq!(metadata["issuer"], "http://localhost:3000/api/v1/oauth");
+    assert_eq!(
+        metadata["token_endpoint"],
+        "http://localhost:3000/api/v1/oauth/token"
+    );
+    let response = app.get("/api/v1/oauth/jwks", None).await;
+    let jwks: serde_json::Value = response.json();
+    assert_eq!(jwks["keys"][0]["alg"], "ES256");
+}
+
+/// Registers an OAuth client with the given redirect URL, returning its ID and secret.
+async fn create_oauth_client(app: &TestApp, redirect_uri: &str) -> (String, String) {
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let session = app.create_session(&admin, true).await;
+    let body = json!({ "name": "App", "redirectUris": [redirect_uri] });
+    let response = app
+        .post("/api/v1/oauth/clients", &body, Some(&session))
+        .await;
+    let created: serde_json::Value = response.json();
+    (
+        created["id"].as_str().unwrap().to_string(),
+        created["clientSecret"].as_str().unwrap().to_string(),
+    )
+}
+
+/// Sends an OAuth authorization request with the given PKCE code challenge, returning the
+/// response.
+async fn oauth_authorize(
+    app: &TestApp,
+    session: Option<&TestSession>,
+    client_id: &str,
+    redirect_uri: &str,
+    challenge: &str,
+) -> TestResponse {
+    let mut url = Url::parse("http://localhost/api/v1/oauth/authorize").unwrap();
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", client_id)
+        .append_pair("redirect_uri", redirect_uri)
+        .append_pair("scope", "openid email")
+        .append_pair("state", "xyz")
+        .append_pair("nonce", "n-0S6")
+        .append_pair("code_challenge", challenge)
+        .append_pair("code_challenge_method", "S256");
+    let path = format!("{}?{}", url.path(), url.query().unwrap());
+    app.get(&path, session).await
+}
+
+/// Returns the authorization code from the redirect of a successful authorization request.
+fn authorization_code(response: &TestResponse) -> String {
+    assert_eq!(
+        response.status,
+        StatusCode::SEE_OTHER,
+        "{}",
+        response.text()
+    );
+    let location = Url::parse(response.headers[LOCATION].to_str().unwrap()).unwrap();
+    assert!(location.as_str().starts_with("https://app.example.com/cb?"));
+    let param = |name: &str| {
+        location
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+    assert_eq!(param("state").as_deref(), Some("xyz"));
+    assert_eq!(
+        param("iss").as_deref(),
+        Some("http://localhost:3000/api/v1/oauth")
+    );
+    param("code").unwrap()
+}
+
+#[tokio::test]
+async fn test_oauth_flow() {
+    let app = TestApp::new().await;
+    let redirect_uri = "https://app.example.com/cb";
+    let (client_id, secret) = create_oauth_client(&app, redirect_uri).await;
+    let basic = format!(
+        "Basic {}",
+        BASE64_STANDARD.encode(format!("{client_id}:{secret}"))
+    );
+    let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    let challenge = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(verifier));
+
+    // Users who aren't logged in are sent to the login page, and bad redirect URLs are rejected
+    let response = oauth_authorize(&app, None, &client_id, redirect_uri, &challenge).await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    let location = response.headers[LOCATION].to_str().unwrap();
+    assert!(location.starts_with("/login?next=%2Fapi%2Fv1%2Foauth%2Fauthorize%3F"));
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let session = app.create_session(&user, false).await;
+    let response = oauth_authorize(
+        &app,
+        Some(&session),
+        &client_id,
+        "https://evil.example.com/cb",
+        &challenge,
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    // The code is consumed even if the code verifier is wrong
+    let code = authorization_code(
+        &oauth_authorize(&app, Some(&session), &client_id, redirect_uri, &challenge).await,
+    );
+    let mut fields = vec![
+        ("grant_type", "authorization_code"),
+        ("code", &code),
+        ("redirect_uri", redirect_uri),
+        ("code_verifier", "wrong"),
+    ];
+    let response = app.post_form("/api/v1/oauth/token", &fields, None).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.json::<serde_json::Value>()["error"],
+        "invalid_client"
+    );
+    let response = app
+        .post_form("/api/v1/oauth/token", &fields, Some(&basic))
+        .await;
+    assert_eq!(
+        response.json::<serde_json::Value>()["error"],
+        "invalid_grant"
+    );
+
+    let code = authorization_code(
+        &oauth_authorize(&app, Some(&session), &client_id, redirect_uri, &challenge).await,
+    );
+    fields[1].1 = &code;
+    fields[3].1 = verifier;
+    fields.extend([
+        ("client_id", client_id.as_str()),
+        ("client_secret", &secret),
+    ]);
+    let response = app.post_form("/api/v1/oauth/token", &fields, None).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let tokens: serde_json::Value = response.json();
+    assert_eq!(tokens["token_type"], "Bearer");
+    let id_token = tokens["id_token"].as_str().unwrap();
+    let claims: serde_json::Value = serde_json::from_slice(
+        &BASE64_URL_SAFE_NO_PAD
+            .decode(id_token.split('.').nth(1).unwrap())
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(claims["sub"], user.id().to_string());
+    assert_eq!(claims["aud"], client_id);
+    assert_eq!(claims["nonce"], "n-0S6");
+    assert_eq!(claims["email"], "test@kasad.com");
+    assert!(claims.get("name").is_none());
+    let response = app.post_form("/api/v1/oauth/token", &fields, None).await;
+    assert_eq!(
+        response.json::<serde_json::Value>()["error"],
+        "invalid_grant"
+    );
+
+    let access_token = tokens["access_token"].as_str().unwrap();
+    let response = app
+        .send_with_token(
+            Method::GET,
+            "/api/v1/oauth/userinfo",
+            None::<&()>,
+            access_token,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(
+        response.json::<serde_json::Value>(),
+        json!({ "sub": user.id(), "email": "test@kasad.com" })
+    );
+    // ID tokens aren't accepted as access tokens
+    let response = app
+        .send_with_token(Method::GET, "/api/v1/oauth/userinfo", None::<&()>, id_token)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_well_known_jwks() {
+    let keys = Arc::new(KeySet::new(SigningKey::generate().unwrap()));
+    let app = TestApp::with_options(ApiOptions {
+        signing_keys: Some(Arc::clone(&keys)),
+        ..Default::default()
+    })
+    .await;
+    let response = new_well_known_router(Arc::clone(&keys))
+        .oneshot(
+            Request::get("/.well-known/jwks.json")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let jwks: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(jwks["keys"][0]["kid"], keys.active().kid());
+
+    // The OAuth provider signs with the same keys
+    let response = app.get("/api/v1/oauth/jwks", None).await;
+    assert_eq!(response.json::<serde_json::Value>(), jwks);
+
+    // Retired keys stay published
+    keys.replace(vec![SigningKey::generate().unwrap(), keys.active()]);
+    let response = app.get("/api/v1/oauth/jwks", None).await;
+    let jwks: serde_json::Value = response.json();
+    assert_eq!(jwks["keys"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_saml_service_providers() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let session = app.create_session(&admin, true).await;
+
+    for body in [
+        json!({ "entityId": " ", "name": "Wiki", "acsUrl": "https://wiki.example.com/acs" }),
+        json!({ "entityId": "wiki", "name": "Wiki", "acsUrl": "/acs" }),
+    ] {
+        let response = app
+            .post("/api/v1/saml/providers", &body, Some(&session))
+            .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST, "{body}");
+    }
+    let body =
+        json!({ "entityId": "wiki", "name": "Wiki", "acsUrl": "https://wiki.example.com/acs" });
+    let response = app
+        .post("/api/v1/saml/providers", &body, Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let created: serde_json::Value = response.json();
+    assert_eq!(created["nameIdFormat"], "persistent");
+    let response = app
+        .post("/api/v1/saml/providers", &body, Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    let path = format!("/api/v1/saml/providers/{}", created["id"].as_str().unwrap());
+    let response = app
+        .patch(
+            &path,
+            &json!({ "nameIdFormat": "email-address" }),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(
+        response.json::<serde_json::Value>()["nameIdFormat"],
+        "email-address"
+    );
+    let response = app.get("/api/v1/saml/providers", Some(&session)).await;
+    assert_eq!(response.json::<Vec<serde_json::Value>>().len(), 1);
+    let response = app.delete(&path, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.get(&path, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    let response = app.get("/api/v1/saml/metadata", None).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.headers[CONTENT_TYPE],
+        "application/samlmetadata+xml"
+    );
+    let metadata = response.text();
+    assert!(metadata.contains(r#"entityID="http://localhost:3000/api/v1/saml/metadata""#));
+    assert!(metadata.contains(r#"Location="http://localhost:3000/api/v1/saml/sso""#));
+    assert!(metadata.contains("<ds:X509Certificate>"));
+}
+
+/// Returns a base64-encoded SAML authentication request from the given service provider.
+fn saml_request(issuer: &str, acs_url: &str) -> String {
+    BASE64_STANDARD.encode(format!(
+        r#"<samlp:AuthnRequest xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol" xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" ID="_req1" Version="2.0" IssueInstant="2026-10-17T00:00:00Z" AssertionConsumerServiceURL="{acs_url}"><saml:Issuer>{issuer}</saml:Issuer></samlp:AuthnRequest>"#
+    ))
+}
+
+#[tokio::test]
+async fn test_saml_sso() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let acs_url = "https://wiki.example.com/acs";
+    let body = json!({ "entityId": "wiki", "name": "Wiki", "acsUrl": acs_url, "nameIdFormat": "email-address" });
+    app.post("/api/v1/saml/providers", &body, Some(&admin_session))
+        .await;
+
+    for request in [
+        saml_request("unknown", acs_url),
+        saml_request("wiki", "https://evil.example.com/acs"),
+        "not base64".to_string(),
+    ] {
+        let response = app
+            .post_form("/api/v1/saml/sso", &[("SAMLRequest", &request)], None)
+            .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    // Users who aren't logged in are sent to the login page, which returns them via `GET`
+    let request = saml_request("wiki", acs_url);
+    let fields = [("SAMLRequest", request.as_str()), ("RelayState", "/page")];
+    let response = app.post_form("/api/v1/saml/sso", &fields, None).await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    let location = Url::parse(&format!(
+        "http://localhost{}",
+        response.headers[LOCATION].to_str().unwrap()
+    ))
+    .unwrap();
+    assert_eq!(location.path(), "/login");
+    let (_, next) = location
+        .query_pairs()
+        .find(|(key, _)| key == "next")
+        .unwrap();
+    assert!(next.starts_with("/api/v1/saml/sso?"));
+
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let response = app
+        .post(
+            "/api/v1/tags",
+            &json!({ "name": "staff" }),
+            Some(&admin_session),
+        )
+        .await;
+    let tag: Tag = response.json();
+    let path = format!("/api/v1/users/{}/tags/{}", user.id(), tag.id);
+    app.send(Method::PUT, &path, None::<&()>, Some(&admin_session))
+        .await;
+    let session = app.create_session(&user, false).await;
+    let response = app.get(&next, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let page = response.text();
+    assert!(page.contains(r#"action="https://wiki.example.com/acs""#));
+    assert!(page.contains(r#"name="RelayState" value="/page""#));
+    let encoded = page
+        .split(r#"name="SAMLResponse" value=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap();
+    let xml = String::from_utf8(BASE64_STANDARD.decode(encoded).unwrap()).unwrap();
+    assert!(xml.contains(r#"InResponseTo="_req1""#));
+    assert!(xml.contains(">test@kasad.com</saml:NameID>"));
+    assert!(xml.contains("<saml:Audience>wiki</saml:Audience>"));
+    assert!(xml.contains("<saml:AttributeValue>staff</saml:AttributeValue>"));
+    assert!(xml.contains("<ds:SignatureValue>"));
+}
+
+#[tokio::test]
+async fn test_federated_providers() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let session = app.create_session(&admin, true).await;
+
+    let valid = json!({
+        "slug": "google",
+        "name": "Google",
+        "issuer": "https://accounts.google.com",
+        "clientId": "client",
+        "clientSecret": "secret",
+    });
+    for (field, value) in [
+        ("slug", json!("Google Workspace")),
+        ("name", json!(" ")),
+        ("issuer", json!("accounts.google.com")),
+    ] {
+        let mut body = valid.clone();
+        body[field] = value;
+        let response = app
+            .post("/api/v1/federation/providers", &body, Some(&session))
+            .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST, "{field}");
+    }
+    let response = app
+        .post("/api/v1/federation/providers", &valid, Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let created: serde_json::Value = response.json();
+    assert_eq!(created["autoProvision"], false);
+    assert!(created.get("clientSecret").is_none());
+    let response = app
+        .post("/api/v1/federation/providers", &valid, Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    let path = format!(
+        "/api/v1/federation/providers/{}",
+        created["id"].as_str().unwrap()
+    );
+    let response = app
+        .patch(&path, &json!({ "autoProvision": true }), Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(response.json::<serde_json::Value>()["autoProvision"], true);
+
+    // The login page can list providers without logging in
+    let response = app.get("/api/v1/auth/federated", None).await;
+    assert_eq!(
+        response.json::<serde_json::Value>(),
+        json!([{ "slug": "google", "name": "Google" }])
+    );
+
+    let response = app.delete(&path, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.get(&path, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+/// Mock upstream OIDC provider, whose token endpoint issues an ID token with the claims in
+/// `claims`
+struct MockProvider {
+    issuer: String,
+    claims: Arc<Mutex<serde_json::Value>>,
+    /// Form fields of the last token request
+    token_request: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+async fn mock_provider() -> MockProvider {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let claims = Arc::new(Mutex::new(json!({})));
+    let token_request = Arc::new(Mutex::new(Vec::new()));
+    let metadata = json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{issuer}/authorize"),
+        "token_endpoint": format!("{issuer}/token"),
+    });
+    let router = axum::Router::new()
+        .route(
+            "/.well-known/openid-configuration",
+            axum::routing::get(move || async move { axum::Json(metadata) }),
+        )
+        .route(
+            "/token",
+            axum::routing::post({
+                let claims = Arc::clone(&claims);
+                let token_request = Arc::clone(&token_request);
+                move |axum::Form(form): axum::Form<Vec<(String, String)>>| async move {
+                    *token_request.lock().unwrap() = form;
+                    let claims = claims.lock().unwrap().to_string();
+                    let id_token = format!("e30.{}.c2ln", BASE64_URL_SAFE_NO_PAD.encode(claims));
+                    axum::Json(json!({ "id_token": id_token, "token_type": "Bearer" }))
+                }
+            }),
+        );
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    MockProvider {
+        issuer,
+        claims,
+        token_request,
+    }
+}
+
+/// Logs in at the mock provider as the account with the given claims, returning the response of
+/// the callback endpoint.
+async fn federated_login(
+    app: &TestApp,
+    provider: &MockProvider,
+    claims: &serde_json::Value,
+    session: Option<&TestSession>,
+) -> TestResponse {
+    let response = app
+        .get("/api/v1/auth/federated/corp/start?next=/account", session)
+        .await;
+    assert_eq!(
+        response.status,
+        StatusCode::SEE_OTHER,
+        "{}",
+        response.text()
+    );
+    let location = Url::parse(response.headers[LOCATION].to_str().unwrap()).unwrap();
+    assert_eq!(location.path(), "/authorize");
+    let param = |name: &str| {
+        location
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .unwrap()
+            .1
+            .into_owned()
+    };
+    let state = param("state");
+    assert_eq!(
+        response.cookie("federation_state").as_deref(),
+        Some(state.as_str())
+    );
+
+    let mut claims = claims.clone();
+    claims["iss"] = json!(provider.issuer);
+    claims["aud"] = json!("client");
+    claims["exp"] = json!(chrono::Utc::now().timestamp() + 60);
+    claims["nonce"] = json!(param("nonce"));
+    *provider.claims.lock().unwrap() = claims;
+    let response = app
+        .send_with_cookie::<()>(
+            Method::GET,
+            &format!("/api/v1/auth/federated/corp/callback?code=abc&state={state}"),
+            None,
+            Some(&format!("federation_state={state}")),
+        )
+        .await;
+
+    // PKCE is used
+    let token_request = provider.token_request.lock().unwrap().clone();
+    let (_, verifier) = token_request
+        .iter()
+        .find(|(key, _)| key == "code_verifier")
+        .unwrap();
+    assert_eq!(
+        BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(verifier)),
+        param("code_challenge")
+    );
+    response
+}
+
+#[tokio::test]
+async fn test_federated_login() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let provider = mock_provider().await;
+    let response = app
+        .post(
+            "/api/v1/federation/providers",
+            &json!({
+                "slug": "corp",
+                "name": "Corp",
+                "issuer": provider.issuer,
+                "clientId": "client",
+                "clientSecret": "secret",
+            }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let provider_id = response.json::<serde_json::Value>()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let response = app
+        .get("/api/v1/auth/federated/corp/start?next=//evil.com", None)
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    // The account is linked to the user with the same verified email address
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    app.verify_email(&user).await;
+    let claims = json!({ "sub": "1", "email": "test@kasad.com", "email_verified": true });
+    let response = federated_login(&app, &provider, &claims, None).await;
+    assert_eq!(
+        response.status,
+        StatusCode::SEE_OTHER,
+        "{}",
+        response.text()
+    );
+    assert_eq!(response.headers[LOCATION], "/account");
+    assert!(response.cookie("session_id").is_some());
+    let session = app.create_session(&user, false).await;
+    let response = app
+        .get("/api/v1/users/me/federated-identities", Some(&session))
+        .await;
+    let identities: Vec<serde_json::Value> = response.json();
+    assert_eq!(identities.len(), 1);
+    assert_eq!(identities[0]["subject"], "1");
+
+    // Once linked, the account logs in as the user even if its email address changes
+    let claims = json!({ "sub": "1", "email": "renamed@kasad.com" });
+    let response = federated_login(&app, &provider, &claims, None).await;
+    assert_eq!(
+        response.status,
+        StatusCode::SEE_OTHER,
+        "{}",
+        response.text()
+    );
+
+    // Unverified email addresses aren't trusted, and users aren't created unless allowed
+    let claims = json!({ "sub": "2", "email": "admin@kasad.com" });
+    let response = federated_login(&app, &provider, &claims, None).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let claims = json!({ "sub": "3", "email": "new@kasad.com", "email_verified": true });
+    let response = federated_login(&app, &provider, &claims, None).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    app.patch(
+        &format!("/api/v1/federation/providers/{provider_id}"),
+        &json!({ "autoProvision": true }),
+        Some(&admin_session),
+    )
+    .await;
+    let claims =
+        json!({ "sub": "3", "email": "new@kasad.com", "email_verified": true, "name": "New User" });
+    let response = federated_login(&app, &provider, &claims, None).await;
+    assert_eq!(
+        response.status,
+        StatusCode::SEE_OTHER,
+        "{}",
+        response.text()
+    );
+    let created = app.db().get_user_by_email("new@kasad.com").await.unwrap();
+    assert_eq!(created.display_name(), "New User");
+
+    // Logged-in users link accounts to themselves, but can't take over linked accounts
+    let claims = json!({ "sub": "4" });
+    let response = federated_login(&app, &provider, &claims, Some(&session)).await;
+    assert_eq!(
+        response.status,
+        StatusCode::SEE_OTHER,
+        "{}",
+        response.text()
+    );
+    assert!(response.cookie("session_id").is_none());
+    let claims = json!({ "sub": "3" });
+    let response = federated_login(&app, &provider, &claims, Some(&session)).await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+
+    let response = app
+        .delete(
+            &format!("/api/v1/users/me/federated-identities/{provider_id}"),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app
+        .get("/api/v1/users/me/federated-identities", Some(&session))
+        .await;
+    assert!(response.json::<Vec<serde_json::Value>>().is_empty());
+}
+
+#[tokio::test]
+async fn test_federated_provisioning_follows_registration_mode() {
+    let config = AppConfig {
+        registration_mode: RegistrationMode::Closed,
+        ..test_config()
+    };
+    let app = TestApp::with_config(&config, ApiOptions::default()).await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let provider = mock_provider().await;
+    let response = app
+        .post(
+            "/api/v1/federation/providers",
+            &json!({
+                "slug": "corp",
+                "name": "Corp",
+                "issuer": provider.issuer,
+                "clientId": "client",
+                "clientSecret": "secret",
+                "autoProvision": true,
+            }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    // Users aren't provisioned while registration is closed
+    let claims = json!({ "sub": "1", "email": "new@kasad.com", "email_verified": true });
+    let response = federated_login(&app, &provider, &claims, None).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.json::<serde_json::Value>()["error"]["code"],
+        "registration_closed"
+    );
+    assert!(app.db().get_user_by_email("new@kasad.com").await.is_err());
+
+    // Existing users can still log in, once they confirmed that they own their address
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let claims = json!({ "sub": "2", "email": "test@kasad.com", "email_verified": true });
+    let response = federated_login(&app, &provider, &claims, None).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    app.verify_email(&user).await;
+    let response = federated_login(&app, &provider, &claims, None).await;
+    assert_eq!(
+        response.status,
+        StatusCode::SEE_OTHER,
+        "{}",
+        response.text()
+    );
+}
+
+#[tokio::test]
+async fn test_federated_login_state() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let provider = mock_provider().await;
+    app.post(
+        "/api/v1/federation/providers",
+        &json!({
+            "slug": "corp",
+            "name": "Corp",
+            "issuer": provider.issuer,
+            "clientId": "client",
+            "clientSecret": "secret",
+        }),
+        Some(&admin_session),
+    )
+    .await;
+
+    let response = app.get("/api/v1/auth/federated/corp/start", None).await;
+    let state = response.cookie("federation_state").unwrap();
+    let callback = format!("/api/v1/auth/federated/corp/callback?code=abc&state={state}");
+
+    // The state must come from the browser which started the login
+    for cookie in [None, Some("federation_state=other")] {
+        let response = app
+            .send_with_cookie::<()>(Method::GET, &callback, None, cookie)
+            .await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    }
+
+    // Invalid ID tokens are rejected, and states can't be reused
+    *provider.claims.lock().unwrap() =
+        json!({ "sub": "1", "iss": provider.issuer, "aud": "client", "exp": 0 });
+    let cookie = format!("federation_state={state}");
+    let response = app
+        .send_with_cookie::<()>(Method::GET, &callback, None, Some(&cookie))
+        .await;
+    assert_eq!(
+        response.status,
+        StatusCode::BAD_GATEWAY,
+        "{}",
+        response.text()
+    );
+    let response = app
+        .send_with_cookie::<()>(Method::GET, &callback, None, Some(&cookie))
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    let response = app.get("/api/v1/auth/federated/unknown/start", None).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+/// Sends a `PUT` request with the given JSON body.
+async fn put(
+    app: &TestApp,
+    path: &str,
+    body: &serde_json::Value,
+    session: &TestSession,
+) -> TestResponse {
+    app.send_with_cookie(Method::PUT, path, Some(body), Some(&session.cookie))
+        .await
+}
+
+/// Creates an organization with an administrator, whose session acts within it. Returns the
+/// organization's ID and the administrator's session.
+async fn create_organization(app: &TestApp) -> (String, TestSession) {
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let owner = app.create_user("owner@kasad.com", "Owner").await;
+    let owner_session = app.create_session(&owner, false).await;
+    let response = app
+        .post(
+            "/api/v1/orgs",
+            &json!({ "name": "Alpha" }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let id = response.json::<serde_json::Value>()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let path = format!("/api/v1/orgs/{id}/users/{}", owner.id());
+    let response = put(app, &path, &json!({ "role": "admin" }), &admin_session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let select = json!({ "organizationId": id });
+    let response = put(app, "/api/v1/auth/organization", &select, &owner_session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    (id, owner_session)
+}
+
+#[tokio::test]
+async fn test_organizations() {
+    let app = TestApp::new().await;
+    let (id, owner_session) = create_organization(&app).await;
+    let org = format!("/api/v1/orgs/{id}");
+    let admin = app.db().get_user_by_email("admin@kasad.com").await.unwrap();
+    let admin_session = app.create_session(&admin, true).await;
+    let member = app.create_user("member@kasad.com", "Member").await;
+    let member_session = app.create_session(&member, false).await;
+
+    // Only administrators create organizations, and names are unique
+    let body = json!({ "name": "Alpha" });
+    let response = app.post("/api/v1/orgs", &body, Some(&owner_session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = app.post("/api/v1/orgs", &body, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+
+    // Sessions must select the organization before acting within it, and only members can
+    let path = format!("{org}/users/{}", member.id());
+    let response = put(&app, &path, &json!({ "role": "member" }), &owner_session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.get(&org, Some(&member_session)).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "organization_not_selected");
+    let select = json!({ "organizationId": id });
+    let response = put(&app, "/api/v1/auth/organization", &select, &member_session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(response.json::<serde_json::Value>()["organizationId"], id);
+    let response = app.get(&org, Some(&member_session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app
+        .get("/api/v1/users/me/organizations", Some(&member_session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.json::<Vec<serde_json::Value>>()[0]["name"],
+        "Alpha"
+    );
+    let outsider = app.create_user("outsider@kasad.com", "Outsider").await;
+    let outsider_session = app.create_session(&outsider, false).await;
+    let response = put(
+        &app,
+        "/api/v1/auth/organization",
+        &select,
+        &outsider_session,
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "not_organization_member");
+
+    // Members can't manage the organization
+    let response = app
+        .patch(&org, &json!({ "name": "Renamed" }), Some(&member_session))
+        .await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "not_organization_admin");
+    let response = app
+        .patch(&org, &json!({ "name": "Renamed" }), Some(&owner_session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    let response = app.delete(&org, Some(&owner_session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = app.delete(&org, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.get(&org, Some(&owner_session)).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_organization_members_and_tags() {
+    let app = TestApp::new().await;
+    let (id, owner_session) = create_organization(&app).await;
+    let org = format!("/api/v1/orgs/{id}");
+    let member = app.create_user("member@kasad.com", "Member").await;
+    let member_session = app.create_session(&member, false).await;
+    let outsider = app.create_user("outsider@kasad.com", "Outsider").await;
+
+    // Organization administrators manage members and tags
+    let path = format!("{org}/users/{}", member.id());
+    let response = put(&app, &path, &json!({ "role": "member" }), &owner_session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app
+        .post(
+            &format!("{org}/tags"),
+            &json!({ "name": "staff" }),
+            Some(&owner_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let tag: Tag = response.json();
+    assert_eq!(tag.organization_id.unwrap().to_string(), id);
+    let path = format!("{org}/users/{}/tags/{}", member.id(), tag.id);
+    let response = put(&app, &path, &json!({}), &owner_session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(
+        app.db()
+            .get_tags_by_user_id(member.id())
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    let path = format!("{org}/users/{}/tags/{}", outsider.id(), tag.id);
+    let response = put(&app, &path, &json!({}), &owner_session).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    // Members only see the organization's users and tags
+    let select = json!({ "organizationId": id });
+    let response = put(&app, "/api/v1/auth/organization", &select, &member_session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app
+        .get(&format!("{org}/users"), Some(&member_session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let members: Vec<serde_json::Value> = response.json();
+    let emails: Vec<&str> = members
+        .iter()
+        .map(|member| member["email"].as_str().unwrap())
+        .collect();
+    assert_eq!(emails, ["member@kasad.com", "owner@kasad.com"]);
+    let response = app.get(&format!("{org}/tags"), Some(&member_session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(response.json::<Vec<Tag>>().len(), 1);
+    let response = app
+        .post(
+            &format!("{org}/tags"),
+            &json!({ "name": "other" }),
+            Some(&member_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+    // Removed members lose the organization's tags and can't act within it anymore
+    let response = app
+        .delete(
+            &format!("{org}/users/{}", member.id()),
+            Some(&owner_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert!(
+        app.db()
+            .get_tags_by_user_id(member.id())
+            .await
+            .unwrap()
+            .is_empty()
+    );
+    let response = app.get(&org, Some(&member_session)).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+}
+
+/// Creates a group with the given name, returning its path.
+async fn create_group(app: &TestApp, name: &str, session: &TestSession) -> String {
+    let response = app
+        .post("/api/v1/groups", &json!({ "name": name }), Some(session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let id = response.json::<serde_json::Value>()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    format!("/api/v1/groups/{id}")
+}
+
+#[tokio::test]
+async fn test_groups() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let user_session = app.create_session(&user, false).await;
+
+    // Only administrators manage groups, and names are unique
+    let body = json!({ "name": "Staff" });
+    let response = app.post("/api/v1/groups", &body, Some(&user_session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let staff = create_group(&app, "Staff", &admin_session).await;
+    let response = app
+        .post("/api/v1/groups", &body, Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "group_exists");
+    let response = app
+        .patch(&staff, &json!({ "name": "" }), Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    // Groups can't contain themselves
+    let engineering = create_group(&app, "Engineering", &admin_session).await;
+    let engineering_id = engineering.rsplit('/').next().unwrap();
+    let staff_id = staff.rsplit('/').next().unwrap();
+    let response = put(
+        &app,
+        &format!("{staff}/groups/{engineering_id}"),
+        &json!(null),
+        &admin_session,
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    for path in [
+        format!("{engineering}/groups/{staff_id}"),
+        format!("{staff}/groups/{staff_id}"),
+    ] {
+        let response = put(&app, &path, &json!(null), &admin_session).await;
+        assert_eq!(response.status, StatusCode::CONFLICT);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["error"]["code"], "group_cycle");
+    }
+
+    // Members of subgroups are listed on request
+    let path = format!("{engineering}/users/{}", user.id());
+    let response = put(&app, &path, &json!(null), &admin_session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app
+        .get(&format!("{staff}/users"), Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.json::<Vec<serde_json::Value>>().is_empty());
+    let response = app
+        .get(
+            &format!("{staff}/users?includeSubgroups=true"),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.json::<Vec<serde_json::Value>>()[0]["email"],
+        "test@kasad.com"
+    );
+    let response = app
+        .get(
+            &format!("/api/v1/users/{}/groups", user.id()),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.json::<Vec<serde_json::Value>>()[0]["name"],
+        "Engineering"
+    );
+
+    // Deleting a group keeps its subgroups
+    let response = app.delete(&staff, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.get(&staff, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let response = app.get(&engineering, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_group_tags() {
+    let app = TestApp::new().await;
+    let (org_id, _) = create_organization(&app).await;
+    let admin = app.db().get_user_by_email("admin@kasad.com").await.unwrap();
+    let admin_session = app.create_session(&admin, true).await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let staff = create_group(&app, "Staff", &admin_session).await;
+    let engineering = create_group(&app, "Engineering", &admin_session).await;
+    let engineering_id = engineering.rsplit('/').next().unwrap();
+    let path = format!("{staff}/groups/{engineering_id}");
+    let response = put(&app, &path, &json!(null), &admin_session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let path = format!("{engineering}/users/{}", user.id());
+    let response = put(&app, &path, &json!(null), &admin_session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    // Organization tags can't be applied to groups
+    let response = app
+        .post(
+            &format!("/api/v1/orgs/{org_id}/tags"),
+            &json!({ "name": "staff" }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let org_tag_id = response.json::<serde_json::Value>()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let path = format!("{staff}/tags/{org_tag_id}");
+    let response = put(&app, &path, &json!(null), &admin_session).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    // The administrator tag applies to the members of subgroups
+    let upgrade = json!({ "target": "Admin" });
+    let session = app.create_session(&user, false).await;
+    let response = app
+        .post("/api/v1/auth/upgrade", &upgrade, Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let admin_tag = app.db().get_tag_by_name("iam::admin").await.unwrap();
+    let tag_path = format!("{staff}/tags/{}", admin_tag.id);
+    let response = put(&app, &tag_path, &json!(null), &admin_session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app
+        .get(&format!("{staff}/tags"), Some(&admin_session))
+        .await;
+    assert_eq!(
+        response.json::<Vec<serde_json::Value>>()[0]["name"],
+        "iam::admin"
+    );
+    let session = app.create_session(&user, false).await;
+    let response = app
+        .post("/api/v1/auth/upgrade", &upgrade, Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    // Losing the administrator tag logs the user out everywhere
+    let session = app.create_session(&user, false).await;
+    let response = app.delete(&tag_path, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.get("/api/v1/users/me", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+/// Creates a role with the given permissions and a tag to which it's assigned, and applies the
+/// tag to the given user. Returns the IDs of the role and the tag.
+async fn grant_role(
+    app: &TestApp,
+    user: &User,
+    name: &str,
+    permissions: &[&str],
+    admin_session: &TestSession,
+) -> (String, String) {
+    let response = app
+        .post(
+            "/api/v1/roles",
+            &json!({ "name": name, "permissions": permissions }),
+            Some(admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let role_id = response.json::<serde_json::Value>()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let response = app
+        .post(
+            "/api/v1/tags",
+            &json!({ "name": name }),
+            Some(admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let tag_id = response.json::<serde_json::Value>()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let path = format!("/api/v1/tags/{tag_id}/roles/{role_id}");
+    let response = put(app, &path, &json!(null), admin_session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let path = format!("/api/v1/users/{}/tags/{tag_id}", user.id());
+    let response = put(app, &path, &json!(null), admin_session).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    (role_id, tag_id)
+}
+
+/// Asserts that the response is a `403 Forbidden` for lack of the given permission.
+fn assert_missing_permission(response: &TestResponse, permission: &str) {
+    assert_eq!(
+        response.status,
+        StatusCode::FORBIDDEN,
+        "{}",
+        response.text()
+    );
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "missing_permission");
+    assert_eq!(body["error"]["details"]["permission"], permission);
+}
+
+#[tokio::test]
+async fn test_roles() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let (role_id, _) = grant_role(
+        &app,
+        &user,
+        "support",
+        &["tags:read", "users:read"],
+        &admin_session,
+    )
+    .await;
+    let body = json!({ "name": "support" });
+    let response = app.post("/api/v1/roles", &body, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+
+    // Users with any permission can start administrator sessions
+    let session = app.create_session(&user, false).await;
+    let response = app.get("/api/v1/auth/session", Some(&session)).await;
+    let info: serde_json::Value = response.json();
+    assert_eq!(info["permissions"], json!(["users:read", "tags:read"]));
+    let response = app
+        .post(
+            "/api/v1/auth/upgrade",
+            &json!({ "target": "Admin" }),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    // Administrator sessions can only use their user's permissions
+    let session = app.create_session(&user, true).await;
+    let response = app.get("/api/v1/users", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app
+        .post("/api/v1/tags", &json!({ "name": "new" }), Some(&session))
+        .await;
+    assert_missing_permission(&response, "tags:write");
+    let response = app.get("/api/v1/webhooks", Some(&session)).await;
+    assert_missing_permission(&response, "*");
+    let impersonate = json!({ "target": "User", "userId": admin.id() });
+    let response = app
+        .post("/api/v1/auth/upgrade", &impersonate, Some(&session))
+        .await;
+    assert_missing_permission(&response, "users:impersonate");
+
+    // Changes to roles apply immediately
+    let role = format!("/api/v1/roles/{role_id}");
+    let response = app
+        .patch(
+            &role,
+            &json!({ "permissions": ["users:read", "tags:read", "tags:write"] }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app
+        .post("/api/v1/tags", &json!({ "name": "new" }), Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.delete(&role, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.get("/api/v1/users", Some(&session)).await;
+    assert_missing_permission(&response, "users:read");
+}
+
+#[tokio::test]
+async fn test_permission_escalation() {
+    let app = TestApp::new().await;
+    let (org_id, _) = create_organization(&app).await;
+    let admin = app.db().get_user_by_email("admin@kasad.com").await.unwrap();
+    let admin_session = app.create_session(&admin, true).await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let permissions = ["roles:write", "tags:write", "users:write"];
+    let (role_id, tag_id) = grant_role(&app, &user, "manager", &permissions, &admin_session).await;
+    let session = app.create_session(&user, true).await;
+
+    // Users can't grant permissions which they don't have
+    let body = json!({ "name": "everything", "permissions": ["*"] });
+    let response = app.post("/api/v1/roles", &body, Some(&session)).await;
+    assert_missing_permission(&response, "*");
+    let body = json!({ "permissions": ["roles:write", "groups:write"] });
+    let response = app
+        .patch(&format!("/api/v1/roles/{role_id}"), &body, Some(&session))
+        .await;
+    assert_missing_permission(&response, "groups:write");
+    let admin_tag = app.db().get_tag_by_name("iam::admin").await.unwrap();
+    let path = format!("/api/v1/users/{}/tags/{}", user.id(), admin_tag.id);
+    let response = put(&app, &path, &json!(null), &session).await;
+    assert_missing_permission(&response, "*");
+
+    // ...nor manage users who have permissions which they don't have
+    let path = format!("/api/v1/users/{}", admin.id());
+    let response = app
+        .patch(&path, &json!({ "displayName": "Mallory" }), Some(&session))
+        .await;
+    assert_missing_permission(&response, "*");
+    let response = app.delete(&path, Some(&session)).await;
+    assert_missing_permission(&response, "*");
+
+    // Roles can't be assigned to organization tags
+    let response = app
+        .post(
+            &format!("/api/v1/orgs/{org_id}/tags"),
+            &json!({ "name": "staff" }),
+            Some(&admin_session),
+        )
+        .await;
+    let org_tag_id = response.json::<serde_json::Value>()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let path = format!("/api/v1/tags/{org_tag_id}/roles/{role_id}");
+    let response = put(&app, &path, &json!(null), &admin_session).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    let response = app
+        .get(
+            &format!("/api/v1/tags/{tag_id}/roles"),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(
+        response.json::<Vec<serde_json::Value>>()[0]["name"],
+        "manager"
+    );
+}
+
+#[tokio::test]
+async fn test_policies() {
+    let app = TestApp::new().await;
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+
+    // Invalid documents are reported, and rejected when creating policies
+    let document = json!({ "statements": [{ "effect": "permit", "actions": ["user:read"] }] });
+    let response = app
+        .post("/api/v1/policies/validate", &document, Some(&admin_session))
+        .await;
+    let validation: serde_json::Value = response.json();
+    assert_eq!(validation["valid"], false);
+    assert_eq!(
+        validation["errors"],
+        json!(["statement 0: `user:read` matches no action"])
+    );
+    let response = app
+        .post(
+            "/api/v1/policies/validate",
+            &json!({ "statements": [{ "effect": "allow", "actions": ["*"] }] }),
+            Some(&admin_session),
+        )
+        .await;
+    assert_eq!(response.json::<serde_json::Value>()["valid"], false);
+    let body = json!({ "name": "readers", "document": document });
+    let response = app
+        .post("/api/v1/policies", &body, Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json::<serde_json::Value>()["error"]["code"],
+        "invalid_policy"
+    );
+
+    // Documents are versioned, and earlier versions can be restored
+    let document = json!({ "statements": [{ "effect": "permit", "actions": ["users:read"] }] });
+    let body = json!({ "name": "readers", "document": document });
+    let response = app
+        .post("/api/v1/policies", &body, Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let policy: serde_json::Value = response.json();
+    assert_eq!(policy["version"], 1);
+    assert_eq!(policy["enabled"], true);
+    let response = app
+        .post("/api/v1/policies", &body, Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    let path = format!("/api/v1/policies/{}", policy["id"].as_str().unwrap());
+    let body = json!({ "document": { "statements": [
+        { "effect": "permit", "actions": ["users:*", "tags:read"] },
+    ] } });
+    let response = app.patch(&path, &body, Some(&admin_session)).await;
+    assert_eq!(response.json::<serde_json::Value>()["version"], 2);
+    let response = app
+        .patch(&path, &json!({ "enabled": false }), Some(&admin_session))
+        .await;
+    assert_eq!(response.json::<serde_json::Value>()["version"], 2);
+    let response = app
+        .get(&format!("{path}/versions"), Some(&admin_session))
+        .await;
+    let versions: Vec<serde_json::Value> = response.json();
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0]["version"], 2);
+    assert_eq!(versions[1]["document"], document);
+    let response = app
+        .post(
+            &format!("{path}/versions/1/restore"),
+            &json!(null),
+            Some(&admin_session),
+        )
+        .await;
+    let policy: serde_json::Value = response.json();
+    assert_eq!(policy["version"], 3);
+    assert_eq!(policy["document"], document);
+    let response = app
+        .get(&format!("{path}/versions/4"), Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    let response = app.delete(&path, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app
+        .get(&format!("{path}/versions"), Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_policy_evaluation() {
+    let app = TestApp::new().await;
+    let redirect_uri = "https://app.example.com/cb";
+    let (client_id, _) = create_oauth_client(&app, redirect_uri).await;
+    let admin = app.db().get_user_by_email("admin@kasad.com").await.unwrap();
+    let admin_session = app.create_session(&admin, true).await;
+    let user = app.create_user("test@kasad.com", "Test User").await;
+    let permissions = ["users:read", "users:write", "users:impersonate"];
+    let (_, tag_id) = grant_role(&app, &user, "support", &permissions, &admin_session).await;
+    let session = app.create_session(&user, true).await;
+
+    // Policies permit and forbid permissions on top of roles, but not to administrators
+    let body = json!({ "name": "support", "document": { "statements": [
+        { "effect": "permit", "actions": ["tags:read"], "tags": [tag_id] },
+        { "effect": "forbid", "actions": ["users:write"] },
+    ] } });
+    let response = app
+        .post("/api/v1/policies", &body, Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.get("/api/v1/tags", Some(&session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let path = format!("/api/v1/users/{}", user.id());
+    let body = json!({ "displayName": "Renamed" });
+    let response = app.patch(&path, &body, Some(&session)).await;
+    assert_missing_permission(&response, "users:write");
+    let response = app.patch(&path, &body, Some(&admin_session)).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app.get("/api/v1/auth/session", Some(&session)).await;
+    assert_eq!(
+        response.json::<serde_json::Value>()["permissions"],
+        json!(["users:read", "users:impersonate", "tags:read"])
+    );
+
+    // Forbidden users are sent back to the client with an error
+    let verifier = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    let challenge = BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(verifier));
+    let body = json!({ "name": "no-app", "document": { "statements": [
+        { "effect": "forbid", "actions": ["oauth:authorize"], "users": [user.id()], "clients": [client_id] },
+    ] } });
+    let response = app
+        .post("/api/v1/policies", &body, Some(&admin_session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response =
+        oauth_authorize(&app, Some(&session), &client_id, redirect_uri, &challenge).await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    let location = Url::parse(response.headers[LOCATION].to_str().unwrap()).unwrap();
+    assert!(
+        location
+            .query_pairs()
+            .any(|(key, value)| key == "error" && value == "access_denied")
+    );
+    authorization_code(
+        &oauth_authorize(
+            &app,
+            Some(&admin_session),
+            &client_id,
+            redirect_uri,
+            &challenge,
+        )
+        .await,
+    );
+}
+
+/// Loads a sample Mailgun webhook request and signs it with the given key at the given time.
+fn mailgun_webhook(name: &str, key: &str, timestamp: i64) -> serde_json::Value {
+    use hmac::{Hmac, Mac};
+
+    let path = format!(
+        "{}/tests/fixtures/mailgun/{name}",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let mut webhook: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    let timestamp = timestamp.to_string();
+    let token = webhook["signature"]["token"].as_str().unwrap().to_string();
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(key.as_bytes()).unwrap();
+    mac.update(format!("{timestamp}{token}").as_bytes());
+    webhook["signature"]["timestamp"] = timestamp.into();
+    webhook["signature"]["signature"] = hex::encode(mac.finalize().into_bytes()).into();
+    webhook
+}
+
+#[tokio::test]
+async fn test_mailgun_bounces() {
+    const KEY: &str = "key-signing";
+    let app = TestApp::with_options(ApiOptions {
+        email_webhook_secret: Some(KEY.to_string()),
+        ..ApiOptions::default()
+    })
+    .await;
+    let now = chrono::Utc::now().timestamp();
+    let suppression = async || app.db().get_email_suppression("alice@example.com").await;
+
+    // Requests must be signed with the key, recently
+    let response = app
+        .post(
+            "/api/v1/email/bounces",
+            &mailgun_webhook("permanent_failure.json", "wrong", now),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = app
+        .post(
+            "/api/v1/email/bounces",
+            &mailgun_webhook("permanent_failure.json", KEY, now - 60 * 60),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let mut tampered = mailgun_webhook("temporary_failure.json", KEY, now);
+    tampered["signature"]["token"] = "other".into();
+    let response = app.post("/api/v1/email/bounces", &tampered, None).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    // Temporary failures are acknowledged, but don't suppress the address
+    let response = app
+        .post(
+            "/api/v1/email/bounces",
+            &mailgun_webhook("temporary_failure.json", KEY, now),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert!(suppression().await.is_err());
+
+    let response = app
+        .post(
+            "/api/v1/email/bounces",
+            &mailgun_webhook("permanent_failure.json", KEY, now),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(
+        suppression().await.unwrap().reason,
+        "suppress-bounce: Not delivering to previously bounced address"
+    );
+
+    app.db()
+        .delete_email_suppression("alice@example.com")
+        .await
+        .unwrap();
+    let response = app
+        .post(
+            "/api/v1/email/bounces",
+            &mailgun_webhook("complained.json", KEY, now),
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(suppression().await.unwrap().reason, "complaint");
+}
+
+#[tokio::test]
+async fn test_bounce_webhook_disabled() {
+    let app = TestApp::new().await;
+    let webhook = mailgun_webhook("permanent_failure.json", "", chrono::Utc::now().timestamp());
+    let response = app.post("/api/v1/email/bounces", &webhook, None).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
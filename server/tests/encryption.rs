@@ -0,0 +1,54 @@
+//! # Stored data encryption tests
+//!
+//! Installing a keyring affects the whole process, so these tests live in their own binary.
+
+use std::sync::Arc;
+
+use axum::http::{Method, StatusCode};
+use iam_server::{
+    crypto::{KEY_LEN, Keyring, set_keyring},
+    models::User,
+    test_utils::{TEST_ORIGIN, TestApp},
+};
+use serde_json::json;
+use webauthn_authenticator_rs::{WebauthnAuthenticator, softpasskey::SoftPasskey};
+use webauthn_rs::prelude::CreationChallengeResponse;
+
+#[tokio::test]
+async fn test_passkeys_are_encrypted() {
+    set_keyring(Some(Arc::new(Keyring::new("old", [1; KEY_LEN]))));
+    let app = TestApp::new().await;
+
+    // Register a user, whose passkey is stored encrypted with the old key
+    let user = json!({ "email": "test@kasad.com", "displayName": "Test User" });
+    let response = app.post("/api/v1/register/start", &user, None).await;
+    let challenge: CreationChallengeResponse = response.json();
+    let credential = WebauthnAuthenticator::new(SoftPasskey::new(true))
+        .do_registration(TEST_ORIGIN.parse().unwrap(), challenge)
+        .expect("authenticator failed to register");
+    let cookie = format!(
+        "registration_id={}",
+        response.cookie("registration_id").unwrap()
+    );
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/register/finish",
+            Some(&json!({ "passkey": credential })),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let user: User = response.json();
+
+    // Without the old key, the passkey can't be read
+    set_keyring(Some(Arc::new(Keyring::new("new", [2; KEY_LEN]))));
+    assert!(app.db().get_passkeys_by_user_id(user.id()).await.is_err());
+
+    // After rotating to a new key, passkeys encrypted with the old one are still readable
+    set_keyring(Some(Arc::new(
+        Keyring::new("new", [2; KEY_LEN]).with_key("old", [1; KEY_LEN]),
+    )));
+    let passkeys = app.db().get_passkeys_by_user_id(user.id()).await.unwrap();
+    assert_eq!(passkeys.len(), 1);
+}
@@ -0,0 +1,56 @@
+#![expect(clippy::doc_markdown)]
+
+//! # OpenAPI drift detection
+//!
+//! Compares the generated API specifications with the snapshots committed in the `openapi/`
+//! directory, so that changes to the API don't go unnoticed. If a change is intentional, update
+//! the snapshots by running the tests with the `BLESS_OPENAPI` environment variable set:
+//!
+//! ```sh
+//! BLESS_OPENAPI=1 cargo test --test openapi
+//! ```
+
+use std::path::PathBuf;
+
+use iam_server::api::generate_api_specs;
+use similar::TextDiff;
+
+/// Environment variable which, when set, makes the test overwrite the snapshots
+const BLESS_VAR: &str = "BLESS_OPENAPI";
+
+#[tokio::test]
+async fn test_openapi_snapshot() {
+    aide::generate::on_error(|err| panic!("failed to generate OpenAPI spec: {err}"));
+    let specs = generate_api_specs().await;
+    let snapshots = [("v1", specs.v1)];
+
+    let mut failures = Vec::new();
+    for (version, spec) in snapshots {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("openapi")
+            .join(format!("{version}.json"));
+        let generated = serde_json::to_string_pretty(&spec).unwrap() + "\n";
+
+        if std::env::var_os(BLESS_VAR).is_some() {
+            std::fs::write(&path, &generated).unwrap();
+            continue;
+        }
+
+        let committed = std::fs::read_to_string(&path).unwrap_or_default();
+        if committed != generated {
+            let diff = TextDiff::from_lines(&committed, &generated)
+                .unified_diff()
+                .context_radius(3)
+                .header(&format!("{} (committed)", path.display()), "generated")
+                .to_string();
+            failures.push(diff);
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "OpenAPI spec differs from the committed snapshot. If the change is intentional, re-run \
+        with {BLESS_VAR}=1 to update the snapshot.\n\n{}",
+        failures.join("\n"),
+    );
+}
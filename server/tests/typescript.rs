@@ -0,0 +1,42 @@
+//! # TypeScript type drift detection
+//!
+//! Compares the generated TypeScript declarations for the API models with the copy used by the
+//! bundled UI, so that the UI's types can't silently fall out of sync with the server's. If a
+//! change is intentional, update the UI's copy by running the tests with the `BLESS_TYPESCRIPT`
+//! environment variable set:
+//!
+//! ```sh
+//! BLESS_TYPESCRIPT=1 cargo test --test typescript
+//! ```
+
+use std::path::PathBuf;
+
+use iam_server::api::generate_typescript_types;
+use similar::TextDiff;
+
+/// Environment variable which, when set, makes the test overwrite the UI's copy
+const BLESS_VAR: &str = "BLESS_TYPESCRIPT";
+
+#[test]
+fn test_typescript_snapshot() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../ui/src/lib/generated/models.ts");
+    let generated = generate_typescript_types();
+
+    if std::env::var_os(BLESS_VAR).is_some() {
+        std::fs::write(&path, &generated).unwrap();
+        return;
+    }
+
+    let committed = std::fs::read_to_string(&path).unwrap_or_default();
+    if committed != generated {
+        let diff = TextDiff::from_lines(&committed, &generated)
+            .unified_diff()
+            .context_radius(3)
+            .header(&format!("{} (committed)", path.display()), "generated")
+            .to_string();
+        panic!(
+            "TypeScript types differ from the UI's copy. If the change is intentional, re-run \
+            with {BLESS_VAR}=1 to update it.\n\n{diff}"
+        );
+    }
+}
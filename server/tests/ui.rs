@@ -0,0 +1,132 @@
+//! # UI server integration tests
+
+use std::path::PathBuf;
+
+use axum::{
+    Router,
+    body::Body,
+    http::{
+        HeaderMap, Request, StatusCode,
+        header::{CACHE_CONTROL, CONTENT_SECURITY_POLICY},
+    },
+};
+use iam_server::{
+    test_utils::test_config,
+    ui::{UiOptions, new_ui_server},
+};
+use tower::ServiceExt;
+
+const INDEX_HTML: &str = r#"<!doctype html>
+<html>
+<head><meta property="csp-nonce" nonce="%iam.nonce%"><script>start()</script></head>
+<body></body>
+</html>"#;
+
+/// Static directory which is removed when dropped
+struct StaticDir(PathBuf);
+
+impl StaticDir {
+    fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!("iam-ui-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("_app/immutable")).unwrap();
+        std::fs::write(dir.join("index.html"), INDEX_HTML).unwrap();
+        std::fs::write(dir.join("favicon.svg"), "<svg></svg>").unwrap();
+        std::fs::write(dir.join("_app/immutable/entry.js"), "export {}").unwrap();
+        Self(dir)
+    }
+
+    fn router(&self) -> Router {
+        new_ui_server(&self.0, &test_config(), &UiOptions::default())
+    }
+}
+
+impl Drop for StaticDir {
+    fn drop(&mut self) {
+        _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+async fn get(router: &Router, path: &str) -> (StatusCode, HeaderMap, String) {
+    let request = Request::get(path).body(Body::empty()).unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, headers, String::from_utf8(body.to_vec()).unwrap())
+}
+
+/// Returns the nonce which the given `Content-Security-Policy` allows scripts with.
+fn policy_nonce(headers: &HeaderMap) -> String {
+    let policy = headers[CONTENT_SECURITY_POLICY].to_str().unwrap();
+    let script_src = policy
+        .split(';')
+        .map(str::trim)
+        .find_map(|directive| directive.strip_prefix("script-src "))
+        .expect("policy has no script-src");
+    script_src
+        .split(' ')
+        .find_map(|source| source.strip_prefix("'nonce-")?.strip_suffix('\''))
+        .expect("script-src has no nonce")
+        .to_string()
+}
+
+#[tokio::test]
+async fn test_index_nonce() {
+    let dir = StaticDir::new();
+    let router = dir.router();
+
+    let (status, headers, html) = get(&router, "/index.html").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[CACHE_CONTROL], "no-store");
+    let nonce = policy_nonce(&headers);
+    assert!(!html.contains("%iam.nonce%"));
+    assert!(html.contains(&format!(r#"<meta property="csp-nonce" nonce="{nonce}">"#)));
+    // Inline scripts, including the injected configuration, carry the nonce
+    assert!(html.contains(&format!(r#"<script nonce="{nonce}">start()</script>"#)));
+    assert!(html.contains(&format!(r#"type="application/json" nonce="{nonce}">"#)));
+
+    // Every response gets a fresh nonce
+    let (_, headers, _) = get(&router, "/index.html").await;
+    assert_ne!(policy_nonce(&headers), nonce);
+}
+
+#[tokio::test]
+async fn test_spa_fallback() {
+    let dir = StaticDir::new();
+    let router = dir.router();
+
+    // Paths without a file are routed by the UI, so they get the index page
+    let (status, headers, html) = get(&router, "/admin/users").await;
+    assert_eq!(status, StatusCode::OK);
+    let nonce = policy_nonce(&headers);
+    assert!(html.contains(&format!(r#"<script nonce="{nonce}">start()</script>"#)));
+}
+
+#[tokio::test]
+async fn test_asset_cache_headers() {
+    let dir = StaticDir::new();
+    let router = dir.router();
+
+    // Content-hashed assets can be cached forever, but other files must be revalidated
+    let (status, headers, body) = get(&router, "/_app/immutable/entry.js").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "export {}");
+    assert_eq!(
+        headers[CACHE_CONTROL],
+        "public, max-age=31536000, immutable"
+    );
+    assert!(!headers.contains_key(CONTENT_SECURITY_POLICY));
+
+    let (status, headers, _) = get(&router, "/favicon.svg").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[CACHE_CONTROL], "no-cache");
+
+    // Missing immutable assets aren't cached
+    let (_, headers, _) = get(&router, "/_app/immutable/missing.js").await;
+    assert_ne!(
+        headers[CACHE_CONTROL],
+        "public, max-age=31536000, immutable"
+    );
+}
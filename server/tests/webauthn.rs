@@ -0,0 +1,1048 @@
+//! # End-to-end `WebAuthn` tests
+//!
+//! Runs the registration and authentication ceremonies against the API using a software
+//! authenticator, so the full start → finish flows are exercised rather than only their storage.
+
+use axum::{
+    body::Body,
+    http::{
+        Method, Request, StatusCode,
+        header::{CONTENT_TYPE, COOKIE, HOST, SET_COOKIE, USER_AGENT},
+    },
+};
+use chrono::{DateTime, Utc};
+use iam_server::{
+    api::{ApiOptions, EnumerationProtection},
+    lockout::LockoutOptions,
+    models::{AppConfig, SessionLifetimes, SessionUpdate, User, UserStatus},
+    relying_party::{AttestationPolicy, PasskeyPolicy, RelyingParties, RelyingPartyConfig},
+    test_utils::{TEST_ORIGIN, TestApp, TestResponse, test_config, test_webauthn},
+};
+use serde_json::json;
+use webauthn_authenticator_rs::{WebauthnAuthenticator, softpasskey::SoftPasskey};
+use webauthn_rs::prelude::{
+    CreationChallengeResponse, PublicKeyCredential, RequestChallengeResponse, Url,
+};
+use webauthn_rs_proto::{
+    AttestationConveyancePreference, AuthenticatorAttachment, PublicKeyCredentialHints,
+    ResidentKeyRequirement, UserVerificationPolicy,
+};
+
+const EMAIL: &str = "test@kasad.com";
+
+fn authenticator() -> WebauthnAuthenticator<SoftPasskey> {
+    WebauthnAuthenticator::new(SoftPasskey::new(true))
+}
+
+fn origin() -> Url {
+    TEST_ORIGIN.parse().unwrap()
+}
+
+/// Returns a `Cookie` header value containing the given cookie set by the response.
+fn cookie_from(response: &TestResponse, name: &str) -> String {
+    let value = response
+        .cookie(name)
+        .unwrap_or_else(|| panic!("response did not set the `{name}` cookie"));
+    format!("{name}={value}")
+}
+
+/// Registers a new user with the given authenticator and returns the response to the finish
+/// request.
+async fn register(
+    app: &TestApp,
+    authenticator: &mut WebauthnAuthenticator<SoftPasskey>,
+) -> TestResponse {
+    let user = json!({ "email": EMAIL, "displayName": "Test User" });
+    let response = app.post("/api/v1/register/start", &user, None).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let challenge: CreationChallengeResponse = response.json();
+    let credential = authenticator
+        .do_registration(origin(), challenge)
+        .expect("authenticator failed to register");
+    app.send_with_cookie(
+        Method::POST,
+        "/api/v1/register/finish",
+        Some(&json!({ "passkey": credential })),
+        Some(&cookie_from(&response, "registration_id")),
+    )
+    .await
+}
+
+/// Starts an authentication ceremony and returns the challenge along with the `Cookie` header
+/// value which identifies it.
+async fn start_authentication(app: &TestApp) -> (RequestChallengeResponse, String) {
+    let response = start_login(app, EMAIL).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    (response.json(), cookie_from(&response, "authentication_id"))
+}
+
+/// Finishes the authentication ceremony identified by the given `Cookie` header value with the
+/// given credential and returns the response.
+async fn finish_login(
+    app: &TestApp,
+    credential: &PublicKeyCredential,
+    cookie: &str,
+) -> TestResponse {
+    app.send_with_cookie(
+        Method::POST,
+        "/api/v1/auth/finish",
+        Some(credential),
+        Some(cookie),
+    )
+    .await
+}
+
+/// Starts an authentication ceremony for the given email address and returns the response.
+async fn start_login(app: &TestApp, email: &str) -> TestResponse {
+    app.post("/api/v1/auth/start", &json!({ "email": email }), None)
+        .await
+}
+
+#[tokio::test]
+async fn test_register_and_authenticate() {
+    let app = TestApp::new().await;
+    let mut authenticator = authenticator();
+
+    // Registration creates the user and their passkey, and logs them in
+    let response = register(&app, &mut authenticator).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let user: User = response.json();
+    assert_eq!(user.email(), EMAIL);
+    let passkeys = app.db().get_passkeys_by_user_id(user.id()).await.unwrap();
+    assert_eq!(passkeys.len(), 1);
+    let session = cookie_from(&response, "session_id");
+    let response = app
+        .send_with_cookie::<()>(Method::GET, "/api/v1/users/me", None, Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    // Authentication with the same authenticator logs the user in again
+    let (challenge, cookie) = start_authentication(&app).await;
+    let credential = authenticator
+        .do_authentication(origin(), challenge)
+        .expect("authenticator failed to authenticate");
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/auth/finish",
+            Some(&credential),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(response.json::<User>().id(), user.id());
+    let user = app.db().get_user_by_id(user.id()).await.unwrap();
+    assert!(user.last_login_at().is_some());
+    let session = cookie_from(&response, "session_id");
+    let response = app
+        .send_with_cookie::<()>(Method::GET, "/api/v1/auth/session", None, Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_session_lifetime() {
+    let config = AppConfig {
+        session_lifetimes: SessionLifetimes {
+            session_secs: 600,
+            admin_session_secs: 60,
+        },
+        ..test_config()
+    };
+    let app = TestApp::with_config(&config, ApiOptions::default()).await;
+    let response = app.get("/api/v1/config", None).await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(
+        body["sessionLifetimes"],
+        json!({ "sessionSecs": 600, "adminSessionSecs": 60 })
+    );
+
+    // Both the session and its cookie last as long as configured
+    let response = register(&app, &mut authenticator()).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let set_cookie = response
+        .headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find(|value| value.starts_with("session_id="))
+        .expect("response did not set the session cookie");
+    assert!(set_cookie.contains("Max-Age=600"), "{set_cookie}");
+    let response = app
+        .send_with_cookie::<()>(
+            Method::GET,
+            "/api/v1/auth/session",
+            None,
+            Some(&cookie_from(&response, "session_id")),
+        )
+        .await;
+    let body: serde_json::Value = response.json();
+    let time = |field: &str| {
+        serde_json::from_value::<DateTime<Utc>>(body["session"][field].clone()).unwrap()
+    };
+    assert_eq!((time("expiresAt") - time("createdAt")).num_seconds(), 600);
+}
+
+#[tokio::test]
+async fn test_authenticate_with_wrong_challenge() {
+    let app = TestApp::new().await;
+    let mut authenticator = authenticator();
+    let response = register(&app, &mut authenticator).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    // Sign the first challenge, but submit the response for the second ceremony
+    let (challenge, _) = start_authentication(&app).await;
+    let (_, cookie) = start_authentication(&app).await;
+    let credential = authenticator
+        .do_authentication(origin(), challenge)
+        .expect("authenticator failed to authenticate");
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/auth/finish",
+            Some(&credential),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert!(response.cookie("session_id").is_none());
+}
+
+#[tokio::test]
+async fn test_retried_registration_excludes_existing_passkeys() {
+    let app = TestApp::new().await;
+    let user = json!({ "email": EMAIL, "displayName": "Test User" });
+    let excluded = |response: &TestResponse| {
+        assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+        let challenge: CreationChallengeResponse = response.json();
+        challenge.public_key.exclude_credentials.unwrap_or_default()
+    };
+
+    // Nothing is excluded for new addresses
+    let response = app.post("/api/v1/register/start", &user, None).await;
+    assert!(excluded(&response).is_empty());
+
+    // Retrying a registration which succeeded excludes the passkey it registered, so that the
+    // authenticator doesn't create another
+    let response = register(&app, &mut authenticator()).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let session = cookie_from(&response, "session_id");
+    let registered: User = response.json();
+    let passkeys = app
+        .db()
+        .get_passkeys_by_user_id(registered.id())
+        .await
+        .unwrap();
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/register/start",
+            Some(&user),
+            Some(&session),
+        )
+        .await;
+    let excluded_by_owner = excluded(&response);
+    assert_eq!(excluded_by_owner.len(), 1);
+    assert_eq!(&excluded_by_owner[0].id, passkeys[0].passkey.0.cred_id());
+
+    // Nobody else learns the account's credentials
+    let response = app.post("/api/v1/register/start", &user, None).await;
+    assert!(excluded(&response).is_empty());
+    let other = json!({ "email": "other@kasad.com", "displayName": "Other User" });
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/register/start",
+            Some(&other),
+            Some(&session),
+        )
+        .await;
+    assert!(excluded(&response).is_empty());
+}
+
+#[tokio::test]
+async fn test_register_without_ceremony_cookie() {
+    let app = TestApp::new().await;
+    let user = json!({ "email": EMAIL, "displayName": "Test User" });
+    let response = app.post("/api/v1/register/start", &user, None).await;
+    let challenge: CreationChallengeResponse = response.json();
+    let credential = authenticator()
+        .do_registration(origin(), challenge)
+        .expect("authenticator failed to register");
+    let response = app
+        .post(
+            "/api/v1/register/finish",
+            &json!({ "passkey": credential }),
+            None,
+        )
+        .await;
+    assert!(response.status.is_client_error(), "{}", response.status);
+    assert!(app.db().get_user_by_email(EMAIL).await.is_err());
+}
+
+#[tokio::test]
+async fn test_register_with_started_details() {
+    let app = TestApp::new().await;
+    let user = json!({ "email": EMAIL, "displayName": "Test User" });
+    let response = app.post("/api/v1/register/start", &user, None).await;
+    let credential = authenticator()
+        .do_registration(origin(), response.json())
+        .expect("authenticator failed to register");
+
+    // Details sent when finishing can't replace the ones which were checked at the start
+    let other = json!({ "email": "not an email", "displayName": " " });
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/register/finish",
+            Some(&json!({ "user": other, "passkey": credential })),
+            Some(&cookie_from(&response, "registration_id")),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let user: User = response.json();
+    assert_eq!(user.email(), EMAIL);
+    assert_eq!(user.display_name(), "Test User");
+}
+
+#[tokio::test]
+async fn test_replay_finished_ceremonies() {
+    let app = TestApp::new().await;
+    let mut authenticator = authenticator();
+
+    // Replaying a successful registration doesn't register again
+    let user = json!({ "email": EMAIL, "displayName": "Test User" });
+    let response = app.post("/api/v1/register/start", &user, None).await;
+    let cookie = cookie_from(&response, "registration_id");
+    let credential = authenticator
+        .do_registration(origin(), response.json())
+        .expect("authenticator failed to register");
+    let body = json!({ "passkey": credential });
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/register/finish",
+            Some(&body),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/register/finish",
+            Some(&body),
+            Some(&cookie),
+        )
+        .await;
+    assert!(response.status.is_client_error(), "{}", response.status);
+    assert!(response.cookie("session_id").is_none());
+
+    // Replaying a successful authentication doesn't log in again
+    let (challenge, cookie) = start_authentication(&app).await;
+    let credential = authenticator
+        .do_authentication(origin(), challenge)
+        .expect("authenticator failed to authenticate");
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/auth/finish",
+            Some(&credential),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/auth/finish",
+            Some(&credential),
+            Some(&cookie),
+        )
+        .await;
+    assert!(response.status.is_client_error(), "{}", response.status);
+    assert!(response.cookie("session_id").is_none());
+}
+
+#[tokio::test]
+async fn test_ceremonies_span_instances() {
+    // Two instances sharing a database, as when running replicas behind a load balancer
+    let first = TestApp::new().await;
+    let second = TestApp::with_db(std::sync::Arc::clone(first.db()), ApiOptions::default());
+    let mut authenticator = authenticator();
+
+    // Registration started on one instance can be finished on the other
+    let user = json!({ "email": EMAIL, "displayName": "Test User" });
+    let response = first.post("/api/v1/register/start", &user, None).await;
+    let cookie = cookie_from(&response, "registration_id");
+    let credential = authenticator
+        .do_registration(origin(), response.json())
+        .expect("authenticator failed to register");
+    let response = second
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/register/finish",
+            Some(&json!({ "passkey": credential })),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    // Likewise for authentication
+    let (challenge, cookie) = start_authentication(&second).await;
+    let credential = authenticator
+        .do_authentication(origin(), challenge)
+        .expect("authenticator failed to authenticate");
+    let response = first
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/auth/finish",
+            Some(&credential),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+}
+
+#[tokio::test]
+async fn test_add_passkey_while_logged_in() {
+    let app = TestApp::new().await;
+    let response = register(&app, &mut authenticator()).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let user: User = response.json();
+    let session = cookie_from(&response, "session_id");
+
+    // Adding a passkey requires a session
+    let response = app
+        .post("/api/v1/users/me/passkeys/register/start", &json!({}), None)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let response = app
+        .send_with_cookie::<()>(
+            Method::POST,
+            "/api/v1/users/me/passkeys/register/start",
+            None,
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let challenge: CreationChallengeResponse = response.json();
+    let excluded = challenge.public_key.exclude_credentials.as_ref().unwrap();
+    assert_eq!(excluded.len(), 1);
+    let cookie = format!("{session}; {}", cookie_from(&response, "registration_id"));
+    let credential = authenticator()
+        .do_registration(origin(), challenge)
+        .expect("authenticator failed to register");
+    let body = json!({ "passkey": credential, "displayName": "Backup key" });
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/users/me/passkeys/register/finish",
+            Some(&body),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let passkeys = app.db().get_passkeys_by_user_id(user.id()).await.unwrap();
+    assert_eq!(passkeys.len(), 2);
+    assert!(
+        passkeys
+            .iter()
+            .any(|passkey| passkey.display_name.as_deref() == Some("Backup key"))
+    );
+
+    // A ceremony started to create a new account can't add a passkey to an existing one
+    let new_user = json!({ "email": "other@kasad.com", "displayName": "Other User" });
+    let response = app.post("/api/v1/register/start", &new_user, None).await;
+    let cookie = format!("{session}; {}", cookie_from(&response, "registration_id"));
+    let credential = authenticator()
+        .do_registration(origin(), response.json())
+        .expect("authenticator failed to register");
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/users/me/passkeys/register/finish",
+            Some(&json!({ "passkey": credential })),
+            Some(&cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    let passkeys = app.db().get_passkeys_by_user_id(user.id()).await.unwrap();
+    assert_eq!(passkeys.len(), 2);
+}
+
+#[tokio::test]
+async fn test_default_passkey_name() {
+    let app = TestApp::new().await;
+    let user = json!({ "email": EMAIL, "displayName": "Test User" });
+    let response = app.post("/api/v1/register/start", &user, None).await;
+    let credential = authenticator()
+        .do_registration(origin(), response.json())
+        .expect("authenticator failed to register");
+
+    // The software authenticator doesn't report an AAGUID, so the passkey is named after the
+    // browser and platform
+    let body = json!({ "passkey": credential });
+    let request = Request::post("/api/v1/register/finish")
+        .header(CONTENT_TYPE, "application/json")
+        .header(COOKIE, cookie_from(&response, "registration_id"))
+        .header(
+            USER_AGENT,
+            "Mozilla/5.0 (X11; Linux x86_64; rv:143.0) Gecko/20100101 Firefox/143.0",
+        )
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = app.request(request).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let user: User = response.json();
+    let passkeys = app.db().get_passkeys_by_user_id(user.id()).await.unwrap();
+    assert_eq!(
+        passkeys[0].display_name.as_deref(),
+        Some("Firefox on Linux")
+    );
+
+    // Without a recognizable client, the passkey is left unnamed
+    let other = TestApp::new().await;
+    let response = register(&other, &mut authenticator()).await;
+    let user: User = response.json();
+    let passkeys = other.db().get_passkeys_by_user_id(user.id()).await.unwrap();
+    assert_eq!(passkeys[0].display_name, None);
+}
+
+#[tokio::test]
+async fn test_reauthenticate_before_sensitive_action() {
+    let app = TestApp::new().await;
+    let mut authenticator = authenticator();
+    let response = register(&app, &mut authenticator).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let user: User = response.json();
+    app.make_admin(&user).await;
+    let session = cookie_from(&response, "session_id");
+    let id_hash = app
+        .session_cookies()
+        .verify(&response.cookie("session_id").unwrap())
+        .unwrap();
+    let authenticated_at = Utc::now() - chrono::Duration::hours(1);
+    app.db()
+        .update_session(
+            &id_hash,
+            &SessionUpdate::new().with_authenticated_at(authenticated_at),
+        )
+        .await
+        .unwrap();
+
+    // Upgrading requires a recent authentication
+    let upgrade = json!({ "target": "Admin" });
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/auth/upgrade",
+            Some(&upgrade),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.json::<serde_json::Value>()["error"]["code"],
+        "reauthentication_required"
+    );
+
+    let response = app
+        .send_with_cookie::<()>(
+            Method::POST,
+            "/api/v1/auth/reauth/start",
+            None,
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let ceremony = cookie_from(&response, "authentication_id");
+    let credential = authenticator
+        .do_authentication(origin(), response.json())
+        .expect("authenticator failed to authenticate");
+
+    // The ceremony can't be finished with another session
+    let other = app.create_session(&user, false).await;
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/auth/reauth/finish",
+            Some(&credential),
+            Some(&format!("{}; {ceremony}", other.cookie)),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/auth/reauth/finish",
+            Some(&credential),
+            Some(&format!("{session}; {ceremony}")),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let body: serde_json::Value = response.json();
+    let reauthenticated_at: DateTime<Utc> =
+        serde_json::from_value(body["authenticatedAt"].clone()).unwrap();
+    assert!(reauthenticated_at > authenticated_at);
+
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/auth/upgrade",
+            Some(&upgrade),
+            Some(&session),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+}
+
+#[tokio::test]
+async fn test_enumeration_protection() {
+    let error_code =
+        |response: &TestResponse| response.json::<serde_json::Value>()["error"]["code"].clone();
+
+    // Without protection, unknown and taken addresses are reported as such
+    let app = TestApp::new().await;
+    let response = start_login(&app, "nobody@kasad.com").await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert_eq!(error_code(&response), "user_not_found");
+    register(&app, &mut authenticator()).await;
+    let response = register(&app, &mut authenticator()).await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    assert_eq!(error_code(&response), "email_taken");
+
+    let app = TestApp::with_options(ApiOptions {
+        enumeration_protection: Some(EnumerationProtection {
+            secret: b"secret".as_slice().into(),
+        }),
+        ..ApiOptions::default()
+    })
+    .await;
+    let mut owner = authenticator();
+    let response = register(&app, &mut owner).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    // Unknown addresses get a challenge for a decoy credential, which is the same every time
+    let decoy_id = |response: &TestResponse| {
+        assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+        let challenge: RequestChallengeResponse = response.json();
+        assert_eq!(challenge.public_key.allow_credentials.len(), 1);
+        challenge.public_key.allow_credentials[0].id.clone()
+    };
+    let response = start_login(&app, "nobody@kasad.com").await;
+    let decoy_cookie = cookie_from(&response, "authentication_id");
+    let first = decoy_id(&response);
+    assert_eq!(
+        decoy_id(&start_login(&app, "nobody@kasad.com").await),
+        first
+    );
+    assert_ne!(
+        decoy_id(&start_login(&app, "someone@kasad.com").await),
+        first
+    );
+
+    // Registrations with unknown and taken addresses exclude nothing
+    for email in ["nobody@kasad.com", EMAIL] {
+        let user = json!({ "email": email, "displayName": "Nobody" });
+        let response = app.post("/api/v1/register/start", &user, None).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+        let challenge: CreationChallengeResponse = response.json();
+        assert!(
+            challenge
+                .public_key
+                .exclude_credentials
+                .unwrap_or_default()
+                .is_empty()
+        );
+    }
+
+    // A decoy login fails in the same way as a login with another account's passkey
+    let (challenge, _) = start_authentication(&app).await;
+    let credential = owner
+        .do_authentication(origin(), challenge)
+        .expect("authenticator failed to authenticate");
+    let response = app
+        .send_with_cookie(
+            Method::POST,
+            "/api/v1/auth/finish",
+            Some(&credential),
+            Some(&decoy_cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(error_code(&response), "authentication_failed");
+
+    // Registering with a taken address fails without saying why
+    let response = register(&app, &mut authenticator()).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(error_code(&response), "registration_failed");
+}
+
+#[tokio::test]
+async fn test_passkey_policy() {
+    let user = json!({ "email": EMAIL, "displayName": "Test User" });
+    // Authenticator which can't verify users
+    let unverified = || WebauthnAuthenticator::new(SoftPasskey::new(false));
+
+    // By default, users must be verified
+    let app = TestApp::new().await;
+    let response = app.post("/api/v1/register/start", &user, None).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let challenge: CreationChallengeResponse = response.json();
+    let selection = challenge
+        .public_key
+        .authenticator_selection
+        .clone()
+        .unwrap();
+    assert_eq!(
+        selection.user_verification,
+        UserVerificationPolicy::Required
+    );
+    assert_eq!(
+        selection.resident_key,
+        Some(ResidentKeyRequirement::Preferred)
+    );
+    assert_eq!(selection.authenticator_attachment, None);
+    assert!(unverified().do_registration(origin(), challenge).is_err());
+
+    let app = TestApp::with_options(ApiOptions {
+        passkey_policy: PasskeyPolicy {
+            user_verification: UserVerificationPolicy::Preferred,
+            resident_key: ResidentKeyRequirement::Discouraged,
+            authenticator_attachment: Some(AuthenticatorAttachment::CrossPlatform),
+            ..PasskeyPolicy::default()
+        },
+        ..ApiOptions::default()
+    })
+    .await;
+    let response = app.post("/api/v1/register/start", &user, None).await;
+    let challenge: CreationChallengeResponse = response.json();
+    let selection = challenge.public_key.authenticator_selection.unwrap();
+    assert_eq!(
+        selection.user_verification,
+        UserVerificationPolicy::Preferred
+    );
+    assert_eq!(
+        selection.resident_key,
+        Some(ResidentKeyRequirement::Discouraged)
+    );
+    assert_eq!(
+        selection.authenticator_attachment,
+        Some(AuthenticatorAttachment::CrossPlatform)
+    );
+
+    // Without user verification required, authenticators which can't verify users can register
+    // and log in
+    let mut authenticator = unverified();
+    let response = register(&app, &mut authenticator).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let (challenge, cookie) = start_authentication(&app).await;
+    assert_eq!(
+        challenge.public_key.user_verification,
+        UserVerificationPolicy::Preferred
+    );
+    assert_eq!(
+        challenge.public_key.hints,
+        Some(vec![
+            PublicKeyCredentialHints::SecurityKey,
+            PublicKeyCredentialHints::Hybrid,
+        ])
+    );
+    let credential = authenticator
+        .do_authentication(origin(), challenge)
+        .expect("authenticator failed to authenticate");
+    let response = finish_login(&app, &credential, &cookie).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+}
+
+#[tokio::test]
+async fn test_attestation_policy() {
+    let user = json!({ "email": EMAIL, "displayName": "Test User" });
+    let with_attestation = |attestation| {
+        TestApp::with_options(ApiOptions {
+            passkey_policy: PasskeyPolicy {
+                attestation,
+                ..PasskeyPolicy::default()
+            },
+            ..ApiOptions::default()
+        })
+    };
+
+    // The software authenticator attests to its passkeys itself, with no AAGUID, so it is only
+    // refused by policies which require a certificate or allow specific models
+    let app = with_attestation(AttestationPolicy {
+        denied_aaguids: [uuid::Uuid::from_u128(1)].into(),
+        ..AttestationPolicy::default()
+    })
+    .await;
+    let response = app.post("/api/v1/register/start", &user, None).await;
+    let challenge: CreationChallengeResponse = response.json();
+    assert!(matches!(
+        challenge.public_key.attestation,
+        Some(AttestationConveyancePreference::Direct)
+    ));
+    let response = register(&app, &mut authenticator()).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let registered: User = response.json();
+    let passkeys = app
+        .db()
+        .get_passkeys_by_user_id(registered.id())
+        .await
+        .unwrap();
+    assert_eq!(passkeys[0].attestation_format, "packed");
+    assert_eq!(passkeys[0].aaguid, None);
+
+    for attestation in [
+        AttestationPolicy {
+            required: true,
+            ..AttestationPolicy::default()
+        },
+        AttestationPolicy {
+            allowed_aaguids: [uuid::Uuid::from_u128(1)].into(),
+            ..AttestationPolicy::default()
+        },
+    ] {
+        let app = with_attestation(attestation).await;
+        let response = register(&app, &mut authenticator()).await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.json::<serde_json::Value>()["error"]["code"],
+            "authenticator_not_allowed"
+        );
+        assert!(app.db().get_user_by_email(EMAIL).await.is_err());
+    }
+}
+
+#[tokio::test]
+async fn test_account_lockout() {
+    let app = TestApp::with_options(ApiOptions {
+        lockout: Some(LockoutOptions {
+            threshold: 2,
+            ..LockoutOptions::default()
+        }),
+        ..ApiOptions::default()
+    })
+    .await;
+    let mut authenticator = authenticator();
+    let response = register(&app, &mut authenticator).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let user: User = response.json();
+
+    // Answering a different login's challenge fails, and enough failures lock the account
+    for _ in 0..2 {
+        let (challenge, _) = start_authentication(&app).await;
+        let credential = authenticator
+            .do_authentication(origin(), challenge)
+            .expect("authenticator failed to authenticate");
+        let (_, cookie) = start_authentication(&app).await;
+        let response = finish_login(&app, &credential, &cookie).await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    }
+    let locked = app.db().get_user_by_id(user.id()).await.unwrap();
+    assert!(locked.is_locked());
+
+    // While locked, even a valid login is refused
+    let (challenge, cookie) = start_authentication(&app).await;
+    let credential = authenticator
+        .do_authentication(origin(), challenge)
+        .expect("authenticator failed to authenticate");
+    let response = finish_login(&app, &credential, &cookie).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "account_locked");
+    assert_eq!(
+        body["error"]["details"]["until"],
+        json!(locked.locked_until().unwrap())
+    );
+
+    // Only administrators can unlock accounts
+    let unlock = format!("/api/v1/users/{}/unlock", user.id());
+    let own_session = app.create_session(&user, false).await;
+    let response = app
+        .send_with_cookie::<()>(Method::POST, &unlock, None, Some(&own_session.cookie))
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let response = app
+        .send_with_cookie::<()>(Method::POST, &unlock, None, Some(&admin_session.cookie))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert!(response.json::<User>().locked_until().is_none());
+
+    let (challenge, cookie) = start_authentication(&app).await;
+    let credential = authenticator
+        .do_authentication(origin(), challenge)
+        .expect("authenticator failed to authenticate");
+    let response = finish_login(&app, &credential, &cookie).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+}
+
+#[tokio::test]
+async fn test_account_status() {
+    let app = TestApp::new().await;
+    let mut authenticator = authenticator();
+    let response = register(&app, &mut authenticator).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let user: User = response.json();
+    let session = cookie_from(&response, "session_id");
+    let admin = app.create_user("admin@kasad.com", "Admin").await;
+    app.make_admin(&admin).await;
+    let admin_session = app.create_session(&admin, true).await;
+    let status = format!("/api/v1/users/{}/status", user.id());
+
+    // A reason is required
+    let response = app
+        .send_with_cookie(
+            Method::PUT,
+            &status,
+            Some(&json!({ "status": "suspended", "reason": " " })),
+            Some(&admin_session.cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+
+    // Suspending the account ends its sessions and refuses logins
+    let response = app
+        .send_with_cookie(
+            Method::PUT,
+            &status,
+            Some(&json!({ "status": "suspended", "reason": "Suspicious activity" })),
+            Some(&admin_session.cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    assert_eq!(response.json::<User>().status(), UserStatus::Suspended);
+    let response = app
+        .send_with_cookie::<()>(Method::GET, "/api/v1/users/me", None, Some(&session))
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let (challenge, cookie) = start_authentication(&app).await;
+    let credential = authenticator
+        .do_authentication(origin(), challenge)
+        .expect("authenticator failed to authenticate");
+    let response = finish_login(&app, &credential, &cookie).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["code"], "account_inactive");
+    assert_eq!(body["error"]["details"]["status"], "suspended");
+
+    // The reason is recorded in the user's notes
+    let notes = app.db().get_user_notes(user.id()).await.unwrap();
+    assert_eq!(notes[0].body, "Account suspended: Suspicious activity");
+    assert_eq!(notes[0].author_id, Some(*admin.id()));
+
+    // Reactivating the account allows logins again
+    let response = app
+        .send_with_cookie(
+            Method::PUT,
+            &status,
+            Some(&json!({ "status": "active", "reason": "Resolved" })),
+            Some(&admin_session.cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let notes = app.db().get_user_notes(user.id()).await.unwrap();
+    assert_eq!(
+        notes[0].body,
+        "Account suspended: Suspicious activity\n\nAccount reactivated: Resolved"
+    );
+    let (challenge, cookie) = start_authentication(&app).await;
+    let credential = authenticator
+        .do_authentication(origin(), challenge)
+        .expect("authenticator failed to authenticate");
+    let response = finish_login(&app, &credential, &cookie).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    // Administrators can't suspend themselves
+    let response = app
+        .send_with_cookie(
+            Method::PUT,
+            &format!("/api/v1/users/{}/status", admin.id()),
+            Some(&json!({ "status": "deactivated", "reason": "Leaving" })),
+            Some(&admin_session.cookie),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+}
+
+/// Sends a POST request with the given JSON body and optional `Cookie` header value to the given
+/// host.
+async fn post_to_host(
+    app: &TestApp,
+    host: &str,
+    path: &str,
+    body: &serde_json::Value,
+    cookie: Option<&str>,
+) -> TestResponse {
+    let mut request = Request::post(path)
+        .header(HOST, host)
+        .header(CONTENT_TYPE, "application/json");
+    if let Some(cookie) = cookie {
+        request = request.header(COOKIE, cookie);
+    }
+    app.request(request.body(Body::from(body.to_string())).unwrap())
+        .await
+}
+
+#[tokio::test]
+async fn test_relying_party_by_host() {
+    const OTHER_HOST: &str = "login.example.org";
+    let other = RelyingPartyConfig {
+        origin: format!("https://{OTHER_HOST}").parse().unwrap(),
+        rp_id: OTHER_HOST.to_string(),
+        name: "Example".to_string(),
+    };
+    let parties = RelyingParties::new(test_webauthn()).with(other.build().unwrap());
+    let app = TestApp::with_relying_parties(parties).await;
+    let mut authenticator = authenticator();
+
+    // Ceremonies use the relying party served at the request's host
+    let user = json!({ "email": EMAIL, "displayName": "Test User" });
+    let response = post_to_host(&app, OTHER_HOST, "/api/v1/register/start", &user, None).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+    let challenge: CreationChallengeResponse = response.json();
+    assert_eq!(challenge.public_key.rp.id, OTHER_HOST);
+    assert_eq!(challenge.public_key.rp.name, "Example");
+    let credential = authenticator
+        .do_registration(other.origin.clone(), challenge)
+        .expect("authenticator failed to register");
+    let body = json!({ "passkey": credential });
+    let cookie = cookie_from(&response, "registration_id");
+
+    // The ceremony can't be finished at another host
+    let response = post_to_host(
+        &app,
+        "localhost:3000",
+        "/api/v1/register/finish",
+        &body,
+        Some(&cookie),
+    )
+    .await;
+    assert!(!response.status.is_success());
+    let response = post_to_host(
+        &app,
+        OTHER_HOST,
+        "/api/v1/register/finish",
+        &body,
+        Some(&cookie),
+    )
+    .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.text());
+
+    // Unknown hosts use the default relying party
+    let response = post_to_host(
+        &app,
+        "unknown.example.org",
+        "/api/v1/auth/start",
+        &json!({ "email": EMAIL }),
+        None,
+    )
+    .await;
+    let challenge: RequestChallengeResponse = response.json();
+    assert_eq!(challenge.public_key.rp_id, "localhost");
+}
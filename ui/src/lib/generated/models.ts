@@ -0,0 +1,989 @@
+// Generated by `ts-generator` from the server's API models. Do not edit by hand.
+
+export type Uuid = string;
+
+/** RFC 3339 date and time */
+export type DateTime = string;
+
+/** HTTP status codes with which the API responds to errors */
+export type ApiErrorStatus = 400 | 401 | 403 | 404 | 409 | 422 | 500 | 502;
+
+/**
+ * A message from the operators which is shown to all users in the login UI, e.g. to warn about
+ * a maintenance window. There is at most one announcement at a time.
+ */
+export interface Announcement {
+    /**
+     * Time after which the announcement is no longer shown, or [`None`] to show it until it is
+     * removed
+     */
+    endsAt: DateTime | null;
+    message: string;
+    severity: AnnouncementSeverity;
+    /**
+     * Time from which the announcement is shown, or [`None`] to show it immediately
+     */
+    startsAt: DateTime | null;
+    /**
+     * Time at which the announcement was last changed
+     */
+    updatedAt: DateTime;
+}
+
+/**
+ * How prominently an [`Announcement`] is displayed
+ */
+export type AnnouncementSeverity = "info" | "warning" | "critical";
+
+/**
+ * Description of an API error
+ */
+export interface ApiError {
+    code: ApiErrorCode;
+    /**
+     * Structured information about the error, depending on its code. For `invalid_field` errors,
+     * this contains the name of the `field` and the `reason` it is invalid; for
+     * `invalid_network` errors, the `network` which couldn't be parsed; for `account_locked`
+     * errors, the time `until` which the account is locked; for `account_inactive` errors, the
+     * account's `status`; for `missing_permission` errors, the `permission` which is missing; for
+     * `invalid_policy` errors, the `errors` found in the policy; for `validation_failed` errors,
+     * the messages describing each invalid field, by field name, in `fields`.
+     */
+    details?: Record<string, unknown> | null;
+    /**
+     * Human-readable description of the error. Messages may change between versions, so clients
+     * should use the code to tell errors apart.
+     */
+    message: string;
+}
+
+/**
+ * Stable, machine-readable code identifying the kind of an API error
+ */
+export type ApiErrorCode = "not_found" | "webauthn_error" | "internal_error" | "invalid_registration_id" | "session_expired" | "invalid_authentication_id" | "user_not_found" | "invalid_session_id" | "not_logged_in" | "not_admin" | "authentication_failed" | "downgrade_impossible" | "invalid_webhook_secret" | "email_disabled" | "merge_into_self" | "invalid_network" | "invalid_announcement" | "invalid_cursor" | "invalid_tag" | "tag_exists" | "last_passkey" | "email_taken" | "invalid_field" | "invalid_api_token" | "insufficient_scope" | "federation_failed" | "no_linked_account" | "identity_linked" | "registration_closed" | "email_domain_not_allowed" | "impersonating" | "reauthentication_required" | "registration_failed" | "authenticator_not_allowed" | "account_locked" | "account_inactive" | "not_organization_member" | "not_organization_admin" | "organization_not_selected" | "organization_exists" | "group_exists" | "group_cycle" | "missing_permission" | "role_exists" | "invalid_policy" | "policy_exists" | "validation_failed" | "payload_too_large" | "rate_limited" | "address_banned";
+
+/**
+ * Body of API error responses
+ */
+export interface ApiErrorResponse {
+    error: ApiError;
+}
+
+/**
+ * A personal access token with which programmatic clients can call the API on behalf of a user,
+ * by sending it in an `Authorization: Bearer <token>` header. Only a hash of the token is stored.
+ *
+ * API tokens never grant administrator privileges.
+ */
+export interface ApiToken {
+    /**
+     * Time at which the token was created
+     */
+    createdAt: DateTime;
+    /**
+     * Time at which the token expires, if it does
+     */
+    expiresAt: DateTime | null;
+    /**
+     * Unique ID
+     */
+    id: Uuid;
+    /**
+     * Name given to the token by its owner
+     */
+    name: string;
+    /**
+     * Permissions granted to the token
+     */
+    scopes: ApiTokenScope[];
+}
+
+/**
+ * Permission granted to an [`ApiToken`]
+ */
+export type ApiTokenScope = "read" | "write";
+
+/**
+ * Contains dynamic app configuration used in the UI, such as the server/instance name.
+ */
+export interface AppConfig {
+    /**
+     * Domains of the email addresses with which users may register themselves, in lowercase.
+     * Empty if addresses at any domain are allowed.
+     */
+    allowedEmailDomains: string[];
+    /**
+     * Path prefix under which the app is served, e.g. `/iam`. Empty if the app is served from the
+     * root of its origin.
+     */
+    basePath: string;
+    /**
+     * Customizations of the UI's appearance
+     */
+    branding: Branding;
+    /**
+     * Optional capabilities which are enabled, so the UI can hide those which aren't
+     */
+    features: FeatureFlags;
+    /**
+     * Name of this IAM server instance, used as a title in the UI
+     */
+    instanceName: string;
+    /**
+     * Who may create new accounts
+     */
+    registrationMode: RegistrationMode;
+    /**
+     * How long new sessions last
+     */
+    sessionLifetimes: SessionLifetimes;
+}
+
+/**
+ * Lets operators customize the UI without modifying it. Every field is optional; the UI's
+ * defaults are used for fields which aren't set.
+ */
+export interface Branding {
+    /**
+     * Accent theme color, as a hex color
+     */
+    accentColor?: string | null;
+    /**
+     * Message shown on the login page, e.g. to say who may use the service
+     */
+    loginMessage?: string | null;
+    /**
+     * URL of a logo image, shown instead of the default icon. Must be an `https://` URL or an
+     * absolute path on this server.
+     */
+    logoUrl?: string | null;
+    /**
+     * Primary theme color, as a hex color (e.g. `#1d4ed8`)
+     */
+    primaryColor?: string | null;
+    /**
+     * URL of the privacy policy
+     */
+    privacyPolicyUrl?: string | null;
+    /**
+     * Address users can contact for help
+     */
+    supportEmail?: string | null;
+    /**
+     * URL of the terms of service
+     */
+    termsUrl?: string | null;
+}
+
+/**
+ * Deliverability of a [`User`][super::User]'s email address
+ */
+export type EmailStatus = { status: "deliverable"; } | { reason: string; since: DateTime; status: "undeliverable"; };
+
+/**
+ * Emails are not sent to suppressed addresses, e.g. because they bounced.
+ */
+export interface EmailSuppression {
+    /**
+     * Suppressed email address
+     */
+    address: string;
+    /**
+     * Time at which the address was suppressed
+     */
+    createdAt: DateTime;
+    /**
+     * Why the address was suppressed, e.g. the bounce message
+     */
+    reason: string;
+}
+
+/**
+ * Indicates which optional capabilities are enabled on this server.
+ */
+export interface FeatureFlags {
+    /**
+     * Whether the interactive API documentation is served
+     */
+    docs: boolean;
+    /**
+     * Whether users can log in with a link sent to their email address
+     */
+    magicLinks: boolean;
+    /**
+     * Whether this server acts as an `OpenID` Connect provider
+     */
+    oidc: boolean;
+    /**
+     * Whether new users can register
+     */
+    registration: boolean;
+    /**
+     * Whether users can use time-based one-time passwords as a second factor
+     */
+    totp: boolean;
+}
+
+/**
+ * Links an account at a [`FederatedProvider`] to a [`User`][super::User], so that logging in with
+ * the account logs in as the user.
+ */
+export interface FederatedIdentity {
+    /**
+     * Time at which the account was linked
+     */
+    createdAt: DateTime;
+    /**
+     * Email address of the account at the time it was linked
+     */
+    email: string | null;
+    /**
+     * UUID of the [`FederatedProvider`]
+     */
+    providerId: Uuid;
+    /**
+     * Identifier of the account at the provider, i.e. the `sub` claim of its ID tokens
+     */
+    subject: string;
+    /**
+     * UUID of the linked [`User`][super::User]
+     */
+    userId: Uuid;
+}
+
+/**
+ * An upstream OIDC provider, such as Google Workspace, with which users can log in instead of
+ * using a passkey. Providers are registered by administrators, with client credentials obtained
+ * from the provider.
+ */
+export interface FederatedProvider {
+    /**
+     * Whether a new [`User`][super::User] is created when someone logs in for the first time
+     * with an identity which can't be linked to an existing user
+     */
+    autoProvision: boolean;
+    /**
+     * Client ID issued by the provider
+     */
+    clientId: string;
+    /**
+     * Time at which the provider was registered
+     */
+    createdAt: DateTime;
+    /**
+     * Unique ID
+     */
+    id: Uuid;
+    /**
+     * Issuer identifier of the provider, from which its endpoints are discovered
+     */
+    issuer: string;
+    /**
+     * Name of the provider, shown on the login page
+     */
+    name: string;
+    /**
+     * Unique short name of the provider, used in the URLs of its login endpoints
+     */
+    slug: string;
+    /**
+     * Time at which the provider was last updated
+     */
+    updatedAt: DateTime;
+}
+
+/**
+ * Data used to update a [`FederatedProvider`]
+ *
+ * Fields with a value will replace the corresponding field's value in the [`FederatedProvider`]
+ * to which the update is applied (via [`DatabaseClient::update_federated_provider()`][1]).
+ *
+ * [1]: crate::db::interface::DatabaseClient::update_federated_provider
+ */
+export interface FederatedProviderUpdate {
+    autoProvision: boolean | null;
+    clientId: string | null;
+    clientSecret: string | null;
+    issuer: string | null;
+    name: string | null;
+}
+
+/**
+ * Requests from clients in a banned network are rejected before they reach any endpoint.
+ */
+export interface IpBan {
+    /**
+     * Time at which the ban was created
+     */
+    createdAt: DateTime;
+    /**
+     * Time at which the ban is lifted, or [`None`] if it is permanent
+     */
+    expiresAt: DateTime | null;
+    /**
+     * Unique ID
+     */
+    id: Uuid;
+    /**
+     * Banned network in CIDR notation, e.g. `192.0.2.0/24`. Single addresses have a prefix
+     * length of 32 (IPv4) or 128 (IPv6).
+     */
+    network: string;
+    /**
+     * Why the network was banned
+     */
+    reason: string;
+    source: IpBanSource;
+}
+
+/**
+ * How an [`IpBan`] was created
+ */
+export type IpBanSource = "manual" | "automatic";
+
+/**
+ * An application which lets users log in with their IAM account via OIDC. Clients are
+ * registered by administrators, and authenticate to the token endpoint with their ID and secret.
+ */
+export interface OAuthClient {
+    /**
+     * Time at which the client was registered
+     */
+    createdAt: DateTime;
+    /**
+     * Unique ID, used as the OAuth `client_id`
+     */
+    id: Uuid;
+    /**
+     * Name of the application
+     */
+    name: string;
+    /**
+     * URLs to which users may be redirected after authorizing the client. Redirect URLs given in
+     * authorization requests must match one of these exactly.
+     */
+    redirectUris: string[];
+    /**
+     * Time at which the client was last updated
+     */
+    updatedAt: DateTime;
+}
+
+/**
+ * Data used to update an [`OAuthClient`]
+ *
+ * Fields with a value will replace the corresponding field's value in the [`OAuthClient`]
+ * to which the update is applied (via [`DatabaseClient::update_oauth_client()`][1]).
+ *
+ * [1]: crate::db::interface::DatabaseClient::update_oauth_client
+ */
+export interface OAuthClientUpdate {
+    name: string | null;
+    redirectUris: string[] | null;
+}
+
+/**
+ * Stores the data needed to maintain and use a passkey for user authentication.
+ */
+export interface PasskeyCredential {
+    /**
+     * AAGUID identifying the model of the authenticator which created this passkey, if its
+     * attestation certified one
+     */
+    aaguid: Uuid | null;
+    /**
+     * Format of the attestation statement with which this passkey was registered, e.g. `packed`,
+     * or `none` if it wasn't attested
+     */
+    attestationFormat: string;
+    /**
+     * Time at which this passkey was created
+     */
+    createdAt: DateTime;
+    /**
+     * Display name of this passkey, if set
+     */
+    displayName: string | null;
+    /**
+     * Unique ID
+     */
+    id: Uuid;
+    /**
+     * Time at which this passkey was last used to log in
+     */
+    lastUsedAt: DateTime | null;
+    /**
+     * UUID of the user to which this passkey belongs
+     */
+    userId: Uuid;
+}
+
+/**
+ * Fine-grained privilege which a [`Role`] grants
+ *
+ * Permissions only apply to administrator sessions: users with any permission can upgrade their
+ * session to one, and each administrative endpoint checks that the session's user has the
+ * permission which it requires.
+ */
+export type Permission = "*" | "users:read" | "users:write" | "users:impersonate" | "tags:read" | "tags:write" | "groups:read" | "groups:write" | "roles:read" | "roles:write";
+
+/**
+ * A policy is a document of [statements][PolicyStatement] which permit or forbid actions, on top
+ * of the [permissions][super::Permission] granted by [roles][super::Role]. See
+ * [`crate::policy`] for how policies are evaluated.
+ *
+ * Policies are versioned: each change to a policy's document gets a new version number, and
+ * earlier versions are kept as [`PolicyVersion`]s.
+ */
+export interface Policy {
+    /**
+     * Time at which the policy was created
+     */
+    createdAt: DateTime;
+    /**
+     * Current document
+     */
+    document: PolicyDocument;
+    /**
+     * Whether the policy is evaluated. Disabled policies are kept but have no effect.
+     */
+    enabled: boolean;
+    /**
+     * Unique identifier
+     */
+    id: Uuid;
+    /**
+     * Policy name (must also be unique)
+     */
+    name: string;
+    /**
+     * Time at which the policy was last updated
+     */
+    updatedAt: DateTime;
+    /**
+     * Version of the policy's document, starting at 1
+     */
+    version: number;
+}
+
+/**
+ * Contents of a [`Policy`]
+ */
+export interface PolicyDocument {
+    /**
+     * Statements of the policy, which are evaluated together
+     */
+    statements: PolicyStatement[];
+}
+
+/**
+ * Effect of a [`PolicyStatement`]
+ */
+export type PolicyEffect = "permit" | "forbid";
+
+/**
+ * Statement in a [`PolicyDocument`], which permits or forbids some actions to some users
+ *
+ * A statement applies to users who are listed in [`users`][Self::users] or have one of the
+ * [`tags`][Self::tags], or to all users if both are empty.
+ */
+export interface PolicyStatement {
+    /**
+     * Actions to which the statement applies: permission names like `users:read`, or
+     * `oauth:authorize` for authorizing OAuth clients. `*` matches every action, and a prefix
+     * followed by `:*` (like `users:*`) matches every action with that prefix.
+     */
+    actions: string[];
+    /**
+     * UUIDs of the OAuth clients to which the statement is limited. Statements which list
+     * clients only apply to authorizing those clients.
+     */
+    clients?: Uuid[];
+    /**
+     * Whether the statement permits or forbids the actions
+     */
+    effect: PolicyEffect;
+    /**
+     * UUIDs of the tags whose users the statement applies to
+     */
+    tags?: Uuid[];
+    /**
+     * UUIDs of the users to whom the statement applies
+     */
+    users?: Uuid[];
+}
+
+/**
+ * Data used to create or update a policy
+ *
+ * Fields with a value will replace the corresponding field's value in the [`Policy`] to which the
+ * update is applied (via [`DatabaseClient::update_policy()`][1]). Replacing the document creates a
+ * new version of the policy.
+ *
+ * [1]: crate::db::interface::DatabaseClient::update_policy
+ */
+export interface PolicyUpdate {
+    document: PolicyDocument | null;
+    enabled: boolean | null;
+    name: string | null;
+}
+
+/**
+ * Version of a [`Policy`]'s document
+ */
+export interface PolicyVersion {
+    /**
+     * Time at which this version was created
+     */
+    createdAt: DateTime;
+    /**
+     * Document of this version
+     */
+    document: PolicyDocument;
+    /**
+     * UUID of the policy
+     */
+    policyId: Uuid;
+    /**
+     * Version number
+     */
+    version: number;
+}
+
+/**
+ * Description of an API error in the format of RFC 7807
+ */
+export interface ProblemDetails {
+    code: ApiErrorCode;
+    /**
+     * Human-readable description of this occurrence of the error
+     */
+    detail: string;
+    /**
+     * Structured information about the error, as in the JSON error format
+     */
+    details?: Record<string, unknown> | null;
+    /**
+     * HTTP status code of the response
+     */
+    status: number;
+    /**
+     * Short summary of the kind of error, i.e. the reason phrase of the status code
+     */
+    title: string;
+    /**
+     * URI identifying the kind of error, `urn:iam:problem:` followed by the error code
+     */
+    type: string;
+}
+
+/**
+ * Who may create new accounts
+ */
+export type RegistrationMode = "open" | "invite-only" | "closed";
+
+/**
+ * A role is a named set of [`Permission`]s. Roles are assigned to [tags][Tag], and grant their
+ * permissions to every user to whom the tag applies, directly or through a
+ * [group][super::Group].
+ *
+ * The built-in `iam::admin` tag grants [every permission][Permission::All] without a role.
+ */
+export interface Role {
+    /**
+     * Time at which the role was created
+     */
+    createdAt: DateTime;
+    /**
+     * Unique identifier
+     */
+    id: Uuid;
+    /**
+     * Role name (must also be unique)
+     */
+    name: string;
+    /**
+     * Permissions granted by the role
+     */
+    permissions: Permission[];
+    /**
+     * Time at which the role was last updated
+     */
+    updatedAt: DateTime;
+}
+
+/**
+ * Data used to create or update a role
+ *
+ * Fields with a value will replace the corresponding field's value in the [`Role`] to which the
+ * update is applied (via [`DatabaseClient::update_role()`][1]).
+ *
+ * [1]: crate::db::interface::DatabaseClient::update_role
+ */
+export interface RoleUpdate {
+    name: string | null;
+    permissions: Permission[] | null;
+}
+
+/**
+ * Format of the `NameID` which identifies users to a [`SamlServiceProvider`]
+ */
+export type SamlNameIdFormat = "persistent" | "email-address";
+
+/**
+ * An application which lets users log in with their IAM account via SAML 2.0. Service providers
+ * are registered by administrators, and are identified in authentication requests by their
+ * entity ID.
+ */
+export interface SamlServiceProvider {
+    /**
+     * URL of the service provider's assertion consumer service, to which responses are posted
+     */
+    acsUrl: string;
+    /**
+     * Time at which the service provider was registered
+     */
+    createdAt: DateTime;
+    /**
+     * Unique SAML entity ID of the service provider, which it sends as the `Issuer` of its
+     * authentication requests
+     */
+    entityId: string;
+    /**
+     * Unique ID
+     */
+    id: Uuid;
+    /**
+     * Name of the application
+     */
+    name: string;
+    /**
+     * Format of the `NameID` sent to the service provider
+     */
+    nameIdFormat: SamlNameIdFormat;
+    /**
+     * Time at which the service provider was last updated
+     */
+    updatedAt: DateTime;
+}
+
+/**
+ * Data used to update a [`SamlServiceProvider`]
+ *
+ * Fields with a value will replace the corresponding field's value in the
+ * [`SamlServiceProvider`] to which the update is applied (via
+ * [`DatabaseClient::update_saml_service_provider()`][1]).
+ *
+ * [1]: crate::db::interface::DatabaseClient::update_saml_service_provider
+ */
+export interface SamlServiceProviderUpdate {
+    acsUrl: string | null;
+    entityId: string | null;
+    name: string | null;
+    nameIdFormat: SamlNameIdFormat | null;
+}
+
+export interface Session {
+    /**
+     * Time at which the user last proved their identity for this session, by logging in or by
+     * re-authenticating. Sessions which replace another keep its value.
+     */
+    authenticatedAt: DateTime;
+    /**
+     * Time at which the session was created
+     */
+    createdAt: DateTime;
+    /**
+     * Time at which the session expires
+     */
+    expiresAt: DateTime;
+    /**
+     * UUID of the administrator acting as the user, if this session was started by
+     * impersonating them
+     */
+    impersonatorId: Uuid | null;
+    /**
+     * IP address from which the session was last used, if known
+     */
+    ip: string | null;
+    /**
+     * Whether this session has admin privileges
+     */
+    isAdmin: boolean;
+    /**
+     * Time at which the session was last used. Only updated every few minutes, so it may lag
+     * behind the actual time of last use.
+     */
+    lastSeenAt: DateTime;
+    /**
+     * UUID of the [organization][super::Organization] within which the session acts, if the
+     * user selected one
+     */
+    organizationId: Uuid | null;
+    /**
+     * State of the session
+     */
+    state: SessionState;
+    /**
+     * User agent of the client which last used the session, if known
+     */
+    userAgent: string | null;
+}
+
+/**
+ * Sessions upgraded to administrator privileges usually have a shorter lifetime than regular
+ * sessions, to limit the damage a stolen administrator session can do.
+ */
+export interface SessionLifetimes {
+    /**
+     * Lifetime of sessions with administrator privileges, in seconds
+     */
+    adminSessionSecs: number;
+    /**
+     * Lifetime of regular sessions, in seconds
+     */
+    sessionSecs: number;
+}
+
+/**
+ * Session state
+ */
+export type SessionState = "active" | "revoked" | "logged-out" | "superseded";
+
+/**
+ * A tag is a marker which can be applied to [`User`]s.
+ * Tags can be applied to multiple users, and users can each have multiple tags.
+ *
+ * Tags are used to grant privileges/permissions to users. For example, the built-in `iam::admin`
+ * tag allows users to act as an administrator and manage other users in the IAM portal.
+ */
+export interface Tag {
+    /**
+     * Time at which the tag was created
+     */
+    createdAt: DateTime;
+    /**
+     * Unique identifier
+     */
+    id: Uuid;
+    /**
+     * Tag name (must also be unique among the tags of the same organization, or among the global
+     * tags)
+     */
+    name: string;
+    /**
+     * UUID of the [organization][super::Organization] to which the tag belongs, or [`None`] for
+     * global tags. Organization tags can only be applied to the organization's members.
+     */
+    organizationId: Uuid | null;
+    /**
+     * Time at which the tag was last updated
+     */
+    updatedAt: DateTime;
+    /**
+     * List of users to which this tag is applied. Depending on the database, this can be more
+     * expensive to retrieve than just the tag information, so it is not fetched by default, and
+     * will have a value of [`None`]. If needed, use [`Tag::fetch_users()`] to populate.
+     */
+    users?: User[] | null;
+}
+
+export interface User {
+    /**
+     * Time at which the user's personal data was scrubbed, if it has been. Anonymized users keep
+     * their ID, tags, and session history, but can no longer log in.
+     */
+    anonymizedAt: DateTime | null;
+    createdAt: DateTime;
+    displayName: string;
+    email: string;
+    /**
+     * Whether emails can be delivered to this user's address. Not fetched by default, and will
+     * have a value of [`None`]. If needed, use [`User::fetch_email_status()`] to populate.
+     */
+    emailStatus?: EmailStatus | null;
+    /**
+     * Time at which the user confirmed that they own their email address, if they have. Cleared
+     * whenever the address changes by other means.
+     */
+    emailVerifiedAt: DateTime | null;
+    id: Uuid;
+    /**
+     * Time at which the user last completed authentication, if ever
+     */
+    lastLoginAt: DateTime | null;
+    /**
+     * Time until which logins to the user's account are refused because of too many failed
+     * attempts, if it has been locked
+     */
+    lockedUntil: DateTime | null;
+    /**
+     * Whether the user wants to be emailed about logins to their account
+     */
+    loginNotifications: boolean;
+    /**
+     * List of passkeys belonging to this user. Depending on the database, this can be more
+     * expensive to retrieve than just the base user information, so it is not fetched by default,
+     * and will have a value of [`None`]. If needed, use [`User::fetch_passkeys()`] to populate.
+     */
+    passkeys?: PasskeyCredential[] | null;
+    /**
+     * Whether the user may log in. Users who aren't [active][UserStatus::Active] can't log in,
+     * and their sessions can't be used.
+     */
+    status: UserStatus;
+    /**
+     * List of tags applied to this user. Depending on the database, this can be more expensive to
+     * retrieve than just the base user information, so it is not fetched by default, and will
+     * have a value of [`None`]. If needed, use [`User::fetch_tags()`] to populate.
+     */
+    tags?: Tag[] | null;
+    updatedAt: DateTime;
+}
+
+/**
+ * Data used to create a user with [`DatabaseClient::create_user()`]
+ */
+export interface UserCreate {
+    displayName: string;
+    email: string;
+}
+
+/**
+ * Describes what was (or, for a dry run, would be) moved from the duplicate user to the user it
+ * was merged into by [`DatabaseClient::merge_users()`]. The duplicate user is deleted after a
+ * merge.
+ */
+export interface UserMerge {
+    /**
+     * Number of known login devices moved or combined
+     */
+    devices: number;
+    /**
+     * Whether this was a dry run, in which case nothing was changed
+     */
+    dryRun: boolean;
+    /**
+     * Number of administrator note revisions moved
+     */
+    notes: number;
+    /**
+     * Number of passkeys moved
+     */
+    passkeys: number;
+    /**
+     * Number of sessions moved
+     */
+    sessions: number;
+    /**
+     * The duplicate user, as it was before the merge
+     */
+    source: User;
+    /**
+     * Number of tags added to the target user, excluding those it already had
+     */
+    tags: number;
+    /**
+     * ID of the user into which the duplicate was merged
+     */
+    targetId: Uuid;
+}
+
+/**
+ * Notes record context about a [`User`][super::User] for other administrators, e.g. why their
+ * account was disabled. They are only visible to administrators.
+ *
+ * Notes are never modified. Editing a user's notes creates a new revision, so the history of
+ * who changed the notes and when is kept. The newest revision holds the current notes.
+ */
+export interface UserNote {
+    /**
+     * ID of the administrator who wrote this revision, or [`None`] if they have been deleted
+     */
+    authorId: Uuid | null;
+    /**
+     * Text of the notes
+     */
+    body: string;
+    /**
+     * Time at which this revision was written
+     */
+    createdAt: DateTime;
+    /**
+     * Unique ID
+     */
+    id: Uuid;
+    /**
+     * ID of the user the note is about
+     */
+    userId: Uuid;
+}
+
+/**
+ * Whether a [`User`] may use their account
+ */
+export type UserStatus = "active" | "suspended" | "deactivated";
+
+/**
+ * Data used to update a user
+ *
+ * Fields with a value will replace the corresponding field's value in the [`User`]
+ * to which the update is applied (via [`DatabaseClient::update_user()`][1]).
+ *
+ * [1]: crate::db::interface::DatabaseClient::update_user
+ */
+export interface UserUpdate {
+    displayName: string | null;
+    email: string | null;
+    loginNotifications: boolean | null;
+}
+
+/**
+ * A URL to which identity events are `POST`ed as JSON, so that downstream systems can stay in
+ * sync. Deliveries are signed with the webhook's secret; see [`crate::webhooks`] for details.
+ */
+export interface Webhook {
+    /**
+     * Time at which the webhook was created
+     */
+    createdAt: DateTime;
+    /**
+     * Whether events are delivered to this webhook
+     */
+    enabled: boolean;
+    /**
+     * Types of events delivered to this webhook. If empty, all events are delivered.
+     */
+    events: WebhookEventType[];
+    /**
+     * Unique ID
+     */
+    id: Uuid;
+    /**
+     * Time at which the webhook was last updated
+     */
+    updatedAt: DateTime;
+    /**
+     * URL to which events are delivered
+     */
+    url: string;
+}
+
+/**
+ * Type of event which can be delivered to a [`Webhook`]
+ */
+export type WebhookEventType = "user.created" | "user.deleted" | "session.created" | "passkey.created" | "passkey.deleted";
+
+/**
+ * Data used to update a [`Webhook`]
+ *
+ * Fields with a value will replace the corresponding field's value in the [`Webhook`]
+ * to which the update is applied (via [`DatabaseClient::update_webhook()`][1]).
+ *
+ * [1]: crate::db::interface::DatabaseClient::update_webhook
+ */
+export interface WebhookUpdate {
+    enabled: boolean | null;
+    events: WebhookEventType[] | null;
+    url: string | null;
+}
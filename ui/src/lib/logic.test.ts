@@ -0,0 +1,33 @@
+import { describe, expect, it } from 'vitest';
+import { sameOriginPath } from './logic';
+
+describe('sameOriginPath', () => {
+    const origin = 'https://iam.example.com';
+
+    it('accepts paths on the same origin', () => {
+        expect(sameOriginPath('/oauth/authorize?client_id=x#top', origin)).toBe(
+            '/oauth/authorize?client_id=x#top'
+        );
+        expect(sameOriginPath('https://iam.example.com/home', origin)).toBe('/home');
+        expect(sameOriginPath('/%5Cevil.com', origin)).toBe('/%5Cevil.com');
+    });
+
+    it('rejects other origins', () => {
+        for (const next of [
+            '//evil.com',
+            '/\\evil.com',
+            '\\\\evil.com',
+            '/\t/evil.com',
+            'https://evil.com/',
+            'javascript:alert(1)',
+            'http://iam.example.com/home'
+        ]) {
+            expect(sameOriginPath(next, origin), next).toBeUndefined();
+        }
+    });
+
+    it('rejects missing values', () => {
+        expect(sameOriginPath(null, origin)).toBeUndefined();
+        expect(sameOriginPath('', origin)).toBeUndefined();
+    });
+});
@@ -1,14 +1,13 @@
-import type { Session, User } from "./models";
+import type { ApiErrorResponse, Permission, Session } from "./models";
 
 /**
  * Checks if the current user can upgrade to admin privileges.
- * @param user Current user
  * @param session Current session
+ * @param permissions Current user's permissions
  * @returns Whether the current user can upgrade to admin privileges
  */
-export function canUpgrade(user: User, session: Session): boolean {
-    console.log(user);
-    return !session.isAdmin && user.tags?.some(tag => tag.name === 'iam::admin') === true;
+export function canUpgrade(session: Session, permissions: Permission[]): boolean {
+    return !session.isAdmin && !session.impersonatorId && permissions.length > 0;
 }
 
 /**
@@ -32,3 +31,81 @@ export function nameToInitials(name: string): string {
         return '?';
     }
 }
+
+/**
+ * Reads the message from an API error response.
+ * @param response Failed API response
+ * @returns The error's message, or the status text if the body isn't an API error
+ */
+export async function errorMessage(response: Response): Promise<string> {
+    try {
+        const body = (await response.json()) as ApiErrorResponse;
+        return body.error.message;
+    } catch {
+        return response.statusText;
+    }
+}
+
+/**
+ * Re-authenticates the current session with one of the user's passkeys, which is required
+ * before sensitive actions like entering administrator mode.
+ * @param base Base path of the app
+ * @returns Whether re-authentication succeeded
+ */
+export async function reauthenticate(base: string): Promise<boolean> {
+    const startResponse = await fetch(`${base}/api/v1/auth/reauth/start`, {
+        method: 'POST',
+        credentials: 'include'
+    });
+    if (!startResponse.ok) {
+        return false;
+    }
+    const { publicKey } = (await startResponse.json()) as {
+        publicKey: PublicKeyCredentialRequestOptionsJSON;
+    };
+    let credential: Credential | null;
+    try {
+        credential = await navigator.credentials.get({
+            publicKey: PublicKeyCredential.parseRequestOptionsFromJSON(publicKey)
+        });
+    } catch (e) {
+        console.error('Passkey error:', e);
+        return false;
+    }
+    if (!(credential instanceof PublicKeyCredential)) {
+        return false;
+    }
+    const finishResponse = await fetch(`${base}/api/v1/auth/reauth/finish`, {
+        method: 'POST',
+        body: JSON.stringify(credential.toJSON()),
+        headers: {
+            'Content-Type': 'application/json'
+        },
+        credentials: 'include'
+    });
+    return finishResponse.ok;
+}
+
+/**
+ * Resolves the page to which to send the user after logging in, e.g. an OAuth authorization
+ * request. Only URLs on the given origin are accepted, so this can't be used as an open redirect.
+ * @param next Requested URL, usually a path from the `next` query parameter
+ * @param origin Origin of the current page
+ * @returns The path, query, and fragment of the requested URL, or `undefined` if it is missing or
+ * on another origin
+ */
+export function sameOriginPath(next: string | null, origin: string): string | undefined {
+    if (!next) {
+        return undefined;
+    }
+    let url: URL;
+    try {
+        url = new URL(next, origin);
+    } catch {
+        return undefined;
+    }
+    if (url.origin !== origin) {
+        return undefined;
+    }
+    return url.pathname + url.search + url.hash;
+}
@@ -1,36 +1,3 @@
-export interface AppConfig {
-    instanceName: string;
-}
-
-export type Uuid = string;
-
-export interface User {
-    id: Uuid;
-    email: string;
-    displayName: string;
-    createdAt: string; // FIXME: use a date type
-    updatedAt: string; // FIXME: use a date type
-    tags?: any[]; // FIXME: use proper type
-    passkeys?: any[]; // FIXME: use proper type
-}
-
-export type SessionState =
-    | 'active'
-    | 'revoked'
-    | 'logged-out'
-    | 'superseded';
-
-export interface Session {
-    state: SessionState;
-    createdAt: string;
-    expiresAt: string;
-    isAdmin: boolean;
-}
-
-export interface Tag {
-    id: Uuid;
-    name: string;
-    createdAt: string; // FIXME: use a date type
-    updatedAt: string; // FIXME: use a date type
-    users?: User[];
-}
+// The API models are generated from the server's types by its `ts-generator` binary, and checked
+// for drift by the server's tests. See `server/tests/typescript.rs`.
+export * from './generated/models';
@@ -1,19 +1,22 @@
-import type { Session, User } from "$lib/models";
+import { base } from "$app/paths";
+import { errorMessage } from "$lib/logic";
+import type { Permission, Session, User } from "$lib/models";
 import type { LayoutLoad } from "./$types";
 
 export const load: LayoutLoad = async ({ fetch }) => {
     let data: {
         user: User;
         session: Session;
+        permissions: Permission[];
     } | undefined;
 
-    let response = await fetch('/api/v1/auth/session', {
+    let response = await fetch(`${base}/api/v1/auth/session`, {
         credentials: 'include',
     });
     if (response.ok) {
         data = await response.json();
     } else {
-        console.error('Failed to fetch session in layout', response.status, response.statusText, await response.text())
+        console.error('Failed to fetch session in layout', response.status, response.statusText, await errorMessage(response))
     }
 
     return data;
@@ -1,14 +1,24 @@
+import { browser } from '$app/environment';
+import { base } from '$app/paths';
 import type { AppConfig } from '$lib/models';
 import type { LayoutLoad } from './$types';
 
 /**
- * Fetches the server config from the API and returns it in the `appConfig` property.
- * If the fetch fails, the domain name is used as the fallback.
+ * Returns the server config in the `appConfig` property.
+ * The config injected into the page by the server is used if present. Otherwise, it is fetched
+ * from the API. If the fetch fails, the domain name is used as the fallback.
  */
 export const load: LayoutLoad = async ({ fetch }) => {
+    const injected = browser ? document.getElementById('iam-config') : null;
+    if (injected?.textContent) {
+        return {
+            appConfig: JSON.parse(injected.textContent) as AppConfig,
+        };
+    }
+
     let appConfig: AppConfig | undefined;
     try {
-        const response = await fetch('/api/v1/config');
+        const response = await fetch(`${base}/api/v1/config`);
         if (response.ok) {
             appConfig = await response.json() satisfies AppConfig;
         } else {
@@ -19,7 +29,22 @@ export const load: LayoutLoad = async ({ fetch }) => {
 
         // Fall back to the domain name
         appConfig = {
-            instanceName: window.location.hostname
+            instanceName: window.location.hostname,
+            basePath: base,
+            branding: {},
+            features: {
+                registration: true,
+                magicLinks: false,
+                totp: false,
+                oidc: false,
+                docs: false,
+            },
+            registrationMode: 'open',
+            allowedEmailDomains: [],
+            sessionLifetimes: {
+                sessionSecs: 86400,
+                adminSessionSecs: 3600,
+            },
         };
     }
 
@@ -12,13 +12,19 @@ const config = {
 			pages: 'build',
 			assets: 'build',
 			fallback: 'index.html',
-			precompress: false,
+			precompress: true,
 			strict: true,
 		}),
+		paths: {
+			// Must match the server's BASE_PATH setting
+			base: process.env.BASE_PATH ?? ''
+		},
 		csp: {
 			mode: 'auto',
 			directives: {
 				'default-src': ['self'],
+				// Allow logos configured by the operator to be hosted elsewhere
+				'img-src': ['self', 'https:'],
 			}
 		}
 	}